@@ -28,12 +28,69 @@ contract FrostBlueprint is BlueprintServiceManagerBase, PaymentManagerBase {
     uint8 public constant KEYGEN_JOB_ID = 0;
     /// @dev The Job Id for `sign` job.
     uint8 public constant SIGN_JOB_ID = 1;
+    /// @dev The Job Id for `sign_derived` job, priced as a `sign` job.
+    uint8 public constant SIGN_DERIVED_JOB_ID = 2;
+    /// @dev The Job Id for `export_package` job, free of charge.
+    uint8 public constant EXPORT_PACKAGE_JOB_ID = 3;
+    /// @dev The Job Id for `sign_typed_data` job, priced as a `sign` job.
+    uint8 public constant SIGN_TYPED_DATA_JOB_ID = 4;
+    /// @dev The Job Id for `sign_ephemeral` job, priced as a `sign` job.
+    uint8 public constant SIGN_EPHEMERAL_JOB_ID = 5;
+    /// @dev The Job Id for `query_audit_log` job, free of charge.
+    uint8 public constant QUERY_AUDIT_LOG_JOB_ID = 6;
+    /// @dev The Job Id for `get_diagnostics` job, free of charge.
+    uint8 public constant GET_DIAGNOSTICS_JOB_ID = 7;
+    /// @dev The Job Id for `batch_sign_shared_setup` job, priced as a `sign` job.
+    uint8 public constant BATCH_SIGN_SHARED_SETUP_JOB_ID = 8;
+    /// @dev The Job Id for `keygen_transcript` job, free of charge.
+    uint8 public constant KEYGEN_TRANSCRIPT_JOB_ID = 9;
+    /// @dev The Job Id for `get_signature` job, free of charge.
+    uint8 public constant GET_SIGNATURE_JOB_ID = 10;
+    /// @dev The Job Id for `set_label` job, free of charge.
+    uint8 public constant SET_LABEL_JOB_ID = 11;
+    /// @dev The Job Id for `keygen_committee` job, priced as a `keygen` job.
+    uint8 public constant KEYGEN_COMMITTEE_JOB_ID = 12;
+    /// @dev The Job Id for `key_usage_stats` job, free of charge.
+    uint8 public constant KEY_USAGE_STATS_JOB_ID = 13;
+    /// @dev The Job Id for `keygen_beacon` job, priced as a `keygen` job.
+    uint8 public constant KEYGEN_BEACON_JOB_ID = 14;
+    /// @dev The Job Id for `batch_verify` job, free of charge.
+    uint8 public constant BATCH_VERIFY_JOB_ID = 15;
+    /// @dev The Job Id for `sign_with_validity` job, priced as a `sign` job.
+    uint8 public constant SIGN_WITH_VALIDITY_JOB_ID = 16;
+    /// @dev The Job Id for `dead_letters` job, free of charge.
+    uint8 public constant DEAD_LETTERS_JOB_ID = 17;
+    /// @dev The Job Id for `set_signing_windows` job, free of charge.
+    uint8 public constant SET_SIGNING_WINDOWS_JOB_ID = 18;
+    /// @dev The Job Id for `set_key_usage_limit` job, free of charge.
+    uint8 public constant SET_KEY_USAGE_LIMIT_JOB_ID = 19;
+    /// @dev The Job Id for `self_test` job, free of charge.
+    uint8 public constant SELF_TEST_JOB_ID = 20;
+    /// @dev The Job Id for `get_signed_transcript` job, free of charge.
+    uint8 public constant GET_SIGNED_TRANSCRIPT_JOB_ID = 21;
+    /// @dev The Job Id for `compact` job, free of charge.
+    uint8 public constant COMPACT_JOB_ID = 22;
+    /// @dev The Job Id for `set_share_refresh` job, free of charge.
+    uint8 public constant SET_SHARE_REFRESH_JOB_ID = 23;
+    /// @dev The Job Id for `propose_config_change` job, free of charge.
+    uint8 public constant PROPOSE_CONFIG_CHANGE_JOB_ID = 24;
 
     /// @dev Keygen Job Avarage duration in seconds.
     uint256 public constant KEYGEN_JOB_DURATION_SECS = 5 seconds;
     /// @dev Sign Job Avarage duration in seconds.
     uint256 public constant SIGN_JOB_DURATION_SECS = 3 seconds;
 
+    /// @dev Length of the operator's ECDSA signature that may follow the key in a keygen output.
+    uint256 private constant OPERATOR_SIGNATURE_LENGTH = 65;
+    /// @dev The `es256k` multicodec of the operator's signature in a multibase keygen output.
+    uint256 private constant ES256K_SIG_CODEC = 0xd0e7;
+    /// @dev The domain of the operator's signature of a keygen result.
+    bytes private constant KEYGEN_RESULT_DOMAIN = "frost-blueprint/keygen-result";
+    /// @dev The prefix of the JSON timing reports and receipts, wrapping the hex encoded output.
+    bytes private constant WRAPPED_OUTPUT_PREFIX = '{"output":"';
+    /// @dev The order of the field of the secp256k1 curve.
+    uint256 private constant SECP256K1_P = 0xFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFEFFFFFC2F;
+
     // ================ STORAGE =======================
 
     /// @dev Mapping of service IDs to service operators addresses
@@ -57,6 +114,8 @@ contract FrostBlueprint is BlueprintServiceManagerBase, PaymentManagerBase {
     error OperatorAlreadyAdded(uint64 serviceId, address operator);
     error UnsupportedJob(uint8 job);
     error InvalidECDSAPublicKey();
+    error UnknownCiphersuite(string ciphersuite);
+    error InvalidOperatorSignature(address operator);
 
     /**
      * @dev Constructor for the FrostBlueprint contract
@@ -132,10 +191,26 @@ contract FrostBlueprint is BlueprintServiceManagerBase, PaymentManagerBase {
         bytes calldata inputs,
         bytes calldata outputs
     ) public payable virtual override onlyFromRootChain {
-        if (job == KEYGEN_JOB_ID) {
-            _handleKeygenJobResult(serviceId, jobCallId, operatorAddressFromPublicKey(participant), inputs, outputs);
-        } else if (job == SIGN_JOB_ID) {
+        if (job == KEYGEN_JOB_ID || job == KEYGEN_COMMITTEE_JOB_ID || job == KEYGEN_BEACON_JOB_ID) {
+            _handleKeygenJobResult(serviceId, jobCallId, participant, inputs, outputs);
+        } else if (
+            job == SIGN_JOB_ID || job == SIGN_DERIVED_JOB_ID || job == SIGN_TYPED_DATA_JOB_ID
+                || job == SIGN_EPHEMERAL_JOB_ID || job == BATCH_SIGN_SHARED_SETUP_JOB_ID
+                || job == SIGN_WITH_VALIDITY_JOB_ID
+        ) {
             _handleSignJobResult(serviceId, jobCallId, operatorAddressFromPublicKey(participant), inputs, outputs);
+        } else if (
+            job == EXPORT_PACKAGE_JOB_ID || job == QUERY_AUDIT_LOG_JOB_ID || job == GET_DIAGNOSTICS_JOB_ID
+                || job == KEYGEN_TRANSCRIPT_JOB_ID || job == GET_SIGNATURE_JOB_ID || job == SET_LABEL_JOB_ID
+                || job == KEY_USAGE_STATS_JOB_ID || job == BATCH_VERIFY_JOB_ID
+                || job == DEAD_LETTERS_JOB_ID || job == SET_SIGNING_WINDOWS_JOB_ID
+                || job == SET_KEY_USAGE_LIMIT_JOB_ID || job == SELF_TEST_JOB_ID
+                || job == GET_SIGNED_TRANSCRIPT_JOB_ID || job == COMPACT_JOB_ID
+                || job == SET_SHARE_REFRESH_JOB_ID || job == PROPOSE_CONFIG_CHANGE_JOB_ID
+        ) {
+            // Nothing to do, exporting a package, labelling a key, setting its signing windows, usage
+            // limit or share refresh, verifying signatures, self-testing, compacting the store, proposing
+            // a configuration change and querying the audit log, diagnostics, dead letters, transcripts, signatures or key usage are free.
         } else {
             revert UnsupportedJob(job);
         }
@@ -215,21 +290,25 @@ contract FrostBlueprint is BlueprintServiceManagerBase, PaymentManagerBase {
     /**
      * @dev Handle the result of a `keygen` job.
      * @param serviceId uint64 The ID of the service.
-     * @param _jobCallId uint64 The ID of the job call.
-     * @param operator address The operator who executed the job.
-     * @param _inputs bytes The inputs used for the job execution.
+     * @param jobCallId uint64 The ID of the job call.
+     * @param participant bytes The ECDSA public key of the operator who executed the job.
+     * @param inputs bytes The inputs used for the job execution.
      * @param outputs bytes The outputs resulting from the job execution.
      */
     function _handleKeygenJobResult(
         uint64 serviceId,
-        uint64 _jobCallId,
-        address operator,
-        bytes calldata _inputs,
+        uint64 jobCallId,
+        bytes calldata participant,
+        bytes calldata inputs,
         bytes calldata outputs
     ) internal {
-        bytes32 pubkey = abi.decode(outputs, (bytes32));
-        if (outputs.length != 32) {
-            revert InvalidECDSAPublicKey();
+        address operator = operatorAddressFromPublicKey(participant);
+        // Every keygen job takes the ciphersuite as its first parameter.
+        string memory ciphersuite = abi.decode(inputs, (string));
+        (uint256 keyLength, uint256 keyCodec) = _publicKeyFormat(ciphersuite);
+        (bytes memory key, bytes memory signature) = _keygenOutputParts(outputs, keyLength, keyCodec);
+        if (signature.length != 0) {
+            _checkKeygenResultSignature(serviceId, jobCallId, participant, key, signature);
         }
         uint256 operatorsCount = _serviceOperators[serviceId].length();
         address[] memory _tokens = supportedTokens();
@@ -241,6 +320,308 @@ contract FrostBlueprint is BlueprintServiceManagerBase, PaymentManagerBase {
         }
     }
 
+    /**
+     * @dev The length and the multicodec of the public keys of a ciphersuite.
+     * @param ciphersuite string The `ID` of the ciphersuite.
+     * @return length uint256 The length of its serialized public keys.
+     * @return codec uint256 The multicodec of its public keys.
+     */
+    function _publicKeyFormat(string memory ciphersuite) internal pure returns (uint256 length, uint256 codec) {
+        bytes32 id = keccak256(bytes(ciphersuite));
+        if (id == keccak256("FROST-ED25519-SHA512-v1")) {
+            return (32, 0xed);
+        } else if (id == keccak256("FROST-secp256k1-SHA256-v1")) {
+            return (33, 0xe7);
+        } else if (id == keccak256("FROST-RISTRETTO255-SHA512-v1")) {
+            return (32, 0x300005);
+        } else if (id == keccak256("FROST-P256-SHA256-v1")) {
+            return (33, 0x1200);
+        } else if (id == keccak256("FROST(Jubjub, BLAKE2b-512)")) {
+            return (32, 0x300003);
+        } else {
+            revert UnknownCiphersuite(ciphersuite);
+        }
+    }
+
+    /**
+     * @dev Split a keygen output into its public key and the operator's signature of it if any,
+     * in any of the encodings of the operators: raw, multibase, or wrapped in the JSON of a
+     * timing report or a receipt.
+     * @param output bytes The output of the keygen job.
+     * @param keyLength uint256 The length of the public keys of the ciphersuite.
+     * @param keyCodec uint256 The multicodec of the public keys of the ciphersuite.
+     * @return key bytes The public key.
+     * @return signature bytes The operator's signature, empty if the output is not signed.
+     */
+    function _keygenOutputParts(bytes memory output, uint256 keyLength, uint256 keyCodec)
+        internal
+        pure
+        returns (bytes memory key, bytes memory signature)
+    {
+        if (_isWrappedOutput(output)) {
+            return _keygenOutputParts(_unwrapOutput(output), keyLength, keyCodec);
+        } else if (output.length == keyLength || output.length == keyLength + OPERATOR_SIGNATURE_LENGTH) {
+            // A raw key, maybe signed.
+            return (_slice(output, 0, keyLength), _slice(output, keyLength, output.length));
+        } else if (output.length > 0 && output[0] == "z") {
+            return _multibaseOutputParts(output, keyLength, keyCodec);
+        } else {
+            revert InvalidECDSAPublicKey();
+        }
+    }
+
+    /**
+     * @dev Check the operator's signature of the keygen result `key`: the recoverable ECDSA
+     * signature of `keccak256(KEYGEN_RESULT_DOMAIN || serviceId || jobCallId || key)`, so that it
+     * cannot be replayed as the result of another job call or service.
+     * @param serviceId uint64 The ID of the service.
+     * @param jobCallId uint64 The ID of the job call.
+     * @param participant bytes The ECDSA public key of the operator.
+     * @param key bytes The public key of the keygen result.
+     * @param signature bytes The operator's signature, `r || s || v`, with `v` 0 or 1.
+     */
+    function _checkKeygenResultSignature(
+        uint64 serviceId,
+        uint64 jobCallId,
+        bytes calldata participant,
+        bytes memory key,
+        bytes memory signature
+    ) internal view {
+        bytes32 digest = keccak256(abi.encodePacked(KEYGEN_RESULT_DOMAIN, serviceId, jobCallId, key));
+        bytes32 r;
+        bytes32 s;
+        uint8 v;
+        assembly {
+            r := mload(add(signature, 32))
+            s := mload(add(signature, 64))
+            v := byte(0, mload(add(signature, 96)))
+        }
+        address signer = ecrecover(digest, v < 27 ? v + 27 : v, r, s);
+        if (signer == address(0) || signer != _ethereumAddress(participant)) {
+            revert InvalidOperatorSignature(operatorAddressFromPublicKey(participant));
+        }
+    }
+
+    /**
+     * @dev The Ethereum address of a secp256k1 public key, compressed or not.
+     * @param publicKey bytes The 33 bytes compressed key, or the 64 bytes of its coordinates.
+     * @return addr address The address, the last 20 bytes of the `keccak256` of the coordinates.
+     */
+    function _ethereumAddress(bytes calldata publicKey) internal view returns (address addr) {
+        if (publicKey.length == 64) {
+            return address(uint160(uint256(keccak256(publicKey))));
+        }
+        if (publicKey.length != 33 || (publicKey[0] != 0x02 && publicKey[0] != 0x03)) {
+            revert InvalidECDSAPublicKey();
+        }
+        uint256 x = uint256(bytes32(publicKey[1:33]));
+        // y^2 = x^3 + 7, and the square root is a power (p + 1) / 4 as p = 3 mod 4.
+        uint256 y2 = addmod(mulmod(mulmod(x, x, SECP256K1_P), x, SECP256K1_P), 7, SECP256K1_P);
+        uint256 y = _modExp(y2, (SECP256K1_P + 1) / 4, SECP256K1_P);
+        if (mulmod(y, y, SECP256K1_P) != y2) {
+            revert InvalidECDSAPublicKey();
+        }
+        if ((y & 1) != uint8(publicKey[0]) - 2) {
+            y = SECP256K1_P - y;
+        }
+        return address(uint160(uint256(keccak256(abi.encodePacked(x, y)))));
+    }
+
+    /**
+     * @dev `base ** exponent % modulus`, with the `modexp` precompile.
+     * @param base uint256 The base.
+     * @param exponent uint256 The exponent.
+     * @param modulus uint256 The modulus.
+     * @return result uint256 The result.
+     */
+    function _modExp(uint256 base, uint256 exponent, uint256 modulus) internal view returns (uint256 result) {
+        (bool success, bytes memory output) =
+            address(0x05).staticcall(abi.encodePacked(uint256(32), uint256(32), uint256(32), base, exponent, modulus));
+        if (!success) {
+            revert InvalidECDSAPublicKey();
+        }
+        return abi.decode(output, (uint256));
+    }
+
+    /**
+     * @dev A copy of `data[start:end]`.
+     * @param data bytes The data.
+     * @param start uint256 The start of the copy.
+     * @param end uint256 The end of the copy.
+     * @return part bytes The copy.
+     */
+    function _slice(bytes memory data, uint256 start, uint256 end) internal pure returns (bytes memory part) {
+        part = new bytes(end - start);
+        for (uint256 i = 0; i < part.length; i++) {
+            part[i] = data[start + i];
+        }
+    }
+
+    /**
+     * @dev Whether an output is wrapped in the JSON of a timing report or a receipt.
+     * @param output bytes The output.
+     * @return wrapped bool Whether it starts with the `output` field of the JSON.
+     */
+    function _isWrappedOutput(bytes memory output) internal pure returns (bool wrapped) {
+        if (output.length <= WRAPPED_OUTPUT_PREFIX.length) {
+            return false;
+        }
+        for (uint256 i = 0; i < WRAPPED_OUTPUT_PREFIX.length; i++) {
+            if (output[i] != WRAPPED_OUTPUT_PREFIX[i]) {
+                return false;
+            }
+        }
+        return true;
+    }
+
+    /**
+     * @dev Decode the hex encoded `output` field of the JSON of a timing report or a receipt.
+     * @param output bytes The JSON, starting with its `output` field.
+     * @return inner bytes The wrapped output.
+     */
+    function _unwrapOutput(bytes memory output) internal pure returns (bytes memory inner) {
+        uint256 start = WRAPPED_OUTPUT_PREFIX.length;
+        uint256 end = start;
+        while (end < output.length && output[end] != '"') {
+            end++;
+        }
+        if (end == output.length || (end - start) % 2 != 0) {
+            revert InvalidECDSAPublicKey();
+        }
+        inner = new bytes((end - start) / 2);
+        for (uint256 i = 0; i < inner.length; i++) {
+            inner[i] = bytes1(_hexDigit(output[start + 2 * i]) * 16 + _hexDigit(output[start + 2 * i + 1]));
+        }
+    }
+
+    /**
+     * @dev The value of a lowercase hex digit.
+     * @param digit bytes1 The digit.
+     * @return value uint8 Its value.
+     */
+    function _hexDigit(bytes1 digit) internal pure returns (uint8 value) {
+        if (digit >= "0" && digit <= "9") {
+            return uint8(digit) - uint8(bytes1("0"));
+        } else if (digit >= "a" && digit <= "f") {
+            return uint8(digit) - uint8(bytes1("a")) + 10;
+        } else {
+            revert InvalidECDSAPublicKey();
+        }
+    }
+
+    /**
+     * @dev Split a multibase keygen output: the key, then the operator's signature if any,
+     * separated by a space, each a base58btc string of its bytes behind their multicodec.
+     * @param output bytes The output.
+     * @param keyLength uint256 The length of the public keys of the ciphersuite.
+     * @param keyCodec uint256 The multicodec of the public keys of the ciphersuite.
+     * @return key bytes The public key.
+     * @return signature bytes The operator's signature, empty if the output is not signed.
+     */
+    function _multibaseOutputParts(bytes memory output, uint256 keyLength, uint256 keyCodec)
+        internal
+        pure
+        returns (bytes memory key, bytes memory signature)
+    {
+        uint256 split = 0;
+        while (split < output.length && output[split] != " ") {
+            split++;
+        }
+        key = _multibasePart(output, 0, split, keyCodec, keyLength);
+        if (split < output.length) {
+            signature = _multibasePart(output, split + 1, output.length, ES256K_SIG_CODEC, OPERATOR_SIGNATURE_LENGTH);
+        }
+    }
+
+    /**
+     * @dev Decode `output[start:end]`, a base58btc multibase string of `length` bytes behind the
+     * multicodec `codec`.
+     * @param output bytes The output.
+     * @param start uint256 The start of the part.
+     * @param end uint256 The end of the part.
+     * @param codec uint256 The multicodec of the part.
+     * @param length uint256 The number of bytes of the part, without its multicodec.
+     * @return part bytes The bytes of the part, without its multicodec.
+     */
+    function _multibasePart(bytes memory output, uint256 start, uint256 end, uint256 codec, uint256 length)
+        internal
+        pure
+        returns (bytes memory part)
+    {
+        if (end <= start || output[start] != "z") {
+            revert InvalidECDSAPublicKey();
+        }
+        uint256 prefixLength = _varintLength(codec);
+        bytes memory decoded = new bytes(prefixLength + length);
+        for (uint256 i = start + 1; i < end; i++) {
+            uint256 carry = _base58Digit(output[i]);
+            for (uint256 j = decoded.length; j > 0; j--) {
+                carry += uint256(uint8(decoded[j - 1])) * 58;
+                decoded[j - 1] = bytes1(uint8(carry));
+                carry >>= 8;
+            }
+            // The string holds more bytes than expected.
+            if (carry != 0) {
+                revert InvalidECDSAPublicKey();
+            }
+        }
+        // The multicodec, an unsigned varint, which also rules out the strings of fewer bytes.
+        for (uint256 k = 0; k < prefixLength; k++) {
+            uint256 digit = codec & 0x7f;
+            codec >>= 7;
+            if (codec != 0) {
+                digit |= 0x80;
+            }
+            if (uint8(decoded[k]) != digit) {
+                revert InvalidECDSAPublicKey();
+            }
+        }
+        return _slice(decoded, prefixLength, decoded.length);
+    }
+
+    /**
+     * @dev The value of a base58btc digit.
+     * @param digit bytes1 The digit.
+     * @return value uint256 Its value.
+     */
+    function _base58Digit(bytes1 digit) internal pure returns (uint256 value) {
+        uint8 c = uint8(digit);
+        if (c >= 0x31 && c <= 0x39) {
+            // 1-9
+            return c - 0x31;
+        } else if (c >= 0x41 && c <= 0x48) {
+            // A-H
+            return c - 0x41 + 9;
+        } else if (c >= 0x4a && c <= 0x4e) {
+            // J-N
+            return c - 0x4a + 17;
+        } else if (c >= 0x50 && c <= 0x5a) {
+            // P-Z
+            return c - 0x50 + 22;
+        } else if (c >= 0x61 && c <= 0x6b) {
+            // a-k
+            return c - 0x61 + 33;
+        } else if (c >= 0x6d && c <= 0x7a) {
+            // m-z
+            return c - 0x6d + 44;
+        } else {
+            revert InvalidECDSAPublicKey();
+        }
+    }
+
+    /**
+     * @dev The length of the unsigned varint of a multicodec.
+     * @param codec uint256 The multicodec.
+     * @return length uint256 The number of bytes of its varint.
+     */
+    function _varintLength(uint256 codec) internal pure returns (uint256 length) {
+        length = 1;
+        while (codec >= 0x80) {
+            codec >>= 7;
+            length++;
+        }
+    }
+
     /**
      * @dev Handle the result of a `sign` job.
      * @param _serviceId uint64 The ID of the service.
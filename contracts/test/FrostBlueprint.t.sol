@@ -210,7 +210,7 @@ contract FrostBlueprintTest is Test {
 
         // Prepare inputs and outputs for keygen job
         uint16 threshold = 1;
-        bytes memory inputs = abi.encodePacked(threshold);
+        bytes memory inputs = abi.encode("FROST-ED25519-SHA512-v1", threshold);
         bytes memory validPublicKey = new bytes(32); // Valid ECDSA public key length
         // Fill the rest with dummy data
         for (uint256 i = 1; i < 32; i++) {
@@ -279,7 +279,7 @@ contract FrostBlueprintTest is Test {
     // Test handling unsupported job
     function testHandleUnsupportedJob() public {
         uint64 serviceId = 1;
-        uint8 unsupportedJobId = 2;
+        uint8 unsupportedJobId = 255;
 
         bytes memory operatorPublicKey = abi.encodePacked(operator1);
 
@@ -304,13 +304,83 @@ contract FrostBlueprintTest is Test {
         vm.prank(rootChain);
         frostBlueprint.onRequest(serviceId, operators, "");
 
-        // Prepare invalid outputs for keygen job (length != 32)
+        // Prepare invalid outputs for keygen job (length != 32 for Ed25519)
+        bytes memory inputs = abi.encode("FROST-ED25519-SHA512-v1", uint16(1));
         bytes memory outputs = new bytes(33); // Invalid length
 
         // Simulate rootChain calling onJobResult
         vm.prank(rootChain);
         vm.expectRevert(abi.encodeWithSelector(FrostBlueprint.InvalidECDSAPublicKey.selector));
-        frostBlueprint.onJobResult(serviceId, KEYGEN_JOB_ID, 1, operatorPublicKey, "", outputs);
+        frostBlueprint.onJobResult(serviceId, KEYGEN_JOB_ID, 1, operatorPublicKey, inputs, outputs);
+    }
+
+    // Test handling keygen results in the other encodings of the operators
+    function testHandleEncodedKeygenJobResults() public {
+        // The compressed key of an operator signing its keygen results
+        bytes memory signingOperator = hex"034f355bdcb7cc0af728ef3cceb9615d90684bb5b2ca5f859ab0f0b704075871aa";
+        vm.prank(rootChain);
+        frostBlueprint.onRegister(signingOperator, "");
+
+        uint64 serviceId = 1;
+
+        // Add the operator to serviceId
+        bytes[] memory operators = new bytes[](1);
+        operators[0] = signingOperator;
+        vm.prank(rootChain);
+        frostBlueprint.onRequest(serviceId, operators, "");
+
+        vm.prank(owner);
+        mockERC20.transfer(address(frostBlueprint), 1e18); // 1 token
+
+        bytes memory inputs = abi.encode("FROST-secp256k1-SHA256-v1", uint16(1));
+        // A compressed secp256k1 key followed by the operator's signature of it for the job call 1
+        bytes memory signedKey =
+            hex"020102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f202b62fbe5cfa96d775be0c86965e6234745adc6dfee19fd6dd6f998bc3f25530b5842b0c46d1c93925deee2307db1b4ec6ebd700def1856becede04d5e609f84900";
+        vm.prank(rootChain);
+        frostBlueprint.onJobResult(serviceId, KEYGEN_JOB_ID, 1, signingOperator, inputs, signedKey);
+
+        // The same as multibase strings, signed for the job call 2
+        bytes memory multibase =
+            "zQ3shMUiwgYY24hGs5upF8sbE9WHp6T7RyfWKT7KM6wVik73D zXJcX9ZVy9YEFgscksnuNkboG8qF3k93ACHHMaEsiHH9HSD8xFP6QzA35jbnTrUQdnKEcx3RyZ6mZM2JHJsJnB7QxaD1gU";
+        vm.prank(rootChain);
+        frostBlueprint.onJobResult(serviceId, KEYGEN_JOB_ID, 2, signingOperator, inputs, multibase);
+
+        // Wrapped in the JSON of a receipt, signed for the job call 3
+        bytes memory receipt =
+            '{"output":"020102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f20940eeea3c7552945bfd22b2bb515af3b2eff68e7c8f34f5dfad972e7303faf2d4afd7259bbef074786a33962f9a3bdb0125dfed046aca049cb784a7cb3aae5b200","receipt":null}';
+        vm.prank(rootChain);
+        frostBlueprint.onJobResult(serviceId, KEYGEN_JOB_ID, 3, signingOperator, inputs, receipt);
+
+        // A signature for another job call is rejected
+        vm.prank(rootChain);
+        vm.expectRevert(
+            abi.encodeWithSelector(
+                FrostBlueprint.InvalidOperatorSignature.selector, operatorAddress(signingOperator)
+            )
+        );
+        frostBlueprint.onJobResult(serviceId, KEYGEN_JOB_ID, 4, signingOperator, inputs, signedKey);
+
+        // A truncated multibase key is rejected
+        vm.prank(rootChain);
+        vm.expectRevert(abi.encodeWithSelector(FrostBlueprint.InvalidECDSAPublicKey.selector));
+        frostBlueprint.onJobResult(
+            serviceId, KEYGEN_JOB_ID, 4, signingOperator, inputs, "zQ3shMUiwgYY24hGs5upF8sbE9WHp6T7RyfWKT7KM6w"
+        );
+
+        uint256 keygenJobCost = frostBlueprint.jobCost(KEYGEN_JOB_ID, TNT_ERC20_ADDRESS);
+        uint256 expectedAmount = keygenJobCost * frostBlueprint.KEYGEN_JOB_DURATION_SECS() * 3;
+        uint256 actualBalance = frostBlueprint.operatorBalanceOf(operatorAddress(signingOperator), TNT_ERC20_ADDRESS);
+        assertEq(actualBalance, expectedAmount, "The operator should be credited for each keygen");
+    }
+
+    // Test handling a keygen result of an unknown ciphersuite
+    function testHandleKeygenJobResultOfUnknownCiphersuite() public {
+        bytes memory inputs = abi.encode("FROST-P384-SHA384-v1", uint16(1));
+        bytes memory outputs = new bytes(49);
+
+        vm.prank(rootChain);
+        vm.expectRevert(abi.encodeWithSelector(FrostBlueprint.UnknownCiphersuite.selector, "FROST-P384-SHA384-v1"));
+        frostBlueprint.onJobResult(1, KEYGEN_JOB_ID, 1, operator1PublicKey, inputs, outputs);
     }
 
     // Test calculateServiceCost
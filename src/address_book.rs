@@ -0,0 +1,475 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+use std::time::Duration;
+
+use gadget_sdk as sdk;
+use sdk::libp2p::multiaddr::Protocol;
+use sdk::libp2p::Multiaddr;
+use sdk::network::{
+    IdentifierInfo, NetworkMultiplexer, ParticipantInfo, ProtocolMessage, StreamKey,
+};
+use sdk::random::rand::{self, Rng};
+use sdk::subxt_core::ext::sp_core::ecdsa;
+
+use crate::kv::SharedDynKVStore;
+use crate::operators::ConnectedPeers;
+
+/// The key under which the address book is stored.
+const ADDRESS_BOOK_KEY: &str = "frost:address-book";
+/// An address is forgotten after this many consecutive failed dials.
+pub const MAX_DIAL_FAILURES: u32 = 3;
+/// How long to wait for a single dial before considering it failed.
+const DIAL_TIMEOUT: Duration = Duration::from_secs(5);
+/// How often [`exchange`] checks for newly connected peers.
+pub const ANNOUNCE_POLL: Duration = Duration::from_secs(30);
+/// The most addresses kept from the announcement of a peer.
+const MAX_ANNOUNCED_ADDRESSES: usize = 8;
+
+/// The IP versions of the addresses dialed on startup.
+///
+/// Only the dialed addresses are affected, the network of `gadget-sdk` always listens on both
+/// the IPv4 and IPv6 wildcard addresses.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum AddressFamily {
+    /// Dial every address in the order they are known.
+    #[default]
+    Any,
+    /// Dial the IPv4 addresses first.
+    PreferIpv4,
+    /// Dial the IPv6 addresses first.
+    PreferIpv6,
+    /// Only dial the IPv4 addresses.
+    Ipv4Only,
+    /// Only dial the IPv6 addresses.
+    Ipv6Only,
+}
+
+impl AddressFamily {
+    /// Order or filter `addrs` by IP version.
+    ///
+    /// The addresses without an IP version, e.g. `/dns`, are kept and dialed after the
+    /// preferred ones.
+    pub fn apply(self, mut addrs: Vec<Multiaddr>) -> Vec<Multiaddr> {
+        let (preferred, only) = match self {
+            AddressFamily::Any => return addrs,
+            AddressFamily::PreferIpv4 => (IpVersion::V4, false),
+            AddressFamily::PreferIpv6 => (IpVersion::V6, false),
+            AddressFamily::Ipv4Only => (IpVersion::V4, true),
+            AddressFamily::Ipv6Only => (IpVersion::V6, true),
+        };
+        if only {
+            addrs.retain(|addr| ip_version(addr).map_or(true, |v| v == preferred));
+        }
+        // The sort is stable, so the addresses of a version stay in their known order.
+        addrs.sort_by_key(|addr| ip_version(addr) != Some(preferred));
+        addrs
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum IpVersion {
+    V4,
+    V6,
+}
+
+fn ip_version(addr: &Multiaddr) -> Option<IpVersion> {
+    match addr.iter().next()? {
+        Protocol::Ip4(_) | Protocol::Dns4(_) => Some(IpVersion::V4),
+        Protocol::Ip6(_) | Protocol::Dns6(_) => Some(IpVersion::V6),
+        _ => None,
+    }
+}
+
+/// Peer addresses known from previous runs, used as extra bootnodes on startup so that the
+/// node does not have to rediscover its peers from scratch.
+///
+/// Each address keeps track of how many times in a row it could not be dialed, and is
+/// pruned once it reaches [`MAX_DIAL_FAILURES`].
+#[derive(Clone, Debug, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct AddressBook {
+    /// Known addresses with their consecutive dial failures.
+    entries: BTreeMap<String, u32>,
+}
+
+impl AddressBook {
+    /// Load the address book from the store, or an empty one if none was persisted yet.
+    pub fn load(store: &SharedDynKVStore<String, Vec<u8>>) -> Result<Self, std::io::Error> {
+        match store.get(&ADDRESS_BOOK_KEY.to_string())? {
+            Some(raw) => serde_json::from_slice(&raw).map_err(Into::into),
+            None => Ok(Self::default()),
+        }
+    }
+
+    /// Persist the address book into the store.
+    pub fn save(&self, store: &SharedDynKVStore<String, Vec<u8>>) -> Result<(), std::io::Error> {
+        store.set(ADDRESS_BOOK_KEY.to_string(), serde_json::to_vec(self)?)
+    }
+
+    /// Apply `f` to the persisted address book, without losing the concurrent updates.
+    pub fn update(
+        store: &SharedDynKVStore<String, Vec<u8>>,
+        mut f: impl FnMut(&mut Self),
+    ) -> Result<Self, std::io::Error> {
+        let key = ADDRESS_BOOK_KEY.to_string();
+        loop {
+            let current = store.get(&key)?;
+            let mut book = match &current {
+                Some(raw) => serde_json::from_slice(raw)?,
+                None => Self::default(),
+            };
+            f(&mut book);
+            if store.compare_and_swap(key.clone(), current, serde_json::to_vec(&book)?)? {
+                return Ok(book);
+            }
+        }
+    }
+
+    /// Add addresses to the book, keeping the failure count of the already known ones.
+    pub fn extend(&mut self, addrs: impl IntoIterator<Item = Multiaddr>) {
+        for addr in addrs {
+            self.entries.entry(addr.to_string()).or_default();
+        }
+    }
+
+    /// All the known addresses.
+    pub fn addresses(&self) -> Vec<Multiaddr> {
+        self.entries
+            .keys()
+            .filter_map(|addr| addr.parse().ok())
+            .collect()
+    }
+
+    /// Record the outcome of dialing `addr`.
+    pub fn record_dial(&mut self, addr: &Multiaddr, success: bool) {
+        if let Some(failures) = self.entries.get_mut(&addr.to_string()) {
+            *failures = if success {
+                0
+            } else {
+                failures.saturating_add(1)
+            };
+        }
+    }
+
+    /// Forget the addresses that failed to be dialed too many times in a row.
+    pub fn prune(&mut self) {
+        self.entries
+            .retain(|_, failures| *failures < MAX_DIAL_FAILURES);
+    }
+
+    /// Dial every known address, prune the stale ones and persist the result.
+    pub async fn refresh(self, store: SharedDynKVStore<String, Vec<u8>>) {
+        let mut dials = vec![];
+        for addr in self.addresses() {
+            let success = dial(&addr).await;
+            if !success {
+                sdk::debug!(%addr, "Failed to dial a known peer address");
+            }
+            dials.push((addr, success));
+        }
+        let updated = Self::update(&store, |book| {
+            // Keep the addresses of this book that were not persisted yet.
+            for (addr, failures) in &self.entries {
+                book.entries.entry(addr.clone()).or_insert(*failures);
+            }
+            for (addr, success) in &dials {
+                book.record_dial(addr, *success);
+            }
+            book.prune();
+        });
+        if let Err(e) = updated {
+            sdk::warn!(error = %e, "Failed to persist the address book");
+        }
+    }
+}
+
+/// Merge the persisted address book with the configured `bootnodes`, persist it and return
+/// the addresses of the `family` to bootstrap the network with.
+pub fn bootnodes(
+    store: &SharedDynKVStore<String, Vec<u8>>,
+    bootnodes: &[Multiaddr],
+    family: AddressFamily,
+) -> Result<(AddressBook, Vec<Multiaddr>), std::io::Error> {
+    let book = AddressBook::update(store, |book| book.extend(bootnodes.iter().cloned()))?;
+    let addrs = family.apply(book.addresses());
+    Ok((book, addrs))
+}
+
+/// The address a node listening on `ip` and `port` announces to its peers, if it can be dialed.
+pub fn listen_address(ip: IpAddr, port: u16) -> Option<Multiaddr> {
+    if ip.is_unspecified() || port == 0 {
+        return None;
+    }
+    Some(Multiaddr::from(ip).with(Protocol::Tcp(port)))
+}
+
+/// Announce the `own` addresses of this node to its peers, and record the ones they announce.
+///
+/// The addresses announced by the connected peers are added to the address book of `store`, so
+/// that the peers found while running are bootnodes of the next startup, along with the
+/// configured ones.
+///
+/// The network of `gadget-sdk` does not expose the addresses of the peers it connects to, so
+/// each node announces its own, every time new peers connected since the last check, polled
+/// every `poll`. Only the announcements of the connected peers are recorded, and their
+/// addresses are pruned like the others once they can no longer be dialed.
+pub async fn exchange(
+    network: Arc<NetworkMultiplexer>,
+    peers: Arc<dyn ConnectedPeers>,
+    me: ecdsa::Public,
+    own: Vec<Multiaddr>,
+    store: SharedDynKVStore<String, Vec<u8>>,
+    poll: Duration,
+) {
+    let stream = network.multiplex(StreamKey {
+        task_hash: crate::session::address_announcement_name(),
+        round_id: 0,
+    });
+    let announcement = own.iter().map(ToString::to_string).collect::<Vec<_>>();
+    let announcement = serde_json::to_vec(&announcement).unwrap_or_default();
+    let mut announced_to = BTreeSet::new();
+    let mut ticks = tokio::time::interval(poll);
+    loop {
+        tokio::select! {
+            _ = ticks.tick() => {
+                let connected = peers.connected().await;
+                let new_peers = connected.difference(&announced_to).any(|peer| *peer != me);
+                announced_to = connected;
+                if own.is_empty() || !new_peers {
+                    continue;
+                }
+                // The multiplexer orders the messages of a sender by its user id, and its
+                // receivers expect the first message of every sender they never heard from:
+                // a fresh user id keeps an announcement after a restart from being held back.
+                let message = ProtocolMessage {
+                    identifier_info: IdentifierInfo::default(),
+                    sender: ParticipantInfo {
+                        user_id: rand::thread_rng().gen(),
+                        ecdsa_key: Some(me),
+                    },
+                    recipient: None,
+                    payload: announcement.clone(),
+                };
+                if let Err(e) = stream.send(message) {
+                    sdk::warn!(error = ?e, "Failed to announce the addresses of this node");
+                }
+            }
+            message = stream.recv() => {
+                let Some(message) = message else {
+                    return;
+                };
+                record_announcement(&store, &*peers, &me, message).await;
+            }
+        }
+    }
+}
+
+/// Add the addresses announced in `message` to the address book, if it comes from a connected
+/// peer.
+async fn record_announcement(
+    store: &SharedDynKVStore<String, Vec<u8>>,
+    peers: &dyn ConnectedPeers,
+    me: &ecdsa::Public,
+    message: ProtocolMessage,
+) {
+    let Some(sender) = message.sender.ecdsa_key.filter(|sender| sender != me) else {
+        return;
+    };
+    if !peers.connected().await.contains(&sender) {
+        sdk::debug!(%sender, "Ignoring the addresses announced by a peer not connected");
+        return;
+    }
+    let Ok(addrs) = serde_json::from_slice::<Vec<String>>(&message.payload) else {
+        sdk::debug!(%sender, "Ignoring a malformed address announcement");
+        return;
+    };
+    let addrs = addrs
+        .iter()
+        .filter_map(|addr| addr.parse::<Multiaddr>().ok())
+        .take(MAX_ANNOUNCED_ADDRESSES)
+        .collect::<Vec<_>>();
+    if let Err(e) = AddressBook::update(store, |book| book.extend(addrs.iter().cloned())) {
+        sdk::warn!(error = %e, "Failed to persist the address book");
+    }
+}
+
+/// Check that something is listening at `addr`.
+///
+/// Only plain IP/TCP addresses can be checked, anything else is assumed to be reachable.
+async fn dial(addr: &Multiaddr) -> bool {
+    let Some(socket) = socket_addr(addr) else {
+        return true;
+    };
+    matches!(
+        tokio::time::timeout(DIAL_TIMEOUT, tokio::net::TcpStream::connect(socket)).await,
+        Ok(Ok(_))
+    )
+}
+
+fn socket_addr(addr: &Multiaddr) -> Option<SocketAddr> {
+    let mut iter = addr.iter();
+    let ip = match iter.next()? {
+        Protocol::Ip4(ip) => ip.into(),
+        Protocol::Ip6(ip) => ip.into(),
+        _ => return None,
+    };
+    match iter.next()? {
+        Protocol::Tcp(port) => Some(SocketAddr::new(ip, port)),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+
+    fn store() -> SharedDynKVStore<String, Vec<u8>> {
+        Arc::new(crate::kv::MemKVStore::new())
+    }
+
+    #[test]
+    fn persisted_addresses_are_reloaded() {
+        let store = store();
+        let first: Multiaddr = "/ip4/10.0.0.1/tcp/30333".parse().unwrap();
+        let (_, addrs) = bootnodes(&store, &[first.clone()], AddressFamily::Any).unwrap();
+        assert_eq!(addrs, vec![first.clone()]);
+
+        // Next startup, with a different set of configured bootnodes.
+        let second: Multiaddr = "/ip4/10.0.0.2/tcp/30333".parse().unwrap();
+        let (_, addrs) = bootnodes(&store, &[second.clone()], AddressFamily::Any).unwrap();
+        assert_eq!(addrs, vec![first, second]);
+    }
+
+    #[test]
+    fn address_family_is_applied() {
+        let store = store();
+        let configured = [
+            "/ip6/::1/tcp/30333",
+            "/dns/boot.example/tcp/30333",
+            "/ip4/10.0.0.1/tcp/30333",
+        ]
+        .map(|addr| addr.parse::<Multiaddr>().unwrap());
+        let [v6, dns, v4] = configured.clone();
+        let dialed = |family| bootnodes(&store, &configured, family).unwrap().1;
+        assert_eq!(
+            dialed(AddressFamily::PreferIpv4),
+            vec![v4.clone(), dns.clone(), v6.clone()]
+        );
+        assert_eq!(
+            dialed(AddressFamily::PreferIpv6),
+            vec![v6.clone(), dns.clone(), v4.clone()]
+        );
+        assert_eq!(
+            dialed(AddressFamily::Ipv4Only),
+            vec![v4.clone(), dns.clone()]
+        );
+        assert_eq!(
+            dialed(AddressFamily::Ipv6Only),
+            vec![v6.clone(), dns.clone()]
+        );
+        // The address book keeps the addresses of both versions for the next startup.
+        assert_eq!(dialed(AddressFamily::Any).len(), 3);
+    }
+
+    #[test]
+    fn stale_addresses_are_pruned() {
+        let addr: Multiaddr = "/ip4/10.0.0.1/tcp/30333".parse().unwrap();
+        let mut book = AddressBook::default();
+        book.extend([addr.clone()]);
+        for _ in 0..MAX_DIAL_FAILURES - 1 {
+            book.record_dial(&addr, false);
+        }
+        book.prune();
+        assert_eq!(book.addresses(), vec![addr.clone()]);
+        // A successful dial resets the failures.
+        book.record_dial(&addr, true);
+        for _ in 0..MAX_DIAL_FAILURES {
+            book.record_dial(&addr, false);
+        }
+        book.prune();
+        assert!(book.addresses().is_empty());
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn announced_peer_addresses_survive_a_restart() {
+        use crate::testing::{MockNetwork, MockNetworkConfig};
+        use sdk::subxt_core::ext::sp_core::Pair;
+
+        let network = MockNetwork::new(MockNetworkConfig::default());
+        let [first, second] = [[1; 32], [2; 32]].map(|seed| ecdsa::Pair::from_seed(&seed).public());
+        let bootnode: Multiaddr = "/ip4/10.0.0.1/tcp/30333".parse().unwrap();
+        let announced: Multiaddr = "/ip4/10.0.0.2/tcp/30333".parse().unwrap();
+        let store = store();
+        let (_, addrs) = bootnodes(&store, &[bootnode.clone()], AddressFamily::Any).unwrap();
+        assert_eq!(addrs, vec![bootnode.clone()]);
+
+        let peers: Arc<dyn ConnectedPeers> = Arc::new(network.clone());
+        let tasks = [
+            tokio::spawn(exchange(
+                network.multiplexer(first),
+                peers.clone(),
+                first,
+                vec![],
+                store.clone(),
+                Duration::from_millis(50),
+            )),
+            tokio::spawn(exchange(
+                network.multiplexer(second),
+                peers,
+                second,
+                vec![announced.clone()],
+                self::store(),
+                Duration::from_millis(50),
+            )),
+        ];
+        tokio::time::timeout(Duration::from_secs(10), async {
+            while !AddressBook::load(&store)
+                .unwrap()
+                .addresses()
+                .contains(&announced)
+            {
+                tokio::time::sleep(Duration::from_millis(50)).await;
+            }
+        })
+        .await
+        .expect("the announced address was never recorded");
+        for task in tasks {
+            task.abort();
+        }
+
+        // Next startup, the peer is dialed though it is not a configured bootnode.
+        let (_, addrs) = bootnodes(&store, &[bootnode.clone()], AddressFamily::Any).unwrap();
+        assert_eq!(addrs, vec![bootnode, announced]);
+    }
+
+    #[tokio::test]
+    async fn refresh_dials_known_addresses() {
+        let store = store();
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let alive: Multiaddr = format!(
+            "/ip4/127.0.0.1/tcp/{}",
+            listener.local_addr().unwrap().port()
+        )
+        .parse()
+        .unwrap();
+        let dead_listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let dead: Multiaddr = format!(
+            "/ip4/127.0.0.1/tcp/{}",
+            dead_listener.local_addr().unwrap().port()
+        )
+        .parse()
+        .unwrap();
+        drop(dead_listener);
+
+        let mut book = AddressBook::default();
+        book.extend([alive.clone(), dead.clone()]);
+        book.record_dial(&dead, false);
+        book.record_dial(&dead, false);
+        book.refresh(store.clone()).await;
+
+        let reloaded = AddressBook::load(&store).unwrap();
+        assert_eq!(reloaded.addresses(), vec![alive]);
+    }
+}
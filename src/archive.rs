@@ -0,0 +1,398 @@
+//! Encrypted archives of the whole key store, to migrate a node.
+//!
+//! [`FrostContext::export_all`] writes every keygen entry listed in the store, label included,
+//! in a single archive encrypted with ChaCha20-Poly1305 under a key derived from a passphrase
+//! with scrypt, and [`FrostContext::import_all`] reads it back into the store of the new node.
+//! The archive holds the secret key shares, so exporting it must be allowed with
+//! [`FrostContext::with_secret_export`], as for a single key package.
+//!
+//! Every keygen entry of the store is archived, the ones generated before the index of the
+//! stored keys existed included, see [`retention`](crate::retention). The entries that cannot
+//! be read are listed in [`Export::unarchived`] rather than left out silently.
+//!
+//! The archive names the scrypt cost it was written with, which is capped on import so that a
+//! forged archive cannot make the node derive a key for hours or exhaust its memory.
+use std::collections::BTreeMap;
+
+use chacha20poly1305::aead::{Aead, KeyInit, Payload};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use gadget_sdk::random::rand::rngs::OsRng;
+use gadget_sdk::random::rand::RngCore;
+use serde::{Deserialize, Serialize};
+
+use crate::FrostContext;
+
+/// The version of the archive format.
+const ARCHIVE_VERSION: u8 = 1;
+/// The associated data of the encryption, binding the ciphertext to the archive format.
+const ARCHIVE_AAD: &[u8] = b"frost-blueprint/archive/v1";
+/// The scrypt cost of the archives written by this node, 2^15 iterations over 32 MiB.
+const SCRYPT_LOG_N: u8 = 15;
+const SCRYPT_R: u32 = 8;
+const SCRYPT_P: u32 = 1;
+/// The highest scrypt cost of the archives imported, 2^18 iterations over 256 MiB.
+const MAX_SCRYPT_LOG_N: u8 = 18;
+const MAX_SCRYPT_R: u32 = 8;
+const MAX_SCRYPT_P: u32 = 4;
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("Exporting secret key packages is not allowed")]
+    SecretExportNotAllowed,
+    #[error("Unsupported archive version {0}")]
+    UnsupportedVersion(u8),
+    #[error("Wrong passphrase or corrupted archive")]
+    Decryption,
+    #[error("Invalid key derivation parameters: {0}")]
+    Kdf(String),
+    #[error(
+        "The key derivation of the archive costs more than this node allows: log_n {log_n}, r {r}, \
+         p {p}"
+    )]
+    KdfTooCostly { log_n: u8, r: u32, p: u32 },
+    #[error("Malformed archive: {0}")]
+    Malformed(#[from] hex::FromHexError),
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[error(transparent)]
+    Entry(#[from] crate::entry::Error),
+    #[error(transparent)]
+    Retention(#[from] crate::retention::Error),
+}
+
+/// The scrypt parameters the key of an archive is derived with.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+struct KdfParams {
+    log_n: u8,
+    r: u32,
+    p: u32,
+}
+
+impl KdfParams {
+    /// Fail if the parameters cost more than the ones this node imports.
+    fn check_cost(self) -> Result<Self, Error> {
+        if self.log_n > MAX_SCRYPT_LOG_N || self.r > MAX_SCRYPT_R || self.p > MAX_SCRYPT_P {
+            return Err(Error::KdfTooCostly {
+                log_n: self.log_n,
+                r: self.r,
+                p: self.p,
+            });
+        }
+        Ok(self)
+    }
+}
+
+/// An archive written by [`FrostContext::export_all`].
+#[derive(Debug)]
+pub struct Export {
+    /// The encrypted archive, to import with [`FrostContext::import_all`].
+    pub archive: Vec<u8>,
+    /// The hex encoded public keys of the keygen entries that could not be archived, with the
+    /// reason.
+    pub unarchived: BTreeMap<String, String>,
+}
+
+/// An encrypted archive, as written by [`FrostContext::export_all`].
+#[derive(Debug, Serialize, Deserialize)]
+struct Archive {
+    version: u8,
+    kdf: KdfParams,
+    /// The hex encoded salt of the key derivation.
+    salt: String,
+    /// The hex encoded nonce of the encryption.
+    nonce: String,
+    /// The hex encoded encrypted [`ArchivedKey`]s.
+    ciphertext: String,
+}
+
+/// A keygen entry of the archive.
+#[derive(Debug, Serialize, Deserialize)]
+struct ArchivedKey {
+    /// The hex encoded public key.
+    pubkey: String,
+    /// The JSON envelope of the keygen entry, see [`crate::entry`].
+    info: serde_json::Value,
+}
+
+fn cipher(passphrase: &str, salt: &[u8], kdf: KdfParams) -> Result<ChaCha20Poly1305, Error> {
+    let params =
+        scrypt::Params::new(kdf.log_n, kdf.r, kdf.p, 32).map_err(|e| Error::Kdf(e.to_string()))?;
+    let mut key = [0u8; 32];
+    scrypt::scrypt(passphrase.as_bytes(), salt, &params, &mut key)
+        .map_err(|e| Error::Kdf(e.to_string()))?;
+    Ok(ChaCha20Poly1305::new(Key::from_slice(&key)))
+}
+
+impl FrostContext {
+    /// Export every key of this node in an archive encrypted with `passphrase`.
+    ///
+    /// The keys whose entry cannot be read are left out of the archive, and listed in
+    /// [`Export::unarchived`]. Only allowed if enabled with
+    /// [`FrostContext::with_secret_export`].
+    pub fn export_all(&self, passphrase: &str) -> Result<Export, Error> {
+        if !self.allow_secret_export {
+            return Err(Error::SecretExportNotAllowed);
+        }
+        // The keygen entries are stored under their hex encoded public key, which no other
+        // entry of the store is.
+        let mut pubkeys = crate::retention::stored_keys(&self.store)?;
+        for key in self.store.keys()? {
+            let Ok(key) = String::from_utf8(key) else {
+                continue;
+            };
+            if !key.is_empty() && hex::decode(&key).is_ok() && !pubkeys.contains(&key) {
+                pubkeys.push(key);
+            }
+        }
+        let mut keys = Vec::new();
+        let mut unarchived = BTreeMap::new();
+        for pubkey in pubkeys {
+            match self.keygen_info(&pubkey) {
+                Ok(Some(info)) => keys.push(ArchivedKey { pubkey, info }),
+                Ok(None) => {
+                    unarchived.insert(pubkey, "Listed, but without a keygen entry".to_string());
+                }
+                Err(e) => {
+                    unarchived.insert(pubkey, e.to_string());
+                }
+            }
+        }
+        for (pubkey, reason) in &unarchived {
+            gadget_sdk::warn!(
+                pubkey = %self.log_redaction.redact(pubkey),
+                %reason,
+                "Key left out of the archive"
+            );
+        }
+        let kdf = KdfParams {
+            log_n: SCRYPT_LOG_N,
+            r: SCRYPT_R,
+            p: SCRYPT_P,
+        };
+        let mut salt = [0u8; 16];
+        let mut nonce = [0u8; 12];
+        OsRng.fill_bytes(&mut salt);
+        OsRng.fill_bytes(&mut nonce);
+        let plaintext = serde_json::to_vec(&keys)?;
+        let ciphertext = cipher(passphrase, &salt, kdf)?
+            .encrypt(
+                Nonce::from_slice(&nonce),
+                Payload {
+                    msg: &plaintext,
+                    aad: ARCHIVE_AAD,
+                },
+            )
+            .map_err(|_| Error::Decryption)?;
+        let archive = Archive {
+            version: ARCHIVE_VERSION,
+            kdf,
+            salt: hex::encode(salt),
+            nonce: hex::encode(nonce),
+            ciphertext: hex::encode(ciphertext),
+        };
+        Ok(Export {
+            archive: serde_json::to_vec(&archive)?,
+            unarchived,
+        })
+    }
+
+    /// Import the keys of an `archive` written by [`FrostContext::export_all`], encrypted with
+    /// `passphrase`, into the store of this node.
+    ///
+    /// The keys this node already stores are kept as they are, and a label already given to
+    /// another key is left to it. Returns the hex encoded public keys imported.
+    ///
+    /// Fails with [`Error::KdfTooCostly`] if the archive names a scrypt cost above 2^18
+    /// iterations over 256 MiB.
+    pub fn import_all(&self, archive: &[u8], passphrase: &str) -> Result<Vec<String>, Error> {
+        let archive: Archive = serde_json::from_slice(archive)?;
+        if archive.version != ARCHIVE_VERSION {
+            return Err(Error::UnsupportedVersion(archive.version));
+        }
+        let salt = hex::decode(&archive.salt)?;
+        let nonce = hex::decode(&archive.nonce)?;
+        if nonce.len() != 12 {
+            return Err(Error::Decryption);
+        }
+        let ciphertext = hex::decode(&archive.ciphertext)?;
+        let plaintext = cipher(passphrase, &salt, archive.kdf.check_cost()?)?
+            .decrypt(
+                Nonce::from_slice(&nonce),
+                Payload {
+                    msg: &ciphertext,
+                    aad: ARCHIVE_AAD,
+                },
+            )
+            .map_err(|_| Error::Decryption)?;
+        let keys: Vec<ArchivedKey> = serde_json::from_slice(&plaintext)?;
+
+        let mut imported = Vec::new();
+        for ArchivedKey { pubkey, info } in keys {
+            if self.keygen_entry(&pubkey)?.is_some() {
+                continue;
+            }
+            if let (Some(label), Ok(bytes)) = (info["label"].as_str(), hex::decode(&pubkey)) {
+                let label_key = crate::labels::label_key(label);
+                if !label.is_empty() && !self.store.ex(&label_key)? {
+                    self.store.set(label_key, bytes)?;
+                }
+            }
+            crate::retention::admit(&self.store, None, &pubkey)?;
+            let entry = crate::entry::encode(self.entry_format, &info)?;
+            self.store.set(pubkey.clone(), entry)?;
+            imported.push(pubkey);
+        }
+        Ok(imported)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use std::time::Duration;
+
+    use super::*;
+    use crate::testing::{
+        keygen_on_all, operator_contexts, sign_on_all, MockCoordinator, MockNetwork,
+        MockNetworkConfig, TempDir,
+    };
+    use frost_core::Ciphersuite;
+    use gadget_sdk as sdk;
+    use sdk::subxt_core::ext::sp_core::Pair;
+    use sdk::subxt_core::utils::AccountId32;
+
+    fn mock_network() -> MockNetwork {
+        MockNetwork::new(MockNetworkConfig {
+            latency: Duration::from_millis(50),
+            loss: 0.0,
+        })
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn migrated_store_keeps_every_key() {
+        type Ed = frost_ed25519::Ed25519Sha512;
+        type Secp = frost_secp256k1::Secp256K1Sha256;
+        let network = mock_network();
+        let dir = TempDir::new("archive-source");
+        let contexts = operator_contexts(&network, &dir, 3, 966)
+            .into_iter()
+            .map(|context| context.with_secret_export(true))
+            .collect::<Vec<_>>();
+
+        let operators = contexts
+            .iter()
+            .zip(1..)
+            .map(|(context, i)| {
+                let key = context
+                    .config
+                    .first_ecdsa_signer()
+                    .unwrap()
+                    .signer()
+                    .public();
+                (AccountId32([i; 32]), key)
+            })
+            .collect::<BTreeMap<_, _>>();
+        let mut pubkeys = vec![];
+        for (ciphersuite, call_id) in [(Ed::ID, 966), (Secp::ID, 967), (Secp::ID, 968)] {
+            let contexts = contexts
+                .iter()
+                .map(|context| {
+                    context.clone().with_coordinator(MockCoordinator {
+                        operators: operators.clone(),
+                        call_id,
+                        change_after: None,
+                        caller: None,
+                    })
+                })
+                .collect::<Vec<_>>();
+            pubkeys.push(keygen_on_all(&contexts, ciphersuite, 2).await);
+        }
+        crate::labels::set_label(pubkeys[0].clone(), "cold".into(), contexts[0].clone())
+            .await
+            .unwrap();
+
+        // A key generated before the index of the stored keys, and an entry that cannot be read.
+        let indexed = crate::retention::stored_keys(&contexts[0].store).unwrap();
+        let unindexed = hex::encode(&pubkeys[2]);
+        let indexed = indexed
+            .into_iter()
+            .filter(|key| *key != unindexed)
+            .collect::<Vec<_>>();
+        contexts[0]
+            .store
+            .set("keys".to_string(), serde_json::to_vec(&indexed).unwrap())
+            .unwrap();
+        contexts[0]
+            .store
+            .set("abcd".to_string(), b"corrupted".to_vec())
+            .unwrap();
+
+        let exports = contexts
+            .iter()
+            .map(|context| context.export_all("correct horse battery staple").unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            exports[0].unarchived.keys().collect::<Vec<_>>(),
+            vec!["abcd"]
+        );
+        assert!(exports[1..]
+            .iter()
+            .all(|export| export.unarchived.is_empty()));
+        let archives = exports
+            .into_iter()
+            .map(|export| export.archive)
+            .collect::<Vec<_>>();
+        assert!(matches!(
+            contexts[0]
+                .clone()
+                .with_secret_export(false)
+                .export_all("any"),
+            Err(Error::SecretExportNotAllowed)
+        ));
+
+        // The same operators, on new nodes with empty stores.
+        let network = mock_network();
+        let dir = TempDir::new("archive-target");
+        let migrated = operator_contexts(&network, &dir, 3, 969);
+        assert!(matches!(
+            migrated[0].import_all(&archives[0], "wrong passphrase"),
+            Err(Error::Decryption)
+        ));
+        let mut costly: serde_json::Value = serde_json::from_slice(&archives[0]).unwrap();
+        costly["kdf"]["log_n"] = 40.into();
+        assert!(matches!(
+            migrated[0].import_all(&serde_json::to_vec(&costly).unwrap(), "any"),
+            Err(Error::KdfTooCostly { log_n: 40, .. })
+        ));
+        for (context, archive) in migrated.iter().zip(&archives) {
+            let imported = context
+                .import_all(archive, "correct horse battery staple")
+                .unwrap();
+            assert_eq!(imported.len(), 3);
+            for pubkey in &pubkeys {
+                assert!(context
+                    .keygen_entry(&hex::encode(pubkey))
+                    .unwrap()
+                    .is_some());
+            }
+            // Importing again keeps the keys as they are.
+            assert!(context
+                .import_all(archive, "correct horse battery staple")
+                .unwrap()
+                .is_empty());
+        }
+        assert_eq!(
+            migrated[0].key_label(&pubkeys[0]).unwrap().as_deref(),
+            Some("cold")
+        );
+
+        let msg = b"signed after the migration".to_vec();
+        for pubkey in pubkeys {
+            let signatures = sign_on_all(&migrated, &pubkey, &msg).await;
+            let signatures = signatures.iter().filter(|result| result.is_ok()).count();
+            assert_eq!(signatures, 2);
+        }
+    }
+}
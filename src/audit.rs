@@ -0,0 +1,188 @@
+//! Audit log of the jobs run by this node.
+//!
+//! Every keygen and signing job records an [`AuditEntry`] in the store, keyed by its call id and
+//! encoded with the [`AuditFormat`] set with [`FrostContext::with_audit_format`]. Entries are
+//! self-describing, so the log can be read back whatever format each entry was written with.
+
+use api::services::events::JobCalled;
+use gadget_sdk as sdk;
+use sdk::event_listener::tangle::{
+    jobs::{services_post_processor, services_pre_processor},
+    TangleEventListener,
+};
+use sdk::tangle_subxt::tangle_testnet_runtime::api;
+
+use crate::kv::SharedDynKVStore;
+use crate::FrostContext;
+
+/// The first byte of a bincode encoded entry, a JSON entry always starts with `{`.
+const BINCODE_TAG: u8 = 0;
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("No audit entry for job call {0}")]
+    NotFound(u64),
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+    #[error(transparent)]
+    Bincode(#[from] bincode::Error),
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+}
+
+/// The encoding of the audit entries.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum AuditFormat {
+    /// Human readable JSON.
+    #[default]
+    Json,
+    /// Compact bincode, prefixed with a zero byte.
+    Bincode,
+}
+
+/// The record of a job run by this node.
+#[derive(Clone, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct AuditEntry {
+    /// The job call id.
+    pub call_id: u64,
+    /// The job name, e.g. `keygen` or `sign`.
+    pub job: String,
+    /// The hex encoded public key the job generated or signed with, if known.
+    pub pubkey: Option<String>,
+    /// When the job finished, in seconds since the Unix epoch.
+    pub timestamp: u64,
+    /// The error the job failed with, if any.
+    pub error: Option<String>,
+}
+
+impl AuditEntry {
+    /// The entry of the job `job` of call `call_id` finishing at `timestamp`.
+    pub fn new<E: std::fmt::Display>(
+        call_id: u64,
+        job: &str,
+        pubkey: Option<&[u8]>,
+        error: Option<E>,
+        timestamp: u64,
+    ) -> Self {
+        Self {
+            call_id,
+            job: job.to_string(),
+            pubkey: pubkey.map(hex::encode),
+            timestamp,
+            error: error.map(|e| e.to_string()),
+        }
+    }
+}
+
+/// Encode `entry` in `format`.
+pub fn encode(entry: &AuditEntry, format: AuditFormat) -> Result<Vec<u8>, Error> {
+    match format {
+        AuditFormat::Json => Ok(serde_json::to_vec(entry)?),
+        AuditFormat::Bincode => {
+            let mut bytes = vec![BINCODE_TAG];
+            bincode::serialize_into(&mut bytes, entry)?;
+            Ok(bytes)
+        }
+    }
+}
+
+/// Decode an entry written by [`encode`] in any format.
+pub fn decode(bytes: &[u8]) -> Result<AuditEntry, Error> {
+    match bytes.split_first() {
+        Some((&BINCODE_TAG, entry)) => Ok(bincode::deserialize(entry)?),
+        _ => Ok(serde_json::from_slice(bytes)?),
+    }
+}
+
+fn store_key(call_id: u64) -> String {
+    format!("audit/{call_id}")
+}
+
+/// Write `entry` to the audit log in `format`.
+pub(crate) fn record(
+    store: &SharedDynKVStore<String, Vec<u8>>,
+    entry: &AuditEntry,
+    format: AuditFormat,
+) -> Result<(), Error> {
+    Ok(store.set(store_key(entry.call_id), encode(entry, format)?)?)
+}
+
+/// Read the entry of the job call `call_id` from the audit log.
+pub(crate) fn read(
+    store: &SharedDynKVStore<String, Vec<u8>>,
+    call_id: u64,
+) -> Result<AuditEntry, Error> {
+    let bytes = store
+        .get(&store_key(call_id))?
+        .ok_or(Error::NotFound(call_id))?;
+    decode(&bytes)
+}
+
+/// Query the audit log of this node.
+///
+/// # Parameters
+/// - `call_id`: The call id of a previous keygen or signing job.
+///
+/// # Returns
+/// The JSON [`AuditEntry`] of that job, whatever format it was stored with.
+///
+/// # Errors
+/// - `NotFound`: If this node has no entry for the job call.
+#[sdk::job(
+    id = 6,
+    params(call_id),
+    result(_),
+    event_listener(
+        listener = TangleEventListener::<FrostContext, JobCalled>,
+        pre_processor = services_pre_processor,
+        post_processor = services_post_processor,
+    )
+)]
+#[tracing::instrument(skip_all, parent = context.config.span.clone(), err)]
+pub async fn query_audit_log(call_id: u64, context: FrostContext) -> Result<Vec<u8>, Error> {
+    let entry = read(&context.store, call_id)?;
+    Ok(serde_json::to_vec(&entry)?)
+}
+
+impl FrostContext {
+    /// Record a finished job in the audit log.
+    ///
+    /// Failing to write the log does not fail the job, it is only logged.
+    pub(crate) fn audit<E: std::fmt::Display>(
+        &self,
+        call_id: u64,
+        job: &str,
+        pubkey: Option<&[u8]>,
+        error: Option<E>,
+    ) {
+        let entry = AuditEntry::new(call_id, job, pubkey, error, self.clock.unix_secs());
+        if let Err(e) = record(&self.store, &entry, self.audit_format) {
+            tracing::warn!(call_id, job, error = %e, "Failed to write the audit log");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    #[test]
+    fn entries_are_read_back_in_any_format() {
+        let store: SharedDynKVStore<String, Vec<u8>> = Arc::new(crate::kv::MemKVStore::new());
+        let signed = AuditEntry::new(1, "sign", Some(&[0xab; 33][..]), None::<String>, 1);
+        let failed = AuditEntry::new(2, "keygen", None, Some("Self not in operators"), 2);
+        record(&store, &signed, AuditFormat::Json).unwrap();
+        record(&store, &failed, AuditFormat::Bincode).unwrap();
+
+        assert_eq!(read(&store, 1).unwrap(), signed);
+        assert_eq!(read(&store, 2).unwrap(), failed);
+        assert!(matches!(read(&store, 3), Err(Error::NotFound(3))));
+
+        // JSON entries stay human readable, bincode ones are more compact.
+        let json = store.get(&store_key(1)).unwrap().unwrap();
+        assert_eq!(serde_json::from_slice::<AuditEntry>(&json).unwrap(), signed);
+        let bincode = encode(&signed, AuditFormat::Bincode).unwrap();
+        assert!(bincode.len() < json.len());
+    }
+}
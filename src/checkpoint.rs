@@ -0,0 +1,57 @@
+//! Checkpoints of the batch signings.
+//!
+//! With [`FrostContext::with_batch_checkpoints`], a batch is signed in chunks, and the
+//! signatures of every completed chunk are written to the store under the id of the batch, see
+//! [`batch_id`]. A batch signed again, e.g. by a later job call after a failure, skips the chunks
+//! already signed and only runs the protocol for the others. The signatures of a checkpoint
+//! are verified against the messages of their chunk before they are returned, and the chunk is
+//! signed again if they do not verify.
+//!
+//! The signers may not hold the same chunks, e.g. when one of them failed to write a checkpoint,
+//! so they first agree on the chunk to resume from, the lowest one that some signer lacks, see
+//! [`resume`](crate::rounds::resume), and all sign every chunk from it on.
+//!
+//! The checkpoints are kept once the batch is complete, so that signing it again returns the
+//! same signatures.
+//!
+//! [`FrostContext::with_batch_checkpoints`]: crate::FrostContext::with_batch_checkpoints
+use std::collections::BTreeMap;
+use std::num::NonZeroUsize;
+
+use gadget_sdk::subxt_core::ext::sp_core::keccak_256;
+
+use crate::kv::SharedDynKVStore;
+
+/// The id of the batch of messages of digest `batch` signed with the key `pubkey` in chunks of
+/// `chunk` messages.
+///
+/// The chunks of another size hold other messages, so their checkpoints are kept apart.
+pub(crate) fn batch_id(pubkey: &[u8], batch: &[u8; 32], chunk: NonZeroUsize) -> [u8; 32] {
+    let chunk = (chunk.get() as u64).to_be_bytes();
+    keccak_256(&[pubkey, &batch[..], &chunk[..]].concat())
+}
+
+fn store_key(batch_id: &[u8; 32]) -> String {
+    format!("batch/{}", hex::encode(batch_id))
+}
+
+/// The serialized signatures of the chunks of the batch `batch_id` signed so far, by chunk
+/// index.
+pub(crate) fn load(
+    store: &SharedDynKVStore<String, Vec<u8>>,
+    batch_id: &[u8; 32],
+) -> std::io::Result<BTreeMap<usize, Vec<Vec<u8>>>> {
+    match store.get(&store_key(batch_id))? {
+        Some(bytes) => Ok(serde_json::from_slice(&bytes)?),
+        None => Ok(BTreeMap::new()),
+    }
+}
+
+/// Write the signatures of the `chunks` of the batch `batch_id` signed so far.
+pub(crate) fn save(
+    store: &SharedDynKVStore<String, Vec<u8>>,
+    batch_id: &[u8; 32],
+    chunks: &BTreeMap<usize, Vec<Vec<u8>>>,
+) -> std::io::Result<()> {
+    store.set(store_key(batch_id), serde_json::to_vec(chunks)?)
+}
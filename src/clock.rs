@@ -0,0 +1,71 @@
+//! Sources of the current time.
+//!
+//! Everything the service timestamps or expires reads the time from the [`Clock`] of the
+//! [`FrostContext`](crate::FrostContext), [`SystemClock`] unless replaced with
+//! [`FrostContext::with_clock`](crate::FrostContext::with_clock), so the time-dependent
+//! behaviors can be tested with a [`MockClock`] instead of sleeping.
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// A source of the current time.
+pub trait Clock: std::fmt::Debug + Send + Sync {
+    /// The current time.
+    fn now(&self) -> SystemTime;
+
+    /// The current time, in seconds since the Unix epoch.
+    fn unix_secs(&self) -> u64 {
+        self.now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default()
+    }
+
+    /// The time elapsed since `earlier`, zero if it is in the future.
+    fn since(&self, earlier: SystemTime) -> Duration {
+        self.now().duration_since(earlier).unwrap_or_default()
+    }
+}
+
+/// The wall-clock time of the system.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> SystemTime {
+        SystemTime::now()
+    }
+}
+
+/// A clock that only moves when told to, shared by all its clones.
+#[cfg(any(test, feature = "testing"))]
+#[derive(Clone, Debug)]
+pub struct MockClock(std::sync::Arc<gadget_sdk::parking_lot::Mutex<SystemTime>>);
+
+#[cfg(any(test, feature = "testing"))]
+impl MockClock {
+    /// A clock stopped at `now`.
+    pub fn new(now: SystemTime) -> Self {
+        Self(std::sync::Arc::new(gadget_sdk::parking_lot::Mutex::new(
+            now,
+        )))
+    }
+
+    /// Move the clock forward `by`.
+    pub fn advance(&self, by: Duration) {
+        *self.0.lock() += by;
+    }
+}
+
+#[cfg(any(test, feature = "testing"))]
+impl Default for MockClock {
+    /// A clock stopped at the Unix epoch.
+    fn default() -> Self {
+        Self::new(UNIX_EPOCH)
+    }
+}
+
+#[cfg(any(test, feature = "testing"))]
+impl Clock for MockClock {
+    fn now(&self) -> SystemTime {
+        *self.0.lock()
+    }
+}
@@ -0,0 +1,362 @@
+//! Versioned encoding of the protocol messages.
+//!
+//! Every message is sent in an [`Envelope`] carrying the codec version it was encoded with, so
+//! that a node receiving a message whose layout it cannot read fails the protocol with
+//! [`Error::IncompatibleVersion`] instead of a garbled deserialization error.
+//!
+//! The failures of the network itself are told apart too: once the network handle of the node
+//! is shut down, every message fails with [`Error::NetworkShutdown`], so the jobs can report the
+//! teardown of the node rather than a fault of the protocol.
+use std::collections::BTreeSet;
+use std::pin::Pin;
+
+use gadget_sdk::futures::stream::BoxStream;
+use gadget_sdk::futures::{future, stream, Sink, SinkExt, StreamExt, TryStreamExt};
+use round_based::{Delivery, Incoming, MessageDestination, Outgoing, ProtocolMessage};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+/// The codec version of the messages of this build.
+pub const CODEC_VERSION: u8 = 1;
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("Party {sender} sent a message with codec version {version}, supported versions are {min}..={max}")]
+    IncompatibleVersion {
+        sender: u16,
+        version: u8,
+        min: u8,
+        max: u8,
+    },
+    #[error("Failed to decode the round {round} message of party {sender}: {source}")]
+    Decode {
+        sender: u16,
+        round: u16,
+        source: bincode::Error,
+    },
+    #[error("Failed to encode message: {0}")]
+    Encode(bincode::Error),
+    #[error("Delivery error: {0}")]
+    Delivery(Box<dyn std::error::Error + Send + Sync>),
+    #[error("The network of this node is shut down")]
+    NetworkShutdown,
+}
+
+impl Error {
+    fn delivery<E: std::error::Error + Send + Sync + 'static>(e: E) -> Self {
+        match is_shutdown(&e) {
+            true => Error::NetworkShutdown,
+            false => Error::Delivery(Box::new(e)),
+        }
+    }
+}
+
+/// Whether the delivery error `e`, or any of its sources, tells that the network handle of the
+/// node is shut down rather than that a message could not reach a peer.
+///
+/// The libp2p handle fails with the error of its closed outbound channel, which only says
+/// "channel closed".
+pub(crate) fn is_shutdown(e: &(dyn std::error::Error + 'static)) -> bool {
+    let mut source = Some(e);
+    while let Some(e) = source {
+        if matches!(e.downcast_ref::<Error>(), Some(Error::NetworkShutdown)) {
+            return true;
+        }
+        if let Some(e) = e.downcast_ref::<std::io::Error>() {
+            if matches!(
+                e.kind(),
+                std::io::ErrorKind::BrokenPipe | std::io::ErrorKind::NotConnected
+            ) {
+                return true;
+            }
+        }
+        if e.to_string().contains("channel closed") {
+            return true;
+        }
+        source = e.source();
+    }
+    false
+}
+
+/// The codec versions a node writes and accepts.
+///
+/// During a rolling upgrade, the upgraded nodes keep writing the old version while accepting
+/// both, and only move `current` forward once every operator runs the new build.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CodecVersion {
+    /// The version written in the outgoing messages.
+    pub current: u8,
+    /// The oldest version accepted in the incoming messages.
+    pub min_compatible: u8,
+}
+
+impl Default for CodecVersion {
+    fn default() -> Self {
+        Self {
+            current: CODEC_VERSION,
+            min_compatible: CODEC_VERSION,
+        }
+    }
+}
+
+impl CodecVersion {
+    fn accepts(&self, version: u8) -> bool {
+        (self.min_compatible..=self.current).contains(&version)
+    }
+}
+
+/// A protocol message, as sent on the network.
+///
+/// The layout of the envelope itself must never change.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Envelope {
+    version: u8,
+    round: u16,
+    payload: Vec<u8>,
+}
+
+impl Envelope {
+    pub(crate) fn new(version: u8, round: u16, payload: Vec<u8>) -> Self {
+        Self {
+            version,
+            round,
+            payload,
+        }
+    }
+
+    /// The encoded protocol message.
+    pub(crate) fn payload(&self) -> &[u8] {
+        &self.payload
+    }
+
+    /// The codec version the message was encoded with.
+    pub(crate) fn version(&self) -> u8 {
+        self.version
+    }
+}
+
+impl ProtocolMessage for Envelope {
+    fn round(&self) -> u16 {
+        self.round
+    }
+}
+
+/// A delivery of the protocol messages `M`, see [`versioned`].
+pub type VersionedDelivery<M> = (
+    BoxStream<'static, Result<Incoming<M>, Error>>,
+    Pin<Box<dyn Sink<Outgoing<M>, Error = Error> + Send>>,
+);
+
+/// Wrap a delivery of [`Envelope`]s into a delivery of the protocol messages `M`, writing and
+/// checking the codec version.
+pub fn versioned<D, M>(delivery: D, codec: CodecVersion) -> VersionedDelivery<M>
+where
+    D: Delivery<Envelope>,
+    D::Send: Send + 'static,
+    D::Receive: Send + 'static,
+    M: ProtocolMessage + Serialize + DeserializeOwned + Send + 'static,
+{
+    let (incoming, outgoing) = delivery.split();
+    let incoming = incoming
+        .map_err(Error::delivery)
+        .and_then(move |incoming| future::ready(decode(incoming, codec)));
+    let outgoing = outgoing
+        .sink_map_err(Error::delivery)
+        .with(move |outgoing| future::ready(encode::<M>(outgoing, codec)));
+    (incoming.boxed(), Box::pin(outgoing))
+}
+
+/// Wrap a delivery of [`Envelope`]s so that the first message sent on every stream is not lost.
+///
+/// The network multiplexer drops the first message of a stream that reaches a node before it
+/// opens the stream, while it buffers the later ones. Each round and destination is thus opened
+/// with an empty envelope, which the receivers skip.
+pub fn primed<D>(delivery: D) -> PrimedDelivery<D::ReceiveError, D::SendError>
+where
+    D: Delivery<Envelope>,
+    D::Send: Send + 'static,
+    D::Receive: Send + 'static,
+{
+    let (incoming, outgoing) = delivery.split();
+    let incoming = incoming.try_filter(|incoming| future::ready(!incoming.msg.payload.is_empty()));
+    let mut opened = BTreeSet::new();
+    let outgoing = outgoing.with_flat_map(move |outgoing: Outgoing<Envelope>| {
+        let destination = match outgoing.recipient {
+            MessageDestination::AllParties => None,
+            MessageDestination::OneParty(j) => Some(j),
+        };
+        let priming = opened
+            .insert((outgoing.msg.round, destination))
+            .then(|| Outgoing {
+                recipient: outgoing.recipient,
+                msg: Envelope::new(outgoing.msg.version, outgoing.msg.round, vec![]),
+            });
+        stream::iter(priming.into_iter().chain([outgoing]).map(Ok))
+    });
+    (incoming.boxed(), Box::pin(outgoing))
+}
+
+/// A delivery of [`Envelope`]s, see [`primed`].
+pub type PrimedDelivery<IErr, OErr> = (
+    BoxStream<'static, Result<Incoming<Envelope>, IErr>>,
+    Pin<Box<dyn Sink<Outgoing<Envelope>, Error = OErr> + Send>>,
+);
+
+fn encode<M: ProtocolMessage + Serialize>(
+    outgoing: Outgoing<M>,
+    codec: CodecVersion,
+) -> Result<Outgoing<Envelope>, Error> {
+    let round = outgoing.msg.round();
+    let payload = bincode::serialize(&outgoing.msg).map_err(Error::Encode)?;
+    Ok(outgoing.map(|_| Envelope::new(codec.current, round, payload)))
+}
+
+fn decode<M: DeserializeOwned>(
+    incoming: Incoming<Envelope>,
+    codec: CodecVersion,
+) -> Result<Incoming<M>, Error> {
+    let version = incoming.msg.version;
+    if !codec.accepts(version) {
+        return Err(Error::IncompatibleVersion {
+            sender: incoming.sender,
+            version,
+            min: codec.min_compatible,
+            max: codec.current,
+        });
+    }
+    let msg = bincode::deserialize(&incoming.msg.payload).map_err(|source| Error::Decode {
+        sender: incoming.sender,
+        round: incoming.msg.round,
+        source,
+    })?;
+    Ok(incoming.map(|_| msg))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use std::time::Duration;
+
+    use super::*;
+    use crate::testing::{MockNetwork, MockNetworkConfig};
+    use gadget_sdk::network::round_based_compat::NetworkDeliveryWrapper;
+    use gadget_sdk::subxt_core::ext::sp_core::ecdsa;
+
+    #[derive(Clone, Debug, PartialEq, ProtocolMessage, Serialize, Deserialize)]
+    enum Msg {
+        Ping(u32),
+    }
+
+    /// Send a ping from a node using `sender` to a node using `receiver`.
+    async fn ping(
+        sender: CodecVersion,
+        receiver: CodecVersion,
+    ) -> Option<Result<Incoming<Msg>, Error>> {
+        let network = MockNetwork::new(MockNetworkConfig::default());
+        let parties = (0..2u8)
+            .map(|i| {
+                let mut key = [0u8; 33];
+                key[0] = 0x02;
+                key[1] = i;
+                (u16::from(i), ecdsa::Public::from_raw(key))
+            })
+            .collect::<BTreeMap<_, _>>();
+        let mut deliveries = parties.iter().map(|(i, key)| {
+            NetworkDeliveryWrapper::<Envelope>::new(
+                network.multiplexer(*key),
+                *i,
+                [7; 32],
+                parties.clone(),
+            )
+        });
+        let (alice, bob) = (deliveries.next().unwrap(), deliveries.next().unwrap());
+        let (_, mut outgoing) = Delivery::<Msg>::split(versioned(alice, sender));
+        let (mut incoming, _) = Delivery::<Msg>::split(versioned(bob, receiver));
+        outgoing
+            .send(Outgoing::broadcast(Msg::Ping(42)))
+            .await
+            .unwrap();
+        tokio::time::timeout(Duration::from_secs(5), incoming.next())
+            .await
+            .expect("message not delivered")
+    }
+
+    #[tokio::test]
+    async fn compatible_versions_are_decoded() {
+        let upgraded = CodecVersion {
+            current: CODEC_VERSION + 1,
+            min_compatible: CODEC_VERSION,
+        };
+        let incoming = ping(CodecVersion::default(), upgraded)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(incoming.msg, Msg::Ping(42));
+        assert_eq!(incoming.sender, 0);
+    }
+
+    #[test]
+    fn closed_network_is_told_apart() {
+        let closed = std::io::Error::from(std::io::ErrorKind::BrokenPipe);
+        assert!(matches!(Error::delivery(closed), Error::NetworkShutdown));
+        let closed = gadget_sdk::Error::Network {
+            reason: "Failed to send message: channel closed".to_string(),
+        };
+        assert!(matches!(Error::delivery(closed), Error::NetworkShutdown));
+        let unreachable = std::io::Error::other("peer unreachable");
+        assert!(matches!(Error::delivery(unreachable), Error::Delivery(_)));
+    }
+
+    #[tokio::test]
+    async fn incompatible_version_is_rejected() {
+        let upgraded = CodecVersion {
+            current: CODEC_VERSION + 1,
+            min_compatible: CODEC_VERSION + 1,
+        };
+        let incoming = ping(upgraded, CodecVersion::default()).await.unwrap();
+        assert!(matches!(
+            incoming,
+            Err(Error::IncompatibleVersion { sender: 0, version, .. }) if version == CODEC_VERSION + 1
+        ));
+    }
+
+    #[tokio::test]
+    async fn primed_messages_reach_a_late_receiver() {
+        let network = MockNetwork::new(MockNetworkConfig::default());
+        let parties = (0..2u8)
+            .map(|i| {
+                let mut key = [0u8; 33];
+                key[0] = 0x02;
+                key[1] = i;
+                (u16::from(i), ecdsa::Public::from_raw(key))
+            })
+            .collect::<BTreeMap<_, _>>();
+        let muxes = parties
+            .values()
+            .map(|key| network.multiplexer(*key))
+            .collect::<Vec<_>>();
+        let delivery = |i: u16| {
+            NetworkDeliveryWrapper::<Envelope>::new(
+                muxes[usize::from(i)].clone(),
+                i,
+                [8; 32],
+                parties.clone(),
+            )
+        };
+        let alice = primed(delivery(0));
+        let (_, mut outgoing) = Delivery::<Msg>::split(versioned(alice, CodecVersion::default()));
+        outgoing
+            .send(Outgoing::broadcast(Msg::Ping(42)))
+            .await
+            .unwrap();
+        // Bob opens the session only once the messages arrived.
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        let bob = primed(delivery(1));
+        let (mut incoming, _) = Delivery::<Msg>::split(versioned(bob, CodecVersion::default()));
+        let incoming = tokio::time::timeout(Duration::from_secs(5), incoming.next())
+            .await
+            .expect("message not delivered")
+            .unwrap()
+            .unwrap();
+        assert_eq!(incoming.msg, Msg::Ping(42));
+    }
+}
@@ -0,0 +1,168 @@
+//! Compaction of the store.
+//!
+//! Besides the keys, the store holds many short-lived entries, e.g. the checkpoints, the
+//! deduplication markers and the audit log, and a log-structured backend such as sled keeps the
+//! space of the overwritten and removed ones until it is compacted. The store is compacted on
+//! demand with the [`compact`] job, or in the background with
+//! [`FrostContext::with_compaction`]. Compacting a store in memory does nothing.
+use std::time::Duration;
+
+use api::services::events::JobCalled;
+use gadget_sdk as sdk;
+use sdk::event_listener::tangle::{
+    jobs::{services_post_processor, services_pre_processor},
+    TangleEventListener,
+};
+use sdk::tangle_subxt::tangle_testnet_runtime::api;
+use serde::{Deserialize, Serialize};
+
+use crate::kv::SharedDynKVStore;
+use crate::FrostContext;
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[error(transparent)]
+    Unauthorized(#[from] crate::operators::Unauthorized),
+}
+
+/// When the store is compacted in the background, see [`FrostContext::with_compaction`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CompactionTrigger {
+    /// Compact the store every `interval`.
+    Periodic(Duration),
+    /// Check the size of the store on disk every `interval`, and compact it once it is at least
+    /// `bytes`.
+    Size { bytes: u64, interval: Duration },
+}
+
+/// The outcome of a compaction.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CompactionReport {
+    /// Whether the store was compacted, `false` if it was smaller than required.
+    pub compacted: bool,
+    /// The size of the store on disk before, in bytes, `None` for a store in memory.
+    pub size_before: Option<u64>,
+    /// The size of the store on disk after, in bytes, `None` for a store in memory.
+    pub size_after: Option<u64>,
+}
+
+/// Compact the store if its size on disk is at least `min_size` bytes.
+///
+/// A store in memory has no size on disk, and is always compacted.
+pub(crate) fn compact_store(
+    store: &SharedDynKVStore<String, Vec<u8>>,
+    min_size: u64,
+) -> Result<CompactionReport, std::io::Error> {
+    let size_before = store.size_on_disk()?;
+    if size_before.is_some_and(|size| size < min_size) {
+        return Ok(CompactionReport {
+            compacted: false,
+            size_before,
+            size_after: size_before,
+        });
+    }
+    store.compact()?;
+    let size_after = store.size_on_disk()?;
+    tracing::debug!(?size_before, ?size_after, "Store compacted");
+    Ok(CompactionReport {
+        compacted: true,
+        size_before,
+        size_after,
+    })
+}
+
+/// Compact the store of this node.
+///
+/// # Parameters
+/// - `min_size`: The size on disk, in bytes, from which the store is compacted, 0 to always
+///   compact it.
+///
+/// # Returns
+/// The JSON [`CompactionReport`].
+///
+/// # Errors
+/// - `Unauthorized`: If the job is not called by the service owner or one of its operators.
+#[sdk::job(
+    id = 22,
+    params(min_size),
+    result(_),
+    event_listener(
+        listener = TangleEventListener::<FrostContext, JobCalled>,
+        pre_processor = services_pre_processor,
+        post_processor = services_post_processor,
+    )
+)]
+#[tracing::instrument(skip_all, parent = context.config.span.clone(), err)]
+pub async fn compact(min_size: u64, context: FrostContext) -> Result<Vec<u8>, Error> {
+    context.authorize_caller().await?;
+    let report = compact_store(&context.store, min_size)?;
+    Ok(serde_json::to_vec(&report)?)
+}
+
+impl FrostContext {
+    /// Compact the store in the background when `trigger` says so, see [`compaction`].
+    ///
+    /// Must be called from within a tokio runtime.
+    ///
+    /// [`compaction`]: crate::compaction
+    pub fn with_compaction(self, trigger: CompactionTrigger) -> Self {
+        let (interval, min_size) = match trigger {
+            CompactionTrigger::Periodic(interval) => (interval, 0),
+            CompactionTrigger::Size { bytes, interval } => (interval, bytes),
+        };
+        let store = self.store.clone();
+        tokio::spawn(async move {
+            loop {
+                tokio::time::sleep(interval).await;
+                if let Err(e) = compact_store(&store, min_size) {
+                    sdk::warn!(error = %e, "Failed to compact the store");
+                }
+            }
+        });
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use frost_core::Ciphersuite;
+
+    use super::*;
+    use crate::testing::{
+        keygen_on_all, operator_contexts, MockNetwork, MockNetworkConfig, TempDir,
+    };
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn keys_survive_compaction() {
+        let network = MockNetwork::new(MockNetworkConfig {
+            latency: Duration::from_millis(50),
+            loss: 0.0,
+        });
+        let dir = TempDir::new("compaction");
+        let contexts = operator_contexts(&network, &dir, 3, 994);
+        let pubkey = keygen_on_all(&contexts, frost_ed25519::Ed25519Sha512::ID, 2).await;
+        let pubkey = hex::encode(&pubkey);
+
+        let context = contexts[0].clone();
+        // Leave some garbage behind.
+        for i in 0..100u32 {
+            let key = format!("garbage/{i}");
+            context.store.set(key.clone(), vec![0; 1024]).unwrap();
+            context.store.del(&key).unwrap();
+        }
+        let report = compact(0, context.clone()).await.unwrap();
+        let report: CompactionReport = serde_json::from_slice(&report).unwrap();
+        assert!(report.compacted);
+        assert!(context.keygen_info(&pubkey).unwrap().is_some());
+
+        // Below the minimum size the store is left as is.
+        let report = compact(u64::MAX, context.clone()).await.unwrap();
+        let report: CompactionReport = serde_json::from_slice(&report).unwrap();
+        assert_eq!(report.compacted, report.size_before.is_none());
+        assert!(context.keygen_info(&pubkey).unwrap().is_some());
+    }
+}
@@ -0,0 +1,527 @@
+//! Coordination of the operators, i.e. who takes part in the protocols and which job call is
+//! being run.
+//!
+//! The jobs only reach the coordinator through the [`Coordinator`] trait, [`TangleCoordinator`]
+//! being the default, so the keygen and signing logic can be driven from another coordinator.
+use std::collections::{BTreeMap, BTreeSet};
+use std::time::Duration;
+
+use color_eyre::eyre;
+use gadget_sdk as sdk;
+use gadget_sdk::contexts::MPCContext;
+use gadget_sdk::subxt_core::ext::sp_core::ecdsa;
+use gadget_sdk::subxt_core::utils::AccountId32;
+use gadget_sdk::tangle_subxt::tangle_testnet_runtime::api;
+use gadget_sdk::tangle_subxt::tangle_testnet_runtime::api::runtime_types::pallet_multi_asset_delegation::types::operator::OperatorStatus;
+use gadget_sdk::tangle_subxt::tangle_testnet_runtime::api::runtime_types::sp_arithmetic::per_things::Percent;
+use gadget_sdk::clients::tangle::runtime::{TangleClient, TangleConfig};
+use gadget_sdk::subxt::blocks::Block;
+use gadget_sdk::subxt::tx::Signer;
+use gadget_sdk::tangle_subxt::tangle_testnet_runtime::api::runtime_types::bounded_collections::bounded_vec::BoundedVec;
+use gadget_sdk::tangle_subxt::tangle_testnet_runtime::api::runtime_types::tangle_primitives::services::field::Field;
+use sdk::contexts::{KeystoreContext, ServicesContext, TangleClientContext};
+
+/// The source of truth of the operators and the job calls.
+#[async_trait::async_trait]
+pub trait Coordinator: Send + Sync {
+    /// The id of the service.
+    fn service_id(&self) -> eyre::Result<u64>;
+
+    /// The ECDSA keys of the service operators, keyed by account.
+    async fn operators(&self) -> eyre::Result<BTreeMap<AccountId32, ecdsa::Public>>;
+
+    /// The restake exposure of the service operators.
+    async fn restakes(&self) -> eyre::Result<Vec<(AccountId32, Percent)>>;
+
+    /// The ECDSA keys of the `operators` that were paused in the block the job call `call_id`
+    /// was made in, see [`Coordinator::call_block`], the same on every node.
+    async fn paused_operators(
+        &self,
+        call_id: u64,
+        operators: &BTreeMap<AccountId32, ecdsa::Public>,
+    ) -> eyre::Result<BTreeSet<ecdsa::Public>>;
+
+    /// Announce whether this operator is online, i.e. not paused.
+    async fn set_online(&self, online: bool) -> eyre::Result<()>;
+
+    /// The id of the job call being run.
+    async fn current_call_id(&self) -> eyre::Result<u64>;
+
+    /// The number of the finalized block the job call `call_id` was made in, the same on every
+    /// node whatever its view of the chain.
+    async fn call_block(&self, call_id: u64) -> eyre::Result<u64>;
+
+    /// The timestamp of the block the job call `call_id` was made in, see
+    /// [`Coordinator::call_block`], in seconds since the Unix epoch, the same on every node.
+    async fn call_time(&self, call_id: u64) -> eyre::Result<u64>;
+
+    /// The account that made the job call `call_id`.
+    async fn caller(&self, call_id: u64) -> eyre::Result<AccountId32>;
+
+    /// The account that requested the service, its owner.
+    async fn service_owner(&self) -> eyre::Result<AccountId32>;
+
+    /// Resolve once the operators differ from the ones at the time of the call, i.e. an operator
+    /// joined or left the service.
+    async fn operators_changed(&self) -> eyre::Result<()>;
+
+    /// The result submitted for the job call `call_id`, if it is on-chain yet.
+    async fn submitted_result(&self, call_id: u64) -> eyre::Result<Option<Vec<u8>>>;
+}
+
+/// How often [`TangleCoordinator::call_block`] checks whether the call is finalized.
+const FINALITY_POLL: Duration = Duration::from_secs(1);
+
+/// How many blocks [`TangleCoordinator::call_block`] walks back from the finalized head to find
+/// the block of a call.
+const MAX_CALL_AGE: u32 = 256;
+
+/// The [`Coordinator`] of a Tangle service, reading the operators and the job calls from the
+/// chain.
+#[derive(Clone, KeystoreContext, TangleClientContext, ServicesContext, MPCContext)]
+pub struct TangleCoordinator {
+    #[config]
+    config: sdk::config::StdGadgetConfiguration,
+}
+
+impl TangleCoordinator {
+    /// The coordinator of the service configured in `config`.
+    pub fn new(config: sdk::config::StdGadgetConfiguration) -> Self {
+        Self { config }
+    }
+
+    /// The first finalized block whose next job call id is past `call_id`, the one the call
+    /// was made in, waiting for the call to be finalized.
+    async fn finalized_call_block(
+        &self,
+        call_id: u64,
+    ) -> eyre::Result<Block<TangleConfig, TangleClient>> {
+        let client = self.tangle_client().await?;
+        let next_call_id = api::storage().services().next_job_call_id();
+        let mut block = loop {
+            let block = client.blocks().at_latest().await?;
+            if block.storage().fetch_or_default(&next_call_id).await? > call_id {
+                break block;
+            }
+            tokio::time::sleep(FINALITY_POLL).await;
+        };
+        for _ in 0..MAX_CALL_AGE {
+            let parent = client.blocks().at(block.header().parent_hash).await?;
+            if parent.storage().fetch_or_default(&next_call_id).await? <= call_id {
+                return Ok(block);
+            }
+            block = parent;
+        }
+        Err(eyre::eyre!(
+            "The job call {call_id} is more than {MAX_CALL_AGE} blocks old"
+        ))
+    }
+}
+
+#[async_trait::async_trait]
+impl Coordinator for TangleCoordinator {
+    /// The id of the configured service.
+    fn service_id(&self) -> eyre::Result<u64> {
+        self.config
+            .protocol_specific
+            .tangle()
+            .map_err(|e| eyre::eyre!("Failed to get tangle configuration: {e}"))?
+            .service_id
+            .ok_or_else(|| eyre::eyre!("No service id configured"))
+    }
+
+    async fn operators(&self) -> eyre::Result<BTreeMap<AccountId32, ecdsa::Public>> {
+        self.current_service_operators_ecdsa_keys().await
+    }
+
+    async fn restakes(&self) -> eyre::Result<Vec<(AccountId32, Percent)>> {
+        let client = self.tangle_client().await?;
+        Ok(self.current_service_operators(&client).await?)
+    }
+
+    /// The paused operators are the ones offline on-chain, in the finalized block of the call.
+    async fn paused_operators(
+        &self,
+        call_id: u64,
+        operators: &BTreeMap<AccountId32, ecdsa::Public>,
+    ) -> eyre::Result<BTreeSet<ecdsa::Public>> {
+        let storage = self.finalized_call_block(call_id).await?.storage();
+        let mut paused = BTreeSet::new();
+        for (account, key) in operators {
+            let address = api::storage().multi_asset_delegation().operators(account);
+            if let Some(metadata) = storage.fetch(&address).await? {
+                if matches!(metadata.status, OperatorStatus::Inactive) {
+                    paused.insert(*key);
+                }
+            }
+        }
+        Ok(paused)
+    }
+
+    async fn set_online(&self, online: bool) -> eyre::Result<()> {
+        let client = self.tangle_client().await?;
+        let signer = self.config.first_sr25519_signer()?;
+        let mad = api::tx().multi_asset_delegation();
+        if online {
+            sdk::tx::tangle::send(&client, &signer, &mad.go_online()).await?;
+        } else {
+            sdk::tx::tangle::send(&client, &signer, &mad.go_offline()).await?;
+        }
+        Ok(())
+    }
+
+    async fn current_call_id(&self) -> eyre::Result<u64> {
+        MPCContext::current_call_id(self).await
+    }
+
+    /// The first finalized block whose next job call id is past `call_id`, waiting for the
+    /// call to be finalized.
+    async fn call_block(&self, call_id: u64) -> eyre::Result<u64> {
+        Ok(self.finalized_call_block(call_id).await?.number().into())
+    }
+
+    /// The `Timestamp::Now` of the finalized block of the call, set by its author.
+    async fn call_time(&self, call_id: u64) -> eyre::Result<u64> {
+        let storage = self.finalized_call_block(call_id).await?.storage();
+        let millis = storage
+            .fetch(&api::storage().timestamp().now())
+            .await?
+            .ok_or_else(|| eyre::eyre!("No timestamp in the block of the job call {call_id}"))?;
+        Ok(millis / 1000)
+    }
+
+    /// The caller of the `JobCalled` event of the call, in the block it was made in.
+    async fn caller(&self, call_id: u64) -> eyre::Result<AccountId32> {
+        let service_id = self.service_id()?;
+        let events = self.finalized_call_block(call_id).await?.events().await?;
+        for event in events.find::<api::services::events::JobCalled>() {
+            let event = event?;
+            if event.service_id == service_id && event.call_id == call_id {
+                return Ok(event.caller);
+            }
+        }
+        Err(eyre::eyre!(
+            "No event of the job call {call_id} in its block"
+        ))
+    }
+
+    async fn service_owner(&self) -> eyre::Result<AccountId32> {
+        let service_id = self.service_id()?;
+        let client = self.tangle_client().await?;
+        let address = api::storage().services().instances(service_id);
+        let service = client.storage().at_latest().await?.fetch(&address).await?;
+        let service =
+            service.ok_or_else(|| eyre::eyre!("The service {service_id} is not found"))?;
+        Ok(service.owner)
+    }
+
+    /// The operators are read again at every finalized block.
+    async fn operators_changed(&self) -> eyre::Result<()> {
+        let client = self.tangle_client().await?;
+        let operators = self.operators().await?;
+        let mut blocks = client.blocks().subscribe_finalized().await?;
+        while let Some(block) = blocks.next().await {
+            block?;
+            if self.operators().await? != operators {
+                return Ok(());
+            }
+        }
+        Err(eyre::eyre!("The finalized block subscription ended"))
+    }
+
+    /// The result is the first field of the stored result of the call, the bytes returned by
+    /// the job.
+    async fn submitted_result(&self, call_id: u64) -> eyre::Result<Option<Vec<u8>>> {
+        let service_id = self.service_id()?;
+        let client = self.tangle_client().await?;
+        let address = api::storage().services().job_results(service_id, call_id);
+        let Some(result) = client.storage().at_latest().await?.fetch(&address).await? else {
+            return Ok(None);
+        };
+        match result.result.0.first() {
+            Some(Field::Bytes(BoundedVec(bytes))) => Ok(Some(bytes.clone())),
+            _ => Err(eyre::eyre!(
+                "The result of the job call {call_id} is not bytes"
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::testing::{
+        keygen_on_all, operator_contexts, sign_on_all, MockCoordinator, MockNetwork,
+        MockNetworkConfig, TempDir,
+    };
+    use crate::FrostContext;
+    use frost_core::Ciphersuite;
+    use gadget_sdk::keystore::Backend;
+    use gadget_sdk::subxt_core::ext::sp_core::Pair;
+
+    /// A [`MockCoordinator`] counting the reads of its operators, which change when told to.
+    struct CountingCoordinator {
+        inner: MockCoordinator,
+        reads: std::sync::Arc<std::sync::atomic::AtomicUsize>,
+        change: std::sync::Arc<tokio::sync::Notify>,
+    }
+
+    #[async_trait::async_trait]
+    impl Coordinator for CountingCoordinator {
+        fn service_id(&self) -> eyre::Result<u64> {
+            self.inner.service_id()
+        }
+
+        async fn operators(&self) -> eyre::Result<BTreeMap<AccountId32, ecdsa::Public>> {
+            self.reads.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            self.inner.operators().await
+        }
+
+        async fn restakes(&self) -> eyre::Result<Vec<(AccountId32, Percent)>> {
+            self.inner.restakes().await
+        }
+
+        async fn paused_operators(
+            &self,
+            call_id: u64,
+            operators: &BTreeMap<AccountId32, ecdsa::Public>,
+        ) -> eyre::Result<BTreeSet<ecdsa::Public>> {
+            self.inner.paused_operators(call_id, operators).await
+        }
+
+        async fn set_online(&self, online: bool) -> eyre::Result<()> {
+            self.inner.set_online(online).await
+        }
+
+        async fn current_call_id(&self) -> eyre::Result<u64> {
+            self.inner.current_call_id().await
+        }
+
+        async fn call_block(&self, call_id: u64) -> eyre::Result<u64> {
+            self.inner.call_block(call_id).await
+        }
+
+        async fn call_time(&self, call_id: u64) -> eyre::Result<u64> {
+            self.inner.call_time(call_id).await
+        }
+
+        async fn caller(&self, call_id: u64) -> eyre::Result<AccountId32> {
+            self.inner.caller(call_id).await
+        }
+
+        async fn service_owner(&self) -> eyre::Result<AccountId32> {
+            self.inner.service_owner().await
+        }
+
+        async fn operators_changed(&self) -> eyre::Result<()> {
+            self.change.notified().await;
+            Ok(())
+        }
+
+        async fn submitted_result(&self, call_id: u64) -> eyre::Result<Option<Vec<u8>>> {
+            self.inner.submitted_result(call_id).await
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn keygen_runs_through_a_custom_coordinator() {
+        type C = frost_secp256k1::Secp256K1Sha256;
+        // The parties start one after the other, the latency lets them all subscribe before
+        // the first broadcast arrives.
+        let network = MockNetwork::new(MockNetworkConfig {
+            latency: Duration::from_millis(50),
+            loss: 0.0,
+        });
+        let dir = TempDir::new("coordinator");
+        let contexts = operator_contexts(&network, &dir, 3, 926);
+        let key = keygen_on_all(&contexts, C::ID, 2).await;
+        for context in &contexts {
+            assert!(context.keygen_entry(&hex::encode(&key)).unwrap().is_some());
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn jobs_share_the_cached_operator_set() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use std::sync::Arc;
+
+        type C = frost_secp256k1::Secp256K1Sha256;
+        let network = MockNetwork::new(MockNetworkConfig {
+            latency: Duration::from_millis(50),
+            loss: 0.0,
+        });
+        let dir = TempDir::new("operator-cache");
+        let contexts = operator_contexts(&network, &dir, 3, 975);
+        let operators = contexts[0].current_operators().await.unwrap();
+        let (reads, change) = (
+            Arc::new(AtomicUsize::new(0)),
+            Arc::new(tokio::sync::Notify::new()),
+        );
+        let contexts = contexts
+            .into_iter()
+            .enumerate()
+            .map(|(i, context)| {
+                // Only the reads of the first operator are counted.
+                let reads = match i {
+                    0 => reads.clone(),
+                    _ => Arc::new(AtomicUsize::new(0)),
+                };
+                context
+                    .with_coordinator(CountingCoordinator {
+                        inner: MockCoordinator {
+                            operators: operators.clone(),
+                            call_id: 975,
+                            change_after: None,
+                            caller: None,
+                        },
+                        reads,
+                        change: change.clone(),
+                    })
+                    .with_operator_cache(Duration::from_secs(60))
+            })
+            .collect::<Vec<_>>();
+
+        let pubkey = keygen_on_all(&contexts, C::ID, 3).await;
+        for result in sign_on_all(&contexts, &pubkey, b"cached").await {
+            result.unwrap();
+        }
+        // The keygen read the operators, the signing reused them.
+        assert_eq!(reads.load(Ordering::SeqCst), 1);
+
+        // Once the operators change, the next job reads them again.
+        change.notify_waiters();
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while reads.load(Ordering::SeqCst) == 1 {
+                contexts[0].current_operators().await.unwrap();
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("the cached operator set was not dropped");
+        assert_eq!(reads.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn signings_reuse_the_persisted_operator_set() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use std::sync::Arc;
+
+        type C = frost_ed25519::Ed25519Sha512;
+        let network = MockNetwork::new(MockNetworkConfig {
+            latency: Duration::from_millis(50),
+            loss: 0.0,
+        });
+        let dir = TempDir::new("peer-mapping");
+        let contexts = operator_contexts(&network, &dir, 3, 985);
+        let operators = contexts[0].current_operators().await.unwrap();
+        let (reads, change) = (
+            Arc::new(AtomicUsize::new(0)),
+            Arc::new(tokio::sync::Notify::new()),
+        );
+        let contexts = contexts
+            .into_iter()
+            .enumerate()
+            .map(|(i, context)| {
+                // Only the reads of the first operator are counted.
+                let reads = match i {
+                    0 => reads.clone(),
+                    _ => Arc::new(AtomicUsize::new(0)),
+                };
+                context
+                    .with_coordinator(CountingCoordinator {
+                        inner: MockCoordinator {
+                            operators: operators.clone(),
+                            call_id: 985,
+                            change_after: None,
+                            caller: None,
+                        },
+                        reads,
+                        change: change.clone(),
+                    })
+                    .with_persisted_peer_mapping()
+            })
+            .collect::<Vec<_>>();
+
+        let pubkey = keygen_on_all(&contexts, C::ID, 2).await;
+        assert_eq!(reads.load(Ordering::SeqCst), 1);
+        for msg in [&b"first"[..], b"second"] {
+            // One of the operators is left out of the 2 signers.
+            let signatures = sign_on_all(&contexts, &pubkey, msg).await;
+            assert_eq!(signatures.into_iter().flatten().count(), 2);
+        }
+        // Both signings reused the operator set persisted by the keygen.
+        assert_eq!(reads.load(Ordering::SeqCst), 1);
+        assert_eq!(
+            crate::peer_mapping::load(&contexts[0].store).unwrap(),
+            Some(operators)
+        );
+
+        // Once the operators change, the persisted set is dropped and read again.
+        change.notify_waiters();
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while reads.load(Ordering::SeqCst) == 1 {
+                contexts[0].current_operators().await.unwrap();
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("the persisted operator set was not dropped");
+        assert_eq!(reads.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn duplicate_instances_are_detected() {
+        type C = frost_secp256k1::Secp256K1Sha256;
+        let network = MockNetwork::new(Default::default());
+        let dir = TempDir::new("duplicate-instance");
+        // Two instances run with the key of the first operator, registered by two accounts.
+        let configs = [1u8, 1, 2]
+            .into_iter()
+            .enumerate()
+            .map(|(instance, seed)| {
+                let keystore = dir.0.join(instance.to_string());
+                std::fs::create_dir_all(&keystore).unwrap();
+                let mut config = sdk::config::StdGadgetConfiguration::default();
+                config.keystore_uri = format!("file:{}", keystore.display());
+                config
+                    .keystore()
+                    .unwrap()
+                    .ecdsa_generate_new(Some(&[seed; 32]))
+                    .unwrap();
+                config
+            })
+            .collect::<Vec<_>>();
+        let operators = configs
+            .iter()
+            .enumerate()
+            .map(|(instance, config)| {
+                let key = config.first_ecdsa_signer().unwrap().signer().public();
+                (AccountId32([instance as u8; 32]), key)
+            })
+            .collect::<BTreeMap<_, _>>();
+
+        for config in configs.into_iter().take(2) {
+            let key = config.first_ecdsa_signer().unwrap().signer().public();
+            let context = FrostContext::with_network(config, network.multiplexer(key))
+                .unwrap()
+                .with_coordinator(MockCoordinator {
+                    operators: operators.clone(),
+                    call_id: 937,
+                    change_after: None,
+                    caller: None,
+                });
+            let error = context.check_operators().await.unwrap_err();
+            assert!(error.is::<crate::operators::DuplicateInstance>(), "{error}");
+            let result = crate::keygen::keygen(C::ID.to_string(), 2, context).await;
+            assert!(
+                matches!(
+                    &result,
+                    Err(crate::keygen::Error::DuplicateInstance(e)) if e.count == 2
+                ),
+                "{result:?}"
+            );
+        }
+    }
+}
@@ -0,0 +1,319 @@
+//! Dead letters: the protocol messages this node failed to send.
+//!
+//! By default, a message the network fails to send aborts the protocol. With
+//! [`FrostContext::with_dead_letters`], the message is instead written to the store along with
+//! the error, and the protocol goes on without it: it may still complete if the message was
+//! not needed, or fail later on a timeout of the recipients, the dead letters telling why. They
+//! are read back with [`dead_letters`].
+//!
+//! The dead letters hold the messages as sent, secret shares included, and are kept as safe as
+//! the store.
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use api::services::events::JobCalled;
+use gadget_sdk as sdk;
+use gadget_sdk::futures::stream::BoxStream;
+use gadget_sdk::futures::{Sink, StreamExt};
+use gadget_sdk::parking_lot::Mutex;
+use round_based::{Delivery, Incoming, MessageDestination, Outgoing, ProtocolMessage};
+use sdk::event_listener::tangle::{
+    jobs::{services_post_processor, services_pre_processor},
+    TangleEventListener,
+};
+use sdk::tangle_subxt::tangle_testnet_runtime::api;
+use serde::{Deserialize, Serialize};
+
+use crate::codec::Envelope;
+use crate::kv::SharedDynKVStore;
+use crate::FrostContext;
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+}
+
+/// A protocol message this node failed to send.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct DeadLetter {
+    /// The protocol of the message, `keygen`, `signing` or `batch_signing`.
+    pub protocol: String,
+    /// The party index of this node.
+    pub sender: u16,
+    /// The party index of the recipient, `None` for a broadcast.
+    pub recipient: Option<u16>,
+    pub round: u16,
+    /// The codec version of the message.
+    pub version: u8,
+    /// The hex encoded message.
+    pub payload: String,
+    /// Why the message could not be sent.
+    pub error: String,
+}
+
+fn store_key(call_id: u64) -> String {
+    format!("dead_letters/{call_id}")
+}
+
+/// Read the dead letters of the job call `call_id` from the store.
+pub(crate) fn read(
+    store: &SharedDynKVStore<String, Vec<u8>>,
+    call_id: u64,
+) -> Result<Vec<DeadLetter>, Error> {
+    match store.get(&store_key(call_id))? {
+        Some(bytes) => Ok(serde_json::from_slice(&bytes)?),
+        None => Ok(Vec::new()),
+    }
+}
+
+/// Get the protocol messages this node failed to send during a keygen or signing.
+///
+/// # Parameters
+/// - `call_id`: The call id of the job.
+///
+/// # Returns
+/// The JSON list of the [`DeadLetter`]s of the job, empty if every message was sent or dead
+/// letters are disabled.
+#[sdk::job(
+    id = 17,
+    params(call_id),
+    result(_),
+    event_listener(
+        listener = TangleEventListener::<FrostContext, JobCalled>,
+        pre_processor = services_pre_processor,
+        post_processor = services_post_processor,
+    )
+)]
+#[tracing::instrument(skip_all, parent = context.config.span.clone(), err)]
+pub async fn dead_letters(call_id: u64, context: FrostContext) -> Result<Vec<u8>, Error> {
+    let letters = read(&context.store, call_id)?;
+    Ok(serde_json::to_vec(&letters)?)
+}
+
+/// A delivery writing the messages it fails to send to the store, see [`DeadLetters::wrap`].
+pub(crate) type DeadLetterDelivery<D> = (
+    BoxStream<'static, Result<Incoming<Envelope>, <D as Delivery<Envelope>>::ReceiveError>>,
+    DeadLetterSink<<D as Delivery<Envelope>>::Send>,
+);
+
+/// Writes the messages of a protocol that could not be sent to the store.
+#[derive(Clone)]
+pub(crate) struct DeadLetters {
+    store: SharedDynKVStore<String, Vec<u8>>,
+    call_id: u64,
+    protocol: String,
+    party_index: u16,
+    /// Serializes the appends to the dead letters of the call.
+    lock: Arc<Mutex<()>>,
+}
+
+impl DeadLetters {
+    fn push(&self, outgoing: Outgoing<Envelope>, error: &str) {
+        let letter = DeadLetter {
+            protocol: self.protocol.clone(),
+            sender: self.party_index,
+            recipient: match outgoing.recipient {
+                MessageDestination::AllParties => None,
+                MessageDestination::OneParty(j) => Some(j),
+            },
+            round: outgoing.msg.round(),
+            version: outgoing.msg.version(),
+            payload: hex::encode(outgoing.msg.payload()),
+            error: error.to_string(),
+        };
+        tracing::warn!(
+            call_id = self.call_id,
+            round = letter.round,
+            recipient = ?letter.recipient,
+            %error,
+            "Failed to send a protocol message, writing it to the dead letters"
+        );
+        let _lock = self.lock.lock();
+        let written = read(&self.store, self.call_id).and_then(|mut letters| {
+            letters.push(letter);
+            let bytes = serde_json::to_vec(&letters)?;
+            Ok(self.store.set(store_key(self.call_id), bytes)?)
+        });
+        // Failing to write it does not change the outcome of the job, it is only logged.
+        if let Err(e) = written {
+            tracing::warn!(call_id = self.call_id, error = %e, "Failed to save a dead letter");
+        }
+    }
+
+    /// Write the messages that `delivery` fails to send to the dead letters, if enabled,
+    /// instead of failing.
+    pub(crate) fn wrap<D>(letters: Option<&Self>, delivery: D) -> DeadLetterDelivery<D>
+    where
+        D: Delivery<Envelope>,
+        D::Receive: Send + 'static,
+    {
+        let (incoming, outgoing) = delivery.split();
+        let outgoing = DeadLetterSink {
+            inner: outgoing,
+            letters: letters.cloned(),
+            pending: Vec::new(),
+            failure: None,
+        };
+        (incoming.boxed(), outgoing)
+    }
+}
+
+/// A sink writing the messages its inner sink fails to send to the dead letters.
+///
+/// A sink is not usable once it failed, so every later message goes to the dead letters too.
+pub(crate) struct DeadLetterSink<S> {
+    inner: S,
+    letters: Option<DeadLetters>,
+    /// The messages sent since the last flush, lost if it fails.
+    pending: Vec<Outgoing<Envelope>>,
+    /// The error the inner sink failed with.
+    failure: Option<String>,
+}
+
+impl<S> DeadLetterSink<S>
+where
+    S: Sink<Outgoing<Envelope>> + Unpin,
+    S::Error: std::fmt::Display,
+{
+    /// Handle the `result` of the inner sink, writing the pending messages to the dead letters
+    /// if it failed.
+    fn handle(&mut self, result: Result<(), S::Error>) -> Result<(), S::Error> {
+        let (Err(e), Some(letters)) = (&result, &self.letters) else {
+            return result;
+        };
+        let error = e.to_string();
+        for outgoing in self.pending.drain(..) {
+            letters.push(outgoing, &error);
+        }
+        self.failure = Some(error);
+        Ok(())
+    }
+}
+
+impl<S> Sink<Outgoing<Envelope>> for DeadLetterSink<S>
+where
+    S: Sink<Outgoing<Envelope>> + Unpin,
+    S::Error: std::fmt::Display,
+{
+    type Error = S::Error;
+
+    fn poll_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        let this = self.get_mut();
+        if this.failure.is_some() {
+            return Poll::Ready(Ok(()));
+        }
+        Pin::new(&mut this.inner)
+            .poll_ready(cx)
+            .map(|result| this.handle(result))
+    }
+
+    fn start_send(self: Pin<&mut Self>, item: Outgoing<Envelope>) -> Result<(), Self::Error> {
+        let this = self.get_mut();
+        if let (Some(error), Some(letters)) = (&this.failure, &this.letters) {
+            letters.push(item, error);
+            return Ok(());
+        }
+        if this.letters.is_some() {
+            this.pending.push(item.clone());
+        }
+        let result = Pin::new(&mut this.inner).start_send(item);
+        this.handle(result)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        let this = self.get_mut();
+        if this.failure.is_some() {
+            return Poll::Ready(Ok(()));
+        }
+        Pin::new(&mut this.inner).poll_flush(cx).map(|result| {
+            let result = this.handle(result);
+            this.pending.clear();
+            result
+        })
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        let this = self.get_mut();
+        if this.failure.is_some() {
+            return Poll::Ready(Ok(()));
+        }
+        Pin::new(&mut this.inner).poll_close(cx).map(|result| {
+            let result = this.handle(result);
+            this.pending.clear();
+            result
+        })
+    }
+}
+
+impl FrostContext {
+    /// The dead letters of the protocol of job call `call_id`, if enabled.
+    pub(crate) fn dead_letters(
+        &self,
+        call_id: u64,
+        protocol: &str,
+        party_index: u16,
+    ) -> Option<DeadLetters> {
+        self.dead_letters.then(|| DeadLetters {
+            store: self.store.clone(),
+            call_id,
+            protocol: protocol.to_string(),
+            party_index,
+            lock: Default::default(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{operator_contexts, MockNetwork, TempDir};
+    use gadget_sdk::futures::{future, sink, stream, SinkExt};
+
+    #[tokio::test]
+    async fn failing_send_lands_in_the_dead_letters() {
+        let network = MockNetwork::new(Default::default());
+        let dir = TempDir::new("dead-letters");
+        let context = operator_contexts(&network, &dir, 1, 969)
+            .remove(0)
+            .with_dead_letters(true);
+
+        // A network that never delivers anything.
+        let incoming = stream::pending::<Result<Incoming<Envelope>, std::io::Error>>();
+        let outgoing = sink::unfold((), |(), _: Outgoing<Envelope>| {
+            future::ready(Err::<(), _>(std::io::Error::other("peer unreachable")))
+        });
+        let letters = context.dead_letters(969, "signing", 0);
+        let (_, mut outgoing) = DeadLetters::wrap(letters.as_ref(), (incoming, outgoing));
+        let sent = [
+            Outgoing::broadcast(Envelope::new(1, 1, vec![1, 2, 3])),
+            Outgoing::p2p(1, Envelope::new(1, 2, vec![4, 5, 6])),
+        ];
+        for outgoing_msg in sent.clone() {
+            outgoing.send(outgoing_msg).await.unwrap();
+        }
+
+        let letters = dead_letters(969, context.clone()).await.unwrap();
+        let letters: Vec<DeadLetter> = serde_json::from_slice(&letters).unwrap();
+        assert_eq!(letters.len(), 2);
+        assert_eq!(letters[0].recipient, None);
+        assert_eq!(letters[0].payload, "010203");
+        assert_eq!(letters[1].recipient, Some(1));
+        assert_eq!(letters[1].round, 2);
+        assert!(letters
+            .iter()
+            .all(|letter| letter.protocol == "signing" && letter.error == "peer unreachable"));
+        assert!(read(&context.store, 970).unwrap().is_empty());
+
+        // Without dead letters, the failure aborts the protocol as before.
+        let incoming = stream::pending::<Result<Incoming<Envelope>, std::io::Error>>();
+        let outgoing = sink::unfold((), |(), _: Outgoing<Envelope>| {
+            future::ready(Err::<(), _>(std::io::Error::other("peer unreachable")))
+        });
+        let (_, mut outgoing) = DeadLetters::wrap(None, (incoming, outgoing));
+        assert!(outgoing.send(sent[0].clone()).await.is_err());
+    }
+}
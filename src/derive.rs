@@ -0,0 +1,191 @@
+//! Child keys derived from a group key by additive tweaks.
+//!
+//! The child at `index` is the group key plus `HDKG(DST || vk || index)` times the generator,
+//! see [`tweak`]. There is no chain code and no HMAC-SHA512, so the derivation is not
+//! compatible with BIP32 nor BIP32-Ed25519: a wallet deriving from the same key at the same
+//! index gets another key, the child keys can only be computed with [`derive_verifying_key`].
+use std::collections::BTreeMap;
+
+use frost_core::keys::{KeyPackage, PublicKeyPackage, SigningShare, VerifyingShare};
+use frost_core::{Ciphersuite, Element, Field, Group, Scalar, VerifyingKey};
+
+/// Domain separator of the derivation tweak.
+const DERIVE_DST: &[u8] = b"frost-blueprint-derive";
+/// Domain separator of the ephemeral tweak.
+const EPHEMERAL_DST: &[u8] = b"frost-blueprint-ephemeral";
+
+/// Key derivation error
+#[derive(Debug, thiserror::Error)]
+pub enum Error<C: Ciphersuite> {
+    #[error("Ciphersuite {0} does not support key derivation")]
+    Unsupported(&'static str),
+    #[error(transparent)]
+    Frost(#[from] frost_core::Error<C>),
+}
+
+/// The tweak added to a key to derive its child at `index`.
+///
+/// It only depends on the parent group key and the index, so every signer computes the same
+/// tweak without any interaction.
+pub fn tweak<C: Ciphersuite>(
+    verifying_key: &VerifyingKey<C>,
+    index: u32,
+) -> Result<Scalar<C>, Error<C>> {
+    let mut m = DERIVE_DST.to_vec();
+    m.extend(verifying_key.serialize()?);
+    m.extend(index.to_be_bytes());
+    C::HDKG(&m).ok_or(Error::Unsupported(C::ID))
+}
+
+/// The tweak of the one-time ephemeral key drawn from `randomness`.
+///
+/// The signers draw the `randomness` together, see [`randomness`](crate::rounds::randomness),
+/// so they all derive the same tweak while no one else can predict it: the ephemeral key cannot
+/// be linked to the group key, even by those who know the job call.
+pub fn ephemeral_tweak<C: Ciphersuite>(
+    verifying_key: &VerifyingKey<C>,
+    randomness: &[u8],
+) -> Result<Scalar<C>, Error<C>> {
+    let mut m = EPHEMERAL_DST.to_vec();
+    m.extend(verifying_key.serialize()?);
+    m.extend(randomness);
+    C::HDKG(&m).ok_or(Error::Unsupported(C::ID))
+}
+
+/// Derive the child group public key at `index`, this is what clients verify signatures
+/// produced by [`derive_child`] packages against.
+pub fn derive_verifying_key<C: Ciphersuite>(
+    verifying_key: &VerifyingKey<C>,
+    index: u32,
+) -> Result<VerifyingKey<C>, Error<C>> {
+    tweak_verifying_key(verifying_key, tweak(verifying_key, index)?)
+}
+
+fn tweak_verifying_key<C: Ciphersuite>(
+    verifying_key: &VerifyingKey<C>,
+    t: Scalar<C>,
+) -> Result<VerifyingKey<C>, Error<C>> {
+    let element = tweak_element::<C>(&verifying_key.serialize()?, t)?;
+    Ok(VerifyingKey::deserialize(&element)?)
+}
+
+/// Derive the child key at `index` from an operator's key packages.
+///
+/// Like a non-hardened derivation, anyone knowing the group key can compute the child key, but
+/// it is not the BIP32 one, see [`derive`](self). The tweak is added to the group key, to the
+/// operator's signing share and to every verifying share. Since the Lagrange coefficients of any signing
+/// set sum to one, shares tweaked this way still interpolate to the tweaked group secret, so the
+/// derived packages can be used as is by the signing protocol.
+pub fn derive_child<C: Ciphersuite>(
+    key_pkg: &KeyPackage<C>,
+    pub_key_pkg: &PublicKeyPackage<C>,
+    index: u32,
+) -> Result<(KeyPackage<C>, PublicKeyPackage<C>), Error<C>> {
+    let t = tweak(pub_key_pkg.verifying_key(), index)?;
+    derive_with_tweak(key_pkg, pub_key_pkg, t)
+}
+
+/// Derive the one-time ephemeral key drawn from `randomness`, see [`ephemeral_tweak`].
+///
+/// The ephemeral group key is the verifying key of the returned public key package.
+pub fn derive_ephemeral<C: Ciphersuite>(
+    key_pkg: &KeyPackage<C>,
+    pub_key_pkg: &PublicKeyPackage<C>,
+    randomness: &[u8],
+) -> Result<(KeyPackage<C>, PublicKeyPackage<C>), Error<C>> {
+    let t = ephemeral_tweak(pub_key_pkg.verifying_key(), randomness)?;
+    derive_with_tweak(key_pkg, pub_key_pkg, t)
+}
+
+fn derive_with_tweak<C: Ciphersuite>(
+    key_pkg: &KeyPackage<C>,
+    pub_key_pkg: &PublicKeyPackage<C>,
+    t: Scalar<C>,
+) -> Result<(KeyPackage<C>, PublicKeyPackage<C>), Error<C>> {
+    let verifying_key = tweak_verifying_key(pub_key_pkg.verifying_key(), t)?;
+
+    let signing_share = {
+        let bytes = key_pkg.signing_share().serialize();
+        let s = <C::Group as Group>::Field::deserialize(&scalar_serialization::<C>(bytes)?)
+            .map_err(frost_core::Error::from)?;
+        SigningShare::deserialize(<C::Group as Group>::Field::serialize(&(s + t)).as_ref())?
+    };
+    let tweak_share = |share: &VerifyingShare<C>| -> Result<VerifyingShare<C>, Error<C>> {
+        let element = tweak_element::<C>(&share.serialize()?, t)?;
+        Ok(VerifyingShare::deserialize(&element)?)
+    };
+    let verifying_share = tweak_share(key_pkg.verifying_share())?;
+    let verifying_shares = pub_key_pkg
+        .verifying_shares()
+        .iter()
+        .map(|(id, share)| Ok((*id, tweak_share(share)?)))
+        .collect::<Result<BTreeMap<_, _>, Error<C>>>()?;
+
+    let key_pkg = KeyPackage::new(
+        *key_pkg.identifier(),
+        signing_share,
+        verifying_share,
+        verifying_key,
+        *key_pkg.min_signers(),
+    );
+    let pub_key_pkg = PublicKeyPackage::new(verifying_shares, verifying_key);
+    Ok((key_pkg, pub_key_pkg))
+}
+
+/// Add `t * G` to the serialized group element `bytes`.
+fn tweak_element<C: Ciphersuite>(bytes: &[u8], t: Scalar<C>) -> Result<Vec<u8>, Error<C>> {
+    let serialization = <C::Group as Group>::Serialization::try_from(bytes.to_vec())
+        .map_err(|_| frost_core::Error::DeserializationError)?;
+    let element: Element<C> =
+        <C::Group as Group>::deserialize(&serialization).map_err(frost_core::Error::from)?;
+    let tweaked = element + <C::Group as Group>::generator() * t;
+    let serialization =
+        <C::Group as Group>::serialize(&tweaked).map_err(frost_core::Error::from)?;
+    Ok(serialization.as_ref().to_vec())
+}
+
+fn scalar_serialization<C: Ciphersuite>(
+    bytes: Vec<u8>,
+) -> Result<<<C::Group as Group>::Field as Field>::Serialization, Error<C>> {
+    <<C::Group as Group>::Field as Field>::Serialization::try_from(bytes)
+        .map_err(|_| frost_core::Error::DeserializationError.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use frost_secp256k1::Secp256K1Sha256;
+    use gadget_sdk::random::rand::rngs::StdRng;
+    use gadget_sdk::random::SeedableRng;
+
+    #[test]
+    fn derived_packages_match_derived_verifying_key() {
+        let rng = &mut StdRng::seed_from_u64(42);
+        let (shares, pub_key_pkg) = frost_core::keys::generate_with_dealer::<Secp256K1Sha256, _>(
+            3,
+            2,
+            frost_core::keys::IdentifierList::Default,
+            rng,
+        )
+        .unwrap();
+        let expected = derive_verifying_key(pub_key_pkg.verifying_key(), 7).unwrap();
+        assert_ne!(&expected, pub_key_pkg.verifying_key());
+        for share in shares.into_values() {
+            let key_pkg = KeyPackage::try_from(share).unwrap();
+            let (child, child_pub) = derive_child(&key_pkg, &pub_key_pkg, 7).unwrap();
+            assert_eq!(child.verifying_key(), &expected);
+            assert_eq!(child_pub.verifying_key(), &expected);
+            assert_eq!(
+                &VerifyingShare::from(*child.signing_share()),
+                child.verifying_share()
+            );
+            assert_eq!(
+                child_pub.verifying_shares().get(child.identifier()),
+                Some(child.verifying_share())
+            );
+        }
+        // Different indices derive different keys.
+        let other = derive_verifying_key(pub_key_pkg.verifying_key(), 8).unwrap();
+        assert_ne!(other, expected);
+    }
+}
@@ -0,0 +1,308 @@
+//! Diagnostics of the failed protocols.
+//!
+//! When enabled with [`FrostContext::with_diagnostics`], the messages received during a keygen
+//! or signing are recorded, and if the protocol fails, a [`Bundle`] with them and the error is
+//! written to the store under the job call id, for a post-mortem with [`get_diagnostics`].
+//!
+//! The payloads of the point-to-point messages are never recorded, since the keygen ones carry
+//! secret shares.
+use std::sync::Arc;
+use std::time::Instant;
+
+use api::services::events::JobCalled;
+use gadget_sdk as sdk;
+use gadget_sdk::futures::stream::BoxStream;
+use gadget_sdk::futures::{StreamExt, TryStreamExt};
+use round_based::{Delivery, Incoming, ProtocolMessage};
+use sdk::event_listener::tangle::{
+    jobs::{services_post_processor, services_pre_processor},
+    TangleEventListener,
+};
+use sdk::parking_lot::Mutex;
+use sdk::tangle_subxt::tangle_testnet_runtime::api;
+
+use crate::codec::Envelope;
+use crate::kv::SharedDynKVStore;
+use crate::FrostContext;
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("No diagnostics for job call {0}")]
+    NotFound(u64),
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+}
+
+/// The diagnostics of a failed protocol.
+#[derive(Clone, Debug, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct Bundle {
+    /// The job call id.
+    pub call_id: u64,
+    /// The protocol, `keygen` or `signing`.
+    pub protocol: String,
+    /// The ciphersuite `ID`.
+    pub ciphersuite: String,
+    /// The index of this party.
+    pub party_index: u16,
+    /// The hex encoded ECDSA keys of the parties, by index.
+    pub parties: Vec<String>,
+    /// The messages received before the failure, in order.
+    pub received: Vec<ReceivedMessage>,
+    /// The error the protocol failed with, including the blamed parties if any.
+    pub error: String,
+}
+
+/// A message received during a protocol.
+#[derive(Clone, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct ReceivedMessage {
+    pub sender: u16,
+    pub round: u16,
+    pub broadcast: bool,
+    /// Milliseconds since the protocol started.
+    pub elapsed_ms: u64,
+    /// The hex encoded message, only for broadcast messages.
+    pub payload: Option<String>,
+}
+
+/// A delivery recording its incoming messages, see [`Recorder::record`].
+pub(crate) type RecordedDelivery<D> = (
+    BoxStream<'static, Result<Incoming<Envelope>, <D as Delivery<Envelope>>::ReceiveError>>,
+    <D as Delivery<Envelope>>::Send,
+);
+
+/// Records the messages of a protocol, see [`Recorder::record`].
+#[derive(Clone, Debug)]
+pub(crate) struct Recorder {
+    started: Instant,
+    bundle: Arc<Mutex<Bundle>>,
+}
+
+impl Recorder {
+    pub(crate) fn new(
+        call_id: u64,
+        protocol: &str,
+        ciphersuite: &str,
+        party_index: u16,
+        parties: impl IntoIterator<Item = impl AsRef<[u8]>>,
+    ) -> Self {
+        let bundle = Bundle {
+            call_id,
+            protocol: protocol.to_string(),
+            ciphersuite: ciphersuite.to_string(),
+            party_index,
+            parties: parties.into_iter().map(hex::encode).collect(),
+            ..Default::default()
+        };
+        Self {
+            started: Instant::now(),
+            bundle: Arc::new(Mutex::new(bundle)),
+        }
+    }
+
+    fn push(&self, incoming: &Incoming<Envelope>) {
+        let message = ReceivedMessage {
+            sender: incoming.sender,
+            round: incoming.msg.round(),
+            broadcast: incoming.is_broadcast(),
+            elapsed_ms: self.started.elapsed().as_millis() as u64,
+            payload: incoming
+                .is_broadcast()
+                .then(|| hex::encode(incoming.msg.payload())),
+        };
+        self.bundle.lock().received.push(message);
+    }
+
+    /// Record the messages received on `delivery`, if there is a recorder.
+    pub(crate) fn record<D>(recorder: Option<&Self>, delivery: D) -> RecordedDelivery<D>
+    where
+        D: Delivery<Envelope>,
+        D::Receive: Send + 'static,
+    {
+        let recorder = recorder.cloned();
+        let (incoming, outgoing) = delivery.split();
+        let incoming = incoming.inspect_ok(move |incoming| {
+            if let Some(recorder) = &recorder {
+                recorder.push(incoming);
+            }
+        });
+        (incoming.boxed(), outgoing)
+    }
+
+    /// The bundle of the protocol that failed with `error`.
+    pub(crate) fn finish(&self, error: &dyn std::fmt::Display) -> Bundle {
+        let mut bundle = self.bundle.lock().clone();
+        bundle.error = error.to_string();
+        bundle
+    }
+}
+
+fn store_key(call_id: u64) -> String {
+    format!("diagnostics/{call_id}")
+}
+
+/// Write `bundle` to the store.
+pub(crate) fn save(
+    store: &SharedDynKVStore<String, Vec<u8>>,
+    bundle: &Bundle,
+) -> Result<(), Error> {
+    Ok(store.set(store_key(bundle.call_id), serde_json::to_vec(bundle)?)?)
+}
+
+/// Read the diagnostics of the job call `call_id` from the store.
+pub(crate) fn read(
+    store: &SharedDynKVStore<String, Vec<u8>>,
+    call_id: u64,
+) -> Result<Bundle, Error> {
+    let bytes = store
+        .get(&store_key(call_id))?
+        .ok_or(Error::NotFound(call_id))?;
+    Ok(serde_json::from_slice(&bytes)?)
+}
+
+/// Get the diagnostics of a failed keygen or signing on this node.
+///
+/// # Parameters
+/// - `call_id`: The call id of the failed job.
+///
+/// # Returns
+/// The JSON [`Bundle`] of the failure.
+///
+/// # Errors
+/// - `NotFound`: If the job did not fail on this node, or diagnostics are disabled.
+#[sdk::job(
+    id = 7,
+    params(call_id),
+    result(_),
+    event_listener(
+        listener = TangleEventListener::<FrostContext, JobCalled>,
+        pre_processor = services_pre_processor,
+        post_processor = services_post_processor,
+    )
+)]
+#[tracing::instrument(skip_all, parent = context.config.span.clone(), err)]
+pub async fn get_diagnostics(call_id: u64, context: FrostContext) -> Result<Vec<u8>, Error> {
+    let bundle = read(&context.store, call_id)?;
+    Ok(serde_json::to_vec(&bundle)?)
+}
+
+impl FrostContext {
+    /// A recorder of the protocol of job call `call_id`, if diagnostics are enabled.
+    pub(crate) fn recorder(
+        &self,
+        call_id: u64,
+        protocol: &str,
+        ciphersuite: &str,
+        party_index: u16,
+        parties: impl IntoIterator<Item = impl AsRef<[u8]>>,
+    ) -> Option<Recorder> {
+        self.diagnostics
+            .then(|| Recorder::new(call_id, protocol, ciphersuite, party_index, parties))
+    }
+
+    /// Persist the diagnostics of a protocol that failed with `error`.
+    ///
+    /// Failing to write them does not change the outcome of the job, it is only logged.
+    pub(crate) fn save_diagnostics(
+        &self,
+        recorder: Option<Recorder>,
+        error: &dyn std::fmt::Display,
+    ) {
+        let Some(recorder) = recorder else {
+            return;
+        };
+        let bundle = recorder.finish(error);
+        match save(&self.store, &bundle) {
+            Ok(()) => tracing::info!(call_id = bundle.call_id, "Saved the protocol diagnostics"),
+            Err(e) => tracing::warn!(
+                call_id = bundle.call_id,
+                error = %e,
+                "Failed to save the protocol diagnostics"
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::testing::{operator_contexts, MockNetwork, MockNetworkConfig, TempDir};
+    use crate::CodecVersion;
+    use frost_core::Ciphersuite;
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn failed_keygen_leaves_secret_free_diagnostics() {
+        type C = frost_secp256k1::Secp256K1Sha256;
+        let network = MockNetwork::new(MockNetworkConfig {
+            latency: Duration::from_millis(50),
+            loss: 0.0,
+        });
+        let dir = TempDir::new("diagnostics");
+        let mut contexts = operator_contexts(&network, &dir, 3, 927)
+            .into_iter()
+            .map(|context| context.with_diagnostics(true))
+            .collect::<Vec<_>>();
+        // The last operator writes messages the others cannot read, failing the keygen.
+        let upgraded = contexts.pop().unwrap().with_codec_version(CodecVersion {
+            current: crate::codec::CODEC_VERSION + 1,
+            min_compatible: crate::codec::CODEC_VERSION + 1,
+        });
+        contexts.push(upgraded);
+
+        let runs = contexts
+            .into_iter()
+            .map(|context| {
+                tokio::spawn(async move {
+                    let result = crate::keygen::keygen(C::ID.to_string(), 2, context.clone())
+                        .await
+                        .map_err(|e| e.to_string());
+                    (result, context)
+                })
+            })
+            .collect::<Vec<_>>();
+        for run in runs {
+            let (result, context) = tokio::time::timeout(Duration::from_secs(30), run)
+                .await
+                .expect("keygen did not fail")
+                .unwrap();
+            assert!(result.is_err());
+
+            let json = get_diagnostics(927, context.clone()).await.unwrap();
+            let bundle: Bundle = serde_json::from_slice(&json).unwrap();
+            assert_eq!(bundle.call_id, 927);
+            assert_eq!(bundle.protocol, "keygen");
+            assert_eq!(bundle.parties.len(), 3);
+            assert!(bundle.error.contains("codec version"), "{}", bundle.error);
+            assert!(!bundle.received.is_empty());
+            // Only the broadcast messages are kept, and never the key material.
+            for message in &bundle.received {
+                assert_eq!(message.payload.is_some(), message.broadcast);
+            }
+        }
+    }
+
+    #[test]
+    fn point_to_point_payloads_are_not_recorded() {
+        let recorder = Recorder::new(1, "keygen", "test", 0, [[2u8; 33], [3u8; 33]]);
+        let envelope = |round| Envelope::new(1, round, vec![0xaa; 4]);
+        recorder.push(&Incoming {
+            id: 0,
+            sender: 1,
+            msg_type: round_based::MessageType::Broadcast,
+            msg: envelope(0),
+        });
+        recorder.push(&Incoming {
+            id: 1,
+            sender: 1,
+            msg_type: round_based::MessageType::P2P,
+            msg: envelope(1),
+        });
+        let bundle = recorder.finish(&"aborted");
+        assert_eq!(bundle.received[0].payload.as_deref(), Some("aaaaaaaa"));
+        assert_eq!(bundle.received[1].payload, None);
+        assert_eq!(bundle.error, "aborted");
+    }
+}
@@ -0,0 +1,110 @@
+use alloy_dyn_abi::TypedData;
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("Invalid typed data: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("Failed to hash typed data: {0}")]
+    Hash(#[from] alloy_dyn_abi::Error),
+}
+
+/// The EIP-712 digest of `typed_data`, i.e. `keccak256(0x19 || 0x01 || domainSeparator ||
+/// hashStruct(message))`.
+///
+/// `typed_data` is the JSON document accepted by `eth_signTypedData_v4`, holding the `types`,
+/// `primaryType`, `domain` and `message`. The digest only depends on that document, so every
+/// signer computes the same one.
+pub fn typed_data_digest(typed_data: &[u8]) -> Result<[u8; 32], Error> {
+    let typed_data: TypedData = serde_json::from_slice(typed_data)?;
+    Ok(typed_data.eip712_signing_hash()?.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use frost_core::keys::{IdentifierList, KeyPackage};
+    use frost_secp256k1::Secp256K1Sha256 as C;
+    use gadget_sdk::random::rand::rngs::StdRng;
+    use gadget_sdk::random::SeedableRng;
+    use std::collections::BTreeMap;
+
+    /// The example of the EIP-712 specification.
+    const MAIL: &str = r#"{
+        "types": {
+            "EIP712Domain": [
+                { "name": "name", "type": "string" },
+                { "name": "version", "type": "string" },
+                { "name": "chainId", "type": "uint256" },
+                { "name": "verifyingContract", "type": "address" }
+            ],
+            "Person": [
+                { "name": "name", "type": "string" },
+                { "name": "wallet", "type": "address" }
+            ],
+            "Mail": [
+                { "name": "from", "type": "Person" },
+                { "name": "to", "type": "Person" },
+                { "name": "contents", "type": "string" }
+            ]
+        },
+        "primaryType": "Mail",
+        "domain": {
+            "name": "Ether Mail",
+            "version": "1",
+            "chainId": 1,
+            "verifyingContract": "0xCcCCccccCCCCcCCCCCCcCcCccCcCCCcCcccccccC"
+        },
+        "message": {
+            "from": { "name": "Cow", "wallet": "0xCD2a3d9F938E13CD947Ec05AbC7FE734Df8DD826" },
+            "to": { "name": "Bob", "wallet": "0xbBbBBBBbbBBBbbbBbbBbbbbBBbBbbbbBbBbbBBbB" },
+            "contents": "Hello, Bob!"
+        }
+    }"#;
+
+    #[test]
+    fn signs_the_known_digest() {
+        let digest = typed_data_digest(MAIL.as_bytes()).unwrap();
+        assert_eq!(
+            hex::encode(digest),
+            "be609aee343fb3c4b28e1df9e632fca64fcfaede20f02e86244efddf30957bd2"
+        );
+
+        let rng = &mut StdRng::seed_from_u64(712);
+        let (shares, pub_key_pkg) =
+            frost_core::keys::generate_with_dealer::<C, _>(3, 2, IdentifierList::Default, rng)
+                .unwrap();
+        let key_pkgs = shares
+            .into_iter()
+            .take(2)
+            .map(|(id, share)| (id, KeyPackage::try_from(share).unwrap()))
+            .collect::<BTreeMap<_, _>>();
+        let (mut nonces, mut commitments) = (BTreeMap::new(), BTreeMap::new());
+        for (id, key_pkg) in &key_pkgs {
+            let (n, c) = frost_core::round1::commit(key_pkg.signing_share(), rng);
+            nonces.insert(*id, n);
+            commitments.insert(*id, c);
+        }
+        let signing_package = frost_core::SigningPackage::new(commitments, &digest);
+        let shares = key_pkgs
+            .iter()
+            .map(|(id, key_pkg)| {
+                let share =
+                    frost_core::round2::sign(&signing_package, &nonces[id], key_pkg).unwrap();
+                (*id, share)
+            })
+            .collect();
+        let signature = frost_core::aggregate(&signing_package, &shares, &pub_key_pkg).unwrap();
+        pub_key_pkg
+            .verifying_key()
+            .verify(&digest, &signature)
+            .unwrap();
+    }
+
+    #[test]
+    fn malformed_typed_data_is_rejected() {
+        assert!(matches!(
+            typed_data_digest(b"{\"primaryType\": \"Mail\"}"),
+            Err(Error::Json(_))
+        ));
+    }
+}
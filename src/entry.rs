@@ -0,0 +1,494 @@
+//! Encoding of the keygen entries in the store.
+//!
+//! An entry is the JSON envelope `{"ciphersuite", "entry", "label", "frost_core"}` of a
+//! [`KeygenEntry`]. It is stored either as is, or with [`EntryFormat::Bincode`] as a tagged
+//! bincode record holding the key packages in their compact `frost-core` serialization. Both are
+//! told apart on read, so a store can hold entries of both formats, see
+//! [`FrostContext::with_entry_format`].
+//!
+//! Both formats depend on the serialization of `frost-core`, which may change with its major
+//! version, so the entries are tagged with the major version they were written with, see
+//! [`check_frost_core`].
+//!
+//! [`FrostContext::with_entry_format`]: crate::FrostContext::with_entry_format
+use frost_core::keys::{KeyPackage, PublicKeyPackage};
+use frost_core::Ciphersuite;
+use gadget_sdk::subxt_core::ext::sp_core::ecdsa;
+use gadget_sdk::subxt_core::utils::AccountId32;
+use serde::{Deserialize, Serialize};
+
+use crate::keygen::KeygenEntry;
+use crate::redjubjub::JubjubBlake2b512;
+use crate::windows::SigningWindow;
+
+/// The first byte of the bincode entries, which never starts a JSON document.
+const BINCODE_TAG: u8 = 0xb4;
+/// The first byte of the bincode entries written before the `frost-core` version tag.
+const PRE_FROST_CORE_BINCODE_TAG: u8 = 0xb3;
+/// The first byte of the bincode entries written before the identifiers.
+const PRE_IDENTIFIERS_BINCODE_TAG: u8 = 0xb2;
+/// The first byte of the bincode entries written before the signing windows.
+const LEGACY_BINCODE_TAG: u8 = 0xb1;
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+    #[error(transparent)]
+    Bincode(#[from] bincode::Error),
+    #[error("Unknown ciphersuite: {0}")]
+    UnknownCiphersuite(String),
+    #[error("Malformed keygen entry: {0}")]
+    Malformed(String),
+    #[error("The keygen entry has no ciphersuite, and its keys match none of the supported ones")]
+    NoMatchingCiphersuite,
+    #[error(
+        "The keygen entry was written with frost-core {written}, but this node reads the entries \
+         of frost-core {minimum} to {FROST_CORE_VERSION}: migrate it by exporting its key packages \
+         with a node of frost-core {written} and importing them with this one"
+    )]
+    IncompatibleFrostCore { written: u64, minimum: u64 },
+}
+
+/// The major version of `frost-core` the key packages are serialized with.
+pub const FROST_CORE_VERSION: u64 = 2;
+
+/// The major version of `frost-core` the entries written before the version tag were
+/// serialized with.
+const UNTAGGED_FROST_CORE_VERSION: u64 = 2;
+
+impl From<Error> for std::io::Error {
+    fn from(e: Error) -> Self {
+        std::io::Error::new(std::io::ErrorKind::InvalidData, e)
+    }
+}
+
+/// How the keygen entries are written in the store.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EntryFormat {
+    /// The JSON envelope, readable with any tool.
+    #[default]
+    Json,
+    /// A bincode record, smaller and faster to load for large operator sets.
+    Bincode,
+}
+
+/// A keygen entry as a bincode record.
+#[derive(Serialize, Deserialize)]
+struct BincodeEntry {
+    ciphersuite: String,
+    label: Option<String>,
+    key_pkg: Vec<u8>,
+    pub_key_pkg: Vec<u8>,
+    committee: Option<Vec<Vec<u8>>>,
+    beacon: Option<String>,
+    signing_windows: Option<Vec<SigningWindow>>,
+    identifiers: Option<Vec<([u8; 32], u16)>>,
+    frost_core: Option<u64>,
+}
+
+/// A keygen entry as a bincode record written before the `frost-core` version tag.
+#[derive(Deserialize)]
+struct PreFrostCoreBincodeEntry {
+    ciphersuite: String,
+    label: Option<String>,
+    key_pkg: Vec<u8>,
+    pub_key_pkg: Vec<u8>,
+    committee: Option<Vec<Vec<u8>>>,
+    beacon: Option<String>,
+    signing_windows: Option<Vec<SigningWindow>>,
+    identifiers: Option<Vec<([u8; 32], u16)>>,
+}
+
+impl From<PreFrostCoreBincodeEntry> for BincodeEntry {
+    fn from(record: PreFrostCoreBincodeEntry) -> Self {
+        Self {
+            ciphersuite: record.ciphersuite,
+            label: record.label,
+            key_pkg: record.key_pkg,
+            pub_key_pkg: record.pub_key_pkg,
+            committee: record.committee,
+            beacon: record.beacon,
+            signing_windows: record.signing_windows,
+            identifiers: record.identifiers,
+            frost_core: None,
+        }
+    }
+}
+
+/// A keygen entry as a bincode record written before the identifiers.
+#[derive(Deserialize)]
+struct PreIdentifiersBincodeEntry {
+    ciphersuite: String,
+    label: Option<String>,
+    key_pkg: Vec<u8>,
+    pub_key_pkg: Vec<u8>,
+    committee: Option<Vec<Vec<u8>>>,
+    beacon: Option<String>,
+    signing_windows: Option<Vec<SigningWindow>>,
+}
+
+impl From<PreIdentifiersBincodeEntry> for BincodeEntry {
+    fn from(record: PreIdentifiersBincodeEntry) -> Self {
+        Self {
+            ciphersuite: record.ciphersuite,
+            label: record.label,
+            key_pkg: record.key_pkg,
+            pub_key_pkg: record.pub_key_pkg,
+            committee: record.committee,
+            beacon: record.beacon,
+            signing_windows: record.signing_windows,
+            identifiers: None,
+            frost_core: None,
+        }
+    }
+}
+
+/// A keygen entry as a bincode record written before the signing windows.
+#[derive(Deserialize)]
+struct LegacyBincodeEntry {
+    ciphersuite: String,
+    label: Option<String>,
+    key_pkg: Vec<u8>,
+    pub_key_pkg: Vec<u8>,
+    committee: Option<Vec<Vec<u8>>>,
+    beacon: Option<String>,
+}
+
+impl From<LegacyBincodeEntry> for BincodeEntry {
+    fn from(record: LegacyBincodeEntry) -> Self {
+        Self {
+            ciphersuite: record.ciphersuite,
+            label: record.label,
+            key_pkg: record.key_pkg,
+            pub_key_pkg: record.pub_key_pkg,
+            committee: record.committee,
+            beacon: record.beacon,
+            signing_windows: None,
+            identifiers: None,
+            frost_core: None,
+        }
+    }
+}
+
+/// The format `raw` is written in.
+pub fn format_of(raw: &[u8]) -> EntryFormat {
+    match raw.first() {
+        Some(
+            &BINCODE_TAG
+            | &PRE_FROST_CORE_BINCODE_TAG
+            | &PRE_IDENTIFIERS_BINCODE_TAG
+            | &LEGACY_BINCODE_TAG,
+        ) => EntryFormat::Bincode,
+        _ => EntryFormat::Json,
+    }
+}
+
+/// Write the JSON envelope `info` in `format`.
+pub fn encode(format: EntryFormat, info: &serde_json::Value) -> Result<Vec<u8>, Error> {
+    if format == EntryFormat::Json {
+        return Ok(serde_json::to_vec(info)?);
+    }
+    let ciphersuite = info["ciphersuite"]
+        .as_str()
+        .ok_or_else(|| Error::Malformed("no ciphersuite".to_string()))?;
+    let record = match ciphersuite {
+        frost_ed25519::Ed25519Sha512::ID => to_record::<frost_ed25519::Ed25519Sha512>(info)?,
+        frost_secp256k1::Secp256K1Sha256::ID => {
+            to_record::<frost_secp256k1::Secp256K1Sha256>(info)?
+        }
+        frost_ristretto255::Ristretto255Sha512::ID => {
+            to_record::<frost_ristretto255::Ristretto255Sha512>(info)?
+        }
+        frost_p256::P256Sha256::ID => to_record::<frost_p256::P256Sha256>(info)?,
+        JubjubBlake2b512::ID => to_record::<JubjubBlake2b512>(info)?,
+        _ => return Err(Error::UnknownCiphersuite(ciphersuite.to_string())),
+    };
+    let mut raw = vec![BINCODE_TAG];
+    bincode::serialize_into(&mut raw, &record)?;
+    Ok(raw)
+}
+
+/// The ciphersuite of the JSON envelope `info`, found by reading its keygen entry with every
+/// supported ciphersuite, for the envelopes missing their `"ciphersuite"`.
+///
+/// The key packages carry the ciphersuite in their header, so at most one of them matches.
+pub fn infer_ciphersuite(info: &serde_json::Value) -> Result<&'static str, Error> {
+    fn matches<C: Ciphersuite>(info: &serde_json::Value) -> bool {
+        KeygenEntry::<C>::deserialize(&info["entry"]).is_ok()
+    }
+    if matches::<frost_ed25519::Ed25519Sha512>(info) {
+        Ok(frost_ed25519::Ed25519Sha512::ID)
+    } else if matches::<frost_secp256k1::Secp256K1Sha256>(info) {
+        Ok(frost_secp256k1::Secp256K1Sha256::ID)
+    } else if matches::<frost_ristretto255::Ristretto255Sha512>(info) {
+        Ok(frost_ristretto255::Ristretto255Sha512::ID)
+    } else if matches::<frost_p256::P256Sha256>(info) {
+        Ok(frost_p256::P256Sha256::ID)
+    } else if matches::<JubjubBlake2b512>(info) {
+        Ok(JubjubBlake2b512::ID)
+    } else {
+        Err(Error::NoMatchingCiphersuite)
+    }
+}
+
+/// Read the JSON envelope of an entry written in any format.
+pub fn decode(raw: &[u8]) -> Result<serde_json::Value, Error> {
+    let record: BincodeEntry = match raw.split_first() {
+        Some((&BINCODE_TAG, record)) => bincode::deserialize(record)?,
+        Some((&PRE_FROST_CORE_BINCODE_TAG, record)) => {
+            bincode::deserialize::<PreFrostCoreBincodeEntry>(record)?.into()
+        }
+        Some((&PRE_IDENTIFIERS_BINCODE_TAG, record)) => {
+            bincode::deserialize::<PreIdentifiersBincodeEntry>(record)?.into()
+        }
+        Some((&LEGACY_BINCODE_TAG, record)) => {
+            bincode::deserialize::<LegacyBincodeEntry>(record)?.into()
+        }
+        _ => return Ok(serde_json::from_slice(raw)?),
+    };
+    match record.ciphersuite.as_str() {
+        frost_ed25519::Ed25519Sha512::ID => from_record::<frost_ed25519::Ed25519Sha512>(record),
+        frost_secp256k1::Secp256K1Sha256::ID => {
+            from_record::<frost_secp256k1::Secp256K1Sha256>(record)
+        }
+        frost_ristretto255::Ristretto255Sha512::ID => {
+            from_record::<frost_ristretto255::Ristretto255Sha512>(record)
+        }
+        frost_p256::P256Sha256::ID => from_record::<frost_p256::P256Sha256>(record),
+        JubjubBlake2b512::ID => from_record::<JubjubBlake2b512>(record),
+        _ => Err(Error::UnknownCiphersuite(record.ciphersuite)),
+    }
+}
+
+fn to_record<C: Ciphersuite>(info: &serde_json::Value) -> Result<BincodeEntry, Error> {
+    let entry: KeygenEntry<C> = serde_json::from_value(info["entry"].clone())?;
+    let malformed = |e: frost_core::Error<C>| Error::Malformed(e.to_string());
+    Ok(BincodeEntry {
+        ciphersuite: C::ID.to_string(),
+        label: info["label"].as_str().map(ToOwned::to_owned),
+        key_pkg: entry.key_pkg.serialize().map_err(malformed)?,
+        pub_key_pkg: entry.pub_key_pkg.serialize().map_err(malformed)?,
+        committee: entry
+            .committee
+            .map(|committee| committee.iter().map(|k| k.0.to_vec()).collect()),
+        beacon: entry.beacon,
+        signing_windows: entry.signing_windows,
+        identifiers: entry.identifiers.map(|identifiers| {
+            identifiers
+                .into_iter()
+                .map(|(account, identifier)| (account.0, identifier))
+                .collect()
+        }),
+        frost_core: info["frost_core"].as_u64(),
+    })
+}
+
+fn from_record<C: Ciphersuite>(record: BincodeEntry) -> Result<serde_json::Value, Error> {
+    let malformed = |e: frost_core::Error<C>| Error::Malformed(e.to_string());
+    let committee = record
+        .committee
+        .map(|committee| {
+            committee
+                .iter()
+                .map(|k| {
+                    <[u8; 33]>::try_from(k.as_slice())
+                        .map(ecdsa::Public::from_raw)
+                        .map_err(|_| Error::Malformed("committee key".to_string()))
+                })
+                .collect::<Result<Vec<_>, _>>()
+        })
+        .transpose()?;
+    let entry = KeygenEntry::<C> {
+        key_pkg: KeyPackage::deserialize(&record.key_pkg).map_err(malformed)?,
+        pub_key_pkg: PublicKeyPackage::deserialize(&record.pub_key_pkg).map_err(malformed)?,
+        committee,
+        beacon: record.beacon,
+        signing_windows: record.signing_windows,
+        identifiers: record.identifiers.map(|identifiers| {
+            identifiers
+                .into_iter()
+                .map(|(account, identifier)| (AccountId32(account), identifier))
+                .collect()
+        }),
+    };
+    let mut info = serde_json::json!({
+        "ciphersuite": C::ID,
+        "entry": entry,
+    });
+    if let Some(label) = record.label {
+        info["label"] = serde_json::Value::String(label);
+    }
+    if let Some(frost_core) = record.frost_core {
+        info["frost_core"] = frost_core.into();
+    }
+    Ok(info)
+}
+
+/// The major version of `frost-core` the entry `raw` was written with, read without
+/// deserializing its key packages.
+pub fn frost_core_of(raw: &[u8]) -> Result<u64, Error> {
+    let tagged = match raw.split_first() {
+        Some((&BINCODE_TAG, record)) => bincode::deserialize::<BincodeEntry>(record)?.frost_core,
+        Some((
+            &PRE_FROST_CORE_BINCODE_TAG | &PRE_IDENTIFIERS_BINCODE_TAG | &LEGACY_BINCODE_TAG,
+            _,
+        )) => None,
+        _ => serde_json::from_slice::<serde_json::Value>(raw)?["frost_core"].as_u64(),
+    };
+    Ok(tagged.unwrap_or(UNTAGGED_FROST_CORE_VERSION))
+}
+
+/// Check the entry `raw` was written with a major version of `frost-core` from `minimum` to
+/// [`FROST_CORE_VERSION`], whose serialization this node reads.
+///
+/// An entry of an older version that is still allowed is only warned about.
+pub fn check_frost_core(raw: &[u8], minimum: u64) -> Result<(), Error> {
+    let written = frost_core_of(raw)?;
+    if written < minimum || written > FROST_CORE_VERSION {
+        return Err(Error::IncompatibleFrostCore { written, minimum });
+    }
+    if written < FROST_CORE_VERSION {
+        tracing::warn!(
+            written,
+            current = FROST_CORE_VERSION,
+            "The keygen entry was written with an older frost-core, consider migrating it"
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use frost_core::keys::{generate_with_dealer, IdentifierList};
+    use gadget_sdk::random::rand::rngs::OsRng;
+
+    #[test]
+    fn bincode_and_json_entries_read_back() {
+        type C = frost_secp256k1::Secp256K1Sha256;
+        let (shares, pub_key_pkg) =
+            generate_with_dealer::<C, _>(3, 2, IdentifierList::Default, &mut OsRng).unwrap();
+        let share = shares.into_values().next().unwrap();
+        let info = serde_json::json!({
+            "ciphersuite": C::ID,
+            "entry": KeygenEntry::<C> {
+                key_pkg: KeyPackage::try_from(share).unwrap(),
+                pub_key_pkg,
+                committee: Some(vec![ecdsa::Public::from_raw([2; 33])]),
+                beacon: None,
+                signing_windows: None,
+                identifiers: Some([(AccountId32([1; 32]), 1)].into()),
+            },
+            "label": "treasury",
+        });
+
+        let bincode = encode(EntryFormat::Bincode, &info).unwrap();
+        assert_eq!(format_of(&bincode), EntryFormat::Bincode);
+        assert_eq!(decode(&bincode).unwrap(), info);
+
+        // The entries written before the bincode format are still read.
+        let json = serde_json::to_vec(&info).unwrap();
+        assert_eq!(format_of(&json), EntryFormat::Json);
+        assert_eq!(decode(&json).unwrap(), info);
+        assert!(bincode.len() < json.len());
+    }
+
+    #[tokio::test]
+    async fn missing_ciphersuite_is_recovered() {
+        use crate::testing::{operator_contexts, MockNetwork, TempDir};
+
+        type C = frost_ed25519::Ed25519Sha512;
+        let (shares, pub_key_pkg) =
+            generate_with_dealer::<C, _>(3, 2, IdentifierList::Default, &mut OsRng).unwrap();
+        let pubkey = hex::encode(pub_key_pkg.verifying_key().serialize().unwrap());
+        let share = shares.into_values().next().unwrap();
+        let info = serde_json::json!({
+            "entry": KeygenEntry::<C> {
+                key_pkg: KeyPackage::try_from(share).unwrap(),
+                pub_key_pkg,
+                committee: None,
+                beacon: None,
+                signing_windows: None,
+                identifiers: None,
+            },
+            "label": "",
+        });
+        assert_eq!(infer_ciphersuite(&info).unwrap(), C::ID);
+        assert!(matches!(
+            infer_ciphersuite(&serde_json::json!({ "entry": { "key_pkg": 1 } })),
+            Err(Error::NoMatchingCiphersuite)
+        ));
+
+        let network = MockNetwork::new(Default::default());
+        let dir = TempDir::new("entry-ciphersuite");
+        let context = operator_contexts(&network, &dir, 1, 968).remove(0);
+        let raw = serde_json::to_vec(&info).unwrap();
+        context.store.set(pubkey.clone(), raw).unwrap();
+        let strict = context.clone().with_ciphersuite_inference(false);
+        assert!(strict.keygen_info(&pubkey).is_err());
+
+        let recovered = context.keygen_info(&pubkey).unwrap().unwrap();
+        assert_eq!(recovered["ciphersuite"], C::ID);
+        // The envelope is rewritten with the recovered ciphersuite.
+        let stored = decode(&context.store.get(&pubkey).unwrap().unwrap()).unwrap();
+        assert_eq!(stored, recovered);
+        assert!(strict.keygen_info(&pubkey).unwrap().is_some());
+    }
+
+    #[tokio::test]
+    async fn incompatible_frost_core_is_refused() {
+        use crate::testing::{operator_contexts, MockNetwork, TempDir};
+
+        type C = frost_ed25519::Ed25519Sha512;
+        let (shares, pub_key_pkg) =
+            generate_with_dealer::<C, _>(3, 2, IdentifierList::Default, &mut OsRng).unwrap();
+        let pubkey = hex::encode(pub_key_pkg.verifying_key().serialize().unwrap());
+        let share = shares.into_values().next().unwrap();
+        let mut info = serde_json::json!({
+            "ciphersuite": C::ID,
+            "frost_core": FROST_CORE_VERSION,
+            "entry": KeygenEntry::<C> {
+                key_pkg: KeyPackage::try_from(share).unwrap(),
+                pub_key_pkg,
+                committee: None,
+                beacon: None,
+                signing_windows: None,
+                identifiers: None,
+            },
+        });
+        let bincode = encode(EntryFormat::Bincode, &info).unwrap();
+        assert_eq!(frost_core_of(&bincode).unwrap(), FROST_CORE_VERSION);
+
+        let network = MockNetwork::new(Default::default());
+        let dir = TempDir::new("entry-frost-core");
+        let context = operator_contexts(&network, &dir, 1, 989).remove(0);
+        for written in [FROST_CORE_VERSION - 1, FROST_CORE_VERSION + 1] {
+            info["frost_core"] = written.into();
+            for format in [EntryFormat::Json, EntryFormat::Bincode] {
+                let raw = encode(format, &info).unwrap();
+                context.store.set(pubkey.clone(), raw).unwrap();
+                let error = context.keygen_info(&pubkey).unwrap_err().to_string();
+                assert!(
+                    error.contains(&format!("written with frost-core {written}")),
+                    "{error}"
+                );
+                assert!(error.contains("migrate it"), "{error}");
+            }
+        }
+
+        // An older version can be allowed, while the entries of a newer one are never read.
+        let lenient = context
+            .clone()
+            .with_min_frost_core_version(FROST_CORE_VERSION - 1);
+        info["frost_core"] = (FROST_CORE_VERSION - 1).into();
+        let raw = encode(EntryFormat::Json, &info).unwrap();
+        context.store.set(pubkey.clone(), raw).unwrap();
+        assert_eq!(lenient.keygen_info(&pubkey).unwrap(), Some(info.clone()));
+        info["frost_core"] = (FROST_CORE_VERSION + 1).into();
+        let raw = encode(EntryFormat::Json, &info).unwrap();
+        context.store.set(pubkey.clone(), raw).unwrap();
+        assert!(lenient.keygen_info(&pubkey).is_err());
+    }
+}
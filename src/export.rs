@@ -0,0 +1,370 @@
+use std::collections::BTreeMap;
+use std::str::FromStr;
+
+use api::services::events::JobCalled;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use frost_core::keys::{KeyPackage, PublicKeyPackage};
+use frost_core::{Ciphersuite, VerifyingKey};
+use gadget_sdk as sdk;
+use k256::elliptic_curve::sec1::ToEncodedPoint;
+use sdk::event_listener::tangle::{
+    jobs::{services_post_processor, services_pre_processor},
+    TangleEventListener,
+};
+use sdk::subxt_core::utils::AccountId32;
+use sdk::tangle_subxt::tangle_testnet_runtime::api;
+
+use crate::keygen::KeygenEntry;
+use crate::redjubjub::JubjubBlake2b512;
+use crate::FrostContext;
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("Unknown ciphersuite: {0}")]
+    UnknwonCiphersuite(String),
+    #[error("Unknown export format: {0}")]
+    UnknownFormat(String),
+    #[error("The Secret Share for that key is not found")]
+    KeyNotFound,
+    #[error("Exporting secret key packages is not allowed")]
+    SecretExportNotAllowed,
+    #[error("The {0} format only holds public keys")]
+    PublicOnlyFormat(&'static str),
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+    #[error("Frost error: {0}")]
+    Frost(Box<dyn std::error::Error>),
+    #[error(transparent)]
+    ToUnsigned16(#[from] std::num::TryFromIntError),
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+}
+
+impl<C: Ciphersuite> From<frost_core::Error<C>> for Error {
+    fn from(e: frost_core::Error<C>) -> Self {
+        Error::Frost(Box::new(e))
+    }
+}
+
+/// The byte layout of an exported package.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ExportFormat {
+    /// The native frost-core serialization, as read back by `KeyPackage::deserialize` and
+    /// `PublicKeyPackage::deserialize`.
+    FrostCore,
+    /// A plain concatenation of the ciphersuite encoded fields, lengths are big-endian:
+    ///
+    /// - `PublicKeyPackage`: `verifying_key || n: u16 || n * (identifier || verifying_share)`,
+    ///   sorted by identifier.
+    /// - `KeyPackage`: `identifier || signing_share || verifying_share || verifying_key ||
+    ///   min_signers: u16`.
+    Raw,
+    /// The group verifying key as a JSON Web Key (RFC 7517), for JOSE and JWT tooling: an `OKP`
+    /// key on the `Ed25519` curve (RFC 8037) or an `EC` key on the `secp256k1` curve (RFC 8812).
+    /// Only for the public key package, the verifying shares are left out.
+    Jwk,
+}
+
+impl FromStr for ExportFormat {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "frost-core" => Ok(ExportFormat::FrostCore),
+            "raw" => Ok(ExportFormat::Raw),
+            "jwk" => Ok(ExportFormat::Jwk),
+            _ => Err(Error::UnknownFormat(s.to_string())),
+        }
+    }
+}
+
+/// Export the public key package of a previously generated key.
+///
+/// The job result is public, so only the public key package can be exported by this job;
+/// see [`FrostContext::export_key_package`] for the operator's secret key package.
+///
+/// # Parameters
+/// - `pubkey`: The public key generated by the [`crate::keygen::keygen`] protocol, or its
+///   label, see [`crate::labels`].
+/// - `format`: The byte layout of the result, oneof [`frost-core`, `raw`, `jwk`], see [`ExportFormat`].
+///
+/// # Returns
+/// The serialized `PublicKeyPackage`, or the JSON Web Key of its group verifying key with the
+/// `jwk` format.
+///
+/// # Errors
+/// - `KeyNotFound`: If the key is not found.
+/// - `UnknownFormat`: If the format is not supported.
+#[sdk::job(
+    id = 3,
+    params(pubkey, format),
+    result(_),
+    event_listener(
+        listener = TangleEventListener::<FrostContext, JobCalled>,
+        pre_processor = services_pre_processor,
+        post_processor = services_post_processor,
+    )
+)]
+#[tracing::instrument(skip_all, parent = context.config.span.clone(), err)]
+pub async fn export_package(
+    pubkey: Vec<u8>,
+    format: String,
+    context: FrostContext,
+) -> Result<Vec<u8>, Error> {
+    let format = format.parse()?;
+    match load_entry(&context, &pubkey)? {
+        Entry::Ed25519(entry) => export_public(&entry.pub_key_pkg, format),
+        Entry::Secp256k1(entry) => export_public(&entry.pub_key_pkg, format),
+        Entry::Ristretto255(entry) => export_public(&entry.pub_key_pkg, format),
+        Entry::P256(entry) => export_public(&entry.pub_key_pkg, format),
+        Entry::RedJubjub(entry) => export_public(&entry.pub_key_pkg, format),
+    }
+}
+
+impl FrostContext {
+    /// Export this operator's secret key package of the key `pubkey`.
+    ///
+    /// Only allowed if enabled with [`FrostContext::with_secret_export`].
+    pub fn export_key_package(
+        &self,
+        pubkey: &[u8],
+        format: ExportFormat,
+    ) -> Result<Vec<u8>, Error> {
+        if !self.allow_secret_export {
+            return Err(Error::SecretExportNotAllowed);
+        }
+        match load_entry(self, pubkey)? {
+            Entry::Ed25519(entry) => export_secret(&entry.key_pkg, format),
+            Entry::Secp256k1(entry) => export_secret(&entry.key_pkg, format),
+            Entry::Ristretto255(entry) => export_secret(&entry.key_pkg, format),
+            Entry::P256(entry) => export_secret(&entry.key_pkg, format),
+            Entry::RedJubjub(entry) => export_secret(&entry.key_pkg, format),
+        }
+    }
+
+    /// The FROST identifier each operator got in the keygen of the key `pubkey`, `None` if the
+    /// key was generated before they were recorded.
+    pub fn keygen_identifiers(
+        &self,
+        pubkey: &[u8],
+    ) -> Result<Option<BTreeMap<AccountId32, u16>>, Error> {
+        match load_entry(self, pubkey)? {
+            Entry::Ed25519(entry) => Ok(entry.identifiers),
+            Entry::Secp256k1(entry) => Ok(entry.identifiers),
+            Entry::Ristretto255(entry) => Ok(entry.identifiers),
+            Entry::P256(entry) => Ok(entry.identifiers),
+            Entry::RedJubjub(entry) => Ok(entry.identifiers),
+        }
+    }
+}
+
+enum Entry {
+    Ed25519(KeygenEntry<frost_ed25519::Ed25519Sha512>),
+    Secp256k1(KeygenEntry<frost_secp256k1::Secp256K1Sha256>),
+    Ristretto255(KeygenEntry<frost_ristretto255::Ristretto255Sha512>),
+    P256(KeygenEntry<frost_p256::P256Sha256>),
+    RedJubjub(KeygenEntry<JubjubBlake2b512>),
+}
+
+fn load_entry(context: &FrostContext, pubkey: &[u8]) -> Result<Entry, Error> {
+    let pubkey = context.resolve_key(pubkey.to_vec())?;
+    let info_json_value = context
+        .keygen_info(&hex::encode(pubkey))?
+        .ok_or(Error::KeyNotFound)?;
+    let ciphersuite = info_json_value["ciphersuite"]
+        .as_str()
+        .ok_or(Error::KeyNotFound)?;
+    let entry = info_json_value["entry"].clone();
+    match ciphersuite {
+        frost_ed25519::Ed25519Sha512::ID => Ok(Entry::Ed25519(serde_json::from_value(entry)?)),
+        frost_secp256k1::Secp256K1Sha256::ID => {
+            Ok(Entry::Secp256k1(serde_json::from_value(entry)?))
+        }
+        frost_ristretto255::Ristretto255Sha512::ID => {
+            Ok(Entry::Ristretto255(serde_json::from_value(entry)?))
+        }
+        frost_p256::P256Sha256::ID => Ok(Entry::P256(serde_json::from_value(entry)?)),
+        JubjubBlake2b512::ID => Ok(Entry::RedJubjub(serde_json::from_value(entry)?)),
+        _ => Err(Error::UnknwonCiphersuite(ciphersuite.to_string())),
+    }
+}
+
+/// Serialize a public key package in the given format.
+pub fn export_public<C: Ciphersuite>(
+    pub_key_pkg: &PublicKeyPackage<C>,
+    format: ExportFormat,
+) -> Result<Vec<u8>, Error> {
+    match format {
+        ExportFormat::FrostCore => Ok(pub_key_pkg.serialize()?),
+        ExportFormat::Raw => {
+            let mut out = pub_key_pkg.verifying_key().serialize()?;
+            let n = u16::try_from(pub_key_pkg.verifying_shares().len())?;
+            out.extend(n.to_be_bytes());
+            for (identifier, share) in pub_key_pkg.verifying_shares() {
+                out.extend(identifier.serialize());
+                out.extend(share.serialize()?);
+            }
+            Ok(out)
+        }
+        ExportFormat::Jwk => Ok(serde_json::to_vec(&jwk(pub_key_pkg.verifying_key())?)?),
+    }
+}
+
+/// The JSON Web Key of a group verifying key, see [`ExportFormat::Jwk`].
+pub fn jwk<C: Ciphersuite>(verifying_key: &VerifyingKey<C>) -> Result<serde_json::Value, Error> {
+    let key = verifying_key.serialize()?;
+    match C::ID {
+        frost_ed25519::Ed25519Sha512::ID => Ok(serde_json::json!({
+            "kty": "OKP",
+            "crv": "Ed25519",
+            "x": URL_SAFE_NO_PAD.encode(&key),
+        })),
+        frost_secp256k1::Secp256K1Sha256::ID => {
+            let point = k256::PublicKey::from_sec1_bytes(&key)
+                .map_err(|_| Error::Frost(Box::new(frost_core::Error::<C>::MalformedVerifyingKey)))?
+                .to_encoded_point(false);
+            let (Some(x), Some(y)) = (point.x(), point.y()) else {
+                return Err(Error::Frost(Box::new(
+                    frost_core::Error::<C>::MalformedVerifyingKey,
+                )));
+            };
+            Ok(serde_json::json!({
+                "kty": "EC",
+                "crv": "secp256k1",
+                "x": URL_SAFE_NO_PAD.encode(x),
+                "y": URL_SAFE_NO_PAD.encode(y),
+            }))
+        }
+        _ => Err(Error::UnknwonCiphersuite(C::ID.to_string())),
+    }
+}
+
+/// Serialize a (secret) key package in the given format.
+pub fn export_secret<C: Ciphersuite>(
+    key_pkg: &KeyPackage<C>,
+    format: ExportFormat,
+) -> Result<Vec<u8>, Error> {
+    match format {
+        ExportFormat::FrostCore => Ok(key_pkg.serialize()?),
+        ExportFormat::Raw => {
+            let mut out = key_pkg.identifier().serialize();
+            out.extend(key_pkg.signing_share().serialize());
+            out.extend(key_pkg.verifying_share().serialize()?);
+            out.extend(key_pkg.verifying_key().serialize()?);
+            out.extend(key_pkg.min_signers().to_be_bytes());
+            Ok(out)
+        }
+        ExportFormat::Jwk => Err(Error::PublicOnlyFormat("jwk")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use frost_core::keys::{IdentifierList, VerifyingShare};
+    use frost_core::Identifier;
+    use frost_secp256k1::Secp256K1Sha256 as C;
+    use gadget_sdk::random::rand::rngs::StdRng;
+    use gadget_sdk::random::SeedableRng;
+
+    /// Length of the ciphersuite encoded scalars and compressed points.
+    const SCALAR_LEN: usize = 32;
+    const ELEMENT_LEN: usize = 33;
+
+    fn packages() -> (Vec<KeyPackage<C>>, PublicKeyPackage<C>) {
+        let rng = &mut StdRng::seed_from_u64(3);
+        let (shares, pub_key_pkg) =
+            frost_core::keys::generate_with_dealer::<C, _>(3, 2, IdentifierList::Default, rng)
+                .unwrap();
+        let key_pkgs = shares
+            .into_values()
+            .map(|share| KeyPackage::try_from(share).unwrap())
+            .collect();
+        (key_pkgs, pub_key_pkg)
+    }
+
+    #[test]
+    fn public_package_round_trips() {
+        let (_, pub_key_pkg) = packages();
+
+        let native = export_public(&pub_key_pkg, ExportFormat::FrostCore).unwrap();
+        assert_eq!(PublicKeyPackage::deserialize(&native).unwrap(), pub_key_pkg);
+
+        let raw = export_public(&pub_key_pkg, ExportFormat::Raw).unwrap();
+        let (verifying_key, rest) = raw.split_at(ELEMENT_LEN);
+        let (n, mut rest) = rest.split_at(2);
+        let n = u16::from_be_bytes([n[0], n[1]]);
+        let mut verifying_shares = BTreeMap::new();
+        for _ in 0..n {
+            let (identifier, share);
+            (identifier, rest) = rest.split_at(SCALAR_LEN);
+            (share, rest) = rest.split_at(ELEMENT_LEN);
+            verifying_shares.insert(
+                Identifier::deserialize(identifier).unwrap(),
+                VerifyingShare::deserialize(share).unwrap(),
+            );
+        }
+        assert!(rest.is_empty());
+        let imported = PublicKeyPackage::new(
+            verifying_shares,
+            VerifyingKey::deserialize(verifying_key).unwrap(),
+        );
+        assert_eq!(imported, pub_key_pkg);
+    }
+
+    #[test]
+    fn key_package_round_trips() {
+        let (key_pkgs, _) = packages();
+        let key_pkg = &key_pkgs[0];
+        let native = export_secret(key_pkg, ExportFormat::FrostCore).unwrap();
+        assert_eq!(&KeyPackage::deserialize(&native).unwrap(), key_pkg);
+        let raw = export_secret(key_pkg, ExportFormat::Raw).unwrap();
+        assert_eq!(raw.len(), 2 * SCALAR_LEN + 2 * ELEMENT_LEN + 2);
+    }
+
+    #[test]
+    fn jwk_holds_the_verifying_key() {
+        let (key_pkgs, pub_key_pkg) = packages();
+        let exported = export_public(&pub_key_pkg, ExportFormat::Jwk).unwrap();
+        let jwk: serde_json::Value = serde_json::from_slice(&exported).unwrap();
+        assert_eq!(jwk["kty"], "EC");
+        assert_eq!(jwk["crv"], "secp256k1");
+        let coordinate = |name: &str| URL_SAFE_NO_PAD.decode(jwk[name].as_str().unwrap()).unwrap();
+        let point = k256::EncodedPoint::from_affine_coordinates(
+            coordinate("x").as_slice().into(),
+            coordinate("y").as_slice().into(),
+            true,
+        );
+        assert_eq!(
+            point.as_bytes(),
+            pub_key_pkg.verifying_key().serialize().unwrap()
+        );
+        assert!(matches!(
+            export_secret(&key_pkgs[0], ExportFormat::Jwk),
+            Err(Error::PublicOnlyFormat(_))
+        ));
+
+        type Ed = frost_ed25519::Ed25519Sha512;
+        let rng = &mut StdRng::seed_from_u64(962);
+        let (_, pub_key_pkg) =
+            frost_core::keys::generate_with_dealer::<Ed, _>(3, 2, IdentifierList::Default, rng)
+                .unwrap();
+        let jwk = super::jwk(pub_key_pkg.verifying_key()).unwrap();
+        assert_eq!(jwk["kty"], "OKP");
+        assert_eq!(jwk["crv"], "Ed25519");
+        assert_eq!(
+            URL_SAFE_NO_PAD.decode(jwk["x"].as_str().unwrap()).unwrap(),
+            pub_key_pkg.verifying_key().serialize().unwrap()
+        );
+    }
+
+    #[test]
+    fn unknown_format_is_rejected() {
+        assert_eq!("raw".parse::<ExportFormat>().unwrap(), ExportFormat::Raw);
+        assert!(matches!(
+            "pem".parse::<ExportFormat>(),
+            Err(Error::UnknownFormat(_))
+        ));
+    }
+}
@@ -0,0 +1,505 @@
+//! Quorum-gated changes of the configuration.
+//!
+//! Some settings, the ciphersuites a keygen may use, the patterns of the messages never signed
+//! and the minimum threshold of a keygen, should only change with the consent of the operators,
+//! not on the decision of a single one. A [`ConfigChange`] is proposed to all the operators
+//! with the [`propose_config_change`] job: each of them votes on it as its [`ConfigApproval`]
+//! says, over the agreement protocol, see [`agreement_protocol::run`], and the change takes
+//! effect on every operator once approved by the quorum set with
+//! [`FrostContext::with_config_quorum`], a majority of the operators by default.
+//!
+//! Every operator must take part in the vote. The votes are echoed, so an operator sending
+//! different votes to different operators makes the vote fail instead of splitting them, and the
+//! proposal is bound to the quorum, so the operators configured with different quorums fail the
+//! vote instead of deciding differently: the operators that complete the vote all count the same
+//! votes against the same quorum, and so all apply the change or none does.
+//!
+//! An operator sent a forged echo fails the vote on its own, and keeps the previous settings
+//! while the others apply the change. It does not diverge silently though: the proposal is also
+//! bound to the [`AgreedConfig::version`] it changes, so every later vote fails on all the
+//! operators with a [`ProposalMismatch`](agreement_protocol::Reason::ProposalMismatch), and every
+//! keygen, which checks the operators agree on the version before keeping the key, fails with
+//! `ConfigMismatch`, until the stale operator gets the agreed settings of the others.
+//!
+//! The agreed settings are persisted in the store as an [`AgreedConfig`], and take precedence
+//! over the ones the node is built with, e.g. with [`FrostContext::with_allowed_ciphersuites`].
+use std::collections::BTreeSet;
+
+use api::services::events::JobCalled;
+use gadget_sdk::futures::TryFutureExt;
+use gadget_sdk::network::round_based_compat::NetworkDeliveryWrapper;
+use gadget_sdk::subxt_core::ext::sp_core::{ecdsa, keccak_256};
+use gadget_sdk::subxt_core::utils::AccountId32;
+use gadget_sdk::{self as sdk};
+use sdk::event_listener::tangle::{
+    jobs::{services_post_processor, services_pre_processor},
+    TangleEventListener,
+};
+use sdk::tangle_subxt::tangle_testnet_runtime::api;
+use serde::{Deserialize, Serialize};
+
+use crate::rounds::agreement as agreement_protocol;
+use crate::FrostContext;
+
+/// The store key of the agreed configuration.
+const AGREED_KEY: &str = "config/agreed";
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("Invalid configuration change: {0}")]
+    InvalidChange(String),
+    #[error("Self not in operators")]
+    SelfNotInOperators,
+    #[error(transparent)]
+    DuplicateInstance(#[from] crate::operators::DuplicateInstance),
+    #[error(transparent)]
+    IdentityMismatch(#[from] crate::operators::IdentityMismatch),
+    #[error(transparent)]
+    TooManySessions(#[from] crate::TooManySessions),
+    #[error(transparent)]
+    JobTimeout(#[from] crate::JobTimeout),
+    #[error(transparent)]
+    Config(#[from] sdk::config::Error),
+    #[error("The network of this node is shut down")]
+    NetworkShutdown,
+    #[error(
+        "The operators do not all agree on the version {version} of the configuration of this node"
+    )]
+    ConfigMismatch { version: u64 },
+    #[error("Protocol error: {0}")]
+    Protocol(agreement_protocol::Error),
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[error(transparent)]
+    Other(color_eyre::eyre::Error),
+    #[error(transparent)]
+    Unauthorized(#[from] crate::operators::Unauthorized),
+}
+
+impl From<agreement_protocol::Error> for Error {
+    fn from(e: agreement_protocol::Error) -> Self {
+        match e.is_network_shutdown() {
+            true => Error::NetworkShutdown,
+            false => Error::Protocol(e),
+        }
+    }
+}
+
+/// A change of a quorum-gated setting.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "setting", content = "value", rename_all = "snake_case")]
+pub enum ConfigChange {
+    /// Only allow a keygen with one of these ciphersuites, by `ID`.
+    AllowedCiphersuites(BTreeSet<String>),
+    /// Refuse to sign the messages containing any of these hex encoded patterns, see
+    /// [`Blocklist`](crate::policy::Blocklist), none if empty.
+    Blocklist(Vec<String>),
+    /// Refuse a keygen with a lower threshold.
+    MinThreshold(u16),
+}
+
+impl ConfigChange {
+    fn validate(&self) -> Result<(), Error> {
+        match self {
+            ConfigChange::AllowedCiphersuites(ciphersuites) => {
+                if ciphersuites.is_empty() {
+                    return Err(Error::InvalidChange("no ciphersuite".to_string()));
+                }
+                if let Some(unsupported) = ciphersuites
+                    .iter()
+                    .find(|c| !crate::self_test::CIPHERSUITES.contains(&c.as_str()))
+                {
+                    return Err(Error::InvalidChange(format!(
+                        "unsupported ciphersuite {unsupported}"
+                    )));
+                }
+            }
+            ConfigChange::Blocklist(patterns) => {
+                if let Some(pattern) = patterns.iter().find(|p| hex::decode(p).is_err()) {
+                    return Err(Error::InvalidChange(format!("{pattern} is not hex")));
+                }
+            }
+            ConfigChange::MinThreshold(0) => {
+                return Err(Error::InvalidChange("a zero threshold".to_string()));
+            }
+            ConfigChange::MinThreshold(_) => {}
+        }
+        Ok(())
+    }
+}
+
+/// The settings the operators agreed on, see [`propose_config_change`].
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AgreedConfig {
+    /// The number of changes applied so far.
+    pub version: u64,
+    /// The ciphersuites a keygen may use, the ones the node is built with if `None`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub allowed_ciphersuites: Option<BTreeSet<String>>,
+    /// The hex encoded patterns of the messages never signed.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub blocklist: Vec<String>,
+    /// The minimum threshold of a keygen, if any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min_threshold: Option<u16>,
+}
+
+impl AgreedConfig {
+    fn apply(&mut self, change: ConfigChange) {
+        match change {
+            ConfigChange::AllowedCiphersuites(ciphersuites) => {
+                self.allowed_ciphersuites = Some(ciphersuites)
+            }
+            ConfigChange::Blocklist(patterns) => self.blocklist = patterns,
+            ConfigChange::MinThreshold(min) => self.min_threshold = Some(min),
+        }
+        self.version += 1;
+    }
+}
+
+/// Whether this operator approves a proposed [`ConfigChange`].
+pub trait ConfigApproval: std::fmt::Debug + Send + Sync {
+    /// Whether to vote for `change`.
+    fn approve(&self, change: &ConfigChange) -> bool;
+}
+
+/// Approve every change.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ApproveAll;
+
+impl ConfigApproval for ApproveAll {
+    fn approve(&self, _change: &ConfigChange) -> bool {
+        true
+    }
+}
+
+/// Reject every change.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct RejectAll;
+
+impl ConfigApproval for RejectAll {
+    fn approve(&self, _change: &ConfigChange) -> bool {
+        false
+    }
+}
+
+/// The outcome of a vote on a configuration change.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ConfigChangeOutcome {
+    /// Whether the change was approved by the quorum, and applied.
+    pub applied: bool,
+    /// The number of operators that approved it.
+    pub approvals: u16,
+    /// The number of approvals needed.
+    pub quorum: u16,
+    /// The version of the agreed configuration after the vote.
+    pub version: u64,
+}
+
+/// Propose a change of a quorum-gated setting to the operators, applied if a quorum of them
+/// approves it.
+///
+/// # Parameters
+/// - `change`: The JSON [`ConfigChange`], e.g.
+///   `{"setting": "allowed_ciphersuites", "value": ["FROST-secp256k1-SHA256-v1"]}`.
+///
+/// # Returns
+/// The JSON [`ConfigChangeOutcome`].
+///
+/// # Errors
+/// - `InvalidChange`: The change is malformed, e.g. with an unsupported ciphersuite.
+/// - `SelfNotInOperators`: The current operator is not in the operators.
+/// - `Unauthorized`: The job is not called by the service owner or one of its operators.
+/// - `JobTimeout`: An operator did not vote within [`FrostContext::with_job_timeout`], nothing
+///   is applied.
+#[sdk::job(
+    id = 24,
+    params(change),
+    result(_),
+    event_listener(
+        listener = TangleEventListener::<FrostContext, JobCalled>,
+        pre_processor = services_pre_processor,
+        post_processor = services_post_processor,
+    )
+)]
+#[tracing::instrument(skip_all, parent = context.config.span.clone(), err)]
+pub async fn propose_config_change(
+    change: String,
+    context: FrostContext,
+) -> Result<Vec<u8>, Error> {
+    let change: ConfigChange = serde_json::from_str(&change)?;
+    change.validate()?;
+    context.authorize_caller().await?;
+    let call_id = context.call_id().map_err(Error::Other).await?;
+    let outcome = context
+        .within_job_timeout(vote(change, call_id, &context))
+        .await?;
+    Ok(serde_json::to_vec(&outcome)?)
+}
+
+/// Vote on `change` in the session of the job call `call_id`, and apply it if approved.
+async fn vote(
+    change: ConfigChange,
+    call_id: u64,
+    context: &FrostContext,
+) -> Result<ConfigChangeOutcome, Error> {
+    let mut agreed = context.agreed_config()?;
+    let operators = context.current_operators().map_err(Error::Other).await?;
+    let me = context.local_identity::<Error>()?;
+    let i = crate::operators::own_index(&operators, &me)?.ok_or(Error::SelfNotInOperators)?;
+    let i = u16::try_from(i).map_err(|_| Error::SelfNotInOperators)?;
+    let n = u16::try_from(operators.len()).map_err(|_| Error::SelfNotInOperators)?;
+    let quorum = context.config_quorum.map_or(n / 2 + 1, |q| q.get());
+    // The proposal is bound to the configuration it changes, so a replayed or concurrent one
+    // is not approved against another, and to the quorum, so every operator decides alike.
+    let proposal = keccak_256(&serde_json::to_vec(&(agreed.version, &change, quorum))?);
+    let approve = context.config_approval.approve(&change);
+    sdk::info!(?change, approve, "Voting on a configuration change");
+
+    let parties = crate::operators::party_indices(&operators)
+        .map(|(j, _, key)| (j, *key))
+        .collect::<std::collections::BTreeMap<u16, ecdsa::Public>>();
+    let task_hash = crate::session::config_session_name(call_id);
+    let _session = context.sessions.register(task_hash, "config")?;
+    let delivery =
+        NetworkDeliveryWrapper::new(context.network_backend.clone(), i, task_hash, parties);
+    let delivery = crate::codec::primed(delivery);
+    let party = round_based::MpcParty::connected(crate::codec::versioned(delivery, context.codec));
+    let approvals = agreement_protocol::run(proposal, approve, n, i, party, None).await?;
+
+    let applied = approvals >= quorum;
+    if applied {
+        agreed.apply(change);
+        context
+            .store
+            .set(AGREED_KEY.to_string(), serde_json::to_vec(&agreed)?)?;
+        sdk::info!(version = agreed.version, "Configuration change applied");
+    } else {
+        sdk::warn!(approvals, quorum, "Configuration change not approved");
+    }
+    Ok(ConfigChangeOutcome {
+        applied,
+        approvals,
+        quorum,
+        version: agreed.version,
+    })
+}
+
+impl FrostContext {
+    /// Check that the `operators` all apply the version `version` of the agreed configuration,
+    /// in the session of the job call `call_id`.
+    ///
+    /// Fails with `ConfigMismatch` on every operator if one of them missed a change, see
+    /// [`governance`](self).
+    pub(crate) async fn check_config_version(
+        &self,
+        version: u64,
+        call_id: u64,
+        operators: &std::collections::BTreeMap<AccountId32, ecdsa::Public>,
+    ) -> Result<(), Error> {
+        let me = self.local_identity::<Error>()?;
+        let i = crate::operators::own_index(operators, &me)?.ok_or(Error::SelfNotInOperators)?;
+        let i = u16::try_from(i).map_err(|_| Error::SelfNotInOperators)?;
+        let n = u16::try_from(operators.len()).map_err(|_| Error::SelfNotInOperators)?;
+        let proposal = keccak_256(&serde_json::to_vec(&("version", version))?);
+
+        let parties = crate::operators::party_indices(operators)
+            .map(|(j, _, key)| (j, *key))
+            .collect::<std::collections::BTreeMap<u16, ecdsa::Public>>();
+        let task_hash = crate::session::config_check_session_name(call_id);
+        let _session = self.sessions.register(task_hash, "config-check")?;
+        let delivery =
+            NetworkDeliveryWrapper::new(self.network_backend.clone(), i, task_hash, parties);
+        let delivery = crate::codec::primed(delivery);
+        let party = round_based::MpcParty::connected(crate::codec::versioned(delivery, self.codec));
+        match agreement_protocol::run(proposal, true, n, i, party, None).await {
+            Ok(_) => Ok(()),
+            Err(agreement_protocol::Error(agreement_protocol::Reason::ProposalMismatch {
+                ..
+            })) => Err(Error::ConfigMismatch { version }),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// The settings the operators agreed on, the default ones if none.
+    pub fn agreed_config(&self) -> Result<AgreedConfig, std::io::Error> {
+        match self.store.get(&AGREED_KEY.to_string())? {
+            Some(bytes) => Ok(serde_json::from_slice(&bytes)?),
+            None => Ok(AgreedConfig::default()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::num::NonZeroU16;
+
+    use frost_core::Ciphersuite;
+
+    use super::*;
+    use crate::testing::{
+        on_all, operator_contexts, MockCoordinator, MockNetwork, MockNetworkConfig, TempDir,
+    };
+
+    /// Propose `change` to all the `contexts`, returning their outcomes.
+    async fn propose(contexts: &[FrostContext], change: &ConfigChange) -> Vec<ConfigChangeOutcome> {
+        let change = serde_json::to_string(change).unwrap();
+        on_all(contexts, |context| {
+            propose_config_change(change.clone(), context)
+        })
+        .await
+        .into_iter()
+        .map(|outcome| serde_json::from_slice(&outcome.unwrap()).unwrap())
+        .collect()
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn config_change_requires_a_quorum() {
+        type C = frost_ed25519::Ed25519Sha512;
+        let network = MockNetwork::new(Default::default());
+        let dir = TempDir::new("config-quorum");
+        // The last operator rejects every change.
+        let contexts = operator_contexts(&network, &dir, 3, 999)
+            .into_iter()
+            .enumerate()
+            .map(|(j, context)| match j {
+                2 => context.with_config_approval(RejectAll),
+                _ => context,
+            })
+            .collect::<Vec<_>>();
+        let change = ConfigChange::AllowedCiphersuites(BTreeSet::from([
+            frost_secp256k1::Secp256K1Sha256::ID.to_string(),
+        ]));
+
+        // All the operators must approve.
+        let unanimous = contexts
+            .iter()
+            .map(|context| {
+                context
+                    .clone()
+                    .with_config_quorum(NonZeroU16::new(3).unwrap())
+            })
+            .collect::<Vec<_>>();
+        for outcome in propose(&unanimous, &change).await {
+            assert!(!outcome.applied);
+            assert_eq!(
+                (outcome.approvals, outcome.quorum, outcome.version),
+                (2, 3, 0)
+            );
+        }
+        for context in &contexts {
+            assert_eq!(context.agreed_config().unwrap(), AgreedConfig::default());
+        }
+
+        // A majority approves, in a later call.
+        let operators = contexts[0].current_operators().await.unwrap();
+        let majority = contexts
+            .iter()
+            .map(|context| {
+                context.clone().with_coordinator(MockCoordinator {
+                    operators: operators.clone(),
+                    call_id: 1000,
+                    change_after: None,
+                    caller: None,
+                })
+            })
+            .collect::<Vec<_>>();
+        for outcome in propose(&majority, &change).await {
+            assert!(outcome.applied);
+            assert_eq!(
+                (outcome.approvals, outcome.quorum, outcome.version),
+                (2, 2, 1)
+            );
+        }
+        // The change applies on every operator, the one that rejected it included.
+        for context in &contexts {
+            let agreed = context.agreed_config().unwrap();
+            assert_eq!(agreed.version, 1);
+            assert!(matches!(
+                crate::keygen::keygen(C::ID.to_string(), 2, context.clone()).await,
+                Err(crate::keygen::Error::CiphersuiteNotAllowed(_))
+            ));
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn stale_operator_is_detected() {
+        type C = frost_ed25519::Ed25519Sha512;
+        let network = MockNetwork::new(MockNetworkConfig {
+            latency: std::time::Duration::from_millis(50),
+            loss: 0.0,
+        });
+        let dir = TempDir::new("config-stale");
+        let contexts = operator_contexts(&network, &dir, 3, 999);
+        let operators = contexts[0].current_operators().await.unwrap();
+        let called = |call_id| {
+            contexts
+                .iter()
+                .map(|context| {
+                    context.clone().with_coordinator(MockCoordinator {
+                        operators: operators.clone(),
+                        call_id,
+                        change_after: None,
+                        caller: None,
+                    })
+                })
+                .collect::<Vec<_>>()
+        };
+        for outcome in propose(&contexts, &ConfigChange::MinThreshold(2)).await {
+            assert!(outcome.applied);
+        }
+        // The last operator misses the change, e.g. because it was sent a forged echo.
+        contexts[2].store.del(&AGREED_KEY.to_string()).unwrap();
+
+        // It fails every later vote along with the others, instead of deciding on its own.
+        for result in on_all(&called(1000), |context| {
+            let change = serde_json::to_string(&ConfigChange::MinThreshold(3)).unwrap();
+            propose_config_change(change, context)
+        })
+        .await
+        {
+            assert!(result.is_err(), "{result:?}");
+        }
+        // And every keygen.
+        let results = on_all(&called(1001), |context| {
+            crate::keygen::keygen(C::ID.to_string(), 2, context)
+        })
+        .await;
+        for (result, version) in results.into_iter().zip([1, 1, 0]) {
+            assert_eq!(
+                result.unwrap_err(),
+                crate::keygen::Error::ConfigMismatch { version }.to_string()
+            );
+        }
+        assert_eq!(contexts[0].agreed_config().unwrap().version, 1);
+        assert_eq!(contexts[2].agreed_config().unwrap().version, 0);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn mismatched_quorums_fail_the_vote() {
+        let network = MockNetwork::new(Default::default());
+        let dir = TempDir::new("config-quorum-mismatch");
+        // The first operator requires all the approvals, the others a majority.
+        let contexts = operator_contexts(&network, &dir, 3, 999)
+            .into_iter()
+            .enumerate()
+            .map(|(j, context)| match j {
+                0 => context.with_config_quorum(NonZeroU16::new(3).unwrap()),
+                _ => context,
+            })
+            .collect::<Vec<_>>();
+        let change = serde_json::to_string(&ConfigChange::MinThreshold(2)).unwrap();
+        for result in on_all(&contexts, |context| {
+            propose_config_change(change.clone(), context)
+        })
+        .await
+        {
+            assert!(result.is_err(), "{result:?}");
+        }
+        for context in &contexts {
+            assert_eq!(context.agreed_config().unwrap(), AgreedConfig::default());
+        }
+    }
+}
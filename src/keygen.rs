@@ -1,16 +1,26 @@
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet};
+use std::time::Duration;
 
+use crate::dead_letter::DeadLetters;
+use crate::diagnostics::Recorder;
+use crate::multiformats::Part;
+use crate::receipt::ParticipationReceipt;
+use crate::replay::TraceRecorder;
 use crate::rounds::keygen as keygen_protocol;
+use crate::rounds::trace::{PerfProfiler, TimingReport, Tracer};
+use crate::transcript::TranscriptRecorder;
 use crate::FrostContext;
 use api::services::events::JobCalled;
 use frost_core::keys::{KeyPackage, PublicKeyPackage};
 use frost_core::{Ciphersuite, VerifyingKey};
-use gadget_sdk::contexts::MPCContext;
 use gadget_sdk::futures::TryFutureExt;
 use gadget_sdk::network::round_based_compat::NetworkDeliveryWrapper;
-use gadget_sdk::subxt_core::ext::sp_core::{ecdsa, Pair};
+use gadget_sdk::random::rand::Rng;
+use gadget_sdk::subxt_core::ext::sp_core::{ecdsa, keccak_256};
 use gadget_sdk::subxt_core::utils::AccountId32;
 use gadget_sdk::{self as sdk, random};
+use rand_chacha::rand_core::SeedableRng;
+use rand_chacha::ChaCha20Rng;
 use sdk::event_listener::tangle::{
     jobs::{services_post_processor, services_pre_processor},
     TangleEventListener,
@@ -21,15 +31,45 @@ use sdk::tangle_subxt::tangle_testnet_runtime::api;
 pub enum Error {
     #[error("Unknown ciphersuite: {0}")]
     UnknwonCiphersuite(String),
+    #[error("Ciphersuite not allowed by this service: {0}")]
+    CiphersuiteNotAllowed(String),
+    #[error("Threshold {threshold} below the minimum {min} agreed by the operators")]
+    ThresholdBelowMinimum { threshold: u16, min: u16 },
     #[error("Self not in operators")]
     SelfNotInOperators,
+    #[error(transparent)]
+    DuplicateInstance(#[from] crate::operators::DuplicateInstance),
+    #[error(transparent)]
+    IdentityMismatch(#[from] crate::operators::IdentityMismatch),
+    #[error("Self not in the keygen committee")]
+    SelfNotInCommittee,
+    #[error("Committee member {0} is not an operator")]
+    NotAnOperator(String),
+    #[error(transparent)]
+    NotParticipating(#[from] crate::operators::NotParticipating),
+    #[error("{0} operator(s) are paused, all the operators must take part in a keygen")]
+    OperatorsPaused(usize),
+    #[error("The key store is full, this node stores at most {max_keys} keys")]
+    KeyStoreFull { max_keys: usize },
+    #[error("The operator set changed during the keygen, it must be restarted with the new one")]
+    OperatorSetChangedMidProtocol,
+    #[error(
+        "The operators do not all agree on the version {version} of the configuration of this node"
+    )]
+    ConfigMismatch { version: u64 },
 
+    #[error(transparent)]
+    TooManySessions(#[from] crate::TooManySessions),
+    #[error(transparent)]
+    JobTimeout(#[from] crate::JobTimeout),
     #[error(transparent)]
     Subxt(#[from] sdk::tangle_subxt::subxt::Error),
     #[error(transparent)]
     Sdk(#[from] sdk::error::Error),
     #[error(transparent)]
     Config(#[from] sdk::config::Error),
+    #[error("The network of this node is shut down")]
+    NetworkShutdown,
     #[error("Frost error: {0}")]
     Frost(Box<dyn std::error::Error>),
     #[error("Protocol error: {0}")]
@@ -41,6 +81,10 @@ pub enum Error {
     #[error(transparent)]
     Io(#[from] std::io::Error),
     #[error(transparent)]
+    Multiformats(#[from] crate::multiformats::Error),
+    #[error(transparent)]
+    Entry(#[from] crate::entry::Error),
+    #[error(transparent)]
     Other(color_eyre::eyre::Error),
 }
 
@@ -50,9 +94,22 @@ impl<C: Ciphersuite> From<frost_core::Error<C>> for Error {
     }
 }
 
+impl From<crate::retention::Error> for Error {
+    fn from(e: crate::retention::Error) -> Self {
+        match e {
+            crate::retention::Error::KeyStoreFull { max_keys } => Error::KeyStoreFull { max_keys },
+            crate::retention::Error::Json(e) => Error::SerdeJson(e),
+            crate::retention::Error::Io(e) => Error::Io(e),
+        }
+    }
+}
+
 impl<C: Ciphersuite> From<keygen_protocol::Error<C>> for Error {
     fn from(e: keygen_protocol::Error<C>) -> Self {
-        Error::Protocol(Box::new(e))
+        match e.is_network_shutdown() {
+            true => Error::NetworkShutdown,
+            false => Error::Protocol(Box::new(e)),
+        }
     }
 }
 
@@ -62,14 +119,33 @@ impl<C: Ciphersuite> From<keygen_protocol::Error<C>> for Error {
 /// - `ciphersuite`: The ciphersuite to use in the keygen protocol
 /// - `threshold`: The threshold of the keygen protocol.
 /// # Returns
-/// The public key generated by the keygen protocol.
+/// The public key generated by the keygen protocol, followed by this operator's signature of it
+/// if enabled with [`FrostContext::with_signed_keygen_result`], encoded as set with
+/// [`FrostContext::with_output_encoding`]. Wrapped in a
+/// [`TimedOutput`](crate::rounds::trace::TimedOutput) if enabled with
+/// [`FrostContext::with_timing_report`].
 ///
 /// # Errors
 /// - `UnknwonCiphersuite`: The ciphersuite is not supported.
+/// - `CiphersuiteNotAllowed`: The ciphersuite is not allowed, see
+///   [`FrostContext::with_allowed_ciphersuites`] and [`governance`](crate::governance).
+/// - `ThresholdBelowMinimum`: The threshold is below the minimum agreed by the operators, see
+///   [`governance`](crate::governance).
 /// - `SelfNotInOperators`: The current operator is not in the operators.
+/// - `DuplicateInstance`: Another operator is registered with the same ECDSA key.
+/// - `IdentityMismatch`: The local ECDSA key is not the one the network was started with, see
+///   [`FrostContext::with_network_identity`].
+/// - `JobTimeout`: The keygen did not complete within [`FrostContext::with_job_timeout`].
+/// - `KeyStoreFull`: This node already stores as many keys as allowed, see
+///   [`FrostContext::with_key_limit`].
+/// - `OperatorSetChangedMidProtocol`: An operator joined or left the service during the keygen,
+///   see [`FrostContext::with_operator_set_watch`].
+/// - `ConfigMismatch`: An operator applies another version of the configuration agreed with
+///   [`governance`](crate::governance), e.g. because it missed a change.
+/// - `NetworkShutdown`: The network of this node shut down during the keygen.
 ///
 /// # Note
-/// - `ciphersuite`: The `ID` of the ciphersuite; oneof [`FROST-ED25519-SHA512-v1`, `FROST-secp256k1-SHA256-v1`].
+/// - `ciphersuite`: The `ID` of the ciphersuite; oneof [`FROST-ED25519-SHA512-v1`, `FROST-secp256k1-SHA256-v1`, `FROST-RISTRETTO255-SHA512-v1`, `FROST-P256-SHA256-v1`, `FROST(Jubjub, BLAKE2b-512)`].
 /// - `threshold`: The threshold of the keygen protocol should be less than the number of operators.
 #[sdk::job(
     id = 0,
@@ -87,44 +163,369 @@ pub async fn keygen(
     threshold: u16,
     context: FrostContext,
 ) -> Result<Vec<u8>, Error> {
-    let operators = context
-        .current_service_operators_ecdsa_keys()
-        .map_err(Error::Other)
-        .await?;
-    let my_ecdsa = context.config.first_ecdsa_signer()?;
-    let current_call_id = context.current_call_id().map_err(Error::Other).await?;
+    keygen_with_committee("keygen", &ciphersuite, threshold, None, None, context).await
+}
 
-    let rng = random::rand::rngs::OsRng;
-    let kv = context.store.clone();
-    let key = match ciphersuite.as_str() {
-        frost_ed25519::Ed25519Sha512::ID => keygen_internal::<frost_ed25519::Ed25519Sha512, _>(
-            rng,
-            kv,
-            my_ecdsa.signer().public(),
-            operators,
+/// Run Keygen Protocol between the members of a committee of the operators and return the
+/// public key.
+///
+/// Only the committee members hold a share of the key, so only they can sign with it, the
+/// other operators take no part in the keygen nor in the signings.
+///
+/// # Parameters
+/// - `ciphersuite`: The ciphersuite to use in the keygen protocol
+/// - `threshold`: The threshold of the keygen protocol, out of the committee members.
+/// - `committee`: The compressed ECDSA public keys of the committee members.
+/// # Returns
+/// The same as [`keygen`], from the committee members.
+///
+/// # Errors
+/// The same as [`keygen`], and:
+/// - `NotAnOperator`: A committee member is not an operator of the service.
+/// - `SelfNotInCommittee`: The current operator is not in the committee.
+///
+/// # Note
+/// Every operator must be called with the same committee, it is recorded in the
+/// [`KeygenEntry`] of the key.
+#[sdk::job(
+    id = 12,
+    params(ciphersuite, threshold, committee),
+    result(_),
+    event_listener(
+        listener = TangleEventListener::<FrostContext, JobCalled>,
+        pre_processor = services_pre_processor,
+        post_processor = services_post_processor,
+    )
+)]
+#[tracing::instrument(skip(context, committee), parent = context.config.span.clone())]
+pub async fn keygen_committee(
+    ciphersuite: String,
+    threshold: u16,
+    committee: Vec<Vec<u8>>,
+    context: FrostContext,
+) -> Result<Vec<u8>, Error> {
+    let committee = committee
+        .iter()
+        .map(|key| {
+            ecdsa::Public::try_from(key.as_slice())
+                .map_err(|_| Error::NotAnOperator(hex::encode(key)))
+        })
+        .collect::<Result<_, _>>()?;
+    keygen_with_committee(
+        "keygen_committee",
+        &ciphersuite,
+        threshold,
+        Some(committee),
+        None,
+        context,
+    )
+    .await
+}
+
+/// Run Keygen Protocol between the operators, mixing a public randomness beacon into the
+/// randomness of every operator, and return the public key.
+///
+/// The beacon, e.g. the randomness of a drand round committed to before the call, makes the
+/// keygen auditable: an operator disclosing its local entropy lets anyone recompute its
+/// contribution to the key from the beacon, see [`keygen_protocol::beacon_rng`].
+///
+/// # Parameters
+/// - `ciphersuite`: The ciphersuite to use in the keygen protocol
+/// - `threshold`: The threshold of the keygen protocol.
+/// - `beacon`: The beacon value, the same for every operator since it is read from the call.
+/// # Returns
+/// The same as [`keygen`].
+///
+/// # Errors
+/// The same as [`keygen`].
+///
+/// # Note
+/// The beacon is recorded in the [`KeygenEntry`] of the key.
+#[sdk::job(
+    id = 14,
+    params(ciphersuite, threshold, beacon),
+    result(_),
+    event_listener(
+        listener = TangleEventListener::<FrostContext, JobCalled>,
+        pre_processor = services_pre_processor,
+        post_processor = services_post_processor,
+    )
+)]
+#[tracing::instrument(skip(context, beacon), parent = context.config.span.clone())]
+pub async fn keygen_beacon(
+    ciphersuite: String,
+    threshold: u16,
+    beacon: Vec<u8>,
+    context: FrostContext,
+) -> Result<Vec<u8>, Error> {
+    keygen_with_committee(
+        "keygen_beacon",
+        &ciphersuite,
+        threshold,
+        None,
+        Some(&beacon),
+        context,
+    )
+    .await
+}
+
+/// Run the keygen `job` among the `committee`, or all the operators if `None`, with the
+/// randomness `beacon` if any.
+async fn keygen_with_committee(
+    job: &str,
+    ciphersuite: &str,
+    threshold: u16,
+    committee: Option<BTreeSet<ecdsa::Public>>,
+    beacon: Option<&[u8]>,
+    context: FrostContext,
+) -> Result<Vec<u8>, Error> {
+    let current_call_id = context.call_id().map_err(Error::Other).await?;
+    let result = context
+        .within_job_timeout(run_keygen(
+            ciphersuite,
             threshold,
+            committee.as_ref(),
+            beacon,
             current_call_id,
             &context,
-        )
-        .await?
-        .serialize()?,
-        frost_secp256k1::Secp256K1Sha256::ID => {
-            keygen_internal::<frost_secp256k1::Secp256K1Sha256, _>(
-                rng,
-                kv,
-                my_ecdsa.signer().public(),
-                operators,
-                threshold,
-                current_call_id,
-                &context,
-            )
-            .await?
-            .serialize()?
+        ))
+        .await;
+    context.audit(
+        current_call_id,
+        job,
+        result.as_ref().ok().map(|(key, _)| key.as_slice()),
+        result.as_ref().err(),
+    );
+    let (key, timing) = result?;
+
+    let signed = if context.sign_keygen_result {
+        let my_ecdsa = context.config.first_ecdsa_signer()?;
+        let service_id = context.coordinator.service_id().map_err(Error::Other)?;
+        sign_keygen_result(my_ecdsa.signer(), service_id, current_call_id, key.clone())
+    } else {
+        key.clone()
+    };
+    let (key, signature) = signed.split_at(key.len());
+    let mut parts = vec![(Part::PublicKey, key)];
+    if !signature.is_empty() {
+        parts.push((Part::OperatorSignature, signature));
+    }
+    let output = crate::multiformats::output(context.output_encoding, ciphersuite, &parts)?;
+    let output = context.job_result(current_call_id, output, timing)?;
+    context.check_submission(current_call_id, &output);
+    Ok(output)
+}
+
+/// Run the keygen of the job call `current_call_id`, returning the serialized verifying key.
+async fn run_keygen(
+    ciphersuite: &str,
+    threshold: u16,
+    committee: Option<&BTreeSet<ecdsa::Public>>,
+    beacon: Option<&[u8]>,
+    current_call_id: u64,
+    context: &FrostContext,
+) -> Result<(Vec<u8>, Option<TimingReport>), Error> {
+    // The ciphersuites agreed by the operators take precedence over the ones of this node.
+    let agreed = context.agreed_config()?;
+    let allowed = match &agreed.allowed_ciphersuites {
+        Some(agreed) => Some(agreed),
+        None => context.allowed_ciphersuites.as_deref(),
+    };
+    if let Some(allowed) = allowed {
+        if !allowed.contains(ciphersuite) {
+            return Err(Error::CiphersuiteNotAllowed(ciphersuite.to_string()));
+        }
+    }
+    if let Some(min) = agreed.min_threshold.filter(|min| threshold < *min) {
+        return Err(Error::ThresholdBelowMinimum { threshold, min });
+    }
+    context.participation.ensure_participating()?;
+    if let Some(limit) = &context.key_limit {
+        crate::retention::check_room(&context.store, limit)?;
+    }
+    let mut operators = context.current_operators().map_err(Error::Other).await?;
+    let me = context.local_identity::<Error>()?;
+    if let Some(committee) = committee {
+        if let Some(outsider) = committee
+            .iter()
+            .find(|member| !operators.values().any(|k| k == *member))
+        {
+            return Err(Error::NotAnOperator(hex::encode(outsider)));
         }
-        _ => return Err(Error::UnknwonCiphersuite(ciphersuite)),
+        if !committee.contains(&me) {
+            return Err(Error::SelfNotInCommittee);
+        }
+        operators.retain(|_, k| committee.contains(k));
+    }
+    // A paused operator would never join, leaving the others waiting for it.
+    let paused = context
+        .paused_operators(current_call_id, &operators)
+        .map_err(Error::Other)
+        .await?;
+    if !paused.is_empty() {
+        return Err(Error::OperatorsPaused(paused.len()));
+    }
+
+    // The operators kept by the restake filter are recorded like a committee, so that the
+    // signings keep their identifiers whatever the restakes become.
+    let record_committee = committee.is_some() || context.min_restake.is_some();
+
+    let rng = match beacon {
+        Some(beacon) => keygen_protocol::beacon_rng(&mut random::rand::rngs::OsRng, beacon),
+        None => ChaCha20Rng::from_seed(random::rand::rngs::OsRng.gen()),
     };
+    let kv = context.store.clone();
+    let keygen = async {
+        let (key, timing) = match ciphersuite {
+            frost_ed25519::Ed25519Sha512::ID => {
+                let (key, timing) = keygen_internal::<frost_ed25519::Ed25519Sha512, _>(
+                    rng,
+                    kv,
+                    me,
+                    operators,
+                    record_committee,
+                    beacon,
+                    threshold,
+                    agreed.version,
+                    current_call_id,
+                    context,
+                )
+                .await?;
+                (key.serialize()?, timing)
+            }
+            frost_secp256k1::Secp256K1Sha256::ID => {
+                let (key, timing) = keygen_internal::<frost_secp256k1::Secp256K1Sha256, _>(
+                    rng,
+                    kv,
+                    me,
+                    operators,
+                    record_committee,
+                    beacon,
+                    threshold,
+                    agreed.version,
+                    current_call_id,
+                    context,
+                )
+                .await?;
+                (key.serialize()?, timing)
+            }
+            frost_ristretto255::Ristretto255Sha512::ID => {
+                let (key, timing) = keygen_internal::<frost_ristretto255::Ristretto255Sha512, _>(
+                    rng,
+                    kv,
+                    me,
+                    operators,
+                    record_committee,
+                    beacon,
+                    threshold,
+                    agreed.version,
+                    current_call_id,
+                    context,
+                )
+                .await?;
+                (key.serialize()?, timing)
+            }
+            frost_p256::P256Sha256::ID => {
+                let (key, timing) = keygen_internal::<frost_p256::P256Sha256, _>(
+                    rng,
+                    kv,
+                    me,
+                    operators,
+                    record_committee,
+                    beacon,
+                    threshold,
+                    agreed.version,
+                    current_call_id,
+                    context,
+                )
+                .await?;
+                (key.serialize()?, timing)
+            }
+            crate::redjubjub::JubjubBlake2b512::ID => {
+                let (key, timing) = keygen_internal::<crate::redjubjub::JubjubBlake2b512, _>(
+                    rng,
+                    kv,
+                    me,
+                    operators,
+                    record_committee,
+                    beacon,
+                    threshold,
+                    agreed.version,
+                    current_call_id,
+                    context,
+                )
+                .await?;
+                (key.serialize()?, timing)
+            }
+            _ => return Err(Error::UnknwonCiphersuite(ciphersuite.to_string())),
+        };
+        Ok((key, timing))
+    };
+    if !context.watch_operator_set {
+        return keygen.await;
+    }
+    let changed = tokio::select! {
+        result = keygen => return result,
+        changed = context.operators_changed() => changed,
+    };
+    changed.map_err(Error::Other)?;
+    sdk::warn!("The operator set changed during the keygen, aborting it");
+    context.invalidate_operators().await;
+    Err(Error::OperatorSetChangedMidProtocol)
+}
+
+/// Length of a recoverable ECDSA signature.
+pub const KEYGEN_RESULT_SIGNATURE_LEN: usize = 65;
+
+/// The domain of the operator's signature of a keygen result.
+const KEYGEN_RESULT_DOMAIN: &[u8] = b"frost-blueprint/keygen-result";
+
+/// The `keccak256` hash signed by the operator for the verifying key `pubkey` of the keygen job
+/// call `call_id` of the service `service_id`, so that the signature cannot be replayed as the
+/// result of another call or service.
+///
+/// It is the hash of the domain, the big-endian service and call ids, then the key, as the
+/// `abi.encodePacked` of the blueprint contract.
+fn keygen_result_hash(service_id: u64, call_id: u64, pubkey: &[u8]) -> [u8; 32] {
+    keccak_256(
+        &[
+            KEYGEN_RESULT_DOMAIN,
+            &service_id.to_be_bytes(),
+            &call_id.to_be_bytes(),
+            pubkey,
+        ]
+        .concat(),
+    )
+}
 
-    Ok(key)
+/// Append to the serialized verifying key `pubkey` of the keygen job call `call_id` of the
+/// service `service_id` the operator's ECDSA signature of it, see [`keygen_result_hash`].
+pub fn sign_keygen_result(
+    signer: &ecdsa::Pair,
+    service_id: u64,
+    call_id: u64,
+    mut pubkey: Vec<u8>,
+) -> Vec<u8> {
+    let signature = signer.sign_prehashed(&keygen_result_hash(service_id, call_id, &pubkey));
+    pubkey.extend_from_slice(&signature.0);
+    pubkey
+}
+
+/// Check a keygen result produced by [`sign_keygen_result`] for the job call `call_id` of the
+/// service `service_id` against the `operator` key.
+///
+/// Returns the serialized verifying key if the signature is valid.
+pub fn verify_keygen_result<'a>(
+    result: &'a [u8],
+    service_id: u64,
+    call_id: u64,
+    operator: &ecdsa::Public,
+) -> Option<&'a [u8]> {
+    let split = result.len().checked_sub(KEYGEN_RESULT_SIGNATURE_LEN)?;
+    let (pubkey, signature) = result.split_at(split);
+    let signature = ecdsa::Signature::from_slice(signature)?;
+    let hash = keygen_result_hash(service_id, call_id, pubkey);
+    ecdsa::Pair::verify_prehashed(&signature, &hash, operator).then_some(pubkey)
 }
 
 /// A KeygenEntry to store the keygen result.
@@ -133,19 +534,38 @@ pub async fn keygen(
 pub struct KeygenEntry<C: Ciphersuite> {
     pub key_pkg: KeyPackage<C>,
     pub pub_key_pkg: PublicKeyPackage<C>,
+    /// The operators that took part in the keygen, in the order of their identifiers, if it
+    /// ran among a committee instead of all the operators, see [`keygen_committee`], or among
+    /// the operators kept by [`FrostContext::with_min_restake`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub committee: Option<Vec<ecdsa::Public>>,
+    /// The hex encoded randomness beacon mixed into the keygen, see [`keygen_beacon`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub beacon: Option<String>,
+    /// The windows the key is restricted to sign in, see [`crate::windows`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signing_windows: Option<Vec<crate::windows::SigningWindow>>,
+    /// The FROST identifier each operator that took part in the keygen got, absent from the
+    /// entries generated before they were recorded.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub identifiers: Option<BTreeMap<AccountId32, u16>>,
 }
 
 /// A genaric keygen protocol over any ciphersuite.
 #[tracing::instrument(skip(rng, kv, context), fields(ciphersuite = %C::ID,  i = tracing::field::Empty, n = %participants.len()))]
+#[allow(clippy::too_many_arguments)]
 async fn keygen_internal<C, R>(
     mut rng: R,
     kv: crate::kv::SharedDynKVStore<String, Vec<u8>>,
     me: ecdsa::Public,
     participants: BTreeMap<AccountId32, ecdsa::Public>,
+    committee: bool,
+    beacon: Option<&[u8]>,
     t: u16,
+    config_version: u64,
     call_id: u64,
     context: &FrostContext,
-) -> Result<VerifyingKey<C>, Error>
+) -> Result<(VerifyingKey<C>, Option<TimingReport>), Error>
 where
     C: Ciphersuite + Send + Unpin,
     <<C as Ciphersuite>::Group as frost_core::Group>::Element: Send + Unpin,
@@ -154,45 +574,867 @@ where
     R: random::RngCore + random::CryptoRng,
 {
     let n = participants.len();
-    let i = participants
-        .values()
-        .position(|k| k == &me)
-        .ok_or(Error::SelfNotInOperators)?;
+    let i = crate::operators::own_index(&participants, &me)?.ok_or(Error::SelfNotInOperators)?;
 
     let n = u16::try_from(n)?;
     let i = u16::try_from(i)?;
     tracing::span::Span::current().record("i", i);
 
-    let parties: BTreeMap<u16, _> = participants
-        .into_iter()
-        .enumerate()
-        .map(|(j, (_, ecdsa))| (j as u16, ecdsa))
+    let committee = committee.then(|| participants.values().copied().collect());
+    let identifiers = identifiers(&participants)?;
+    let parties: BTreeMap<u16, _> = crate::operators::party_indices(&participants)
+        .map(|(j, _, ecdsa)| (j, *ecdsa))
         .collect();
 
-    let keygen_task_hash = gadget_sdk::compute_sha256_hash!(call_id.to_be_bytes(), "frost-keygen");
+    let keygen_task_hash = crate::session::keygen_session_name(call_id, C::ID);
+    let _session = context.sessions.register(keygen_task_hash, "keygen")?;
 
+    let recorder = context.recorder(call_id, "keygen", C::ID, i, parties.values().map(|k| k.0));
     let delivery = NetworkDeliveryWrapper::new(
         context.network_backend.clone(),
         i as _,
         keygen_task_hash,
         parties.clone(),
     );
-    let party = round_based::MpcParty::connected(delivery);
+    let delivery = context.prioritize(keygen_task_hash, delivery);
+    let letters = context.dead_letters(call_id, "keygen", i);
+    let delivery = DeadLetters::wrap(letters.as_ref(), delivery);
+    let delivery = Recorder::record(recorder.as_ref(), delivery);
+    let signer = context.config.first_ecdsa_signer()?.signer().clone();
+    let transcript =
+        TranscriptRecorder::new(call_id, keygen_task_hash, i, signer, parties.values());
+    let delivery = transcript.record(delivery);
+    let trace = context.trace_recorder(call_id, "keygen", i);
+    let delivery = TraceRecorder::record(trace.as_ref(), delivery);
+    let party = round_based::MpcParty::connected(crate::codec::versioned(delivery, context.codec));
+    // The delivery is already listening, so the messages of the operators that start earlier
+    // are buffered in the meantime.
+    if let Some(max) = context.keygen_jitter {
+        let delay = startup_delay(&mut rng, max);
+        sdk::debug!(?delay, "Delaying the keygen start");
+        tokio::time::sleep(delay).await;
+    }
+    let mut profiler = context.timing_report.then(PerfProfiler::new);
+    let result = keygen_protocol::run::<R, C, _>(
+        &mut rng,
+        t,
+        n,
+        i,
+        context.keygen_broadcast_check,
+        context.keygen_round2_concurrency,
+        party,
+        profiler.as_mut().map(|p| p as &mut dyn Tracer),
+    )
+    .await;
+    context.save_transcript(&transcript);
     let (key_package, public_key_package) =
-        keygen_protocol::run::<R, C, _>(&mut rng, t, n, i, party, None).await?;
+        result.inspect_err(|e| context.save_diagnostics(recorder, e))?;
+    // The checks before the keygen used the agreed configuration, so an operator that missed a
+    // change of it may accept a keygen the others would refuse. The key is only kept if all the
+    // operators apply the same version.
+    context
+        .check_config_version(config_version, call_id, &participants)
+        .await
+        .map_err(|e| match e {
+            crate::governance::Error::ConfigMismatch { version } => {
+                Error::ConfigMismatch { version }
+            }
+            crate::governance::Error::NetworkShutdown => Error::NetworkShutdown,
+            e => Error::Protocol(Box::new(e)),
+        })?;
+    let timing = profiler.and_then(|p| p.timing_report());
     let verifying_key = *public_key_package.verifying_key();
-    let pubkey = hex::encode(verifying_key.serialize()?);
-    sdk::debug!(%pubkey, "Keygen Done");
+    let serialized = verifying_key.serialize()?;
+    context.save_receipt(ParticipationReceipt::new(
+        call_id,
+        keygen_task_hash,
+        &serialized,
+        parties.values(),
+    ));
+    let pubkey = hex::encode(serialized);
+    sdk::debug!(pubkey = %context.log_redaction.redact(&pubkey), "Keygen Done");
     let entry = serde_json::json!({
         "ciphersuite": C::ID,
+        "frost_core": crate::entry::FROST_CORE_VERSION,
         "entry": KeygenEntry {
             key_pkg: key_package,
             pub_key_pkg: public_key_package,
+            committee,
+            beacon: beacon.map(hex::encode),
+            signing_windows: None,
+            identifiers: Some(identifiers),
         },
     });
+    if let Some(limit) = &context.key_limit {
+        crate::retention::check_room(&kv, limit)?;
+    }
     // Save the keygen entry.
-    kv.set(pubkey, serde_json::to_vec(&entry)?)?;
-    Ok(verifying_key)
+    save_entry(
+        &kv,
+        &context.unpersisted,
+        context.write_retry,
+        context.key_limit.as_ref(),
+        context.log_redaction,
+        pubkey,
+        crate::entry::encode(context.entry_format, &entry)?,
+    )
+    .await;
+    Ok((verifying_key, timing))
+}
+
+/// The FROST identifier of each of the `participants`, the one following its party index, see
+/// [`PartyIndex`](crate::rounds::PartyIndex).
+pub fn identifiers(
+    participants: &BTreeMap<AccountId32, ecdsa::Public>,
+) -> Result<BTreeMap<AccountId32, u16>, Error> {
+    crate::operators::party_indices(participants)
+        .map(|(j, account, _)| {
+            let identifier = u16::try_from(usize::from(j) + 1)?;
+            Ok((account.clone(), identifier))
+        })
+        .collect()
+}
+
+/// A random delay of at most `max`.
+fn startup_delay<R: random::RngCore>(rng: &mut R, max: Duration) -> Duration {
+    max.mul_f64(rng.gen::<f64>())
+}
+
+/// Save the keygen entry into the store.
+///
+/// The other operators may already have persisted their shares, so failing the job here would
+/// leave the group inconsistent. Instead, if the store keeps failing, the entry is held in
+/// `unpersisted` where it stays usable, until [`FrostContext::recover_unpersisted`] is called.
+///
+/// The key is listed by [`retention`](crate::retention), evicting another one if `limit` says
+/// so, only once the entry is written, so that no key is lost for an entry that is not.
+async fn save_entry(
+    kv: &crate::kv::SharedDynKVStore<String, Vec<u8>>,
+    unpersisted: &crate::UnpersistedEntries,
+    policy: crate::RetryPolicy,
+    limit: Option<&crate::retention::KeyLimit>,
+    redaction: crate::Redaction,
+    pubkey: String,
+    entry: Vec<u8>,
+) {
+    match crate::kv::set_with_retry(&**kv, pubkey.clone(), entry.clone(), policy).await {
+        Ok(()) => admit_key(kv, limit, redaction, &pubkey),
+        Err(e) => {
+            sdk::error!(
+                pubkey = %redaction.redact(&pubkey),
+                error = %e,
+                attempts = policy.attempts,
+                "Failed to persist the keygen entry, the key share is only held in memory until recovered"
+            );
+            unpersisted.lock().insert(pubkey, entry);
+        }
+    }
+}
+
+/// List the persisted key `pubkey` with [`retention::admit`](crate::retention::admit).
+///
+/// The entry is already written, so a failure is only logged rather than failing the job.
+pub(crate) fn admit_key(
+    kv: &crate::kv::SharedDynKVStore<String, Vec<u8>>,
+    limit: Option<&crate::retention::KeyLimit>,
+    redaction: crate::Redaction,
+    pubkey: &str,
+) {
+    match crate::retention::admit(kv, limit, pubkey) {
+        Ok(evicted) => {
+            for evicted in evicted {
+                sdk::warn!(
+                    pubkey = %redaction.redact(&evicted),
+                    "Evicted the least recently used key to make room"
+                );
+            }
+        }
+        Err(e) => sdk::error!(
+            pubkey = %redaction.redact(pubkey),
+            error = %e,
+            "Failed to list the persisted keygen entry"
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicU32, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use super::*;
+    use crate::coordinator::Coordinator;
+    use crate::kv::{KVStore, MemKVStore, SharedDynKVStore};
+    use color_eyre::eyre;
+    use gadget_sdk::parking_lot;
+    use gadget_sdk::random::SeedableRng;
+    use gadget_sdk::subxt_core::ext::sp_core::Pair;
+    use gadget_sdk::tangle_subxt::tangle_testnet_runtime::api::runtime_types::sp_arithmetic::per_things::Percent;
+
+    #[test]
+    fn keygen_result_signature_verifies() {
+        let operator = ecdsa::Pair::from_seed(&[7u8; 32]);
+        let pubkey = vec![0x02; 33];
+        let result = sign_keygen_result(&operator, 1, 913, pubkey.clone());
+        assert_eq!(result.len(), pubkey.len() + KEYGEN_RESULT_SIGNATURE_LEN);
+        assert_eq!(
+            verify_keygen_result(&result, 1, 913, &operator.public()),
+            Some(&pubkey[..])
+        );
+
+        let other = ecdsa::Pair::from_seed(&[8u8; 32]);
+        assert_eq!(verify_keygen_result(&result, 1, 913, &other.public()), None);
+        let mut tampered = result.clone();
+        tampered[0] ^= 1;
+        assert_eq!(
+            verify_keygen_result(&tampered, 1, 913, &operator.public()),
+            None
+        );
+        assert_eq!(
+            verify_keygen_result(&pubkey[..10], 1, 913, &operator.public()),
+            None
+        );
+        // The signature is bound to the job call and the service.
+        assert_eq!(
+            verify_keygen_result(&result, 1, 914, &operator.public()),
+            None
+        );
+        assert_eq!(
+            verify_keygen_result(&result, 2, 913, &operator.public()),
+            None
+        );
+    }
+
+    /// A store whose first `failures` writes fail.
+    struct FlakyStore {
+        failures: AtomicU32,
+        inner: MemKVStore<String, Vec<u8>, std::io::Error>,
+    }
+
+    impl KVStore for FlakyStore {
+        type Key = String;
+        type Value = Vec<u8>;
+        type Error = std::io::Error;
+
+        fn get(&self, key: &String) -> Result<Option<Vec<u8>>, std::io::Error> {
+            KVStore::get(&self.inner, key)
+        }
+
+        fn set(&self, key: String, value: Vec<u8>) -> Result<(), std::io::Error> {
+            let remaining = self.failures.load(Ordering::SeqCst);
+            if remaining > 0 {
+                self.failures.store(remaining - 1, Ordering::SeqCst);
+                return Err(std::io::Error::other("disk full"));
+            }
+            KVStore::set(&self.inner, key, value)
+        }
+
+        fn del(&self, key: &String) -> Result<(), std::io::Error> {
+            KVStore::del(&self.inner, key)
+        }
+
+        fn ex(&self, key: &String) -> Result<bool, std::io::Error> {
+            KVStore::ex(&self.inner, key)
+        }
+
+        fn compare_and_swap(
+            &self,
+            key: String,
+            expected: Option<Vec<u8>>,
+            new: Vec<u8>,
+        ) -> Result<bool, std::io::Error> {
+            KVStore::compare_and_swap(&self.inner, key, expected, new)
+        }
+
+        fn keys(&self) -> Result<Vec<Vec<u8>>, std::io::Error> {
+            KVStore::keys(&self.inner)
+        }
+    }
+
+    fn flaky_store(failures: u32) -> SharedDynKVStore<String, Vec<u8>> {
+        Arc::new(FlakyStore {
+            failures: AtomicU32::new(failures),
+            inner: MemKVStore::new(),
+        })
+    }
+
+    const POLICY: crate::RetryPolicy = crate::RetryPolicy {
+        attempts: 3,
+        backoff: Duration::from_millis(1),
+    };
+
+    #[tokio::test]
+    async fn transient_write_failures_are_retried() {
+        let kv = flaky_store(2);
+        let unpersisted = crate::UnpersistedEntries::default();
+        save_entry(
+            &kv,
+            &unpersisted,
+            POLICY,
+            None,
+            crate::Redaction::Off,
+            "key".into(),
+            vec![1],
+        )
+        .await;
+        assert_eq!(kv.get(&"key".into()).unwrap(), Some(vec![1]));
+        assert!(unpersisted.lock().is_empty());
+    }
+
+    #[tokio::test]
+    async fn persistent_write_failure_keeps_entry_in_memory() {
+        let kv = flaky_store(3);
+        let unpersisted = crate::UnpersistedEntries::default();
+        save_entry(
+            &kv,
+            &unpersisted,
+            POLICY,
+            None,
+            crate::Redaction::Off,
+            "key".into(),
+            vec![1],
+        )
+        .await;
+        assert_eq!(kv.get(&"key".into()).unwrap(), None);
+        assert_eq!(unpersisted.lock().get("key"), Some(&vec![1]));
+    }
+
+    #[tokio::test]
+    async fn failed_write_evicts_no_key() {
+        let store = Arc::new(FlakyStore {
+            failures: AtomicU32::new(0),
+            inner: MemKVStore::new(),
+        });
+        let kv: SharedDynKVStore<String, Vec<u8>> = store.clone();
+        kv.set("aa".into(), vec![1]).unwrap();
+        crate::retention::admit(&kv, None, "aa").unwrap();
+        store.failures.store(3, Ordering::SeqCst);
+
+        let limit = crate::retention::KeyLimit {
+            max_keys: 1,
+            policy: crate::retention::KeyLimitPolicy::EvictLeastRecentlyUsed,
+        };
+        let unpersisted = crate::UnpersistedEntries::default();
+        save_entry(
+            &kv,
+            &unpersisted,
+            POLICY,
+            Some(&limit),
+            crate::Redaction::Off,
+            "bb".into(),
+            vec![2],
+        )
+        .await;
+        assert_eq!(kv.get(&"aa".into()).unwrap(), Some(vec![1]));
+        assert_eq!(crate::retention::stored_keys(&kv).unwrap(), ["aa"]);
+        assert_eq!(unpersisted.lock().get("bb"), Some(&vec![2]));
+    }
+
+    #[test]
+    fn startup_delay_is_bounded() {
+        let rng = &mut random::rand::rngs::StdRng::seed_from_u64(7);
+        let max = Duration::from_millis(200);
+        for _ in 0..1000 {
+            assert!(startup_delay(rng, max) <= max);
+        }
+        assert_eq!(startup_delay(rng, Duration::ZERO), Duration::ZERO);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn keygen_completes_with_jitter() {
+        use crate::testing::{MockNetwork, MockNetworkConfig};
+
+        const N: u16 = 4;
+        const T: u16 = 3;
+        const MAX_JITTER: Duration = Duration::from_millis(300);
+        let network = MockNetwork::new(MockNetworkConfig::default());
+        let parties = (0..N)
+            .map(|i| {
+                let mut key = [0u8; 33];
+                key[0] = 0x02;
+                key[1] = i as u8;
+                (i, ecdsa::Public::from_raw(key))
+            })
+            .collect::<BTreeMap<_, _>>();
+        let muxes = parties
+            .values()
+            .map(|k| network.multiplexer(*k))
+            .collect::<Vec<_>>();
+
+        let mut tasks = vec![];
+        for (i, mux) in (0..N).zip(muxes) {
+            let delivery = NetworkDeliveryWrapper::new(mux, i, [1u8; 32], parties.clone());
+            let party = round_based::MpcParty::connected(delivery);
+            tasks.push(tokio::spawn(async move {
+                let rng = &mut random::rand::rngs::StdRng::seed_from_u64(u64::from(i));
+                let delay = startup_delay(rng, MAX_JITTER);
+                tokio::time::sleep(delay).await;
+                let output = keygen_protocol::run::<_, frost_secp256k1::Secp256K1Sha256, _>(
+                    rng,
+                    T,
+                    N,
+                    i,
+                    keygen_protocol::BroadcastCheck::Unchecked,
+                    None,
+                    party,
+                    None,
+                )
+                .await;
+                (delay, output)
+            }));
+        }
+
+        let mut verifying_keys = vec![];
+        for task in tasks {
+            let (delay, output) = task.await.unwrap();
+            assert!(delay <= MAX_JITTER);
+            verifying_keys.push(*output.unwrap().1.verifying_key());
+        }
+        assert!(verifying_keys.windows(2).all(|w| w[0] == w[1]));
+    }
+
+    /// Collects everything logged while it is the default subscriber.
+    #[derive(Clone, Default)]
+    struct CapturedLogs(Arc<std::sync::Mutex<Vec<u8>>>);
+
+    impl std::io::Write for CapturedLogs {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn logged_pubkey_is_redacted() {
+        let logs = CapturedLogs::default();
+        let writer = logs.clone();
+        let subscriber = tracing_subscriber::fmt()
+            .with_writer(move || writer.clone())
+            .with_ansi(false)
+            .finish();
+        let _guard = tracing::subscriber::set_default(subscriber);
+
+        let pubkey = "02a8f3b1c2d4e5f60718293a4b5c6d7e8f90a1b2c3d4e5f60718293a4b5c6d7e8f";
+        let unpersisted = crate::UnpersistedEntries::default();
+        for redaction in [crate::Redaction::Truncate, crate::Redaction::Hash] {
+            save_entry(
+                &flaky_store(3),
+                &unpersisted,
+                POLICY,
+                None,
+                redaction,
+                pubkey.into(),
+                vec![1],
+            )
+            .await;
+        }
+
+        let logs = String::from_utf8(logs.0.lock().unwrap().clone()).unwrap();
+        assert!(logs.contains("Failed to persist the keygen entry"));
+        assert!(logs.contains(&crate::Redaction::Truncate.redact(pubkey)));
+        assert!(logs.contains(&crate::Redaction::Hash.redact(pubkey)));
+        assert!(!logs.contains(pubkey));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn disallowed_ciphersuite_is_rejected() {
+        use crate::testing::{operator_contexts, MockNetwork, TempDir};
+
+        let network = MockNetwork::new(Default::default());
+        let dir = TempDir::new("allowed-ciphersuites");
+        let context = operator_contexts(&network, &dir, 1, 929)
+            .pop()
+            .unwrap()
+            .with_allowed_ciphersuites([frost_secp256k1::Secp256K1Sha256::ID]);
+        // Ed25519 is compiled in, but this service only allows secp256k1.
+        let result = keygen(
+            frost_ed25519::Ed25519Sha512::ID.to_string(),
+            1,
+            context.clone(),
+        )
+        .await;
+        assert!(
+            matches!(&result, Err(Error::CiphersuiteNotAllowed(c)) if c == frost_ed25519::Ed25519Sha512::ID),
+            "{result:?}"
+        );
+        let entry = crate::audit::read(&context.store, 929).unwrap();
+        assert!(entry.error.unwrap().contains("not allowed"));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn unsupported_expected_ciphersuite_fails_at_startup() {
+        use crate::testing::{operator_contexts, MockNetwork, TempDir};
+
+        let network = MockNetwork::new(Default::default());
+        let dir = TempDir::new("expected-ciphersuites");
+        let context = operator_contexts(&network, &dir, 1, 991).pop().unwrap();
+        let error = context
+            .clone()
+            .with_expected_ciphersuites(["FROST(P-256, SHA-256)"])
+            .err()
+            .unwrap();
+        assert!(
+            error.to_string().contains("FROST(P-256, SHA-256)"),
+            "{error}"
+        );
+
+        let context = context
+            .with_expected_ciphersuites([frost_secp256k1::Secp256K1Sha256::ID])
+            .unwrap();
+        let result = keygen(frost_ed25519::Ed25519Sha512::ID.to_string(), 1, context).await;
+        assert!(
+            matches!(result, Err(Error::CiphersuiteNotAllowed(_))),
+            "{result:?}"
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn identifiers_are_the_same_on_every_operator() {
+        use crate::testing::{
+            keygen_on_all, operator_contexts, MockNetwork, MockNetworkConfig, TempDir,
+        };
+        use frost_core::Identifier;
+
+        type C = frost_ed25519::Ed25519Sha512;
+        let network = MockNetwork::new(MockNetworkConfig {
+            latency: Duration::from_millis(50),
+            loss: 0.0,
+        });
+        let dir = TempDir::new("keygen-identifiers");
+        let contexts = operator_contexts(&network, &dir, 3, 986);
+        let operators = contexts[0].current_operators().await.unwrap();
+        let pubkey = keygen_on_all(&contexts, C::ID, 2).await;
+
+        let expected = identifiers(&operators).unwrap();
+        assert_eq!(expected.values().copied().collect::<Vec<_>>(), [1, 2, 3]);
+        for context in &contexts {
+            let recorded = context.keygen_identifiers(&pubkey).unwrap();
+            assert_eq!(recorded.as_ref(), Some(&expected));
+            // The identifier recorded for this operator is the one of its key share.
+            let me = context.local_identity::<Error>().unwrap();
+            let (account, _) = operators.iter().find(|(_, k)| **k == me).unwrap();
+            let info = context.keygen_info(&hex::encode(&pubkey)).unwrap().unwrap();
+            let entry: KeygenEntry<C> = serde_json::from_value(info["entry"].clone()).unwrap();
+            assert_eq!(
+                *entry.key_pkg.identifier(),
+                Identifier::<C>::try_from(expected[account]).unwrap()
+            );
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn slow_keygen_is_aborted() {
+        use crate::testing::{operator_contexts, MockNetwork, TempDir};
+
+        const BUDGET: Duration = Duration::from_millis(500);
+        let network = MockNetwork::new(Default::default());
+        let dir = TempDir::new("job-timeout");
+        let mut contexts = operator_contexts(&network, &dir, 3, 931);
+        // The last operator never starts, so the keygen waits for it forever.
+        contexts.pop();
+        let runs = contexts
+            .into_iter()
+            .map(|context| {
+                let context = context.with_job_timeout(BUDGET);
+                tokio::spawn(async move {
+                    let started = std::time::Instant::now();
+                    let result = keygen(
+                        frost_secp256k1::Secp256K1Sha256::ID.to_string(),
+                        2,
+                        context.clone(),
+                    )
+                    .await;
+                    (
+                        result.map_err(|e| e.to_string()),
+                        started.elapsed(),
+                        context,
+                    )
+                })
+            })
+            .collect::<Vec<_>>();
+        for run in runs {
+            let (result, elapsed, context) = tokio::time::timeout(Duration::from_secs(10), run)
+                .await
+                .expect("the keygen was not aborted")
+                .unwrap();
+            let error = result.unwrap_err();
+            assert!(error.contains("did not complete within"), "{error}");
+            assert!(elapsed >= BUDGET);
+            // The session is released with the aborted protocol.
+            assert_eq!(context.active_sessions(), 0);
+            let entry = crate::audit::read(&context.store, 931).unwrap();
+            assert_eq!(entry.error, Some(error));
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn operator_set_change_aborts_the_keygen() {
+        use crate::testing::{operator_contexts, MockCoordinator, MockNetwork, TempDir};
+
+        let network = MockNetwork::new(Default::default());
+        let dir = TempDir::new("operator-set-watch");
+        let mut contexts = operator_contexts(&network, &dir, 3, 954);
+        let operators = contexts
+            .iter()
+            .enumerate()
+            .map(|(i, context)| {
+                let key = context
+                    .config
+                    .first_ecdsa_signer()
+                    .unwrap()
+                    .signer()
+                    .public();
+                (AccountId32([i as u8 + 1; 32]), key)
+            })
+            .collect::<BTreeMap<_, _>>();
+        // The last operator leaves the service before joining the keygen.
+        contexts.pop();
+        let runs = contexts
+            .into_iter()
+            .map(|context| {
+                let context = context
+                    .with_coordinator(MockCoordinator {
+                        operators: operators.clone(),
+                        call_id: 954,
+                        change_after: Some(Duration::from_millis(200)),
+                        caller: None,
+                    })
+                    .with_operator_set_watch(true);
+                tokio::spawn(async move {
+                    let result = keygen(
+                        frost_secp256k1::Secp256K1Sha256::ID.to_string(),
+                        2,
+                        context.clone(),
+                    )
+                    .await;
+                    (result.map_err(|e| e.to_string()), context)
+                })
+            })
+            .collect::<Vec<_>>();
+        for run in runs {
+            let (result, context) = tokio::time::timeout(Duration::from_secs(10), run)
+                .await
+                .expect("the keygen was not aborted")
+                .unwrap();
+            assert_eq!(
+                result.unwrap_err(),
+                Error::OperatorSetChangedMidProtocol.to_string()
+            );
+            assert_eq!(context.active_sessions(), 0);
+            assert!(crate::retention::stored_keys(&context.store)
+                .unwrap()
+                .is_empty());
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn committee_key_is_only_signed_by_the_committee() {
+        use crate::testing::{
+            on_all, operator_contexts, sign_on_all, MockNetwork, MockNetworkConfig, TempDir,
+        };
+        use frost_core::{Signature, VerifyingKey};
+
+        type C = frost_secp256k1::Secp256K1Sha256;
+        let network = MockNetwork::new(MockNetworkConfig {
+            latency: Duration::from_millis(50),
+            loss: 0.0,
+        });
+        let dir = TempDir::new("keygen-committee");
+        let contexts = operator_contexts(&network, &dir, 7, 936);
+        let keys = contexts
+            .iter()
+            .map(|context| {
+                let signer = context.config.first_ecdsa_signer().unwrap();
+                signer.signer().public()
+            })
+            .collect::<Vec<_>>();
+        // A 3-of-5 committee of the 7 operators.
+        let committee = keys[1..6].to_vec();
+        let is_member = |context: &FrostContext| {
+            let signer = context.config.first_ecdsa_signer().unwrap();
+            committee.contains(&signer.signer().public())
+        };
+
+        let results = on_all(&contexts, |context| {
+            let committee = committee.iter().map(|k| k.0.to_vec()).collect();
+            keygen_committee(C::ID.to_string(), 3, committee, context)
+        })
+        .await;
+        let mut pubkey = vec![];
+        for (result, context) in results.into_iter().zip(&contexts) {
+            if is_member(context) {
+                pubkey = result.unwrap();
+            } else {
+                assert_eq!(result.unwrap_err(), Error::SelfNotInCommittee.to_string());
+            }
+        }
+        for context in &contexts {
+            let raw_info = context.keygen_entry(&hex::encode(&pubkey)).unwrap();
+            if !is_member(context) {
+                assert!(raw_info.is_none());
+                continue;
+            }
+            let info = serde_json::from_slice::<serde_json::Value>(&raw_info.unwrap()).unwrap();
+            let entry: KeygenEntry<C> = serde_json::from_value(info["entry"].clone()).unwrap();
+            assert_eq!(entry.committee.as_deref(), Some(committee.as_slice()));
+        }
+
+        let msg = b"committee only".to_vec();
+        let results = sign_on_all(&contexts, &pubkey, &msg).await;
+        let verifying_key = VerifyingKey::<C>::deserialize(&pubkey).unwrap();
+        let mut signers = 0;
+        for (result, context) in results.into_iter().zip(&contexts) {
+            if let Ok(signature) = result {
+                assert!(is_member(context));
+                let signature = Signature::<C>::deserialize(&signature).unwrap();
+                verifying_key.verify(&msg, &signature).unwrap();
+                signers += 1;
+            }
+        }
+        assert_eq!(signers, 3);
+    }
+
+    type Restakes = Arc<parking_lot::Mutex<Vec<(AccountId32, Percent)>>>;
+
+    /// A [`crate::testing::MockCoordinator`] whose restakes change when told to.
+    struct RestakingCoordinator {
+        inner: crate::testing::MockCoordinator,
+        restakes: Restakes,
+    }
+
+    #[async_trait::async_trait]
+    impl Coordinator for RestakingCoordinator {
+        fn service_id(&self) -> eyre::Result<u64> {
+            self.inner.service_id()
+        }
+
+        async fn operators(&self) -> eyre::Result<BTreeMap<AccountId32, ecdsa::Public>> {
+            self.inner.operators().await
+        }
+
+        async fn restakes(&self) -> eyre::Result<Vec<(AccountId32, Percent)>> {
+            Ok(self.restakes.lock().clone())
+        }
+
+        async fn paused_operators(
+            &self,
+            call_id: u64,
+            operators: &BTreeMap<AccountId32, ecdsa::Public>,
+        ) -> eyre::Result<BTreeSet<ecdsa::Public>> {
+            self.inner.paused_operators(call_id, operators).await
+        }
+
+        async fn set_online(&self, online: bool) -> eyre::Result<()> {
+            self.inner.set_online(online).await
+        }
+
+        async fn current_call_id(&self) -> eyre::Result<u64> {
+            self.inner.current_call_id().await
+        }
+
+        async fn call_block(&self, call_id: u64) -> eyre::Result<u64> {
+            self.inner.call_block(call_id).await
+        }
+
+        async fn call_time(&self, call_id: u64) -> eyre::Result<u64> {
+            self.inner.call_time(call_id).await
+        }
+
+        async fn caller(&self, call_id: u64) -> eyre::Result<AccountId32> {
+            self.inner.caller(call_id).await
+        }
+
+        async fn service_owner(&self) -> eyre::Result<AccountId32> {
+            self.inner.service_owner().await
+        }
+
+        async fn operators_changed(&self) -> eyre::Result<()> {
+            self.inner.operators_changed().await
+        }
+
+        async fn submitted_result(&self, call_id: u64) -> eyre::Result<Option<Vec<u8>>> {
+            self.inner.submitted_result(call_id).await
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn restake_changes_keep_the_signers_of_a_key() {
+        use crate::testing::{
+            keygen_on_all, operator_contexts, sign_on_all, MockCoordinator, MockNetwork,
+            MockNetworkConfig, TempDir,
+        };
+        use frost_core::{Signature, VerifyingKey};
+
+        type C = frost_secp256k1::Secp256K1Sha256;
+        let network = MockNetwork::new(MockNetworkConfig {
+            latency: Duration::from_millis(50),
+            loss: 0.0,
+        });
+        let dir = TempDir::new("keygen-restakes");
+        let contexts = operator_contexts(&network, &dir, 4, 901);
+        let operators = contexts[0].current_operators().await.unwrap();
+        // The first operator is below the minimum restake at the time of the keygen.
+        let restakes: Restakes = Arc::new(parking_lot::Mutex::new(
+            operators
+                .keys()
+                .enumerate()
+                .map(|(j, account)| (account.clone(), Percent(if j == 0 { 5 } else { 50 })))
+                .collect(),
+        ));
+        let contexts = contexts
+            .into_iter()
+            .map(|context| {
+                context
+                    .with_min_restake(Percent(10))
+                    .with_coordinator(RestakingCoordinator {
+                        inner: MockCoordinator {
+                            operators: operators.clone(),
+                            call_id: 901,
+                            change_after: None,
+                            caller: None,
+                        },
+                        restakes: restakes.clone(),
+                    })
+            })
+            .collect::<Vec<_>>();
+        let (left_out, members) = contexts.split_first().unwrap();
+        let pubkey = keygen_on_all(members, C::ID, 3).await;
+        let committee = operators.values().skip(1).copied().collect::<Vec<_>>();
+        for context in members {
+            let info = context.keygen_info(&hex::encode(&pubkey)).unwrap().unwrap();
+            let entry: KeygenEntry<C> = serde_json::from_value(info["entry"].clone()).unwrap();
+            assert_eq!(entry.committee.as_deref(), Some(committee.as_slice()));
+        }
+
+        // Once it reaches the minimum restake, the first operator would shift the identifiers
+        // of the others if they were taken from the operators of the time of the signing.
+        restakes.lock()[0].1 = Percent(50);
+        let msg = b"restaked".to_vec();
+        let results = sign_on_all(members, &pubkey, &msg).await;
+        let verifying_key = VerifyingKey::<C>::deserialize(&pubkey).unwrap();
+        for result in results {
+            let signature = Signature::<C>::deserialize(&result.unwrap()).unwrap();
+            verifying_key.verify(&msg, &signature).unwrap();
+        }
+        assert!(left_out
+            .keygen_info(&hex::encode(&pubkey))
+            .unwrap()
+            .is_none());
+
+        // Once a member falls below it, the key can no longer sign.
+        restakes.lock()[1].1 = Percent(5);
+        for result in sign_on_all(&members[1..], &pubkey, &msg).await {
+            assert_eq!(
+                result.unwrap_err(),
+                crate::sign::Error::CommitteeChanged.to_string()
+            );
+        }
+    }
 }
 
 #[cfg(all(test, feature = "e2e"))]
@@ -81,4 +81,28 @@ where
     fn ex(&self, key: &Self::Key) -> Result<bool, Self::Error> {
         Ok(self.contains_key(key))
     }
+
+    fn compare_and_swap(
+        &self,
+        key: Self::Key,
+        expected: Option<Self::Value>,
+        new: Self::Value,
+    ) -> Result<bool, Self::Error> {
+        let mut store = self.store.lock();
+        let current = store.get(&key).map(AsRef::as_ref);
+        if current != expected.as_ref().map(AsRef::as_ref) {
+            return Ok(false);
+        }
+        store.insert(key, new);
+        Ok(true)
+    }
+
+    fn keys(&self) -> Result<Vec<Vec<u8>>, Self::Error> {
+        Ok(self
+            .store
+            .lock()
+            .keys()
+            .map(|key| key.as_ref().to_vec())
+            .collect())
+    }
 }
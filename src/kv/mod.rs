@@ -1,13 +1,16 @@
 use std::sync::Arc;
+use std::time::{Duration, UNIX_EPOCH};
+
+use crate::clock::Clock;
 
 /// In-memory storage for the key-value store.
-#[cfg(feature = "kv-mem")]
+#[cfg(any(test, feature = "kv-mem"))]
 mod mem;
 /// Storage using [`sled`](https://docs.rs/sled) as the backend.
 #[cfg(feature = "kv-sled")]
 mod sled;
 
-#[cfg(feature = "kv-mem")]
+#[cfg(any(test, feature = "kv-mem"))]
 pub use mem::MemKVStore;
 #[cfg(feature = "kv-sled")]
 pub use sled::SledKVStore;
@@ -25,8 +28,192 @@ pub trait KVStore {
     fn del(&self, key: &Self::Key) -> Result<(), Self::Error>;
     #[allow(dead_code)]
     fn ex(&self, key: &Self::Key) -> Result<bool, Self::Error>;
+    /// Atomically set `key` to `new` if its current value is `expected`, `None` meaning that
+    /// the key is absent.
+    ///
+    /// Returns whether the value was swapped.
+    fn compare_and_swap(
+        &self,
+        key: Self::Key,
+        expected: Option<Self::Value>,
+        new: Self::Value,
+    ) -> Result<bool, Self::Error>;
+    /// All the keys of the store.
+    fn keys(&self) -> Result<Vec<Vec<u8>>, Self::Error>;
+    /// Flush the pending writes and reclaim the space left by the overwritten and removed
+    /// entries, if the backend keeps any.
+    fn compact(&self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+    /// The size of the store on disk in bytes, `None` for a store in memory.
+    fn size_on_disk(&self) -> Result<Option<u64>, Self::Error> {
+        Ok(None)
+    }
 }
 
 /// A shared, thread-safe, dynamic key-value store independent of the underlying storage.
 pub type SharedDynKVStore<K, V> =
     Arc<dyn KVStore<Key = K, Value = V, Error = std::io::Error> + Send + Sync + 'static>;
+
+/// How to retry a write that must not be lost.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// The total number of attempts, including the first one.
+    pub attempts: u32,
+    /// The delay before the first retry, doubled after every failed attempt.
+    pub backoff: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            attempts: 5,
+            backoff: Duration::from_millis(100),
+        }
+    }
+}
+
+/// Set `key` to `value`, retrying with an exponential backoff according to `policy`.
+///
+/// Returns the last error if all the attempts failed.
+pub async fn set_with_retry<S>(
+    store: &S,
+    key: S::Key,
+    value: S::Value,
+    policy: RetryPolicy,
+) -> Result<(), S::Error>
+where
+    S: KVStore + ?Sized,
+    S::Key: Clone,
+    S::Value: Clone,
+    S::Error: std::fmt::Display,
+{
+    let mut backoff = policy.backoff;
+    let mut attempt = 1;
+    loop {
+        match store.set(key.clone(), value.clone()) {
+            Ok(()) => return Ok(()),
+            Err(e) if attempt < policy.attempts => {
+                tracing::warn!(%attempt, error = %e, "Failed to write to the store, retrying");
+                tokio::time::sleep(backoff).await;
+                backoff = backoff.saturating_mul(2);
+                attempt += 1;
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// Set `key` to `value` until `ttl` from the time of `clock`, read it back with
+/// [`get_unexpired`].
+///
+/// The expiry, in milliseconds since the Unix epoch, is stored ahead of the value.
+#[allow(dead_code)]
+pub fn set_expiring(
+    store: &SharedDynKVStore<String, Vec<u8>>,
+    key: String,
+    value: &[u8],
+    ttl: Duration,
+    clock: &dyn Clock,
+) -> Result<(), std::io::Error> {
+    let expiry = (clock.now() + ttl)
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or_default();
+    store.set(key, [&expiry.to_be_bytes()[..], value].concat())
+}
+
+/// The value of `key` set with [`set_expiring`], `None` once expired at the time of `clock`,
+/// in which case the entry is removed.
+#[allow(dead_code)]
+pub fn get_unexpired(
+    store: &SharedDynKVStore<String, Vec<u8>>,
+    key: &String,
+    clock: &dyn Clock,
+) -> Result<Option<Vec<u8>>, std::io::Error> {
+    let Some(entry) = store.get(key)? else {
+        return Ok(None);
+    };
+    let (expiry, value) = entry.split_first_chunk::<8>().ok_or_else(|| {
+        std::io::Error::new(std::io::ErrorKind::InvalidData, "Expiring entry too short")
+    })?;
+    let expiry = UNIX_EPOCH + Duration::from_millis(u64::from_be_bytes(*expiry));
+    if clock.now() >= expiry {
+        store.del(key)?;
+        return Ok(None);
+    }
+    Ok(Some(value.to_vec()))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Barrier;
+
+    use super::*;
+    use crate::clock::MockClock;
+
+    /// Race `contenders` threads swapping the same absent key, returning the winners.
+    fn race(store: SharedDynKVStore<String, Vec<u8>>, contenders: u8) -> Vec<u8> {
+        let barrier = Arc::new(Barrier::new(usize::from(contenders)));
+        let threads = (0..contenders)
+            .map(|i| {
+                let (store, barrier) = (store.clone(), barrier.clone());
+                std::thread::spawn(move || {
+                    barrier.wait();
+                    store
+                        .compare_and_swap("key".into(), None, vec![i])
+                        .unwrap()
+                        .then_some(i)
+                })
+            })
+            .collect::<Vec<_>>();
+        let winners = threads
+            .into_iter()
+            .filter_map(|t| t.join().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(store.get(&"key".into()).unwrap(), Some(winners.clone()));
+        winners
+    }
+
+    #[test]
+    fn exactly_one_concurrent_swap_wins() {
+        let mem: SharedDynKVStore<String, Vec<u8>> = Arc::new(MemKVStore::new());
+        assert_eq!(race(mem, 16).len(), 1);
+        #[cfg(feature = "kv-sled")]
+        {
+            let sled: SharedDynKVStore<String, Vec<u8>> =
+                Arc::new(SledKVStore::in_memory().unwrap());
+            assert_eq!(race(sled.clone(), 16).len(), 1);
+            // A stale expectation is refused, the current one is accepted.
+            assert!(!sled.compare_and_swap("key".into(), None, vec![42]).unwrap());
+            let current = sled.get(&"key".into()).unwrap();
+            assert!(sled
+                .compare_and_swap("key".into(), current, vec![42])
+                .unwrap());
+        }
+    }
+
+    #[test]
+    fn entries_expire_with_the_clock() {
+        let store: SharedDynKVStore<String, Vec<u8>> = Arc::new(MemKVStore::new());
+        let clock = MockClock::default();
+        let key = "expiring".to_string();
+        set_expiring(
+            &store,
+            key.clone(),
+            b"value",
+            Duration::from_secs(60),
+            &clock,
+        )
+        .unwrap();
+        clock.advance(Duration::from_secs(59));
+        assert_eq!(
+            get_unexpired(&store, &key, &clock).unwrap(),
+            Some(b"value".to_vec())
+        );
+        clock.advance(Duration::from_secs(1));
+        assert_eq!(get_unexpired(&store, &key, &clock).unwrap(), None);
+        // The expired entry is gone from the store.
+        assert!(!store.ex(&key).unwrap());
+    }
+}
@@ -61,4 +61,38 @@ where
     fn ex(&self, key: &Self::Key) -> Result<bool, Self::Error> {
         self.db.contains_key(key).map_err(Into::into)
     }
+
+    fn compare_and_swap(
+        &self,
+        key: Self::Key,
+        expected: Option<Self::Value>,
+        new: Self::Value,
+    ) -> Result<bool, Self::Error> {
+        self.db
+            .compare_and_swap(
+                key,
+                expected.as_ref().map(AsRef::as_ref),
+                Some(new.as_ref()),
+            )
+            .map(|swapped| swapped.is_ok())
+            .map_err(Into::into)
+    }
+
+    fn keys(&self) -> Result<Vec<Vec<u8>>, Self::Error> {
+        self.db
+            .iter()
+            .keys()
+            .map(|key| key.map(|key| key.to_vec()).map_err(Into::into))
+            .collect()
+    }
+
+    /// Flush the log, so that sled rewrites the fragmented segments and frees the ones left
+    /// empty by the overwritten and removed entries.
+    fn compact(&self) -> Result<(), Self::Error> {
+        self.db.flush().map(|_| ()).map_err(Into::into)
+    }
+
+    fn size_on_disk(&self) -> Result<Option<u64>, Self::Error> {
+        self.db.size_on_disk().map(Some).map_err(Into::into)
+    }
 }
@@ -0,0 +1,199 @@
+//! Operator-local labels of the keys.
+//!
+//! A label is a human friendly name of a key, stored alongside its keygen entry and set with
+//! [`set_label`]. The signing and query jobs accept a label wherever they take a public key.
+//!
+//! Labels are not part of the protocols: every operator sets its own, and signing by label only
+//! works if the selected signers gave the same label to the same key.
+use api::services::events::JobCalled;
+use gadget_sdk as sdk;
+use sdk::event_listener::tangle::{
+    jobs::{services_post_processor, services_pre_processor},
+    TangleEventListener,
+};
+use sdk::tangle_subxt::tangle_testnet_runtime::api;
+
+use crate::FrostContext;
+
+/// The longest label, in bytes.
+pub const MAX_LABEL_LEN: usize = 64;
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("The Secret Share for that key is not found")]
+    KeyNotFound,
+    #[error("Label {0:?} is already used by another key")]
+    LabelInUse(String),
+    #[error("Labels are at most {MAX_LABEL_LEN} bytes long")]
+    LabelTooLong,
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[error(transparent)]
+    Entry(#[from] crate::entry::Error),
+    #[error(transparent)]
+    Unauthorized(#[from] crate::operators::Unauthorized),
+}
+
+pub(crate) fn label_key(label: &str) -> String {
+    format!("label/{label}")
+}
+
+/// Label a previously generated key on this node.
+///
+/// # Parameters
+/// - `pubkey`: The public key generated by the [`crate::keygen::keygen`] protocol, or its
+///   current label.
+/// - `label`: The new label of the key, or empty to remove its label.
+///
+/// # Returns
+/// The public key.
+///
+/// # Errors
+/// - `KeyNotFound`: If the key is not found.
+/// - `LabelInUse`: If another key already has that label.
+/// - `Unauthorized`: If the job is not called by the service owner or one of its operators.
+#[sdk::job(
+    id = 11,
+    params(pubkey, label),
+    result(_),
+    event_listener(
+        listener = TangleEventListener::<FrostContext, JobCalled>,
+        pre_processor = services_pre_processor,
+        post_processor = services_post_processor,
+    )
+)]
+#[tracing::instrument(skip_all, parent = context.config.span.clone(), err)]
+pub async fn set_label(
+    pubkey: Vec<u8>,
+    label: String,
+    context: FrostContext,
+) -> Result<Vec<u8>, Error> {
+    if label.len() > MAX_LABEL_LEN {
+        return Err(Error::LabelTooLong);
+    }
+    context.authorize_caller().await?;
+    let pubkey = context.resolve_key(pubkey)?;
+    let hex_pubkey = hex::encode(&pubkey);
+    let mut info = context
+        .keygen_info(&hex_pubkey)?
+        .ok_or(Error::KeyNotFound)?;
+    if !label.is_empty() {
+        match context.store.get(&label_key(&label))? {
+            Some(labelled) if labelled != pubkey => return Err(Error::LabelInUse(label)),
+            _ => context.store.set(label_key(&label), pubkey.clone())?,
+        }
+    }
+    if let Some(previous) = info["label"].as_str().filter(|previous| *previous != label) {
+        context.store.del(&label_key(previous))?;
+    }
+    info["label"] = serde_json::Value::String(label);
+    let info = crate::entry::encode(context.entry_format, &info)?;
+    context.store.set(hex_pubkey, info)?;
+    Ok(pubkey)
+}
+
+impl FrostContext {
+    /// The public key referenced by `key`, either a public key or the label of one.
+    ///
+    /// A public key with a keygen entry wins over a label with the same bytes, and a `key`
+    /// that is neither is returned as is.
+    pub(crate) fn resolve_key(&self, key: Vec<u8>) -> Result<Vec<u8>, std::io::Error> {
+        if self.keygen_entry(&hex::encode(&key))?.is_some() {
+            return Ok(key);
+        }
+        let Ok(label) = std::str::from_utf8(&key) else {
+            return Ok(key);
+        };
+        Ok(self.store.get(&label_key(label))?.unwrap_or(key))
+    }
+
+    /// The label of the key `pubkey` on this node, if any.
+    pub fn key_label(&self, pubkey: &[u8]) -> Result<Option<String>, std::io::Error> {
+        let Some(info) = self.keygen_info(&hex::encode(pubkey))? else {
+            return Ok(None);
+        };
+        Ok(info["label"]
+            .as_str()
+            .filter(|label| !label.is_empty())
+            .map(ToOwned::to_owned))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::testing::{
+        keygen_on_all, operator_contexts, sign_on_all, MockCoordinator, MockNetwork,
+        MockNetworkConfig, TempDir, SERVICE_OWNER,
+    };
+    use frost_core::{Ciphersuite, Signature, VerifyingKey};
+    use gadget_sdk::subxt_core::utils::AccountId32;
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn sign_by_label() {
+        type C = frost_secp256k1::Secp256K1Sha256;
+        let network = MockNetwork::new(MockNetworkConfig {
+            latency: Duration::from_millis(50),
+            loss: 0.0,
+        });
+        let dir = TempDir::new("labels");
+        let contexts = operator_contexts(&network, &dir, 3, 933);
+
+        let pubkey = keygen_on_all(&contexts, C::ID, 2).await;
+        for context in &contexts {
+            set_label(pubkey.clone(), "treasury".into(), context.clone())
+                .await
+                .unwrap();
+            assert_eq!(context.key_label(&pubkey).unwrap().unwrap(), "treasury");
+            assert!(matches!(
+                set_label(b"unknown".to_vec(), "other".into(), context.clone()).await,
+                Err(Error::KeyNotFound)
+            ));
+        }
+
+        let msg = b"signed by label".to_vec();
+        // The operator left out of the signers fails, the others sign with the labelled key.
+        let signatures = sign_on_all(&contexts, b"treasury", &msg).await;
+        let signatures = signatures.into_iter().flatten().collect::<Vec<_>>();
+        assert_eq!(signatures.len(), 2);
+        let verifying_key = VerifyingKey::<C>::deserialize(&pubkey).unwrap();
+        for signature in signatures {
+            let signature = Signature::<C>::deserialize(&signature).unwrap();
+            verifying_key.verify(&msg, &signature).unwrap();
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn only_the_owner_and_the_operators_label_keys() {
+        let network = MockNetwork::new(Default::default());
+        let dir = TempDir::new("labels-callers");
+        let context = operator_contexts(&network, &dir, 1, 933).remove(0);
+        let operators = context.current_operators().await.unwrap();
+        let called_by = |caller| {
+            context.clone().with_coordinator(MockCoordinator {
+                operators: operators.clone(),
+                call_id: 933,
+                change_after: None,
+                caller: Some(caller),
+            })
+        };
+
+        let outsider = called_by(AccountId32([0xff; 32]));
+        assert!(matches!(
+            set_label(b"unknown".to_vec(), "other".into(), outsider).await,
+            Err(Error::Unauthorized(
+                crate::operators::Unauthorized::NotPermitted { .. }
+            ))
+        ));
+        // The owner gets past the check, to the missing key.
+        let owner = called_by(SERVICE_OWNER);
+        assert!(matches!(
+            set_label(b"unknown".to_vec(), "other".into(), owner).await,
+            Err(Error::KeyNotFound)
+        ));
+    }
+}
@@ -1,24 +1,132 @@
 //! FROST Blueprint
+use std::collections::{BTreeMap, BTreeSet};
 use std::sync::Arc;
+use std::time::Duration;
 
 use color_eyre::eyre;
 use gadget_sdk as sdk;
 use gadget_sdk::contexts::MPCContext;
 use gadget_sdk::keystore::TanglePairSigner;
 use gadget_sdk::network::NetworkMultiplexer;
-use gadget_sdk::subxt_core::ext::sp_core::ecdsa;
+use gadget_sdk::subxt_core::ext::sp_core::{ecdsa, Pair};
+use gadget_sdk::subxt_core::utils::AccountId32;
+use gadget_sdk::tangle_subxt::tangle_testnet_runtime::api::runtime_types::sp_arithmetic::per_things::Percent;
 
 use gadget_sdk::subxt::tx::Signer;
 use sdk::contexts::{KeystoreContext, ServicesContext, TangleClientContext};
 
+/// Persistent peer address book
+pub mod address_book;
+/// Encrypted archives of the key store
+pub mod archive;
+/// Audit log of the jobs
+pub mod audit;
+/// Checkpoints of the batch signings
+mod checkpoint;
+/// Sources of the current time
+pub mod clock;
+/// Versioned encoding of the protocol messages
+pub mod codec;
+/// Compaction of the store
+pub mod compaction;
+/// Operator discovery and job calls
+pub mod coordinator;
+/// Protocol messages that could not be sent
+pub mod dead_letter;
+/// Child key derivation by additive tweaks of the group key
+pub mod derive;
+/// Diagnostics of the failed protocols
+pub mod diagnostics;
+/// EIP-712 typed data hashing
+pub mod eip712;
+/// Encoding of the stored keygen entries
+pub mod entry;
+/// Key package export
+pub mod export;
+/// Quorum-gated changes of the configuration
+pub mod governance;
 /// FROST Keygen module
 pub mod keygen;
 /// Key-Value Storage module
 mod kv;
+/// Operator-local key labels
+pub mod labels;
+/// Multibase and multicodec encoding of the job outputs
+pub mod multiformats;
+/// Operator selection policies
+pub mod operators;
+/// Persisted mapping of the party indices to the operators
+mod peer_mapping;
+/// Policies on the signed messages
+pub mod policy;
+/// Priorities of the protocol messages
+pub mod priority;
+/// Signed receipts of the participation in the keygens and signings
+pub mod receipt;
+/// Log redaction of sensitive values
+pub mod redact;
+/// FROST(Jubjub, BLAKE2b-512) ciphersuite
+pub mod redjubjub;
+/// Proactive refresh of the key shares
+pub mod refresh;
+/// Recording and replay of the protocol messages
+pub mod replay;
+/// Responsiveness of the selected signers
+mod responsiveness;
+/// Bound of the number of stored keys
+pub mod retention;
 /// FROST round-based module
 pub mod rounds;
+/// Routing of the protocol messages by an opaque identity of the parties
+pub mod routing;
+/// Loopback self-test of the protocols
+pub mod self_test;
+/// Network session identifiers
+mod session;
 /// FROST Signing module
 pub mod sign;
+/// Storage of the produced signatures
+pub mod signatures;
+/// Checks of the job results submitted on-chain
+pub mod submission;
+/// In-memory network for testing protocols
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
+/// Transcripts of the keygen messages
+pub mod transcript;
+/// Signing statistics of the keys
+pub mod usage;
+/// Deterministic keygen test vectors
+#[cfg(any(test, feature = "testing"))]
+pub mod vectors;
+/// Batch verification of signatures
+pub mod verify;
+/// Signature notifications webhook
+#[cfg(feature = "webhook")]
+pub mod webhook;
+/// Signing windows of the keys
+pub mod windows;
+/// Zcash transaction signature fields
+pub mod zcash;
+
+pub use address_book::AddressFamily;
+pub use codec::CodecVersion;
+pub use coordinator::{Coordinator, TangleCoordinator};
+pub use kv::RetryPolicy;
+pub use redact::Redaction;
+pub use session::{keygen_session_name, session_name, JobTimeout, TooManySessions};
+
+/// Keygen entries that could not be persisted, keyed by the hex encoded public key.
+type UnpersistedEntries = Arc<sdk::parking_lot::Mutex<BTreeMap<String, Vec<u8>>>>;
+
+/// Default bound of the active sessions registry.
+const DEFAULT_SESSION_LIMIT: usize = 1024;
+/// Default age after which an active session is considered stale.
+const DEFAULT_SESSION_MAX_AGE: Duration = Duration::from_secs(60 * 60);
+/// How long to wait before watching the operator set again after failing to.
+const OPERATOR_WATCH_RETRY: Duration = Duration::from_secs(10);
+/// Default bound of the rounds the operators run around a signing.
+const DEFAULT_SIGNING_TIMEOUT: Duration = Duration::from_secs(60);
 
 /// The network protocol for the FROST service
 const NETWORK_PROTOCOL: &str = "/zcash/frost/1.0.0";
@@ -32,41 +140,1234 @@ pub struct FrostContext {
     config: sdk::config::StdGadgetConfiguration,
     /// The gossip handle for the network
     network_backend: Arc<NetworkMultiplexer>,
+    /// Where the operators and job calls come from
+    coordinator: Arc<dyn Coordinator>,
     /// The key-value store for the service
     store: kv::SharedDynKVStore<String, Vec<u8>>,
     /// Account id
     #[allow(dead_code)]
     account_id: TanglePairSigner<ecdsa::Pair>,
+    /// The ECDSA key the network was started with, checked against the local key of the jobs
+    network_identity: Option<ecdsa::Public>,
+    /// Minimum restake exposure an operator needs to take part in the protocols
+    min_restake: Option<Percent>,
+    /// How to retry writing a keygen result to the store
+    write_retry: RetryPolicy,
+    /// Keygen entries that could not be written to the store, held until recovered
+    unpersisted: UnpersistedEntries,
+    /// How public keys and messages are written to the logs
+    log_redaction: Redaction,
+    /// Upper bound of the random delay before starting a keygen
+    keygen_jitter: Option<Duration>,
+    /// Whether the secret key packages can be exported
+    allow_secret_export: bool,
+    /// Whether the keygen result is signed with the operator's ECDSA key
+    sign_keygen_result: bool,
+    /// The codec versions of the protocol messages
+    codec: CodecVersion,
+    /// The keygen and signing sessions running on this node
+    sessions: session::SessionRegistry,
+    /// Whether the job results include the protocol timings
+    timing_report: bool,
+    /// Whether the job results include the signed participation receipt of this node
+    participation_receipts: bool,
+    /// Whether the signing results start with the aggregate nonce `R`
+    aggregate_nonce: bool,
+    /// Whether the `sign` signatures are bound to the block of the job call
+    block_bound: bool,
+    /// Whether a keygen is aborted when the operator set changes while it runs
+    watch_operator_set: bool,
+    /// The directory the message traces of the protocols are written to, if enabled
+    message_traces: Option<Arc<std::path::PathBuf>>,
+    /// Whether this node takes part in the protocols
+    participation: operators::Participation,
+    /// The ECDSA keys of the current operators, kept up to date by the operator-set refresh
+    allowed_keys: tokio::sync::watch::Receiver<BTreeSet<ecdsa::Public>>,
+    /// The operator set shared by the jobs, read again for every job if `None`
+    operator_cache: Option<Arc<operators::OperatorCache>>,
+    /// Whether the operator set is persisted and reused while unchanged
+    peer_mapping: bool,
+    /// The background task dropping the cached and persisted operator sets on a change
+    operator_watcher: Arc<sdk::parking_lot::Mutex<Option<tokio::task::JoinHandle<()>>>>,
+    /// What to do when the service has no operators at startup
+    empty_operators: operators::EmptyOperatorSet,
+    /// The encoding of the audit log entries
+    audit_format: audit::AuditFormat,
+    /// Whether the diagnostics of the failed protocols are persisted
+    diagnostics: bool,
+    /// Whether the messages that could not be sent are persisted instead of failing the protocol
+    dead_letters: bool,
+    /// The ciphersuites a keygen can use, all the supported ones if `None`
+    allowed_ciphersuites: Option<Arc<BTreeSet<String>>>,
+    /// The wall-clock budget of a keygen or signing job
+    job_timeout: Option<Duration>,
+    /// How long the operators wait on the rounds around a signing
+    signing_timeout: Duration,
+    /// How long to wait for the submitted job results to check them, unchecked if `None`
+    submission_check: Option<Duration>,
+    /// What the signers do with a signature share they cannot decode
+    malformed_shares: rounds::sign::MalformedShares,
+    /// What the signers do with a signer missing from the public key package
+    unknown_signers: rounds::sign::UnknownSigners,
+    /// What the signers do when the commitments of some signers do not arrive
+    missing_commitments: rounds::sign::MissingCommitments,
+    /// How the keygen parties check that they received the same round 1 broadcasts
+    keygen_broadcast_check: rounds::keygen::BroadcastCheck,
+    /// How many round 2 packages of a keygen are sent before waiting for them, all if `None`
+    keygen_round2_concurrency: Option<std::num::NonZeroUsize>,
+    /// What a signing does when fewer operators than the threshold are reachable
+    offline_signers: operators::OfflineSigners,
+    /// Which messages this node signs
+    message_policy: Arc<dyn policy::MessagePolicy>,
+    /// Which messages are well-formed
+    message_validator: Arc<dyn policy::MessageValidator>,
+    /// The number of messages of the checkpointed chunks of a batch, unchunked if `None`
+    batch_checkpoints: Option<std::num::NonZeroUsize>,
+    /// Whether a key signs every message at most once
+    unique_messages: bool,
+    /// The number of approvals a configuration change needs, a majority if `None`
+    config_quorum: Option<std::num::NonZeroU16>,
+    /// Which configuration changes this node votes for
+    config_approval: Arc<dyn governance::ConfigApproval>,
+    /// The nonce providers of the signers, by ciphersuite, each an
+    /// `Arc<dyn NonceProvider<C>>` of its ciphersuite `C`
+    nonce_providers: BTreeMap<&'static str, Arc<dyn std::any::Any + Send + Sync>>,
+    /// Which protocol messages are sent ahead of the others
+    message_priority: priority::MessagePriority,
+    /// The network the priorities of the protocol messages are handed to, if any
+    prioritized_network: Option<Arc<dyn priority::PrioritizedNetwork>>,
+    /// The peers this node is connected to, if known
+    connected_peers: Option<Arc<dyn operators::ConnectedPeers>>,
+    /// Where the current time is read from
+    clock: Arc<dyn clock::Clock>,
+    /// How the keygen and signing results are encoded
+    output_encoding: multiformats::OutputEncoding,
+    /// The bound of the number of stored keys, unbounded if `None`
+    key_limit: Option<retention::KeyLimit>,
+    /// How the keygen entries are written in the store
+    entry_format: entry::EntryFormat,
+    /// The oldest major version of `frost-core` whose keygen entries are read
+    min_frost_core: u64,
+    /// Whether the ciphersuite of the keygen entries without one is inferred from their keys
+    infer_ciphersuite: bool,
+    /// Webhook notified about every produced signature
+    #[cfg(feature = "webhook")]
+    webhook: Option<webhook::Webhook>,
 }
 
 impl FrostContext {
     /// Create a new service context
     pub fn new(config: sdk::config::StdGadgetConfiguration) -> eyre::Result<Self> {
+        Self::new_with_address_family(config, AddressFamily::default())
+    }
+
+    /// Create a new service context, dialing the bootnodes of the IP `family` only or first.
+    pub fn new_with_address_family(
+        config: sdk::config::StdGadgetConfiguration,
+        family: AddressFamily,
+    ) -> eyre::Result<Self> {
+        Self::start(config, family, None)
+    }
+
+    /// Create a new service context keeping its store in `storage_path` instead of the
+    /// `data_dir` of the blueprint, e.g. on a dedicated encrypted volume for the key shares.
+    pub fn new_with_storage_path(
+        config: sdk::config::StdGadgetConfiguration,
+        family: AddressFamily,
+        storage_path: impl Into<std::path::PathBuf>,
+    ) -> eyre::Result<Self> {
+        Self::start(config, family, Some(storage_path.into()))
+    }
+
+    fn start(
+        config: sdk::config::StdGadgetConfiguration,
+        family: AddressFamily,
+        storage_path: Option<std::path::PathBuf>,
+    ) -> eyre::Result<Self> {
         let network_identity = {
             let ed25519 = *config.first_ed25519_signer()?.signer();
             sdk::libp2p::identity::Keypair::ed25519_from_bytes(ed25519.seed())?
         };
         let my_ecdsa_key = config.first_ecdsa_signer()?;
+        let store = open_store(&config, storage_path.as_deref())?;
+        let (address_book, bootnodes) = address_book::bootnodes(&store, &config.bootnodes, family)?;
         let network_config = sdk::network::setup::NetworkConfig::new_service_network(
             network_identity,
             my_ecdsa_key.signer().clone(),
-            config.bootnodes.clone(),
+            bootnodes,
             config.target_port,
             NETWORK_PROTOCOL,
         );
         let gossip_handle = sdk::network::setup::start_p2p_network(network_config)
             .map_err(|e| eyre::eyre!("Failed to start the network: {e:?}"))?;
+        let peers = operators::GossipPeers::from(&gossip_handle);
+        let network_backend = Arc::new(NetworkMultiplexer::new(gossip_handle));
+        let identity = my_ecdsa_key.signer().public();
+        if let Ok(runtime) = tokio::runtime::Handle::try_current() {
+            // Prune the addresses that are no longer reachable, and record the ones of the
+            // peers connecting, for the next startup.
+            runtime.spawn(address_book.refresh(store.clone()));
+            let own = address_book::listen_address(config.target_addr, config.target_port);
+            runtime.spawn(address_book::exchange(
+                network_backend.clone(),
+                Arc::new(peers.clone()),
+                identity,
+                own.into_iter().collect(),
+                store.clone(),
+                address_book::ANNOUNCE_POLL,
+            ));
+        }
+        Ok(Self::from_parts(config, network_backend, store)?
+            .with_connected_peers(peers)
+            .with_network_identity(identity))
+    }
+
+    /// Create a service context running the protocols over an already started `network`.
+    ///
+    /// Unlike [`FrostContext::new`], no peer-to-peer network is started, this is meant to
+    /// run the protocols outside of a Tangle deployment along with
+    /// [`FrostContext::with_coordinator`].
+    pub fn with_network(
+        config: sdk::config::StdGadgetConfiguration,
+        network: Arc<NetworkMultiplexer>,
+    ) -> eyre::Result<Self> {
+        let store = open_store(&config, None)?;
+        Self::from_parts(config, network, store)
+    }
+
+    fn from_parts(
+        config: sdk::config::StdGadgetConfiguration,
+        network_backend: Arc<NetworkMultiplexer>,
+        store: kv::SharedDynKVStore<String, Vec<u8>>,
+    ) -> eyre::Result<Self> {
+        let my_ecdsa_key = config.first_ecdsa_signer()?;
+        let clock: Arc<dyn clock::Clock> = Arc::new(clock::SystemClock);
         Ok(Self {
-            #[cfg(not(feature = "kv-sled"))]
-            store: Arc::new(kv::MemKVStore::new()),
-            #[cfg(feature = "kv-sled")]
-            store: match config.data_dir.as_ref() {
-                Some(data_dir) => Arc::new(kv::SledKVStore::from_path(data_dir)?),
-                None => Arc::new(kv::SledKVStore::in_memory()?),
-            },
+            store,
+            coordinator: Arc::new(TangleCoordinator::new(config.clone())),
             config,
             account_id: my_ecdsa_key,
-            network_backend: Arc::new(NetworkMultiplexer::new(gossip_handle)),
+            network_identity: None,
+            network_backend,
+            min_restake: None,
+            write_retry: RetryPolicy::default(),
+            unpersisted: Default::default(),
+            log_redaction: Redaction::default(),
+            keygen_jitter: None,
+            allow_secret_export: false,
+            sign_keygen_result: false,
+            codec: CodecVersion::default(),
+            sessions: session::SessionRegistry::new(
+                DEFAULT_SESSION_LIMIT,
+                DEFAULT_SESSION_MAX_AGE,
+                clock.clone(),
+            ),
+            timing_report: false,
+            participation_receipts: false,
+            aggregate_nonce: false,
+            block_bound: false,
+            watch_operator_set: false,
+            message_traces: None,
+            participation: Default::default(),
+            allowed_keys: tokio::sync::watch::channel(BTreeSet::new()).1,
+            operator_cache: None,
+            peer_mapping: false,
+            operator_watcher: Default::default(),
+            empty_operators: Default::default(),
+            audit_format: Default::default(),
+            diagnostics: false,
+            dead_letters: false,
+            allowed_ciphersuites: None,
+            job_timeout: None,
+            signing_timeout: DEFAULT_SIGNING_TIMEOUT,
+            submission_check: None,
+            malformed_shares: Default::default(),
+            unknown_signers: Default::default(),
+            missing_commitments: Default::default(),
+            keygen_broadcast_check: Default::default(),
+            keygen_round2_concurrency: None,
+            offline_signers: Default::default(),
+            message_policy: Arc::new(policy::AllowAll),
+            message_validator: Arc::new(policy::AnyMessage),
+            batch_checkpoints: None,
+            unique_messages: false,
+            config_quorum: None,
+            config_approval: Arc::new(governance::ApproveAll),
+            nonce_providers: BTreeMap::new(),
+            message_priority: Default::default(),
+            prioritized_network: None,
+            connected_peers: None,
+            clock,
+            output_encoding: Default::default(),
+            key_limit: None,
+            entry_format: Default::default(),
+            min_frost_core: entry::FROST_CORE_VERSION,
+            infer_ciphersuite: true,
+            #[cfg(feature = "webhook")]
+            webhook: None,
+        })
+    }
+
+    /// Discover the operators and job calls from `coordinator` instead of the Tangle service.
+    ///
+    /// Every operator of the group must use the same coordinator, or at least agree on the
+    /// operators and call ids it returns.
+    pub fn with_coordinator(mut self, coordinator: impl Coordinator + 'static) -> Self {
+        self.coordinator = Arc::new(coordinator);
+        self
+    }
+
+    /// Exclude operators with less than `min_restake` restake exposure from keygen and signing.
+    ///
+    /// All the operators of the service must use the same value, otherwise they will not agree
+    /// on the participants and their identifiers.
+    ///
+    /// The operators kept by a keygen are recorded with its key, see
+    /// [`keygen::KeygenEntry::committee`], so its signings keep their identifiers when the
+    /// restakes change: an operator reaching `min_restake` later does not sign with the key,
+    /// and the signings fail with [`sign::Error::CommitteeChanged`] once one of the recorded
+    /// operators falls below it.
+    pub fn with_min_restake(mut self, min_restake: Percent) -> Self {
+        self.min_restake = Some(min_restake);
+        self
+    }
+
+    /// Set how writing a keygen result to the store is retried before giving up.
+    pub fn with_write_retry(mut self, policy: RetryPolicy) -> Self {
+        self.write_retry = policy;
+        self
+    }
+
+    /// Redact public keys and messages in the logs.
+    pub fn with_log_redaction(mut self, redaction: Redaction) -> Self {
+        self.log_redaction = redaction;
+        self
+    }
+
+    /// Wait a random delay of up to `max` before starting a keygen.
+    ///
+    /// This spreads the first broadcasts of large operator sets over time instead of having
+    /// every operator flood the gossip network at once.
+    pub fn with_keygen_jitter(mut self, max: Duration) -> Self {
+        self.keygen_jitter = Some(max);
+        self
+    }
+
+    /// Store at most `max_keys` keys, a keygen past that being handled according to `policy`.
+    ///
+    /// Only the keys generated since the index of the stored keys exists are counted, see
+    /// [`retention`].
+    pub fn with_key_limit(mut self, max_keys: usize, policy: retention::KeyLimitPolicy) -> Self {
+        self.key_limit = Some(retention::KeyLimit { max_keys, policy });
+        self
+    }
+
+    /// Set how the keygen entries are written in the store.
+    ///
+    /// Defaults to [`entry::EntryFormat::Json`]. The entries of the other format are still
+    /// read, and rewritten in this one the first time they are.
+    pub fn with_entry_format(mut self, format: entry::EntryFormat) -> Self {
+        self.entry_format = format;
+        self
+    }
+
+    /// Read the keygen entries written with a major version of `frost-core` from `version`,
+    /// warning about the ones older than [`entry::FROST_CORE_VERSION`], and refusing the others
+    /// with [`entry::Error::IncompatibleFrostCore`].
+    ///
+    /// Defaults to [`entry::FROST_CORE_VERSION`], for an older serialization may not read back.
+    pub fn with_min_frost_core_version(mut self, version: u64) -> Self {
+        self.min_frost_core = version;
+        self
+    }
+
+    /// Infer the ciphersuite of the keygen entries missing it from their key packages, see
+    /// [`entry::infer_ciphersuite`], and rewrite them with it.
+    ///
+    /// Enabled by default. When disabled, such an entry is reported as an invalid entry instead.
+    pub fn with_ciphersuite_inference(mut self, infer: bool) -> Self {
+        self.infer_ciphersuite = infer;
+        self
+    }
+
+    /// Get the raw keygen entry of the hex encoded public key, from the store or from the
+    /// entries that could not be persisted.
+    pub(crate) fn keygen_entry(&self, pubkey: &str) -> Result<Option<Vec<u8>>, std::io::Error> {
+        match self.store.get(&pubkey.to_string())? {
+            Some(entry) => Ok(Some(entry)),
+            None => Ok(self.unpersisted.lock().get(pubkey).cloned()),
+        }
+    }
+
+    /// Get the JSON envelope of the keygen entry of the hex encoded public key, see
+    /// [`entry`], migrating a stored entry to the format set with
+    /// [`FrostContext::with_entry_format`], and recovering its ciphersuite if missing, see
+    /// [`FrostContext::with_ciphersuite_inference`].
+    ///
+    /// Fails if the entry was written with a version of `frost-core` this node does not read,
+    /// see [`FrostContext::with_min_frost_core_version`].
+    pub(crate) fn keygen_info(
+        &self,
+        pubkey: &str,
+    ) -> Result<Option<serde_json::Value>, std::io::Error> {
+        let Some(raw) = self.store.get(&pubkey.to_string())? else {
+            let raw = self.unpersisted.lock().get(pubkey).cloned();
+            return raw
+                .map(|raw| {
+                    entry::check_frost_core(&raw, self.min_frost_core)?;
+                    self.with_ciphersuite(entry::decode(&raw)?)
+                })
+                .transpose()
+                .map_err(Into::into);
+        };
+        entry::check_frost_core(&raw, self.min_frost_core)?;
+        let decoded = entry::decode(&raw)?;
+        let inferred = !decoded["ciphersuite"].is_string();
+        let info = self.with_ciphersuite(decoded)?;
+        if inferred {
+            sdk::warn!(
+                pubkey = %self.log_redaction.redact(pubkey),
+                ciphersuite = %info["ciphersuite"],
+                "Recovered the missing ciphersuite of the keygen entry"
+            );
+        }
+        if inferred || entry::format_of(&raw) != self.entry_format {
+            let migrated = entry::encode(self.entry_format, &info)
+                .map_err(std::io::Error::from)
+                .and_then(|migrated| self.store.set(pubkey.to_string(), migrated));
+            if let Err(e) = migrated {
+                sdk::warn!(
+                    pubkey = %self.log_redaction.redact(pubkey),
+                    error = %e,
+                    "Failed to migrate the keygen entry"
+                );
+            }
+        }
+        Ok(Some(info))
+    }
+
+    /// The JSON envelope `info`, with its ciphersuite inferred if missing.
+    fn with_ciphersuite(
+        &self,
+        mut info: serde_json::Value,
+    ) -> Result<serde_json::Value, entry::Error> {
+        if !info["ciphersuite"].is_string() {
+            if !self.infer_ciphersuite {
+                return Err(entry::Error::Malformed("no ciphersuite".to_string()));
+            }
+            info["ciphersuite"] = entry::infer_ciphersuite(&info)?.into();
+        }
+        Ok(info)
+    }
+
+    /// Allow exporting this operator's secret key packages with
+    /// [`FrostContext::export_key_package`].
+    pub fn with_secret_export(mut self, allow: bool) -> Self {
+        self.allow_secret_export = allow;
+        self
+    }
+
+    /// Append to the keygen result this operator's ECDSA signature of the verifying key, bound
+    /// to the service and the job call, see [`keygen::sign_keygen_result`].
+    ///
+    /// This lets anyone hold the operator accountable for the key it reported.
+    pub fn with_signed_keygen_result(mut self, sign: bool) -> Self {
+        self.sign_keygen_result = sign;
+        self
+    }
+
+    /// Set the codec versions of the protocol messages this node writes and accepts.
+    ///
+    /// Defaults to [`codec::CODEC_VERSION`] for both.
+    pub fn with_codec_version(mut self, codec: CodecVersion) -> Self {
+        self.codec = codec;
+        self
+    }
+
+    /// Run at most `limit` keygen and signing sessions at once, new sessions failing with
+    /// [`TooManySessions`] beyond that.
+    ///
+    /// When the limit is reached, the sessions started more than `max_age` ago are considered
+    /// leaked and evicted from the registry.
+    pub fn with_session_limit(mut self, limit: usize, max_age: Duration) -> Self {
+        self.sessions = session::SessionRegistry::new(limit, max_age, self.clock.clone());
+        self
+    }
+
+    /// Encode the keygen and signing results in `encoding`.
+    ///
+    /// Defaults to the raw bytes, see [`multiformats`] for the
+    /// [`OutputEncoding::Multibase`](multiformats::OutputEncoding::Multibase) layout.
+    pub fn with_output_encoding(mut self, encoding: multiformats::OutputEncoding) -> Self {
+        self.output_encoding = encoding;
+        self
+    }
+
+    /// Read the current time from `clock`, for the audit log, the key usage and the age of the
+    /// sessions among others.
+    ///
+    /// Meant for tests, with a [`MockClock`](clock::MockClock), this resets the registry of the
+    /// active sessions.
+    pub fn with_clock(mut self, clock: impl clock::Clock + 'static) -> Self {
+        self.clock = Arc::new(clock);
+        self.sessions = self.sessions.with_clock(self.clock.clone());
+        self
+    }
+
+    /// Return the keygen and signing results as a JSON
+    /// [`TimedOutput`](rounds::trace::TimedOutput), along with the round and stage durations
+    /// of the protocol.
+    pub fn with_timing_report(mut self, enabled: bool) -> Self {
+        self.timing_report = enabled;
+        self
+    }
+
+    /// Return the keygen and signing results as a JSON [`ReceiptOutput`](receipt::ReceiptOutput),
+    /// along with the receipt of the participation of this node signed with its operator key,
+    /// see [`receipt`].
+    ///
+    /// Applied after [`FrostContext::with_timing_report`], whose output is then the one
+    /// wrapped.
+    pub fn with_participation_receipts(mut self, enabled: bool) -> Self {
+        self.participation_receipts = enabled;
+        self
+    }
+
+    /// Return the serialized aggregate nonce `R` of the signature ahead of the signing results,
+    /// for the verifiers that need it separately, see [`rounds::sign::aggregate_nonce`].
+    ///
+    /// Batch signing results are left as they are.
+    pub fn with_aggregate_nonce(mut self, enabled: bool) -> Self {
+        self.aggregate_nonce = enabled;
+        self
+    }
+
+    /// Have the `sign` job sign its message bound to the number of the block of the job call,
+    /// see [`sign::block_bound_message`], and return the block number ahead of the signature,
+    /// so that the signature cannot be replayed at another height.
+    ///
+    /// Every signer reads the same block number from the chain, whatever its own view of it.
+    pub fn with_block_bound_signing(mut self, enabled: bool) -> Self {
+        self.block_bound = enabled;
+        self
+    }
+
+    /// Abort a keygen with [`keygen::Error::OperatorSetChangedMidProtocol`] if an operator joins
+    /// or leaves the service while it runs.
+    ///
+    /// The identifiers of the keygen follow the order of the operators, so its key would not
+    /// match the new set. Aborting lets the keygen be restarted with it.
+    pub fn with_operator_set_watch(mut self, enabled: bool) -> Self {
+        self.watch_operator_set = enabled;
+        self
+    }
+
+    /// Record every message sent and received during the keygens and signings, and write their
+    /// [`replay::Trace`] to a file of `directory`, to be replayed with [`replay::replay`].
+    ///
+    /// The traces hold the secret shares sent to this node, `directory` must be kept as safe
+    /// as the store.
+    pub fn with_message_traces(mut self, directory: impl Into<std::path::PathBuf>) -> Self {
+        self.message_traces = Some(Arc::new(directory.into()));
+        self
+    }
+
+    /// Set the encoding of the audit log entries written from now on.
+    ///
+    /// Defaults to [`audit::AuditFormat::Json`], the entries already written stay readable.
+    pub fn with_audit_format(mut self, format: audit::AuditFormat) -> Self {
+        self.audit_format = format;
+        self
+    }
+
+    /// Persist the messages received and the error of every failed keygen and signing, see
+    /// [`diagnostics::get_diagnostics`].
+    pub fn with_diagnostics(mut self, enabled: bool) -> Self {
+        self.diagnostics = enabled;
+        self
+    }
+
+    /// Persist the protocol messages the network fails to send and go on without them, instead
+    /// of failing the protocol, see [`dead_letter::dead_letters`].
+    pub fn with_dead_letters(mut self, enabled: bool) -> Self {
+        self.dead_letters = enabled;
+        self
+    }
+
+    /// Only allow a keygen with one of the `ciphersuites`, by `ID`, even if others are supported.
+    ///
+    /// By default, any supported ciphersuite is allowed.
+    pub fn with_allowed_ciphersuites(
+        mut self,
+        ciphersuites: impl IntoIterator<Item = impl Into<String>>,
+    ) -> Self {
+        let ciphersuites = ciphersuites.into_iter().map(Into::into).collect();
+        self.allowed_ciphersuites = Some(Arc::new(ciphersuites));
+        self
+    }
+
+    /// Only allow a keygen with one of the `ciphersuites`, by `ID`, as
+    /// [`FrostContext::with_allowed_ciphersuites`], failing if this build does not support one
+    /// of them.
+    ///
+    /// A service misconfigured with a ciphersuite missing from the build then fails closed at
+    /// startup, instead of at its first keygen.
+    pub fn with_expected_ciphersuites(
+        self,
+        ciphersuites: impl IntoIterator<Item = impl Into<String>>,
+    ) -> eyre::Result<Self> {
+        let ciphersuites = ciphersuites
+            .into_iter()
+            .map(Into::into)
+            .collect::<BTreeSet<String>>();
+        let unsupported = ciphersuites
+            .iter()
+            .filter(|c| !self_test::CIPHERSUITES.contains(&c.as_str()))
+            .collect::<Vec<_>>();
+        if !unsupported.is_empty() {
+            return Err(eyre::eyre!(
+                "This build does not support the ciphersuites {unsupported:?} of the service, \
+                 only {:?}",
+                self_test::CIPHERSUITES
+            ));
+        }
+        Ok(self.with_allowed_ciphersuites(ciphersuites))
+    }
+
+    /// Read back the results of the keygen and signing jobs once submitted on-chain, waiting up
+    /// to `timeout` for them, and log an error if they are not the ones computed locally, see
+    /// [`submission`].
+    pub fn with_submission_check(mut self, timeout: Duration) -> Self {
+        self.submission_check = Some(timeout);
+        self
+    }
+
+    /// Abort a keygen or signing job with [`JobTimeout`] if it runs for longer than `budget`.
+    ///
+    /// The budget covers the whole job, from the network setup to the rounds, the aggregation
+    /// and the persistence of the result, so it should leave room for every round of the
+    /// protocol. By default, a job runs until its protocol completes or fails.
+    pub fn with_job_timeout(mut self, budget: Duration) -> Self {
+        self.job_timeout = Some(budget);
+        self
+    }
+
+    /// Give up on the rounds the operators run around a signing after `timeout`, 60 seconds by
+    /// default.
+    ///
+    /// It bounds how long an operator waits for the outcome of the selected signers, see
+    /// [`FrostContext::with_unique_messages`], and for the randomness of an ephemeral key, see
+    /// [`crate::derive::derive_ephemeral`], so that a signer that crashed or left the network
+    /// does not leave the others waiting, whatever the [`FrostContext::with_job_timeout`].
+    pub fn with_signing_timeout(mut self, timeout: Duration) -> Self {
+        self.signing_timeout = timeout;
+        self
+    }
+
+    /// Set what the signers do with a signature share that fails to decode.
+    ///
+    /// Defaults to [`MalformedShares::Abort`](rounds::sign::MalformedShares::Abort), with
+    /// [`MalformedShares::Blame`](rounds::sign::MalformedShares::Blame) the signing still fails,
+    /// but only once every other share is in, with the sender blamed with the invalid shares.
+    pub fn with_malformed_shares(mut self, policy: rounds::sign::MalformedShares) -> Self {
+        self.malformed_shares = policy;
+        self
+    }
+
+    /// Set what the signers do with a signer missing from the public key package of the key.
+    ///
+    /// Defaults to [`UnknownSigners::Abort`](rounds::sign::UnknownSigners::Abort), with
+    /// [`UnknownSigners::Blame`](rounds::sign::UnknownSigners::Blame) the signing still fails,
+    /// but with the unknown signers blamed instead of a bug.
+    pub fn with_unknown_signers(mut self, policy: rounds::sign::UnknownSigners) -> Self {
+        self.unknown_signers = policy;
+        self
+    }
+
+    /// Set what the signers do when the commitments of some signers do not arrive, e.g. when a
+    /// network partition splits them so that neither side has the threshold.
+    ///
+    /// Defaults to [`MissingCommitments::Wait`](rounds::sign::MissingCommitments::Wait), the
+    /// signing then runs until [`FrostContext::with_job_timeout`]. With
+    /// [`MissingCommitments::FailFast`](rounds::sign::MissingCommitments::FailFast) it fails
+    /// early with [`sign::Error::QuorumNotReached`] instead.
+    pub fn with_missing_commitments(mut self, policy: rounds::sign::MissingCommitments) -> Self {
+        self.missing_commitments = policy;
+        self
+    }
+
+    /// Set how the keygen parties check that they all received the same round 1 broadcasts.
+    ///
+    /// Defaults to [`BroadcastCheck::Unchecked`](rounds::keygen::BroadcastCheck::Unchecked).
+    /// [`BroadcastCheck::Echo`](rounds::keygen::BroadcastCheck::Echo) adds a round to detect a
+    /// party sending different packages to different operators, every operator of the service
+    /// must then enable it.
+    pub fn with_keygen_broadcast_check(mut self, check: rounds::keygen::BroadcastCheck) -> Self {
+        self.keygen_broadcast_check = check;
+        self
+    }
+
+    /// Send at most `max_in_flight` round 2 packages of a keygen before waiting for them to be
+    /// sent, instead of all of them at once.
+    pub fn with_keygen_round2_concurrency(mut self, max_in_flight: std::num::NonZeroUsize) -> Self {
+        self.keygen_round2_concurrency = Some(max_in_flight);
+        self
+    }
+
+    /// Set what a signing does when fewer operators than the threshold are reachable.
+    ///
+    /// Defaults to [`OfflineSigners::Wait`](operators::OfflineSigners::Wait), with
+    /// [`OfflineSigners::FailFast`](operators::OfflineSigners::FailFast) the signing fails with
+    /// [`sign::Error::InsufficientSigners`] instead of stalling until the job times out. The
+    /// check needs the [`FrostContext::with_connected_peers`], which the gossip network of
+    /// [`FrostContext::new`] provides.
+    pub fn with_offline_signers(mut self, policy: operators::OfflineSigners) -> Self {
+        self.offline_signers = policy;
+        self
+    }
+
+    /// Only sign the messages `policy` allows, the signing jobs of the others failing with
+    /// [`sign::Error::MessageRejected`] before the protocol runs.
+    ///
+    /// Defaults to [`AllowAll`](policy::AllowAll).
+    pub fn with_message_policy(mut self, policy: impl policy::MessagePolicy + 'static) -> Self {
+        self.message_policy = Arc::new(policy);
+        self
+    }
+
+    /// Sign the batches in chunks of `chunk` messages, checkpointing the signatures of every
+    /// completed chunk, so that a batch signed again, e.g. by a later job call after a failure,
+    /// resumes after the last chunk completed by all its signers instead of signing it all again.
+    ///
+    /// The same signers are then selected for every job call signing a batch.
+    pub fn with_batch_checkpoints(mut self, chunk: std::num::NonZeroUsize) -> Self {
+        self.batch_checkpoints = Some(chunk);
+        self
+    }
+
+    /// Only sign the messages `validator` finds well-formed, the signing jobs of the others
+    /// failing with [`sign::Error::InvalidMessageSchema`] before the protocol runs.
+    ///
+    /// Defaults to [`AnyMessage`](policy::AnyMessage).
+    pub fn with_message_validator(
+        mut self,
+        validator: impl policy::MessageValidator + 'static,
+    ) -> Self {
+        self.message_validator = Arc::new(validator);
+        self
+    }
+
+    /// Sign every message at most once with a key, the signing jobs of a message the key
+    /// already signed failing with [`sign::Error::MessageAlreadySigned`] instead of signing it
+    /// again, see [`signatures`].
+    ///
+    /// For the keys of one-time authorizations. A client that lost a signature can still fetch
+    /// it with [`signatures::get_signature`].
+    ///
+    /// Every operator holding a share of the key then waits for the outcome of the selected
+    /// signers, up to [`FrostContext::with_signing_timeout`], to keep the claim on the messages
+    /// once they are signed.
+    pub fn with_unique_messages(mut self, enabled: bool) -> Self {
+        self.unique_messages = enabled;
+        self
+    }
+
+    /// Apply a configuration change once approved by `quorum` operators, see [`governance`],
+    /// instead of a majority of them.
+    ///
+    /// Every operator must set the same quorum, a vote among operators with different ones
+    /// fails.
+    pub fn with_config_quorum(mut self, quorum: std::num::NonZeroU16) -> Self {
+        self.config_quorum = Some(quorum);
+        self
+    }
+
+    /// Vote on the proposed configuration changes as `approval` decides, see [`governance`].
+    ///
+    /// Defaults to [`ApproveAll`](governance::ApproveAll).
+    pub fn with_config_approval(
+        mut self,
+        approval: impl governance::ConfigApproval + 'static,
+    ) -> Self {
+        self.config_approval = Arc::new(approval);
+        self
+    }
+
+    /// Source the nonces of the signings of the ciphersuite `C` from `provider`, e.g. a hardware
+    /// module, instead of generating them in memory, see
+    /// [`NonceProvider`](rounds::sign::NonceProvider), in the single and the batch signings.
+    pub fn with_nonce_provider<C>(
+        mut self,
+        provider: impl rounds::sign::NonceProvider<C> + 'static,
+    ) -> Self
+    where
+        C: frost_core::Ciphersuite + 'static,
+    {
+        let provider: Arc<dyn rounds::sign::NonceProvider<C>> = Arc::new(provider);
+        self.nonce_providers.insert(C::ID, Arc::new(provider));
+        self
+    }
+
+    /// The nonce provider of the signings of the ciphersuite `C`, if any.
+    pub(crate) fn nonce_provider<C>(&self) -> Option<Arc<dyn rounds::sign::NonceProvider<C>>>
+    where
+        C: frost_core::Ciphersuite + 'static,
+    {
+        self.nonce_providers
+            .get(C::ID)?
+            .downcast_ref::<Arc<dyn rounds::sign::NonceProvider<C>>>()
+            .cloned()
+    }
+
+    /// Hand the priority of every protocol message, given by `policy`, to `network` before it
+    /// is sent, see [`priority`].
+    ///
+    /// Defaults to [`MessagePriority::Unprioritized`](priority::MessagePriority::Unprioritized).
+    pub fn with_message_priority(
+        mut self,
+        policy: priority::MessagePriority,
+        network: impl priority::PrioritizedNetwork + 'static,
+    ) -> Self {
+        self.message_priority = policy;
+        self.prioritized_network = Some(Arc::new(network));
+        self
+    }
+
+    /// Set the ECDSA key the network of [`FrostContext::with_network`] was started with, the one
+    /// the peers know this node by.
+    ///
+    /// The keygen and signing jobs then fail with [`operators::IdentityMismatch`] if the local
+    /// ECDSA key of the keystore is another one. [`FrostContext::new`] sets it to the key it
+    /// starts the network with.
+    pub fn with_network_identity(mut self, key: ecdsa::Public) -> Self {
+        self.network_identity = Some(key);
+        self
+    }
+
+    /// The local ECDSA key, checked against the network identity if known.
+    pub(crate) fn local_identity<E>(&self) -> Result<ecdsa::Public, E>
+    where
+        E: From<sdk::config::Error> + From<operators::IdentityMismatch>,
+    {
+        let local = self.config.first_ecdsa_signer()?.signer().public();
+        match self.network_identity {
+            Some(network) if network != local => Err(operators::IdentityMismatch {
+                network: hex::encode(network),
+                local: hex::encode(local),
+            }
+            .into()),
+            _ => Ok(local),
+        }
+    }
+
+    /// Read the peers this node is connected to from `peers`.
+    pub fn with_connected_peers(mut self, peers: impl operators::ConnectedPeers + 'static) -> Self {
+        self.connected_peers = Some(Arc::new(peers));
+        self
+    }
+
+    /// Run the protocol of a job `job` within the budget set with
+    /// [`FrostContext::with_job_timeout`].
+    pub(crate) async fn within_job_timeout<T, E>(
+        &self,
+        job: impl std::future::Future<Output = Result<T, E>>,
+    ) -> Result<T, E>
+    where
+        E: From<JobTimeout>,
+    {
+        let Some(budget) = self.job_timeout else {
+            return job.await;
+        };
+        match tokio::time::timeout(budget, job).await {
+            Ok(result) => result,
+            Err(_) => {
+                sdk::warn!(?budget, "The job ran out of time, aborting it");
+                Err(JobTimeout { budget }.into())
+            }
+        }
+    }
+
+    /// The job result of `output` of the job call `call_id`, with the protocol `timing` and the
+    /// participation receipt if enabled.
+    pub(crate) fn job_result(
+        &self,
+        call_id: u64,
+        output: Vec<u8>,
+        timing: Option<rounds::trace::TimingReport>,
+    ) -> Result<Vec<u8>, serde_json::Error> {
+        let output = match self.timing_report {
+            true => serde_json::to_vec(&rounds::trace::TimedOutput {
+                output: hex::encode(output),
+                timing,
+            })?,
+            false => output,
+        };
+        if !self.participation_receipts {
+            return Ok(output);
+        }
+        serde_json::to_vec(&receipt::ReceiptOutput {
+            output: hex::encode(output),
+            receipt: self.receipt(call_id),
         })
     }
+
+    /// Re-read the on-chain operator set every `interval` in the background, and publish it to
+    /// the [`FrostContext::allowed_keys`] subscribers.
+    ///
+    /// Must be called from within a tokio runtime.
+    pub fn with_operator_refresh(mut self, interval: Duration) -> Self {
+        let (tx, rx) = tokio::sync::watch::channel(BTreeSet::new());
+        self.allowed_keys = rx;
+        let context = self.clone();
+        tokio::spawn(operators::refresh_allowed_keys(
+            move || {
+                let context = context.clone();
+                async move { context.fetch_operators().await }
+            },
+            interval,
+            tx,
+        ));
+        self
+    }
+
+    /// Share the operator set read by a job with the jobs starting within `ttl` after it,
+    /// instead of reading it from the chain for every job.
+    ///
+    /// The cached set is dropped as soon as the operators change, watched in the background,
+    /// so that no job assigns the identifiers of a stale set. Must be called from within a
+    /// tokio runtime, after [`FrostContext::with_coordinator`] if any.
+    pub fn with_operator_cache(mut self, ttl: Duration) -> Self {
+        self.operator_cache = Some(Arc::new(operators::OperatorCache::new(ttl)));
+        self.watch_operators();
+        self
+    }
+
+    /// Persist the operator set, which gives the party indices of the operators, and reuse it
+    /// for the jobs while it is unchanged, instead of reading it from the chain for every job.
+    ///
+    /// The persisted set is dropped as soon as the operators change, watched in the
+    /// background, and checked against the chain first, as it may be from before a restart.
+    /// Must be called from within a tokio runtime, after [`FrostContext::with_coordinator`] if
+    /// any.
+    pub fn with_persisted_peer_mapping(mut self) -> Self {
+        self.peer_mapping = true;
+        let context = self.clone();
+        tokio::spawn(async move {
+            match peer_mapping::load(&context.store) {
+                Ok(Some(persisted)) => match context.fetch_operators().await {
+                    Ok(operators) if operators == persisted => {}
+                    Ok(_) => {
+                        sdk::info!("Operator set changed since it was persisted, dropping it");
+                        context.invalidate_operators().await;
+                    }
+                    Err(e) => {
+                        sdk::warn!(error = %e, "Failed to check the persisted operator set");
+                    }
+                },
+                Ok(None) => {}
+                Err(e) => sdk::warn!(error = %e, "Failed to read the persisted operator set"),
+            }
+        });
+        self.watch_operators();
+        self
+    }
+
+    /// Watch the operator set in the background, dropping the cached and persisted sets as
+    /// soon as it changes.
+    ///
+    /// The context and its clones share a single watcher: it is restarted with the current
+    /// configuration, so that it drops the sets enabled since it was started.
+    fn watch_operators(&self) {
+        let context = self.clone();
+        let watcher = tokio::spawn(async move {
+            loop {
+                match context.coordinator.operators_changed().await {
+                    Ok(()) => sdk::info!("Operator set changed, dropping the known one"),
+                    Err(e) => {
+                        sdk::warn!(error = %e, "Failed to watch the operator set");
+                        tokio::time::sleep(OPERATOR_WATCH_RETRY).await;
+                    }
+                }
+                context.invalidate_operators().await;
+            }
+        });
+        if let Some(previous) = self.operator_watcher.lock().replace(watcher) {
+            previous.abort();
+        }
+    }
+
+    /// Set what [`FrostContext::check_operators`] does when the service has no operators.
+    ///
+    /// Defaults to [`operators::EmptyOperatorSet::Warn`].
+    pub fn with_empty_operator_set(mut self, policy: operators::EmptyOperatorSet) -> Self {
+        self.empty_operators = policy;
+        self
+    }
+
+    /// Check the service has operators before starting, warning, failing or waiting for them
+    /// as set with [`FrostContext::with_empty_operator_set`].
+    ///
+    /// Fails with [`operators::DuplicateInstance`] if another operator is registered with the
+    /// ECDSA key of this node.
+    pub async fn check_operators(&self) -> eyre::Result<()> {
+        let operators =
+            operators::check_operator_set(|| self.current_operators(), self.empty_operators)
+                .await?;
+        let me = self.config.first_ecdsa_signer()?.signer().public();
+        operators::own_index(&operators, &me)?;
+        sdk::info!(operators = operators.len(), "Service operators");
+        Ok(())
+    }
+
+    /// Wait until at least `min_peers` other peers are connected, e.g. the threshold or all the
+    /// other operators, so that the node does not accept jobs it cannot fulfill.
+    ///
+    /// Fails with [`operators::NotReady`] if they are not connected within `timeout`. Needs the
+    /// [`FrostContext::with_connected_peers`], which the gossip network of
+    /// [`FrostContext::new`] sets.
+    pub async fn wait_until_ready(&self, min_peers: usize, timeout: Duration) -> eyre::Result<()> {
+        let peers = self
+            .connected_peers
+            .as_deref()
+            .ok_or_else(|| eyre::eyre!("The connected peers of this node are unknown"))?;
+        let me = self.config.first_ecdsa_signer()?.signer().public();
+        let connected = operators::wait_for_peers(peers, &me, min_peers, timeout).await?;
+        sdk::info!(%connected, "Ready");
+        Ok(())
+    }
+
+    /// Subscribe to the ECDSA keys of the operators allowed on the network, updated when the
+    /// operator set changes if enabled with [`FrostContext::with_operator_refresh`].
+    pub fn allowed_keys(&self) -> tokio::sync::watch::Receiver<BTreeSet<ecdsa::Public>> {
+        self.allowed_keys.clone()
+    }
+
+    /// The number of keygen and signing sessions running on this node.
+    pub fn active_sessions(&self) -> usize {
+        self.sessions.len()
+    }
+
+    /// The hex encoded public keys whose keygen entry could not be persisted and is only held
+    /// in memory.
+    pub fn unpersisted_keys(&self) -> Vec<String> {
+        self.unpersisted.lock().keys().cloned().collect()
+    }
+
+    /// Try again to persist the keygen entries that could not be written to the store, listing
+    /// each recovered key by [`retention`].
+    ///
+    /// Returns the number of entries that are still not persisted.
+    pub fn recover_unpersisted(&self) -> Result<usize, std::io::Error> {
+        let mut unpersisted = self.unpersisted.lock();
+        while let Some((pubkey, entry)) = unpersisted.pop_first() {
+            if let Err(e) = self.store.set(pubkey.clone(), entry.clone()) {
+                unpersisted.insert(pubkey, entry);
+                return Err(e);
+            }
+            sdk::info!(
+                pubkey = %self.log_redaction.redact(&pubkey),
+                "Recovered unpersisted keygen entry"
+            );
+            keygen::admit_key(
+                &self.store,
+                self.key_limit.as_ref(),
+                self.log_redaction,
+                &pubkey,
+            );
+        }
+        Ok(unpersisted.len())
+    }
+
+    /// Post every produced signature to the webhook at `url`.
+    ///
+    /// Every selected signer aggregates the signature, but only the one with the party index 0
+    /// in the signing posts it, so each signature is delivered once. A signing whose first
+    /// signer fails after aggregating is not delivered.
+    #[cfg(feature = "webhook")]
+    pub fn with_webhook(mut self, url: reqwest::Url) -> Self {
+        self.webhook = Some(webhook::Webhook::new(url));
+        self
+    }
+
+    /// Pause this node: keygen and signing jobs are declined until [`FrostContext::resume`].
+    ///
+    /// The operator also goes offline on-chain, which is how the other operators learn about
+    /// it and leave it out of the signer selection, without unregistering from the service.
+    /// The node only declines once it is offline on-chain, so that if going offline fails it is
+    /// not selected for jobs it would decline.
+    pub async fn pause(&self) -> eyre::Result<()> {
+        self.set_online(false).await?;
+        self.participation.pause();
+        Ok(())
+    }
+
+    /// Take part in the protocols again after [`FrostContext::pause`].
+    ///
+    /// As with pausing, the node only changes once the chain did, so it stays paused if going
+    /// online fails.
+    pub async fn resume(&self) -> eyre::Result<()> {
+        self.set_online(true).await?;
+        self.participation.resume();
+        Ok(())
+    }
+
+    /// Whether this node is paused.
+    pub fn is_paused(&self) -> bool {
+        self.participation.is_paused()
+    }
+
+    async fn set_online(&self, online: bool) -> eyre::Result<()> {
+        self.coordinator.set_online(online).await
+    }
+
+    /// Get the ECDSA keys of the `operators` that were paused when the job call `call_id` was
+    /// made.
+    pub(crate) async fn paused_operators(
+        &self,
+        call_id: u64,
+        operators: &BTreeMap<AccountId32, ecdsa::Public>,
+    ) -> eyre::Result<BTreeSet<ecdsa::Public>> {
+        self.coordinator.paused_operators(call_id, operators).await
+    }
+
+    /// The id of the job call being run.
+    pub(crate) async fn call_id(&self) -> eyre::Result<u64> {
+        self.coordinator.current_call_id().await
+    }
+
+    /// Check that the job call being run was made by the owner of the service or one of its
+    /// operators, the only accounts allowed to change its configuration.
+    pub(crate) async fn authorize_caller(&self) -> Result<(), operators::Unauthorized> {
+        let lookup = async {
+            let call_id = self.coordinator.current_call_id().await?;
+            let caller = self.coordinator.caller(call_id).await?;
+            let permitted = caller == self.coordinator.service_owner().await?
+                || self.coordinator.operators().await?.contains_key(&caller);
+            eyre::Ok((caller, permitted))
+        };
+        match lookup.await.map_err(operators::Unauthorized::Lookup)? {
+            (_, true) => Ok(()),
+            (caller, false) => Err(operators::Unauthorized::NotPermitted {
+                caller: hex::encode(caller.0),
+            }),
+        }
+    }
+
+    /// The number of the block the job call `call_id` was made in.
+    pub(crate) async fn call_block(&self, call_id: u64) -> eyre::Result<u64> {
+        self.coordinator.call_block(call_id).await
+    }
+
+    /// The timestamp of the block the job call `call_id` was made in, in seconds since the Unix
+    /// epoch.
+    pub(crate) async fn call_time(&self, call_id: u64) -> eyre::Result<u64> {
+        self.coordinator.call_time(call_id).await
+    }
+
+    /// Resolve once the operators of the service change.
+    pub(crate) async fn operators_changed(&self) -> eyre::Result<()> {
+        self.coordinator.operators_changed().await
+    }
+
+    /// Get the ECDSA keys of the service operators that are eligible to participate in the
+    /// protocols, after applying the configured policies, from the persisted operator set or
+    /// the operator cache if enabled.
+    pub(crate) async fn current_operators(
+        &self,
+    ) -> eyre::Result<BTreeMap<AccountId32, ecdsa::Public>> {
+        if self.peer_mapping {
+            match peer_mapping::load(&self.store) {
+                Ok(Some(operators)) => return Ok(operators),
+                Ok(None) => {}
+                Err(e) => sdk::warn!(error = %e, "Failed to read the persisted operator set"),
+            }
+        }
+        let operators = match &self.operator_cache {
+            Some(cache) => {
+                cache
+                    .get_or_fetch(self.clock.as_ref(), || self.fetch_operators())
+                    .await?
+            }
+            None => self.fetch_operators().await?,
+        };
+        if self.peer_mapping {
+            if let Err(e) = peer_mapping::save(&self.store, &operators) {
+                sdk::warn!(error = %e, "Failed to persist the operator set");
+            }
+        }
+        Ok(operators)
+    }
+
+    /// Drop the cached and persisted operator sets, if any.
+    pub(crate) async fn invalidate_operators(&self) {
+        if let Some(cache) = &self.operator_cache {
+            cache.invalidate().await;
+        }
+        if self.peer_mapping {
+            if let Err(e) = peer_mapping::invalidate(&self.store) {
+                sdk::warn!(error = %e, "Failed to drop the persisted operator set");
+            }
+        }
+    }
+
+    async fn fetch_operators(&self) -> eyre::Result<BTreeMap<AccountId32, ecdsa::Public>> {
+        let operators = self.coordinator.operators().await?;
+        let Some(min_restake) = &self.min_restake else {
+            return Ok(operators);
+        };
+        let restakes = self.coordinator.restakes().await?;
+        Ok(operators::filter_by_restake(
+            operators,
+            &restakes,
+            min_restake,
+        ))
+    }
+}
+
+/// Open the key-value store of the service, in `storage_path` if set or else in the `data_dir`
+/// of the blueprint.
+fn open_store(
+    config: &sdk::config::StdGadgetConfiguration,
+    storage_path: Option<&std::path::Path>,
+) -> eyre::Result<kv::SharedDynKVStore<String, Vec<u8>>> {
+    #[cfg(not(feature = "kv-sled"))]
+    let store: kv::SharedDynKVStore<String, Vec<u8>> = {
+        let _ = (config, storage_path);
+        Arc::new(kv::MemKVStore::new())
+    };
+    #[cfg(feature = "kv-sled")]
+    let store: kv::SharedDynKVStore<String, Vec<u8>> =
+        match storage_path.or(config.data_dir.as_deref()) {
+            Some(path) => Arc::new(kv::SledKVStore::from_path(path)?),
+            None => Arc::new(kv::SledKVStore::in_memory()?),
+        };
+    Ok(store)
+}
+
+#[cfg(all(test, feature = "kv-sled"))]
+mod tests {
+    use super::*;
+    use crate::testing::TempDir;
+
+    #[test]
+    fn storage_path_overrides_the_data_dir() {
+        let dir = TempDir::new("storage-path");
+        let (data_dir, storage_path) = (dir.0.join("data"), dir.0.join("frost"));
+        let mut config = sdk::config::StdGadgetConfiguration::default();
+        config.data_dir = Some(data_dir.clone());
+
+        let store = open_store(&config, Some(&storage_path)).unwrap();
+        store.set("key".to_string(), vec![1]).unwrap();
+        drop(store);
+        assert!(storage_path.exists());
+        assert!(!data_dir.exists());
+        let store = open_store(&config, Some(&storage_path)).unwrap();
+        assert_eq!(store.get(&"key".to_string()).unwrap(), Some(vec![1]));
+        drop(store);
+
+        // Without the override, the store stays in the data dir.
+        let store = open_store(&config, None).unwrap();
+        assert!(data_dir.exists());
+        assert_eq!(store.get(&"key".to_string()).unwrap(), None);
+    }
 }
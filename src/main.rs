@@ -32,6 +32,10 @@ async fn main() -> Result<()> {
         }
     };
 
+    if tangle.service_id.is_some() {
+        context.check_operators().await?;
+    }
+
     let client = env.client().await?;
     let signer = env.first_sr25519_signer()?;
 
@@ -43,6 +47,167 @@ async fn main() -> Result<()> {
     };
 
     let sign = blueprint::sign::SignEventHandler {
+        service_id,
+        client: client.clone(),
+        signer: signer.clone(),
+        context: context.clone(),
+    };
+
+    let sign_derived = blueprint::sign::SignDerivedEventHandler {
+        service_id,
+        client: client.clone(),
+        signer: signer.clone(),
+        context: context.clone(),
+    };
+
+    let sign_typed_data = blueprint::sign::SignTypedDataEventHandler {
+        service_id,
+        client: client.clone(),
+        signer: signer.clone(),
+        context: context.clone(),
+    };
+
+    let sign_ephemeral = blueprint::sign::SignEphemeralEventHandler {
+        service_id,
+        client: client.clone(),
+        signer: signer.clone(),
+        context: context.clone(),
+    };
+
+    let batch_sign_shared_setup = blueprint::sign::BatchSignSharedSetupEventHandler {
+        service_id,
+        client: client.clone(),
+        signer: signer.clone(),
+        context: context.clone(),
+    };
+
+    let keygen_transcript = blueprint::transcript::KeygenTranscriptEventHandler {
+        service_id,
+        client: client.clone(),
+        signer: signer.clone(),
+        context: context.clone(),
+    };
+
+    let get_signature = blueprint::signatures::GetSignatureEventHandler {
+        service_id,
+        client: client.clone(),
+        signer: signer.clone(),
+        context: context.clone(),
+    };
+
+    let set_label = blueprint::labels::SetLabelEventHandler {
+        service_id,
+        client: client.clone(),
+        signer: signer.clone(),
+        context: context.clone(),
+    };
+
+    let keygen_committee = blueprint::keygen::KeygenCommitteeEventHandler {
+        service_id,
+        client: client.clone(),
+        signer: signer.clone(),
+        context: context.clone(),
+    };
+
+    let keygen_beacon = blueprint::keygen::KeygenBeaconEventHandler {
+        service_id,
+        client: client.clone(),
+        signer: signer.clone(),
+        context: context.clone(),
+    };
+
+    let key_usage_stats = blueprint::usage::KeyUsageStatsEventHandler {
+        service_id,
+        client: client.clone(),
+        signer: signer.clone(),
+        context: context.clone(),
+    };
+
+    let export_package = blueprint::export::ExportPackageEventHandler {
+        service_id,
+        client: client.clone(),
+        signer: signer.clone(),
+        context: context.clone(),
+    };
+
+    let query_audit_log = blueprint::audit::QueryAuditLogEventHandler {
+        service_id,
+        client: client.clone(),
+        signer: signer.clone(),
+        context: context.clone(),
+    };
+
+    let get_diagnostics = blueprint::diagnostics::GetDiagnosticsEventHandler {
+        service_id,
+        client: client.clone(),
+        signer: signer.clone(),
+        context: context.clone(),
+    };
+
+    let batch_verify = blueprint::verify::BatchVerifyEventHandler {
+        service_id,
+        client: client.clone(),
+        signer: signer.clone(),
+        context: context.clone(),
+    };
+
+    let sign_with_validity = blueprint::sign::SignWithValidityEventHandler {
+        service_id,
+        client: client.clone(),
+        signer: signer.clone(),
+        context: context.clone(),
+    };
+
+    let dead_letters = blueprint::dead_letter::DeadLettersEventHandler {
+        service_id,
+        client: client.clone(),
+        signer: signer.clone(),
+        context: context.clone(),
+    };
+
+    let set_signing_windows = blueprint::windows::SetSigningWindowsEventHandler {
+        service_id,
+        client: client.clone(),
+        signer: signer.clone(),
+        context: context.clone(),
+    };
+
+    let set_key_usage_limit = blueprint::usage::SetKeyUsageLimitEventHandler {
+        service_id,
+        client: client.clone(),
+        signer: signer.clone(),
+        context: context.clone(),
+    };
+
+    let self_test = blueprint::self_test::SelfTestEventHandler {
+        service_id,
+        client: client.clone(),
+        signer: signer.clone(),
+        context: context.clone(),
+    };
+
+    let get_signed_transcript = blueprint::transcript::GetSignedTranscriptEventHandler {
+        service_id,
+        client: client.clone(),
+        signer: signer.clone(),
+        context: context.clone(),
+    };
+
+    let compact = blueprint::compaction::CompactEventHandler {
+        service_id,
+        client: client.clone(),
+        signer: signer.clone(),
+        context: context.clone(),
+    };
+
+    let set_share_refresh = blueprint::refresh::SetShareRefreshEventHandler {
+        service_id,
+        client: client.clone(),
+        signer: signer.clone(),
+        context: context.clone(),
+    };
+
+    let propose_config_change = blueprint::governance::ProposeConfigChangeEventHandler {
         service_id,
         client,
         signer,
@@ -53,6 +218,29 @@ async fn main() -> Result<()> {
     BlueprintRunner::new(config, env)
         .job(keygen)
         .job(sign)
+        .job(sign_derived)
+        .job(export_package)
+        .job(sign_typed_data)
+        .job(sign_ephemeral)
+        .job(query_audit_log)
+        .job(get_diagnostics)
+        .job(batch_sign_shared_setup)
+        .job(keygen_transcript)
+        .job(get_signature)
+        .job(set_label)
+        .job(keygen_committee)
+        .job(key_usage_stats)
+        .job(keygen_beacon)
+        .job(batch_verify)
+        .job(sign_with_validity)
+        .job(dead_letters)
+        .job(set_signing_windows)
+        .job(set_key_usage_limit)
+        .job(self_test)
+        .job(get_signed_transcript)
+        .job(compact)
+        .job(set_share_refresh)
+        .job(propose_config_change)
         .run()
         .in_current_span()
         .await?;
@@ -0,0 +1,183 @@
+//! Multibase and multicodec encoding of the job outputs, for the libp2p and IPFS tooling.
+//!
+//! With [`OutputEncoding::Multibase`], every part of a keygen or signing result is written as a
+//! base58btc [multibase](https://github.com/multiformats/multibase) string of its bytes behind
+//! their varint [multicodec](https://github.com/multiformats/multicodec) prefix, the parts being
+//! separated by a space in the order of the raw output.
+use frost_core::Ciphersuite;
+use serde::{Deserialize, Serialize};
+
+use crate::redjubjub::JubjubBlake2b512;
+
+/// The `ed25519-pub` multicodec.
+pub const ED25519_PUB: u64 = 0xed;
+/// The `secp256k1-pub` multicodec, of a compressed key.
+pub const SECP256K1_PUB: u64 = 0xe7;
+/// The `p256-pub` multicodec, of a compressed key.
+pub const P256_PUB: u64 = 0x1200;
+/// The `eddsa` varsig multicodec, of an Ed25519 signature.
+pub const EDDSA_SIG: u64 = 0xd0ed;
+/// The `es256k` varsig multicodec, of an ECDSA secp256k1 signature.
+pub const ES256K_SIG: u64 = 0xd0e7;
+/// No multicodec is registered for the FROST secp256k1 Schnorr signatures, they take one of the
+/// private use range.
+pub const FROST_SECP256K1_SIG: u64 = 0x30_0001;
+/// The big-endian `u64` number of a block, in the private use range.
+pub const BLOCK_NUMBER: u64 = 0x30_0002;
+/// No multicodec is registered for the RedJubjub keys and signatures either.
+pub const REDJUBJUB_PUB: u64 = 0x30_0003;
+/// See [`REDJUBJUB_PUB`].
+pub const REDJUBJUB_SIG: u64 = 0x30_0004;
+/// No multicodec is registered for the Ristretto255 keys and signatures either.
+pub const RISTRETTO255_PUB: u64 = 0x30_0005;
+/// See [`RISTRETTO255_PUB`].
+pub const RISTRETTO255_SIG: u64 = 0x30_0006;
+/// The aggregate nonce `R` of a signature, a group element of its ciphersuite, in the private
+/// use range.
+pub const AGGREGATE_NONCE: u64 = 0x30_0007;
+/// The one-time public key of an ephemeral signing, in the private use range, so it is not
+/// mistaken for the key it is derived from.
+pub const EPHEMERAL_PUB: u64 = 0x30_0008;
+/// No multicodec is registered for the FROST P-256 Schnorr signatures, they take one of the
+/// private use range too.
+pub const FROST_P256_SIG: u64 = 0x30_0009;
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("Unknown ciphersuite: {0}")]
+    UnknownCiphersuite(String),
+    #[error(transparent)]
+    Multibase(#[from] multibase::Error),
+    #[error("Malformed multicodec prefix")]
+    MalformedCodec,
+}
+
+/// How the keygen and signing jobs encode their results.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OutputEncoding {
+    /// The raw bytes.
+    #[default]
+    Raw,
+    /// Base58btc multibase strings with multicodec prefixes.
+    Multibase,
+}
+
+/// What a part of a job output is.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Part {
+    /// A public key of the ciphersuite, or any other of its group elements.
+    PublicKey,
+    /// A signature of the ciphersuite.
+    Signature,
+    /// The ECDSA signature of an operator.
+    OperatorSignature,
+    /// A block number.
+    BlockNumber,
+    /// The aggregate nonce of a signature.
+    AggregateNonce,
+    /// The ephemeral public key a signature is made with.
+    EphemeralKey,
+}
+
+/// The multicodec of a `part` under the ciphersuite `ciphersuite`, by `ID`.
+pub fn codec(ciphersuite: &str, part: Part) -> Result<u64, Error> {
+    match (ciphersuite, part) {
+        (_, Part::OperatorSignature) => Ok(ES256K_SIG),
+        (_, Part::BlockNumber) => Ok(BLOCK_NUMBER),
+        (_, Part::AggregateNonce) => Ok(AGGREGATE_NONCE),
+        (_, Part::EphemeralKey) => Ok(EPHEMERAL_PUB),
+        (frost_ed25519::Ed25519Sha512::ID, Part::PublicKey) => Ok(ED25519_PUB),
+        (frost_ed25519::Ed25519Sha512::ID, Part::Signature) => Ok(EDDSA_SIG),
+        (frost_secp256k1::Secp256K1Sha256::ID, Part::PublicKey) => Ok(SECP256K1_PUB),
+        (frost_secp256k1::Secp256K1Sha256::ID, Part::Signature) => Ok(FROST_SECP256K1_SIG),
+        (frost_ristretto255::Ristretto255Sha512::ID, Part::PublicKey) => Ok(RISTRETTO255_PUB),
+        (frost_ristretto255::Ristretto255Sha512::ID, Part::Signature) => Ok(RISTRETTO255_SIG),
+        (frost_p256::P256Sha256::ID, Part::PublicKey) => Ok(P256_PUB),
+        (frost_p256::P256Sha256::ID, Part::Signature) => Ok(FROST_P256_SIG),
+        (JubjubBlake2b512::ID, Part::PublicKey) => Ok(REDJUBJUB_PUB),
+        (JubjubBlake2b512::ID, Part::Signature) => Ok(REDJUBJUB_SIG),
+        _ => Err(Error::UnknownCiphersuite(ciphersuite.to_string())),
+    }
+}
+
+/// Encode `bytes` behind the multicodec `codec` as a base58btc multibase string.
+pub fn encode(codec: u64, bytes: &[u8]) -> String {
+    let mut prefixed = Vec::with_capacity(bytes.len() + 3);
+    let mut codec = codec;
+    loop {
+        let byte = (codec & 0x7f) as u8;
+        codec >>= 7;
+        if codec == 0 {
+            prefixed.push(byte);
+            break;
+        }
+        prefixed.push(byte | 0x80);
+    }
+    prefixed.extend_from_slice(bytes);
+    multibase::encode(multibase::Base::Base58Btc, prefixed)
+}
+
+/// Decode a multibase string written by [`encode`], in any base, into its multicodec and bytes.
+pub fn decode(encoded: &str) -> Result<(u64, Vec<u8>), Error> {
+    let (_, bytes) = multibase::decode(encoded)?;
+    let mut codec = 0u64;
+    for (i, byte) in bytes.iter().enumerate().take(9) {
+        codec |= u64::from(byte & 0x7f) << (7 * i);
+        if byte & 0x80 == 0 {
+            return Ok((codec, bytes[i + 1..].to_vec()));
+        }
+    }
+    Err(Error::MalformedCodec)
+}
+
+/// The job output of the `parts` in `encoding`, under the ciphersuite `ciphersuite`.
+///
+/// Raw parts are concatenated, multibase ones joined with a space.
+pub fn output(
+    encoding: OutputEncoding,
+    ciphersuite: &str,
+    parts: &[(Part, &[u8])],
+) -> Result<Vec<u8>, Error> {
+    match encoding {
+        OutputEncoding::Raw => Ok(parts
+            .iter()
+            .flat_map(|(_, bytes)| *bytes)
+            .copied()
+            .collect()),
+        OutputEncoding::Multibase => Ok(parts
+            .iter()
+            .map(|(part, bytes)| Ok(encode(codec(ciphersuite, *part)?, bytes)))
+            .collect::<Result<Vec<_>, Error>>()?
+            .join(" ")
+            .into_bytes()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use frost_core::{Signature, SigningKey};
+    use gadget_sdk::random::rand::rngs::OsRng;
+
+    #[test]
+    fn multibase_signature_decodes_to_the_raw_bytes() {
+        type C = frost_secp256k1::Secp256K1Sha256;
+        let key = SigningKey::<C>::new(&mut OsRng);
+        let signature = key.sign(OsRng, b"multibase").serialize().unwrap();
+        let output = output(
+            OutputEncoding::Multibase,
+            C::ID,
+            &[(Part::Signature, &signature)],
+        )
+        .unwrap();
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.starts_with('z'), "{output}");
+        let (codec, bytes) = decode(&output).unwrap();
+        assert_eq!(codec, FROST_SECP256K1_SIG);
+        assert_eq!(bytes, signature);
+        let signature = Signature::<C>::deserialize(&bytes).unwrap();
+        let verifying_key = frost_core::VerifyingKey::from(&key);
+        verifying_key.verify(b"multibase", &signature).unwrap();
+    }
+}
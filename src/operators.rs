@@ -0,0 +1,632 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::future::Future;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use gadget_sdk::libp2p::PeerId;
+use gadget_sdk::network::gossip::GossipHandle;
+use gadget_sdk::random::rand::seq::index;
+use gadget_sdk::random::SeedableRng;
+use gadget_sdk::subxt_core::ext::sp_core::ecdsa;
+use gadget_sdk::subxt_core::utils::AccountId32;
+use gadget_sdk::tangle_subxt::tangle_testnet_runtime::api::runtime_types::sp_arithmetic::per_things::Percent;
+use tokio::sync::watch;
+
+/// Keep only the operators whose restake exposure is at least `min_restake`.
+///
+/// Operators that are missing from `restakes` are treated as having no exposure and are
+/// dropped. The result only depends on on-chain data, so every node derives the same set
+/// (and therefore the same identifiers) from it.
+pub fn filter_by_restake(
+    operators: BTreeMap<AccountId32, ecdsa::Public>,
+    restakes: &[(AccountId32, Percent)],
+    min_restake: &Percent,
+) -> BTreeMap<AccountId32, ecdsa::Public> {
+    operators
+        .into_iter()
+        .filter(|(account, _)| {
+            restakes
+                .iter()
+                .find(|(other, _)| other == account)
+                .is_some_and(|(_, restake)| restake.0 >= min_restake.0)
+        })
+        .collect()
+}
+
+/// The same ECDSA key is registered by several operators.
+#[derive(Debug, thiserror::Error)]
+#[error(
+    "The ECDSA key {key} is registered by {count} operators, only one instance can run per key"
+)]
+pub struct DuplicateInstance {
+    /// The hex encoded ECDSA key.
+    pub key: String,
+    /// The number of operators registered with it.
+    pub count: usize,
+}
+
+/// The local ECDSA key of the node is not the one its network was started with.
+///
+/// The peers check the messages of a node against its network identity, so a node taking part
+/// in the protocols under another key would see its messages silently dropped.
+#[derive(Debug, thiserror::Error)]
+#[error("The local ECDSA key {local} is not the network identity {network}")]
+pub struct IdentityMismatch {
+    /// The hex encoded ECDSA key of the network.
+    pub network: String,
+    /// The hex encoded local ECDSA key.
+    pub local: String,
+}
+
+/// A job changing the configuration of the service was not called by its owner or one of its
+/// operators.
+#[derive(Debug, thiserror::Error)]
+pub enum Unauthorized {
+    #[error("The account {caller} is neither the owner nor an operator of the service")]
+    NotPermitted {
+        /// The hex encoded account of the caller.
+        caller: String,
+    },
+    #[error("Failed to check the caller of the job: {0}")]
+    Lookup(color_eyre::eyre::Error),
+}
+
+/// The party index of each of the `operators`, its position in the account-sorted map.
+///
+/// This is the only place the indices are assigned: the keygen and the signings index the
+/// operators with it, so an operator signs with the index, and the FROST identifier, it got at
+/// keygen, whatever order the operators were fetched in.
+pub fn party_indices(
+    operators: &BTreeMap<AccountId32, ecdsa::Public>,
+) -> impl Iterator<Item = (u16, &AccountId32, &ecdsa::Public)> {
+    // There are fewer operators than `u16::MAX` in a service.
+    operators
+        .iter()
+        .enumerate()
+        .map(|(i, (account, key))| (i as u16, account, key))
+}
+
+/// The position of `me` in `operators`, the index it takes in the protocols, if it is one of
+/// them, see [`party_indices`].
+///
+/// An instance finds itself at the first operator with its key, so two instances sharing a key
+/// would both claim the same index and corrupt the protocols. That is an error instead.
+pub fn own_index(
+    operators: &BTreeMap<AccountId32, ecdsa::Public>,
+    me: &ecdsa::Public,
+) -> Result<Option<usize>, DuplicateInstance> {
+    let mut positions = party_indices(operators)
+        .filter(|(_, _, key)| *key == me)
+        .map(|(i, _, _)| usize::from(i));
+    let first = positions.next();
+    let others = positions.count();
+    if others > 0 {
+        return Err(DuplicateInstance {
+            key: hex::encode(me),
+            count: others + 1,
+        });
+    }
+    Ok(first)
+}
+
+/// Select `t` signers out of `operators` using a RNG seeded by `seed`.
+///
+/// Operators are indexed by their position in the account-sorted map, the same index they
+/// got at keygen. The sampled positions are sorted before use, so the result only depends
+/// on the seed and on the set of operators (never on the order they were fetched in), and
+/// every honest node that derives the same seed selects the same signers.
+pub fn select_signers(
+    operators: &BTreeMap<AccountId32, ecdsa::Public>,
+    seed: [u8; 32],
+    t: u16,
+) -> BTreeMap<u16, ecdsa::Public> {
+    select_signers_excluding(operators, &BTreeSet::new(), seed, t)
+}
+
+/// Like [`select_signers`], but never selects the `excluded` operators.
+///
+/// The excluded operators keep their position, so the selected ones keep their keygen index.
+pub fn select_signers_excluding(
+    operators: &BTreeMap<AccountId32, ecdsa::Public>,
+    excluded: &BTreeSet<ecdsa::Public>,
+    seed: [u8; 32],
+    t: u16,
+) -> BTreeMap<u16, ecdsa::Public> {
+    let candidates = party_indices(operators)
+        .filter(|(_, _, key)| !excluded.contains(key))
+        .map(|(i, _, key)| (i, *key))
+        .collect::<Vec<_>>();
+    let amount = usize::from(t).min(candidates.len());
+    let mut rng = rand_chacha::ChaChaRng::from_seed(seed);
+    let mut picked = index::sample(&mut rng, candidates.len(), amount).into_vec();
+    picked.sort_unstable();
+    picked.into_iter().map(|j| candidates[j]).collect()
+}
+
+/// What a signing does when fewer operators than the threshold are reachable.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OfflineSigners {
+    /// Start the signing anyway, it stalls in round 1 until the missing signers come back or
+    /// the job times out.
+    #[default]
+    Wait,
+    /// Fail before selecting the signers, when the reachable operators, this node included,
+    /// are fewer than the threshold.
+    FailFast,
+}
+
+/// The peers this node is connected to.
+#[async_trait::async_trait]
+pub trait ConnectedPeers: Send + Sync {
+    /// The ECDSA keys of the connected peers.
+    async fn connected(&self) -> BTreeSet<ecdsa::Public>;
+}
+
+/// The peers of the gossip network that completed the handshake with this node.
+#[derive(Clone)]
+pub struct GossipPeers(Arc<tokio::sync::RwLock<BTreeMap<ecdsa::Public, PeerId>>>);
+
+impl From<&GossipHandle> for GossipPeers {
+    fn from(handle: &GossipHandle) -> Self {
+        Self(handle.ecdsa_peer_id_to_libp2p_id.clone())
+    }
+}
+
+#[async_trait::async_trait]
+impl ConnectedPeers for GossipPeers {
+    async fn connected(&self) -> BTreeSet<ecdsa::Public> {
+        self.0.read().await.keys().copied().collect()
+    }
+}
+
+/// How often [`wait_for_peers`] checks the connected peers.
+const READINESS_POLL: Duration = Duration::from_millis(100);
+
+/// Fewer peers than required connected in time, see [`wait_for_peers`].
+#[derive(Debug, thiserror::Error)]
+#[error("Only {connected} of the {required} required peers connected within {timeout:?}")]
+pub struct NotReady {
+    /// The peers connected when the time ran out.
+    pub connected: usize,
+    /// The peers required.
+    pub required: usize,
+    /// How long the peers were waited for.
+    pub timeout: Duration,
+}
+
+/// Wait until at least `min_peers` peers other than `me` are connected, for at most `timeout`.
+///
+/// Returns the number of connected peers.
+pub async fn wait_for_peers(
+    peers: &dyn ConnectedPeers,
+    me: &ecdsa::Public,
+    min_peers: usize,
+    timeout: Duration,
+) -> Result<usize, NotReady> {
+    let count = || async { peers.connected().await.iter().filter(|k| *k != me).count() };
+    let wait = async {
+        loop {
+            let connected = count().await;
+            if connected >= min_peers {
+                return connected;
+            }
+            tracing::debug!(%connected, required = %min_peers, "Waiting for peers");
+            tokio::time::sleep(READINESS_POLL).await;
+        }
+    };
+    match tokio::time::timeout(timeout, wait).await {
+        Ok(connected) => Ok(connected),
+        Err(_) => Err(NotReady {
+            connected: count().await,
+            required: min_peers,
+            timeout,
+        }),
+    }
+}
+
+/// The node is paused and does not take part in the protocols.
+#[derive(Debug, thiserror::Error)]
+#[error("This node is paused and not participating")]
+pub struct NotParticipating;
+
+/// Whether this node takes part in the protocols, shared by all the clones of the context.
+#[derive(Clone, Debug, Default)]
+pub struct Participation {
+    paused: Arc<AtomicBool>,
+}
+
+impl Participation {
+    /// Stop taking part in the protocols.
+    pub fn pause(&self) {
+        self.paused.store(true, Ordering::SeqCst);
+    }
+
+    /// Take part in the protocols again.
+    pub fn resume(&self) {
+        self.paused.store(false, Ordering::SeqCst);
+    }
+
+    /// Whether the node is paused.
+    pub fn is_paused(&self) -> bool {
+        self.paused.load(Ordering::SeqCst)
+    }
+
+    /// Decline with [`NotParticipating`] if the node is paused.
+    pub fn ensure_participating(&self) -> Result<(), NotParticipating> {
+        if self.is_paused() {
+            Err(NotParticipating)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// Keep `allowed` up to date with the operator set returned by `fetch`, polled every
+/// `interval`, so that the operators joining or leaving the service are admitted or denied
+/// without a restart.
+///
+/// If `fetch` fails, e.g. while the chain is unreachable, the last known set is kept.
+/// Returns once every receiver of `allowed` is dropped.
+pub async fn refresh_allowed_keys<F, Fut, E>(
+    mut fetch: F,
+    interval: Duration,
+    allowed: watch::Sender<BTreeSet<ecdsa::Public>>,
+) where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<BTreeMap<AccountId32, ecdsa::Public>, E>>,
+    E: std::fmt::Display,
+{
+    let mut ticker = tokio::time::interval(interval);
+    ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    while !allowed.is_closed() {
+        ticker.tick().await;
+        match fetch().await {
+            Ok(operators) => {
+                let keys = operators.into_values().collect::<BTreeSet<_>>();
+                allowed.send_if_modified(|current| {
+                    let changed = *current != keys;
+                    if changed {
+                        tracing::info!(operators = keys.len(), "Operator set changed");
+                        *current = keys;
+                    }
+                    changed
+                });
+            }
+            Err(e) => {
+                tracing::warn!(error = %e, "Failed to refresh the operator set, keeping the last known one");
+            }
+        }
+    }
+}
+
+/// The operator set shared by the jobs for a short time, instead of each job reading it again
+/// from the chain, see [`FrostContext::with_operator_cache`](crate::FrostContext::with_operator_cache).
+#[derive(Debug)]
+pub struct OperatorCache {
+    ttl: Duration,
+    cached: tokio::sync::Mutex<Option<(SystemTime, BTreeMap<AccountId32, ecdsa::Public>)>>,
+}
+
+impl OperatorCache {
+    /// A cache keeping the operator set for `ttl` after it is read.
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            cached: tokio::sync::Mutex::new(None),
+        }
+    }
+
+    /// The cached operator set if it is younger than the TTL on `clock`, or else the one
+    /// returned by `fetch`, cached if it succeeds.
+    ///
+    /// The jobs asking at the same time as a fetch wait for it rather than fetching again.
+    pub async fn get_or_fetch<F, Fut, E>(
+        &self,
+        clock: &dyn crate::clock::Clock,
+        fetch: F,
+    ) -> Result<BTreeMap<AccountId32, ecdsa::Public>, E>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<BTreeMap<AccountId32, ecdsa::Public>, E>>,
+    {
+        let mut cached = self.cached.lock().await;
+        if let Some((fetched_at, operators)) = &*cached {
+            if clock.since(*fetched_at) < self.ttl {
+                return Ok(operators.clone());
+            }
+        }
+        let operators = fetch().await?;
+        *cached = Some((clock.now(), operators.clone()));
+        Ok(operators)
+    }
+
+    /// Drop the cached operator set, e.g. because it changed, so the next job reads it again.
+    pub async fn invalidate(&self) {
+        *self.cached.lock().await = None;
+    }
+}
+
+/// What to do when the service has no operators at startup, e.g. before any operator
+/// registered.
+///
+/// With no operators, every peer is rejected from the network and no job can run.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum EmptyOperatorSet {
+    /// Log a warning and start anyway.
+    #[default]
+    Warn,
+    /// Fail the startup with [`NoOperators`].
+    Error,
+    /// Wait for operators, re-checking the operator set every interval.
+    Wait(Duration),
+}
+
+/// The service has no operators.
+#[derive(Debug, thiserror::Error)]
+#[error("The service has no operators")]
+pub struct NoOperators;
+
+/// Fetch the operator set at startup, handling an empty one according to `policy`.
+pub async fn check_operator_set<F, Fut, E>(
+    mut fetch: F,
+    policy: EmptyOperatorSet,
+) -> Result<BTreeMap<AccountId32, ecdsa::Public>, E>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<BTreeMap<AccountId32, ecdsa::Public>, E>>,
+    E: From<NoOperators>,
+{
+    let mut operators = fetch().await?;
+    if !operators.is_empty() {
+        return Ok(operators);
+    }
+    match policy {
+        EmptyOperatorSet::Warn => {
+            tracing::warn!("The service has no operators, every peer will be rejected");
+        }
+        EmptyOperatorSet::Error => return Err(NoOperators.into()),
+        EmptyOperatorSet::Wait(interval) => {
+            while operators.is_empty() {
+                tracing::info!(?interval, "Waiting for operators");
+                tokio::time::sleep(interval).await;
+                operators = fetch().await?;
+            }
+            tracing::info!(operators = operators.len(), "Operators registered");
+        }
+    }
+    Ok(operators)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use gadget_sdk::random::rand::rngs::StdRng;
+    use gadget_sdk::random::rand::seq::SliceRandom;
+
+    fn operator(i: u8) -> (AccountId32, ecdsa::Public) {
+        let mut key = [0u8; 33];
+        key[0] = 0x02;
+        key[1] = i;
+        (AccountId32([i; 32]), ecdsa::Public::from_raw(key))
+    }
+
+    #[test]
+    fn below_threshold_operator_is_excluded() {
+        let operators = (1..=4).map(operator).collect::<BTreeMap<_, _>>();
+        let restakes = vec![
+            (AccountId32([1; 32]), Percent(50)),
+            (AccountId32([2; 32]), Percent(5)),
+            (AccountId32([3; 32]), Percent(10)),
+            (AccountId32([4; 32]), Percent(20)),
+        ];
+
+        let filtered = filter_by_restake(operators, &restakes, &Percent(10));
+        let ids = filtered.keys().cloned().collect::<Vec<_>>();
+        assert_eq!(
+            ids,
+            vec![
+                AccountId32([1; 32]),
+                AccountId32([3; 32]),
+                AccountId32([4; 32])
+            ]
+        );
+        // Identifiers are assigned by position, so the remaining operators are packed.
+        let (_, me) = operator(3);
+        let i = filtered.values().position(|k| k == &me);
+        assert_eq!(i, Some(1));
+    }
+
+    #[test]
+    fn operator_without_restake_entry_is_excluded() {
+        let operators = (1..=2).map(operator).collect::<BTreeMap<_, _>>();
+        let restakes = vec![(AccountId32([1; 32]), Percent(50))];
+        let filtered = filter_by_restake(operators, &restakes, &Percent(0));
+        assert_eq!(filtered.len(), 1);
+        assert!(filtered.contains_key(&AccountId32([1; 32])));
+    }
+
+    #[test]
+    fn signer_selection_ignores_input_order() {
+        let operators = (1..=10).map(operator).collect::<Vec<_>>();
+        let seed = [7u8; 32];
+        let expected = select_signers(&operators.iter().cloned().collect(), seed, 4);
+        assert_eq!(expected.len(), 4);
+        for i in 0..20 {
+            let mut shuffled = operators.clone();
+            shuffled.shuffle(&mut StdRng::seed_from_u64(i));
+            let selected = select_signers(&shuffled.into_iter().collect(), seed, 4);
+            assert_eq!(selected, expected);
+        }
+    }
+
+    #[test]
+    fn signer_selection_is_stable() {
+        // Pinned so that a change in the sampling algorithm (e.g. a `rand` upgrade) is caught,
+        // since nodes running different versions would otherwise select different signers.
+        let operators = (1..=10).map(operator).collect();
+        let selected = select_signers(&operators, [7u8; 32], 4);
+        let ids = selected.keys().copied().collect::<Vec<_>>();
+        assert_eq!(ids, vec![2, 3, 4, 6]);
+    }
+
+    #[test]
+    fn paused_operator_is_never_selected() {
+        let operators = (1..=5).map(operator).collect::<BTreeMap<_, _>>();
+        let (_, paused) = operator(2);
+        let excluded = BTreeSet::from([paused]);
+        for seed in 0..50u8 {
+            let selected = select_signers_excluding(&operators, &excluded, [seed; 32], 4);
+            assert_eq!(selected.len(), 4);
+            assert!(!selected.values().any(|k| k == &paused));
+            // The others keep their keygen index.
+            for (i, key) in selected {
+                let keygen_index = operators.values().position(|k| k == &key).unwrap();
+                assert_eq!(usize::from(i), keygen_index);
+            }
+        }
+    }
+
+    #[test]
+    fn paused_node_declines() {
+        let participation = Participation::default();
+        assert!(participation.ensure_participating().is_ok());
+        // Clones of the context share the same state.
+        let other = participation.clone();
+        other.pause();
+        assert!(participation.ensure_participating().is_err());
+        participation.resume();
+        assert!(other.ensure_participating().is_ok());
+    }
+
+    #[test]
+    fn signer_selection_indices_match_keygen() {
+        let operators = (1..=5).map(operator).collect::<BTreeMap<_, _>>();
+        let selected = select_signers(&operators, [1u8; 32], 3);
+        for (i, key) in selected {
+            let keygen_index = operators.values().position(|k| k == &key).unwrap();
+            assert_eq!(usize::from(i), keygen_index);
+        }
+    }
+
+    #[test]
+    fn keygen_and_signing_indices_agree() {
+        type C = frost_secp256k1::Secp256K1Sha256;
+        // The accounts are sorted in the reverse order of the keys, and fetched in any order.
+        let operators = (1..=7u8)
+            .map(|i| (AccountId32([8 - i; 32]), operator(i).1))
+            .collect::<Vec<_>>();
+        for seed in 0..10 {
+            let mut shuffled = operators.clone();
+            shuffled.shuffle(&mut StdRng::seed_from_u64(seed));
+            let shuffled = shuffled.into_iter().collect::<BTreeMap<_, _>>();
+            let identifiers = crate::keygen::identifiers(&shuffled).unwrap();
+            let selected = select_signers(&shuffled, [seed as u8; 32], 7);
+            for (account, key) in &shuffled {
+                let keygen_index = own_index(&shuffled, key).unwrap().unwrap();
+                let signing_index = selected.iter().find(|(_, k)| *k == key).unwrap().0;
+                assert_eq!(usize::from(*signing_index), keygen_index);
+                assert_eq!(
+                    crate::rounds::PartyIndex(*signing_index).to_identifier::<C>(),
+                    frost_core::Identifier::try_from(identifiers[account]),
+                );
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn operator_set_changes_propagate() {
+        const INTERVAL: Duration = Duration::from_millis(20);
+        let chain = Arc::new(std::sync::Mutex::new(Err("unreachable")));
+        let (tx, mut allowed) = watch::channel(BTreeSet::new());
+        let fetch = {
+            let chain = chain.clone();
+            move || std::future::ready(chain.lock().unwrap().clone())
+        };
+        tokio::spawn(refresh_allowed_keys(fetch, INTERVAL, tx));
+        let within_interval = |allowed: &mut watch::Receiver<_>| {
+            let mut allowed = allowed.clone();
+            async move {
+                tokio::time::timeout(INTERVAL * 3, allowed.changed())
+                    .await
+                    .expect("operator set not propagated")
+                    .unwrap();
+            }
+        };
+
+        *chain.lock().unwrap() = Ok(BTreeMap::from([operator(1), operator(2)]));
+        within_interval(&mut allowed).await;
+        let keys = |ids: &[u8]| ids.iter().map(|i| operator(*i).1).collect::<BTreeSet<_>>();
+        assert_eq!(*allowed.borrow_and_update(), keys(&[1, 2]));
+
+        // The chain becomes unreachable: the last known set is kept.
+        *chain.lock().unwrap() = Err("unreachable");
+        tokio::time::sleep(INTERVAL * 3).await;
+        assert!(!allowed.has_changed().unwrap());
+        assert_eq!(*allowed.borrow(), keys(&[1, 2]));
+
+        // Operator 2 leaves and operator 3 joins.
+        *chain.lock().unwrap() = Ok(BTreeMap::from([operator(1), operator(3)]));
+        within_interval(&mut allowed).await;
+        assert_eq!(*allowed.borrow_and_update(), keys(&[1, 3]));
+    }
+
+    #[tokio::test]
+    async fn empty_operator_set_follows_the_policy() {
+        let empty = || std::future::ready(Ok::<_, color_eyre::eyre::Report>(BTreeMap::new()));
+        let operators = check_operator_set(empty, EmptyOperatorSet::Warn).await;
+        assert!(operators.unwrap().is_empty());
+        let err = check_operator_set(empty, EmptyOperatorSet::Error)
+            .await
+            .unwrap_err();
+        assert!(err.is::<NoOperators>());
+
+        // The operators register after a few checks.
+        let mut checks = 0;
+        let registering = || {
+            checks += 1;
+            let operators = match checks {
+                ..=3 => BTreeMap::new(),
+                _ => BTreeMap::from([operator(1)]),
+            };
+            std::future::ready(Ok::<_, color_eyre::eyre::Report>(operators))
+        };
+        let waiting = check_operator_set(
+            registering,
+            EmptyOperatorSet::Wait(Duration::from_millis(10)),
+        );
+        let operators = tokio::time::timeout(Duration::from_secs(5), waiting)
+            .await
+            .expect("still waiting for operators")
+            .unwrap();
+        assert_eq!(operators, BTreeMap::from([operator(1)]));
+        assert_eq!(checks, 4);
+    }
+
+    #[tokio::test]
+    async fn readiness_resolves_once_enough_peers_connect() {
+        let network = crate::testing::MockNetwork::default();
+        let (_, me) = operator(0);
+        let _me = network.connect(me);
+        let err = wait_for_peers(&network, &me, 2, Duration::from_millis(200))
+            .await
+            .unwrap_err();
+        assert_eq!((err.connected, err.required), (0, 2));
+
+        let joining = network.clone();
+        let handles = tokio::spawn(async move {
+            let mut handles = vec![];
+            for i in 1..=2 {
+                tokio::time::sleep(Duration::from_millis(150)).await;
+                handles.push(joining.connect(operator(i).1));
+            }
+            handles
+        });
+        let connected = wait_for_peers(&network, &me, 2, Duration::from_secs(5))
+            .await
+            .unwrap();
+        assert_eq!(connected, 2);
+        drop(handles.await.unwrap());
+    }
+}
@@ -0,0 +1,84 @@
+//! Persisted mapping of the party indices to the operators.
+//!
+//! The party index of an operator in the protocols is its position in the operator set, read
+//! from the chain by every job. With [`FrostContext::with_persisted_peer_mapping`], the operator
+//! set is kept in the key-value store, under the hash of the set, and reused by the jobs while
+//! it is unchanged: the sessions start without reading the chain, and still run while the chain
+//! is briefly unreachable.
+//!
+//! The persisted mapping is dropped as soon as the operators change, and checked against the
+//! chain when the node starts, as the operators may have changed while it was down.
+//!
+//! [`FrostContext::with_persisted_peer_mapping`]: crate::FrostContext::with_persisted_peer_mapping
+use std::collections::BTreeMap;
+
+use gadget_sdk::subxt_core::ext::sp_core::{ecdsa, keccak_256};
+use gadget_sdk::subxt_core::utils::AccountId32;
+
+use crate::kv::SharedDynKVStore;
+
+/// The key of the hash of the persisted operator set.
+const CURRENT_KEY: &str = "peers/current";
+
+fn store_key(hash: &[u8; 32]) -> String {
+    format!("peers/{}", hex::encode(hash))
+}
+
+/// The hash of the operator set, its accounts and keys in the order of their party index.
+pub(crate) fn operator_set_hash(operators: &BTreeMap<AccountId32, ecdsa::Public>) -> [u8; 32] {
+    let bytes = operators
+        .iter()
+        .flat_map(|(account, key)| [&account.0[..], &key.0[..]].concat())
+        .collect::<Vec<_>>();
+    keccak_256(&bytes)
+}
+
+/// Read the persisted operator set, `None` if there is none or if it does not match its hash.
+pub(crate) fn load(
+    store: &SharedDynKVStore<String, Vec<u8>>,
+) -> std::io::Result<Option<BTreeMap<AccountId32, ecdsa::Public>>> {
+    let Some(hash) = store.get(&CURRENT_KEY.to_string())? else {
+        return Ok(None);
+    };
+    let Ok(hash) = <[u8; 32]>::try_from(hash.as_slice()) else {
+        return Ok(None);
+    };
+    let Some(bytes) = store.get(&store_key(&hash))? else {
+        return Ok(None);
+    };
+    // The position of an operator in the persisted list is its party index.
+    let operators: Vec<(AccountId32, ecdsa::Public)> = serde_json::from_slice(&bytes)?;
+    let operators = operators.into_iter().collect();
+    Ok((operator_set_hash(&operators) == hash).then_some(operators))
+}
+
+/// Persist `operators` in place of the previous operator set, if any.
+pub(crate) fn save(
+    store: &SharedDynKVStore<String, Vec<u8>>,
+    operators: &BTreeMap<AccountId32, ecdsa::Public>,
+) -> std::io::Result<()> {
+    let hash = operator_set_hash(operators);
+    let previous = store.get(&CURRENT_KEY.to_string())?;
+    if previous.as_deref() == Some(&hash[..]) {
+        return Ok(());
+    }
+    let operators = operators.iter().collect::<Vec<_>>();
+    store.set(store_key(&hash), serde_json::to_vec(&operators)?)?;
+    store.set(CURRENT_KEY.to_string(), hash.to_vec())?;
+    if let Some(Ok(previous)) = previous.map(<[u8; 32]>::try_from) {
+        store.del(&store_key(&previous))?;
+    }
+    Ok(())
+}
+
+/// Drop the persisted operator set, if any.
+pub(crate) fn invalidate(store: &SharedDynKVStore<String, Vec<u8>>) -> std::io::Result<()> {
+    let Some(hash) = store.get(&CURRENT_KEY.to_string())? else {
+        return Ok(());
+    };
+    store.del(&CURRENT_KEY.to_string())?;
+    if let Ok(hash) = <[u8; 32]>::try_from(hash) {
+        store.del(&store_key(&hash))?;
+    }
+    Ok(())
+}
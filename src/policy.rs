@@ -0,0 +1,129 @@
+//! Policies on the messages this node signs.
+//!
+//! Before running a signing protocol, the signing jobs ask the [`MessagePolicy`] of the
+//! [`FrostContext`](crate::FrostContext) whether the message may be signed, [`AllowAll`] unless
+//! replaced with [`FrostContext::with_message_policy`](crate::FrostContext::with_message_policy).
+//! A rejected message fails the job with `MessageRejected` on this node, which then does not
+//! take part in the signing. All the operators should use the same policy, otherwise the others
+//! wait for this node until the job times out.
+//!
+//! Before the policy, the [`MessageValidator`] checks that the message is well-formed for the
+//! chain it is signed for, e.g. that it decodes as a Bitcoin sighash or an EVM transaction,
+//! failing the job with `InvalidMessageSchema` otherwise. Every message is accepted, with
+//! [`AnyMessage`], unless replaced with
+//! [`FrostContext::with_message_validator`](crate::FrostContext::with_message_validator).
+use crate::FrostContext;
+
+/// Whether a message may be signed.
+pub trait MessagePolicy: std::fmt::Debug + Send + Sync {
+    /// Check that `msg`, as given to the job, may be signed with the key `pubkey`, returning the
+    /// reason of the rejection otherwise.
+    fn check(&self, pubkey: &[u8], msg: &[u8]) -> Result<(), String>;
+}
+
+/// Sign every message.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct AllowAll;
+
+impl MessagePolicy for AllowAll {
+    fn check(&self, _pubkey: &[u8], _msg: &[u8]) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+/// Refuse the messages containing any of a list of byte patterns, e.g. the selector of a
+/// dangerous contract call.
+#[derive(Clone, Debug, Default)]
+pub struct Blocklist {
+    patterns: Vec<Vec<u8>>,
+}
+
+impl Blocklist {
+    /// Refuse the messages containing any of `patterns`.
+    pub fn new(patterns: impl IntoIterator<Item = impl Into<Vec<u8>>>) -> Self {
+        Self {
+            patterns: patterns
+                .into_iter()
+                .map(Into::into)
+                .filter(|pattern: &Vec<u8>| !pattern.is_empty())
+                .collect(),
+        }
+    }
+}
+
+impl MessagePolicy for Blocklist {
+    fn check(&self, _pubkey: &[u8], msg: &[u8]) -> Result<(), String> {
+        match self
+            .patterns
+            .iter()
+            .find(|pattern| msg.windows(pattern.len()).any(|w| w == pattern.as_slice()))
+        {
+            Some(pattern) => Err(format!(
+                "it contains the blocked pattern {}",
+                hex::encode(pattern)
+            )),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Whether a message is well-formed, see [`MessageValidator::validate`].
+pub trait MessageValidator: std::fmt::Debug + Send + Sync {
+    /// Check that `msg`, as given to the job, follows the format of the messages this node
+    /// signs, returning what is wrong with it otherwise.
+    fn validate(&self, msg: &[u8]) -> Result<(), String>;
+}
+
+/// Accept every message.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct AnyMessage;
+
+impl MessageValidator for AnyMessage {
+    fn validate(&self, _msg: &[u8]) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+/// Accept the messages of exactly `len` bytes, e.g. the 32 bytes of a sighash or of a
+/// transaction hash.
+#[derive(Clone, Copy, Debug)]
+pub struct FixedLength(pub usize);
+
+impl MessageValidator for FixedLength {
+    fn validate(&self, msg: &[u8]) -> Result<(), String> {
+        match msg.len() == self.0 {
+            true => Ok(()),
+            false => Err(format!("{} bytes instead of {}", msg.len(), self.0)),
+        }
+    }
+}
+
+impl FrostContext {
+    /// Check with the message validator that `msg` is well-formed, then with the message policy
+    /// and the blocklist agreed by the operators, see [`governance`](crate::governance), that it
+    /// may be signed with the key `pubkey`.
+    pub(crate) fn check_message(
+        &self,
+        pubkey: &[u8],
+        msg: &[u8],
+    ) -> Result<(), crate::sign::Error> {
+        self.message_validator.validate(msg).map_err(|reason| {
+            tracing::warn!(%reason, "Refusing to sign a malformed message");
+            crate::sign::Error::InvalidMessageSchema { reason }
+        })?;
+        let agreed = self.agreed_config()?;
+        let blocklist = Blocklist::new(
+            agreed
+                .blocklist
+                .iter()
+                .filter_map(|pattern| hex::decode(pattern).ok()),
+        );
+        self.message_policy
+            .check(pubkey, msg)
+            .and_then(|()| blocklist.check(pubkey, msg))
+            .map_err(|reason| {
+                tracing::warn!(%reason, "Refusing to sign a message");
+                crate::sign::Error::MessageRejected { reason }
+            })
+    }
+}
@@ -0,0 +1,174 @@
+//! Priorities of the protocol messages.
+//!
+//! The messages of the later rounds, e.g. the signature shares of a signing, are the most
+//! latency-sensitive, as every party waits for them to complete the protocol. With
+//! [`FrostContext::with_message_priority`], every outgoing message is given a [`Priority`] by
+//! its round, which is handed to the [`PrioritizedNetwork`] of the context right before the
+//! message is sent, so a network supporting priorities can send the critical messages ahead of
+//! the bulk traffic.
+//!
+//! The networks of the SDK have no priorities, so the messages are not prioritized by default.
+use std::pin::Pin;
+use std::sync::Arc;
+
+use gadget_sdk::futures::stream::BoxStream;
+use gadget_sdk::futures::{future, Sink, SinkExt, StreamExt};
+use round_based::{Delivery, Incoming, MessageDestination, Outgoing, ProtocolMessage};
+use serde::{Deserialize, Serialize};
+
+use crate::codec::Envelope;
+use crate::FrostContext;
+
+/// How urgently a message should be sent.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Priority {
+    /// Sent along with the other traffic.
+    #[default]
+    Normal,
+    /// Sent ahead of the other traffic.
+    High,
+}
+
+/// Which messages are prioritized.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MessagePriority {
+    /// Every message has the [`Priority::Normal`] priority.
+    #[default]
+    Unprioritized,
+    /// The messages of the first round have the [`Priority::Normal`] priority, and the ones of
+    /// the later rounds, which the parties wait for to complete, the [`Priority::High`] one.
+    LaterRoundsFirst,
+}
+
+impl MessagePriority {
+    /// The priority of a message of `round`.
+    pub fn priority(&self, round: u16) -> Priority {
+        match self {
+            MessagePriority::LaterRoundsFirst if round > 0 => Priority::High,
+            _ => Priority::Normal,
+        }
+    }
+}
+
+/// A network sending its messages by priority.
+pub trait PrioritizedNetwork: std::fmt::Debug + Send + Sync {
+    /// Send the next message of the protocol session `session` to the party `recipient`, or
+    /// every party if `None`, with `priority`.
+    fn prioritize(&self, session: [u8; 32], round: u16, recipient: Option<u16>, priority: Priority);
+}
+
+/// A delivery handing the priority of its messages to a network, see [`tag`].
+pub(crate) type PrioritizedDelivery<D> = (
+    BoxStream<'static, Result<Incoming<Envelope>, <D as Delivery<Envelope>>::ReceiveError>>,
+    Pin<
+        Box<
+            dyn Sink<Outgoing<Envelope>, Error = <D as Delivery<Envelope>>::SendError>
+                + Send
+                + 'static,
+        >,
+    >,
+);
+
+/// Hand the priority of every message sent on `delivery`, given by `policy`, to `network`.
+pub(crate) fn tag<D>(
+    network: Option<Arc<dyn PrioritizedNetwork>>,
+    policy: MessagePriority,
+    session: [u8; 32],
+    delivery: D,
+) -> PrioritizedDelivery<D>
+where
+    D: Delivery<Envelope>,
+    D::Send: Send + 'static,
+    D::Receive: Send + 'static,
+{
+    let (incoming, outgoing) = delivery.split();
+    let network = match network {
+        Some(network) if policy != MessagePriority::Unprioritized => network,
+        _ => return (incoming.boxed(), Box::pin(outgoing)),
+    };
+    let outgoing = outgoing.with(move |outgoing: Outgoing<Envelope>| {
+        let round = outgoing.msg.round();
+        let recipient = match outgoing.recipient {
+            MessageDestination::AllParties => None,
+            MessageDestination::OneParty(j) => Some(j),
+        };
+        network.prioritize(session, round, recipient, policy.priority(round));
+        future::ready(Ok(outgoing))
+    });
+    (incoming.boxed(), Box::pin(outgoing))
+}
+
+impl FrostContext {
+    /// Hand the priority of every message sent on `delivery` in the protocol session
+    /// `session` to the prioritized network, if enabled.
+    pub(crate) fn prioritize<D>(&self, session: [u8; 32], delivery: D) -> PrioritizedDelivery<D>
+    where
+        D: Delivery<Envelope>,
+        D::Send: Send + 'static,
+        D::Receive: Send + 'static,
+    {
+        tag(
+            self.prioritized_network.clone(),
+            self.message_priority,
+            session,
+            delivery,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use gadget_sdk::futures::{sink, stream};
+    use gadget_sdk::parking_lot::Mutex;
+
+    /// A network recording the priorities of the messages.
+    #[derive(Debug, Default)]
+    struct RecordingNetwork(Mutex<Vec<(u16, Option<u16>, Priority)>>);
+
+    impl PrioritizedNetwork for RecordingNetwork {
+        fn prioritize(
+            &self,
+            _session: [u8; 32],
+            round: u16,
+            recipient: Option<u16>,
+            priority: Priority,
+        ) {
+            self.0.lock().push((round, recipient, priority));
+        }
+    }
+
+    async fn send_rounds(network: &Arc<RecordingNetwork>, policy: MessagePriority) {
+        let incoming = stream::pending::<Result<Incoming<Envelope>, std::io::Error>>();
+        let outgoing = sink::drain().sink_map_err(|e| -> std::io::Error { match e {} });
+        let network = network.clone() as Arc<dyn PrioritizedNetwork>;
+        let (_, mut outgoing) = tag(Some(network), policy, [0; 32], (incoming, outgoing));
+        // A signing broadcasts its commitments, then its signature share.
+        outgoing
+            .send(Outgoing::broadcast(Envelope::new(1, 0, vec![1])))
+            .await
+            .unwrap();
+        outgoing
+            .send(Outgoing::p2p(2, Envelope::new(1, 1, vec![2])))
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn later_rounds_are_prioritized() {
+        let network = Arc::new(RecordingNetwork::default());
+        send_rounds(&network, MessagePriority::LaterRoundsFirst).await;
+        let tagged = network.0.lock().clone();
+        assert_eq!(
+            tagged,
+            [(0, None, Priority::Normal), (1, Some(2), Priority::High)]
+        );
+        assert!(tagged[1].2 > tagged[0].2);
+
+        // Unprioritized, the network is not told anything.
+        let network = Arc::new(RecordingNetwork::default());
+        send_rounds(&network, MessagePriority::Unprioritized).await;
+        assert!(network.0.lock().is_empty());
+    }
+}
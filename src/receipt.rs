@@ -0,0 +1,310 @@
+//! Signed receipts of the participation in a keygen or signing.
+//!
+//! With [`FrostContext::with_participation_receipts`], every operator that completes a keygen or
+//! signing signs a [`ParticipationReceipt`], "I took part in the session X, among these
+//! operators, for the key Y", with its operator key, and returns it along with the job result in
+//! a [`ReceiptOutput`]. Collecting the receipts of a session, a coordinator can prove which
+//! operators contributed to it, off-chain with [`SignedReceipt::verify`], or on-chain from the
+//! [`ParticipationReceipt::signing_hash`] and the recoverable ECDSA signature.
+//!
+//! The receipts are also kept in the store under the job call id.
+use gadget_sdk as sdk;
+use sdk::subxt_core::ext::sp_core::{ecdsa, keccak_256, Pair};
+
+use crate::kv::SharedDynKVStore;
+use crate::FrostContext;
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error(transparent)]
+    Config(#[from] sdk::config::Error),
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+}
+
+/// The participation of an operator in a keygen or signing session.
+#[derive(Clone, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct ParticipationReceipt {
+    /// The job call id.
+    pub call_id: u64,
+    /// The hex encoded name of the network session, see
+    /// [`keygen_session_name`](crate::session::keygen_session_name) and
+    /// [`session_name`](crate::session::session_name).
+    pub session: String,
+    /// The hex encoded verifying key generated or signed with.
+    pub key: String,
+    /// The hex encoded ECDSA keys of the operators that took part in the session, in the order
+    /// of their party indices.
+    pub participants: Vec<String>,
+}
+
+impl ParticipationReceipt {
+    pub(crate) fn new<'a>(
+        call_id: u64,
+        session: [u8; 32],
+        key: &[u8],
+        participants: impl IntoIterator<Item = &'a ecdsa::Public>,
+    ) -> Self {
+        Self {
+            call_id,
+            session: hex::encode(session),
+            key: hex::encode(key),
+            participants: participants.into_iter().map(hex::encode).collect(),
+        }
+    }
+
+    /// The `keccak256` hash signed by the operators, that of
+    /// `abi.encodePacked(session, callId, keccak256(key), keccak256(abi.encodePacked(participants)))`
+    /// in Solidity, with the 32 bytes `session`, the `uint64` `callId` and the 33 bytes
+    /// compressed ECDSA keys of the `participants`.
+    pub fn signing_hash(&self) -> Option<[u8; 32]> {
+        let session = hex::decode(&self.session).ok()?;
+        let key = hex::decode(&self.key).ok()?;
+        let participants = self
+            .participants
+            .iter()
+            .map(hex::decode)
+            .collect::<Result<Vec<_>, _>>()
+            .ok()?;
+        if session.len() != 32 {
+            return None;
+        }
+        let mut packed = session;
+        packed.extend(self.call_id.to_be_bytes());
+        packed.extend(keccak_256(&key));
+        packed.extend(keccak_256(&participants.concat()));
+        Some(keccak_256(&packed))
+    }
+
+    /// Sign the receipt with the operator key `signer`.
+    pub fn sign(self, signer: &ecdsa::Pair) -> Option<SignedReceipt> {
+        let signature = signer.sign_prehashed(&self.signing_hash()?);
+        Some(SignedReceipt {
+            receipt: self,
+            operator: hex::encode(signer.public()),
+            signature: hex::encode(signature.0),
+        })
+    }
+}
+
+/// A [`ParticipationReceipt`] signed by an operator, the ECDSA signature of its
+/// [`ParticipationReceipt::signing_hash`].
+#[derive(Clone, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct SignedReceipt {
+    pub receipt: ParticipationReceipt,
+    /// The hex encoded ECDSA key of the operator.
+    pub operator: String,
+    /// The hex encoded recoverable ECDSA signature.
+    pub signature: String,
+}
+
+impl SignedReceipt {
+    /// Whether the receipt is signed by `operator`, one of its participants.
+    ///
+    /// The key claimed in the receipt is not trusted, the one of the operator must be read from
+    /// the chain.
+    pub fn verify(&self, operator: &ecdsa::Public) -> bool {
+        let Some(hash) = self.receipt.signing_hash() else {
+            return false;
+        };
+        let signature = hex::decode(&self.signature)
+            .ok()
+            .and_then(|bytes| ecdsa::Signature::from_slice(&bytes));
+        self.receipt.participants.contains(&hex::encode(operator))
+            && signature.is_some_and(|s| ecdsa::Pair::verify_prehashed(&s, &hash, operator))
+    }
+}
+
+/// A job result along with the participation receipt of the operator that produced it
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct ReceiptOutput {
+    /// The hex encoded job result
+    pub output: String,
+    /// The receipt, `None` if it could not be signed or saved
+    pub receipt: Option<SignedReceipt>,
+}
+
+fn store_key(call_id: u64) -> String {
+    format!("receipt/{call_id}")
+}
+
+/// Read the receipt of the job call `call_id` from the store, if any.
+pub(crate) fn read(
+    store: &SharedDynKVStore<String, Vec<u8>>,
+    call_id: u64,
+) -> Result<Option<SignedReceipt>, Error> {
+    match store.get(&store_key(call_id))? {
+        Some(bytes) => Ok(Some(serde_json::from_slice(&bytes)?)),
+        None => Ok(None),
+    }
+}
+
+impl FrostContext {
+    /// Sign and persist the `receipt` of this node, if enabled.
+    ///
+    /// Failing to do so does not change the outcome of the keygen or signing, it is only logged,
+    /// and the job result then comes without a receipt.
+    pub(crate) fn save_receipt(&self, receipt: ParticipationReceipt) {
+        if !self.participation_receipts {
+            return;
+        }
+        let call_id = receipt.call_id;
+        let result = self
+            .config
+            .first_ecdsa_signer()
+            .map_err(Error::from)
+            .and_then(|signer| {
+                // The receipt is built from well-formed keys, so it always has a signing hash.
+                let Some(signed) = receipt.sign(signer.signer()) else {
+                    return Ok(());
+                };
+                Ok(self
+                    .store
+                    .set(store_key(call_id), serde_json::to_vec(&signed)?)?)
+            });
+        if let Err(e) = result {
+            tracing::warn!(call_id, error = %e, "Failed to save the participation receipt");
+        }
+    }
+
+    /// The signed receipt of this node for the job call `call_id`, if any.
+    pub(crate) fn receipt(&self, call_id: u64) -> Option<SignedReceipt> {
+        read(&self.store, call_id)
+            .inspect_err(
+                |e| tracing::warn!(call_id, error = %e, "Failed to read the participation receipt"),
+            )
+            .ok()
+            .flatten()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeSet;
+    use std::time::Duration;
+
+    use frost_core::Ciphersuite;
+
+    use super::*;
+    use crate::testing::{
+        on_all, operator_contexts, MockCoordinator, MockNetwork, MockNetworkConfig, TempDir,
+    };
+
+    /// Run `job` on every context, returning the results of the operators that completed it,
+    /// along with their operator keys.
+    async fn run_all<F, Fut>(contexts: &[FrostContext], job: F) -> Vec<(ecdsa::Public, Vec<u8>)>
+    where
+        F: Fn(FrostContext) -> Fut,
+        Fut: std::future::Future<Output = Result<Vec<u8>, String>> + Send + 'static,
+    {
+        let operators = contexts.iter().map(|context| {
+            context
+                .config
+                .first_ecdsa_signer()
+                .unwrap()
+                .signer()
+                .public()
+        });
+        operators
+            .zip(on_all(contexts, job).await)
+            .filter_map(|(operator, result)| Some((operator, result.ok()?)))
+            .collect()
+    }
+
+    /// Check that the receipts of `outputs` are signed by their operators, for the same session
+    /// among exactly these operators, returning the inner job results.
+    fn check_receipts(outputs: &[(ecdsa::Public, Vec<u8>)], call_id: u64) -> Vec<Vec<u8>> {
+        let participants = outputs
+            .iter()
+            .map(|(operator, _)| hex::encode(operator))
+            .collect::<BTreeSet<_>>();
+        let mut sessions = BTreeSet::new();
+        let mut results = vec![];
+        for (operator, output) in outputs {
+            let output: ReceiptOutput = serde_json::from_slice(output).unwrap();
+            let signed = output.receipt.expect("no receipt");
+            assert!(signed.verify(operator));
+            assert_eq!(signed.receipt.call_id, call_id);
+            assert_eq!(
+                signed
+                    .receipt
+                    .participants
+                    .iter()
+                    .cloned()
+                    .collect::<BTreeSet<_>>(),
+                participants
+            );
+            sessions.insert(signed.receipt.session.clone());
+            results.push(hex::decode(output.output).unwrap());
+        }
+        assert_eq!(sessions.len(), 1);
+        results
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn receipts_verify_for_the_participants() {
+        type C = frost_secp256k1::Secp256K1Sha256;
+        let network = MockNetwork::new(MockNetworkConfig {
+            latency: Duration::from_millis(50),
+            loss: 0.0,
+        });
+        let dir = TempDir::new("participation-receipts");
+        let contexts = operator_contexts(&network, &dir, 3, 1000)
+            .into_iter()
+            .map(|context| context.with_participation_receipts(true))
+            .collect::<Vec<_>>();
+
+        // All the operators take part in the keygen.
+        let keygens = run_all(&contexts, |context| async move {
+            crate::keygen::keygen(C::ID.to_string(), 2, context)
+                .await
+                .map_err(|e| e.to_string())
+        })
+        .await;
+        assert_eq!(keygens.len(), 3);
+        let pubkey = check_receipts(&keygens, 1000).remove(0);
+
+        // Only the 2 selected signers take part in the signing.
+        let operators = contexts[0].current_operators().await.unwrap();
+        let signing = contexts
+            .iter()
+            .map(|context| {
+                context.clone().with_coordinator(MockCoordinator {
+                    operators: operators.clone(),
+                    call_id: 1001,
+                    change_after: None,
+                    caller: None,
+                })
+            })
+            .collect::<Vec<_>>();
+        let signatures = run_all(&signing, |context| {
+            let pubkey = pubkey.clone();
+            async move {
+                crate::sign::sign(pubkey, b"receipts".to_vec(), context)
+                    .await
+                    .map_err(|e| e.to_string())
+            }
+        })
+        .await;
+        assert_eq!(signatures.len(), 2);
+        check_receipts(&signatures, 1001);
+
+        // A receipt does not verify for another operator, nor once altered.
+        let (operator, output) = &signatures[0];
+        let signed = serde_json::from_slice::<ReceiptOutput>(output)
+            .unwrap()
+            .receipt
+            .unwrap();
+        let other = keygens
+            .iter()
+            .map(|(key, _)| key)
+            .find(|key| *key != operator)
+            .unwrap();
+        assert!(!signed.verify(other));
+        let mut forged = signed.clone();
+        forged.receipt.participants.pop();
+        assert!(!forged.verify(operator));
+    }
+}
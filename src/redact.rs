@@ -0,0 +1,64 @@
+use gadget_sdk::subxt_core::ext::sp_core::keccak_256;
+
+/// How many characters of a value are kept by [`Redaction::Truncate`].
+const TRUNCATED_LEN: usize = 8;
+/// How many bytes of the hash are kept by [`Redaction::Hash`].
+const HASH_LEN: usize = 8;
+
+/// How sensitive values (public keys, messages) are written to the logs.
+///
+/// Both redacting modes keep a short, stable representation of the value, so log lines about
+/// the same key or message can still be correlated across operators.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Redaction {
+    /// Log values in full.
+    #[default]
+    Off,
+    /// Log only the first few characters of values.
+    Truncate,
+    /// Log a short hash of values instead of the values themselves.
+    Hash,
+}
+
+impl Redaction {
+    /// Redact a (hex encoded) value for logging.
+    pub fn redact(&self, value: &str) -> String {
+        match self {
+            Redaction::Off => value.to_string(),
+            Redaction::Truncate => match value.get(..TRUNCATED_LEN) {
+                Some(prefix) if value.len() > TRUNCATED_LEN => format!("{prefix}…"),
+                _ => value.to_string(),
+            },
+            Redaction::Hash => format!(
+                "keccak:{}",
+                hex::encode(&keccak_256(value.as_bytes())[..HASH_LEN])
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PUBKEY: &str = "02a8f3b1c2d4e5f60718293a4b5c6d7e8f90a1b2c3d4e5f60718293a4b5c6d7e8f";
+
+    #[test]
+    fn off_keeps_values() {
+        assert_eq!(Redaction::Off.redact(PUBKEY), PUBKEY);
+    }
+
+    #[test]
+    fn truncate_keeps_a_prefix() {
+        assert_eq!(Redaction::Truncate.redact(PUBKEY), "02a8f3b1…");
+        assert_eq!(Redaction::Truncate.redact("02a8"), "02a8");
+    }
+
+    #[test]
+    fn hash_is_stable_and_hides_the_value() {
+        let redacted = Redaction::Hash.redact(PUBKEY);
+        assert_eq!(redacted, Redaction::Hash.redact(PUBKEY));
+        assert_ne!(redacted, Redaction::Hash.redact("00"));
+        assert!(!redacted.contains(&PUBKEY[..TRUNCATED_LEN]));
+    }
+}
@@ -0,0 +1,262 @@
+//! The FROST(Jubjub, BLAKE2b-512) ciphersuite, producing the RedJubjub signatures of the Zcash
+//! Sapling spend authorizations.
+//!
+//! It is the ciphersuite of the `reddsa` crate, defined here against the `frost-core` version of
+//! the other ciphersuites: the group is the prime order subgroup of Jubjub generated by the
+//! `SpendAuthSig` basepoint, and the challenge `H2` is the `H*` of RedDSA, BLAKE2b-512
+//! personalized with `Zcash_RedJubjubH` and reduced modulo the subgroup order. An aggregated
+//! signature `R || S` is thus a RedJubjub `spendAuthSig` under the group key taken as `rk`.
+#![allow(non_snake_case)]
+use frost_core::{Ciphersuite, Field, FieldError, Group, GroupError};
+use gadget_sdk::random::rand::{CryptoRng, RngCore};
+use group::ff::{Field as _, PrimeField};
+use group::GroupEncoding;
+
+/// The byte encoding of the Sapling `SpendAuthSig` basepoint.
+pub const SPENDAUTHSIG_BASEPOINT_BYTES: [u8; 32] = [
+    48, 181, 242, 170, 173, 50, 86, 48, 188, 221, 219, 206, 77, 103, 101, 109, 5, 253, 28, 194,
+    208, 55, 187, 83, 117, 182, 233, 109, 158, 1, 161, 215,
+];
+
+/// The scalar field of [`JubjubBlake2b512`].
+#[derive(Clone, Copy)]
+pub struct JubjubScalarField;
+
+impl Field for JubjubScalarField {
+    type Scalar = jubjub::Scalar;
+
+    type Serialization = [u8; 32];
+
+    fn zero() -> Self::Scalar {
+        jubjub::Scalar::ZERO
+    }
+
+    fn one() -> Self::Scalar {
+        jubjub::Scalar::ONE
+    }
+
+    fn invert(scalar: &Self::Scalar) -> Result<Self::Scalar, FieldError> {
+        Option::from(scalar.invert()).ok_or(FieldError::InvalidZeroScalar)
+    }
+
+    fn random<R: RngCore + CryptoRng>(rng: &mut R) -> Self::Scalar {
+        jubjub::Scalar::random(rng)
+    }
+
+    fn serialize(scalar: &Self::Scalar) -> Self::Serialization {
+        scalar.to_repr()
+    }
+
+    fn little_endian_serialize(scalar: &Self::Scalar) -> Self::Serialization {
+        Self::serialize(scalar)
+    }
+
+    fn deserialize(buf: &Self::Serialization) -> Result<Self::Scalar, FieldError> {
+        Option::from(jubjub::Scalar::from_repr(*buf)).ok_or(FieldError::MalformedScalar)
+    }
+}
+
+/// The group of [`JubjubBlake2b512`].
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct JubjubGroup;
+
+impl Group for JubjubGroup {
+    type Field = JubjubScalarField;
+
+    type Element = jubjub::ExtendedPoint;
+
+    type Serialization = [u8; 32];
+
+    fn cofactor() -> <Self::Field as Field>::Scalar {
+        // The deserialized elements are checked to be in the prime order subgroup.
+        Self::Field::one()
+    }
+
+    fn identity() -> Self::Element {
+        jubjub::ExtendedPoint::identity()
+    }
+
+    fn generator() -> Self::Element {
+        jubjub::ExtendedPoint::from_bytes(&SPENDAUTHSIG_BASEPOINT_BYTES).unwrap()
+    }
+
+    fn serialize(element: &Self::Element) -> Result<Self::Serialization, GroupError> {
+        if *element == Self::identity() {
+            return Err(GroupError::InvalidIdentityElement);
+        }
+        Ok(element.to_bytes())
+    }
+
+    fn deserialize(buf: &Self::Serialization) -> Result<Self::Element, GroupError> {
+        let point = Option::<jubjub::ExtendedPoint>::from(jubjub::ExtendedPoint::from_bytes(buf))
+            .ok_or(GroupError::MalformedElement)?;
+        if point == Self::identity() {
+            Err(GroupError::InvalidIdentityElement)
+        } else if bool::from(point.is_torsion_free()) {
+            Ok(point)
+        } else {
+            Err(GroupError::InvalidNonPrimeOrderElement)
+        }
+    }
+}
+
+/// The FROST(Jubjub, BLAKE2b-512) ciphersuite.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct JubjubBlake2b512;
+
+/// BLAKE2b-512 of `m` personalized with `personal`, reduced modulo the subgroup order.
+fn hash_to_scalar(personal: &[u8], m: &[u8]) -> jubjub::Scalar {
+    jubjub::Scalar::from_bytes_wide(&hash(personal, m))
+}
+
+/// BLAKE2b-512 of `m` personalized with `personal`.
+fn hash(personal: &[u8], m: &[u8]) -> [u8; 64] {
+    *blake2b_simd::Params::new()
+        .hash_length(64)
+        .personal(personal)
+        .hash(m)
+        .as_array()
+}
+
+impl Ciphersuite for JubjubBlake2b512 {
+    const ID: &'static str = "FROST(Jubjub, BLAKE2b-512)";
+
+    type Group = JubjubGroup;
+
+    type HashOutput = [u8; 64];
+
+    type SignatureSerialization = [u8; 64];
+
+    fn H1(m: &[u8]) -> <<Self::Group as Group>::Field as Field>::Scalar {
+        hash_to_scalar(b"FROST_RedJubjubR", m)
+    }
+
+    fn H2(m: &[u8]) -> <<Self::Group as Group>::Field as Field>::Scalar {
+        hash_to_scalar(b"Zcash_RedJubjubH", m)
+    }
+
+    fn H3(m: &[u8]) -> <<Self::Group as Group>::Field as Field>::Scalar {
+        hash_to_scalar(b"FROST_RedJubjubN", m)
+    }
+
+    fn H4(m: &[u8]) -> Self::HashOutput {
+        hash(b"FROST_RedJubjubM", m)
+    }
+
+    fn H5(m: &[u8]) -> Self::HashOutput {
+        hash(b"FROST_RedJubjubC", m)
+    }
+
+    fn HDKG(m: &[u8]) -> Option<<<Self::Group as Group>::Field as Field>::Scalar> {
+        Some(hash_to_scalar(b"FROST_RedJubjubD", m))
+    }
+
+    fn HID(m: &[u8]) -> Option<<<Self::Group as Group>::Field as Field>::Scalar> {
+        Some(hash_to_scalar(b"FROST_RedJubjubI", m))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use super::*;
+    use frost_core::keys::dkg;
+    use frost_core::Identifier;
+    use gadget_sdk::random::rand::rngs::StdRng;
+    use gadget_sdk::random::SeedableRng;
+    use test_strategy::proptest;
+
+    type C = JubjubBlake2b512;
+
+    #[test]
+    fn identifiers_round_trip() {
+        for i in [1u16, 2, 255, 256, u16::MAX] {
+            let id = Identifier::<C>::try_from(i).unwrap();
+            let bytes = id.serialize();
+            // The little-endian encoding of the index.
+            assert_eq!(bytes[..2], i.to_le_bytes());
+            assert!(bytes[2..].iter().all(|b| *b == 0));
+            assert_eq!(Identifier::<C>::deserialize(&bytes).unwrap(), id);
+        }
+        assert!(Identifier::<C>::try_from(0).is_err());
+        // Above the order of the Jubjub scalar field.
+        assert!(Identifier::<C>::deserialize(&[0xff; 32]).is_err());
+    }
+
+    #[proptest(cases = 10)]
+    fn dkg_signature_verifies_as_spend_auth_sig(
+        #[strategy(2..6u16)] n: u16,
+        #[strategy(2..=#n)] t: u16,
+        seed: u64,
+        msg: [u8; 32],
+    ) {
+        let rng = &mut StdRng::seed_from_u64(seed);
+        let ids = (1..=n)
+            .map(|i| Identifier::<C>::try_from(i).unwrap())
+            .collect::<Vec<_>>();
+        let mut round1_secrets = BTreeMap::new();
+        let mut round1_packages = BTreeMap::new();
+        for id in &ids {
+            let (secret, package) = dkg::part1::<C, _>(*id, n, t, &mut *rng).unwrap();
+            round1_secrets.insert(*id, secret);
+            round1_packages.insert(*id, package);
+        }
+        let received = |me: &Identifier<C>| {
+            round1_packages
+                .iter()
+                .filter(|(id, _)| *id != me)
+                .map(|(id, package)| (*id, package.clone()))
+                .collect::<BTreeMap<_, _>>()
+        };
+        let mut round2_secrets = BTreeMap::new();
+        let mut round2_packages = BTreeMap::new();
+        for (id, secret) in round1_secrets {
+            let (secret, packages) = dkg::part2(secret, &received(&id)).unwrap();
+            round2_secrets.insert(id, secret);
+            for (to, package) in packages {
+                round2_packages
+                    .entry(to)
+                    .or_insert_with(BTreeMap::new)
+                    .insert(id, package);
+            }
+        }
+        let mut key_pkgs = BTreeMap::new();
+        let mut pub_key_pkg = None;
+        for (id, secret) in &round2_secrets {
+            let (key_pkg, pkg) = dkg::part3(secret, &received(id), &round2_packages[id]).unwrap();
+            key_pkgs.insert(*id, key_pkg);
+            pub_key_pkg = Some(pkg);
+        }
+        let pub_key_pkg = pub_key_pkg.unwrap();
+
+        let signers = key_pkgs.iter().take(usize::from(t)).collect::<Vec<_>>();
+        let (mut nonces, mut commitments) = (BTreeMap::new(), BTreeMap::new());
+        for (id, key_pkg) in &signers {
+            let (nonce, commitment) = frost_core::round1::commit(key_pkg.signing_share(), rng);
+            nonces.insert(**id, nonce);
+            commitments.insert(**id, commitment);
+        }
+        let signing_pkg = frost_core::SigningPackage::new(commitments, &msg);
+        let shares = signers
+            .iter()
+            .map(|(id, key_pkg)| {
+                let share = frost_core::round2::sign(&signing_pkg, &nonces[*id], key_pkg).unwrap();
+                (**id, share)
+            })
+            .collect();
+        let signature = frost_core::aggregate(&signing_pkg, &shares, &pub_key_pkg).unwrap();
+
+        let rk: [u8; 32] = pub_key_pkg
+            .verifying_key()
+            .serialize()
+            .unwrap()
+            .try_into()
+            .unwrap();
+        let sig = crate::zcash::spend_auth_sig(&signature).unwrap();
+        let rk = reddsa::VerificationKey::<reddsa::sapling::SpendAuth>::try_from(rk).unwrap();
+        let sig = reddsa::Signature::<reddsa::sapling::SpendAuth>::from(sig);
+        rk.verify(&msg, &sig).unwrap();
+        assert!(rk.verify(b"another message", &sig).is_err());
+    }
+}
@@ -0,0 +1,554 @@
+//! Proactive refresh of the key shares.
+//!
+//! With [`FrostContext::with_share_refresh`], the operators refresh the shares of every stored
+//! key once per interval, see [`refresh_protocol::run`]: the shares an attacker steals from
+//! fewer than the threshold of operators before a refresh are of no use with the shares it
+//! steals after it, while the group verifying key, and so the signatures, are unchanged.
+//!
+//! The refreshes are scheduled by the [`Clock`](crate::clock::Clock) of the node, in epochs of
+//! the interval since the Unix epoch, so the operators refreshing a key all start the same
+//! session without a job call: they must enable the same interval and keep their clocks
+//! synchronized. A key is refreshed in the epochs following the one it was first seen in, among
+//! all the operators holding a share of it, which must all be online. A refresh that fails is
+//! retried in the next epoch. The refresh of a key can be turned off with [`set_share_refresh`].
+//!
+//! A refresh may fail for some operators only: one missing the last message, or timing out,
+//! keeps its share while the others store their refreshed ones, and fails the signings it is
+//! selected for until the next refresh. So the operators keep the shares of the generation
+//! before the last refresh next to the current ones, and the next refresh starts from the
+//! newest generation all of them hold, which realigns the ones left behind. The previous
+//! generation is dropped once a refresh completes from the current one, every operator having
+//! then held it.
+//!
+//! Only the keys listed by [`retention`](crate::retention) are refreshed, not the ones
+//! generated before it existed.
+use std::time::Duration;
+
+use api::services::events::JobCalled;
+use frost_core::Ciphersuite;
+use gadget_sdk::network::round_based_compat::NetworkDeliveryWrapper;
+use gadget_sdk::subxt_core::ext::sp_core::ecdsa;
+use gadget_sdk::{self as sdk, random};
+use sdk::event_listener::tangle::{
+    jobs::{services_post_processor, services_pre_processor},
+    TangleEventListener,
+};
+use sdk::tangle_subxt::tangle_testnet_runtime::api;
+use serde::{Deserialize, Serialize};
+
+use crate::rounds::refresh as refresh_protocol;
+use crate::FrostContext;
+
+/// How often the scheduler checks whether a new epoch started.
+const CHECK_INTERVAL: Duration = Duration::from_secs(1);
+/// How long the refresh of a key waits for the other holders before giving up.
+const REFRESH_TIMEOUT: Duration = Duration::from_secs(5 * 60);
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("The Secret Share for that key is not found")]
+    KeyNotFound,
+    #[error("Unknown ciphersuite: {0}")]
+    UnknownCiphersuite(String),
+    #[error("Self not in the holders of the key")]
+    SelfNotInHolders,
+    #[error("The holders of the key changed since the keygen")]
+    HoldersChanged,
+    #[error("The refresh did not complete within {0:?}")]
+    Timeout(Duration),
+    #[error(transparent)]
+    DuplicateInstance(#[from] crate::operators::DuplicateInstance),
+    #[error(transparent)]
+    IdentityMismatch(#[from] crate::operators::IdentityMismatch),
+    #[error(transparent)]
+    TooManySessions(#[from] crate::TooManySessions),
+    #[error(transparent)]
+    Config(#[from] sdk::config::Error),
+    #[error("Frost error: {0}")]
+    Frost(String),
+    #[error("Protocol error: {0}")]
+    Protocol(String),
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[error(transparent)]
+    Entry(#[from] crate::entry::Error),
+    #[error(transparent)]
+    Other(color_eyre::eyre::Error),
+    #[error(transparent)]
+    Unauthorized(#[from] crate::operators::Unauthorized),
+}
+
+impl<C: Ciphersuite> From<frost_core::Error<C>> for Error {
+    fn from(e: frost_core::Error<C>) -> Self {
+        Error::Frost(e.to_string())
+    }
+}
+
+impl<C: Ciphersuite> From<refresh_protocol::Error<C>> for Error {
+    fn from(e: refresh_protocol::Error<C>) -> Self {
+        Error::Protocol(e.to_string())
+    }
+}
+
+/// The refresh schedule of a key on this node.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+struct RefreshState {
+    /// Whether the key is refreshed, see [`set_share_refresh`].
+    enabled: bool,
+    /// The epoch the key was last refreshed, or first seen, in.
+    epoch: Option<u64>,
+}
+
+impl Default for RefreshState {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            epoch: None,
+        }
+    }
+}
+
+fn state_key(pubkey: &str) -> String {
+    format!("refresh/{pubkey}")
+}
+
+/// The store key of the keygen entry of the generation before the last refresh of the key
+/// `pubkey`.
+fn previous_key(pubkey: &str) -> String {
+    format!("refresh/previous/{pubkey}")
+}
+
+fn load_state(context: &FrostContext, pubkey: &str) -> Result<RefreshState, Error> {
+    match context.store.get(&state_key(pubkey))? {
+        Some(bytes) => Ok(serde_json::from_slice(&bytes)?),
+        None => Ok(RefreshState::default()),
+    }
+}
+
+fn save_state(context: &FrostContext, pubkey: &str, state: RefreshState) -> Result<(), Error> {
+    context
+        .store
+        .set(state_key(pubkey), serde_json::to_vec(&state)?)?;
+    Ok(())
+}
+
+/// Turn on or off the proactive refresh of the shares of a key on this node.
+///
+/// # Parameters
+/// - `pubkey`: The public key generated by the [`crate::keygen::keygen`] protocol, or its label.
+/// - `enabled`: Whether the shares of the key are refreshed, see
+///   [`FrostContext::with_share_refresh`].
+///
+/// # Returns
+/// The public key.
+///
+/// # Errors
+/// - `KeyNotFound`: If the key is not found.
+/// - `Unauthorized`: If the job is not called by the service owner or one of its operators.
+///
+/// # Note
+/// Every holder of the key must be called the same, as a refresh needs all of them.
+#[sdk::job(
+    id = 23,
+    params(pubkey, enabled),
+    result(_),
+    event_listener(
+        listener = TangleEventListener::<FrostContext, JobCalled>,
+        pre_processor = services_pre_processor,
+        post_processor = services_post_processor,
+    )
+)]
+#[tracing::instrument(skip_all, parent = context.config.span.clone(), err)]
+pub async fn set_share_refresh(
+    pubkey: Vec<u8>,
+    enabled: bool,
+    context: FrostContext,
+) -> Result<Vec<u8>, Error> {
+    context.authorize_caller().await?;
+    let pubkey = context.resolve_key(pubkey)?;
+    let hex_pubkey = hex::encode(&pubkey);
+    if context.keygen_info(&hex_pubkey)?.is_none() {
+        return Err(Error::KeyNotFound);
+    }
+    let state = load_state(&context, &hex_pubkey)?;
+    save_state(&context, &hex_pubkey, RefreshState { enabled, ..state })?;
+    Ok(pubkey)
+}
+
+impl FrostContext {
+    /// Refresh the shares of every stored key once per `interval`, in the background, see
+    /// [`refresh`](crate::refresh).
+    ///
+    /// Must be called from within a tokio runtime.
+    pub fn with_share_refresh(self, interval: Duration) -> Self {
+        let context = self.clone();
+        let interval = interval.as_secs().max(1);
+        tokio::spawn(async move {
+            loop {
+                tokio::time::sleep(CHECK_INTERVAL).await;
+                let epoch = context.clock.unix_secs() / interval;
+                if let Err(e) = context.refresh_due_keys(epoch).await {
+                    sdk::warn!(error = %e, "Failed to schedule the refresh of the keys");
+                }
+            }
+        });
+        self
+    }
+
+    /// Refresh the keys not refreshed yet in `epoch`.
+    async fn refresh_due_keys(&self, epoch: u64) -> Result<(), Error> {
+        let keys = crate::retention::stored_keys(&self.store).map_err(|e| match e {
+            crate::retention::Error::Json(e) => Error::Json(e),
+            crate::retention::Error::Io(e) => Error::Io(e),
+            e => Error::Other(e.into()),
+        })?;
+        for pubkey in keys {
+            let state = load_state(self, &pubkey)?;
+            let due = match state.epoch {
+                Some(last) => state.enabled && epoch > last,
+                None => false,
+            };
+            if due {
+                sdk::info!(
+                    pubkey = %self.log_redaction.redact(&pubkey),
+                    epoch,
+                    "Refreshing the key shares"
+                );
+                let refresh =
+                    tokio::time::timeout(REFRESH_TIMEOUT, self.refresh_key(&pubkey, epoch));
+                let result = refresh
+                    .await
+                    .unwrap_or(Err(Error::Timeout(REFRESH_TIMEOUT)));
+                if let Err(e) = result {
+                    sdk::warn!(
+                        pubkey = %self.log_redaction.redact(&pubkey),
+                        error = %e,
+                        "Failed to refresh the key shares, retrying in the next epoch"
+                    );
+                }
+            }
+            if state.epoch != Some(epoch) {
+                save_state(
+                    self,
+                    &pubkey,
+                    RefreshState {
+                        epoch: Some(epoch),
+                        ..state
+                    },
+                )?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Refresh the shares of the hex encoded key `pubkey` in the session of `epoch`.
+    async fn refresh_key(&self, pubkey: &str, epoch: u64) -> Result<(), Error> {
+        let info = self.keygen_info(pubkey)?.ok_or(Error::KeyNotFound)?;
+        let previous = match self.store.get(&previous_key(pubkey))? {
+            Some(raw) => Some(crate::entry::decode(&raw)?["entry"].clone()),
+            None => None,
+        };
+        let ciphersuite = info["ciphersuite"].as_str().unwrap_or_default().to_string();
+        let (base, entry) = match ciphersuite.as_str() {
+            frost_ed25519::Ed25519Sha512::ID => {
+                self.refresh_entry::<frost_ed25519::Ed25519Sha512>(&info, previous.as_ref(), epoch)
+                    .await?
+            }
+            frost_secp256k1::Secp256K1Sha256::ID => {
+                self.refresh_entry::<frost_secp256k1::Secp256K1Sha256>(
+                    &info,
+                    previous.as_ref(),
+                    epoch,
+                )
+                .await?
+            }
+            frost_ristretto255::Ristretto255Sha512::ID => {
+                self.refresh_entry::<frost_ristretto255::Ristretto255Sha512>(
+                    &info,
+                    previous.as_ref(),
+                    epoch,
+                )
+                .await?
+            }
+            frost_p256::P256Sha256::ID => {
+                self.refresh_entry::<frost_p256::P256Sha256>(&info, previous.as_ref(), epoch)
+                    .await?
+            }
+            crate::redjubjub::JubjubBlake2b512::ID => {
+                self.refresh_entry::<crate::redjubjub::JubjubBlake2b512>(
+                    &info,
+                    previous.as_ref(),
+                    epoch,
+                )
+                .await?
+            }
+            _ => return Err(Error::UnknownCiphersuite(ciphersuite)),
+        };
+        // Every holder held the base generation, the older ones are dropped.
+        let mut info = info;
+        info["entry"] = base;
+        let previous = crate::entry::encode(self.entry_format, &info)?;
+        self.store.set(previous_key(pubkey), previous)?;
+        info["entry"] = entry;
+        let info = crate::entry::encode(self.entry_format, &info)?;
+        self.store.set(pubkey.to_string(), info)?;
+        sdk::debug!(pubkey = %self.log_redaction.redact(pubkey), "Refresh Done");
+        Ok(())
+    }
+
+    /// Run the refresh of the keygen entry of the JSON envelope `info`, or of the entry
+    /// `previous` of the generation before it, returning the entry refreshed and the one with the
+    /// refreshed key packages.
+    async fn refresh_entry<C>(
+        &self,
+        info: &serde_json::Value,
+        previous: Option<&serde_json::Value>,
+        epoch: u64,
+    ) -> Result<(serde_json::Value, serde_json::Value), Error>
+    where
+        C: Ciphersuite + Send + Unpin,
+        <<C as Ciphersuite>::Group as frost_core::Group>::Element: Send + Unpin,
+        <<<C as Ciphersuite>::Group as frost_core::Group>::Field as frost_core::Field>::Scalar:
+            Send + Unpin,
+    {
+        let entry: crate::keygen::KeygenEntry<C> = serde_json::from_value(info["entry"].clone())?;
+        let mut entries = vec![entry];
+        if let Some(previous) = previous {
+            let previous: crate::keygen::KeygenEntry<C> = serde_json::from_value(previous.clone())?;
+            // A previous generation of other holders is of no use.
+            if previous
+                .pub_key_pkg
+                .verifying_shares()
+                .keys()
+                .eq(entries[0].pub_key_pkg.verifying_shares().keys())
+            {
+                entries.push(previous);
+            }
+        }
+        let entry = &entries[0];
+        let operators = self.current_operators().await.map_err(Error::Other)?;
+        let holders = crate::sign::key_holders(info, operators).map_err(|e| match e {
+            crate::sign::Error::Json(e) => Error::Json(e),
+            _ => Error::HoldersChanged,
+        })?;
+        if holders.len() != entry.pub_key_pkg.verifying_shares().len() {
+            return Err(Error::HoldersChanged);
+        }
+        let me = self.local_identity::<Error>()?;
+        let i = crate::operators::own_index(&holders, &me)?.ok_or(Error::SelfNotInHolders)?;
+        let i = u16::try_from(i).map_err(|_| Error::SelfNotInHolders)?;
+        let parties = crate::operators::party_indices(&holders)
+            .map(|(j, _, key)| (j, *key))
+            .collect::<std::collections::BTreeMap<u16, ecdsa::Public>>();
+
+        let pubkey = entry.pub_key_pkg.verifying_key().serialize()?;
+        let task_hash = crate::session::refresh_session_name(&pubkey, epoch);
+        let _session = self.sessions.register(task_hash, "refresh")?;
+        let delivery =
+            NetworkDeliveryWrapper::new(self.network_backend.clone(), i, task_hash, parties);
+        let party = round_based::MpcParty::connected(crate::codec::versioned(delivery, self.codec));
+        let generations = entries
+            .iter()
+            .map(|entry| (entry.key_pkg.clone(), entry.pub_key_pkg.clone()))
+            .collect::<Vec<_>>();
+        let refreshed = refresh_protocol::run::<_, C, _>(
+            &mut random::rand::rngs::OsRng,
+            &generations,
+            i,
+            party,
+            None,
+        )
+        .await?;
+        let base = entries.swap_remove(refreshed.base);
+        let entry = crate::keygen::KeygenEntry {
+            key_pkg: refreshed.key_pkg,
+            pub_key_pkg: refreshed.pub_key_pkg,
+            ..base.clone()
+        };
+        Ok((serde_json::to_value(base)?, serde_json::to_value(entry)?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::UNIX_EPOCH;
+
+    use super::*;
+    use crate::clock::{Clock, MockClock};
+    use crate::testing::{
+        keygen_on_all, operator_contexts, sign_on_all, MockNetwork, MockNetworkConfig, TempDir,
+    };
+
+    /// The serialized signing share of the key `pubkey` on the node of `context`.
+    fn signing_share(context: &FrostContext, pubkey: &str) -> Vec<u8> {
+        let info = context.keygen_info(pubkey).unwrap().unwrap();
+        let entry: crate::keygen::KeygenEntry<frost_ed25519::Ed25519Sha512> =
+            serde_json::from_value(info["entry"].clone()).unwrap();
+        entry.key_pkg.signing_share().serialize()
+    }
+
+    /// Wait until `condition` holds, for at most 30 seconds.
+    async fn eventually(mut condition: impl FnMut() -> bool) {
+        tokio::time::timeout(Duration::from_secs(30), async {
+            while !condition() {
+                tokio::time::sleep(Duration::from_millis(100)).await;
+            }
+        })
+        .await
+        .expect("the condition never held");
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn scheduled_refresh_keeps_the_verifying_key() {
+        type C = frost_ed25519::Ed25519Sha512;
+        const INTERVAL: Duration = Duration::from_secs(24 * 3600);
+        let network = MockNetwork::new(MockNetworkConfig {
+            latency: Duration::from_millis(20),
+            loss: 0.0,
+        });
+        let dir = TempDir::new("share-refresh");
+        let clock = MockClock::new(UNIX_EPOCH + Duration::from_secs(1_704_067_200));
+        let contexts = operator_contexts(&network, &dir, 3, 998)
+            .into_iter()
+            .map(|context| context.with_clock(clock.clone()))
+            .collect::<Vec<_>>();
+        let pubkey = keygen_on_all(&contexts, C::ID, 2).await;
+        let hex_pubkey = hex::encode(&pubkey);
+        let contexts = contexts
+            .into_iter()
+            .map(|context| context.with_share_refresh(INTERVAL))
+            .collect::<Vec<_>>();
+        // The key is first seen in the current epoch, and not refreshed in it.
+        eventually(|| {
+            contexts
+                .iter()
+                .all(|context| load_state(context, &hex_pubkey).unwrap().epoch.is_some())
+        })
+        .await;
+        let before = contexts
+            .iter()
+            .map(|context| signing_share(context, &hex_pubkey))
+            .collect::<Vec<_>>();
+
+        clock.advance(INTERVAL);
+        eventually(|| {
+            contexts
+                .iter()
+                .zip(&before)
+                .all(|(context, before)| signing_share(context, &hex_pubkey) != *before)
+        })
+        .await;
+        let verifying_key = frost_core::VerifyingKey::<C>::deserialize(&pubkey).unwrap();
+        for context in &contexts {
+            let info = context.keygen_info(&hex_pubkey).unwrap().unwrap();
+            let entry: crate::keygen::KeygenEntry<C> =
+                serde_json::from_value(info["entry"].clone()).unwrap();
+            assert_eq!(entry.pub_key_pkg.verifying_key(), &verifying_key);
+            assert_eq!(entry.key_pkg.verifying_key(), &verifying_key);
+        }
+
+        // The refreshed shares sign for the same key.
+        // The operator not selected to sign fails.
+        let signatures = sign_on_all(&contexts, &pubkey, b"refreshed").await;
+        let signatures = signatures.into_iter().flatten().collect::<Vec<_>>();
+        assert_eq!(signatures.len(), 2);
+        for signature in signatures {
+            let signature = frost_core::Signature::<C>::deserialize(&signature).unwrap();
+            verifying_key.verify(b"refreshed", &signature).unwrap();
+        }
+
+        // A key whose refresh is turned off keeps its shares.
+        for context in &contexts {
+            set_share_refresh(pubkey.clone(), false, context.clone())
+                .await
+                .unwrap();
+        }
+        let before = contexts
+            .iter()
+            .map(|context| signing_share(context, &hex_pubkey))
+            .collect::<Vec<_>>();
+        clock.advance(INTERVAL);
+        let epoch = clock.unix_secs() / INTERVAL.as_secs();
+        eventually(|| {
+            contexts
+                .iter()
+                .all(|context| load_state(context, &hex_pubkey).unwrap().epoch == Some(epoch))
+        })
+        .await;
+        for (context, before) in contexts.iter().zip(&before) {
+            assert_eq!(signing_share(context, &hex_pubkey), *before);
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn holder_missing_a_refresh_catches_up_in_the_next_one() {
+        type C = frost_ed25519::Ed25519Sha512;
+        const INTERVAL: Duration = Duration::from_secs(24 * 3600);
+        let network = MockNetwork::new(MockNetworkConfig {
+            latency: Duration::from_millis(20),
+            loss: 0.0,
+        });
+        let dir = TempDir::new("share-refresh-catch-up");
+        let clock = MockClock::new(UNIX_EPOCH + Duration::from_secs(1_704_067_200));
+        let contexts = operator_contexts(&network, &dir, 3, 998)
+            .into_iter()
+            .map(|context| context.with_clock(clock.clone()))
+            .collect::<Vec<_>>();
+        let pubkey = keygen_on_all(&contexts, C::ID, 2).await;
+        let hex_pubkey = hex::encode(&pubkey);
+        let contexts = contexts
+            .into_iter()
+            .map(|context| context.with_share_refresh(INTERVAL))
+            .collect::<Vec<_>>();
+        eventually(|| {
+            contexts
+                .iter()
+                .all(|context| load_state(context, &hex_pubkey).unwrap().epoch.is_some())
+        })
+        .await;
+        let before = contexts
+            .iter()
+            .map(|context| signing_share(context, &hex_pubkey))
+            .collect::<Vec<_>>();
+        clock.advance(INTERVAL);
+        eventually(|| {
+            contexts
+                .iter()
+                .zip(&before)
+                .all(|(context, before)| signing_share(context, &hex_pubkey) != *before)
+        })
+        .await;
+
+        // The first holder missed the end of the refresh, and kept its share.
+        let previous = contexts[0]
+            .store
+            .get(&previous_key(&hex_pubkey))
+            .unwrap()
+            .unwrap();
+        contexts[0].store.set(hex_pubkey.clone(), previous).unwrap();
+        assert_eq!(signing_share(&contexts[0], &hex_pubkey), before[0]);
+
+        let before = contexts
+            .iter()
+            .map(|context| signing_share(context, &hex_pubkey))
+            .collect::<Vec<_>>();
+        clock.advance(INTERVAL);
+        eventually(|| {
+            contexts
+                .iter()
+                .zip(&before)
+                .all(|(context, before)| signing_share(context, &hex_pubkey) != *before)
+        })
+        .await;
+        let verifying_key = frost_core::VerifyingKey::<C>::deserialize(&pubkey).unwrap();
+        let signatures = sign_on_all(&contexts, &pubkey, b"caught up").await;
+        let signatures = signatures.into_iter().flatten().collect::<Vec<_>>();
+        assert_eq!(signatures.len(), 2);
+        for signature in signatures {
+            let signature = frost_core::Signature::<C>::deserialize(&signature).unwrap();
+            verifying_key.verify(b"caught up", &signature).unwrap();
+        }
+    }
+}
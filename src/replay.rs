@@ -0,0 +1,382 @@
+//! Traces of the protocol messages, recorded in the field and replayed locally.
+//!
+//! With [`FrostContext::with_message_traces`], every message a node sends and receives during a
+//! keygen or signing is recorded, and the resulting [`Trace`] is written to a file of the trace
+//! directory once the protocol ends, whatever its outcome, even when it is aborted by the job
+//! timeout. [`replay`] turns a trace into a
+//! delivery feeding the received messages back to a local run of the protocol, e.g.
+//! [`rounds::keygen::run`](crate::rounds::keygen::run), so that a failure seen by an operator
+//! becomes a reproducible test case.
+//!
+//! Unlike the [diagnostics](crate::diagnostics), the traces hold the point-to-point messages,
+//! including the secret shares a keygen sent to the node, so they must be kept as safe as the
+//! key store.
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::Instant;
+
+use gadget_sdk::futures::stream::BoxStream;
+use gadget_sdk::futures::{future, sink, stream, Sink, SinkExt, StreamExt, TryStreamExt};
+use gadget_sdk::parking_lot::Mutex;
+use round_based::{Delivery, Incoming, MessageDestination, MessageType, Outgoing, ProtocolMessage};
+
+use crate::codec::Envelope;
+use crate::FrostContext;
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[error("Malformed traced message: {0}")]
+    Malformed(#[from] hex::FromHexError),
+}
+
+/// Whether a traced message was sent or received by the node.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Direction {
+    Sent,
+    Received,
+}
+
+/// A message sent or received during a protocol.
+#[derive(Clone, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct TracedMessage {
+    /// Milliseconds since the protocol started.
+    pub elapsed_ms: u64,
+    pub direction: Direction,
+    pub sender: u16,
+    /// The recipient of a point-to-point message, `None` for a broadcast message.
+    pub recipient: Option<u16>,
+    pub round: u16,
+    /// The codec version of the message.
+    pub version: u8,
+    /// The hex encoded message.
+    pub payload: String,
+}
+
+/// The messages a party sent and received during a protocol, in order.
+#[derive(Clone, Debug, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct Trace {
+    /// The job call id.
+    pub call_id: u64,
+    pub protocol: String,
+    /// The index of the party.
+    pub party_index: u16,
+    pub messages: Vec<TracedMessage>,
+}
+
+impl Trace {
+    /// Read a trace written by [`Trace::write`].
+    pub fn read(path: impl AsRef<Path>) -> Result<Self, Error> {
+        Ok(serde_json::from_slice(&std::fs::read(path)?)?)
+    }
+
+    /// Write the trace to the file `path`.
+    pub fn write(&self, path: impl AsRef<Path>) -> Result<(), Error> {
+        Ok(std::fs::write(path, serde_json::to_vec_pretty(self)?)?)
+    }
+
+    /// The name of the file of the trace in the trace directory.
+    pub fn file_name(&self) -> String {
+        format!(
+            "{}-{}-{}.json",
+            self.call_id, self.protocol, self.party_index
+        )
+    }
+}
+
+/// A delivery replaying a trace, see [`replay`].
+pub type ReplayDelivery = (
+    BoxStream<'static, Result<Incoming<Envelope>, std::convert::Infallible>>,
+    sink::Drain<Outgoing<Envelope>>,
+);
+
+/// A delivery receiving the messages `trace` recorded as received, in their order, and
+/// dropping the messages sent.
+///
+/// The received messages do not depend on the randomness of the party, so a replay reaches
+/// the same outcome as the recorded run only with the same randomness, while a failure caused
+/// by the messages of another party is reproduced with any.
+pub fn replay(trace: &Trace) -> Result<ReplayDelivery, Error> {
+    let received = trace
+        .messages
+        .iter()
+        .filter(|m| m.direction == Direction::Received)
+        .enumerate()
+        .map(|(id, m)| {
+            Ok(Ok(Incoming {
+                id: id as u64,
+                sender: m.sender,
+                msg_type: match m.recipient {
+                    None => MessageType::Broadcast,
+                    Some(_) => MessageType::P2P,
+                },
+                msg: Envelope::new(m.version, m.round, hex::decode(&m.payload)?),
+            }))
+        })
+        .collect::<Result<Vec<_>, Error>>()?;
+    Ok((stream::iter(received).boxed(), sink::drain()))
+}
+
+/// A delivery recording its messages in a trace, see [`TraceRecorder::record`].
+pub(crate) type TracedDelivery<D> = (
+    BoxStream<'static, Result<Incoming<Envelope>, <D as Delivery<Envelope>>::ReceiveError>>,
+    Pin<
+        Box<
+            dyn Sink<Outgoing<Envelope>, Error = <D as Delivery<Envelope>>::SendError>
+                + Send
+                + 'static,
+        >,
+    >,
+);
+
+/// A trace being recorded, written to its file in `directory` when dropped.
+#[derive(Debug)]
+struct TraceFile {
+    directory: Arc<PathBuf>,
+    trace: Trace,
+}
+
+impl Drop for TraceFile {
+    fn drop(&mut self) {
+        let path = self.directory.join(self.trace.file_name());
+        // Failing to write it does not change the outcome of the job, it is only logged.
+        match self.trace.write(&path) {
+            Ok(()) => tracing::info!(path = %path.display(), "Saved the message trace"),
+            Err(e) => tracing::warn!(
+                call_id = self.trace.call_id,
+                error = %e,
+                "Failed to save the message trace"
+            ),
+        }
+    }
+}
+
+/// Records the trace of a protocol, see [`TraceRecorder::record`].
+///
+/// The trace is written once the recorder and the deliveries it records are all dropped.
+#[derive(Clone, Debug)]
+pub(crate) struct TraceRecorder {
+    started: Instant,
+    file: Arc<Mutex<TraceFile>>,
+}
+
+impl TraceRecorder {
+    fn new(directory: Arc<PathBuf>, trace: Trace) -> Self {
+        Self {
+            started: Instant::now(),
+            file: Arc::new(Mutex::new(TraceFile { directory, trace })),
+        }
+    }
+
+    fn push(&self, direction: Direction, sender: u16, recipient: Option<u16>, envelope: &Envelope) {
+        let message = TracedMessage {
+            elapsed_ms: self.started.elapsed().as_millis() as u64,
+            direction,
+            sender,
+            recipient,
+            round: envelope.round(),
+            version: envelope.version(),
+            payload: hex::encode(envelope.payload()),
+        };
+        self.file.lock().trace.messages.push(message);
+    }
+
+    /// Record the messages sent and received on `delivery`, if there is a recorder.
+    pub(crate) fn record<D>(recorder: Option<&Self>, delivery: D) -> TracedDelivery<D>
+    where
+        D: Delivery<Envelope>,
+        D::Send: Send + 'static,
+        D::Receive: Send + 'static,
+    {
+        let (incoming, outgoing) = delivery.split();
+        let Some(recorder) = recorder else {
+            return (incoming.boxed(), Box::pin(outgoing));
+        };
+        let party_index = recorder.file.lock().trace.party_index;
+        let received = recorder.clone();
+        let incoming = incoming.inspect_ok(move |incoming| {
+            let recipient = (!incoming.is_broadcast()).then_some(party_index);
+            received.push(
+                Direction::Received,
+                incoming.sender,
+                recipient,
+                &incoming.msg,
+            );
+        });
+        let sent = recorder.clone();
+        let outgoing = outgoing.with(move |outgoing: Outgoing<Envelope>| {
+            let recipient = match outgoing.recipient {
+                MessageDestination::AllParties => None,
+                MessageDestination::OneParty(j) => Some(j),
+            };
+            sent.push(Direction::Sent, party_index, recipient, &outgoing.msg);
+            future::ready(Ok(outgoing))
+        });
+        (incoming.boxed(), Box::pin(outgoing))
+    }
+}
+
+impl FrostContext {
+    /// A recorder of the protocol of job call `call_id`, if message traces are enabled.
+    pub(crate) fn trace_recorder(
+        &self,
+        call_id: u64,
+        protocol: &str,
+        party_index: u16,
+    ) -> Option<TraceRecorder> {
+        let directory = self.message_traces.clone()?;
+        let trace = Trace {
+            call_id,
+            protocol: protocol.to_string(),
+            party_index,
+            messages: Vec::new(),
+        };
+        Some(TraceRecorder::new(directory, trace))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codec::{versioned, CodecVersion};
+    use crate::rounds::keygen as keygen_protocol;
+    use crate::testing::TempDir;
+    use gadget_sdk::random::rand::rngs::StdRng;
+    use gadget_sdk::random::SeedableRng;
+    use round_based::simulation::Simulation;
+    use round_based::{Mpc, MpcParty};
+
+    type C = frost_secp256k1::Secp256K1Sha256;
+    const N: u16 = 3;
+    const T: u16 = 2;
+
+    /// Run a keygen among `N` parties, recording the trace of the first one.
+    async fn recorded_keygen(
+        dir: &TempDir,
+    ) -> (
+        Trace,
+        (
+            frost_core::keys::KeyPackage<C>,
+            frost_core::keys::PublicKeyPackage<C>,
+        ),
+    ) {
+        std::fs::create_dir_all(&dir.0).unwrap();
+        let recorder = TraceRecorder::new(
+            Arc::new(dir.0.clone()),
+            Trace {
+                call_id: 957,
+                protocol: "keygen".to_string(),
+                party_index: 0,
+                messages: Vec::new(),
+            },
+        );
+        let mut simulation = Simulation::<Envelope>::new();
+        let parties = (0..N).map(|_| simulation.add_party()).collect::<Vec<_>>();
+        let mut tasks = vec![];
+        for (i, party) in (0..N).zip(parties) {
+            let delivery = party.into_party().delivery;
+            let delivery = TraceRecorder::record((i == 0).then_some(&recorder), delivery);
+            let delivery = versioned(delivery, CodecVersion::default());
+            tasks.push(tokio::spawn(async move {
+                let rng = &mut StdRng::seed_from_u64(u64::from(i));
+                keygen_protocol::run::<_, C, _>(
+                    rng,
+                    T,
+                    N,
+                    i,
+                    keygen_protocol::BroadcastCheck::Unchecked,
+                    None,
+                    MpcParty::connected(delivery),
+                    None,
+                )
+                .await
+                .map_err(|e| e.to_string())
+            }));
+        }
+        let mut results = vec![];
+        for task in tasks {
+            results.push(task.await.unwrap().unwrap());
+        }
+
+        // The trace is written once the recorder and the deliveries are dropped.
+        drop(recorder);
+        let trace = Trace::read(dir.0.join("957-keygen-0.json")).unwrap();
+        (trace, results.swap_remove(0))
+    }
+
+    #[tokio::test]
+    async fn recorded_keygen_replays_to_the_same_result() {
+        let dir = TempDir::new("message-traces");
+        let (trace, expected) = recorded_keygen(&dir).await;
+        // A broadcast, and a share to each of the others.
+        let sent = trace
+            .messages
+            .iter()
+            .filter(|m| m.direction == Direction::Sent)
+            .map(|m| m.recipient)
+            .collect::<Vec<_>>();
+        assert_eq!(sent, [None, Some(1), Some(2)]);
+        let shares = trace
+            .messages
+            .iter()
+            .filter(|m| m.direction == Direction::Received && m.recipient == Some(0))
+            .map(|m| m.sender)
+            .collect::<Vec<_>>();
+        assert_eq!(shares.len(), 2);
+
+        let delivery = versioned(replay(&trace).unwrap(), CodecVersion::default());
+        let rng = &mut StdRng::seed_from_u64(0);
+        let (key_pkg, pub_key_pkg) = keygen_protocol::run::<_, C, _>(
+            rng,
+            T,
+            N,
+            0,
+            keygen_protocol::BroadcastCheck::Unchecked,
+            None,
+            MpcParty::connected(delivery),
+            None,
+        )
+        .await
+        .unwrap();
+        assert_eq!(key_pkg, expected.0);
+        assert_eq!(pub_key_pkg, expected.1);
+    }
+
+    #[tokio::test]
+    async fn out_of_order_burst_is_buffered_by_the_router() {
+        let dir = TempDir::new("out-of-order-burst");
+        let (mut trace, expected) = recorded_keygen(&dir).await;
+        // Every message arrives at once, the shares of the second round before the
+        // commitments of the first one.
+        trace.messages.reverse();
+        let rounds = trace
+            .messages
+            .iter()
+            .filter(|m| m.direction == Direction::Received)
+            .map(|m| m.round)
+            .collect::<Vec<_>>();
+        assert!(rounds.windows(2).any(|w| w[0] > w[1]));
+
+        let delivery = versioned(replay(&trace).unwrap(), CodecVersion::default());
+        let rng = &mut StdRng::seed_from_u64(0);
+        let (key_pkg, pub_key_pkg) = keygen_protocol::run::<_, C, _>(
+            rng,
+            T,
+            N,
+            0,
+            keygen_protocol::BroadcastCheck::Unchecked,
+            None,
+            MpcParty::connected(delivery),
+            None,
+        )
+        .await
+        .unwrap();
+        assert_eq!(key_pkg, expected.0);
+        assert_eq!(pub_key_pkg, expected.1);
+    }
+}
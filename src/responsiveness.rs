@@ -0,0 +1,92 @@
+//! Tracking of the signers that responded during a signing.
+//!
+//! A signing waits for a message of every selected signer, so a signer that never answers stalls
+//! it until the job timeout. The senders of the messages received are recorded, so that a timed
+//! out signing reports which of the selected signers responded and which did not, see
+//! [`crate::sign::Error::UnresponsiveSigners`].
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::Arc;
+
+use gadget_sdk::futures::stream::BoxStream;
+use gadget_sdk::futures::{StreamExt, TryStreamExt};
+use gadget_sdk::parking_lot::Mutex;
+use gadget_sdk::subxt_core::ext::sp_core::ecdsa;
+use round_based::{Delivery, Incoming, ProtocolMessage};
+
+use crate::codec::Envelope;
+
+/// A delivery recording the senders of its incoming messages, see [`Responsiveness::track`].
+pub(crate) type TrackedDelivery<D> = (
+    BoxStream<'static, Result<Incoming<Envelope>, <D as Delivery<Envelope>>::ReceiveError>>,
+    <D as Delivery<Envelope>>::Send,
+);
+
+/// The selected signers and the senders of the messages received in each round.
+#[derive(Debug, Default)]
+struct State {
+    party_index: u16,
+    selected: Vec<ecdsa::Public>,
+    senders: BTreeMap<u16, BTreeSet<u16>>,
+}
+
+/// Records which of the selected signers sent their messages.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Responsiveness {
+    state: Arc<Mutex<State>>,
+}
+
+impl Responsiveness {
+    /// Set the `selected` signers, this node being the one at `party_index`.
+    pub(crate) fn select(&self, party_index: u16, selected: &BTreeMap<u16, ecdsa::Public>) {
+        let mut state = self.state.lock();
+        state.party_index = party_index;
+        state.selected = selected.values().copied().collect();
+    }
+
+    /// Record the senders of the messages received on `delivery`.
+    pub(crate) fn track<D>(&self, delivery: D) -> TrackedDelivery<D>
+    where
+        D: Delivery<Envelope>,
+        D::Receive: Send + 'static,
+    {
+        let state = self.state.clone();
+        let (incoming, outgoing) = delivery.split();
+        let incoming = incoming.inspect_ok(move |incoming| {
+            state
+                .lock()
+                .senders
+                .entry(incoming.msg.round())
+                .or_default()
+                .insert(incoming.sender);
+        });
+        (incoming.boxed(), outgoing)
+    }
+
+    /// The responsive and unresponsive selected signers, or `None` before they are selected.
+    ///
+    /// A signer responded if it sent its message in every round this node received messages in,
+    /// this node always responding.
+    pub(crate) fn report(&self) -> Option<(Vec<ecdsa::Public>, Vec<ecdsa::Public>)> {
+        let state = self.state.lock();
+        if state.selected.is_empty() {
+            return None;
+        }
+        let responded = |index: u16| {
+            index == state.party_index
+                || (!state.senders.is_empty()
+                    && state
+                        .senders
+                        .values()
+                        .all(|senders| senders.contains(&index)))
+        };
+        let (responsive, unresponsive): (Vec<_>, Vec<_>) = state
+            .selected
+            .iter()
+            .enumerate()
+            .partition(|(index, _)| u16::try_from(*index).is_ok_and(responded));
+        let keys = |signers: Vec<(usize, &ecdsa::Public)>| {
+            signers.into_iter().map(|(_, key)| *key).collect::<Vec<_>>()
+        };
+        Some((keys(responsive), keys(unresponsive)))
+    }
+}
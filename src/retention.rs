@@ -0,0 +1,159 @@
+//! Bound of the number of keys a node stores.
+//!
+//! The keys generated by this node are listed in the store in the order they were generated,
+//! so that once [`KeyLimit::max_keys`] are stored a new keygen is either refused or makes room
+//! by deleting the key that signed the least recently, according to its
+//! [`KeyUsage`](crate::usage::KeyUsage).
+use gadget_sdk::parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+
+use crate::kv::SharedDynKVStore;
+
+/// Serializes the updates of the index of the keys, which is read, changed and written back.
+static UPDATES: Mutex<()> = Mutex::new(());
+
+/// The store key of the index of the keys.
+const INDEX_KEY: &str = "keys";
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("The key store is full, this node stores at most {max_keys} keys")]
+    KeyStoreFull { max_keys: usize },
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+}
+
+/// What a keygen does when the node already stores [`KeyLimit::max_keys`] keys.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum KeyLimitPolicy {
+    /// Fail the keygen before it starts.
+    #[default]
+    Reject,
+    /// Delete the key share that signed the least recently, or never, the oldest first.
+    ///
+    /// The other operators are not told, so the key stays usable only as long as enough of
+    /// them still hold their share.
+    EvictLeastRecentlyUsed,
+}
+
+/// The bound of the number of keys a node stores.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct KeyLimit {
+    /// The maximum number of keys.
+    pub max_keys: usize,
+    /// What a keygen does once the maximum is reached.
+    pub policy: KeyLimitPolicy,
+}
+
+/// The hex encoded public keys stored by this node, in the order they were generated.
+///
+/// The keys generated before the index existed are not listed.
+pub(crate) fn stored_keys(store: &SharedDynKVStore<String, Vec<u8>>) -> Result<Vec<String>, Error> {
+    match store.get(&INDEX_KEY.to_string())? {
+        Some(bytes) => Ok(serde_json::from_slice(&bytes)?),
+        None => Ok(Vec::new()),
+    }
+}
+
+/// Fail early if a keygen would be refused by [`admit`].
+pub(crate) fn check_room(
+    store: &SharedDynKVStore<String, Vec<u8>>,
+    limit: &KeyLimit,
+) -> Result<(), Error> {
+    if limit.policy == KeyLimitPolicy::Reject && stored_keys(store)?.len() >= limit.max_keys {
+        return Err(Error::KeyStoreFull {
+            max_keys: limit.max_keys,
+        });
+    }
+    Ok(())
+}
+
+/// List the new key `pubkey`, hex encoded, making room for it according to `limit`.
+///
+/// Returns the evicted keys.
+pub(crate) fn admit(
+    store: &SharedDynKVStore<String, Vec<u8>>,
+    limit: Option<&KeyLimit>,
+    pubkey: &str,
+) -> Result<Vec<String>, Error> {
+    let _updating = UPDATES.lock();
+    let mut keys = stored_keys(store)?;
+    if keys.iter().any(|k| k == pubkey) {
+        return Ok(Vec::new());
+    }
+    let mut evicted = Vec::new();
+    if let Some(limit) = limit {
+        if limit.policy == KeyLimitPolicy::Reject && keys.len() >= limit.max_keys {
+            return Err(Error::KeyStoreFull {
+                max_keys: limit.max_keys,
+            });
+        }
+        while keys.len() >= limit.max_keys {
+            // A key whose usage cannot be read is taken as never used.
+            let Some((position, _)) = keys.iter().enumerate().min_by_key(|(_, key)| {
+                hex::decode(key)
+                    .ok()
+                    .and_then(|key| crate::usage::read(store, &key).ok())
+                    .and_then(|usage| usage.last_signed)
+            }) else {
+                break;
+            };
+            let key = keys.remove(position);
+            store.del(&key)?;
+            if let Ok(bytes) = hex::decode(&key) {
+                crate::usage::forget(store, &bytes)?;
+            }
+            evicted.push(key);
+        }
+    }
+    keys.push(pubkey.to_string());
+    store.set(INDEX_KEY.to_string(), serde_json::to_vec(&keys)?)?;
+    Ok(evicted)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::kv::MemKVStore;
+
+    #[test]
+    fn exceeding_the_cap_triggers_the_policy() {
+        let store: SharedDynKVStore<String, Vec<u8>> = Arc::new(MemKVStore::new());
+        let keys = ["aa", "bb", "cc"];
+        for key in &keys[..2] {
+            store.set(key.to_string(), vec![1]).unwrap();
+            admit(&store, None, key).unwrap();
+        }
+
+        let reject = KeyLimit {
+            max_keys: 2,
+            policy: KeyLimitPolicy::Reject,
+        };
+        assert!(matches!(
+            check_room(&store, &reject),
+            Err(Error::KeyStoreFull { max_keys: 2 })
+        ));
+        assert!(matches!(
+            admit(&store, Some(&reject), keys[2]),
+            Err(Error::KeyStoreFull { max_keys: 2 })
+        ));
+        assert_eq!(stored_keys(&store).unwrap(), ["aa", "bb"]);
+
+        // The older key signed recently, the newer one never did.
+        crate::usage::record(&store, &[0xaa], 1, true, 100).unwrap();
+        let evict = KeyLimit {
+            max_keys: 2,
+            policy: KeyLimitPolicy::EvictLeastRecentlyUsed,
+        };
+        check_room(&store, &evict).unwrap();
+        assert_eq!(admit(&store, Some(&evict), keys[2]).unwrap(), ["bb"]);
+        assert_eq!(stored_keys(&store).unwrap(), ["aa", "cc"]);
+        assert!(!store.ex(&"bb".to_string()).unwrap());
+        assert!(store.ex(&"aa".to_string()).unwrap());
+    }
+}
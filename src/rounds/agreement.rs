@@ -0,0 +1,170 @@
+use gadget_sdk::subxt_core::ext::sp_core::keccak_256;
+use round_based::rounds_router::simple_store::RoundInput;
+use round_based::rounds_router::RoundsRouter;
+use round_based::{Delivery, Mpc, MpcParty, Outgoing, ProtocolMessage, SinkExt};
+use serde::{Deserialize, Serialize};
+
+use crate::rounds::IoError;
+
+use super::trace::Tracer;
+
+/// Protocol message
+#[derive(Clone, Debug, PartialEq, ProtocolMessage, Serialize, Deserialize)]
+pub enum Msg {
+    /// The vote of the sender
+    Vote(Vote),
+    /// The digests of the votes received from each party, this one's included
+    Echo(Vec<[u8; 32]>),
+}
+
+/// The vote of a party on a proposal.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Vote {
+    /// The digest of the proposal voted on.
+    pub proposal: [u8; 32],
+    /// Whether the party approves it.
+    pub approve: bool,
+}
+
+impl Vote {
+    fn digest(&self) -> [u8; 32] {
+        let mut bytes = self.proposal.to_vec();
+        bytes.push(u8::from(self.approve));
+        keccak_256(&bytes)
+    }
+}
+
+/// Agreement protocol error
+#[derive(Debug, displaydoc::Display)]
+#[cfg_attr(feature = "std", derive(thiserror::Error))]
+#[displaydoc("agreement protocol is failed to complete: {0}")]
+pub struct Error(#[cfg_attr(feature = "std", source)] pub Reason);
+
+/// Agreement protocol abort reason
+#[derive(Debug, displaydoc::Display)]
+#[cfg_attr(feature = "std", derive(thiserror::Error))]
+pub enum Reason {
+    /// Party {party} sent different votes to different parties
+    Equivocation { party: u16 },
+    /// Party {party} voted on another proposal
+    ProposalMismatch { party: u16 },
+    /// IO error: {0}
+    IoError(#[cfg_attr(feature = "std", source)] super::IoError),
+    /// Bug occurred: {0}
+    Bug(Bug),
+}
+
+impl Error {
+    /// Whether the agreement failed because the network of this node is shut down.
+    pub fn is_network_shutdown(&self) -> bool {
+        matches!(self.0, Reason::IoError(super::IoError::NetworkShutdown))
+    }
+}
+
+super::impl_from! {
+    impl From for Error {
+        err: Reason => Error(err),
+        err: super::IoError => Error(Reason::IoError(err)),
+        err: Bug => Error(Reason::Bug(err)),
+    }
+}
+
+#[derive(Debug, displaydoc::Display)]
+#[cfg_attr(feature = "std", derive(thiserror::Error))]
+pub enum Bug {
+    /// Invalid party index, must be in range 0..n
+    InvalidPartyIndex,
+}
+
+/// Run the agreement on the proposal of digest `proposal` among `n` parties, this one voting
+/// `approve`.
+///
+/// Every party broadcasts its vote and waits for the votes of all the others, then echoes the
+/// digests of the votes it received, like the [`Echo`](super::keygen::BroadcastCheck::Echo)
+/// check of the keygen: the agreement aborts with [`Reason::Equivocation`] if a party sent
+/// different votes to different parties, so the parties that complete it all count the same
+/// votes. It aborts with [`Reason::ProposalMismatch`] if a party voted on another proposal,
+/// e.g. because it sees another configuration.
+///
+/// Returns the number of parties approving the proposal, this one included.
+#[tracing::instrument(target = "gadget", name = "agreement", skip(tracer, party), err)]
+pub async fn run<M>(
+    proposal: [u8; 32],
+    approve: bool,
+    n: u16,
+    i: u16,
+    party: M,
+    mut tracer: Option<&mut dyn Tracer>,
+) -> Result<u16, Error>
+where
+    M: Mpc<ProtocolMessage = Msg>,
+{
+    if i >= n {
+        return Err(Bug::InvalidPartyIndex.into());
+    }
+    tracer.protocol_begins();
+    tracer.stage("Setup networking");
+    let MpcParty { delivery, .. } = party.into_party();
+    let (incomings, mut outgoings) = delivery.split();
+    let mut router = RoundsRouter::<Msg>::builder();
+    let round = router.add_round(RoundInput::<Vote>::broadcast(i, n));
+    let echo_round = router.add_round(RoundInput::<Vec<[u8; 32]>>::broadcast(i, n));
+    let mut rounds = router.listen(incomings);
+
+    tracer.round_begins();
+    tracer.stage("Broadcast the vote");
+    let vote = Vote { proposal, approve };
+    tracer.send_msg();
+    outgoings
+        .send(Outgoing::broadcast(Msg::Vote(vote)))
+        .await
+        .map_err(IoError::send_message)?;
+    tracer.msg_sent();
+    tracer.receive_msgs();
+    let votes = rounds
+        .complete(round)
+        .await
+        .map_err(IoError::receive_message)?;
+    tracer.msgs_received();
+    let mut votes = votes
+        .into_iter_indexed()
+        .map(|(j, _, vote)| (j, vote))
+        .collect::<std::collections::BTreeMap<_, _>>();
+    votes.insert(i, vote);
+
+    tracer.named_round_begins("Echo");
+    tracer.stage("Broadcast the vote digests");
+    let digests = votes.values().map(Vote::digest).collect::<Vec<_>>();
+    tracer.send_msg();
+    outgoings
+        .send(Outgoing::broadcast(Msg::Echo(digests.clone())))
+        .await
+        .map_err(IoError::send_message)?;
+    tracer.msg_sent();
+    tracer.receive_msgs();
+    let echoes = rounds
+        .complete(echo_round)
+        .await
+        .map_err(IoError::receive_message)?;
+    tracer.msgs_received();
+    // This party knows what it sent, so only the votes of the others are compared.
+    for (echoer, _, echo) in echoes.into_iter_indexed() {
+        if echo.len() != digests.len() {
+            return Err(Reason::Equivocation { party: echoer }.into());
+        }
+        let equivocation = (0..n)
+            .zip(echo.iter().zip(&digests))
+            .find(|(j, (theirs, ours))| *j != i && theirs != ours);
+        if let Some((party, _)) = equivocation {
+            tracing::warn!(party, echoer, "Vote equivocation");
+            return Err(Reason::Equivocation { party }.into());
+        }
+    }
+    if let Some((party, _)) = votes.iter().find(|(_, vote)| vote.proposal != proposal) {
+        return Err(Reason::ProposalMismatch { party: *party }.into());
+    }
+    let approvals = votes.values().filter(|vote| vote.approve).count();
+    tracer.protocol_ends();
+    // There are at most `n` votes.
+    Ok(approvals as u16)
+}
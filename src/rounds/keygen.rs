@@ -1,10 +1,14 @@
 use std::collections::BTreeMap;
+use std::num::NonZeroUsize;
 
 use frost_core::keys::dkg::round2::Package as Round2Package;
 use frost_core::keys::{dkg, PublicKeyPackage};
 use frost_core::keys::{dkg::round1::Package as Round1Package, KeyPackage};
 use frost_core::{Ciphersuite, Group, Identifier};
 use gadget_sdk::random::rand;
+use gadget_sdk::subxt_core::ext::sp_core::keccak_256;
+use rand_chacha::rand_core::SeedableRng;
+use rand_chacha::ChaCha20Rng;
 use round_based::rounds_router::simple_store::RoundInput;
 use round_based::rounds_router::RoundsRouter;
 use round_based::{Delivery, Mpc, MpcParty, Outgoing, ProtocolMessage, SinkExt};
@@ -22,6 +26,21 @@ pub enum Msg<C: Ciphersuite> {
     Round1(Round1Package<C>),
     /// Round 2
     Round2(Round2Package<C>),
+    /// The digests of the round 1 packages received from each party, see [`BroadcastCheck::Echo`]
+    Echo(Vec<[u8; 32]>),
+}
+
+/// How the parties make sure every one of them received the same round 1 broadcasts.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BroadcastCheck {
+    /// Trust the delivery to send a broadcast to every party as is.
+    #[default]
+    Unchecked,
+    /// Add an echo round where every party broadcasts the digests of the round 1 packages it
+    /// received, and abort with [`KeygenAborted::Equivocation`] if a sender's package differs
+    /// from a recipient to another. Every party must use the same check.
+    Echo,
 }
 
 /// Keygen protocol error
@@ -42,6 +61,14 @@ pub enum Reason<C: Ciphersuite> {
     Bug(Bug),
 }
 
+impl<C: Ciphersuite> Error<C> {
+    /// Whether the keygen failed because the network of this node is shut down, rather than
+    /// because of a fault of the protocol or of another party.
+    pub fn is_network_shutdown(&self) -> bool {
+        matches!(self.0, Reason::IoError(super::IoError::NetworkShutdown))
+    }
+}
+
 super::impl_from! {
     impl<C: Ciphersuite> From for Error<C> {
         err: KeygenAborted<C> => Error(Reason::Aborted(err)),
@@ -64,6 +91,8 @@ impl<C: Ciphersuite> From<KeygenAborted<C>> for Reason<C> {
 pub enum KeygenAborted<C: Ciphersuite> {
     /// A party has aborted the protocol: {0}
     Frost(frost_core::Error<C>),
+    /// Party {party} sent different round 1 packages to different parties
+    Equivocation { party: u16 },
 }
 
 #[derive(Debug, displaydoc::Display)]
@@ -75,13 +104,47 @@ pub enum Bug {
     InvalidProtocolParameters,
 }
 
+/// The domain of the seed of [`beacon_rng`].
+const BEACON_DOMAIN: &[u8] = b"frost-blueprint/keygen-beacon/v1";
+
+/// A RNG for [`run`] mixing the public randomness `beacon`, e.g. a drand round, with 32 bytes
+/// of the local `rng`.
+///
+/// The same beacon and local entropy always give the same round 1 package, so once a party
+/// discloses its entropy anyone can check that its contribution to the key was derived from the
+/// beacon. The local entropy keeps the secret contribution unpredictable to those who only know
+/// the beacon.
+pub fn beacon_rng<R>(rng: &mut R, beacon: &[u8]) -> ChaCha20Rng
+where
+    R: rand::RngCore + rand::CryptoRng,
+{
+    let mut entropy = [0u8; 32];
+    rng.fill_bytes(&mut entropy);
+    let seed = keccak_256(
+        &[
+            BEACON_DOMAIN,
+            &(beacon.len() as u64).to_be_bytes(),
+            beacon,
+            &entropy,
+        ]
+        .concat(),
+    );
+    ChaCha20Rng::from_seed(seed)
+}
+
 /// Run FROST Keygen Protocol
+///
+/// The round 2 packages are sent together and flushed once, or every `round2_concurrency`
+/// packages if set, instead of waiting for each one to be sent before the next.
 #[tracing::instrument(target = "gadget", name = "keygen", skip(rng, tracer, party), err)]
+#[allow(clippy::too_many_arguments)]
 pub async fn run<R, C, M>(
     rng: &mut R,
     t: u16,
     n: u16,
     i: u16,
+    broadcast_check: BroadcastCheck,
+    round2_concurrency: Option<NonZeroUsize>,
     party: M,
     mut tracer: Option<&mut dyn Tracer>,
 ) -> Result<(KeyPackage<C>, PublicKeyPackage<C>), Error<C>>
@@ -105,6 +168,7 @@ where
     let mut router = RoundsRouter::<Msg<C>>::builder();
     let round1 = router.add_round(RoundInput::<Round1Package<C>>::broadcast(i, n));
     let round2 = router.add_round(RoundInput::<Round2Package<C>>::p2p(i, n));
+    let echo_round = router.add_round(RoundInput::<Vec<[u8; 32]>>::broadcast(i, n));
     let mut rounds = router.listen(incomings);
     // Round 1
     gadget_sdk::debug!("Round 1 started");
@@ -116,7 +180,7 @@ where
     gadget_sdk::debug!("Broadcasting round 1 package");
     tracer.send_msg();
     outgoings
-        .send(Outgoing::broadcast(Msg::Round1(round1_package)))
+        .send(Outgoing::broadcast(Msg::Round1(round1_package.clone())))
         .await
         .map_err(IoError::send_message)?;
     tracer.msg_sent();
@@ -137,6 +201,49 @@ where
         })
         .collect::<Result<BTreeMap<Identifier<C>, _>, _>>()?;
 
+    if broadcast_check == BroadcastCheck::Echo {
+        tracer.named_round_begins("Echo");
+        tracer.stage("Broadcast round 1 digests");
+        let digests = (0..n)
+            .map(|j| {
+                let package = if j == i {
+                    &round1_package
+                } else {
+                    let party =
+                        IdentifierWrapper::<C>::try_from(j).map_err(|_| Bug::InvalidPartyIndex)?;
+                    round1_packages.get(&party).ok_or(Bug::InvalidPartyIndex)?
+                };
+                let bytes = package.serialize().map_err(KeygenAborted::Frost)?;
+                Result::<_, Error<C>>::Ok(keccak_256(&bytes))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        tracer.send_msg();
+        outgoings
+            .send(Outgoing::broadcast(Msg::Echo(digests.clone())))
+            .await
+            .map_err(IoError::send_message)?;
+        tracer.msg_sent();
+        tracer.receive_msgs();
+        let echoes = rounds
+            .complete(echo_round)
+            .await
+            .map_err(IoError::receive_message)?;
+        tracer.msgs_received();
+        // This party knows what it sent, so only the packages of the others are compared.
+        for (echoer, _, echo) in echoes.into_iter_indexed() {
+            if echo.len() != digests.len() {
+                return Err(KeygenAborted::Equivocation { party: echoer }.into());
+            }
+            let equivocation = (0..n)
+                .zip(echo.iter().zip(&digests))
+                .find(|(j, (theirs, ours))| *j != i && theirs != ours);
+            if let Some((party, _)) = equivocation {
+                tracing::warn!(party, echoer, "Round 1 package equivocation");
+                return Err(KeygenAborted::Equivocation { party }.into());
+            }
+        }
+    }
+
     // Round 2
     tracer.round_begins();
     gadget_sdk::debug!("Round 2 started");
@@ -144,17 +251,27 @@ where
     let (round2_secret_package, my_round2_packages) =
         dkg::part2(round1_secret_package, &round1_packages).map_err(KeygenAborted::Frost)?;
     let span = tracing::debug_span!(target: "gadget", "Sending round 2 packages");
+    let in_flight = round2_concurrency.map_or(usize::MAX, NonZeroUsize::get);
+    let mut unflushed = 0;
     for (to, round2_package) in my_round2_packages {
         let _guard = span.enter();
         tracer.send_msg();
         let to = IdentifierWrapper(to).as_u16();
         gadget_sdk::debug!(%to, "Sending to party");
         outgoings
-            .send(Outgoing::p2p(to, Msg::Round2(round2_package)))
+            .feed(Outgoing::p2p(to, Msg::Round2(round2_package)))
             .await
             .map_err(IoError::send_message)?;
+        unflushed += 1;
+        if unflushed == in_flight {
+            outgoings.flush().await.map_err(IoError::send_message)?;
+            unflushed = 0;
+        }
         tracer.msg_sent();
     }
+    if unflushed > 0 {
+        outgoings.flush().await.map_err(IoError::send_message)?;
+    }
     drop(span);
 
     gadget_sdk::debug!("Waiting for round 2 packages");
@@ -194,6 +311,7 @@ mod tests {
 
     use super::*;
     use blueprint_test_utils::setup_log;
+    use gadget_sdk::futures::{Future, Sink, TryStreamExt};
     use proptest::prelude::*;
     use rand::rngs::StdRng;
     use rand::SeedableRng;
@@ -213,6 +331,9 @@ mod tests {
     enum TestCase {
         Ed25519(TestInputArgs),
         Secp256k1(TestInputArgs),
+        Ristretto255(TestInputArgs),
+        P256(TestInputArgs),
+        RedJubjub(TestInputArgs),
     }
 
     #[proptest(async = "tokio", cases = 20, fork = true)]
@@ -223,7 +344,200 @@ mod tests {
             TestCase::Secp256k1(args) => {
                 run_keygen::<frost_secp256k1::Secp256K1Sha256>(args).await?
             }
+            TestCase::Ristretto255(args) => {
+                run_keygen::<frost_ristretto255::Ristretto255Sha512>(args).await?
+            }
+            TestCase::P256(args) => run_keygen::<frost_p256::P256Sha256>(args).await?,
+            TestCase::RedJubjub(args) => {
+                run_keygen::<crate::redjubjub::JubjubBlake2b512>(args).await?
+            }
+        }
+    }
+
+    #[test]
+    fn beacon_reproduces_round1_packages() {
+        type C = frost_ed25519::Ed25519Sha512;
+        let me = *IdentifierWrapper::<C>::try_from(0).unwrap();
+        let round1_package = |beacon: &[u8]| {
+            let rng = &mut beacon_rng(&mut StdRng::seed_from_u64(7), beacon);
+            dkg::part1::<C, _>(me, 3, 2, rng).unwrap().1
+        };
+        assert_eq!(round1_package(b"round 1"), round1_package(b"round 1"));
+        assert_ne!(round1_package(b"round 1"), round1_package(b"round 2"));
+    }
+
+    #[tokio::test]
+    async fn equivocating_party_is_detected() {
+        type C = frost_secp256k1::Secp256K1Sha256;
+        const N: u16 = 3;
+        const T: u16 = 2;
+        const EQUIVOCATOR: u16 = 2;
+
+        // The equivocator sends party 1 another round 1 package than the one the others get.
+        let rng = &mut StdRng::seed_from_u64(964);
+        let me = *IdentifierWrapper::<C>::try_from(EQUIVOCATOR).unwrap();
+        let (_, other_package) = dkg::part1::<C, _>(me, N, T, rng).unwrap();
+
+        let mut simulation = Simulation::<Msg<C>>::new();
+        let parties = (0..N).map(|_| simulation.add_party()).collect::<Vec<_>>();
+        let mut tasks = vec![];
+        for (i, party) in (0..N).zip(parties) {
+            let (incomings, outgoings) = party.into_party().delivery.split();
+            let other_package = other_package.clone();
+            let incomings = incomings.map_ok(move |mut incoming| {
+                if i == 1 && incoming.sender == EQUIVOCATOR {
+                    if let Msg::Round1(_) = incoming.msg {
+                        incoming.msg = Msg::Round1(other_package.clone());
+                    }
+                }
+                incoming
+            });
+            let party = MpcParty::connected((incomings, outgoings));
+            tasks.push(tokio::spawn(async move {
+                let rng = &mut StdRng::seed_from_u64(u64::from(i + 1));
+                run::<_, C, _>(rng, T, N, i, BroadcastCheck::Echo, None, party, None).await
+            }));
+        }
+        let equivocator = tasks.pop().unwrap();
+        for task in tasks {
+            let err = task.await.unwrap().unwrap_err();
+            assert!(
+                matches!(
+                    err.0,
+                    Reason::Aborted(KeygenAborted::Equivocation { party: EQUIVOCATOR })
+                ),
+                "{err}"
+            );
+        }
+        // The equivocator waits for the round 2 packages the others never send.
+        equivocator.abort();
+    }
+
+    #[tokio::test]
+    async fn p256_key_is_sec1_encoded() {
+        type C = frost_p256::P256Sha256;
+        const N: u16 = 3;
+        const T: u16 = 2;
+
+        let mut simulation = Simulation::<Msg<C>>::new();
+        let parties = (0..N).map(|_| simulation.add_party()).collect::<Vec<_>>();
+        let mut tasks = vec![];
+        for (i, party) in (0..N).zip(parties) {
+            tasks.push(tokio::spawn(async move {
+                let rng = &mut StdRng::seed_from_u64(u64::from(i + 1));
+                run::<_, C, _>(rng, T, N, i, BroadcastCheck::Echo, None, party, None).await
+            }));
+        }
+        for task in tasks {
+            let (_, pubkey_pkg) = task.await.unwrap().unwrap();
+            let key = pubkey_pkg.verifying_key().serialize().unwrap();
+            // The compressed SEC1 point the P-256 verifiers expect.
+            let point = p256::PublicKey::from_sec1_bytes(&key).unwrap();
+            assert_eq!(key.len(), 33);
+            assert_eq!(
+                p256::EncodedPoint::from(point).compress().as_bytes(),
+                key.as_slice()
+            );
+        }
+    }
+
+    /// A sink taking `delay` to flush, as a network waiting for its messages to be sent,
+    /// counting its flushes.
+    struct SlowFlush<S> {
+        inner: S,
+        delay: std::time::Duration,
+        sleep: Option<std::pin::Pin<Box<tokio::time::Sleep>>>,
+        flushes: std::sync::Arc<std::sync::atomic::AtomicUsize>,
+    }
+
+    impl<S: Sink<T> + Unpin, T> Sink<T> for SlowFlush<S> {
+        type Error = S::Error;
+
+        fn poll_ready(
+            mut self: std::pin::Pin<&mut Self>,
+            cx: &mut std::task::Context<'_>,
+        ) -> std::task::Poll<Result<(), Self::Error>> {
+            std::pin::Pin::new(&mut self.inner).poll_ready(cx)
+        }
+
+        fn start_send(mut self: std::pin::Pin<&mut Self>, item: T) -> Result<(), Self::Error> {
+            std::pin::Pin::new(&mut self.inner).start_send(item)
+        }
+
+        fn poll_flush(
+            mut self: std::pin::Pin<&mut Self>,
+            cx: &mut std::task::Context<'_>,
+        ) -> std::task::Poll<Result<(), Self::Error>> {
+            let delay = self.delay;
+            let sleep = self
+                .sleep
+                .get_or_insert_with(|| Box::pin(tokio::time::sleep(delay)));
+            std::task::ready!(sleep.as_mut().poll(cx));
+            self.sleep = None;
+            self.flushes
+                .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+            std::pin::Pin::new(&mut self.inner).poll_flush(cx)
+        }
+
+        fn poll_close(
+            mut self: std::pin::Pin<&mut Self>,
+            cx: &mut std::task::Context<'_>,
+        ) -> std::task::Poll<Result<(), Self::Error>> {
+            std::pin::Pin::new(&mut self.inner).poll_close(cx)
+        }
+    }
+
+    /// Run a keygen among parties whose flushes are slow, returning how long it took and how
+    /// many times each party flushed.
+    async fn slow_network_keygen(
+        round2_concurrency: Option<NonZeroUsize>,
+    ) -> (std::time::Duration, usize) {
+        type C = frost_ed25519::Ed25519Sha512;
+        const N: u16 = 6;
+        const T: u16 = 4;
+        let mut simulation = Simulation::<Msg<C>>::new();
+        let parties = (0..N).map(|_| simulation.add_party()).collect::<Vec<_>>();
+        let flushes = std::sync::Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let started = std::time::Instant::now();
+        let mut tasks = vec![];
+        for (i, party) in (0..N).zip(parties) {
+            let (incomings, outgoings) = party.into_party().delivery.split();
+            let outgoings = SlowFlush {
+                inner: outgoings,
+                delay: std::time::Duration::from_millis(100),
+                sleep: None,
+                flushes: flushes.clone(),
+            };
+            let party = MpcParty::connected((incomings, outgoings));
+            tasks.push(tokio::spawn(async move {
+                let rng = &mut StdRng::seed_from_u64(u64::from(i + 1));
+                let unchecked = BroadcastCheck::Unchecked;
+                run::<_, C, _>(rng, T, N, i, unchecked, round2_concurrency, party, None).await
+            }));
         }
+        let mut verifying_keys = vec![];
+        for task in tasks {
+            let (_, pub_key_pkg) = task.await.unwrap().unwrap();
+            verifying_keys.push(*pub_key_pkg.verifying_key());
+        }
+        // Every party received its round 2 packages and got the same key.
+        assert!(verifying_keys.windows(2).all(|keys| keys[0] == keys[1]));
+        let flushes = flushes.load(std::sync::atomic::Ordering::Relaxed);
+        (started.elapsed(), flushes / usize::from(N))
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn round2_packages_are_sent_concurrently() {
+        let (sequential, flushes) = slow_network_keygen(NonZeroUsize::new(1)).await;
+        // The round 1 broadcast, then each of the 5 round 2 packages.
+        assert_eq!(flushes, 6);
+        let (concurrent, flushes) = slow_network_keygen(None).await;
+        // The round 1 broadcast, then all the round 2 packages at once.
+        assert_eq!(flushes, 2);
+        assert!(
+            concurrent < sequential,
+            "concurrent {concurrent:?}, sequential {sequential:?}"
+        );
     }
 
     async fn run_keygen<C>(args: &TestInputArgs) -> Result<(), TestCaseError>
@@ -237,13 +551,25 @@ mod tests {
 
         eprintln!("Running a {} {t}-out-of-{n} Keygen", C::ID);
         let mut simulation = Simulation::<Msg<C>>::new();
+        // Connect every party before any of them starts sending, otherwise the parties that
+        // join late miss the first messages.
+        let parties = (0..n).map(|_| simulation.add_party()).collect::<Vec<_>>();
         let mut tasks = vec![];
-        for i in 0..n {
-            let party = simulation.add_party();
+        for (i, party) in (0..n).zip(parties) {
             let output = tokio::spawn(async move {
                 let rng = &mut StdRng::seed_from_u64(u64::from(i + 1));
                 let mut tracer = PerfProfiler::new();
-                let output = run(rng, t, n, i, party, Some(tracer.borrow_mut())).await?;
+                let output = run(
+                    rng,
+                    t,
+                    n,
+                    i,
+                    BroadcastCheck::Unchecked,
+                    None,
+                    party,
+                    Some(tracer.borrow_mut()),
+                )
+                .await?;
                 let report = tracer.get_report().unwrap();
                 eprintln!("Party {} report: {}\n", i, report);
                 Result::<_, Error<C>>::Ok(output)
@@ -1,5 +1,24 @@
+//! The round-based FROST protocols.
+//!
+//! The messages are routed to their rounds by the [`RoundsRouter`] of `round_based`, which has
+//! no buffering to configure: a message received ahead of its round is kept in the store of that
+//! round until the round completes, whatever the order the messages arrive in. Each round stores at most one message per other party, so a run holds at most
+//! `n - 1` messages per round of the protocol, e.g. the `n - 1` secret shares of the second round
+//! of the keygen, and a burst of out-of-order messages is never dropped.
+//!
+//! [`RoundsRouter`]: round_based::rounds_router::RoundsRouter
+/// Agreement Protocol Rounds
+pub mod agreement;
 /// FROST Keygen Protocol Rounds
 pub mod keygen;
+/// Signing Outcome Protocol Rounds
+pub mod outcome;
+/// Shared Randomness Protocol Rounds
+pub mod randomness;
+/// Proactive Refresh Protocol Rounds
+pub mod refresh;
+/// Batch Signing Resume Protocol Rounds
+pub mod resume;
 /// FROST Signing Protocol Rounds
 pub mod sign;
 /// Traces progress of protocol execution
@@ -19,6 +38,7 @@ use std::convert::Infallible;
 use frost_core::{Ciphersuite, Identifier};
 use round_based::rounds_router::simple_store;
 use round_based::rounds_router::{errors as router_error, CompleteRoundError};
+use round_based::{Incoming, MessageType, Stream, StreamExt};
 pub use std_error::StdError;
 pub type BoxedError = Box<dyn StdError + Send + Sync>;
 
@@ -31,6 +51,8 @@ pub enum IoError {
     ReceiveMessage(#[cfg_attr(feature = "std", source)] BoxedError),
     /// got eof while recieving messages
     ReceiveMessageEof,
+    /// the network of this node is shut down
+    NetworkShutdown,
     /// route received message (possibly malicious behavior): {0} ({0:?})
     RouteReceivedError(
         #[cfg_attr(feature = "std", source)]
@@ -40,6 +62,10 @@ pub enum IoError {
 
 impl IoError {
     pub fn send_message<E: StdError + Send + Sync + 'static>(err: E) -> Self {
+        #[cfg(feature = "std")]
+        if crate::codec::is_shutdown(&err) {
+            return Self::NetworkShutdown;
+        }
         Self::SendMessage(Box::new(err))
     }
 
@@ -47,6 +73,12 @@ impl IoError {
         err: CompleteRoundError<simple_store::RoundInputError, E>,
     ) -> Self {
         match err {
+            #[cfg(feature = "std")]
+            CompleteRoundError::Io(router_error::IoError::Io(e))
+                if crate::codec::is_shutdown(&e) =>
+            {
+                Self::NetworkShutdown
+            }
             CompleteRoundError::Io(router_error::IoError::Io(e)) => {
                 Self::ReceiveMessage(Box::new(e))
             }
@@ -82,8 +114,129 @@ macro_rules! impl_from {
 }
 
 pub(crate) use impl_from;
+
+/// A message of another party that could not be decoded.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MalformedMessage {
+    /// The party that sent the message.
+    pub sender: u16,
+    /// The round the message was sent in.
+    pub round: u16,
+}
+
+/// An error receiving a message, telling the malformed messages of a party apart from the
+/// failures of the delivery itself.
+pub trait Attributable: StdError {
+    /// The malformed message behind this error, if any.
+    fn malformed(&self) -> Option<MalformedMessage>;
+}
+
+impl Attributable for crate::codec::Error {
+    fn malformed(&self) -> Option<MalformedMessage> {
+        match self {
+            crate::codec::Error::Decode { sender, round, .. } => Some(MalformedMessage {
+                sender: *sender,
+                round: *round,
+            }),
+            _ => None,
+        }
+    }
+}
+
+impl Attributable for gadget_sdk::Error {
+    fn malformed(&self) -> Option<MalformedMessage> {
+        None
+    }
+}
+
+#[cfg(test)]
+impl Attributable for tokio_stream::wrappers::errors::BroadcastStreamRecvError {
+    fn malformed(&self) -> Option<MalformedMessage> {
+        None
+    }
+}
+
+/// Replace the malformed messages of `round` in `incomings` by `placeholder`, so the round
+/// completes without them and the protocol blames their senders when checking the round.
+pub(crate) fn replace_malformed<M, E, S>(
+    incomings: S,
+    round: u16,
+    placeholder: M,
+) -> impl Stream<Item = Result<Incoming<M>, E>> + Unpin
+where
+    M: Clone,
+    E: Attributable,
+    S: Stream<Item = Result<Incoming<M>, E>> + Unpin,
+{
+    incomings.map(
+        move |incoming| match incoming.as_ref().map_err(E::malformed) {
+            Err(Some(malformed)) if malformed.round == round => {
+                tracing::warn!(from = %malformed.sender, round, "Received a malformed message");
+                Ok(Incoming {
+                    // Out of the range of the delivery ids, so it never collides with a real message.
+                    id: u64::MAX - u64::from(malformed.sender),
+                    sender: malformed.sender,
+                    msg_type: MessageType::Broadcast,
+                    msg: placeholder.clone(),
+                })
+            }
+            _ => incoming,
+        },
+    )
+}
+
+/// The 0-based index of a party, as numbered by `round_based` and in the signer sets.
+///
+/// FROST numbers the parties from 1 instead: the [`Identifier`] of the party of index `i` is the
+/// scalar `i + 1`. This is the only place the two are converted, with
+/// [`PartyIndex::to_identifier`] and [`PartyIndex::from_identifier`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct PartyIndex(pub u16);
+
+impl PartyIndex {
+    /// The FROST identifier of the party, the scalar `index + 1`.
+    ///
+    /// There are at most `u16::MAX` parties, so the index `u16::MAX` has no identifier.
+    pub fn to_identifier<C: Ciphersuite>(self) -> Result<Identifier<C>, frost_core::Error<C>> {
+        let value = self
+            .0
+            .checked_add(1)
+            .ok_or(frost_core::Error::InvalidMaxSigners)?;
+        Identifier::try_from(value)
+    }
+
+    /// The index of the party of FROST identifier `id`, if it is the identifier of a party, i.e.
+    /// a scalar from 1 to `u16::MAX`.
+    pub fn from_identifier<C: Ciphersuite>(id: &Identifier<C>) -> Option<Self> {
+        let bytes =
+            <<C::Group as frost_core::Group>::Field as frost_core::Field>::little_endian_serialize(
+                &id.to_scalar(),
+            );
+        let bytes = bytes.as_ref();
+        tracing::trace!("Identifier bytes: 0x{}", hex::encode(bytes));
+        if bytes[2..].iter().any(|b| *b != 0) {
+            return None;
+        }
+        u16::from_le_bytes([bytes[0], bytes[1]])
+            .checked_sub(1)
+            .map(PartyIndex)
+    }
+}
+
+impl From<u16> for PartyIndex {
+    fn from(index: u16) -> Self {
+        PartyIndex(index)
+    }
+}
+
+impl From<PartyIndex> for u16 {
+    fn from(index: PartyIndex) -> Self {
+        index.0
+    }
+}
+
 /// A wrapper around an identifier that can be converted back and forth between
-/// `Identifier` and `u16`.
+/// `Identifier` and the `u16` index of its party, see [`PartyIndex`].
 #[cfg_attr(feature = "std", derive(serde::Serialize, serde::Deserialize))]
 pub struct IdentifierWrapper<C: Ciphersuite>(pub Identifier<C>);
 
@@ -141,16 +294,11 @@ impl<C: Ciphersuite> IdentifierWrapper<C> {
         Self::try_from(i).expect("u16 is always valid")
     }
 
-    /// Get the inner `Identifier` as a `u16`.
+    /// Get the index of the party of the inner `Identifier`, see [`PartyIndex::from_identifier`].
+    ///
+    /// The identifiers of no party, above `u16::MAX`, are all read as the index `u16::MAX`.
     pub fn as_u16(&self) -> u16 {
-        let bytes =
-            <<C::Group as frost_core::Group>::Field as frost_core::Field>::little_endian_serialize(
-                &self.0.to_scalar(),
-            )
-            .as_ref()
-            .to_vec();
-        tracing::trace!("Identifier bytes: 0x{}", hex::encode(&bytes));
-        u16::from_le_bytes([bytes[0], bytes[1]]).saturating_sub(1)
+        PartyIndex::from_identifier(&self.0).map_or(u16::MAX, u16::from)
     }
 }
 
@@ -158,7 +306,15 @@ impl<C: Ciphersuite> TryFrom<u16> for IdentifierWrapper<C> {
     type Error = frost_core::Error<C>;
 
     fn try_from(value: u16) -> Result<Self, Self::Error> {
-        Identifier::try_from(value + 1).map(IdentifierWrapper)
+        PartyIndex(value).to_identifier().map(IdentifierWrapper)
+    }
+}
+
+impl<C: Ciphersuite> TryFrom<PartyIndex> for IdentifierWrapper<C> {
+    type Error = frost_core::Error<C>;
+
+    fn try_from(index: PartyIndex) -> Result<Self, Self::Error> {
+        index.to_identifier().map(IdentifierWrapper)
     }
 }
 
@@ -195,4 +351,35 @@ mod tests {
         let wrapper = IdentifierWrapper(Identifier::<MockCiphersuite>::try_from(2u16).unwrap());
         assert_eq!(wrapper.as_u16(), 1);
     }
+
+    #[test]
+    fn party_index_boundaries() {
+        type C = MockCiphersuite;
+        let n = 5u16;
+        for index in [0, n - 1] {
+            let id = PartyIndex(index).to_identifier::<C>().unwrap();
+            assert_eq!(id, Identifier::try_from(index + 1).unwrap());
+            assert_eq!(PartyIndex::from_identifier(&id), Some(PartyIndex(index)));
+        }
+        // The last index with an identifier, and the one past it.
+        let last = PartyIndex(u16::MAX - 1).to_identifier::<C>().unwrap();
+        assert_eq!(last, Identifier::try_from(u16::MAX).unwrap());
+        assert_eq!(
+            PartyIndex::from_identifier(&last),
+            Some(PartyIndex(u16::MAX - 1))
+        );
+        assert!(PartyIndex(u16::MAX).to_identifier::<C>().is_err());
+        assert!(IdentifierWrapper::<C>::try_from(u16::MAX).is_err());
+        // An identifier derived from a name is no party index.
+        let derived = Identifier::<C>::derive(b"alice").unwrap();
+        assert_eq!(PartyIndex::from_identifier(&derived), None);
+    }
+
+    #[test]
+    fn p256_identifiers_round_trip() {
+        type C = frost_p256::P256Sha256;
+        for index in [0, 1, 255, 256, u16::MAX - 1] {
+            assert_eq!(IdentifierWrapper::<C>::new(index).as_u16(), index);
+        }
+    }
 }
@@ -0,0 +1,100 @@
+use std::collections::BTreeSet;
+
+use round_based::{Delivery, Mpc, MpcParty, Outgoing, ProtocolMessage, SinkExt, StreamExt};
+use serde::{Deserialize, Serialize};
+
+use crate::rounds::IoError;
+
+/// Protocol message
+#[derive(Clone, Debug, PartialEq, ProtocolMessage, Serialize, Deserialize)]
+pub enum Msg {
+    /// The serialized signatures the sender produced, or `None` if its signing failed
+    Outcome(Option<Vec<Vec<u8>>>),
+}
+
+/// Outcome protocol error
+#[derive(Debug, displaydoc::Display)]
+#[cfg_attr(feature = "std", derive(thiserror::Error))]
+#[displaydoc("outcome protocol is failed to complete: {0}")]
+pub struct Error(#[cfg_attr(feature = "std", source)] pub Reason);
+
+/// Outcome protocol abort reason
+#[derive(Debug, displaydoc::Display)]
+#[cfg_attr(feature = "std", derive(thiserror::Error))]
+pub enum Reason {
+    /// IO error: {0}
+    IoError(#[cfg_attr(feature = "std", source)] super::IoError),
+}
+
+impl Error {
+    /// Whether the protocol failed because the network of this node is shut down.
+    pub fn is_network_shutdown(&self) -> bool {
+        matches!(self.0, Reason::IoError(super::IoError::NetworkShutdown))
+    }
+}
+
+super::impl_from! {
+    impl From for Error {
+        err: Reason => Error(err),
+        err: super::IoError => Error(Reason::IoError(err)),
+    }
+}
+
+/// Learn how a signing ended on the `signers`, this party being `i`.
+///
+/// A signer broadcasts the serialized signatures it produced, `signed`, or that it failed.
+/// A signer that produced them returns them right away, the others wait for the outcome of the
+/// other signers: the first signatures that pass `verifies` are returned, and `None` once every
+/// other signer reported a failure, or signatures that do not verify. A signature is only
+/// accepted once it verifies, so a signer cannot make the others believe a failed signing
+/// succeeded, nor the other way around as long as one of the signers is honest.
+///
+/// A signer that never reports, e.g. because it crashed, leaves the others waiting, so the
+/// protocol is meant to run under a timeout. A malformed report is skipped, like a missing one.
+#[tracing::instrument(target = "gadget", name = "outcome", skip_all, fields(i), err)]
+pub async fn run<M>(
+    i: u16,
+    signers: &BTreeSet<u16>,
+    signed: Option<Vec<Vec<u8>>>,
+    verifies: impl Fn(&[Vec<u8>]) -> bool,
+    party: M,
+) -> Result<Option<Vec<Vec<u8>>>, Error>
+where
+    M: Mpc<ProtocolMessage = Msg>,
+{
+    let MpcParty { delivery, .. } = party.into_party();
+    let (mut incomings, mut outgoings) = delivery.split();
+    if signers.contains(&i) {
+        outgoings
+            .send(Outgoing::broadcast(Msg::Outcome(signed.clone())))
+            .await
+            .map_err(IoError::send_message)?;
+    }
+    if signed.is_some() {
+        return Ok(signed);
+    }
+    let mut failed = BTreeSet::new();
+    while failed.len() < signers.iter().filter(|j| **j != i).count() {
+        let incoming = match incomings.next().await.ok_or(IoError::ReceiveMessageEof)? {
+            Ok(incoming) => incoming,
+            #[cfg(feature = "std")]
+            Err(e) if crate::codec::is_shutdown(&e) => return Err(IoError::NetworkShutdown.into()),
+            // A malformed outcome proves nothing, the other signers may still report theirs.
+            Err(e) => {
+                tracing::warn!(error = %e, "Skipping an outcome that cannot be received");
+                continue;
+            }
+        };
+        if incoming.sender == i || !signers.contains(&incoming.sender) {
+            continue;
+        }
+        let Msg::Outcome(outcome) = incoming.msg;
+        match outcome {
+            Some(signatures) if verifies(&signatures) => return Ok(Some(signatures)),
+            _ => {
+                failed.insert(incoming.sender);
+            }
+        }
+    }
+    Ok(None)
+}
@@ -0,0 +1,244 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::time::Duration;
+
+use gadget_sdk::random::rand;
+use gadget_sdk::subxt_core::ext::sp_core::keccak_256;
+use round_based::{Delivery, Mpc, MpcParty, Outgoing, ProtocolMessage, SinkExt, StreamExt};
+use serde::{Deserialize, Serialize};
+
+use crate::rounds::IoError;
+
+/// Domain separator of the commitments to the contributions.
+const COMMITMENT_DST: &[u8] = b"frost-randomness-commitment";
+/// Domain separator of the randomness the contributions are combined into.
+const RANDOMNESS_DST: &[u8] = b"frost-randomness";
+
+/// Protocol message
+#[derive(Clone, Debug, PartialEq, ProtocolMessage, Serialize, Deserialize)]
+pub enum Msg {
+    /// The commitment of the sender to its contribution
+    Commit([u8; 32]),
+    /// The contribution of the sender, once it received the commitments of every contributor
+    Reveal(Contribution),
+}
+
+/// The random contribution of a party.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Contribution(pub [u8; 32]);
+
+/// Randomness protocol error
+#[derive(Debug, displaydoc::Display)]
+#[cfg_attr(feature = "std", derive(thiserror::Error))]
+#[displaydoc("randomness protocol is failed to complete: {0}")]
+pub struct Error(#[cfg_attr(feature = "std", source)] pub Reason);
+
+/// Randomness protocol abort reason
+#[derive(Debug, displaydoc::Display)]
+#[cfg_attr(feature = "std", derive(thiserror::Error))]
+pub enum Reason {
+    /// Party {party} revealed a contribution it did not commit to
+    InvalidReveal { party: u16 },
+    /// The parties {missing:?} did not reveal their contribution in time
+    Timeout { missing: Vec<u16> },
+    /// IO error: {0}
+    IoError(#[cfg_attr(feature = "std", source)] super::IoError),
+}
+
+impl Error {
+    /// Whether the protocol failed because the network of this node is shut down.
+    pub fn is_network_shutdown(&self) -> bool {
+        matches!(self.0, Reason::IoError(super::IoError::NetworkShutdown))
+    }
+}
+
+super::impl_from! {
+    impl From for Error {
+        err: Reason => Error(err),
+        err: super::IoError => Error(Reason::IoError(err)),
+    }
+}
+
+/// Draw 32 bytes of randomness none of the `contributors` can predict nor choose alone, this
+/// party being `i`.
+///
+/// Every contributor commits to a random contribution, and reveals it once it received the
+/// commitment of every other contributor, so no contribution can depend on another one. The
+/// randomness is the hash of all the contributions, unpredictable as long as one of the
+/// contributors is honest. The other parties only listen, and learn the same randomness.
+///
+/// A contributor that never reveals fails the protocol with [`Reason::Timeout`] once `timeout`
+/// elapsed, on every party. One that commits to different contributions towards different
+/// parties leaves them with different randomness, so the output is meant to be checked by the
+/// protocol using it, e.g. by the aggregation of a signature. A malformed message is skipped,
+/// like a missing one.
+#[tracing::instrument(target = "gadget", name = "randomness", skip_all, fields(i), err)]
+pub async fn run<R, M>(
+    rng: &mut R,
+    i: u16,
+    contributors: &BTreeSet<u16>,
+    timeout: Duration,
+    party: M,
+) -> Result<[u8; 32], Error>
+where
+    R: rand::RngCore + rand::CryptoRng,
+    M: Mpc<ProtocolMessage = Msg>,
+{
+    let deadline = tokio::time::Instant::now() + timeout;
+    let MpcParty { delivery, .. } = party.into_party();
+    let (mut incomings, mut outgoings) = delivery.split();
+    let mut commitments = BTreeMap::new();
+    let mut contributions = BTreeMap::new();
+    let contribution = contributors.contains(&i).then(|| {
+        let mut contribution = [0u8; 32];
+        rng.fill_bytes(&mut contribution);
+        contribution
+    });
+    if let Some(contribution) = contribution {
+        let commitment = commitment(i, &contribution);
+        commitments.insert(i, commitment);
+        outgoings
+            .send(Outgoing::broadcast(Msg::Commit(commitment)))
+            .await
+            .map_err(IoError::send_message)?;
+    }
+    loop {
+        if let Some(contribution) = contribution {
+            if commitments.len() == contributors.len() && !contributions.contains_key(&i) {
+                contributions.insert(i, contribution);
+                outgoings
+                    .send(Outgoing::broadcast(Msg::Reveal(Contribution(contribution))))
+                    .await
+                    .map_err(IoError::send_message)?;
+            }
+        }
+        if commitments.len() == contributors.len() && contributions.len() == contributors.len() {
+            break;
+        }
+        let Ok(incoming) = tokio::time::timeout_at(deadline, incomings.next()).await else {
+            let missing = contributors
+                .iter()
+                .filter(|j| **j != i && !contributions.contains_key(*j))
+                .copied()
+                .collect::<Vec<_>>();
+            tracing::warn!(?missing, "Some contributions did not arrive in time");
+            return Err(Reason::Timeout { missing }.into());
+        };
+        let incoming = match incoming.ok_or(IoError::ReceiveMessageEof)? {
+            Ok(incoming) => incoming,
+            #[cfg(feature = "std")]
+            Err(e) if crate::codec::is_shutdown(&e) => return Err(IoError::NetworkShutdown.into()),
+            Err(e) => {
+                tracing::warn!(error = %e, "Skipping a contribution that cannot be received");
+                continue;
+            }
+        };
+        if incoming.sender == i || !contributors.contains(&incoming.sender) {
+            continue;
+        }
+        // Only the first message of each kind counts, a contributor cannot take it back.
+        match incoming.msg {
+            Msg::Commit(commitment) => {
+                commitments.entry(incoming.sender).or_insert(commitment);
+            }
+            Msg::Reveal(Contribution(contribution)) => {
+                contributions.entry(incoming.sender).or_insert(contribution);
+            }
+        }
+    }
+
+    // A reveal may arrive ahead of its commitment, so they are only checked once all in.
+    let mut m = RANDOMNESS_DST.to_vec();
+    for (j, contribution) in &contributions {
+        if commitments.get(j) != Some(&commitment(*j, contribution)) {
+            return Err(Reason::InvalidReveal { party: *j }.into());
+        }
+        m.extend(contribution);
+    }
+    Ok(keccak_256(&m))
+}
+
+/// The commitment of the party `i` to its `contribution`, bound to the party so that another
+/// one cannot replay it as its own.
+fn commitment(i: u16, contribution: &[u8; 32]) -> [u8; 32] {
+    let mut m = COMMITMENT_DST.to_vec();
+    m.extend(i.to_be_bytes());
+    m.extend(contribution);
+    keccak_256(&m)
+}
+
+#[cfg(test)]
+mod tests {
+    use gadget_sdk::random::rand::rngs::StdRng;
+    use gadget_sdk::random::SeedableRng;
+    use round_based::simulation::Simulation;
+
+    use super::*;
+
+    const TIMEOUT: Duration = Duration::from_secs(10);
+
+    async fn draw(n: u16, contributors: &BTreeSet<u16>, seed: u64) -> Vec<[u8; 32]> {
+        let mut simulation = Simulation::<Msg>::new();
+        let parties = (0..n).map(|_| simulation.add_party()).collect::<Vec<_>>();
+        let tasks = parties
+            .into_iter()
+            .zip(0u16..)
+            .map(|(party, i)| {
+                let contributors = contributors.clone();
+                tokio::spawn(async move {
+                    let rng = &mut StdRng::seed_from_u64(seed + u64::from(i));
+                    run(rng, i, &contributors, TIMEOUT, party).await
+                })
+            })
+            .collect::<Vec<_>>();
+        let mut outputs = vec![];
+        for task in tasks {
+            outputs.push(task.await.unwrap().unwrap());
+        }
+        outputs
+    }
+
+    #[tokio::test]
+    async fn every_party_draws_the_same_randomness() {
+        let contributors = [0, 2].into_iter().collect();
+        let outputs = draw(3, &contributors, 1).await;
+        assert!(outputs.windows(2).all(|w| w[0] == w[1]));
+        // Other contributions, other randomness.
+        let other = draw(3, &contributors, 100).await;
+        assert_ne!(other[0], outputs[0]);
+    }
+
+    #[tokio::test]
+    async fn contributor_that_never_reveals_times_out() {
+        let contributors = BTreeSet::from([0, 1, 2]);
+        let mut simulation = Simulation::<Msg>::new();
+        let parties = (0..3).map(|_| simulation.add_party()).collect::<Vec<_>>();
+        let mut parties = parties.into_iter();
+        let honest = (0u16..2)
+            .zip(parties.by_ref())
+            .map(|(i, party)| {
+                let contributors = contributors.clone();
+                tokio::spawn(async move {
+                    let rng = &mut StdRng::seed_from_u64(u64::from(i));
+                    let timeout = Duration::from_millis(200);
+                    run(rng, i, &contributors, timeout, party).await
+                })
+            })
+            .collect::<Vec<_>>();
+
+        // The last contributor commits, then never reveals.
+        let MpcParty { delivery, .. } = parties.next().unwrap().into_party();
+        let (_incomings, mut outgoings) = delivery.split();
+        outgoings
+            .send(Outgoing::broadcast(Msg::Commit([7; 32])))
+            .await
+            .unwrap();
+
+        for task in honest {
+            let result = task.await.unwrap();
+            assert!(
+                matches!(&result, Err(Error(Reason::Timeout { missing })) if missing == &[2]),
+                "{result:?}"
+            );
+        }
+    }
+}
@@ -0,0 +1,404 @@
+use std::collections::BTreeMap;
+
+use frost_core::keys::{KeyPackage, PublicKeyPackage, SigningShare, VerifyingShare};
+use frost_core::{Ciphersuite, Element, Field, Group, Scalar};
+use gadget_sdk::random::rand;
+use gadget_sdk::subxt_core::ext::sp_core::keccak_256;
+use round_based::rounds_router::simple_store::RoundInput;
+use round_based::rounds_router::RoundsRouter;
+use round_based::{Delivery, Mpc, MpcParty, Outgoing, ProtocolMessage, SinkExt};
+use serde::{Deserialize, Serialize};
+
+use crate::rounds::{IoError, PartyIndex};
+
+use super::trace::Tracer;
+
+/// Protocol message
+#[derive(Clone, Debug, PartialEq, ProtocolMessage, Serialize, Deserialize)]
+#[serde(bound = "C: Ciphersuite")]
+pub enum Msg<C: Ciphersuite> {
+    /// The commitments of the sender, and the generations of the key it holds
+    Commitment(Commitment),
+    /// The evaluation of the refresh polynomial of the sender at the identifier of the recipient
+    Share(SigningShare<C>),
+    /// The digest of the refreshed public key package computed by the sender
+    Confirmation([u8; 32]),
+}
+
+/// The first message of a party, see [`run`].
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Commitment {
+    /// The serialized commitments to the non-constant coefficients of the refresh polynomial of
+    /// the sender.
+    pub coefficients: Vec<Vec<u8>>,
+    /// The digests of the generations of the key the sender holds, newest first, see
+    /// [`generation_digest`].
+    pub generations: Vec<[u8; 32]>,
+}
+
+/// The key packages refreshed by [`run`].
+pub struct Refreshed<C: Ciphersuite> {
+    /// The index of the generation refreshed in the ones given to [`run`], the newest one every
+    /// party holds.
+    pub base: usize,
+    /// The refreshed key package of this party.
+    pub key_pkg: KeyPackage<C>,
+    /// The refreshed public key package.
+    pub pub_key_pkg: PublicKeyPackage<C>,
+}
+
+/// The digest of the generation of the key of public key package `pub_key_pkg`, i.e. of its
+/// verifying shares.
+pub fn generation_digest<C: Ciphersuite>(
+    pub_key_pkg: &PublicKeyPackage<C>,
+) -> Result<[u8; 32], frost_core::Error<C>> {
+    Ok(keccak_256(&pub_key_pkg.serialize()?))
+}
+
+/// Refresh protocol error
+#[derive(Debug, displaydoc::Display)]
+#[cfg_attr(feature = "std", derive(thiserror::Error))]
+#[displaydoc("refresh protocol is failed to complete: {0}")]
+pub struct Error<C: Ciphersuite>(#[cfg_attr(feature = "std", source)] pub Reason<C>);
+
+/// Refresh protocol abort reason
+#[derive(Debug, displaydoc::Display)]
+#[cfg_attr(feature = "std", derive(thiserror::Error))]
+pub enum Reason<C: Ciphersuite> {
+    /// Protocol was maliciously aborted by another party: {0}
+    Aborted(#[cfg_attr(feature = "std", source)] RefreshAborted<C>),
+    /// IO error: {0}
+    IoError(#[cfg_attr(feature = "std", source)] super::IoError),
+    /// Bug occurred: {0}
+    Bug(Bug),
+}
+
+impl<C: Ciphersuite> Error<C> {
+    /// Whether the refresh failed because the network of this node is shut down, rather than
+    /// because of a fault of the protocol or of another party.
+    pub fn is_network_shutdown(&self) -> bool {
+        matches!(self.0, Reason::IoError(super::IoError::NetworkShutdown))
+    }
+}
+
+super::impl_from! {
+    impl<C: Ciphersuite> From for Error<C> {
+        err: RefreshAborted<C> => Error(Reason::Aborted(err)),
+        err: super::IoError => Error(Reason::IoError(err)),
+        err: Bug => Error(Reason::Bug(err)),
+    }
+}
+
+/// Error indicating that protocol was aborted by malicious party
+#[derive(Debug, displaydoc::Display)]
+#[cfg_attr(feature = "std", derive(thiserror::Error))]
+pub enum RefreshAborted<C: Ciphersuite> {
+    /// A party has aborted the protocol: {0}
+    Frost(frost_core::Error<C>),
+    /// Party {party} sent a malformed commitment
+    InvalidCommitment { party: u16 },
+    /// Party {party} sent a share that does not match its commitment
+    InvalidShare { party: u16 },
+    /// Party {party} refreshed the key to other verifying shares
+    Inconsistent { party: u16 },
+    /// Party {party} holds none of the generations of the key of this party
+    NoCommonGeneration { party: u16 },
+}
+
+#[derive(Debug, displaydoc::Display)]
+#[cfg_attr(feature = "std", derive(thiserror::Error))]
+pub enum Bug {
+    /// No generation of the key to refresh
+    NoGeneration,
+    /// Invalid party index, must be the index of the identifier of the key package
+    InvalidPartyIndex,
+    /// The verifying shares are not those of the parties 1..=n
+    InvalidVerifyingShares,
+}
+
+/// Run the proactive refresh of the shares of a key among all the `n` parties holding one.
+///
+/// Every party adds to its signing share the evaluations at its identifier of a random
+/// polynomial of degree `t - 1` from each party, all of them with a zero constant term: the
+/// shares change, so the shares leaked before the refresh cannot be combined with the ones
+/// leaked after it, but they still interpolate to the same secret, and the group verifying key
+/// is unchanged. The commitments to the polynomials let each party check the evaluations it
+/// receives, and update the verifying shares of the others. A last round checks that every
+/// party got the same refreshed verifying shares.
+///
+/// The last round does not make the refresh atomic: a party that misses a confirmation, or
+/// times out, does not take the refreshed share while the others do. So the parties keep
+/// the generations of the key before the last refresh, `generations` holding the key packages
+/// of this party newest first, and announce their digests with their commitments: the
+/// generation refreshed is the newest one every party holds, so the parties left behind by a
+/// refresh catch up with the others in the next one.
+///
+/// The party of index `i` must be the one of the identifier of the key packages, and the
+/// identifiers of the verifying shares of the public key packages those of the parties `0..n`.
+#[tracing::instrument(
+    target = "gadget",
+    name = "refresh",
+    skip(rng, generations, tracer, party),
+    err
+)]
+pub async fn run<R, C, M>(
+    rng: &mut R,
+    generations: &[(KeyPackage<C>, PublicKeyPackage<C>)],
+    i: u16,
+    party: M,
+    mut tracer: Option<&mut dyn Tracer>,
+) -> Result<Refreshed<C>, Error<C>>
+where
+    R: rand::RngCore + rand::CryptoRng,
+    C: Ciphersuite + Send,
+    M: Mpc<ProtocolMessage = Msg<C>>,
+    <<C as Ciphersuite>::Group as Group>::Element: Send,
+    <<<C as Ciphersuite>::Group as Group>::Field as frost_core::Field>::Scalar: Send,
+{
+    let (newest_key_pkg, newest) = generations.first().ok_or(Bug::NoGeneration)?;
+    let n =
+        u16::try_from(newest.verifying_shares().len()).map_err(|_| Bug::InvalidVerifyingShares)?;
+    let identifiers = (0..n)
+        .map(|j| PartyIndex(j).to_identifier::<C>())
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|_| Bug::InvalidVerifyingShares)?;
+    let me = *identifiers
+        .get(usize::from(i))
+        .ok_or(Bug::InvalidPartyIndex)?;
+    for (key_pkg, pub_key_pkg) in generations {
+        if pub_key_pkg.verifying_shares().len() != identifiers.len()
+            || identifiers
+                .iter()
+                .any(|id| !pub_key_pkg.verifying_shares().contains_key(id))
+        {
+            return Err(Bug::InvalidVerifyingShares.into());
+        }
+        if *key_pkg.identifier() != me {
+            return Err(Bug::InvalidPartyIndex.into());
+        }
+    }
+    let digests = generations
+        .iter()
+        .map(|(_, pub_key_pkg)| generation_digest(pub_key_pkg))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(RefreshAborted::Frost)?;
+    let t = *newest_key_pkg.min_signers();
+    tracer.protocol_begins();
+    gadget_sdk::debug!("Refresh protocol started");
+
+    tracer.stage("Setup networking");
+    let MpcParty { delivery, .. } = party.into_party();
+    let (incomings, mut outgoings) = delivery.split();
+    let mut router = RoundsRouter::<Msg<C>>::builder();
+    let round1 = router.add_round(RoundInput::<Commitment>::broadcast(i, n));
+    let round2 = router.add_round(RoundInput::<SigningShare<C>>::p2p(i, n));
+    let round3 = router.add_round(RoundInput::<[u8; 32]>::broadcast(i, n));
+    let mut rounds = router.listen(incomings);
+
+    // Round 1
+    tracer.round_begins();
+    tracer.stage("Commit to the refresh polynomial");
+    let coefficients = (1..t)
+        .map(|_| <<C::Group as Group>::Field as Field>::random(rng))
+        .collect::<Vec<_>>();
+    let commitment = coefficients
+        .iter()
+        .map(|a| <C::Group as Group>::generator() * *a)
+        .collect::<Vec<_>>();
+    let serialized = commitment
+        .iter()
+        .map(serialize_element::<C>)
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(RefreshAborted::Frost)?;
+    tracer.send_msg();
+    outgoings
+        .send(Outgoing::broadcast(Msg::Commitment(Commitment {
+            coefficients: serialized,
+            generations: digests.clone(),
+        })))
+        .await
+        .map_err(IoError::send_message)?;
+    tracer.msg_sent();
+    tracer.receive_msgs();
+    let others = rounds
+        .complete(round1)
+        .await
+        .map_err(IoError::receive_message)?;
+    tracer.msgs_received();
+    let mut commitments = BTreeMap::from([(i, commitment)]);
+    let mut held = Vec::new();
+    for (
+        j,
+        _,
+        Commitment {
+            coefficients: serialized,
+            generations,
+        },
+    ) in others.into_iter_indexed()
+    {
+        held.push((j, generations));
+        let commitment = serialized
+            .iter()
+            .map(|bytes| deserialize_element::<C>(bytes))
+            .collect::<Result<Vec<_>, _>>()
+            .ok()
+            .filter(|commitment| commitment.len() == coefficients.len())
+            .ok_or(RefreshAborted::InvalidCommitment { party: j })?;
+        commitments.insert(j, commitment);
+    }
+    let base = digests
+        .iter()
+        .position(|digest| held.iter().all(|(_, theirs)| theirs.contains(digest)));
+    let Some(base) = base else {
+        // A party holding none of the generations of this one, or else the first one missing
+        // the newest of them.
+        let (party, _) = held
+            .iter()
+            .find(|(_, theirs)| !digests.iter().any(|digest| theirs.contains(digest)))
+            .or_else(|| {
+                held.iter()
+                    .find(|(_, theirs)| !theirs.contains(&digests[0]))
+            })
+            .ok_or(Bug::NoGeneration)?;
+        return Err(RefreshAborted::NoCommonGeneration { party: *party }.into());
+    };
+    if base > 0 {
+        gadget_sdk::warn!(
+            base,
+            "Refreshing an older generation of the key, held by every party"
+        );
+    }
+    let (key_pkg, pub_key_pkg) = &generations[base];
+
+    // Round 2
+    tracer.round_begins();
+    tracer.stage("Send the evaluations of the refresh polynomial");
+    for (j, id) in (0..n).zip(&identifiers) {
+        if j == i {
+            continue;
+        }
+        let share = evaluate::<C>(&coefficients, id.to_scalar());
+        let share = SigningShare::deserialize(
+            <<C::Group as Group>::Field as Field>::serialize(&share).as_ref(),
+        )
+        .map_err(RefreshAborted::Frost)?;
+        tracer.send_msg();
+        outgoings
+            .feed(Outgoing::p2p(j, Msg::Share(share)))
+            .await
+            .map_err(IoError::send_message)?;
+        tracer.msg_sent();
+    }
+    outgoings.flush().await.map_err(IoError::send_message)?;
+    tracer.receive_msgs();
+    let shares = rounds
+        .complete(round2)
+        .await
+        .map_err(IoError::receive_message)?;
+    tracer.msgs_received();
+
+    tracer.named_round_begins("Refresh (Offline)");
+    tracer.stage("Refresh the key packages");
+    let x = me.to_scalar();
+    let mut signing_share = deserialize_scalar::<C>(&key_pkg.signing_share().serialize())
+        .map_err(RefreshAborted::Frost)?
+        + evaluate::<C>(&coefficients, x);
+    for (j, _, share) in shares.into_iter_indexed() {
+        let share = deserialize_scalar::<C>(&share.serialize()).map_err(RefreshAborted::Frost)?;
+        if <C::Group as Group>::generator() * share != evaluate_commitment::<C>(&commitments[&j], x)
+        {
+            return Err(RefreshAborted::InvalidShare { party: j }.into());
+        }
+        signing_share = signing_share + share;
+    }
+    let signing_share = SigningShare::deserialize(
+        <<C::Group as Group>::Field as Field>::serialize(&signing_share).as_ref(),
+    )
+    .map_err(RefreshAborted::Frost)?;
+    let verifying_shares = identifiers
+        .iter()
+        .map(|id| {
+            let share = &pub_key_pkg.verifying_shares()[id];
+            let mut element = deserialize_element::<C>(&share.serialize()?)?;
+            for commitment in commitments.values() {
+                element = element + evaluate_commitment::<C>(commitment, id.to_scalar());
+            }
+            let share = VerifyingShare::deserialize(&serialize_element::<C>(&element)?)?;
+            Ok((*id, share))
+        })
+        .collect::<Result<BTreeMap<_, _>, frost_core::Error<C>>>()
+        .map_err(RefreshAborted::Frost)?;
+    let verifying_share = verifying_shares[&me];
+    let verifying_key = *pub_key_pkg.verifying_key();
+    let refreshed_pub_key_pkg = PublicKeyPackage::new(verifying_shares, verifying_key);
+    let refreshed_key_pkg = KeyPackage::new(me, signing_share, verifying_share, verifying_key, t);
+
+    // Round 3
+    tracer.round_begins();
+    tracer.stage("Confirm the refreshed verifying shares");
+    let digest = generation_digest(&refreshed_pub_key_pkg).map_err(RefreshAborted::Frost)?;
+    tracer.send_msg();
+    outgoings
+        .send(Outgoing::broadcast(Msg::Confirmation(digest)))
+        .await
+        .map_err(IoError::send_message)?;
+    tracer.msg_sent();
+    tracer.receive_msgs();
+    let confirmations = rounds
+        .complete(round3)
+        .await
+        .map_err(IoError::receive_message)?;
+    tracer.msgs_received();
+    if let Some((party, _, _)) = confirmations
+        .into_iter_indexed()
+        .find(|(_, _, theirs)| *theirs != digest)
+    {
+        return Err(RefreshAborted::Inconsistent { party }.into());
+    }
+    gadget_sdk::debug!("Refresh protocol completed");
+    tracer.protocol_ends();
+    Ok(Refreshed {
+        base,
+        key_pkg: refreshed_key_pkg,
+        pub_key_pkg: refreshed_pub_key_pkg,
+    })
+}
+
+/// The polynomial of non-constant `coefficients`, and a zero constant term, at `x`.
+fn evaluate<C: Ciphersuite>(coefficients: &[Scalar<C>], x: Scalar<C>) -> Scalar<C> {
+    coefficients
+        .iter()
+        .rev()
+        .fold(<<C::Group as Group>::Field as Field>::zero(), |acc, a| {
+            (acc + *a) * x
+        })
+}
+
+/// The commitment to the evaluation at `x` of the polynomial of `commitment`, see [`evaluate`].
+fn evaluate_commitment<C: Ciphersuite>(commitment: &[Element<C>], x: Scalar<C>) -> Element<C> {
+    commitment
+        .iter()
+        .rev()
+        .fold(<C::Group as Group>::identity(), |acc, c| (acc + *c) * x)
+}
+
+fn deserialize_scalar<C: Ciphersuite>(bytes: &[u8]) -> Result<Scalar<C>, frost_core::Error<C>> {
+    let serialization =
+        <<C::Group as Group>::Field as Field>::Serialization::try_from(bytes.to_vec())
+            .map_err(|_| frost_core::Error::DeserializationError)?;
+    Ok(<<C::Group as Group>::Field as Field>::deserialize(
+        &serialization,
+    )?)
+}
+
+fn deserialize_element<C: Ciphersuite>(bytes: &[u8]) -> Result<Element<C>, frost_core::Error<C>> {
+    let serialization = <C::Group as Group>::Serialization::try_from(bytes.to_vec())
+        .map_err(|_| frost_core::Error::DeserializationError)?;
+    Ok(<C::Group as Group>::deserialize(&serialization)?)
+}
+
+fn serialize_element<C: Ciphersuite>(
+    element: &Element<C>,
+) -> Result<Vec<u8>, frost_core::Error<C>> {
+    Ok(<C::Group as Group>::serialize(element)?.as_ref().to_vec())
+}
@@ -0,0 +1,117 @@
+use std::collections::BTreeSet;
+
+use round_based::rounds_router::simple_store::RoundInput;
+use round_based::rounds_router::RoundsRouter;
+use round_based::{Delivery, Mpc, MpcParty, Outgoing, ProtocolMessage, SinkExt};
+use serde::{Deserialize, Serialize};
+
+use crate::rounds::IoError;
+
+use super::trace::Tracer;
+
+/// Protocol message
+#[derive(Clone, Debug, PartialEq, ProtocolMessage, Serialize, Deserialize)]
+pub enum Msg {
+    /// The indices of the chunks the sender holds the signatures of
+    Held(Vec<u64>),
+}
+
+/// Resume protocol error
+#[derive(Debug, displaydoc::Display)]
+#[cfg_attr(feature = "std", derive(thiserror::Error))]
+#[displaydoc("resume protocol is failed to complete: {0}")]
+pub struct Error(#[cfg_attr(feature = "std", source)] pub Reason);
+
+/// Resume protocol abort reason
+#[derive(Debug, displaydoc::Display)]
+#[cfg_attr(feature = "std", derive(thiserror::Error))]
+pub enum Reason {
+    /// IO error: {0}
+    IoError(#[cfg_attr(feature = "std", source)] super::IoError),
+    /// Bug occurred: {0}
+    Bug(Bug),
+}
+
+impl Error {
+    /// Whether the protocol failed because the network of this node is shut down.
+    pub fn is_network_shutdown(&self) -> bool {
+        matches!(self.0, Reason::IoError(super::IoError::NetworkShutdown))
+    }
+}
+
+super::impl_from! {
+    impl From for Error {
+        err: Reason => Error(err),
+        err: super::IoError => Error(Reason::IoError(err)),
+        err: Bug => Error(Reason::Bug(err)),
+    }
+}
+
+#[derive(Debug, displaydoc::Display)]
+#[cfg_attr(feature = "std", derive(thiserror::Error))]
+pub enum Bug {
+    /// Invalid party index, must be in range 0..n
+    InvalidPartyIndex,
+}
+
+/// Agree among `n` parties on the chunk of a batch to resume its signing from, this one
+/// holding the signatures of the chunks `held`.
+///
+/// Every party broadcasts the chunks it holds and waits for those of all the others. The
+/// chunks held by every party are skipped, so the signing resumes from the lowest chunk that
+/// some party does not hold, and every later chunk is signed again by all of them.
+///
+/// A party sending different chunks to different parties leads them to resume from different
+/// chunks, which then run in different sessions and time out, like a party withholding its
+/// messages.
+#[tracing::instrument(target = "gadget", name = "resume", skip(held, tracer, party), err)]
+pub async fn run<M>(
+    held: &BTreeSet<u64>,
+    n: u16,
+    i: u16,
+    party: M,
+    mut tracer: Option<&mut dyn Tracer>,
+) -> Result<u64, Error>
+where
+    M: Mpc<ProtocolMessage = Msg>,
+{
+    if i >= n {
+        return Err(Bug::InvalidPartyIndex.into());
+    }
+    tracer.protocol_begins();
+    tracer.stage("Setup networking");
+    let MpcParty { delivery, .. } = party.into_party();
+    let (incomings, mut outgoings) = delivery.split();
+    let mut router = RoundsRouter::<Msg>::builder();
+    let round = router.add_round(RoundInput::<Vec<u64>>::broadcast(i, n));
+    let mut rounds = router.listen(incomings);
+
+    tracer.round_begins();
+    tracer.stage("Broadcast the held chunks");
+    tracer.send_msg();
+    outgoings
+        .send(Outgoing::broadcast(Msg::Held(
+            held.iter().copied().collect(),
+        )))
+        .await
+        .map_err(IoError::send_message)?;
+    tracer.msg_sent();
+    tracer.receive_msgs();
+    let others = rounds
+        .complete(round)
+        .await
+        .map_err(IoError::receive_message)?;
+    tracer.msgs_received();
+    let others = others
+        .into_iter_indexed()
+        .map(|(_, _, chunks)| chunks.into_iter().collect::<BTreeSet<_>>())
+        .collect::<Vec<_>>();
+    // The first chunk this party lacks is at most the number of chunks it holds.
+    let resume = held
+        .iter()
+        .zip(0..)
+        .find(|(chunk, k)| **chunk != *k || others.iter().any(|chunks| !chunks.contains(k)))
+        .map_or(held.len() as u64, |(_, k)| k);
+    tracer.protocol_ends();
+    Ok(resume)
+}
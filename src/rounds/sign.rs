@@ -1,18 +1,23 @@
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::Arc;
+use std::time::Duration;
 
 use frost_core::keys::{KeyPackage, PublicKeyPackage};
-use frost_core::round1::{commit, SigningCommitments};
+use frost_core::round1::{commit, SigningCommitments, SigningNonces};
 use frost_core::round2::{sign, SignatureShare};
 use frost_core::{
-    aggregate, verify_signature_share, Ciphersuite, Group, Identifier, Signature, SigningPackage,
+    aggregate, verify_signature_share, Ciphersuite, Element, Field, Group, Identifier, Signature,
+    SigningPackage, VerifyingKey,
 };
+use gadget_sdk::parking_lot::Mutex;
 use gadget_sdk::random::rand;
+use gadget_sdk::subxt_core::ext::sp_core::keccak_256;
 use round_based::rounds_router::simple_store::RoundInput;
 use round_based::rounds_router::RoundsRouter;
-use round_based::{Delivery, Mpc, MpcParty, Outgoing, ProtocolMessage, SinkExt};
+use round_based::{Delivery, Mpc, MpcParty, Outgoing, ProtocolMessage, SinkExt, StreamExt};
 use serde::{Deserialize, Serialize};
 
-use crate::rounds::{IdentifierWrapper, IoError};
+use crate::rounds::{replace_malformed, Attributable, IdentifierWrapper, IoError, PartyIndex};
 
 use super::trace::Tracer;
 
@@ -24,6 +29,173 @@ pub enum Msg<C: Ciphersuite> {
     Round1(SigningCommitments<C>),
     /// Round 2
     Round2(SignatureShare<C>),
+    /// Sent before the round 2 package, the hash of the signing package, see
+    /// [`signing_package_hash`]
+    PackageHash([u8; 32]),
+}
+
+/// Batch signing protocol message
+#[derive(Clone, Debug, PartialEq, ProtocolMessage, Serialize, Deserialize)]
+#[serde(bound = "C: Ciphersuite")]
+pub enum BatchMsg<C: Ciphersuite> {
+    /// Round 1, one commitment per message
+    Round1(Vec<SigningCommitments<C>>),
+    /// Round 2, one signature share per message
+    Round2(Vec<SignatureShare<C>>),
+}
+
+/// What a signer does with a signature share it cannot decode.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MalformedShares {
+    /// Fail the protocol with an IO error.
+    #[default]
+    Abort,
+    /// Keep collecting the other shares, then blame the sender with
+    /// [`SigningAborted::InvalidSignatureShare`], or [`SigningAborted::InvalidBatch`] when
+    /// batch signing.
+    Blame,
+}
+
+/// What a signer does with a signer missing from the public key package, e.g. a party of a
+/// stale operator set, whose share cannot be verified.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UnknownSigners {
+    /// Fail the protocol with [`Bug::VerifyingShareNotFound`].
+    #[default]
+    Abort,
+    /// Verify the other shares, then blame the unknown signers with the invalid shares in
+    /// [`SigningAborted::InvalidSignatureShare`].
+    Blame,
+}
+
+/// What a signer does when the commitments of some signers do not arrive, e.g. when a network
+/// partition splits the signers so that neither side has the threshold.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MissingCommitments {
+    /// Wait for the commitments of all the signers, until the job times out.
+    #[default]
+    Wait,
+    /// Fail with [`Reason::QuorumNotReached`] if fewer commitments than the threshold, this
+    /// signer's included, arrived that long after round 1 started. With enough of them, keep
+    /// waiting for the others.
+    FailFast(Duration),
+}
+
+/// The source of the nonces of a signer, e.g. a hardware module keeping them out of the
+/// process, see [`run`].
+///
+/// The nonces of a signing are generated by [`NonceProvider::commit`], and used up by
+/// [`NonceProvider::sign`] along with the commitments it returned, so they never have to leave
+/// the provider. The nonces of a signing aborted before its round 2 are never used, and are
+/// dropped with [`NonceProvider::discard`].
+pub trait NonceProvider<C: Ciphersuite>: Send + Sync {
+    /// Generate fresh nonces for the signing share of `key_pkg`, and return their commitments.
+    fn commit(
+        &self,
+        key_pkg: &KeyPackage<C>,
+    ) -> Result<SigningCommitments<C>, frost_core::Error<C>>;
+
+    /// Sign `signing_pkg` with the share of `key_pkg` and the nonces committed to with
+    /// `commitments`, which must never be used again.
+    fn sign(
+        &self,
+        signing_pkg: &SigningPackage<C>,
+        commitments: &SigningCommitments<C>,
+        key_pkg: &KeyPackage<C>,
+    ) -> Result<SignatureShare<C>, frost_core::Error<C>>;
+
+    /// Drop the nonces committed to with `commitments`, if they were not used up by
+    /// [`NonceProvider::sign`]. Called whenever a signing ends, whether it succeeded or not.
+    fn discard(&self, _commitments: &SigningCommitments<C>) {}
+}
+
+/// A [`NonceProvider`] generating the nonces in memory with the OS randomness, as the
+/// signers do without a provider.
+pub struct LocalNonces<C: Ciphersuite> {
+    nonces: Mutex<BTreeMap<Vec<u8>, SigningNonces<C>>>,
+}
+
+impl<C: Ciphersuite> Default for LocalNonces<C> {
+    fn default() -> Self {
+        Self {
+            nonces: Default::default(),
+        }
+    }
+}
+
+impl<C> NonceProvider<C> for LocalNonces<C>
+where
+    C: Ciphersuite + Send + Sync,
+    <<C as Ciphersuite>::Group as Group>::Element: Send + Sync,
+    <<<C as Ciphersuite>::Group as Group>::Field as frost_core::Field>::Scalar: Send + Sync,
+{
+    fn commit(
+        &self,
+        key_pkg: &KeyPackage<C>,
+    ) -> Result<SigningCommitments<C>, frost_core::Error<C>> {
+        let (nonces, commitments) = commit::<C, _>(key_pkg.signing_share(), &mut rand::rngs::OsRng);
+        self.nonces.lock().insert(commitments.serialize()?, nonces);
+        Ok(commitments)
+    }
+
+    fn sign(
+        &self,
+        signing_pkg: &SigningPackage<C>,
+        commitments: &SigningCommitments<C>,
+        key_pkg: &KeyPackage<C>,
+    ) -> Result<SignatureShare<C>, frost_core::Error<C>> {
+        let nonces = self
+            .nonces
+            .lock()
+            .remove(&commitments.serialize()?)
+            .ok_or(frost_core::Error::IncorrectCommitment)?;
+        sign::<C>(signing_pkg, &nonces, key_pkg)
+    }
+
+    fn discard(&self, commitments: &SigningCommitments<C>) {
+        if let Ok(key) = commitments.serialize() {
+            self.nonces.lock().remove(&key);
+        }
+    }
+}
+
+/// The nonces of a signer in a running signing.
+enum Nonces<'a, C: Ciphersuite> {
+    /// Generated by the signer itself
+    Local(SigningNonces<C>),
+    /// Kept by a provider, along with their commitments
+    Provided(&'a dyn NonceProvider<C>, SigningCommitments<C>),
+}
+
+impl<C: Ciphersuite> Drop for Nonces<'_, C> {
+    fn drop(&mut self) {
+        if let Nonces::Provided(provider, commitments) = self {
+            provider.discard(commitments);
+        }
+    }
+}
+
+/// Output of the signing protocol
+#[derive(Clone, Copy)]
+pub struct Output<C: Ciphersuite> {
+    /// The aggregated signature
+    pub signature: Signature<C>,
+    /// The aggregate public nonce `R`, i.e. the group commitment of the signing package, see
+    /// [`aggregate_nonce`]
+    pub nonce: Element<C>,
+}
+
+impl<C: Ciphersuite> std::fmt::Debug for Output<C> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let nonce = <C::Group as Group>::serialize(&self.nonce).map(hex::encode);
+        f.debug_struct("Output")
+            .field("signature", &self.signature)
+            .field("nonce", &nonce)
+            .finish()
+    }
 }
 
 /// Signing protocol error
@@ -40,10 +212,34 @@ pub enum Reason<C: Ciphersuite> {
     Aborted(#[cfg_attr(feature = "std", source)] SigningAborted<C>),
     /// IO error: {0}
     IoError(#[cfg_attr(feature = "std", source)] super::IoError),
+    /// Only {received} of the {required} required commitments arrived, the signers may be partitioned
+    QuorumNotReached {
+        /// The commitments received, this signer's included
+        received: u16,
+        /// The threshold
+        required: u16,
+    },
     /// Bug occurred: {0}
     Bug(Bug),
 }
 
+impl<C: Ciphersuite> Error<C> {
+    /// Whether the signing failed because the network of this node is shut down, rather than
+    /// because of a fault of the protocol or of another party.
+    pub fn is_network_shutdown(&self) -> bool {
+        matches!(self.0, Reason::IoError(super::IoError::NetworkShutdown))
+    }
+
+    /// The commitments received and required, if the signing failed because fewer commitments
+    /// than the threshold arrived in time, see [`MissingCommitments::FailFast`].
+    pub fn quorum_not_reached(&self) -> Option<(u16, u16)> {
+        match self.0 {
+            Reason::QuorumNotReached { received, required } => Some((received, required)),
+            _ => None,
+        }
+    }
+}
+
 super::impl_from! {
     impl<C: Ciphersuite> From for Error<C> {
         err: SigningAborted<C> => Error(Reason::Aborted(err)),
@@ -72,6 +268,18 @@ pub enum SigningAborted<C: Ciphersuite> {
         /// parties
         blames: Vec<u16>,
     },
+    /// A party has sent a malformed batch: {blames:?}
+    InvalidBatch {
+        /// Sent a wrong number of commitments or shares, or the same commitment for two
+        /// messages
+        blames: Vec<u16>,
+    },
+    /// The signing package of some parties differs from ours: {blames:?}
+    SigningPackageMismatch {
+        /// Built a signing package from other commitments, or ours is the odd one out when
+        /// all the others are blamed
+        blames: Vec<u16>,
+    },
 }
 
 #[derive(Debug, displaydoc::Display)]
@@ -85,26 +293,72 @@ pub enum Bug {
     VerifyingShareNotFound,
 }
 
+/// Wait for the round 1 packages with `complete`, failing with [`Reason::QuorumNotReached`]
+/// under [`MissingCommitments::FailFast`] if fewer than `t` signers, this one included, are
+/// among the `committed` ones by then.
+async fn within_quorum<C: Ciphersuite, T>(
+    complete: impl std::future::Future<Output = T>,
+    missing: MissingCommitments,
+    committed: &Mutex<BTreeSet<u16>>,
+    t: u16,
+) -> Result<T, Error<C>> {
+    let MissingCommitments::FailFast(deadline) = missing else {
+        return Ok(complete.await);
+    };
+    let mut complete = std::pin::pin!(complete);
+    tokio::select! {
+        output = &mut complete => return Ok(output),
+        _ = tokio::time::sleep(deadline) => {}
+    }
+    // At most `n` signers committed, this one included.
+    let received = committed.lock().len() as u16 + 1;
+    if received < t {
+        tracing::warn!(
+            received,
+            required = t,
+            "Too few commitments arrived, the signers may be partitioned"
+        );
+        return Err(Error(Reason::QuorumNotReached {
+            received,
+            required: t,
+        }));
+    }
+    Ok(complete.await)
+}
+
 /// Run FROST Signing protocol
+///
+/// There is no designated aggregator: every signer broadcasts its signature share and
+/// aggregates the signature itself, so each signer that completes the protocol holds the
+/// signature, and no single signer failing after round 2 keeps the others from it.
+///
+/// The nonces are generated with `rng`, unless a `nonces` provider is given, see
+/// [`NonceProvider`].
 #[tracing::instrument(
     target = "gadget",
     name = "sign",
-    skip(rng, tracer, party, key_pkg, pub_key_pkg, msg),
+    skip(rng, tracer, party, key_pkg, pub_key_pkg, msg, nonces),
     err
 )]
+#[allow(clippy::too_many_arguments)]
 pub async fn run<R, C, M>(
     rng: &mut R,
     key_pkg: &KeyPackage<C>,
     pub_key_pkg: &PublicKeyPackage<C>,
     signer_set: &[u16],
     msg: &[u8],
+    malformed: MalformedShares,
+    unknown: UnknownSigners,
+    missing: MissingCommitments,
+    nonces: Option<&dyn NonceProvider<C>>,
     party: M,
     mut tracer: Option<&mut dyn Tracer>,
-) -> Result<Signature<C>, Error<C>>
+) -> Result<Output<C>, Error<C>>
 where
     R: rand::RngCore + rand::CryptoRng,
     C: Ciphersuite + Send,
     M: Mpc<ProtocolMessage = Msg<C>>,
+    M::ReceiveError: Attributable,
     <<C as Ciphersuite>::Group as Group>::Element: Send,
     <<<C as Ciphersuite>::Group as Group>::Field as frost_core::Field>::Scalar: Send,
 {
@@ -128,15 +382,49 @@ where
     tracer.stage("Setup networking");
     let MpcParty { delivery, .. } = party.into_party();
     let (incomings, mut outgoings) = delivery.split();
+    let committed = Arc::new(Mutex::new(BTreeSet::new()));
+    let incomings = incomings.inspect({
+        let committed = committed.clone();
+        // A transport echoing the broadcasts must not count this signer twice.
+        move |incoming| match incoming {
+            Ok(incoming) if incoming.sender != i && matches!(incoming.msg, Msg::Round1(_)) => {
+                committed.lock().insert(incoming.sender);
+            }
+            _ => {}
+        }
+    });
     let mut router = RoundsRouter::<Msg<C>>::builder();
     let round1 = router.add_round(RoundInput::<SigningCommitments<C>>::broadcast(i, n));
     let round2 = router.add_round(RoundInput::<SignatureShare<C>>::broadcast(i, n));
-    let mut rounds = router.listen(incomings);
+    let package_round = router.add_round(RoundInput::<[u8; 32]>::broadcast(i, n));
+    let mut rounds = match malformed {
+        MalformedShares::Abort => router.listen(incomings.left_stream()),
+        MalformedShares::Blame => {
+            // A zero share never verifies, so its sender ends up blamed with the invalid ones.
+            let zero = <<C::Group as Group>::Field as Field>::serialize(
+                &<<C::Group as Group>::Field as Field>::zero(),
+            );
+            let placeholder = Msg::Round2(
+                SignatureShare::deserialize(zero.as_ref()).map_err(SigningAborted::Frost)?,
+            );
+            let round = placeholder.round();
+            router.listen(replace_malformed(incomings, round, placeholder).right_stream())
+        }
+    };
     // Round 1
     tracing::debug!("Round 1 started");
     tracer.round_begins();
     tracer.stage("Create Signing Commitments");
-    let (signing_nonces, signing_commitments) = commit::<C, _>(key_pkg.signing_share(), rng);
+    let (signing_nonces, signing_commitments) = match nonces {
+        Some(provider) => {
+            let commitments = provider.commit(key_pkg).map_err(SigningAborted::Frost)?;
+            (Nonces::Provided(provider, commitments), commitments)
+        }
+        None => {
+            let (nonces, commitments) = commit::<C, _>(key_pkg.signing_share(), rng);
+            (Nonces::Local(nonces), commitments)
+        }
+    };
     tracer.stage("Broadcast shares");
     tracing::debug!("Broadcasting round 1 package");
     tracer.send_msg();
@@ -147,36 +435,55 @@ where
     tracer.msg_sent();
     tracing::debug!("Waiting for round 1 packages");
     tracer.receive_msgs();
-    let other_packages = rounds
-        .complete(round1)
-        .await
+    let other_packages = within_quorum::<C, _>(rounds.complete(round1), missing, &committed, t)
+        .await?
         .map_err(IoError::receive_message)?;
     tracing::debug!("Received round 1 packages");
     tracer.msgs_received();
-    let all_signing_commitments = other_packages
-        .into_vec_including_me(signing_commitments)
-        .into_iter()
-        .enumerate()
-        .map(|(index, package)| {
-            let party_i = signer_set
-                .get(index)
-                .copied()
-                .ok_or(Bug::InvalidPartyIndex)?;
-            let party =
-                IdentifierWrapper::<C>::try_from(party_i).map_err(|_| Bug::InvalidPartyIndex)?;
-            Result::<_, Error<C>>::Ok((*party, package))
-        })
-        .collect::<Result<BTreeMap<Identifier<C>, _>, _>>()?;
+    let all_signing_commitments = by_identifier(
+        signer_set,
+        other_packages.into_vec_including_me(signing_commitments),
+    )?;
 
     // Round 2
     tracer.round_begins();
     tracing::debug!("Round 2 started");
-    tracer.stage("Create Signature Share");
-
+    tracer.stage("Create Signing Package");
     let signing_pkg = SigningPackage::new(all_signing_commitments, msg);
 
-    let signature_share =
-        sign::<C>(&signing_pkg, &signing_nonces, key_pkg).map_err(SigningAborted::Frost)?;
+    // A dropped or duplicated commitment would make the shares fail to aggregate, so the
+    // signers make sure they all built the same signing package before signing it.
+    tracer.stage("Exchange signing package hash");
+    let package_hash = signing_package_hash(&signing_pkg).map_err(SigningAborted::Frost)?;
+    tracer.send_msg();
+    outgoings
+        .send(Outgoing::broadcast(Msg::PackageHash(package_hash)))
+        .await
+        .map_err(IoError::send_message)?;
+    tracer.msg_sent();
+    tracer.receive_msgs();
+    let other_hashes = rounds
+        .complete(package_round)
+        .await
+        .map_err(IoError::receive_message)?;
+    tracer.msgs_received();
+    let blames = signer_set
+        .iter()
+        .zip(other_hashes.into_vec_including_me(package_hash))
+        .filter(|(_, hash)| *hash != package_hash)
+        .map(|(&j, _)| j)
+        .collect::<Vec<_>>();
+    if !blames.is_empty() {
+        tracing::warn!(?blames, "Signing package mismatch");
+        return Err(SigningAborted::SigningPackageMismatch { blames }.into());
+    }
+
+    tracer.stage("Create Signature Share");
+    let signature_share = match &signing_nonces {
+        Nonces::Local(nonces) => sign::<C>(&signing_pkg, nonces, key_pkg),
+        Nonces::Provided(provider, _) => provider.sign(&signing_pkg, &signing_commitments, key_pkg),
+    }
+    .map_err(SigningAborted::Frost)?;
     tracing::debug!("Broadcasting round 2 package");
     tracer.stage("Broadcast signature share");
     tracer.send_msg();
@@ -195,8 +502,293 @@ where
     tracing::debug!("Received round 2 packages");
     tracer.msgs_received();
 
-    let all_signature_shares = other_packages
-        .into_vec_including_me(signature_share)
+    let all_signature_shares = by_identifier(
+        signer_set,
+        other_packages.into_vec_including_me(signature_share),
+    )?;
+
+    // Verify signature shares
+    tracer.stage("Verify signature shares");
+    let blames = invalid_shares(
+        key_pkg,
+        pub_key_pkg,
+        &signing_pkg,
+        &all_signature_shares,
+        unknown,
+    )?;
+    if !blames.is_empty() {
+        return Err(SigningAborted::InvalidSignatureShare { blames }.into());
+    }
+    tracer.stage("Aggregate signature shares");
+    let signature = aggregate::<C>(&signing_pkg, &all_signature_shares, pub_key_pkg)
+        .map_err(SigningAborted::Frost)?;
+    let nonce =
+        aggregate_nonce(&signing_pkg, key_pkg.verifying_key()).map_err(SigningAborted::Frost)?;
+    // Done
+    tracer.protocol_ends();
+    Ok(Output { signature, nonce })
+}
+
+/// Run FROST Signing protocol over a batch of messages, with a single exchange of commitments
+/// and a single exchange of signature shares for the whole batch.
+///
+/// Every signer commits to fresh nonces for each message in round 1, and each of them is used
+/// for exactly one signature share in round 2, so no nonce is ever reused across the batch.
+///
+/// The nonces are generated with `rng`, unless a `nonces` provider is given, as in [`run`].
+#[tracing::instrument(
+    target = "gadget",
+    name = "batch_sign",
+    skip(rng, tracer, party, key_pkg, pub_key_pkg, msgs, nonces),
+    fields(batch = msgs.len()),
+    err
+)]
+#[allow(clippy::too_many_arguments)]
+pub async fn run_batch<R, C, M>(
+    rng: &mut R,
+    key_pkg: &KeyPackage<C>,
+    pub_key_pkg: &PublicKeyPackage<C>,
+    signer_set: &[u16],
+    msgs: &[Vec<u8>],
+    malformed: MalformedShares,
+    unknown: UnknownSigners,
+    missing: MissingCommitments,
+    nonces: Option<&dyn NonceProvider<C>>,
+    party: M,
+    mut tracer: Option<&mut dyn Tracer>,
+) -> Result<Vec<Signature<C>>, Error<C>>
+where
+    R: rand::RngCore + rand::CryptoRng,
+    C: Ciphersuite + Send,
+    M: Mpc<ProtocolMessage = BatchMsg<C>>,
+    M::ReceiveError: Attributable,
+    <<C as Ciphersuite>::Group as Group>::Element: Send,
+    <<<C as Ciphersuite>::Group as Group>::Field as frost_core::Field>::Scalar: Send,
+{
+    let t = *key_pkg.min_signers();
+    let n = signer_set.len() as u16;
+    if n < t {
+        return Err(Bug::InvalidProtocolParameters.into());
+    }
+
+    let me = IdentifierWrapper(*key_pkg.identifier());
+    let me = me.as_u16();
+    // i is my index in the signer set
+    let i = signer_set
+        .iter()
+        .position(|&x| x == me)
+        .map(|i| i as u16)
+        .ok_or(Bug::InvalidPartyIndex)?;
+
+    tracer.protocol_begins();
+    tracing::debug!("Batch signing protocol started");
+    tracer.stage("Setup networking");
+    let MpcParty { delivery, .. } = party.into_party();
+    let (incomings, mut outgoings) = delivery.split();
+    let committed = Arc::new(Mutex::new(BTreeSet::new()));
+    let incomings = incomings.inspect({
+        let committed = committed.clone();
+        // A transport echoing the broadcasts must not count this signer twice.
+        move |incoming| match incoming {
+            Ok(incoming) if incoming.sender != i && matches!(incoming.msg, BatchMsg::Round1(_)) => {
+                committed.lock().insert(incoming.sender);
+            }
+            _ => {}
+        }
+    });
+    let mut router = RoundsRouter::<BatchMsg<C>>::builder();
+    let round1 = router.add_round(RoundInput::<Vec<SigningCommitments<C>>>::broadcast(i, n));
+    let round2 = router.add_round(RoundInput::<Vec<SignatureShare<C>>>::broadcast(i, n));
+    let mut rounds = match malformed {
+        MalformedShares::Abort => router.listen(incomings.left_stream()),
+        MalformedShares::Blame => {
+            // An empty batch of shares never has the length of the batch, so its sender ends up
+            // blamed with the malformed batches.
+            let placeholder = BatchMsg::Round2(vec![]);
+            let round = placeholder.round();
+            router.listen(replace_malformed(incomings, round, placeholder).right_stream())
+        }
+    };
+    // Round 1
+    tracing::debug!("Round 1 started");
+    tracer.round_begins();
+    tracer.stage("Create Signing Commitments");
+    let (signing_nonces, signing_commitments): (Vec<Nonces<C>>, Vec<_>) = msgs
+        .iter()
+        .map(|_| match nonces {
+            Some(provider) => {
+                let commitments = provider.commit(key_pkg)?;
+                Ok((Nonces::Provided(provider, commitments), commitments))
+            }
+            None => {
+                let (nonces, commitments) = commit::<C, _>(key_pkg.signing_share(), rng);
+                Ok((Nonces::Local(nonces), commitments))
+            }
+        })
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(SigningAborted::Frost)?
+        .into_iter()
+        .unzip();
+    tracer.stage("Broadcast shares");
+    tracing::debug!("Broadcasting round 1 package");
+    tracer.send_msg();
+    outgoings
+        .send(Outgoing::broadcast(BatchMsg::Round1(
+            signing_commitments.clone(),
+        )))
+        .await
+        .map_err(IoError::send_message)?;
+    tracer.msg_sent();
+    tracing::debug!("Waiting for round 1 packages");
+    tracer.receive_msgs();
+    let other_packages = within_quorum::<C, _>(rounds.complete(round1), missing, &committed, t)
+        .await?
+        .map_err(IoError::receive_message)?;
+    tracing::debug!("Received round 1 packages");
+    tracer.msgs_received();
+    let all_signing_commitments = by_identifier(
+        signer_set,
+        other_packages.into_vec_including_me(signing_commitments),
+    )?;
+    let blames = all_signing_commitments
+        .iter()
+        .filter(|(_, commitments)| {
+            let distinct = commitments
+                .iter()
+                .enumerate()
+                .all(|(j, c)| !commitments[..j].contains(c));
+            commitments.len() != msgs.len() || !distinct
+        })
+        .map(|(from, _)| IdentifierWrapper(*from).as_u16())
+        .collect::<Vec<_>>();
+    if !blames.is_empty() {
+        tracing::warn!(?blames, "Received malformed batch commitments");
+        return Err(SigningAborted::InvalidBatch { blames }.into());
+    }
+
+    // Round 2
+    tracer.round_begins();
+    tracing::debug!("Round 2 started");
+    tracer.stage("Create Signature Shares");
+    let signing_pkgs = msgs
+        .iter()
+        .enumerate()
+        .map(|(k, msg)| {
+            let commitments = all_signing_commitments
+                .iter()
+                .map(|(id, commitments)| (*id, commitments[k]))
+                .collect();
+            SigningPackage::new(commitments, msg)
+        })
+        .collect::<Vec<_>>();
+    // The nonces are moved in, so each one signs a single message and is then dropped.
+    let signature_shares = signing_nonces
+        .into_iter()
+        .zip(&signing_pkgs)
+        .map(|(nonces, signing_pkg)| match &nonces {
+            Nonces::Local(nonces) => sign::<C>(signing_pkg, nonces, key_pkg),
+            Nonces::Provided(provider, commitments) => {
+                provider.sign(signing_pkg, commitments, key_pkg)
+            }
+        })
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(SigningAborted::Frost)?;
+    tracing::debug!("Broadcasting round 2 package");
+    tracer.stage("Broadcast signature shares");
+    tracer.send_msg();
+    outgoings
+        .send(Outgoing::broadcast(BatchMsg::Round2(
+            signature_shares.clone(),
+        )))
+        .await
+        .map_err(IoError::send_message)?;
+    tracer.msg_sent();
+
+    tracing::debug!("Waiting for round 2 packages");
+    tracer.receive_msgs();
+    let other_packages = rounds
+        .complete(round2)
+        .await
+        .map_err(IoError::receive_message)?;
+    tracing::debug!("Received round 2 packages");
+    tracer.msgs_received();
+    let all_signature_shares = by_identifier(
+        signer_set,
+        other_packages.into_vec_including_me(signature_shares),
+    )?;
+    let blames = all_signature_shares
+        .iter()
+        .filter(|(_, shares)| shares.len() != msgs.len())
+        .map(|(from, _)| IdentifierWrapper(*from).as_u16())
+        .collect::<Vec<_>>();
+    if !blames.is_empty() {
+        return Err(SigningAborted::InvalidBatch { blames }.into());
+    }
+
+    tracer.stage("Verify and aggregate signature shares");
+    let mut signatures = Vec::with_capacity(msgs.len());
+    for (k, signing_pkg) in signing_pkgs.iter().enumerate() {
+        let shares = all_signature_shares
+            .iter()
+            .map(|(id, shares)| (*id, shares[k]))
+            .collect();
+        let blames = invalid_shares(key_pkg, pub_key_pkg, signing_pkg, &shares, unknown)?;
+        if !blames.is_empty() {
+            return Err(SigningAborted::InvalidSignatureShare { blames }.into());
+        }
+        let signature =
+            aggregate::<C>(signing_pkg, &shares, pub_key_pkg).map_err(SigningAborted::Frost)?;
+        signatures.push(signature);
+    }
+    // Done
+    tracer.protocol_ends();
+    Ok(signatures)
+}
+
+/// The `keccak256` hash of the serialized `signing_pkg`, which the signers compare before
+/// round 2.
+pub fn signing_package_hash<C: Ciphersuite>(
+    signing_pkg: &SigningPackage<C>,
+) -> Result<[u8; 32], frost_core::Error<C>> {
+    Ok(keccak_256(&signing_pkg.serialize()?))
+}
+
+/// The aggregate public nonce `R` of `signing_pkg` under the group key `verifying_key`, the sum
+/// of the hiding commitments and of the binding commitments weighted by their binding factors.
+///
+/// This is the commitment embedded in the aggregated signature, computed from the signing
+/// package alone for the verifiers that need it on its own.
+pub fn aggregate_nonce<C: Ciphersuite>(
+    signing_pkg: &SigningPackage<C>,
+    verifying_key: &VerifyingKey<C>,
+) -> Result<Element<C>, frost_core::Error<C>> {
+    let element = |bytes: Vec<u8>| {
+        let bytes = bytes
+            .try_into()
+            .map_err(|_| frost_core::GroupError::MalformedElement)?;
+        Ok::<_, frost_core::Error<C>>(<C::Group as Group>::deserialize(&bytes)?)
+    };
+    let mut nonce = <C::Group as Group>::identity();
+    for (identifier, preimage) in signing_pkg.binding_factor_preimages(verifying_key, &[])? {
+        let commitments = signing_pkg
+            .signing_commitment(&identifier)
+            .ok_or(frost_core::Error::UnknownIdentifier)?;
+        let hiding = element(commitments.hiding().serialize()?)?;
+        let binding = element(commitments.binding().serialize()?)?;
+        nonce = nonce + hiding + binding * C::H1(&preimage);
+    }
+    Ok(nonce)
+}
+
+/// Key the `packages` of the signers, in the order of `signer_set`, by their identifiers.
+///
+/// The map is ordered, so every signer aggregates the same bytes whatever the order the
+/// packages arrived in.
+fn by_identifier<C: Ciphersuite, T>(
+    signer_set: &[u16],
+    packages: Vec<T>,
+) -> Result<BTreeMap<Identifier<C>, T>, Error<C>> {
+    packages
         .into_iter()
         .enumerate()
         .map(|(index, package)| {
@@ -204,25 +796,41 @@ where
                 .get(index)
                 .copied()
                 .ok_or(Bug::InvalidPartyIndex)?;
-            let party =
-                IdentifierWrapper::<C>::try_from(party_i).map_err(|_| Bug::InvalidPartyIndex)?;
-            Result::<_, Error<C>>::Ok((*party, package))
+            let party = PartyIndex(party_i)
+                .to_identifier()
+                .map_err(|_| Bug::InvalidPartyIndex)?;
+            Ok((party, package))
         })
-        .collect::<Result<BTreeMap<Identifier<C>, _>, _>>()?;
+        .collect()
+}
 
-    // Verify signature shares
-    tracer.stage("Verify signature shares");
+/// The signers whose share in `shares` does not verify against `signing_pkg`, along with those
+/// missing from `pub_key_pkg` if `unknown` blames them.
+fn invalid_shares<C: Ciphersuite>(
+    key_pkg: &KeyPackage<C>,
+    pub_key_pkg: &PublicKeyPackage<C>,
+    signing_pkg: &SigningPackage<C>,
+    shares: &BTreeMap<Identifier<C>, SignatureShare<C>>,
+    unknown: UnknownSigners,
+) -> Result<Vec<u16>, Error<C>> {
     let mut blames = vec![];
-    for (from, share) in all_signature_shares.iter() {
-        let verifying_share = pub_key_pkg
-            .verifying_shares()
-            .get(from)
-            .ok_or(Bug::VerifyingShareNotFound)?;
+    for (from, share) in shares.iter() {
+        let Some(verifying_share) = pub_key_pkg.verifying_shares().get(from) else {
+            match unknown {
+                UnknownSigners::Abort => return Err(Bug::VerifyingShareNotFound.into()),
+                UnknownSigners::Blame => {
+                    let who = IdentifierWrapper(*from).as_u16();
+                    tracing::warn!(from = %who, "Signer not in the public key package");
+                    blames.push(who);
+                    continue;
+                }
+            }
+        };
         let result = verify_signature_share(
             *from,
             verifying_share,
             share,
-            &signing_pkg,
+            signing_pkg,
             key_pkg.verifying_key(),
         );
         if result.is_err() {
@@ -231,15 +839,7 @@ where
             blames.push(who);
         }
     }
-    if !blames.is_empty() {
-        return Err(SigningAborted::InvalidSignatureShare { blames }.into());
-    }
-    tracer.stage("Aggregate signature shares");
-    let signature = aggregate::<C>(&signing_pkg, &all_signature_shares, pub_key_pkg)
-        .map_err(SigningAborted::Frost)?;
-    // Done
-    tracer.protocol_ends();
-    Ok(signature)
+    Ok(blames)
 }
 
 #[cfg(test)]
@@ -249,12 +849,15 @@ mod tests {
     use crate::rounds::trace::PerfProfiler;
 
     use super::*;
+    use crate::codec::{versioned, CodecVersion, Envelope, CODEC_VERSION};
     use blueprint_test_utils::setup_log;
+    use gadget_sdk::futures::TryStreamExt;
     use proptest::prelude::*;
     use rand::rngs::StdRng;
     use rand::seq::IteratorRandom;
     use rand::SeedableRng;
     use round_based::simulation::Simulation;
+    use round_based::Incoming;
     use test_strategy::proptest;
     use test_strategy::Arbitrary;
 
@@ -271,20 +874,765 @@ mod tests {
     enum TestCase {
         Ed25519(TestInputArgs),
         Secp256k1(TestInputArgs),
+        Ristretto255(TestInputArgs),
+        P256(TestInputArgs),
+        RedJubjub(TestInputArgs),
     }
 
     #[proptest(async = "tokio", cases = 20, fork = true)]
     async fn it_works(case: TestCase) {
         setup_log();
         match &case {
-            TestCase::Ed25519(args) => run_signing::<frost_ed25519::Ed25519Sha512>(args).await?,
+            TestCase::Ed25519(args) => {
+                run_signing::<frost_ed25519::Ed25519Sha512>(args, None).await?
+            }
             TestCase::Secp256k1(args) => {
-                run_signing::<frost_secp256k1::Secp256K1Sha256>(args).await?
+                run_signing::<frost_secp256k1::Secp256K1Sha256>(args, None).await?
             }
+            TestCase::Ristretto255(args) => {
+                run_signing::<frost_ristretto255::Ristretto255Sha512>(args, None).await?
+            }
+            TestCase::P256(args) => run_signing::<frost_p256::P256Sha256>(args, None).await?,
+            TestCase::RedJubjub(args) => {
+                run_signing::<crate::redjubjub::JubjubBlake2b512>(args, None).await?
+            }
+        }
+    }
+
+    #[proptest(async = "tokio", cases = 10, fork = true)]
+    async fn derived_key_works(args: TestInputArgs, index: u32) {
+        setup_log();
+        run_signing::<frost_secp256k1::Secp256K1Sha256>(&args, Some(Derivation::Index(index)))
+            .await?
+    }
+
+    #[proptest(async = "tokio", cases = 10, fork = true)]
+    async fn ephemeral_key_works(args: TestInputArgs, randomness: [u8; 32]) {
+        setup_log();
+        run_signing::<frost_secp256k1::Secp256K1Sha256>(
+            &args,
+            Some(Derivation::Ephemeral(randomness)),
+        )
+        .await?
+    }
+
+    #[proptest(async = "tokio", cases = 10, fork = true)]
+    async fn batch_works(args: TestInputArgs, #[strategy(1..5usize)] batch: usize) {
+        setup_log();
+        run_batch_signing::<frost_secp256k1::Secp256K1Sha256>(&args, batch).await?
+    }
+
+    #[proptest(async = "tokio", cases = 10, fork = true)]
+    async fn signatures_are_byte_identical(
+        args: TestInputArgs,
+        #[strategy(proptest::collection::vec(0..30u64, 15))] delays: Vec<u64>,
+    ) {
+        setup_log();
+        type C = frost_secp256k1::Secp256K1Sha256;
+        let keygen_output = run_keygen::<C>(&args).await?;
+        let rng = &mut StdRng::from_seed(args.msg);
+        let signers = keygen_output
+            .into_iter()
+            .choose_multiple(rng, usize::from(args.t));
+        let signer_set = signers.iter().map(|(i, _)| *i).collect::<Vec<_>>();
+
+        let mut simulation = Simulation::<Msg<C>>::new();
+        let parties = signers
+            .iter()
+            .map(|_| simulation.add_party())
+            .collect::<Vec<_>>();
+        let mut tasks = vec![];
+        for (((i, (key_pkg, pub_key_pkg)), party), delay) in
+            signers.into_iter().zip(parties).zip(delays)
+        {
+            let signer_set = signer_set.clone();
+            tasks.push(tokio::spawn(async move {
+                // The parties start at different times, so each one receives the commitments
+                // and the shares in another order.
+                tokio::time::sleep(std::time::Duration::from_millis(delay)).await;
+                let rng = &mut StdRng::seed_from_u64(u64::from(i + 1));
+                let output = run(
+                    rng,
+                    &key_pkg,
+                    &pub_key_pkg,
+                    &signer_set,
+                    &args.msg,
+                    MalformedShares::Abort,
+                    UnknownSigners::Abort,
+                    MissingCommitments::Wait,
+                    None,
+                    party,
+                    None,
+                )
+                .await?;
+                Result::<_, Error<C>>::Ok(output.signature.serialize().unwrap())
+            }));
+        }
+        let mut signatures = std::collections::BTreeSet::new();
+        for task in tasks {
+            signatures.insert(task.await.unwrap()?);
         }
+        prop_assert_eq!(
+            signatures.len(),
+            1,
+            "the signers aggregated different bytes"
+        );
     }
 
-    async fn run_signing<C>(args: &TestInputArgs) -> Result<(), TestCaseError>
+    #[tokio::test]
+    async fn aggregate_nonce_is_the_signature_commitment() {
+        type C = frost_ed25519::Ed25519Sha512;
+        let args = TestInputArgs {
+            n: 3,
+            t: 2,
+            msg: [7; 32],
+        };
+        let keygen_output = run_keygen::<C>(&args).await.unwrap();
+        let signers = keygen_output.into_iter().take(2).collect::<Vec<_>>();
+        let signer_set = signers.iter().map(|(i, _)| *i).collect::<Vec<_>>();
+        let mut simulation = Simulation::<Msg<C>>::new();
+        let parties = signers
+            .iter()
+            .map(|_| simulation.add_party())
+            .collect::<Vec<_>>();
+        let mut tasks = vec![];
+        for ((i, (key_pkg, pub_key_pkg)), party) in signers.into_iter().zip(parties) {
+            let signer_set = signer_set.clone();
+            tasks.push(tokio::spawn(async move {
+                let rng = &mut StdRng::seed_from_u64(u64::from(i + 1));
+                run(
+                    rng,
+                    &key_pkg,
+                    &pub_key_pkg,
+                    &signer_set,
+                    &args.msg,
+                    MalformedShares::Abort,
+                    UnknownSigners::Abort,
+                    MissingCommitments::Wait,
+                    None,
+                    party,
+                    None,
+                )
+                .await
+            }));
+        }
+        for task in tasks {
+            let Output { signature, nonce } = task.await.unwrap().unwrap();
+            let nonce = <<C as Ciphersuite>::Group as Group>::serialize(&nonce).unwrap();
+            let signature = signature.serialize().unwrap();
+            assert_eq!(nonce.as_ref(), &signature[..nonce.as_ref().len()]);
+        }
+    }
+
+    /// A provider keeping its nonces like a hardware module would, counting its calls.
+    struct MockNonceProvider<C: Ciphersuite> {
+        nonces: LocalNonces<C>,
+        commits: std::sync::atomic::AtomicUsize,
+        signs: std::sync::atomic::AtomicUsize,
+    }
+
+    impl<C: Ciphersuite> Default for MockNonceProvider<C> {
+        fn default() -> Self {
+            Self {
+                nonces: Default::default(),
+                commits: Default::default(),
+                signs: Default::default(),
+            }
+        }
+    }
+
+    impl<C: Ciphersuite> NonceProvider<C> for MockNonceProvider<C>
+    where
+        LocalNonces<C>: NonceProvider<C>,
+    {
+        fn commit(
+            &self,
+            key_pkg: &KeyPackage<C>,
+        ) -> Result<SigningCommitments<C>, frost_core::Error<C>> {
+            self.commits
+                .fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            self.nonces.commit(key_pkg)
+        }
+
+        fn sign(
+            &self,
+            signing_pkg: &SigningPackage<C>,
+            commitments: &SigningCommitments<C>,
+            key_pkg: &KeyPackage<C>,
+        ) -> Result<SignatureShare<C>, frost_core::Error<C>> {
+            self.signs.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            self.nonces.sign(signing_pkg, commitments, key_pkg)
+        }
+
+        fn discard(&self, commitments: &SigningCommitments<C>) {
+            self.nonces.discard(commitments)
+        }
+    }
+
+    #[tokio::test]
+    async fn external_nonces_sign() {
+        type C = frost_secp256k1::Secp256K1Sha256;
+        let args = TestInputArgs {
+            n: 3,
+            t: 2,
+            msg: [5; 32],
+        };
+        let keygen_output = run_keygen::<C>(&args).await.unwrap();
+        let signers = keygen_output.into_iter().take(2).collect::<Vec<_>>();
+        let signer_set = signers.iter().map(|(i, _)| *i).collect::<Vec<_>>();
+        // The first signer sources its nonces from the provider, the other one generates them.
+        let provider = std::sync::Arc::new(MockNonceProvider::<C>::default());
+        let mut simulation = Simulation::<Msg<C>>::new();
+        let parties = signers
+            .iter()
+            .map(|_| simulation.add_party())
+            .collect::<Vec<_>>();
+        let mut tasks = vec![];
+        for ((i, (key_pkg, pub_key_pkg)), party) in signers.into_iter().zip(parties) {
+            let signer_set = signer_set.clone();
+            let provider = (i == signer_set[0]).then(|| provider.clone());
+            tasks.push(tokio::spawn(async move {
+                let rng = &mut StdRng::seed_from_u64(u64::from(i + 1));
+                let output = run(
+                    rng,
+                    &key_pkg,
+                    &pub_key_pkg,
+                    &signer_set,
+                    &args.msg,
+                    MalformedShares::Abort,
+                    UnknownSigners::Abort,
+                    MissingCommitments::Wait,
+                    provider.as_deref().map(|p| p as &dyn NonceProvider<C>),
+                    party,
+                    None,
+                )
+                .await?;
+                Result::<_, Error<C>>::Ok((output, pub_key_pkg))
+            }));
+        }
+        for task in tasks {
+            let (output, pub_key_pkg) = task.await.unwrap().unwrap();
+            pub_key_pkg
+                .verifying_key()
+                .verify(&args.msg, &output.signature)
+                .unwrap();
+        }
+        assert_eq!(
+            provider.commits.load(std::sync::atomic::Ordering::SeqCst),
+            1
+        );
+        assert_eq!(provider.signs.load(std::sync::atomic::Ordering::SeqCst), 1);
+        // The nonces were used up by the signing.
+        assert!(provider.nonces.nonces.lock().is_empty());
+    }
+
+    #[tokio::test]
+    async fn external_nonces_sign_batches() {
+        type C = frost_secp256k1::Secp256K1Sha256;
+        let args = TestInputArgs {
+            n: 3,
+            t: 2,
+            msg: [8; 32],
+        };
+        let keygen_output = run_keygen::<C>(&args).await.unwrap();
+        let signers = keygen_output.into_iter().take(2).collect::<Vec<_>>();
+        let signer_set = signers.iter().map(|(i, _)| *i).collect::<Vec<_>>();
+        let msgs = (0..3u8).map(|k| vec![k; 8]).collect::<Vec<_>>();
+        // The first signer sources its nonces from the provider, the other one generates them.
+        let provider = std::sync::Arc::new(MockNonceProvider::<C>::default());
+        let mut simulation = Simulation::<BatchMsg<C>>::new();
+        let parties = signers
+            .iter()
+            .map(|_| simulation.add_party())
+            .collect::<Vec<_>>();
+        let mut tasks = vec![];
+        for ((i, (key_pkg, pub_key_pkg)), party) in signers.into_iter().zip(parties) {
+            let (signer_set, msgs) = (signer_set.clone(), msgs.clone());
+            let provider = (i == signer_set[0]).then(|| provider.clone());
+            tasks.push(tokio::spawn(async move {
+                let rng = &mut StdRng::seed_from_u64(u64::from(i + 1));
+                let signatures = run_batch(
+                    rng,
+                    &key_pkg,
+                    &pub_key_pkg,
+                    &signer_set,
+                    &msgs,
+                    MalformedShares::Abort,
+                    UnknownSigners::Abort,
+                    MissingCommitments::Wait,
+                    provider.as_deref().map(|p| p as &dyn NonceProvider<C>),
+                    party,
+                    None,
+                )
+                .await?;
+                Result::<_, Error<C>>::Ok((signatures, pub_key_pkg))
+            }));
+        }
+        for task in tasks {
+            let (signatures, pub_key_pkg) = task.await.unwrap().unwrap();
+            for (msg, signature) in msgs.iter().zip(&signatures) {
+                pub_key_pkg.verifying_key().verify(msg, signature).unwrap();
+            }
+        }
+        // One nonce per message, all used up.
+        assert_eq!(
+            provider.commits.load(std::sync::atomic::Ordering::SeqCst),
+            msgs.len()
+        );
+        assert_eq!(
+            provider.signs.load(std::sync::atomic::Ordering::SeqCst),
+            msgs.len()
+        );
+        assert!(provider.nonces.nonces.lock().is_empty());
+    }
+
+    #[tokio::test]
+    async fn aborted_signing_discards_external_nonces() {
+        type C = frost_secp256k1::Secp256K1Sha256;
+        let args = TestInputArgs {
+            n: 3,
+            t: 2,
+            msg: [6; 32],
+        };
+        let keygen_output = run_keygen::<C>(&args).await.unwrap();
+        let signers = keygen_output.into_iter().take(2).collect::<Vec<_>>();
+        let signer_set = signers.iter().map(|(i, _)| *i).collect::<Vec<_>>();
+        let provider = MockNonceProvider::<C>::default();
+        let mut simulation = Simulation::<Msg<C>>::new();
+        // The other signer never commits, so the signing fails before round 2.
+        let party = simulation.add_party();
+        let _silent = simulation.add_party();
+        let (key_pkg, pub_key_pkg) = &signers[0].1;
+        let result = run(
+            &mut StdRng::seed_from_u64(1),
+            key_pkg,
+            pub_key_pkg,
+            &signer_set,
+            &args.msg,
+            MalformedShares::Abort,
+            UnknownSigners::Abort,
+            MissingCommitments::FailFast(Duration::from_millis(100)),
+            Some(&provider),
+            party,
+            None,
+        )
+        .await;
+        assert!(result.is_err());
+        assert_eq!(
+            provider.commits.load(std::sync::atomic::Ordering::SeqCst),
+            1
+        );
+        assert_eq!(provider.signs.load(std::sync::atomic::Ordering::SeqCst), 0);
+        assert!(provider.nonces.nonces.lock().is_empty());
+    }
+
+    #[tokio::test]
+    async fn malformed_share_is_blamed() {
+        type C = frost_secp256k1::Secp256K1Sha256;
+        let args = TestInputArgs {
+            n: 3,
+            t: 3,
+            msg: [9; 32],
+        };
+        let keygen_output = run_keygen::<C>(&args).await.unwrap();
+        let signer_set = keygen_output.keys().copied().collect::<Vec<_>>();
+        // The last signer sends a signature share that does not decode.
+        const DEVIATOR: u16 = 2;
+
+        for policy in [MalformedShares::Abort, MalformedShares::Blame] {
+            let mut simulation = Simulation::<Envelope>::new();
+            let parties = signer_set
+                .iter()
+                .map(|_| simulation.add_party())
+                .collect::<Vec<_>>();
+            let mut tasks = vec![];
+            for ((&i, (key_pkg, pub_key_pkg)), party) in keygen_output.iter().zip(parties) {
+                let (key_pkg, pub_key_pkg) = (key_pkg.clone(), pub_key_pkg.clone());
+                let signer_set = signer_set.clone();
+                let (incoming, outgoing) = party.into_party().delivery.split();
+                // The share of the deviator is truncated on its way to the other signers.
+                let incoming = incoming.map_ok(move |mut incoming: Incoming<Envelope>| {
+                    if i != DEVIATOR && incoming.sender == DEVIATOR && incoming.msg.round() == 1 {
+                        let mut payload = incoming.msg.payload().to_vec();
+                        payload.pop();
+                        incoming.msg = Envelope::new(CODEC_VERSION, 1, payload);
+                    }
+                    incoming
+                });
+                let delivery = versioned((incoming, outgoing), CodecVersion::default());
+                tasks.push(tokio::spawn(async move {
+                    let rng = &mut StdRng::seed_from_u64(u64::from(i + 1));
+                    let result = run(
+                        rng,
+                        &key_pkg,
+                        &pub_key_pkg,
+                        &signer_set,
+                        &args.msg,
+                        policy,
+                        UnknownSigners::Abort,
+                        MissingCommitments::Wait,
+                        None,
+                        MpcParty::connected(delivery),
+                        None,
+                    )
+                    .await;
+                    (i, result)
+                }));
+            }
+            for task in tasks {
+                let (i, result) = task.await.unwrap();
+                match (i, policy, result.map_err(|e| e.0)) {
+                    // The deviator reads the honest shares and signs.
+                    (DEVIATOR, _, result) => assert!(result.is_ok(), "{result:?}"),
+                    (_, MalformedShares::Abort, result) => {
+                        assert!(matches!(result, Err(Reason::IoError(_))))
+                    }
+                    (_, MalformedShares::Blame, result) => assert!(matches!(
+                        result,
+                        Err(Reason::Aborted(SigningAborted::InvalidSignatureShare { blames }))
+                            if blames == [DEVIATOR]
+                    )),
+                }
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn unknown_signer_is_blamed() {
+        type C = frost_secp256k1::Secp256K1Sha256;
+        let args = TestInputArgs {
+            n: 3,
+            t: 2,
+            msg: [3; 32],
+        };
+        let keygen_output = run_keygen::<C>(&args).await.unwrap();
+        let signer_set = keygen_output.keys().copied().collect::<Vec<_>>();
+        // The public key package is stale, it misses the last signer.
+        const UNKNOWN: u16 = 2;
+        let (_, pub_key_pkg) = &keygen_output[&UNKNOWN];
+        let unknown = *IdentifierWrapper::<C>::try_from(UNKNOWN).unwrap();
+        let mut verifying_shares = pub_key_pkg.verifying_shares().clone();
+        verifying_shares.remove(&unknown);
+        let stale = PublicKeyPackage::new(verifying_shares, *pub_key_pkg.verifying_key());
+
+        for policy in [UnknownSigners::Abort, UnknownSigners::Blame] {
+            let mut simulation = Simulation::<Msg<C>>::new();
+            let parties = signer_set
+                .iter()
+                .map(|_| simulation.add_party())
+                .collect::<Vec<_>>();
+            let mut tasks = vec![];
+            for ((&i, (key_pkg, _)), party) in keygen_output.iter().zip(parties) {
+                let (key_pkg, stale) = (key_pkg.clone(), stale.clone());
+                let signer_set = signer_set.clone();
+                tasks.push(tokio::spawn(async move {
+                    let rng = &mut StdRng::seed_from_u64(u64::from(i + 1));
+                    run(
+                        rng,
+                        &key_pkg,
+                        &stale,
+                        &signer_set,
+                        &args.msg,
+                        MalformedShares::Abort,
+                        policy,
+                        MissingCommitments::Wait,
+                        None,
+                        party,
+                        None,
+                    )
+                    .await
+                }));
+            }
+            for task in tasks {
+                let result = task.await.unwrap().map_err(|e| e.0);
+                match policy {
+                    UnknownSigners::Abort => assert!(
+                        matches!(result, Err(Reason::Bug(Bug::VerifyingShareNotFound))),
+                        "{result:?}"
+                    ),
+                    UnknownSigners::Blame => assert!(
+                        matches!(
+                            &result,
+                            Err(Reason::Aborted(SigningAborted::InvalidSignatureShare { blames }))
+                                if *blames == [UNKNOWN]
+                        ),
+                        "{result:?}"
+                    ),
+                }
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn divergent_commitments_abort_before_signing() {
+        type C = frost_secp256k1::Secp256K1Sha256;
+        let args = TestInputArgs {
+            n: 3,
+            t: 3,
+            msg: [7; 32],
+        };
+        let keygen_output = run_keygen::<C>(&args).await.unwrap();
+        let signer_set = keygen_output.keys().copied().collect::<Vec<_>>();
+        // The commitment of the first signer reaches the last one replaced with another.
+        const SENDER: u16 = 0;
+        const VICTIM: u16 = 2;
+
+        let mut simulation = Simulation::<Envelope>::new();
+        let parties = signer_set
+            .iter()
+            .map(|_| simulation.add_party())
+            .collect::<Vec<_>>();
+        let mut tasks = vec![];
+        for ((&i, (key_pkg, pub_key_pkg)), party) in keygen_output.iter().zip(parties) {
+            let (key_pkg, pub_key_pkg) = (key_pkg.clone(), pub_key_pkg.clone());
+            let signer_set = signer_set.clone();
+            let (incoming, outgoing) = party.into_party().delivery.split();
+            let signing_share = *key_pkg.signing_share();
+            let incoming = incoming.map_ok(move |mut incoming: Incoming<Envelope>| {
+                if i == VICTIM && incoming.sender == SENDER && incoming.msg.round() == 0 {
+                    let (_, other) = commit::<C, _>(&signing_share, &mut StdRng::seed_from_u64(0));
+                    let payload = bincode::serialize(&Msg::Round1(other)).unwrap();
+                    incoming.msg = Envelope::new(CODEC_VERSION, 0, payload);
+                }
+                incoming
+            });
+            let delivery = versioned((incoming, outgoing), CodecVersion::default());
+            tasks.push(tokio::spawn(async move {
+                let rng = &mut StdRng::seed_from_u64(u64::from(i + 1));
+                let result = run(
+                    rng,
+                    &key_pkg,
+                    &pub_key_pkg,
+                    &signer_set,
+                    &args.msg,
+                    MalformedShares::Abort,
+                    UnknownSigners::Abort,
+                    MissingCommitments::Wait,
+                    None,
+                    MpcParty::connected(delivery),
+                    None,
+                )
+                .await;
+                (i, result)
+            }));
+        }
+        for task in tasks {
+            let (i, result) = task.await.unwrap();
+            let expected = match i {
+                VICTIM => vec![0, 1],
+                _ => vec![VICTIM],
+            };
+            assert!(
+                matches!(
+                    result.as_ref().map_err(|e| &e.0),
+                    Err(Reason::Aborted(SigningAborted::SigningPackageMismatch { blames }))
+                        if *blames == expected
+                ),
+                "{i}: {result:?}"
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn network_shutdown_is_reported() {
+        use gadget_sdk::futures::sink;
+
+        type C = frost_ed25519::Ed25519Sha512;
+        let args = TestInputArgs {
+            n: 3,
+            t: 3,
+            msg: [5; 32],
+        };
+        let keygen_output = run_keygen::<C>(&args).await.unwrap();
+        let signer_set = keygen_output.keys().copied().collect::<Vec<_>>();
+        // The network of this signer shuts down once it sent its commitment.
+        const SHUT_DOWN: u16 = 1;
+
+        let mut simulation = Simulation::<Envelope>::new();
+        let parties = signer_set
+            .iter()
+            .map(|_| simulation.add_party())
+            .collect::<Vec<_>>();
+        let mut tasks = BTreeMap::new();
+        for ((&i, (key_pkg, pub_key_pkg)), party) in keygen_output.iter().zip(parties) {
+            let (key_pkg, pub_key_pkg) = (key_pkg.clone(), pub_key_pkg.clone());
+            let signer_set = signer_set.clone();
+            let (incoming, outgoing) = party.into_party().delivery.split();
+            let outgoing = Box::pin(sink::unfold(
+                (outgoing, 0),
+                move |(mut outgoing, sent), msg: Outgoing<Envelope>| async move {
+                    if i == SHUT_DOWN && sent > 0 {
+                        return Err(std::io::Error::from(std::io::ErrorKind::BrokenPipe));
+                    }
+                    outgoing.send(msg).await.map_err(std::io::Error::other)?;
+                    Ok((outgoing, sent + 1))
+                },
+            ));
+            let delivery = versioned((incoming, outgoing), CodecVersion::default());
+            let task = tokio::spawn(async move {
+                let rng = &mut StdRng::seed_from_u64(u64::from(i + 1));
+                run(
+                    rng,
+                    &key_pkg,
+                    &pub_key_pkg,
+                    &signer_set,
+                    &args.msg,
+                    MalformedShares::Abort,
+                    UnknownSigners::Abort,
+                    MissingCommitments::Wait,
+                    None,
+                    MpcParty::connected(delivery),
+                    None,
+                )
+                .await
+            });
+            tasks.insert(i, task);
+        }
+        let result = tokio::time::timeout(
+            std::time::Duration::from_secs(10),
+            tasks.remove(&SHUT_DOWN).unwrap(),
+        )
+        .await
+        .expect("signing did not fail")
+        .unwrap();
+        let error = result.unwrap_err();
+        assert!(error.is_network_shutdown(), "{error:?}");
+        assert!(matches!(
+            crate::sign::Error::from(error),
+            crate::sign::Error::NetworkShutdown
+        ));
+        // The others wait for the signature share that never comes.
+        tasks.values().for_each(|task| task.abort());
+    }
+
+    #[tokio::test]
+    async fn partitioned_signers_fail_fast() {
+        use gadget_sdk::futures::sink;
+
+        type C = frost_ed25519::Ed25519Sha512;
+        let args = TestInputArgs {
+            n: 3,
+            t: 3,
+            msg: [6; 32],
+        };
+        let keygen_output = run_keygen::<C>(&args).await.unwrap();
+        let signer_set = keygen_output.keys().copied().collect::<Vec<_>>();
+        // The messages of this signer never reach the others, which only get `t - 1`
+        // commitments, theirs included.
+        const PARTITIONED: u16 = 2;
+        const DEADLINE: std::time::Duration = std::time::Duration::from_millis(200);
+
+        let mut simulation = Simulation::<Envelope>::new();
+        let parties = signer_set
+            .iter()
+            .map(|_| simulation.add_party())
+            .collect::<Vec<_>>();
+        let started = std::time::Instant::now();
+        let mut tasks = BTreeMap::new();
+        for ((&i, (key_pkg, pub_key_pkg)), party) in keygen_output.iter().zip(parties) {
+            let (key_pkg, pub_key_pkg) = (key_pkg.clone(), pub_key_pkg.clone());
+            let signer_set = signer_set.clone();
+            let (incoming, outgoing) = party.into_party().delivery.split();
+            let outgoing = Box::pin(sink::unfold(
+                outgoing,
+                move |mut outgoing, msg: Outgoing<Envelope>| async move {
+                    if i != PARTITIONED {
+                        outgoing.send(msg).await.map_err(std::io::Error::other)?;
+                    }
+                    Ok::<_, std::io::Error>(outgoing)
+                },
+            ));
+            let delivery = versioned((incoming, outgoing), CodecVersion::default());
+            let task = tokio::spawn(async move {
+                let rng = &mut StdRng::seed_from_u64(u64::from(i + 1));
+                run(
+                    rng,
+                    &key_pkg,
+                    &pub_key_pkg,
+                    &signer_set,
+                    &args.msg,
+                    MalformedShares::Abort,
+                    UnknownSigners::Abort,
+                    MissingCommitments::FailFast(DEADLINE),
+                    None,
+                    MpcParty::connected(delivery),
+                    None,
+                )
+                .await
+            });
+            tasks.insert(i, task);
+        }
+        // The partitioned signer got all the commitments, it keeps waiting.
+        let partitioned = tasks.remove(&PARTITIONED).unwrap();
+        for (_, task) in tasks {
+            let error = tokio::time::timeout(std::time::Duration::from_secs(10), task)
+                .await
+                .expect("signing did not fail fast")
+                .unwrap()
+                .unwrap_err();
+            assert_eq!(error.quorum_not_reached(), Some((2, 3)), "{error:?}");
+            assert!(matches!(
+                crate::sign::Error::from(error),
+                crate::sign::Error::QuorumNotReached {
+                    received: 2,
+                    required: 3
+                }
+            ));
+        }
+        assert!(started.elapsed() < std::time::Duration::from_secs(5));
+        assert!(!partitioned.is_finished());
+        partitioned.abort();
+    }
+
+    #[tokio::test]
+    async fn partitioned_batch_signer_fails_fast() {
+        type C = frost_ed25519::Ed25519Sha512;
+        let args = TestInputArgs {
+            n: 3,
+            t: 2,
+            msg: [7; 32],
+        };
+        let keygen_output = run_keygen::<C>(&args).await.unwrap();
+        let signers = keygen_output.into_iter().take(2).collect::<Vec<_>>();
+        let signer_set = signers.iter().map(|(i, _)| *i).collect::<Vec<_>>();
+        let mut simulation = Simulation::<BatchMsg<C>>::new();
+        // The other signer never commits: with its own commitment echoed back, this signer
+        // holds `t - 1` of them.
+        let party = simulation.add_party();
+        let _silent = simulation.add_party();
+        let (key_pkg, pub_key_pkg) = &signers[0].1;
+        let result = tokio::time::timeout(
+            Duration::from_secs(10),
+            run_batch(
+                &mut StdRng::seed_from_u64(1),
+                key_pkg,
+                pub_key_pkg,
+                &signer_set,
+                &[args.msg.to_vec(), args.msg.to_vec()],
+                MalformedShares::Abort,
+                UnknownSigners::Abort,
+                MissingCommitments::FailFast(Duration::from_millis(100)),
+                None,
+                party,
+                None,
+            ),
+        )
+        .await
+        .expect("batch signing did not fail fast");
+        let error = result.unwrap_err();
+        assert_eq!(error.quorum_not_reached(), Some((1, 2)), "{error:?}");
+    }
+
+    #[derive(Debug, Clone, Copy)]
+    enum Derivation {
+        Index(u32),
+        Ephemeral([u8; 32]),
+    }
+
+    async fn run_signing<C>(
+        args: &TestInputArgs,
+        derivation: Option<Derivation>,
+    ) -> Result<(), TestCaseError>
     where
         C: Ciphersuite + Send + Unpin + Sync,
         <<C as Ciphersuite>::Group as Group>::Element: Send + Unpin + Sync,
@@ -292,7 +1640,30 @@ mod tests {
             Send + Unpin + Sync,
     {
         let TestInputArgs { n, t, msg } = *args;
-        let keygen_output = run_keygen::<C>(args).await?;
+        let mut keygen_output = run_keygen::<C>(args).await?;
+        let mut expected_key = None;
+        match derivation {
+            Some(Derivation::Index(index)) => {
+                for (key_pkg, pub_key_pkg) in keygen_output.values_mut() {
+                    let key =
+                        crate::derive::derive_verifying_key(pub_key_pkg.verifying_key(), index)?;
+                    (*key_pkg, *pub_key_pkg) =
+                        crate::derive::derive_child(key_pkg, pub_key_pkg, index)?;
+                    expected_key = Some(key);
+                }
+            }
+            Some(Derivation::Ephemeral(randomness)) => {
+                for (key_pkg, pub_key_pkg) in keygen_output.values_mut() {
+                    let group_key = *pub_key_pkg.verifying_key();
+                    (*key_pkg, *pub_key_pkg) =
+                        crate::derive::derive_ephemeral(key_pkg, pub_key_pkg, &randomness)?;
+                    // The returned ephemeral key is a fresh key.
+                    prop_assert_ne!(pub_key_pkg.verifying_key(), &group_key);
+                    expected_key = Some(*pub_key_pkg.verifying_key());
+                }
+            }
+            None => {}
+        }
         let public_key = keygen_output
             .values()
             .map(|(_, pkg)| pkg.clone())
@@ -306,9 +1677,14 @@ mod tests {
 
         eprintln!("Running a {} {t}-out-of-{n} Signing", C::ID);
         let mut simulation = Simulation::<Msg<C>>::new();
+        // Connect every party before any of them starts sending, otherwise the parties that
+        // join late miss the first messages.
+        let parties = signers
+            .iter()
+            .map(|_| simulation.add_party())
+            .collect::<Vec<_>>();
         let mut tasks = vec![];
-        for (i, (key_pkg, pub_key_pkg)) in signers {
-            let party = simulation.add_party();
+        for ((i, (key_pkg, pub_key_pkg)), party) in signers.into_iter().zip(parties) {
             let signer_set = signer_set.clone();
             let msg = msg.to_vec();
             let output = tokio::spawn(async move {
@@ -320,6 +1696,10 @@ mod tests {
                     &pub_key_pkg,
                     &signer_set,
                     &msg,
+                    MalformedShares::Abort,
+                    UnknownSigners::Abort,
+                    MissingCommitments::Wait,
+                    None,
                     party,
                     Some(tracer.borrow_mut()),
                 )
@@ -335,10 +1715,17 @@ mod tests {
         for task in tasks {
             outputs.push(task.await.unwrap());
         }
-        let outputs = outputs.into_iter().collect::<Result<BTreeMap<_, _>, _>>()?;
+        let outputs = outputs
+            .into_iter()
+            .map(|output| output.map(|(i, output)| (i, output.signature)))
+            .collect::<Result<BTreeMap<_, _>, _>>()?;
         // Assert that all parties produced a valid signature
         let signature = outputs.values().next().unwrap();
         C::verify_signature(&msg, signature, public_key.verifying_key())?;
+        if let Some(key) = expected_key {
+            // The signature verifies against the key clients derive on their own.
+            C::verify_signature(&msg, signature, &key)?;
+        }
         for other_signature in outputs.values().skip(1) {
             prop_assert_eq!(signature, other_signature);
         }
@@ -346,6 +1733,98 @@ mod tests {
         Ok(())
     }
 
+    async fn run_batch_signing<C>(args: &TestInputArgs, batch: usize) -> Result<(), TestCaseError>
+    where
+        C: Ciphersuite + Send + Unpin + Sync,
+        <<C as Ciphersuite>::Group as Group>::Element: Send + Unpin + Sync,
+        <<<C as Ciphersuite>::Group as Group>::Field as frost_core::Field>::Scalar:
+            Send + Unpin + Sync,
+    {
+        let TestInputArgs { n, t, msg } = *args;
+        let keygen_output = run_keygen::<C>(args).await?;
+        let public_key = keygen_output
+            .values()
+            .map(|(_, pkg)| pkg.clone())
+            .next()
+            .unwrap();
+        let rng = &mut StdRng::from_seed(msg);
+        let signers = keygen_output
+            .into_iter()
+            .choose_multiple(rng, usize::from(t));
+        let signer_set = signers.iter().map(|(i, _)| *i).collect::<Vec<_>>();
+        // The same message twice in the batch must still be signed with different nonces.
+        let mut msgs = (0..batch)
+            .map(|k| [&msg[..], &[k as u8]].concat())
+            .collect::<Vec<_>>();
+        msgs.push(msgs[0].clone());
+
+        eprintln!(
+            "Running a {} {t}-out-of-{n} Batch Signing of {}",
+            C::ID,
+            msgs.len()
+        );
+        let mut simulation = Simulation::<BatchMsg<C>>::new();
+        let parties = signers
+            .iter()
+            .map(|_| simulation.add_party())
+            .collect::<Vec<_>>();
+        let mut tasks = vec![];
+        for ((i, (key_pkg, pub_key_pkg)), party) in signers.into_iter().zip(parties) {
+            let signer_set = signer_set.clone();
+            let msgs = msgs.clone();
+            let output = tokio::spawn(async move {
+                let rng = &mut StdRng::seed_from_u64(u64::from(i + 1));
+                let output = run_batch(
+                    rng,
+                    &key_pkg,
+                    &pub_key_pkg,
+                    &signer_set,
+                    &msgs,
+                    MalformedShares::Abort,
+                    UnknownSigners::Abort,
+                    MissingCommitments::Wait,
+                    None,
+                    party,
+                    None,
+                )
+                .await?;
+                Result::<_, Error<C>>::Ok((i, output))
+            });
+            tasks.push(output);
+        }
+
+        let mut outputs = Vec::with_capacity(tasks.len());
+        for task in tasks {
+            outputs.push(task.await.unwrap());
+        }
+        let outputs = outputs.into_iter().collect::<Result<BTreeMap<_, _>, _>>()?;
+        let signatures = outputs.values().next().unwrap();
+        prop_assert_eq!(signatures.len(), msgs.len());
+        for (msg, signature) in msgs.iter().zip(signatures) {
+            C::verify_signature(msg, signature, public_key.verifying_key())?;
+        }
+        for other_signatures in outputs.values().skip(1) {
+            prop_assert_eq!(signatures, other_signatures);
+        }
+        // A signature is the group commitment R followed by a scalar, distinct commitments
+        // mean no nonce was used twice.
+        let scalar_len = <<C::Group as Group>::Field as frost_core::Field>::serialize(
+            &<<C::Group as Group>::Field as frost_core::Field>::zero(),
+        )
+        .as_ref()
+        .len();
+        let commitments = signatures
+            .iter()
+            .map(|signature| {
+                let bytes = signature.serialize()?;
+                Ok(bytes[..bytes.len() - scalar_len].to_vec())
+            })
+            .collect::<Result<std::collections::BTreeSet<_>, frost_core::Error<C>>>()?;
+        prop_assert_eq!(commitments.len(), msgs.len(), "a nonce was reused");
+
+        Ok(())
+    }
+
     async fn run_keygen<C>(
         args: &TestInputArgs,
     ) -> Result<BTreeMap<u16, (KeyPackage<C>, PublicKeyPackage<C>)>, TestCaseError>
@@ -361,13 +1840,23 @@ mod tests {
 
         eprintln!("Running a {} {t}-out-of-{n} Keygen", C::ID);
         let mut simulation = Simulation::<Msg<C>>::new();
+        let parties = (0..n).map(|_| simulation.add_party()).collect::<Vec<_>>();
         let mut tasks = vec![];
-        for i in 0..n {
-            let party = simulation.add_party();
+        for (i, party) in (0..n).zip(parties) {
             let output = tokio::spawn(async move {
                 let rng = &mut StdRng::seed_from_u64(u64::from(i + 1));
                 let mut tracer = PerfProfiler::new();
-                let output = run(rng, t, n, i, party, Some(tracer.borrow_mut())).await?;
+                let output = run(
+                    rng,
+                    t,
+                    n,
+                    i,
+                    BroadcastCheck::Unchecked,
+                    None,
+                    party,
+                    Some(tracer.borrow_mut()),
+                )
+                .await?;
                 let report = tracer.get_report().unwrap();
                 eprintln!("Party {} report: {}\n", i, report);
                 Result::<_, Error<C>>::Ok((i, output))
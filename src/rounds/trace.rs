@@ -169,6 +169,41 @@ mod requires_std {
         pub duration: Duration,
     }
 
+    /// Machine-readable form of a [`PerfReport`], all durations are in microseconds
+    #[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+    pub struct TimingReport {
+        /// Duration of setup phase
+        pub setup_us: u64,
+        /// Stages of setup phase
+        pub setup_stages: Vec<StageTiming>,
+        /// Timings of each round
+        pub rounds: Vec<RoundTiming>,
+    }
+
+    /// Timings of specific round (part of [`TimingReport`])
+    #[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+    pub struct RoundTiming {
+        /// Round name (if provided)
+        pub name: Option<String>,
+        /// Stages of the round
+        pub stages: Vec<StageTiming>,
+        /// Pure computation performed during the round
+        pub computation_us: u64,
+        /// Time spent sending messages
+        pub sending_us: u64,
+        /// Time spent receiving messages
+        pub receiving_us: u64,
+    }
+
+    /// Timing of specific stage (part of [`TimingReport`])
+    #[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+    pub struct StageTiming {
+        /// Stage name
+        pub name: String,
+        /// Duration of the stage
+        pub duration_us: u64,
+    }
+
     /// Protocol profiling resulted into error
     #[derive(Debug, Error, Clone)]
     #[error("profiler failed to trace protocol: it behaved unexpectedly")]
@@ -348,6 +383,54 @@ mod requires_std {
         }
     }
 
+    impl From<&PerfReport> for TimingReport {
+        fn from(report: &PerfReport) -> Self {
+            fn micros(d: Duration) -> u64 {
+                u64::try_from(d.as_micros()).unwrap_or(u64::MAX)
+            }
+            fn stages(stages: &[StageDuration]) -> Vec<StageTiming> {
+                stages
+                    .iter()
+                    .map(|s| StageTiming {
+                        name: s.name.to_string(),
+                        duration_us: micros(s.duration),
+                    })
+                    .collect()
+            }
+            TimingReport {
+                setup_us: micros(report.setup),
+                setup_stages: stages(&report.setup_stages),
+                rounds: report
+                    .rounds
+                    .iter()
+                    .map(|r| RoundTiming {
+                        name: r.round_name.map(str::to_string),
+                        stages: stages(&r.stages),
+                        computation_us: micros(r.computation),
+                        sending_us: micros(r.sending),
+                        receiving_us: micros(r.receiving),
+                    })
+                    .collect(),
+            }
+        }
+    }
+
+    impl PerfProfiler {
+        /// Obtains a [`TimingReport`], if the protocol behaved as expected
+        pub fn timing_report(&self) -> Option<TimingReport> {
+            self.get_report().ok().map(|r| TimingReport::from(&r))
+        }
+    }
+
+    /// A job result along with the timings of the protocol that produced it
+    #[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+    pub struct TimedOutput {
+        /// The hex encoded job result
+        pub output: String,
+        /// The protocol timings, `None` if they could not be measured
+        pub timing: Option<TimingReport>,
+    }
+
     impl PerfReport {
         /// Specifies whether time spent on i/o should be rendered in the final report
         ///
@@ -486,3 +569,55 @@ mod requires_std {
         Percentage(part, total)
     }
 }
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::*;
+    use crate::rounds::keygen;
+    use frost_secp256k1::Secp256K1Sha256 as C;
+    use gadget_sdk::random::rand::rngs::StdRng;
+    use gadget_sdk::random::SeedableRng;
+    use round_based::simulation::Simulation;
+
+    #[tokio::test]
+    async fn timing_report_has_every_round() {
+        const N: u16 = 3;
+        let mut simulation = Simulation::<keygen::Msg<C>>::new();
+        let parties = (0..N).map(|_| simulation.add_party()).collect::<Vec<_>>();
+        let mut tasks = vec![];
+        for (i, party) in (0..N).zip(parties) {
+            tasks.push(tokio::spawn(async move {
+                let rng = &mut StdRng::seed_from_u64(u64::from(i));
+                let mut profiler = PerfProfiler::new();
+                keygen::run::<_, C, _>(
+                    rng,
+                    2,
+                    N,
+                    i,
+                    keygen::BroadcastCheck::Unchecked,
+                    None,
+                    party,
+                    Some(&mut profiler),
+                )
+                .await
+                .unwrap();
+                profiler.timing_report()
+            }));
+        }
+        for task in tasks {
+            let timing = task.await.unwrap().expect("protocol traced");
+            let output = TimedOutput {
+                output: hex::encode([1, 2, 3]),
+                timing: Some(timing),
+            };
+            let decoded: TimedOutput =
+                serde_json::from_slice(&serde_json::to_vec(&output).unwrap()).unwrap();
+            assert_eq!(decoded, output);
+
+            let timing = decoded.timing.unwrap();
+            assert_eq!(timing.rounds.len(), 3);
+            assert!(timing.rounds.iter().all(|r| !r.stages.is_empty()));
+            assert_eq!(timing.rounds[2].name.as_deref(), Some("Part 3 (Offline)"));
+        }
+    }
+}
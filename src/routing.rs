@@ -0,0 +1,185 @@
+//! Routing of the protocol messages by an opaque identity of the parties.
+//!
+//! The protocols number their parties with a `u16` index, and the network addresses them by
+//! their ECDSA key. A deployment identifying its operators otherwise, e.g. by a DID or an
+//! account, describes the parties of a session with a [`RoutingTable`] of these identities, and
+//! resolves them to their network key with an [`IdentityResolver`]: the index of a party stays
+//! internal to the session, and is the position of its identity in the sorted table, the same
+//! on every node.
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use gadget_sdk::network::round_based_compat::NetworkDeliveryWrapper;
+use gadget_sdk::network::NetworkMultiplexer;
+use gadget_sdk::subxt_core::ext::sp_core::ecdsa;
+use round_based::{Incoming, Outgoing};
+
+use crate::codec::Envelope;
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("{0} is not a party of the session")]
+    NotAParty(String),
+    #[error("No network key for the party {0}")]
+    Unresolved(String),
+}
+
+/// Resolves the identity of an operator to the ECDSA key the network addresses it with.
+pub trait IdentityResolver<Id>: Send + Sync {
+    /// The network key of the operator with the identity `id`, `None` if unknown.
+    fn network_key(&self, id: &Id) -> Option<ecdsa::Public>;
+}
+
+impl<Id: Ord + Send + Sync> IdentityResolver<Id> for BTreeMap<Id, ecdsa::Public> {
+    fn network_key(&self, id: &Id) -> Option<ecdsa::Public> {
+        self.get(id).copied()
+    }
+}
+
+/// The parties of a session by identity, each at the index of the protocols given by its
+/// position in the sorted identities.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RoutingTable<Id> {
+    identities: Vec<Id>,
+}
+
+impl<Id: Ord + Clone + std::fmt::Debug> RoutingTable<Id> {
+    /// The table of the parties with the `identities`, in any order.
+    pub fn new(identities: impl IntoIterator<Item = Id>) -> Self {
+        let mut identities = identities.into_iter().collect::<Vec<_>>();
+        identities.sort();
+        identities.dedup();
+        Self { identities }
+    }
+
+    /// The number of parties.
+    pub fn len(&self) -> usize {
+        self.identities.len()
+    }
+
+    /// Whether the table has no party.
+    pub fn is_empty(&self) -> bool {
+        self.identities.is_empty()
+    }
+
+    /// The index of the party `id`, if it is one.
+    pub fn index_of(&self, id: &Id) -> Option<u16> {
+        let position = self.identities.binary_search(id).ok()?;
+        u16::try_from(position).ok()
+    }
+
+    /// The identity of the party of index `i`, if any.
+    pub fn identity_of(&self, i: u16) -> Option<&Id> {
+        self.identities.get(usize::from(i))
+    }
+
+    /// The message `msg` sent to the party `id` only, `None` if it is not a party.
+    pub fn to<M>(&self, id: &Id, msg: M) -> Option<Outgoing<M>> {
+        Some(Outgoing::p2p(self.index_of(id)?, msg))
+    }
+
+    /// The identity of the sender of `incoming`.
+    pub fn sender_of<M>(&self, incoming: &Incoming<M>) -> Option<&Id> {
+        self.identity_of(incoming.sender)
+    }
+
+    /// The delivery of the session `task_hash` for the party `me`, addressing the other parties
+    /// on `network` by the key `resolver` gives their identity.
+    ///
+    /// Fails if `me` is not a party, or if the key of a party is unknown.
+    pub fn delivery(
+        &self,
+        network: Arc<NetworkMultiplexer>,
+        me: &Id,
+        task_hash: [u8; 32],
+        resolver: &dyn IdentityResolver<Id>,
+    ) -> Result<NetworkDeliveryWrapper<Envelope>, Error> {
+        let i = self
+            .index_of(me)
+            .ok_or_else(|| Error::NotAParty(format!("{me:?}")))?;
+        let parties = self
+            .identities
+            .iter()
+            .zip(0u16..)
+            .map(|(id, j)| {
+                let key = resolver
+                    .network_key(id)
+                    .ok_or_else(|| Error::Unresolved(format!("{id:?}")))?;
+                Ok((j, key))
+            })
+            .collect::<Result<BTreeMap<_, _>, Error>>()?;
+        Ok(NetworkDeliveryWrapper::new(network, i, task_hash, parties))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use gadget_sdk::futures::{SinkExt, StreamExt};
+    use round_based::{Delivery, ProtocolMessage};
+    use serde::{Deserialize, Serialize};
+
+    use super::*;
+    use crate::codec::{versioned, CodecVersion};
+    use crate::testing::MockNetwork;
+
+    #[derive(Clone, Debug, PartialEq, ProtocolMessage, Serialize, Deserialize)]
+    enum Msg {
+        Ping(u32),
+    }
+
+    fn key(i: u8) -> ecdsa::Public {
+        let mut key = [0u8; 33];
+        key[0] = 0x02;
+        key[1] = i;
+        ecdsa::Public::from_raw(key)
+    }
+
+    #[tokio::test]
+    async fn messages_reach_the_party_of_the_identity() {
+        let network = MockNetwork::new(Default::default());
+        // The identities are sorted in another order than the network keys.
+        let resolver = BTreeMap::from([
+            ("did:example:carol".to_string(), key(1)),
+            ("did:example:bob".to_string(), key(2)),
+            ("did:example:alice".to_string(), key(3)),
+        ]);
+        let table = RoutingTable::new(resolver.keys().rev().cloned());
+        let (alice, bob, carol) = (
+            "did:example:alice".to_string(),
+            "did:example:bob".to_string(),
+            "did:example:carol".to_string(),
+        );
+        let mut deliveries = [&alice, &bob, &carol].map(|id| {
+            let delivery = table
+                .delivery(network.multiplexer(resolver[id]), id, [9; 32], &resolver)
+                .unwrap();
+            Delivery::<Msg>::split(versioned(delivery, CodecVersion::default()))
+        });
+
+        let outgoing = table.to(&carol, Msg::Ping(7)).unwrap();
+        deliveries[0].1.send(outgoing).await.unwrap();
+        let incoming = tokio::time::timeout(Duration::from_secs(5), deliveries[2].0.next())
+            .await
+            .expect("message not delivered")
+            .unwrap()
+            .unwrap();
+        assert_eq!(incoming.msg, Msg::Ping(7));
+        assert_eq!(table.sender_of(&incoming), Some(&alice));
+        // The message was for carol only.
+        let other = tokio::time::timeout(Duration::from_millis(100), deliveries[1].0.next()).await;
+        assert!(other.is_err());
+
+        assert!(table.to(&"did:example:mallory".to_string(), ()).is_none());
+        assert!(matches!(
+            table.delivery(
+                network.multiplexer(key(4)),
+                &bob,
+                [9; 32],
+                &BTreeMap::<String, ecdsa::Public>::new()
+            ),
+            Err(Error::Unresolved(_))
+        ));
+    }
+}
@@ -0,0 +1,342 @@
+//! Self-test of the cryptography of the node.
+//!
+//! [`self_test`] runs a small keygen and signing of the compiled-in ciphersuites entirely
+//! in-process, over the `round_based` simulation instead of the network, so an operator can
+//! confirm that the protocols, the codec of their messages and the encodings of the stored keys
+//! work on the node before it joins real sessions.
+//!
+//! The simulation is a development tool of `round_based`, so the loopback is only compiled in
+//! with the `self-test` feature: without it, the job reports every ciphersuite as failed.
+use api::services::events::JobCalled;
+use frost_core::Ciphersuite;
+use gadget_sdk as sdk;
+use sdk::event_listener::tangle::{
+    jobs::{services_post_processor, services_pre_processor},
+    TangleEventListener,
+};
+use sdk::tangle_subxt::tangle_testnet_runtime::api;
+use serde::{Deserialize, Serialize};
+
+use crate::redjubjub::JubjubBlake2b512;
+use crate::FrostContext;
+#[cfg(any(test, feature = "self-test"))]
+use loopback::loopback;
+
+/// The ciphersuites compiled in the node.
+pub const CIPHERSUITES: [&str; 5] = [
+    frost_ed25519::Ed25519Sha512::ID,
+    frost_secp256k1::Secp256K1Sha256::ID,
+    frost_ristretto255::Ristretto255Sha512::ID,
+    frost_p256::P256Sha256::ID,
+    JubjubBlake2b512::ID,
+];
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("Unknown ciphersuite: {0}")]
+    UnknownCiphersuite(String),
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+}
+
+/// The outcome of the self-test of a ciphersuite.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SelfTestReport {
+    /// The ciphersuite tested.
+    pub ciphersuite: String,
+    /// Why the self-test failed, `None` if it passed.
+    pub error: Option<String>,
+    /// How long the keygen took, in milliseconds.
+    pub keygen_ms: u64,
+    /// How long the signing took, in milliseconds.
+    pub signing_ms: u64,
+}
+
+impl SelfTestReport {
+    /// Whether the self-test passed.
+    pub fn passed(&self) -> bool {
+        self.error.is_none()
+    }
+}
+
+/// Run a loopback keygen and signing, without the network, to check the node.
+///
+/// # Parameters
+/// - `ciphersuite`: The ciphersuite to test, or an empty string for all the compiled-in ones.
+///
+/// # Returns
+/// The JSON list of the [`SelfTestReport`]s of the tested ciphersuites, whether they passed or
+/// not.
+///
+/// # Errors
+/// - `UnknownCiphersuite`: If the ciphersuite is not supported.
+#[sdk::job(
+    id = 20,
+    params(ciphersuite),
+    result(_),
+    event_listener(
+        listener = TangleEventListener::<FrostContext, JobCalled>,
+        pre_processor = services_pre_processor,
+        post_processor = services_post_processor,
+    )
+)]
+#[tracing::instrument(skip_all, parent = context.config.span.clone(), err)]
+pub async fn self_test(ciphersuite: String, context: FrostContext) -> Result<Vec<u8>, Error> {
+    let ciphersuites = match ciphersuite.as_str() {
+        "" => CIPHERSUITES.to_vec(),
+        ciphersuite => vec![ciphersuite],
+    };
+    let mut reports = Vec::with_capacity(ciphersuites.len());
+    for ciphersuite in ciphersuites {
+        let report = run_self_test(ciphersuite).await?;
+        match &report.error {
+            None => tracing::info!(%ciphersuite, "Self-test passed"),
+            Some(error) => tracing::error!(%ciphersuite, %error, "Self-test failed"),
+        }
+        reports.push(report);
+    }
+    Ok(serde_json::to_vec(&reports)?)
+}
+
+/// Run the self-test of `ciphersuite`.
+pub async fn run_self_test(ciphersuite: &str) -> Result<SelfTestReport, Error> {
+    let mut report = SelfTestReport {
+        ciphersuite: ciphersuite.to_string(),
+        error: None,
+        keygen_ms: 0,
+        signing_ms: 0,
+    };
+    let result = match ciphersuite {
+        frost_ed25519::Ed25519Sha512::ID => {
+            loopback::<frost_ed25519::Ed25519Sha512>(&mut report).await
+        }
+        frost_secp256k1::Secp256K1Sha256::ID => {
+            loopback::<frost_secp256k1::Secp256K1Sha256>(&mut report).await
+        }
+        frost_ristretto255::Ristretto255Sha512::ID => {
+            loopback::<frost_ristretto255::Ristretto255Sha512>(&mut report).await
+        }
+        frost_p256::P256Sha256::ID => loopback::<frost_p256::P256Sha256>(&mut report).await,
+        JubjubBlake2b512::ID => loopback::<JubjubBlake2b512>(&mut report).await,
+        _ => return Err(Error::UnknownCiphersuite(ciphersuite.to_string())),
+    };
+    report.error = result.err();
+    Ok(report)
+}
+
+/// Without the `round_based` simulation, report the self-test as failed.
+#[cfg(not(any(test, feature = "self-test")))]
+#[allow(clippy::extra_unused_type_parameters)]
+async fn loopback<C: Ciphersuite>(_report: &mut SelfTestReport) -> Result<(), String> {
+    Err("The node is built without the `self-test` feature".to_string())
+}
+
+#[cfg(any(test, feature = "self-test"))]
+mod loopback {
+    use std::collections::BTreeMap;
+    use std::time::{Duration, Instant};
+
+    use frost_core::keys::{KeyPackage, PublicKeyPackage};
+    use frost_core::Ciphersuite;
+    use round_based::simulation::Simulation;
+    use round_based::{Mpc, MpcParty};
+    use sdk::random::rand::rngs::OsRng;
+
+    use super::*;
+    use crate::codec::{versioned, CodecVersion, Envelope};
+    use crate::entry::EntryFormat;
+    use crate::keygen::KeygenEntry;
+    use crate::rounds::{keygen as keygen_protocol, sign as sign_protocol};
+
+    /// The number of parties of the self-test keygen.
+    const PARTIES: u16 = 3;
+    /// The threshold of the self-test keygen, and number of signers of its signing.
+    const THRESHOLD: u16 = 2;
+    /// The message signed by the self-test.
+    const MESSAGE: &[u8] = b"frost-blueprint self-test";
+
+    type KeyPackages<C> = BTreeMap<u16, (KeyPackage<C>, PublicKeyPackage<C>)>;
+
+    /// Run a keygen, store and read back its key packages, then sign with them, timing the
+    /// protocols in `report`.
+    pub(super) async fn loopback<C>(report: &mut SelfTestReport) -> Result<(), String>
+    where
+        C: Ciphersuite + Send + Sync + Unpin,
+        <<C as Ciphersuite>::Group as frost_core::Group>::Element: Send + Sync + Unpin,
+        <<<C as Ciphersuite>::Group as frost_core::Group>::Field as frost_core::Field>::Scalar:
+            Send + Sync + Unpin,
+    {
+        let started = Instant::now();
+        let key_pkgs = keygen::<C>().await?;
+        report.keygen_ms = millis(started.elapsed());
+        let key_pkgs = key_pkgs
+            .into_iter()
+            .map(|(i, (key_pkg, pub_key_pkg))| Ok((i, stored(key_pkg, pub_key_pkg)?)))
+            .collect::<Result<KeyPackages<C>, String>>()?;
+        let started = Instant::now();
+        sign(key_pkgs).await?;
+        report.signing_ms = millis(started.elapsed());
+        Ok(())
+    }
+
+    fn millis(elapsed: Duration) -> u64 {
+        u64::try_from(elapsed.as_millis()).unwrap_or(u64::MAX)
+    }
+
+    /// The key packages read back from their keygen entry, in both entry formats.
+    fn stored<C: Ciphersuite>(
+        key_pkg: KeyPackage<C>,
+        pub_key_pkg: PublicKeyPackage<C>,
+    ) -> Result<(KeyPackage<C>, PublicKeyPackage<C>), String> {
+        let entry = KeygenEntry {
+            key_pkg,
+            pub_key_pkg,
+            committee: None,
+            beacon: None,
+            signing_windows: None,
+            identifiers: None,
+        };
+        let entry = serde_json::to_value(&entry).map_err(|e| e.to_string())?;
+        let info = serde_json::json!({
+            "ciphersuite": C::ID,
+            "frost_core": crate::entry::FROST_CORE_VERSION,
+            "entry": entry,
+        });
+        for format in [EntryFormat::Json, EntryFormat::Bincode] {
+            let raw = crate::entry::encode(format, &info).map_err(|e| e.to_string())?;
+            let decoded = crate::entry::decode(&raw).map_err(|e| e.to_string())?;
+            if decoded != info {
+                return Err(format!("The {format:?} keygen entry does not read back"));
+            }
+        }
+        let entry: KeygenEntry<C> =
+            serde_json::from_value(info["entry"].clone()).map_err(|e| e.to_string())?;
+        Ok((entry.key_pkg, entry.pub_key_pkg))
+    }
+
+    /// Run a keygen among [`PARTIES`] simulated parties.
+    async fn keygen<C>() -> Result<KeyPackages<C>, String>
+    where
+        C: Ciphersuite + Send + Sync + Unpin,
+        <<C as Ciphersuite>::Group as frost_core::Group>::Element: Send + Sync + Unpin,
+        <<<C as Ciphersuite>::Group as frost_core::Group>::Field as frost_core::Field>::Scalar:
+            Send + Sync + Unpin,
+    {
+        let mut simulation = Simulation::<Envelope>::new();
+        // Connect every party before any of them starts sending.
+        let parties = (0..PARTIES)
+            .map(|_| simulation.add_party())
+            .collect::<Vec<_>>();
+        let mut tasks = vec![];
+        for (i, party) in (0..PARTIES).zip(parties) {
+            let delivery = versioned(party.into_party().delivery, CodecVersion::default());
+            tasks.push(tokio::spawn(async move {
+                keygen_protocol::run::<_, C, _>(
+                    &mut OsRng,
+                    THRESHOLD,
+                    PARTIES,
+                    i,
+                    keygen_protocol::BroadcastCheck::Unchecked,
+                    None,
+                    MpcParty::connected(delivery),
+                    None,
+                )
+                .await
+                .map(|output| (i, output))
+            }));
+        }
+        let mut outputs = BTreeMap::new();
+        for task in tasks {
+            let (i, output) = task
+                .await
+                .map_err(|e| e.to_string())?
+                .map_err(|e| e.to_string())?;
+            outputs.insert(i, output);
+        }
+        let mut pub_key_pkgs = outputs.values().map(|(_, pub_key_pkg)| pub_key_pkg);
+        let first = pub_key_pkgs.next();
+        if pub_key_pkgs.any(|pub_key_pkg| Some(pub_key_pkg) != first) {
+            return Err("The parties generated different keys".to_string());
+        }
+        Ok(outputs)
+    }
+
+    /// Sign [`MESSAGE`] among the first [`THRESHOLD`] parties, and verify the signature.
+    async fn sign<C>(key_pkgs: KeyPackages<C>) -> Result<(), String>
+    where
+        C: Ciphersuite + Send + Sync + Unpin,
+        <<C as Ciphersuite>::Group as frost_core::Group>::Element: Send + Sync + Unpin,
+        <<<C as Ciphersuite>::Group as frost_core::Group>::Field as frost_core::Field>::Scalar:
+            Send + Sync + Unpin,
+    {
+        let signers = key_pkgs
+            .into_iter()
+            .take(usize::from(THRESHOLD))
+            .collect::<Vec<_>>();
+        let signer_set = signers.iter().map(|(i, _)| *i).collect::<Vec<_>>();
+        let mut simulation = Simulation::<Envelope>::new();
+        let parties = signers
+            .iter()
+            .map(|_| simulation.add_party())
+            .collect::<Vec<_>>();
+        let mut tasks = vec![];
+        for ((_, (key_pkg, pub_key_pkg)), party) in signers.into_iter().zip(parties) {
+            let signer_set = signer_set.clone();
+            let delivery = versioned(party.into_party().delivery, CodecVersion::default());
+            tasks.push(tokio::spawn(async move {
+                let output = sign_protocol::run::<_, C, _>(
+                    &mut OsRng,
+                    &key_pkg,
+                    &pub_key_pkg,
+                    &signer_set,
+                    MESSAGE,
+                    sign_protocol::MalformedShares::Abort,
+                    sign_protocol::UnknownSigners::Abort,
+                    sign_protocol::MissingCommitments::Wait,
+                    None,
+                    MpcParty::connected(delivery),
+                    None,
+                )
+                .await?;
+                Ok::<_, sign_protocol::Error<C>>((output, pub_key_pkg))
+            }));
+        }
+        for task in tasks {
+            let (output, pub_key_pkg) = task
+                .await
+                .map_err(|e| e.to_string())?
+                .map_err(|e| e.to_string())?;
+            pub_key_pkg
+                .verifying_key()
+                .verify(MESSAGE, &output.signature)
+                .map_err(|e| format!("The signature does not verify: {e}"))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{operator_contexts, MockNetwork, TempDir};
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn compiled_in_ciphersuites_pass() {
+        let network = MockNetwork::new(Default::default());
+        let dir = TempDir::new("self-test");
+        let context = operator_contexts(&network, &dir, 1, 984).remove(0);
+
+        let reports = self_test(String::new(), context.clone()).await.unwrap();
+        let reports: Vec<SelfTestReport> = serde_json::from_slice(&reports).unwrap();
+        assert_eq!(reports.len(), CIPHERSUITES.len());
+        for (report, ciphersuite) in reports.iter().zip(CIPHERSUITES) {
+            assert_eq!(report.ciphersuite, ciphersuite);
+            assert!(report.passed(), "{report:?}");
+        }
+
+        assert!(matches!(
+            self_test("FROST(P-256, SHA-256)".to_string(), context).await,
+            Err(Error::UnknownCiphersuite(_))
+        ));
+    }
+}
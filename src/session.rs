@@ -0,0 +1,516 @@
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use gadget_sdk as sdk;
+use sdk::parking_lot::Mutex;
+
+use crate::clock::Clock;
+
+/// Domain of the keygen sessions.
+const KEYGEN_SESSION: &[u8] = b"frost-keygen";
+/// Domain of the signing sessions.
+const SIGNING_SESSION: &[u8] = b"frost-signing";
+/// Domain of the refresh sessions.
+const REFRESH_SESSION: &[u8] = b"frost-refresh";
+/// Domain of the configuration change sessions.
+const CONFIG_SESSION: &[u8] = b"frost-config";
+/// Domain of the sessions checking the operators agree on the configuration.
+const CONFIG_CHECK_SESSION: &[u8] = b"frost-config-check";
+/// Domain of the batch digests.
+const BATCH_DIGEST: &[u8] = b"frost-batch";
+/// Domain of the signer selection seeds.
+const SIGNERS_SEED: &[u8] = b"frost-signers";
+/// Domain of the digests of the chunks of a batch.
+const BATCH_CHUNK: &[u8] = b"frost-batch-chunk";
+/// Domain of the sessions agreeing on the chunk a batch resumes from.
+const BATCH_RESUME: &[u8] = b"frost-batch-resume";
+/// Domain of the sessions reporting how a signing ended.
+const SIGNING_OUTCOME: &[u8] = b"frost-signing-outcome";
+/// Domain of the sessions drawing the randomness of an ephemeral key.
+const EPHEMERAL_RANDOMNESS: &[u8] = b"frost-ephemeral-randomness";
+/// Domain of the stream the peers announce their addresses on.
+const ADDRESS_ANNOUNCEMENT: &[u8] = b"frost-addresses";
+
+/// The name of the network session of the keygen job `call_id` with the `ciphersuite`.
+///
+/// All the sessions of a node share the same network multiplexer, which only delivers to a
+/// session the messages sent with its name, so the names of concurrent sessions must not
+/// collide.
+pub fn keygen_session_name(call_id: u64, ciphersuite: &str) -> [u8; 32] {
+    session_id(
+        KEYGEN_SESSION,
+        &[&call_id.to_be_bytes(), ciphersuite.as_bytes()],
+    )
+}
+
+/// The name of the network session of the signing job `call_id` of `msg` with the key `pubkey`,
+/// see [`keygen_session_name`].
+///
+/// It lets a coordinator predict the session of a job, to follow its traffic or to connect the
+/// signers ahead of it. `pubkey` is the key that signs, so the derived key when the job derives
+/// one, but the generated key for an ephemeral key, which is only drawn once the signers are
+/// selected, and `msg` the message that is signed, which is not always the one sent to the job:
+/// - [`block_bound_message`](crate::sign::block_bound_message) of it, with
+///   [`FrostContext::with_block_bound_signing`](crate::FrostContext::with_block_bound_signing).
+/// - [`validity_bound_message`](crate::sign::validity_bound_message) of it, for the
+///   [`sign_with_validity`](crate::sign::sign_with_validity) job.
+/// - The [`typed_data_digest`](crate::eip712::typed_data_digest) of the typed data, for the
+///   [`sign_typed_data`](crate::sign::sign_typed_data) job.
+pub fn session_name(call_id: u64, pubkey: &[u8], msg: &[u8]) -> [u8; 32] {
+    session_id(SIGNING_SESSION, &[&call_id.to_be_bytes(), pubkey, msg])
+}
+
+/// The name of the network session refreshing the shares of the key `pubkey` in `epoch`, see
+/// [`FrostContext::with_share_refresh`](crate::FrostContext::with_share_refresh).
+pub(crate) fn refresh_session_name(pubkey: &[u8], epoch: u64) -> [u8; 32] {
+    session_id(REFRESH_SESSION, &[pubkey, &epoch.to_be_bytes()])
+}
+
+/// The name of the network session voting on the configuration change of the job `call_id`,
+/// see [`governance`](crate::governance).
+pub(crate) fn config_session_name(call_id: u64) -> [u8; 32] {
+    session_id(CONFIG_SESSION, &[&call_id.to_be_bytes()])
+}
+
+/// The name of the network session of the job `call_id` checking the operators agree on the
+/// version of the configuration, see [`governance`](crate::governance).
+pub(crate) fn config_check_session_name(call_id: u64) -> [u8; 32] {
+    session_id(CONFIG_CHECK_SESSION, &[&call_id.to_be_bytes()])
+}
+
+/// The seed of the signer selection of a signing job.
+///
+/// It includes the call id, so concurrent requests to sign the same message with the same key
+/// are spread over different operators instead of all landing on the same signers.
+pub(crate) fn signers_seed(call_id: u64, pubkey: &[u8], msg: &[u8]) -> [u8; 32] {
+    session_id(SIGNERS_SEED, &[&call_id.to_be_bytes(), pubkey, msg])
+}
+
+/// The seed of the signer selection of a batch signed in chunks, see
+/// [`FrostContext::with_batch_checkpoints`](crate::FrostContext::with_batch_checkpoints).
+///
+/// Unlike [`signers_seed`], it is the same for every job call signing the `batch`, so the
+/// signers holding the checkpoints of the batch are the ones selected to resume it.
+pub(crate) fn batch_signers_seed(pubkey: &[u8], batch: &[u8; 32]) -> [u8; 32] {
+    session_id(SIGNERS_SEED, &[pubkey, batch])
+}
+
+/// The name of the network session of the job `call_id` agreeing on the chunk the signing of
+/// the `batch` with the key `pubkey` resumes from, see [`resume`](crate::rounds::resume).
+pub(crate) fn batch_resume_session_name(call_id: u64, pubkey: &[u8], batch: &[u8; 32]) -> [u8; 32] {
+    session_id(BATCH_RESUME, &[&call_id.to_be_bytes(), pubkey, batch])
+}
+
+/// The name of the network session the operators learn how the signing session `session` ended
+/// in, see [`outcome`](crate::rounds::outcome).
+pub(crate) fn outcome_session_name(session: &[u8; 32]) -> [u8; 32] {
+    session_id(SIGNING_OUTCOME, &[session])
+}
+
+/// The name of the network session the signers of the signing session `session` draw the
+/// randomness of its ephemeral key in, see [`randomness`](crate::rounds::randomness).
+pub(crate) fn ephemeral_session_name(session: &[u8; 32]) -> [u8; 32] {
+    session_id(EPHEMERAL_RANDOMNESS, &[session])
+}
+
+/// The name of the network stream the peers announce their addresses on, see
+/// [`address_book::exchange`](crate::address_book::exchange).
+pub(crate) fn address_announcement_name() -> [u8; 32] {
+    session_id(ADDRESS_ANNOUNCEMENT, &[])
+}
+
+/// The digest standing for the chunk of index `chunk` of the `batch` in its session id.
+pub(crate) fn chunk_digest(batch: &[u8; 32], chunk: usize) -> [u8; 32] {
+    session_id(BATCH_CHUNK, &[batch, &(chunk as u64).to_be_bytes()])
+}
+
+/// The digest standing for a batch of messages in the session id and the signers seed of a
+/// batch signing job.
+pub(crate) fn batch_digest(msgs: &[Vec<u8>]) -> [u8; 32] {
+    let msgs = msgs.iter().map(Vec::as_slice).collect::<Vec<_>>();
+    session_id(BATCH_DIGEST, &msgs)
+}
+
+/// Hash the session `domain` and `parts`, each prefixed by its length so that different
+/// parts can never produce the same input.
+fn session_id(domain: &[u8], parts: &[&[u8]]) -> [u8; 32] {
+    let mut input = Vec::new();
+    for part in std::iter::once(&domain).chain(parts) {
+        input.extend((part.len() as u64).to_be_bytes());
+        input.extend_from_slice(part);
+    }
+    sdk::compute_sha256_hash!(&input)
+}
+
+/// The registry of active sessions is full.
+#[derive(Debug, thiserror::Error)]
+#[error("Too many active sessions, the limit is {limit}")]
+pub struct TooManySessions {
+    pub limit: usize,
+}
+
+/// A job did not complete within its time budget.
+#[derive(Debug, thiserror::Error)]
+#[error("The job did not complete within {budget:?}")]
+pub struct JobTimeout {
+    pub budget: Duration,
+}
+
+#[derive(Debug)]
+struct ActiveSession {
+    kind: &'static str,
+    started: SystemTime,
+    /// Tells apart the registrations of the same session id.
+    generation: u64,
+}
+
+#[derive(Debug, Default)]
+struct Sessions {
+    active: BTreeMap<[u8; 32], ActiveSession>,
+    next_generation: u64,
+}
+
+/// The sessions running on this node, shared by all the clones of the context.
+///
+/// The registry is bounded: when full, the sessions started more than `max_age` ago are
+/// evicted, and a new session is refused if there is still no room.
+#[derive(Clone, Debug)]
+pub(crate) struct SessionRegistry {
+    sessions: Arc<Mutex<Sessions>>,
+    limit: usize,
+    max_age: Duration,
+    clock: Arc<dyn Clock>,
+}
+
+impl SessionRegistry {
+    pub(crate) fn new(limit: usize, max_age: Duration, clock: Arc<dyn Clock>) -> Self {
+        Self {
+            sessions: Default::default(),
+            limit,
+            max_age,
+            clock,
+        }
+    }
+
+    /// An empty registry with the same bounds, reading the age of the sessions from `clock`.
+    pub(crate) fn with_clock(&self, clock: Arc<dyn Clock>) -> Self {
+        Self::new(self.limit, self.max_age, clock)
+    }
+
+    /// Register the session `id`, until the returned guard is dropped.
+    ///
+    /// The guard is dropped when the protocol completes, fails or panics, so a session can
+    /// never outlive its run.
+    pub(crate) fn register(
+        &self,
+        id: [u8; 32],
+        kind: &'static str,
+    ) -> Result<SessionGuard, TooManySessions> {
+        let mut sessions = self.sessions.lock();
+        if sessions.active.len() >= self.limit {
+            let max_age = self.max_age;
+            sessions.active.retain(|id, session| {
+                let stale = self.clock.since(session.started) > max_age;
+                if stale {
+                    sdk::warn!(session = %hex::encode(id), kind = session.kind, "Evicting a stale session");
+                }
+                !stale
+            });
+        }
+        if sessions.active.len() >= self.limit {
+            return Err(TooManySessions { limit: self.limit });
+        }
+        let generation = sessions.next_generation;
+        sessions.next_generation += 1;
+        sessions.active.insert(
+            id,
+            ActiveSession {
+                kind,
+                started: self.clock.now(),
+                generation,
+            },
+        );
+        Ok(SessionGuard {
+            registry: self.sessions.clone(),
+            id,
+            generation,
+        })
+    }
+
+    /// The number of active sessions.
+    pub(crate) fn len(&self) -> usize {
+        self.sessions.lock().active.len()
+    }
+}
+
+/// Removes its session from the [`SessionRegistry`] when dropped.
+#[derive(Debug)]
+pub(crate) struct SessionGuard {
+    registry: Arc<Mutex<Sessions>>,
+    id: [u8; 32],
+    generation: u64,
+}
+
+impl Drop for SessionGuard {
+    fn drop(&mut self) {
+        let mut sessions = self.registry.lock();
+        // The session may have been evicted and registered again since.
+        if sessions
+            .active
+            .get(&self.id)
+            .is_some_and(|s| s.generation == self.generation)
+        {
+            sessions.active.remove(&self.id);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::clock::{MockClock, SystemClock};
+    use crate::rounds::{keygen, sign};
+    use crate::testing::{MockNetwork, MockNetworkConfig};
+    use frost_core::keys::{IdentifierList, KeyPackage};
+    use frost_core::Ciphersuite;
+    use frost_secp256k1::Secp256K1Sha256 as C;
+    use sdk::network::round_based_compat::NetworkDeliveryWrapper;
+    use sdk::random::rand::rngs::StdRng;
+    use sdk::random::SeedableRng;
+    use sdk::subxt_core::ext::sp_core::ecdsa;
+
+    #[test]
+    fn sessions_do_not_collide() {
+        assert_ne!(
+            keygen_session_name(1, C::ID),
+            session_name(1, &[], C::ID.as_bytes())
+        );
+        assert_ne!(keygen_session_name(1, C::ID), keygen_session_name(2, C::ID));
+        // Moving bytes from the public key to the message changes the session.
+        assert_ne!(session_name(1, b"ab", b"c"), session_name(1, b"a", b"bc"));
+    }
+
+    #[test]
+    fn concurrent_requests_select_independent_signers() {
+        let operators = (1..=10u8)
+            .map(|i| {
+                let mut key = [0u8; 33];
+                key[0] = 0x02;
+                key[1] = i;
+                (
+                    sdk::subxt_core::utils::AccountId32([i; 32]),
+                    ecdsa::Public::from_raw(key),
+                )
+            })
+            .collect();
+        let (pubkey, msg) = (b"group key".as_slice(), b"message".as_slice());
+        assert_ne!(signers_seed(1, pubkey, msg), signers_seed(2, pubkey, msg));
+        assert_ne!(signers_seed(1, pubkey, msg), session_name(1, pubkey, msg));
+
+        let selections = (0..16)
+            .map(|call_id| {
+                crate::operators::select_signers(&operators, signers_seed(call_id, pubkey, msg), 3)
+            })
+            .collect::<std::collections::BTreeSet<_>>();
+        assert!(selections.len() > 1, "every call selected the same signers");
+    }
+
+    #[tokio::test]
+    async fn panicking_run_removes_its_session() {
+        let registry = SessionRegistry::new(4, Duration::from_secs(60), Arc::new(SystemClock));
+        let run = {
+            let registry = registry.clone();
+            tokio::spawn(async move {
+                let _session = registry.register([1; 32], "keygen").unwrap();
+                panic!("protocol failed");
+            })
+        };
+        assert!(run.await.unwrap_err().is_panic());
+        assert_eq!(registry.len(), 0);
+    }
+
+    #[test]
+    fn full_registry_evicts_stale_sessions() {
+        let clock = MockClock::default();
+        let registry = SessionRegistry::new(2, Duration::from_secs(20), Arc::new(clock.clone()));
+        let _a = registry.register([1; 32], "keygen").unwrap();
+        let _b = registry.register([2; 32], "signing").unwrap();
+        assert!(registry.register([3; 32], "signing").is_err());
+        clock.advance(Duration::from_secs(30));
+        let _c = registry.register([3; 32], "signing").unwrap();
+        assert_eq!(registry.len(), 1);
+        // Dropping an evicted session's guard leaves the registry alone.
+        drop(_a);
+        assert_eq!(registry.len(), 1);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn concurrent_keygen_and_sign_are_isolated() {
+        const N: u16 = 3;
+        const T: u16 = 2;
+        let network = MockNetwork::new(MockNetworkConfig {
+            latency: Duration::from_millis(5),
+            loss: 0.0,
+        });
+        let parties = (0..N)
+            .map(|i| {
+                let mut key = [0u8; 33];
+                key[0] = 0x02;
+                key[1] = i as u8;
+                (i, ecdsa::Public::from_raw(key))
+            })
+            .collect::<BTreeMap<_, _>>();
+        // One multiplexer per node, shared by both sessions.
+        let muxes = parties
+            .values()
+            .map(|k| network.multiplexer(*k))
+            .collect::<Vec<_>>();
+
+        // A key from a previous keygen, to sign with.
+        let rng = &mut StdRng::seed_from_u64(0);
+        let (shares, pub_key_pkg) =
+            frost_core::keys::generate_with_dealer::<C, _>(N, T, IdentifierList::Default, rng)
+                .unwrap();
+        let key_pkgs = shares
+            .into_values()
+            .map(|share| KeyPackage::try_from(share).unwrap())
+            .collect::<Vec<_>>();
+        let msg = b"concurrent".to_vec();
+        let pubkey = pub_key_pkg.verifying_key().serialize().unwrap();
+
+        let keygen_session = keygen_session_name(7, C::ID);
+        let signing_session = session_name(8, &pubkey, &msg);
+        let signers = (0..T).collect::<Vec<_>>();
+        let signing_parties = parties
+            .iter()
+            .filter(|(i, _)| signers.contains(i))
+            .map(|(i, k)| (*i, *k))
+            .collect::<BTreeMap<_, _>>();
+
+        let mut keygens = vec![];
+        let mut signs = vec![];
+        for (i, mux) in (0..N).zip(muxes) {
+            let delivery =
+                NetworkDeliveryWrapper::new(mux.clone(), i, keygen_session, parties.clone());
+            let party = round_based::MpcParty::connected(delivery);
+            keygens.push(tokio::spawn(async move {
+                let rng = &mut StdRng::seed_from_u64(u64::from(i));
+                keygen::run::<_, C, _>(
+                    rng,
+                    T,
+                    N,
+                    i,
+                    keygen::BroadcastCheck::Unchecked,
+                    None,
+                    party,
+                    None,
+                )
+                .await
+            }));
+            if !signers.contains(&i) {
+                continue;
+            }
+            let delivery =
+                NetworkDeliveryWrapper::new(mux, i, signing_session, signing_parties.clone());
+            let party = round_based::MpcParty::connected(delivery);
+            let (key_pkg, pub_key_pkg) = (key_pkgs[usize::from(i)].clone(), pub_key_pkg.clone());
+            let (signers, msg) = (signers.clone(), msg.clone());
+            signs.push(tokio::spawn(async move {
+                let rng = &mut StdRng::seed_from_u64(u64::from(i));
+                let malformed = sign::MalformedShares::Abort;
+                sign::run(
+                    rng,
+                    &key_pkg,
+                    &pub_key_pkg,
+                    &signers,
+                    &msg,
+                    malformed,
+                    sign::UnknownSigners::Abort,
+                    sign::MissingCommitments::Wait,
+                    None,
+                    party,
+                    None,
+                )
+                .await
+            }));
+        }
+
+        let timeout = Duration::from_secs(60);
+        let mut keys = vec![];
+        for task in keygens {
+            let (_, pkg) = tokio::time::timeout(timeout, task)
+                .await
+                .expect("keygen timed out")
+                .unwrap()
+                .unwrap();
+            keys.push(*pkg.verifying_key());
+        }
+        assert!(keys.windows(2).all(|w| w[0] == w[1]));
+        for task in signs {
+            let output = tokio::time::timeout(timeout, task)
+                .await
+                .expect("signing timed out")
+                .unwrap()
+                .unwrap();
+            pub_key_pkg
+                .verifying_key()
+                .verify(&msg, &output.signature)
+                .unwrap();
+        }
+    }
+
+    /// Collect the sessions active on the `contexts` until `done` is set.
+    fn watch_sessions(
+        contexts: &[crate::FrostContext],
+        done: Arc<std::sync::atomic::AtomicBool>,
+    ) -> tokio::task::JoinHandle<std::collections::BTreeSet<[u8; 32]>> {
+        let registries = contexts
+            .iter()
+            .map(|context| context.sessions.clone())
+            .collect::<Vec<_>>();
+        tokio::spawn(async move {
+            let mut seen = std::collections::BTreeSet::new();
+            while !done.load(std::sync::atomic::Ordering::SeqCst) {
+                for registry in &registries {
+                    seen.extend(registry.sessions.lock().active.keys().copied());
+                }
+                tokio::time::sleep(Duration::from_millis(1)).await;
+            }
+            seen
+        })
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn jobs_use_the_predicted_session_names() {
+        const CALL_ID: u64 = 935;
+        let network = MockNetwork::new(MockNetworkConfig {
+            latency: Duration::from_millis(50),
+            loss: 0.0,
+        });
+        let dir = crate::testing::TempDir::new("session-names");
+        // The operators left out of the signers learn the outcome of the others.
+        let contexts = crate::testing::operator_contexts(&network, &dir, 3, CALL_ID)
+            .into_iter()
+            .map(|context| context.with_unique_messages(true))
+            .collect::<Vec<_>>();
+
+        let done = Arc::new(std::sync::atomic::AtomicBool::new(false));
+        let watcher = watch_sessions(&contexts, done.clone());
+        let pubkey = crate::testing::keygen_on_all(&contexts, C::ID, 2).await;
+        let msg = b"predictable".to_vec();
+        // The operator left out of the signers fails.
+        crate::testing::sign_on_all(&contexts, &pubkey, &msg).await;
+        done.store(true, std::sync::atomic::Ordering::SeqCst);
+
+        let seen = watcher.await.unwrap();
+        let expected = [
+            config_check_session_name(CALL_ID),
+            keygen_session_name(CALL_ID, C::ID),
+            session_name(CALL_ID, &pubkey, &msg),
+            outcome_session_name(&session_name(CALL_ID, &pubkey, &msg)),
+        ];
+        assert_eq!(seen, expected.into_iter().collect());
+    }
+}
@@ -1,16 +1,14 @@
+use crate::dead_letter::DeadLetters;
+use crate::diagnostics::Recorder;
 use crate::rounds::sign as sign_protocol;
+use crate::rounds::trace::{PerfProfiler, TimingReport, Tracer};
 use api::services::events::JobCalled;
 use color_eyre::eyre;
 use frost_core::keys::{KeyPackage, PublicKeyPackage};
 use frost_core::{Ciphersuite, Signature};
-use gadget_sdk::contexts::MPCContext;
 use gadget_sdk::futures::TryFutureExt;
 use gadget_sdk::network::round_based_compat::NetworkDeliveryWrapper;
-use gadget_sdk::random::rand::seq::IteratorRandom;
-use gadget_sdk::random::SeedableRng;
 use gadget_sdk::subxt_core::ext::sp_core::ecdsa;
-use gadget_sdk::subxt_core::ext::sp_core::keccak_256;
-use gadget_sdk::subxt_core::ext::sp_core::Pair;
 use gadget_sdk::subxt_core::utils::AccountId32;
 use gadget_sdk::{self as sdk, random};
 use sdk::event_listener::tangle::{
@@ -18,22 +16,82 @@ use sdk::event_listener::tangle::{
     TangleEventListener,
 };
 use sdk::tangle_subxt::tangle_testnet_runtime::api;
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet};
 
+use crate::multiformats::Part;
+use crate::operators::OfflineSigners;
+use crate::receipt::ParticipationReceipt;
+use crate::replay::TraceRecorder;
+use crate::responsiveness::Responsiveness;
+use crate::transcript::TranscriptRecorder;
 use crate::FrostContext;
 
+/// The tag of the messages bound to a validity window, see [`validity_bound_message`].
+const VALIDITY_TAG: &[u8] = b"VALIDITY";
+
 #[derive(Debug, thiserror::Error)]
 pub enum Error {
     #[error("Unknown ciphersuite: {0}")]
     UnknwonCiphersuite(String),
     #[error("The Secret Share for that key is not found")]
     KeyNotFound,
-    #[error("Self not in operators")]
+    #[error("This node is not an operator of the service, it cannot sign")]
     SelfNotInOperators,
+    #[error(transparent)]
+    DuplicateInstance(#[from] crate::operators::DuplicateInstance),
+    #[error(transparent)]
+    IdentityMismatch(#[from] crate::operators::IdentityMismatch),
     #[error("Self not in signers")]
     SelfNotInSigners,
+    #[error("Only {online} signers are online, {required} are required")]
+    InsufficientSigners { online: usize, required: u16 },
+    #[error("Only {received} commitments arrived, {required} are required, the signers may be partitioned")]
+    QuorumNotReached { received: u16, required: u16 },
+    #[error(
+        "A member of the keygen committee is no longer an operator, or below the minimum restake"
+    )]
+    CommitteeChanged,
     #[error("Verifiying Share not found")]
     VerifyingShareNotFound,
+    #[error("The aggregated signature is not in the canonical encoding of the ciphersuite")]
+    NonCanonicalSignature,
+    #[error("The batch has no message to sign")]
+    EmptyBatch,
+    #[error("The message is rejected by the signing policy: {reason}")]
+    MessageRejected { reason: String },
+    #[error("The message is not well-formed: {reason}")]
+    InvalidMessageSchema { reason: String },
+    #[error(
+        "The key cannot sign at {now}, the time of the job call, outside of its signing windows"
+    )]
+    OutsideAllowedWindow { now: u64 },
+    #[error(
+        "The key already signed {signings} of the at most {max_signings} messages it can sign"
+    )]
+    KeyUsageLimitReached { signings: u64, max_signings: u64 },
+    #[error("The message was already signed with this key by the job call {call_id}")]
+    MessageAlreadySigned { call_id: u64 },
+    #[error("The validity window ends at {not_after}, before it starts at {not_before}")]
+    InvalidValidityWindow { not_before: u64, not_after: u64 },
+    #[error("The payload is not a message bound to a validity window")]
+    MalformedValidityPayload,
+    #[error("The signed message is only valid from {not_before} to {not_after}, not at {now}")]
+    OutsideValidityWindow {
+        not_before: u64,
+        not_after: u64,
+        now: u64,
+    },
+    #[error(transparent)]
+    NotParticipating(#[from] crate::operators::NotParticipating),
+    #[error(transparent)]
+    TooManySessions(#[from] crate::TooManySessions),
+    #[error(transparent)]
+    JobTimeout(#[from] crate::JobTimeout),
+    #[error("The signing timed out, {} of the selected signers did not respond", unresponsive.len())]
+    UnresponsiveSigners {
+        responsive: Vec<ecdsa::Public>,
+        unresponsive: Vec<ecdsa::Public>,
+    },
     #[error(transparent)]
     Subxt(#[from] sdk::tangle_subxt::subxt::Error),
     #[error(transparent)]
@@ -42,10 +100,21 @@ pub enum Error {
     Json(#[from] serde_json::Error),
     #[error(transparent)]
     Config(#[from] sdk::config::Error),
+    #[error("The network of this node is shut down")]
+    NetworkShutdown,
     #[error("Protocol error: {0}")]
-    Protocol(Box<dyn std::error::Error>),
+    Protocol(Box<dyn std::error::Error + Send + Sync>),
     #[error("Frost error: {0}")]
-    Frost(Box<dyn std::error::Error>),
+    Frost(Box<dyn std::error::Error + Send + Sync>),
+    #[error("Key derivation error: {0}")]
+    Derive(Box<dyn std::error::Error + Send + Sync>),
+    #[error(
+        "Typed data can only be signed with a {} key, not {0}",
+        frost_secp256k1::Secp256K1Sha256::ID
+    )]
+    TypedDataCiphersuite(String),
+    #[error(transparent)]
+    Eip712(#[from] crate::eip712::Error),
     #[error(transparent)]
     ToUnsigned16(#[from] std::num::TryFromIntError),
     #[error(transparent)]
@@ -56,30 +125,97 @@ pub enum Error {
 
 impl<C: Ciphersuite> From<frost_core::Error<C>> for Error {
     fn from(e: frost_core::Error<C>) -> Self {
-        Error::Frost(Box::new(e))
+        Error::Frost(e.to_string().into())
+    }
+}
+
+impl From<crate::rounds::resume::Error> for Error {
+    fn from(e: crate::rounds::resume::Error) -> Self {
+        match e.is_network_shutdown() {
+            true => Error::NetworkShutdown,
+            false => Error::Protocol(Box::new(e)),
+        }
+    }
+}
+
+impl From<crate::rounds::outcome::Error> for Error {
+    fn from(e: crate::rounds::outcome::Error) -> Self {
+        match e.is_network_shutdown() {
+            true => Error::NetworkShutdown,
+            false => Error::Protocol(Box::new(e)),
+        }
+    }
+}
+
+impl From<crate::rounds::randomness::Error> for Error {
+    fn from(e: crate::rounds::randomness::Error) -> Self {
+        match e.is_network_shutdown() {
+            true => Error::NetworkShutdown,
+            false => Error::Protocol(Box::new(e)),
+        }
+    }
+}
+
+impl<C: Ciphersuite> From<crate::derive::Error<C>> for Error {
+    fn from(e: crate::derive::Error<C>) -> Self {
+        Error::Derive(e.to_string().into())
     }
 }
 
 impl<C: Ciphersuite> From<sign_protocol::Error<C>> for Error {
     fn from(e: sign_protocol::Error<C>) -> Self {
-        Error::Protocol(Box::new(e))
+        if let Some((received, required)) = e.quorum_not_reached() {
+            return Error::QuorumNotReached { received, required };
+        }
+        match e.is_network_shutdown() {
+            true => Error::NetworkShutdown,
+            false => Error::Protocol(e.to_string().into()),
+        }
     }
 }
 
 /// Run Signing Protocol using a previously generated key and a message.
 ///
 /// # Parameters
-/// - `pubkey`: The public key generated by the [`crate::keygen::keygen`] protocol.
+/// - `pubkey`: The public key generated by the [`crate::keygen::keygen`] protocol, or its
+///   label, see [`crate::labels`].
 /// - `msg`: The message to sign.
 ///
 /// # Returns
-/// The Signature of the message hash (the hash function is defined by the ciphersuite).
+/// The Signature of the message hash (the hash function is defined by the ciphersuite), after
+/// the aggregate nonce `R` if enabled with [`FrostContext::with_aggregate_nonce`], itself after
+/// the number of the block of the call if enabled with
+/// [`FrostContext::with_block_bound_signing`], the message being then bound to it, see
+/// [`block_bound_message`]. Encoded as set with [`FrostContext::with_output_encoding`] and wrapped
+/// in a [`TimedOutput`](crate::rounds::trace::TimedOutput) if enabled with
+/// [`FrostContext::with_timing_report`].
 ///
 /// # Errors
+/// - `SelfNotInOperators`: If this node is not an operator of the service.
 /// - `KeyNotFound`: If the secret share for the key is not found.
+/// - `JobTimeout`: If the signing did not complete within [`FrostContext::with_job_timeout`].
+/// - `UnresponsiveSigners`: If it did not complete in time because some of the selected signers
+///   did not respond, reporting them apart from the responsive ones.
+/// - `InsufficientSigners`: If fewer operators than the threshold can sign, see
+///   [`FrostContext::with_offline_signers`].
+/// - `QuorumNotReached`: If fewer commitments than the threshold arrived in time, e.g. because
+///   of a network partition, see [`FrostContext::with_missing_commitments`].
+/// - `InvalidMessageSchema`: If the message is not well-formed for
+///   [`FrostContext::with_message_validator`].
+/// - `MessageRejected`: If the message is refused by [`FrostContext::with_message_policy`].
+/// - `OutsideAllowedWindow`: If the key is restricted to signing windows that do not contain
+///   the time of the job call, see [`crate::windows`].
+/// - `KeyUsageLimitReached`: If the key already signed as many messages as allowed, see
+///   [`crate::usage::set_key_usage_limit`].
+/// - `MessageAlreadySigned`: If the key already signed the message and signs every message at
+///   most once, see [`FrostContext::with_unique_messages`].
+/// - `NetworkShutdown`: If the network of this node shut down during the signing.
 /// # Note
-/// - `ciphersuite`: 0 for Ed25519, 1 for Secp256k1.
+/// - `ciphersuite`: The one of the keygen of `pubkey`, any of [`crate::keygen::keygen`].
 /// - `threshold`: The threshold of the keygen protocol should be less than the number of operators.
+/// - `msg`: The whole message is held in memory. `frost-core` hashes it with one-shot calls to
+///   the ciphersuite hashes, for the binding factors and for the challenge, so it cannot be fed
+///   to them in chunks: a message too large to hold should be signed as its digest.
 #[sdk::job(
     id = 1,
     params(pubkey, msg),
@@ -92,157 +228,2232 @@ impl<C: Ciphersuite> From<sign_protocol::Error<C>> for Error {
 )]
 #[tracing::instrument(skip_all, parent = context.config.span.clone(), err)]
 pub async fn sign(pubkey: Vec<u8>, msg: Vec<u8>, context: FrostContext) -> Result<Vec<u8>, Error> {
-    let pubkey_hex = hex::encode(&pubkey);
-    let kv = context.store.clone();
-    let raw_info = kv.get(&pubkey_hex)?.ok_or(Error::KeyNotFound)?;
-    let info_json_value = serde_json::from_slice::<serde_json::Value>(&raw_info)?;
+    let bind_block = context.block_bound;
+    sign_with_key("sign", pubkey, None, msg, bind_block, context).await
+}
+
+/// Run Signing Protocol using a one-time ephemeral key derived from a previously generated key.
+///
+/// The ephemeral key is tweaked with randomness the selected signers draw together in an extra
+/// round, see [`crate::derive::ephemeral_tweak`], so every signer derives the same key, and the
+/// key is never used again.
+///
+/// # Parameters
+/// - `pubkey`: The public key generated by the [`crate::keygen::keygen`] protocol, or its
+///   label, see [`crate::labels`].
+/// - `msg`: The message to sign.
+///
+/// # Returns
+/// The serialized ephemeral public key followed by the Signature of the message hash under it.
+///
+/// # Errors
+/// - `KeyNotFound`: If the secret share for the key is not found.
+///
+/// # Note
+/// No one but the operators can link the ephemeral key to its group key, not even those who
+/// know the job call, as long as one of the signers is honest.
+#[sdk::job(
+    id = 5,
+    params(pubkey, msg),
+    result(_),
+    event_listener(
+        listener = TangleEventListener::<FrostContext, JobCalled>,
+        pre_processor = services_pre_processor,
+        post_processor = services_post_processor,
+    )
+)]
+#[tracing::instrument(skip_all, parent = context.config.span.clone(), err)]
+pub async fn sign_ephemeral(
+    pubkey: Vec<u8>,
+    msg: Vec<u8>,
+    context: FrostContext,
+) -> Result<Vec<u8>, Error> {
+    sign_with_key(
+        "sign_ephemeral",
+        pubkey,
+        Some(Derivation::Ephemeral),
+        msg,
+        false,
+        context,
+    )
+    .await
+}
+
+/// Run Signing Protocol using a child key derived from a previously generated key.
+///
+/// # Parameters
+/// - `pubkey`: The public key generated by the [`crate::keygen::keygen`] protocol, or its
+///   label, see [`crate::labels`].
+/// - `index`: The index of the child key, see [`crate::derive::derive_child`].
+/// - `msg`: The message to sign.
+///
+/// # Returns
+/// The Signature of the message hash, verifiable against the public key returned by
+/// [`crate::derive::derive_verifying_key`] for the same `pubkey` and `index`, which is not
+/// the BIP32 child key at `index`.
+///
+/// # Errors
+/// - `KeyNotFound`: If the secret share for the key is not found.
+#[sdk::job(
+    id = 2,
+    params(pubkey, index, msg),
+    result(_),
+    event_listener(
+        listener = TangleEventListener::<FrostContext, JobCalled>,
+        pre_processor = services_pre_processor,
+        post_processor = services_post_processor,
+    )
+)]
+#[tracing::instrument(skip_all, parent = context.config.span.clone(), err)]
+pub async fn sign_derived(
+    pubkey: Vec<u8>,
+    index: u32,
+    msg: Vec<u8>,
+    context: FrostContext,
+) -> Result<Vec<u8>, Error> {
+    sign_with_key(
+        "sign_derived",
+        pubkey,
+        Some(Derivation::Index(index)),
+        msg,
+        false,
+        context,
+    )
+    .await
+}
+
+/// Run Signing Protocol over the EIP-712 digest of typed data, using a previously generated
+/// secp256k1 key.
+///
+/// # Parameters
+/// - `pubkey`: The public key generated by the [`crate::keygen::keygen`] protocol, or its
+///   label, see [`crate::labels`].
+/// - `typed_data`: The JSON typed data, with its domain and message, as accepted by
+///   `eth_signTypedData_v4`.
+///
+/// # Returns
+/// The Signature of the 32 bytes EIP-712 digest, see [`crate::eip712::typed_data_digest`].
+///
+/// # Errors
+/// - `KeyNotFound`: If the secret share for the key is not found.
+/// - `TypedDataCiphersuite`: If the key is not a secp256k1 key.
+/// - `Eip712`: If the typed data is malformed.
+#[sdk::job(
+    id = 4,
+    params(pubkey, typed_data),
+    result(_),
+    event_listener(
+        listener = TangleEventListener::<FrostContext, JobCalled>,
+        pre_processor = services_pre_processor,
+        post_processor = services_post_processor,
+    )
+)]
+#[tracing::instrument(skip_all, parent = context.config.span.clone(), err)]
+pub async fn sign_typed_data(
+    pubkey: Vec<u8>,
+    typed_data: String,
+    context: FrostContext,
+) -> Result<Vec<u8>, Error> {
+    let pubkey = context.resolve_key(pubkey)?;
+    let info_json_value = context
+        .keygen_info(&hex::encode(&pubkey))?
+        .ok_or(Error::KeyNotFound)?;
     let ciphersuite = info_json_value["ciphersuite"]
         .as_str()
         .ok_or(Error::KeyNotFound)?;
-    let operators = context
-        .current_service_operators_ecdsa_keys()
-        .map_err(Error::Other)
-        .await?;
+    if ciphersuite != frost_secp256k1::Secp256K1Sha256::ID {
+        return Err(Error::TypedDataCiphersuite(ciphersuite.to_string()));
+    }
+    let digest = crate::eip712::typed_data_digest(typed_data.as_bytes())?;
+    sign_with_key(
+        "sign_typed_data",
+        pubkey,
+        None,
+        digest.to_vec(),
+        false,
+        context,
+    )
+    .await
+}
 
-    let my_ecdsa = context.config.first_ecdsa_signer()?;
+/// Run Signing Protocol over a message bound to a validity window, using a previously generated
+/// key.
+///
+/// The window is part of the job call, so every signer binds the message to the same one
+/// whatever its own clock, and it is up to the verifier to check it, see [`check_validity`].
+///
+/// # Parameters
+/// - `pubkey`: The public key generated by the [`crate::keygen::keygen`] protocol, or its
+///   label, see [`crate::labels`].
+/// - `not_before`: The first second the message is valid at, as a UNIX timestamp.
+/// - `not_after`: The last second the message is valid at, as a UNIX timestamp.
+/// - `msg`: The message to sign.
+///
+/// # Returns
+/// The Signature of the message bound to the window, see [`validity_bound_message`].
+///
+/// # Errors
+/// - `KeyNotFound`: If the secret share for the key is not found.
+/// - `InvalidValidityWindow`: If the window ends before it starts.
+#[sdk::job(
+    id = 16,
+    params(pubkey, not_before, not_after, msg),
+    result(_),
+    event_listener(
+        listener = TangleEventListener::<FrostContext, JobCalled>,
+        pre_processor = services_pre_processor,
+        post_processor = services_post_processor,
+    )
+)]
+#[tracing::instrument(skip_all, parent = context.config.span.clone(), err)]
+pub async fn sign_with_validity(
+    pubkey: Vec<u8>,
+    not_before: u64,
+    not_after: u64,
+    msg: Vec<u8>,
+    context: FrostContext,
+) -> Result<Vec<u8>, Error> {
+    if not_after < not_before {
+        return Err(Error::InvalidValidityWindow {
+            not_before,
+            not_after,
+        });
+    }
+    let msg = validity_bound_message(&msg, not_before, not_after);
+    sign_with_key("sign_with_validity", pubkey, None, msg, false, context).await
+}
 
-    let i = operators
-        .values()
-        .position(|k| k == &my_ecdsa.signer().public())
-        .ok_or(Error::SelfNotInOperators)?;
-    let current_call_id = context.current_call_id().map_err(Error::Other).await?;
+/// Run Signing Protocol over a batch of messages using a previously generated key, with a single
+/// commitment round and a single signature share round for the whole batch.
+///
+/// Every signer uses fresh nonces for each message of the batch, see
+/// [`sign_protocol::run_batch`].
+///
+/// # Parameters
+/// - `pubkey`: The public key generated by the [`crate::keygen::keygen`] protocol, or its
+///   label, see [`crate::labels`].
+/// - `msgs`: The messages to sign.
+///
+/// # Returns
+/// The Signatures of the messages, in order and concatenated, each one of the fixed size of the
+/// ciphersuite.
+///
+/// # Errors
+/// - `KeyNotFound`: If the secret share for the key is not found.
+/// - `EmptyBatch`: If there is no message to sign.
+/// - `MessageAlreadySigned`: If the key already signed one of the messages and signs every
+///   message at most once, see [`FrostContext::with_unique_messages`].
+#[sdk::job(
+    id = 8,
+    params(pubkey, msgs),
+    result(_),
+    event_listener(
+        listener = TangleEventListener::<FrostContext, JobCalled>,
+        pre_processor = services_pre_processor,
+        post_processor = services_post_processor,
+    )
+)]
+#[tracing::instrument(skip_all, parent = context.config.span.clone(), err)]
+pub async fn batch_sign_shared_setup(
+    pubkey: Vec<u8>,
+    msgs: Vec<Vec<u8>>,
+    context: FrostContext,
+) -> Result<Vec<u8>, Error> {
+    let pubkey = context.resolve_key(pubkey)?;
+    let current_call_id = context.call_id().map_err(Error::Other).await?;
+    let result = context
+        .within_job_timeout(run_batch_signing(&pubkey, msgs, current_call_id, &context))
+        .await;
+    context.audit(
+        current_call_id,
+        "batch_sign_shared_setup",
+        Some(&pubkey),
+        result.as_ref().err(),
+    );
+    result
+}
+
+/// How the signing key is derived from the generated key.
+#[derive(Clone, Copy, Debug)]
+enum Derivation {
+    /// The child key at an index, see [`crate::derive::derive_child`].
+    Index(u32),
+    /// The one-time key of the signing session, see [`crate::derive::derive_ephemeral`].
+    Ephemeral,
+}
+
+/// Sign `msg` with the key `pubkey`, or with its key derived at `derivation` if any, bound to
+/// the block of the call if `bind_block`, and record the `job` in the audit log.
+async fn sign_with_key(
+    job: &str,
+    pubkey: Vec<u8>,
+    derivation: Option<Derivation>,
+    msg: Vec<u8>,
+    bind_block: bool,
+    context: FrostContext,
+) -> Result<Vec<u8>, Error> {
+    let pubkey = context.resolve_key(pubkey)?;
+    let current_call_id = context.call_id().map_err(Error::Other).await?;
+    let responsiveness = Responsiveness::default();
+    let result = context
+        .within_job_timeout(run_signing(
+            &pubkey,
+            derivation,
+            msg,
+            bind_block,
+            current_call_id,
+            &responsiveness,
+            &context,
+        ))
+        .await
+        .map_err(|e| unresponsive_signers(e, &responsiveness));
+    context.audit(current_call_id, job, Some(&pubkey), result.as_ref().err());
+    result
+}
+
+/// Explain the timeout `e` of a signing with the selected signers that did not respond.
+fn unresponsive_signers(e: Error, responsiveness: &Responsiveness) -> Error {
+    match (e, responsiveness.report()) {
+        (Error::JobTimeout(_), Some((responsive, unresponsive))) if !unresponsive.is_empty() => {
+            Error::UnresponsiveSigners {
+                responsive,
+                unresponsive,
+            }
+        }
+        (e, _) => e,
+    }
+}
+
+/// Run the signing of the job call `current_call_id`.
+async fn run_signing(
+    pubkey: &[u8],
+    derivation: Option<Derivation>,
+    msg: Vec<u8>,
+    bind_block: bool,
+    current_call_id: u64,
+    responsiveness: &Responsiveness,
+    context: &FrostContext,
+) -> Result<Vec<u8>, Error> {
+    context.participation.ensure_participating()?;
+    context.check_message(pubkey, &msg)?;
+    let me = context.local_identity::<Error>()?;
+    let operators = own_operators(&me, context).await?;
+    // The signatures are saved under the message as given.
+    let msg_hash = sdk::subxt_core::ext::sp_core::keccak_256(&msg);
+    let block = match bind_block {
+        true => Some(
+            context
+                .call_block(current_call_id)
+                .map_err(Error::Other)
+                .await?,
+        ),
+        false => None,
+    };
+    let msg = match block {
+        Some(block) => block_bound_message(&msg, block),
+        None => msg,
+    };
+    let info_json_value = context
+        .keygen_info(&hex::encode(pubkey))?
+        .ok_or(Error::KeyNotFound)?;
+    context
+        .check_signing_window(&info_json_value, current_call_id)
+        .await?;
+    context.check_usage_limit(pubkey, 1)?;
+    context.claim_messages(current_call_id, pubkey, &[msg_hash])?;
+    let ciphersuite = info_json_value["ciphersuite"]
+        .as_str()
+        .ok_or(Error::KeyNotFound)?;
+    let operators = key_holders(&info_json_value, operators)?;
+    let ephemeral = matches!(derivation, Some(Derivation::Ephemeral));
     let rng = random::rand::rngs::OsRng;
 
     let res = match ciphersuite {
         frost_ed25519::Ed25519Sha512::ID => {
             let entry: crate::keygen::KeygenEntry<frost_ed25519::Ed25519Sha512> =
                 serde_json::from_value(info_json_value["entry"].clone())?;
+            let (key_pkg, pub_key_pkg) = key_packages(entry, derivation)?;
             signing_internal(
                 rng,
-                my_ecdsa.signer().public(),
+                me,
                 operators,
-                entry.key_pkg,
-                entry.pub_key_pkg,
+                key_pkg,
+                pub_key_pkg,
+                ephemeral,
                 msg,
                 current_call_id,
-                &context,
+                responsiveness,
+                context,
             )
-            .map_ok(|s| s.serialize().ok())
+            .map_ok(|settled| {
+                settled.map(|((output, prefix), timing)| {
+                    signing_output(block, prefix, output, context, timing)
+                })
+            })
             .await
         }
         frost_secp256k1::Secp256K1Sha256::ID => {
             let entry: crate::keygen::KeygenEntry<frost_secp256k1::Secp256K1Sha256> =
                 serde_json::from_value(info_json_value["entry"].clone())?;
+            let (key_pkg, pub_key_pkg) = key_packages(entry, derivation)?;
+            signing_internal(
+                rng,
+                me,
+                operators,
+                key_pkg,
+                pub_key_pkg,
+                ephemeral,
+                msg,
+                current_call_id,
+                responsiveness,
+                context,
+            )
+            .map_ok(|settled| {
+                settled.map(|((output, prefix), timing)| {
+                    signing_output(block, prefix, output, context, timing)
+                })
+            })
+            .await
+        }
+        frost_ristretto255::Ristretto255Sha512::ID => {
+            let entry: crate::keygen::KeygenEntry<frost_ristretto255::Ristretto255Sha512> =
+                serde_json::from_value(info_json_value["entry"].clone())?;
+            let (key_pkg, pub_key_pkg) = key_packages(entry, derivation)?;
+            signing_internal(
+                rng,
+                me,
+                operators,
+                key_pkg,
+                pub_key_pkg,
+                ephemeral,
+                msg,
+                current_call_id,
+                responsiveness,
+                context,
+            )
+            .map_ok(|settled| {
+                settled.map(|((output, prefix), timing)| {
+                    signing_output(block, prefix, output, context, timing)
+                })
+            })
+            .await
+        }
+        frost_p256::P256Sha256::ID => {
+            let entry: crate::keygen::KeygenEntry<frost_p256::P256Sha256> =
+                serde_json::from_value(info_json_value["entry"].clone())?;
+            let (key_pkg, pub_key_pkg) = key_packages(entry, derivation)?;
+            signing_internal(
+                rng,
+                me,
+                operators,
+                key_pkg,
+                pub_key_pkg,
+                ephemeral,
+                msg,
+                current_call_id,
+                responsiveness,
+                context,
+            )
+            .map_ok(|settled| {
+                settled.map(|((output, prefix), timing)| {
+                    signing_output(block, prefix, output, context, timing)
+                })
+            })
+            .await
+        }
+        crate::redjubjub::JubjubBlake2b512::ID => {
+            let entry: crate::keygen::KeygenEntry<crate::redjubjub::JubjubBlake2b512> =
+                serde_json::from_value(info_json_value["entry"].clone())?;
+            let (key_pkg, pub_key_pkg) = key_packages(entry, derivation)?;
             signing_internal(
                 rng,
-                my_ecdsa.signer().public(),
+                me,
                 operators,
-                entry.key_pkg,
-                entry.pub_key_pkg,
+                key_pkg,
+                pub_key_pkg,
+                ephemeral,
                 msg,
                 current_call_id,
-                &context,
+                responsiveness,
+                context,
             )
-            .map_ok(|s| s.serialize().ok())
+            .map_ok(|settled| {
+                settled.map(|((output, prefix), timing)| {
+                    signing_output(block, prefix, output, context, timing)
+                })
+            })
             .await
         }
         _ => return Err(Error::UnknwonCiphersuite(ciphersuite.to_string())),
     };
 
+    // The message stays claimed unless the signing failed on every signer, so that it is not
+    // signed again by other signers, whether or not this node signed it.
     match res {
-        Ok(Some(signature)) => Ok(signature),
-        Err(Error::SelfNotInSigners) => {
-            // This is a special case where the signer is not in the signers list.
-            // This is a valid case, as the signer is not required to be in the signers list.
-            Err(Error::Other(eyre::eyre!(
-                "Self not in signers list, this is a valid case"
-            )))
+        Ok(Settled::Signed(Some((output, signature, timing)))) => {
+            context.save_signature(current_call_id, pubkey, &msg_hash, &signature);
+            context.record_usage(pubkey, 1, true)?;
+            let output = context.job_result(current_call_id, output, timing)?;
+            context.check_submission(current_call_id, &output);
+            Ok(output)
+        }
+        Ok(Settled::Signed(None)) => {
+            context.record_usage(pubkey, 1, true)?;
+            Err(Error::Other(eyre::eyre!("Signature serialization failed")))
+        }
+        Ok(Settled::SignedByOthers(e)) => {
+            context.record_usage(pubkey, 1, false)?;
+            Err(not_in_signers(e))
+        }
+        Ok(Settled::Unknown(e)) => Err(not_in_signers(e)),
+        Err(e) => {
+            context.release_messages(pubkey, &[msg_hash]);
+            Err(not_in_signers(e))
         }
-        Ok(None) => Err(Error::Other(eyre::eyre!("Signature serialization failed"))),
-        Err(e) => Err(e),
     }
 }
 
-/// A genaric signing protocol over a given ciphersuite.
-#[tracing::instrument(skip(rng, key_pkg, pub_key_pkg, msg, context))]
-#[allow(clippy::too_many_arguments)]
-async fn signing_internal<C, R>(
-    mut rng: R,
-    my_ecdsa_key: ecdsa::Public,
-    participants: BTreeMap<AccountId32, ecdsa::Public>,
-    key_pkg: KeyPackage<C>,
-    pub_key_pkg: PublicKeyPackage<C>,
-    msg: Vec<u8>,
-    call_id: u64,
-    context: &FrostContext,
-) -> Result<Signature<C>, Error>
-where
-    C: Ciphersuite + Send + Unpin,
-    <<C as Ciphersuite>::Group as frost_core::Group>::Element: Send + Unpin,
-    <<<C as Ciphersuite>::Group as frost_core::Group>::Field as frost_core::Field>::Scalar:
-        Send + Unpin,
-    R: random::RngCore + random::CryptoRng,
-{
-    let pub_key = pub_key_pkg.verifying_key().serialize()?;
-    let signers_seed = {
-        let mut key = pub_key.clone();
-        key.extend_from_slice(&msg);
-        keccak_256(&pub_key)
-    };
-
-    let t = *key_pkg.min_signers();
+/// The error of a signing that failed with `e` on this node.
+fn not_in_signers(e: Error) -> Error {
+    match e {
+        // This is a special case where the signer is not in the signers list.
+        // This is a valid case, as the signer is not required to be in the signers list.
+        Error::SelfNotInSigners => Error::Other(eyre::eyre!(
+            "Self not in signers list, this is a valid case"
+        )),
+        e => e,
+    }
+}
 
-    let mut signers_rng = rand_chacha::ChaChaRng::from_seed(signers_seed);
-    let signers = participants
+/// Run the batch signing of the job call `current_call_id`.
+async fn run_batch_signing(
+    pubkey: &[u8],
+    msgs: Vec<Vec<u8>>,
+    current_call_id: u64,
+    context: &FrostContext,
+) -> Result<Vec<u8>, Error> {
+    context.participation.ensure_participating()?;
+    if msgs.is_empty() {
+        return Err(Error::EmptyBatch);
+    }
+    for msg in &msgs {
+        context.check_message(pubkey, msg)?;
+    }
+    let me = context.local_identity::<Error>()?;
+    let operators = own_operators(&me, context).await?;
+    let batch = msgs.len() as u64;
+    let info_json_value = context
+        .keygen_info(&hex::encode(pubkey))?
+        .ok_or(Error::KeyNotFound)?;
+    context
+        .check_signing_window(&info_json_value, current_call_id)
+        .await?;
+    context.check_usage_limit(pubkey, batch)?;
+    let msg_hashes = msgs
         .iter()
-        .enumerate()
-        .map(|(i, (_, v))| (i as u16, *v))
-        .choose_multiple(&mut signers_rng, usize::from(t));
+        .map(|msg| sdk::subxt_core::ext::sp_core::keccak_256(msg))
+        .collect::<Vec<_>>();
+    context.claim_messages(current_call_id, pubkey, &msg_hashes)?;
+    let ciphersuite = info_json_value["ciphersuite"]
+        .as_str()
+        .ok_or(Error::KeyNotFound)?;
+    let operators = key_holders(&info_json_value, operators)?;
+    let rng = random::rand::rngs::OsRng;
+    let entry = info_json_value["entry"].clone();
 
-    let selected_parties: BTreeMap<u16, _> = signers.into_iter().collect();
-    let signers_ids: Vec<_> = selected_parties.keys().copied().collect();
+    let res = match ciphersuite {
+        frost_ed25519::Ed25519Sha512::ID => {
+            batch_signing_internal::<frost_ed25519::Ed25519Sha512, _>(
+                rng,
+                me,
+                operators,
+                serde_json::from_value(entry)?,
+                msgs,
+                current_call_id,
+                context,
+            )
+            .await
+        }
+        frost_secp256k1::Secp256K1Sha256::ID => {
+            batch_signing_internal::<frost_secp256k1::Secp256K1Sha256, _>(
+                rng,
+                me,
+                operators,
+                serde_json::from_value(entry)?,
+                msgs,
+                current_call_id,
+                context,
+            )
+            .await
+        }
+        frost_ristretto255::Ristretto255Sha512::ID => {
+            batch_signing_internal::<frost_ristretto255::Ristretto255Sha512, _>(
+                rng,
+                me,
+                operators,
+                serde_json::from_value(entry)?,
+                msgs,
+                current_call_id,
+                context,
+            )
+            .await
+        }
+        frost_p256::P256Sha256::ID => {
+            batch_signing_internal::<frost_p256::P256Sha256, _>(
+                rng,
+                me,
+                operators,
+                serde_json::from_value(entry)?,
+                msgs,
+                current_call_id,
+                context,
+            )
+            .await
+        }
+        crate::redjubjub::JubjubBlake2b512::ID => {
+            batch_signing_internal::<crate::redjubjub::JubjubBlake2b512, _>(
+                rng,
+                me,
+                operators,
+                serde_json::from_value(entry)?,
+                msgs,
+                current_call_id,
+                context,
+            )
+            .await
+        }
+        _ => return Err(Error::UnknwonCiphersuite(ciphersuite.to_string())),
+    };
 
-    let i = selected_parties
+    match res {
+        Ok(Settled::Signed((signatures, timing))) => {
+            context.record_usage(pubkey, batch, true)?;
+            let output = context.job_result(current_call_id, signatures, timing)?;
+            context.check_submission(current_call_id, &output);
+            Ok(output)
+        }
+        Ok(Settled::SignedByOthers(e)) => {
+            context.record_usage(pubkey, batch, false)?;
+            Err(not_in_signers(e))
+        }
+        Ok(Settled::Unknown(e)) => Err(not_in_signers(e)),
+        Err(e) => {
+            context.release_messages(pubkey, &msg_hashes);
+            Err(not_in_signers(e))
+        }
+    }
+}
+
+/// The current operators, checking that this node, with the key `me`, is one of them.
+///
+/// A node that is not an operator holds no share to sign with, so it fails with
+/// `SelfNotInOperators` before looking up the key or selecting the signers.
+async fn own_operators(
+    me: &ecdsa::Public,
+    context: &FrostContext,
+) -> Result<BTreeMap<AccountId32, ecdsa::Public>, Error> {
+    let operators = context.current_operators().map_err(Error::Other).await?;
+    crate::operators::own_index(&operators, me)?.ok_or(Error::SelfNotInOperators)?;
+    Ok(operators)
+}
+
+/// The operators holding a share of the key of the keygen entry `info`: its committee if it
+/// recorded one, see [`crate::keygen::KeygenEntry::committee`], or else all the `operators`.
+pub(crate) fn key_holders(
+    info: &serde_json::Value,
+    operators: BTreeMap<AccountId32, ecdsa::Public>,
+) -> Result<BTreeMap<AccountId32, ecdsa::Public>, Error> {
+    let committee: Option<BTreeSet<ecdsa::Public>> =
+        serde_json::from_value(info["entry"]["committee"].clone())?;
+    let Some(committee) = committee else {
+        return Ok(operators);
+    };
+    let members = operators
+        .into_iter()
+        .filter(|(_, k)| committee.contains(k))
+        .collect::<BTreeMap<_, _>>();
+    // The identifiers of the members follow their order, so they must all be there.
+    if members.len() != committee.len() {
+        return Err(Error::CommitteeChanged);
+    }
+    Ok(members)
+}
+
+/// The key packages to sign with, derived at `derivation` if any.
+///
+/// An ephemeral key is only derived once the signers are selected, from the randomness they
+/// draw, see [`signing_internal`], so the generated key packages are returned for it.
+fn key_packages<C: Ciphersuite>(
+    entry: crate::keygen::KeygenEntry<C>,
+    derivation: Option<Derivation>,
+) -> Result<(KeyPackage<C>, PublicKeyPackage<C>), Error> {
+    match derivation {
+        Some(Derivation::Index(index)) => Ok(crate::derive::derive_child(
+            &entry.key_pkg,
+            &entry.pub_key_pkg,
+            index,
+        )?),
+        Some(Derivation::Ephemeral) | None => Ok((entry.key_pkg, entry.pub_key_pkg)),
+    }
+}
+
+/// The `msg` bound to the block number `block`.
+///
+/// As signed by the `sign` job with [`FrostContext::with_block_bound_signing`]: the big-endian
+/// `u64` length of `msg`, `msg`, then the big-endian `u64` block number.
+pub fn block_bound_message(msg: &[u8], block: u64) -> Vec<u8> {
+    [
+        &(msg.len() as u64).to_be_bytes()[..],
+        msg,
+        &block.to_be_bytes(),
+    ]
+    .concat()
+}
+
+/// The `msg` bound to the validity window from `not_before` to `not_after`, both included.
+///
+/// As signed by the [`sign_with_validity`] job: the 8 bytes `VALIDITY`, the big-endian `u64`
+/// `not_before` and `not_after` UNIX timestamps, then `msg`.
+pub fn validity_bound_message(msg: &[u8], not_before: u64, not_after: u64) -> Vec<u8> {
+    [
+        VALIDITY_TAG,
+        &not_before.to_be_bytes(),
+        &not_after.to_be_bytes(),
+        msg,
+    ]
+    .concat()
+}
+
+/// The message of a `payload` signed by the [`sign_with_validity`] job, if it is valid at `now`.
+///
+/// The signature must be verified over the whole `payload`, before or after the window is
+/// checked.
+pub fn check_validity(payload: &[u8], now: u64) -> Result<&[u8], Error> {
+    let rest = payload
+        .strip_prefix(VALIDITY_TAG)
+        .ok_or(Error::MalformedValidityPayload)?;
+    let (not_before, rest) = rest
+        .split_first_chunk::<8>()
+        .ok_or(Error::MalformedValidityPayload)?;
+    let (not_after, msg) = rest
+        .split_first_chunk::<8>()
+        .ok_or(Error::MalformedValidityPayload)?;
+    let (not_before, not_after) = (
+        u64::from_be_bytes(*not_before),
+        u64::from_be_bytes(*not_after),
+    );
+    if !(not_before..=not_after).contains(&now) {
+        return Err(Error::OutsideValidityWindow {
+            not_before,
+            not_after,
+            now,
+        });
+    }
+    Ok(msg)
+}
+
+/// The job output of a signing `output`, in the encoding set with
+/// [`FrostContext::with_output_encoding`]: the `block` number if bound to one, the aggregate
+/// nonce if enabled with [`FrostContext::with_aggregate_nonce`], the `prefix` key if any and the
+/// signature. Along with the serialized signature after the block number and the `prefix`, as
+/// saved.
+#[allow(clippy::type_complexity)]
+fn signing_output<C: Ciphersuite>(
+    block: Option<u64>,
+    prefix: Vec<u8>,
+    output: sign_protocol::Output<C>,
+    context: &FrostContext,
+    timing: Option<TimingReport>,
+) -> Option<(Vec<u8>, Vec<u8>, Option<TimingReport>)> {
+    let nonce = context
+        .aggregate_nonce
+        .then(|| <C::Group as frost_core::Group>::serialize(&output.nonce))
+        .transpose()
+        .ok()?;
+    let signature = output.signature.serialize().ok()?;
+    let block = block.map(u64::to_be_bytes);
+    let mut parts = vec![];
+    if let Some(block) = &block {
+        parts.push((Part::BlockNumber, block.as_slice()));
+    }
+    if let Some(nonce) = &nonce {
+        parts.push((Part::AggregateNonce, nonce.as_ref()));
+    }
+    if !prefix.is_empty() {
+        parts.push((Part::EphemeralKey, prefix.as_slice()));
+    }
+    parts.push((Part::Signature, signature.as_slice()));
+    let job_output = crate::multiformats::output(context.output_encoding, C::ID, &parts).ok()?;
+    let block = block.map(Vec::from).unwrap_or_default();
+    Some((job_output, [block, prefix, signature].concat(), timing))
+}
+
+/// Select the `t` signers of the session seeded with `signers_seed` among the `participants`
+/// not paused when the job call `call_id` was made, and the index of this node among them, if
+/// it is one of them.
+///
+/// Fails with `InsufficientSigners` if fewer than `t` of them can sign, or are reachable under
+/// [`OfflineSigners::FailFast`].
+async fn select_signers(
+    my_ecdsa_key: ecdsa::Public,
+    participants: &BTreeMap<AccountId32, ecdsa::Public>,
+    signers_seed: [u8; 32],
+    t: u16,
+    call_id: u64,
+    context: &FrostContext,
+) -> Result<(Option<u16>, BTreeMap<u16, ecdsa::Public>), Error> {
+    if let (OfflineSigners::FailFast, Some(peers)) =
+        (context.offline_signers, &context.connected_peers)
+    {
+        let connected = peers.connected().await;
+        let online = participants
+            .values()
+            .filter(|key| **key == my_ecdsa_key || connected.contains(key))
+            .count();
+        if online < usize::from(t) {
+            return Err(Error::InsufficientSigners {
+                online,
+                required: t,
+            });
+        }
+    }
+    // Every node reads the paused operators in the block of the call, so they all leave out the
+    // same ones whatever their view of the chain.
+    let paused = context
+        .paused_operators(call_id, participants)
+        .await
+        .map_err(Error::Other)?;
+    let selected_parties =
+        crate::operators::select_signers_excluding(participants, &paused, signers_seed, t);
+    // Too many paused operators, the same on every node.
+    if selected_parties.len() < usize::from(t) {
+        return Err(Error::InsufficientSigners {
+            online: selected_parties.len(),
+            required: t,
+        });
+    }
+
+    let i = selected_parties
         .iter()
         .position(|(_, v)| v == &my_ecdsa_key)
-        .ok_or(Error::SelfNotInSigners)?;
+        .map(u16::try_from)
+        .transpose()?;
+    Ok((i, selected_parties))
+}
+
+/// The settled output of a signing, with the timing of its rounds.
+type Timed<T> = Settled<(T, Option<TimingReport>)>;
+
+/// How a signing ended, as seen by this node once it learnt the outcome of the other signers.
+enum Settled<T> {
+    /// This node produced the signatures.
+    Signed(T),
+    /// Another signer produced them, while this node failed with the error, `SelfNotInSigners`
+    /// if it was not selected.
+    SignedByOthers(Error),
+    /// This node failed with the error and could not learn the outcome of the other signers.
+    Unknown(Error),
+}
+
+impl<T> Settled<T> {
+    /// The outcome of a signing this node ended with `signed`, and where the other signers
+    /// produced the signatures if `others` is true, see [`settle`], or `None` if the outcome of
+    /// the other signers was not asked for.
+    ///
+    /// Fails with the error of this node if no signer produced them.
+    fn new(signed: Result<T, Error>, others: Option<Result<bool, Error>>) -> Result<Self, Error> {
+        match (signed, others) {
+            (Ok(signed), _) => Ok(Settled::Signed(signed)),
+            (Err(e), None) => Ok(Settled::Unknown(e)),
+            (Err(e), Some(Ok(true))) => Ok(Settled::SignedByOthers(e)),
+            (Err(e), Some(Ok(false))) => Err(e),
+            (Err(e), Some(Err(outcome))) => {
+                tracing::warn!(error = %outcome, "Failed to learn the outcome of the other signers");
+                Ok(Settled::Unknown(e))
+            }
+        }
+    }
+
+    fn map<U>(self, f: impl FnOnce(T) -> U) -> Settled<U> {
+        match self {
+            Settled::Signed(signed) => Settled::Signed(f(signed)),
+            Settled::SignedByOthers(e) => Settled::SignedByOthers(e),
+            Settled::Unknown(e) => Settled::Unknown(e),
+        }
+    }
+}
+
+/// Learn whether a signer of the `selected_parties` produced the signatures of `msgs` with the
+/// `verifying_key` in the signing session `session`, this node having produced `signatures` if
+/// any, see [`outcome`](crate::rounds::outcome).
+///
+/// Every operator holding a share of the key, the `participants`, takes part, the ones not
+/// selected included, so that they all keep their claim on the messages once signed, see
+/// [`FrostContext::with_unique_messages`]. The round is skipped, returning `None`, when the
+/// messages are not claimed, and fails if the outcome does not arrive within
+/// [`FrostContext::with_signing_timeout`], e.g. because a signer crashed.
+#[allow(clippy::too_many_arguments)]
+async fn settle<C: Ciphersuite>(
+    my_ecdsa_key: &ecdsa::Public,
+    participants: &BTreeMap<AccountId32, ecdsa::Public>,
+    selected_parties: &BTreeMap<u16, ecdsa::Public>,
+    session: [u8; 32],
+    verifying_key: &frost_core::VerifyingKey<C>,
+    msgs: &[Vec<u8>],
+    signatures: Option<Vec<Vec<u8>>>,
+    context: &FrostContext,
+) -> Option<Result<bool, Error>> {
+    if !context.unique_messages {
+        return None;
+    }
+    let outcome = tokio::time::timeout(
+        context.signing_timeout,
+        run_outcome(
+            my_ecdsa_key,
+            participants,
+            selected_parties,
+            session,
+            verifying_key,
+            msgs,
+            signatures,
+            context,
+        ),
+    )
+    .await
+    .unwrap_or_else(|_| {
+        Err(Error::Other(eyre::eyre!(
+            "The outcome of the signers did not arrive within {:?}",
+            context.signing_timeout
+        )))
+    });
+    Some(outcome)
+}
+
+/// Run the outcome round of [`settle`].
+#[allow(clippy::too_many_arguments)]
+async fn run_outcome<C: Ciphersuite>(
+    my_ecdsa_key: &ecdsa::Public,
+    participants: &BTreeMap<AccountId32, ecdsa::Public>,
+    selected_parties: &BTreeMap<u16, ecdsa::Public>,
+    session: [u8; 32],
+    verifying_key: &frost_core::VerifyingKey<C>,
+    msgs: &[Vec<u8>],
+    signatures: Option<Vec<Vec<u8>>>,
+    context: &FrostContext,
+) -> Result<bool, Error> {
+    let i = crate::operators::own_index(participants, my_ecdsa_key)?
+        .ok_or(Error::SelfNotInOperators)?;
+    let i = u16::try_from(i)?;
+    let parties = crate::operators::party_indices(participants)
+        .map(|(j, _, key)| (j, *key))
+        .collect::<BTreeMap<_, _>>();
+    let session = crate::session::outcome_session_name(&session);
+    let _session = context.sessions.register(session, "outcome")?;
+    let delivery =
+        NetworkDeliveryWrapper::new(context.network_backend.clone(), i, session, parties);
+    let delivery = crate::codec::primed(delivery);
+    let party = round_based::MpcParty::connected(crate::codec::versioned(delivery, context.codec));
+    let signers = selected_parties.keys().copied().collect();
+    let outcome = crate::rounds::outcome::run(
+        i,
+        &signers,
+        signatures,
+        |signatures| chunk_verifies(verifying_key, msgs, signatures),
+        party,
+    )
+    .await?;
+    Ok(outcome.is_some())
+}
 
+/// Draw the randomness of the ephemeral key of the signing session `session` among the
+/// `selected_parties`, see [`randomness`](crate::rounds::randomness).
+///
+/// The other operators holding a share of the key, the `participants`, listen, so that they
+/// can check the outcome of the signers under the ephemeral key, see [`settle`]. Fails if the
+/// contributions do not all arrive within [`FrostContext::with_signing_timeout`].
+async fn draw_randomness<R: random::RngCore + random::CryptoRng>(
+    rng: &mut R,
+    my_ecdsa_key: &ecdsa::Public,
+    participants: &BTreeMap<AccountId32, ecdsa::Public>,
+    selected_parties: &BTreeMap<u16, ecdsa::Public>,
+    session: [u8; 32],
+    context: &FrostContext,
+) -> Result<[u8; 32], Error> {
+    let i = crate::operators::own_index(participants, my_ecdsa_key)?
+        .ok_or(Error::SelfNotInOperators)?;
     let i = u16::try_from(i)?;
-    assert_eq!(
-        signers_ids.len(),
-        usize::from(*key_pkg.min_signers()),
-        "Invalid number of signers"
-    );
+    let parties = crate::operators::party_indices(participants)
+        .map(|(j, _, key)| (j, *key))
+        .collect::<BTreeMap<_, _>>();
+    let session = crate::session::ephemeral_session_name(&session);
+    let _session = context.sessions.register(session, "randomness")?;
+    let delivery =
+        NetworkDeliveryWrapper::new(context.network_backend.clone(), i, session, parties);
+    let delivery = crate::codec::primed(delivery);
+    let party = round_based::MpcParty::connected(crate::codec::versioned(delivery, context.codec));
+    let contributors = selected_parties.keys().copied().collect();
+    let timeout = context.signing_timeout;
+    Ok(crate::rounds::randomness::run(rng, i, &contributors, timeout, party).await?)
+}
+
+/// A genaric signing protocol over a given ciphersuite.
+///
+/// With `ephemeral`, the selected signers sign under a one-time key derived from randomness
+/// they draw together, see [`crate::derive::derive_ephemeral`], and the output comes with its
+/// serialized verifying key, otherwise with an empty one.
+#[tracing::instrument(skip(rng, key_pkg, pub_key_pkg, msg, responsiveness, context))]
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
+async fn signing_internal<C, R>(
+    mut rng: R,
+    my_ecdsa_key: ecdsa::Public,
+    participants: BTreeMap<AccountId32, ecdsa::Public>,
+    key_pkg: KeyPackage<C>,
+    pub_key_pkg: PublicKeyPackage<C>,
+    ephemeral: bool,
+    msg: Vec<u8>,
+    call_id: u64,
+    responsiveness: &Responsiveness,
+    context: &FrostContext,
+) -> Result<Timed<(sign_protocol::Output<C>, Vec<u8>)>, Error>
+where
+    C: Ciphersuite + Send + Unpin + 'static,
+    <<C as Ciphersuite>::Group as frost_core::Group>::Element: Send + Unpin,
+    <<<C as Ciphersuite>::Group as frost_core::Group>::Field as frost_core::Field>::Scalar:
+        Send + Unpin,
+    R: random::RngCore + random::CryptoRng,
+{
+    let pub_key = pub_key_pkg.verifying_key().serialize()?;
+    let signers_seed = crate::session::signers_seed(call_id, &pub_key, &msg);
+
+    let (i, selected_parties) = select_signers(
+        my_ecdsa_key,
+        &participants,
+        signers_seed,
+        *key_pkg.min_signers(),
+        call_id,
+        context,
+    )
+    .await?;
+    let signing_task_hash = crate::session::session_name(call_id, &pub_key, &msg);
+    // The operators left out only listen to the randomness to settle the outcome.
+    if ephemeral && i.is_none() && !context.unique_messages {
+        return Ok(Settled::Unknown(Error::SelfNotInSigners));
+    }
+    let (key_pkg, pub_key_pkg) = match ephemeral {
+        true => {
+            let randomness = draw_randomness(
+                &mut rng,
+                &my_ecdsa_key,
+                &participants,
+                &selected_parties,
+                signing_task_hash,
+                context,
+            )
+            .await;
+            // Without the ephemeral key the outcome of the signers cannot be checked.
+            let randomness = match randomness {
+                Ok(randomness) => randomness,
+                Err(e) => return Ok(Settled::Unknown(e)),
+            };
+            crate::derive::derive_ephemeral(&key_pkg, &pub_key_pkg, &randomness)?
+        }
+        false => (key_pkg, pub_key_pkg),
+    };
+    let prefix = match ephemeral {
+        true => pub_key_pkg.verifying_key().serialize()?,
+        false => Vec::new(),
+    };
+    let signed = match i {
+        Some(i) => {
+            sign_as(
+                &mut rng,
+                i,
+                &selected_parties,
+                &key_pkg,
+                &pub_key_pkg,
+                &msg,
+                signing_task_hash,
+                call_id,
+                responsiveness,
+                context,
+            )
+            .await
+        }
+        None => Err(Error::SelfNotInSigners),
+    };
+    let signatures = match &signed {
+        Ok((output, _)) => Some(vec![output.signature.serialize()?]),
+        Err(_) => None,
+    };
+    let signed = signed.map(|(output, timing)| ((output, prefix), timing));
+    let settled = settle(
+        &my_ecdsa_key,
+        &participants,
+        &selected_parties,
+        signing_task_hash,
+        pub_key_pkg.verifying_key(),
+        std::slice::from_ref(&msg),
+        signatures,
+        context,
+    )
+    .await;
+    Settled::new(signed, settled)
+}
+
+/// Run the signing protocol of `msg` as the signer `i` of the `selected_parties`, in the
+/// network session `signing_task_hash`.
+#[allow(clippy::too_many_arguments)]
+async fn sign_as<C, R>(
+    rng: &mut R,
+    i: u16,
+    selected_parties: &BTreeMap<u16, ecdsa::Public>,
+    key_pkg: &KeyPackage<C>,
+    pub_key_pkg: &PublicKeyPackage<C>,
+    msg: &[u8],
+    signing_task_hash: [u8; 32],
+    call_id: u64,
+    responsiveness: &Responsiveness,
+    context: &FrostContext,
+) -> Result<(sign_protocol::Output<C>, Option<TimingReport>), Error>
+where
+    C: Ciphersuite + Send + Unpin + 'static,
+    <<C as Ciphersuite>::Group as frost_core::Group>::Element: Send + Unpin,
+    <<<C as Ciphersuite>::Group as frost_core::Group>::Field as frost_core::Field>::Scalar:
+        Send + Unpin,
+    R: random::RngCore + random::CryptoRng,
+{
+    let pub_key = pub_key_pkg.verifying_key().serialize()?;
+    let signers_ids: Vec<_> = selected_parties.keys().copied().collect();
+    responsiveness.select(i, selected_parties);
 
-    let signing_task_hash =
-        gadget_sdk::compute_sha256_hash!(call_id.to_be_bytes(), &msg, "frost-signing");
+    let _session = context.sessions.register(signing_task_hash, "signing")?;
 
+    let recorder = context.recorder(
+        call_id,
+        "signing",
+        C::ID,
+        i,
+        selected_parties.values().map(|k| k.0),
+    );
     let delivery = NetworkDeliveryWrapper::new(
         context.network_backend.clone(),
         i,
         signing_task_hash,
         selected_parties.clone(),
     );
+    let delivery = context.prioritize(signing_task_hash, delivery);
+    let letters = context.dead_letters(call_id, "signing", i);
+    let delivery = DeadLetters::wrap(letters.as_ref(), delivery);
+    let delivery = responsiveness.track(delivery);
+    let delivery = Recorder::record(recorder.as_ref(), delivery);
+    let signer = context.config.first_ecdsa_signer()?.signer().clone();
+    let transcript = TranscriptRecorder::new(
+        call_id,
+        signing_task_hash,
+        i,
+        signer,
+        selected_parties.values(),
+    );
+    let delivery = transcript.record(delivery);
+    let trace = context.trace_recorder(call_id, "signing", i);
+    let delivery = TraceRecorder::record(trace.as_ref(), delivery);
 
-    let party = round_based::MpcParty::connected(delivery);
-    let signature = sign_protocol::run::<R, C, _>(
-        &mut rng,
-        &key_pkg,
-        &pub_key_pkg,
+    let party = round_based::MpcParty::connected(crate::codec::versioned(delivery, context.codec));
+    let mut profiler = context.timing_report.then(PerfProfiler::new);
+    let nonces = context.nonce_provider::<C>();
+    let output = sign_protocol::run::<R, C, _>(
+        rng,
+        key_pkg,
+        pub_key_pkg,
         &signers_ids,
-        &msg,
+        msg,
+        context.malformed_shares,
+        context.unknown_signers,
+        context.missing_commitments,
+        nonces.as_deref(),
         party,
-        None,
+        profiler.as_mut().map(|p| p as &mut dyn Tracer),
     )
-    .await?;
+    .await;
+    context.save_transcript(&transcript);
+    let output = output.inspect_err(|e| context.save_diagnostics(recorder, e))?;
+    let timing = profiler.and_then(|p| p.timing_report());
+    let signature = output.signature;
+    ensure_canonical(&signature)?;
+    context.save_receipt(ParticipationReceipt::new(
+        call_id,
+        signing_task_hash,
+        &pub_key,
+        selected_parties.values(),
+    ));
 
     sdk::debug!(
-        pubkey = %hex::encode(pub_key),
+        pubkey = %context.log_redaction.redact(&hex::encode(&pub_key)),
         signature = %hex::encode(signature.serialize()?),
-        msg = %hex::encode(&msg),
+        msg = %context.log_redaction.redact(&hex::encode(msg)),
         "Signing Done"
     );
-    Ok(signature)
+
+    // Every signer ends up with the same signature, only the first one notifies the webhook.
+    #[cfg(feature = "webhook")]
+    if let (0, Some(webhook)) = (i, context.webhook.clone()) {
+        let notification = crate::webhook::SignatureNotification {
+            pubkey: hex::encode(&pub_key),
+            msg_hash: hex::encode(sdk::subxt_core::ext::sp_core::keccak_256(msg)),
+            signature: hex::encode(signature.serialize()?),
+        };
+        tokio::spawn(async move { webhook.notify(&notification).await });
+    }
+    Ok((output, timing))
+}
+
+/// Check that `signature` is in the canonical encoding of the ciphersuite, which strict
+/// verifiers require.
+///
+/// The commitment must be a valid encoding of a group element and the response a scalar
+/// reduced modulo the group order, so that decoding the signature and encoding it again gives
+/// back the same bytes. Unlike ECDSA, a Schnorr response cannot be negated, so there is no low-S
+/// form to normalize to.
+fn ensure_canonical<C: Ciphersuite>(signature: &Signature<C>) -> Result<(), Error> {
+    if is_canonical::<C>(&signature.serialize()?) {
+        Ok(())
+    } else {
+        Err(Error::NonCanonicalSignature)
+    }
+}
+
+fn is_canonical<C: Ciphersuite>(bytes: &[u8]) -> bool {
+    Signature::<C>::deserialize(bytes)
+        .and_then(|signature| signature.serialize())
+        .is_ok_and(|encoded| encoded == bytes)
+}
+
+/// The batch signing protocol over a given ciphersuite, returning the concatenated signatures.
+#[tracing::instrument(skip(rng, entry, msgs, context))]
+async fn batch_signing_internal<C, R>(
+    mut rng: R,
+    my_ecdsa_key: ecdsa::Public,
+    participants: BTreeMap<AccountId32, ecdsa::Public>,
+    entry: crate::keygen::KeygenEntry<C>,
+    msgs: Vec<Vec<u8>>,
+    call_id: u64,
+    context: &FrostContext,
+) -> Result<Timed<Vec<u8>>, Error>
+where
+    C: Ciphersuite + Send + Unpin,
+    <<C as Ciphersuite>::Group as frost_core::Group>::Element: Send + Unpin,
+    <<<C as Ciphersuite>::Group as frost_core::Group>::Field as frost_core::Field>::Scalar:
+        Send + Unpin,
+    R: random::RngCore + random::CryptoRng,
+{
+    let crate::keygen::KeygenEntry {
+        key_pkg,
+        pub_key_pkg,
+        ..
+    } = entry;
+    let pub_key = pub_key_pkg.verifying_key().serialize()?;
+    let batch = crate::session::batch_digest(&msgs);
+    let checkpoint = context
+        .batch_checkpoints
+        .map(|chunk| (chunk, crate::checkpoint::batch_id(&pub_key, &batch, chunk)));
+    // The signers of a checkpointed batch are the same for every job call signing it, so that
+    // the ones holding its checkpoints resume it.
+    let signers_seed = match checkpoint {
+        Some(_) => crate::session::batch_signers_seed(&pub_key, &batch),
+        None => crate::session::signers_seed(call_id, &pub_key, &batch),
+    };
+    let (i, selected_parties) = select_signers(
+        my_ecdsa_key,
+        &participants,
+        signers_seed,
+        *key_pkg.min_signers(),
+        call_id,
+        context,
+    )
+    .await?;
+    let signed = match i {
+        Some(i) => {
+            batch_sign_as(
+                &mut rng,
+                i,
+                &selected_parties,
+                &key_pkg,
+                &pub_key_pkg,
+                &msgs,
+                checkpoint,
+                call_id,
+                context,
+            )
+            .await
+        }
+        None => Err(Error::SelfNotInSigners),
+    };
+    let settled = settle(
+        &my_ecdsa_key,
+        &participants,
+        &selected_parties,
+        crate::session::session_name(call_id, &pub_key, &batch),
+        pub_key_pkg.verifying_key(),
+        &msgs,
+        signed
+            .as_ref()
+            .ok()
+            .map(|(signatures, _)| signatures.clone()),
+        context,
+    )
+    .await;
+    Settled::new(
+        signed.map(|(signatures, timing)| (signatures.concat(), timing)),
+        settled,
+    )
+}
+
+/// Sign the batch of `msgs` as the signer `i` of the `selected_parties`, in chunks from the
+/// checkpoints of `checkpoint` if any, returning the serialized signatures.
+#[allow(clippy::too_many_arguments)]
+async fn batch_sign_as<C, R>(
+    rng: &mut R,
+    i: u16,
+    selected_parties: &BTreeMap<u16, ecdsa::Public>,
+    key_pkg: &KeyPackage<C>,
+    pub_key_pkg: &PublicKeyPackage<C>,
+    msgs: &[Vec<u8>],
+    checkpoint: Option<(std::num::NonZeroUsize, [u8; 32])>,
+    call_id: u64,
+    context: &FrostContext,
+) -> Result<(Vec<Vec<u8>>, Option<TimingReport>), Error>
+where
+    C: Ciphersuite + Send + Unpin,
+    <<C as Ciphersuite>::Group as frost_core::Group>::Element: Send + Unpin,
+    <<<C as Ciphersuite>::Group as frost_core::Group>::Field as frost_core::Field>::Scalar:
+        Send + Unpin,
+    R: random::RngCore + random::CryptoRng,
+{
+    let pub_key = pub_key_pkg.verifying_key().serialize()?;
+    let batch = crate::session::batch_digest(msgs);
+
+    let Some((chunk, batch_id)) = checkpoint else {
+        let session = crate::session::session_name(call_id, &pub_key, &batch);
+        let (signatures, timing) = batch_session(
+            rng,
+            i,
+            selected_parties,
+            key_pkg,
+            pub_key_pkg,
+            msgs,
+            session,
+            call_id,
+            context,
+        )
+        .await?;
+        context.save_receipt(ParticipationReceipt::new(
+            call_id,
+            session,
+            &pub_key,
+            selected_parties.values(),
+        ));
+        return batch_output(&pub_key, i, msgs, signatures, timing, context);
+    };
+    let mut checkpoints = crate::checkpoint::load(&context.store, &batch_id)?;
+    let chunks = msgs.chunks(chunk.get()).collect::<Vec<_>>();
+    checkpoints.retain(|k, signed| {
+        let verifies = chunks.get(*k).is_some_and(|chunk_msgs| {
+            chunk_verifies(pub_key_pkg.verifying_key(), chunk_msgs, signed)
+        });
+        if !verifies {
+            tracing::warn!(
+                chunk = k,
+                "Checkpoint does not verify, signing the chunk again"
+            );
+        }
+        verifies
+    });
+    // The signers may hold different chunks, e.g. when one of them failed to write its
+    // checkpoint, so they agree on the chunk to resume from before signing the others.
+    let resume = resume_point(
+        i,
+        selected_parties,
+        &checkpoints,
+        &pub_key,
+        &batch,
+        call_id,
+        context,
+    )
+    .await?;
+    checkpoints.retain(|k, _| (*k as u64) < resume);
+    let mut signatures = Vec::with_capacity(msgs.len());
+    let mut timing = None;
+    for (k, chunk_msgs) in chunks.into_iter().enumerate() {
+        if let Some(signed) = checkpoints.get(&k) {
+            tracing::debug!(chunk = k, "Chunk already signed, skipping it");
+            for signature in signed {
+                signatures.push(Signature::<C>::deserialize(signature)?);
+            }
+            continue;
+        }
+        let session = crate::session::session_name(
+            call_id,
+            &pub_key,
+            &crate::session::chunk_digest(&batch, k),
+        );
+        let (signed, chunk_timing) = batch_session(
+            rng,
+            i,
+            selected_parties,
+            key_pkg,
+            pub_key_pkg,
+            chunk_msgs,
+            session,
+            call_id,
+            context,
+        )
+        .await?;
+        let serialized = signed
+            .iter()
+            .map(|signature| signature.serialize())
+            .collect::<Result<Vec<_>, _>>()?;
+        checkpoints.insert(k, serialized);
+        crate::checkpoint::save(&context.store, &batch_id, &checkpoints)?;
+        signatures.extend(signed);
+        timing = timing.or(chunk_timing);
+    }
+    // The chunks each run in their own session, the receipt names the one of the whole batch.
+    context.save_receipt(ParticipationReceipt::new(
+        call_id,
+        crate::session::session_name(call_id, &pub_key, &batch),
+        &pub_key,
+        selected_parties.values(),
+    ));
+    batch_output(&pub_key, i, msgs, signatures, timing, context)
+}
+
+/// Agree with the other `selected_parties` on the chunk of the `batch` to resume the signing
+/// from, holding the `checkpoints`, see [`resume`](crate::rounds::resume).
+async fn resume_point(
+    i: u16,
+    selected_parties: &BTreeMap<u16, ecdsa::Public>,
+    checkpoints: &BTreeMap<usize, Vec<Vec<u8>>>,
+    pub_key: &[u8],
+    batch: &[u8; 32],
+    call_id: u64,
+    context: &FrostContext,
+) -> Result<u64, Error> {
+    let held = checkpoints
+        .keys()
+        .map(|k| *k as u64)
+        .collect::<BTreeSet<_>>();
+    let n = u16::try_from(selected_parties.len())?;
+    let session = crate::session::batch_resume_session_name(call_id, pub_key, batch);
+    let _session = context.sessions.register(session, "resume")?;
+    let delivery = NetworkDeliveryWrapper::new(
+        context.network_backend.clone(),
+        i,
+        session,
+        selected_parties.clone(),
+    );
+    let delivery = crate::codec::primed(delivery);
+    let party = round_based::MpcParty::connected(crate::codec::versioned(delivery, context.codec));
+    let resume = crate::rounds::resume::run(&held, n, i, party, None).await?;
+    tracing::debug!(resume, "Resuming the batch");
+    Ok(resume)
+}
+
+/// Whether the serialized `signatures` are those of the messages of a chunk, `chunk_msgs`, with
+/// the key `verifying_key`.
+fn chunk_verifies<C: Ciphersuite>(
+    verifying_key: &frost_core::VerifyingKey<C>,
+    chunk_msgs: &[Vec<u8>],
+    signatures: &[Vec<u8>],
+) -> bool {
+    chunk_msgs.len() == signatures.len()
+        && chunk_msgs.iter().zip(signatures).all(|(msg, signature)| {
+            Signature::<C>::deserialize(signature)
+                .is_ok_and(|signature| verifying_key.verify(msg, &signature).is_ok())
+        })
+}
+
+/// Run the batch signing protocol of `msgs` as the signer `i` of the `selected_parties`, in the
+/// network session `session`.
+#[allow(clippy::too_many_arguments)]
+async fn batch_session<C, R>(
+    rng: &mut R,
+    i: u16,
+    selected_parties: &BTreeMap<u16, ecdsa::Public>,
+    key_pkg: &KeyPackage<C>,
+    pub_key_pkg: &PublicKeyPackage<C>,
+    msgs: &[Vec<u8>],
+    session: [u8; 32],
+    call_id: u64,
+    context: &FrostContext,
+) -> Result<(Vec<Signature<C>>, Option<TimingReport>), Error>
+where
+    C: Ciphersuite + Send + Unpin,
+    <<C as Ciphersuite>::Group as frost_core::Group>::Element: Send + Unpin,
+    <<<C as Ciphersuite>::Group as frost_core::Group>::Field as frost_core::Field>::Scalar:
+        Send + Unpin,
+    R: random::RngCore + random::CryptoRng,
+{
+    let signers_ids: Vec<_> = selected_parties.keys().copied().collect();
+    let _session = context.sessions.register(session, "signing")?;
+
+    let recorder = context.recorder(
+        call_id,
+        "signing",
+        C::ID,
+        i,
+        selected_parties.values().map(|k| k.0),
+    );
+    let delivery = NetworkDeliveryWrapper::new(
+        context.network_backend.clone(),
+        i,
+        session,
+        selected_parties.clone(),
+    );
+    let delivery = context.prioritize(session, delivery);
+    let letters = context.dead_letters(call_id, "batch_signing", i);
+    let delivery = DeadLetters::wrap(letters.as_ref(), delivery);
+    let delivery = Recorder::record(recorder.as_ref(), delivery);
+    let signer = context.config.first_ecdsa_signer()?.signer().clone();
+    let transcript =
+        TranscriptRecorder::new(call_id, session, i, signer, selected_parties.values());
+    let delivery = transcript.record(delivery);
+    let trace = context.trace_recorder(call_id, "batch_signing", i);
+    let delivery = TraceRecorder::record(trace.as_ref(), delivery);
+
+    let party = round_based::MpcParty::connected(crate::codec::versioned(delivery, context.codec));
+    let mut profiler = context.timing_report.then(PerfProfiler::new);
+    let nonces = context.nonce_provider::<C>();
+    let signatures = sign_protocol::run_batch::<R, C, _>(
+        rng,
+        key_pkg,
+        pub_key_pkg,
+        &signers_ids,
+        msgs,
+        context.malformed_shares,
+        context.unknown_signers,
+        context.missing_commitments,
+        nonces.as_deref(),
+        party,
+        profiler.as_mut().map(|p| p as &mut dyn Tracer),
+    )
+    .await;
+    context.save_transcript(&transcript);
+    let signatures = signatures.inspect_err(|e| context.save_diagnostics(recorder, e))?;
+    let timing = profiler.and_then(|p| p.timing_report());
+    Ok((signatures, timing))
+}
+
+/// The serialized `signatures` of the batch of `msgs` signed with the key `pub_key`, notifying
+/// them if this node is the first signer `i`.
+#[cfg_attr(not(feature = "webhook"), allow(unused_variables))]
+fn batch_output<C: Ciphersuite>(
+    pub_key: &[u8],
+    i: u16,
+    msgs: &[Vec<u8>],
+    signatures: Vec<Signature<C>>,
+    timing: Option<TimingReport>,
+    context: &FrostContext,
+) -> Result<(Vec<Vec<u8>>, Option<TimingReport>), Error> {
+    let mut output = Vec::with_capacity(signatures.len());
+    for signature in &signatures {
+        ensure_canonical(signature)?;
+        output.push(signature.serialize()?);
+    }
+    sdk::debug!(
+        pubkey = %context.log_redaction.redact(&hex::encode(pub_key)),
+        batch = msgs.len(),
+        "Batch Signing Done"
+    );
+
+    #[cfg(feature = "webhook")]
+    if let (0, Some(webhook)) = (i, context.webhook.clone()) {
+        let notifications = msgs
+            .iter()
+            .zip(&signatures)
+            .map(|(msg, signature)| {
+                Ok(crate::webhook::SignatureNotification {
+                    pubkey: hex::encode(pub_key),
+                    msg_hash: hex::encode(sdk::subxt_core::ext::sp_core::keccak_256(msg)),
+                    signature: hex::encode(signature.serialize()?),
+                })
+            })
+            .collect::<Result<Vec<_>, Error>>()?;
+        tokio::spawn(async move {
+            for notification in &notifications {
+                webhook.notify(notification).await;
+            }
+        });
+    }
+    Ok((output, timing))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::num::NonZeroUsize;
+    use std::time::Duration;
+
+    use super::*;
+    use crate::testing::{
+        keygen_on_all, on_all, operator_contexts, sign_on_all, MockCoordinator, MockNetwork,
+        MockNetworkConfig, TempDir,
+    };
+    use gadget_sdk::subxt_core::ext::sp_core::Pair;
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn unreachable_signers_fail_fast() {
+        type C = frost_secp256k1::Secp256K1Sha256;
+        let network = MockNetwork::new(MockNetworkConfig {
+            latency: Duration::from_millis(50),
+            loss: 0.0,
+        });
+        let dir = TempDir::new("offline-signers");
+        let contexts = operator_contexts(&network, &dir, 3, 942)
+            .into_iter()
+            .map(|context| {
+                context
+                    .with_offline_signers(OfflineSigners::FailFast)
+                    .with_connected_peers(network.clone())
+            })
+            .collect::<Vec<_>>();
+        let pubkey = keygen_on_all(&contexts, C::ID, 3).await;
+
+        // The last operator goes offline, the other two cannot reach the threshold.
+        let offline = contexts[2].config.first_ecdsa_signer().unwrap();
+        network.disconnect(&offline.signer().public());
+        for context in contexts.into_iter().take(2) {
+            let result = tokio::time::timeout(
+                Duration::from_secs(5),
+                sign(pubkey.clone(), b"offline".to_vec(), context),
+            )
+            .await
+            .expect("the signing did not fail fast");
+            assert!(
+                matches!(
+                    result,
+                    Err(Error::InsufficientSigners {
+                        online: 2,
+                        required: 3
+                    })
+                ),
+                "{result:?}"
+            );
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn ephemeral_keys_are_drawn_afresh() {
+        type C = frost_secp256k1::Secp256K1Sha256;
+        let network = MockNetwork::new(MockNetworkConfig {
+            latency: Duration::from_millis(50),
+            loss: 0.0,
+        });
+        let dir = TempDir::new("ephemeral-keys");
+        let mut contexts = operator_contexts(&network, &dir, 3, 922);
+        let pubkey = keygen_on_all(&contexts, C::ID, 2).await;
+
+        // Two sessions of the same job call, signing the same message with the same key, the
+        // second one replayed over a fresh network.
+        let mut keys = vec![];
+        for session in 0..2 {
+            if session > 0 {
+                let network = MockNetwork::new(MockNetworkConfig {
+                    latency: Duration::from_millis(50),
+                    loss: 0.0,
+                });
+                for context in &mut contexts {
+                    let key = context
+                        .config
+                        .first_ecdsa_signer()
+                        .unwrap()
+                        .signer()
+                        .public();
+                    context.network_backend = network.multiplexer(key);
+                }
+            }
+            let outputs = on_all(&contexts, |context| {
+                sign_ephemeral(pubkey.clone(), b"unlinkable".to_vec(), context)
+            })
+            .await;
+            let outputs = outputs.into_iter().flatten().collect::<Vec<_>>();
+            assert_eq!(outputs.len(), 2);
+            assert!(outputs.windows(2).all(|w| w[0] == w[1]));
+            let (key, signature) = outputs[0].split_at(33);
+            let key = frost_core::VerifyingKey::<C>::deserialize(key).unwrap();
+            let signature = frost_core::Signature::<C>::deserialize(signature).unwrap();
+            key.verify(b"unlinkable", &signature).unwrap();
+            keys.push(key.serialize().unwrap());
+        }
+        assert_ne!(keys[0], pubkey);
+        assert_ne!(keys[1], pubkey);
+        assert_ne!(keys[0], keys[1]);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn block_bound_signature_covers_the_call_block() {
+        type C = frost_secp256k1::Secp256K1Sha256;
+        let network = MockNetwork::new(MockNetworkConfig {
+            latency: Duration::from_millis(50),
+            loss: 0.0,
+        });
+        let dir = TempDir::new("block-bound");
+        let contexts = operator_contexts(&network, &dir, 3, 949)
+            .into_iter()
+            .map(|context| context.with_block_bound_signing(true))
+            .collect::<Vec<_>>();
+        let pubkey = keygen_on_all(&contexts, C::ID, 3).await;
+
+        let outputs = sign_on_all(&contexts, &pubkey, b"replay").await;
+
+        // The mock coordinator makes the call in the block following its id.
+        let message = block_bound_message(b"replay", 950);
+        let verifying_key = frost_core::VerifyingKey::<C>::deserialize(&pubkey).unwrap();
+        for output in outputs {
+            let output = output.unwrap();
+            let (block, signature) = output.split_at(8);
+            assert_eq!(block, 950u64.to_be_bytes());
+            let signature = frost_core::Signature::<C>::deserialize(signature).unwrap();
+            verifying_key.verify(&message, &signature).unwrap();
+            assert!(verifying_key.verify(b"replay", &signature).is_err());
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn malformed_message_is_refused() {
+        let network = MockNetwork::new(Default::default());
+        let dir = TempDir::new("message-validator");
+        let context = operator_contexts(&network, &dir, 1, 996)
+            .remove(0)
+            .with_message_validator(crate::policy::FixedLength(32));
+        let pubkey = vec![2; 33];
+
+        for msg in [vec![], vec![7; 31], vec![7; 33]] {
+            let result = sign(pubkey.clone(), msg, context.clone()).await;
+            assert!(
+                matches!(&result, Err(Error::InvalidMessageSchema { reason }) if reason.contains("instead of 32")),
+                "{result:?}"
+            );
+        }
+        // A well-formed message goes on to the signing, and fails for the unknown key.
+        let result = sign(pubkey, vec![7; 32], context).await;
+        assert!(
+            !matches!(result, Err(Error::InvalidMessageSchema { .. })),
+            "{result:?}"
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn blocked_message_is_refused() {
+        type C = frost_secp256k1::Secp256K1Sha256;
+        let network = MockNetwork::new(MockNetworkConfig {
+            latency: Duration::from_millis(50),
+            loss: 0.0,
+        });
+        let dir = TempDir::new("message-policy");
+        let contexts = operator_contexts(&network, &dir, 3, 974)
+            .into_iter()
+            .map(|context| {
+                context.with_message_policy(crate::policy::Blocklist::new([b"\xde\xad".to_vec()]))
+            })
+            .collect::<Vec<_>>();
+        let pubkey = keygen_on_all(&contexts, C::ID, 3).await;
+
+        for context in contexts.iter().cloned() {
+            let result = sign(pubkey.clone(), b"pay \xde\xad".to_vec(), context).await;
+            assert!(
+                matches!(&result, Err(Error::MessageRejected { reason }) if reason.contains("dead")),
+                "{result:?}"
+            );
+        }
+
+        let verifying_key = frost_core::VerifyingKey::<C>::deserialize(&pubkey).unwrap();
+        for signature in sign_on_all(&contexts, &pubkey, b"pay \xbe\xef").await {
+            let signature = signature.unwrap();
+            let signature = frost_core::Signature::<C>::deserialize(&signature).unwrap();
+            verifying_key.verify(b"pay \xbe\xef", &signature).unwrap();
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn node_outside_the_operators_fails_clearly() {
+        let network = MockNetwork::new(MockNetworkConfig {
+            latency: Duration::from_millis(50),
+            loss: 0.0,
+        });
+        let dir = TempDir::new("not-an-operator");
+        let mut contexts = operator_contexts(&network, &dir, 3, 952);
+        let outsider = contexts.pop().unwrap();
+        // The service only has the first two operators.
+        let operators = contexts
+            .iter()
+            .enumerate()
+            .map(|(i, context)| {
+                let key = context
+                    .config
+                    .first_ecdsa_signer()
+                    .unwrap()
+                    .signer()
+                    .public();
+                (AccountId32([i as u8 + 1; 32]), key)
+            })
+            .collect();
+        let outsider = outsider.with_coordinator(MockCoordinator {
+            operators,
+            call_id: 952,
+            change_after: None,
+            caller: None,
+        });
+        let result = sign(vec![2; 33], b"outsider".to_vec(), outsider).await;
+        assert!(
+            matches!(result, Err(Error::SelfNotInOperators)),
+            "{result:?}"
+        );
+    }
+
+    #[tokio::test]
+    async fn local_key_must_be_the_network_identity() {
+        let network = MockNetwork::new(MockNetworkConfig {
+            latency: Duration::ZERO,
+            loss: 0.0,
+        });
+        let dir = TempDir::new("identity-mismatch");
+        let context = operator_contexts(&network, &dir, 1, 965).remove(0);
+        let local = context.config.first_ecdsa_signer().unwrap();
+        let local = local.signer().public();
+
+        // The network was started with another key than the first one of the keystore.
+        let other = ecdsa::Public::from_raw([3; 33]);
+        let mismatched = context.clone().with_network_identity(other);
+        let result = sign(vec![2; 33], b"identity".to_vec(), mismatched).await;
+        match result {
+            Err(Error::IdentityMismatch(e)) => {
+                assert_eq!(e.network, hex::encode(other));
+                assert_eq!(e.local, hex::encode(local));
+            }
+            other => panic!("expected an identity mismatch, got {other:?}"),
+        }
+
+        // With the same key, the signing gets past the check to the missing key.
+        let matching = context.with_network_identity(local);
+        let result = sign(vec![2; 33], b"identity".to_vec(), matching).await;
+        assert!(matches!(result, Err(Error::KeyNotFound)), "{result:?}");
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn timed_out_signing_reports_the_absent_signer() {
+        type C = frost_secp256k1::Secp256K1Sha256;
+        let network = MockNetwork::new(MockNetworkConfig {
+            latency: Duration::from_millis(50),
+            loss: 0.0,
+        });
+        let dir = TempDir::new("responsiveness");
+        let contexts = operator_contexts(&network, &dir, 3, 951);
+        let pubkey = keygen_on_all(&contexts, C::ID, 3).await;
+
+        // The last operator is selected but goes away before the signing.
+        let keys = contexts
+            .iter()
+            .map(|context| {
+                context
+                    .config
+                    .first_ecdsa_signer()
+                    .unwrap()
+                    .signer()
+                    .public()
+            })
+            .collect::<Vec<_>>();
+        network.disconnect(&keys[2]);
+        let signings = contexts
+            .into_iter()
+            .take(2)
+            .map(|context| {
+                let context = context.with_job_timeout(Duration::from_secs(3));
+                sign(pubkey.clone(), b"absent".to_vec(), context)
+            })
+            .collect::<Vec<_>>();
+        let results = tokio::time::timeout(
+            Duration::from_secs(10),
+            gadget_sdk::futures::future::join_all(signings),
+        )
+        .await
+        .expect("the signing did not time out");
+        for result in results {
+            let Err(Error::UnresponsiveSigners {
+                mut responsive,
+                unresponsive,
+            }) = result
+            else {
+                panic!("{result:?}");
+            };
+            responsive.sort();
+            let mut expected = keys[..2].to_vec();
+            expected.sort();
+            assert_eq!(responsive, expected);
+            assert_eq!(unresponsive, [keys[2]]);
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn absent_signer_does_not_hold_up_the_operators_left_out() {
+        type C = frost_ed25519::Ed25519Sha512;
+        const CALL_ID: u64 = 987;
+        let network = MockNetwork::new(MockNetworkConfig {
+            latency: Duration::from_millis(50),
+            loss: 0.0,
+        });
+        let dir = TempDir::new("absent-outcome");
+        let contexts = operator_contexts(&network, &dir, 3, CALL_ID)
+            .into_iter()
+            .map(|context| {
+                context
+                    .with_unique_messages(true)
+                    .with_signing_timeout(Duration::from_secs(2))
+            })
+            .collect::<Vec<_>>();
+        let operators = contexts[0].current_operators().await.unwrap();
+        let pubkey = keygen_on_all(&contexts, C::ID, 2).await;
+
+        let msg = b"absent signer".to_vec();
+        let seed = crate::session::signers_seed(CALL_ID, &pubkey, &msg);
+        let selected =
+            crate::operators::select_signers_excluding(&operators, &Default::default(), seed, 2);
+        let left_out = contexts
+            .into_iter()
+            .find(|context| {
+                let key = context
+                    .config
+                    .first_ecdsa_signer()
+                    .unwrap()
+                    .signer()
+                    .public();
+                !selected.values().any(|k| *k == key)
+            })
+            .unwrap();
+
+        // The selected signers never report their outcome, e.g. because one of them crashed.
+        let result = tokio::time::timeout(
+            Duration::from_secs(10),
+            sign(pubkey.clone(), msg.clone(), left_out.clone()),
+        )
+        .await
+        .expect("the operator left out waits for the absent signer");
+        assert!(
+            result
+                .as_ref()
+                .is_err_and(|e| e.to_string().contains("Self not in signers")),
+            "{result:?}"
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn signature_covers_the_validity_window() {
+        type C = frost_ed25519::Ed25519Sha512;
+        let network = MockNetwork::new(MockNetworkConfig {
+            latency: Duration::from_millis(50),
+            loss: 0.0,
+        });
+        let dir = TempDir::new("validity");
+        let contexts = operator_contexts(&network, &dir, 2, 967);
+        let pubkey = keygen_on_all(&contexts, C::ID, 2).await;
+        assert!(matches!(
+            sign_with_validity(
+                pubkey.clone(),
+                2,
+                1,
+                b"backwards".to_vec(),
+                contexts[0].clone()
+            )
+            .await,
+            Err(Error::InvalidValidityWindow { .. })
+        ));
+
+        let (not_before, not_after) = (1_700_000_000, 1_700_003_600);
+        let signatures = on_all(&contexts, |context| {
+            sign_with_validity(
+                pubkey.clone(),
+                not_before,
+                not_after,
+                b"credential".to_vec(),
+                context,
+            )
+        })
+        .await;
+
+        let verifying_key = frost_core::VerifyingKey::<C>::deserialize(&pubkey).unwrap();
+        let payload = validity_bound_message(b"credential", not_before, not_after);
+        for signature in signatures {
+            let signature = Signature::<C>::deserialize(&signature.unwrap()).unwrap();
+            verifying_key.verify(&payload, &signature).unwrap();
+            assert_eq!(check_validity(&payload, not_before).unwrap(), b"credential");
+            assert_eq!(check_validity(&payload, not_after).unwrap(), b"credential");
+            assert!(matches!(
+                check_validity(&payload, not_after + 1),
+                Err(Error::OutsideValidityWindow { .. })
+            ));
+            // Extending the window breaks the signature.
+            let extended = validity_bound_message(b"credential", not_before, u64::MAX);
+            assert!(verifying_key.verify(&extended, &signature).is_err());
+            assert!(verifying_key.verify(b"credential", &signature).is_err());
+        }
+        assert!(matches!(
+            check_validity(b"credential", not_before),
+            Err(Error::MalformedValidityPayload)
+        ));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn resumed_batch_skips_the_signed_chunks() {
+        type C = frost_ed25519::Ed25519Sha512;
+        const SIGNATURE_LEN: usize = 64;
+        let network = MockNetwork::new(MockNetworkConfig {
+            latency: Duration::from_millis(50),
+            loss: 0.0,
+        });
+        let dir = TempDir::new("batch-checkpoints");
+        let contexts = operator_contexts(&network, &dir, 3, 997)
+            .into_iter()
+            .map(|context| context.with_batch_checkpoints(NonZeroUsize::new(2).unwrap()))
+            .collect::<Vec<_>>();
+        let pubkey = keygen_on_all(&contexts, C::ID, 2).await;
+        let msgs = (0..5u8).map(|i| vec![i; 8]).collect::<Vec<_>>();
+        let batch_sign = |contexts: Vec<FrostContext>| {
+            let (pubkey, msgs) = (pubkey.clone(), msgs.clone());
+            async move {
+                let results = on_all(&contexts, |context| {
+                    batch_sign_shared_setup(pubkey.clone(), msgs.clone(), context)
+                })
+                .await;
+                // The operator not selected to sign fails.
+                let output = results.into_iter().flatten().last();
+                output.expect("no operator signed the batch")
+            }
+        };
+        let first = batch_sign(contexts.clone()).await;
+        assert_eq!(first.len(), msgs.len() * SIGNATURE_LEN);
+
+        // Interrupt the batch after its first chunk, of 2 signatures.
+        let batch_id = crate::checkpoint::batch_id(
+            &pubkey,
+            &crate::session::batch_digest(&msgs),
+            NonZeroUsize::new(2).unwrap(),
+        );
+        // Only the selected signers hold a checkpoint.
+        let mut signers = 0;
+        for context in &contexts {
+            let mut chunks = crate::checkpoint::load(&context.store, &batch_id).unwrap();
+            if chunks.is_empty() {
+                continue;
+            }
+            signers += 1;
+            assert_eq!(chunks.len(), 3);
+            chunks.retain(|k, _| *k == 0);
+            crate::checkpoint::save(&context.store, &batch_id, &chunks).unwrap();
+        }
+        assert_eq!(signers, 2);
+
+        // Resume it in a later call.
+        let operators = contexts[0].current_operators().await.unwrap();
+        let resumed = contexts
+            .iter()
+            .map(|context| {
+                context.clone().with_coordinator(MockCoordinator {
+                    operators: operators.clone(),
+                    call_id: 998,
+                    change_after: None,
+                    caller: None,
+                })
+            })
+            .collect::<Vec<_>>();
+        let second = batch_sign(resumed).await;
+        assert_eq!(second.len(), msgs.len() * SIGNATURE_LEN);
+        // The first 2 signatures are the checkpointed ones, the others are signed anew.
+        assert_eq!(second[..2 * SIGNATURE_LEN], first[..2 * SIGNATURE_LEN]);
+        let verifying_key = frost_core::VerifyingKey::<C>::deserialize(&pubkey).unwrap();
+        for (k, (msg, signature)) in msgs.iter().zip(second.chunks(SIGNATURE_LEN)).enumerate() {
+            let signature = Signature::<C>::deserialize(signature).unwrap();
+            verifying_key.verify(msg, &signature).unwrap();
+            if k >= 2 {
+                assert_ne!(
+                    second[k * SIGNATURE_LEN..][..SIGNATURE_LEN],
+                    first[k * SIGNATURE_LEN..][..SIGNATURE_LEN]
+                );
+            }
+        }
+        let complete = contexts
+            .iter()
+            .filter(|context| {
+                crate::checkpoint::load(&context.store, &batch_id)
+                    .unwrap()
+                    .len()
+                    == 3
+            })
+            .count();
+        assert_eq!(complete, 2);
+    }
+
+    #[tokio::test]
+    async fn resumed_batch_starts_from_the_chunk_a_signer_lacks() {
+        type C = frost_ed25519::Ed25519Sha512;
+        const SIGNATURE_LEN: usize = 64;
+        let network = MockNetwork::new(MockNetworkConfig {
+            latency: Duration::from_millis(50),
+            loss: 0.0,
+        });
+        let dir = TempDir::new("partial-checkpoints");
+        let chunk = NonZeroUsize::new(2).unwrap();
+        let contexts = operator_contexts(&network, &dir, 3, 997)
+            .into_iter()
+            .map(|context| context.with_batch_checkpoints(chunk))
+            .collect::<Vec<_>>();
+        let pubkey = keygen_on_all(&contexts, C::ID, 2).await;
+        let msgs = (0..6u8).map(|i| vec![i; 8]).collect::<Vec<_>>();
+        let batch_sign = |contexts: Vec<FrostContext>| {
+            let (pubkey, msgs) = (pubkey.clone(), msgs.clone());
+            async move {
+                on_all(&contexts, |context| {
+                    batch_sign_shared_setup(pubkey.clone(), msgs.clone(), context)
+                })
+                .await
+                .into_iter()
+                .flatten()
+                .collect::<Vec<_>>()
+            }
+        };
+        let first = batch_sign(contexts.clone()).await;
+        assert_eq!(first.len(), 2);
+
+        // One signer holds the chunks 0 and 1, the other only the chunk 0.
+        let batch_id =
+            crate::checkpoint::batch_id(&pubkey, &crate::session::batch_digest(&msgs), chunk);
+        let mut kept = [1, 0].into_iter();
+        for context in &contexts {
+            let mut chunks = crate::checkpoint::load(&context.store, &batch_id).unwrap();
+            if chunks.is_empty() {
+                continue;
+            }
+            let last = kept.next().unwrap();
+            chunks.retain(|k, _| *k <= last);
+            crate::checkpoint::save(&context.store, &batch_id, &chunks).unwrap();
+        }
+
+        let operators = contexts[0].current_operators().await.unwrap();
+        let resumed = contexts
+            .iter()
+            .map(|context| {
+                context.clone().with_coordinator(MockCoordinator {
+                    operators: operators.clone(),
+                    call_id: 998,
+                    change_after: None,
+                    caller: None,
+                })
+            })
+            .collect::<Vec<_>>();
+        let second = batch_sign(resumed).await;
+        // Both signers resume from the chunk 1 and sign every later chunk again.
+        assert_eq!(second.len(), 2);
+        assert_eq!(second[0], second[1]);
+        let verifying_key = frost_core::VerifyingKey::<C>::deserialize(&pubkey).unwrap();
+        for (k, (msg, signature)) in msgs.iter().zip(second[0].chunks(SIGNATURE_LEN)).enumerate() {
+            verifying_key
+                .verify(msg, &Signature::<C>::deserialize(signature).unwrap())
+                .unwrap();
+            let signature = &second[0][k * SIGNATURE_LEN..][..SIGNATURE_LEN];
+            let previous = &first[0][k * SIGNATURE_LEN..][..SIGNATURE_LEN];
+            assert_eq!(signature == previous, k < 2);
+        }
+    }
+
+    #[tokio::test]
+    async fn tampered_checkpoint_is_signed_again() {
+        type C = frost_ed25519::Ed25519Sha512;
+        const SIGNATURE_LEN: usize = 64;
+        let network = MockNetwork::new(MockNetworkConfig {
+            latency: Duration::from_millis(50),
+            loss: 0.0,
+        });
+        let dir = TempDir::new("tampered-checkpoints");
+        let chunk = NonZeroUsize::new(2).unwrap();
+        let contexts = operator_contexts(&network, &dir, 3, 997)
+            .into_iter()
+            .map(|context| context.with_batch_checkpoints(chunk))
+            .collect::<Vec<_>>();
+        let pubkey = keygen_on_all(&contexts, C::ID, 2).await;
+        let msgs = (0..4u8).map(|i| vec![i; 8]).collect::<Vec<_>>();
+
+        // A checkpoint of the first chunk with its signatures swapped.
+        let mut swapped = Vec::new();
+        for msg in msgs[..2].iter().rev() {
+            let outputs = sign_on_all(&contexts, &pubkey, msg).await;
+            swapped.push(outputs.into_iter().flatten().next().unwrap());
+        }
+        let batch_id =
+            crate::checkpoint::batch_id(&pubkey, &crate::session::batch_digest(&msgs), chunk);
+        let tampered = BTreeMap::from([(0, swapped)]);
+        for context in &contexts {
+            crate::checkpoint::save(&context.store, &batch_id, &tampered).unwrap();
+        }
+
+        let output = on_all(&contexts, |context| {
+            batch_sign_shared_setup(pubkey.clone(), msgs.clone(), context)
+        })
+        .await
+        .into_iter()
+        .flatten()
+        .last()
+        .expect("no operator signed the batch");
+        let verifying_key = frost_core::VerifyingKey::<C>::deserialize(&pubkey).unwrap();
+        for (msg, signature) in msgs.iter().zip(output.chunks(SIGNATURE_LEN)) {
+            let signature = Signature::<C>::deserialize(signature).unwrap();
+            verifying_key.verify(msg, &signature).unwrap();
+        }
+    }
+
+    #[test]
+    fn secp256k1_signatures_are_canonical() {
+        use frost_core::keys::{generate_with_dealer, IdentifierList};
+        use sdk::random::rand::rngs::StdRng;
+        use sdk::random::SeedableRng;
+
+        type C = frost_secp256k1::Secp256K1Sha256;
+        // The order of the secp256k1 group, big endian.
+        let order = hex::decode("fffffffffffffffffffffffffffffffebaaedce6af48a03bbfd25e8cd0364141")
+            .unwrap();
+        let rng = &mut StdRng::seed_from_u64(959);
+        let (shares, pub_key_pkg) =
+            generate_with_dealer::<C, _>(3, 2, IdentifierList::Default, rng).unwrap();
+        let key_pkgs = shares
+            .into_iter()
+            .take(2)
+            .map(|(id, share)| (id, KeyPackage::try_from(share).unwrap()))
+            .collect::<BTreeMap<_, _>>();
+        for msg in 0..16u8 {
+            let mut nonces = BTreeMap::new();
+            let mut commitments = BTreeMap::new();
+            for (id, key_pkg) in &key_pkgs {
+                let (nonce, commitment) = frost_core::round1::commit(key_pkg.signing_share(), rng);
+                nonces.insert(*id, nonce);
+                commitments.insert(*id, commitment);
+            }
+            let signing_pkg = frost_core::SigningPackage::new(commitments, &[msg]);
+            let shares = key_pkgs
+                .iter()
+                .map(|(id, key_pkg)| {
+                    let share =
+                        frost_core::round2::sign(&signing_pkg, &nonces[id], key_pkg).unwrap();
+                    (*id, share)
+                })
+                .collect::<BTreeMap<_, _>>();
+            let signature = frost_core::aggregate(&signing_pkg, &shares, &pub_key_pkg).unwrap();
+            ensure_canonical(&signature).unwrap();
+            let bytes = signature.serialize().unwrap();
+            // The response after the compressed commitment is reduced modulo the order.
+            assert!(bytes[33..] < order[..]);
+
+            // A response that is not reduced is rejected.
+            let mut unreduced = bytes.clone();
+            unreduced[33..].copy_from_slice(&[0xff; 32]);
+            assert!(!is_canonical::<C>(&unreduced));
+        }
+    }
 }
 
 #[cfg(all(test, feature = "e2e"))]
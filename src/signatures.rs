@@ -0,0 +1,333 @@
+//! Storage of the produced signatures.
+//!
+//! Every signature produced by a signing job on this node is written to the store, keyed by its
+//! job call id and indexed by its public key and message hash, so a client that lost it can
+//! fetch it again with [`get_signature`] instead of signing again.
+//!
+//! With [`FrostContext::with_unique_messages`], a key signs every message at most once: the
+//! signing jobs claim their messages before signing, and the jobs asking for a message already
+//! claimed by another job call fail with
+//! [`MessageAlreadySigned`](crate::sign::Error::MessageAlreadySigned), rather than signing it
+//! again or returning its signature. Every operator claims the messages, the ones not selected
+//! as signers included, and keeps them claimed once a signer reports a signature of them, see
+//! [`outcome`](crate::rounds::outcome): whichever signers a later job call selects, they refuse
+//! it. A message is only released when its signing failed on every signer, an operator that
+//! does not learn the outcome within [`FrostContext::with_signing_timeout`] keeps it claimed.
+//!
+//! [`FrostContext::with_unique_messages`]: crate::FrostContext::with_unique_messages
+//! [`FrostContext::with_signing_timeout`]: crate::FrostContext::with_signing_timeout
+use api::services::events::JobCalled;
+use gadget_sdk as sdk;
+use sdk::event_listener::tangle::{
+    jobs::{services_post_processor, services_pre_processor},
+    TangleEventListener,
+};
+use sdk::tangle_subxt::tangle_testnet_runtime::api;
+
+use crate::kv::SharedDynKVStore;
+use crate::FrostContext;
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("Signature not found")]
+    SignatureNotFound,
+    #[error("Either the call id, or the public key and the message hash are required")]
+    MissingQuery,
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+}
+
+/// A signature produced by this node.
+#[derive(Clone, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct SignatureRecord {
+    /// The call id of the signing job.
+    pub call_id: u64,
+    /// The hex encoded public key the message was signed with.
+    pub pubkey: String,
+    /// The hex encoded `keccak256` hash of the signed message.
+    pub msg_hash: String,
+    /// The hex encoded signature, as returned by the signing job.
+    pub signature: String,
+}
+
+fn store_key(call_id: u64) -> String {
+    format!("signature/{call_id}")
+}
+
+fn index_key(pubkey: &str, msg_hash: &str) -> String {
+    format!("signature/{pubkey}/{msg_hash}")
+}
+
+fn claim_key(pubkey: &[u8], msg_hash: &[u8]) -> String {
+    format!("signed/{}/{}", hex::encode(pubkey), hex::encode(msg_hash))
+}
+
+/// Write `record` to the store, replacing the last signature of the same message and key in
+/// the index.
+pub(crate) fn save(
+    store: &SharedDynKVStore<String, Vec<u8>>,
+    record: &SignatureRecord,
+) -> Result<(), Error> {
+    store.set(store_key(record.call_id), serde_json::to_vec(record)?)?;
+    store.set(
+        index_key(&record.pubkey, &record.msg_hash),
+        record.call_id.to_be_bytes().to_vec(),
+    )?;
+    Ok(())
+}
+
+/// Read the signature of the job call `call_id` from the store.
+pub(crate) fn by_call_id(
+    store: &SharedDynKVStore<String, Vec<u8>>,
+    call_id: u64,
+) -> Result<SignatureRecord, Error> {
+    let bytes = store
+        .get(&store_key(call_id))?
+        .ok_or(Error::SignatureNotFound)?;
+    Ok(serde_json::from_slice(&bytes)?)
+}
+
+/// Read the last signature of the message hashed to `msg_hash` with the key `pubkey`.
+pub(crate) fn by_message(
+    store: &SharedDynKVStore<String, Vec<u8>>,
+    pubkey: &[u8],
+    msg_hash: &[u8],
+) -> Result<SignatureRecord, Error> {
+    let call_id = store
+        .get(&index_key(&hex::encode(pubkey), &hex::encode(msg_hash)))?
+        .and_then(|bytes| bytes.try_into().ok())
+        .map(u64::from_be_bytes)
+        .ok_or(Error::SignatureNotFound)?;
+    by_call_id(store, call_id)
+}
+
+/// Get a signature previously produced on this node.
+///
+/// # Parameters
+/// - `call_id`: The call id of the signing job, or
+/// - `pubkey` and `msg_hash`: The public key, or its label, and the `keccak256` hash of the
+///   signed message.
+///
+/// # Returns
+/// The signature, as returned by the signing job.
+///
+/// # Errors
+/// - `SignatureNotFound`: If this node did not produce the signature.
+/// - `MissingQuery`: If neither the call id nor the public key and the message hash are given.
+///
+/// # Note
+/// Only the selected signers of a job hold its signature.
+#[sdk::job(
+    id = 10,
+    params(call_id, pubkey, msg_hash),
+    result(_),
+    event_listener(
+        listener = TangleEventListener::<FrostContext, JobCalled>,
+        pre_processor = services_pre_processor,
+        post_processor = services_post_processor,
+    )
+)]
+#[tracing::instrument(skip_all, parent = context.config.span.clone(), err)]
+pub async fn get_signature(
+    call_id: Option<u64>,
+    pubkey: Option<Vec<u8>>,
+    msg_hash: Option<Vec<u8>>,
+    context: FrostContext,
+) -> Result<Vec<u8>, Error> {
+    let record = match (call_id, pubkey, msg_hash) {
+        (Some(call_id), _, _) => by_call_id(&context.store, call_id)?,
+        (None, Some(pubkey), Some(msg_hash)) => {
+            let pubkey = context.resolve_key(pubkey)?;
+            by_message(&context.store, &pubkey, &msg_hash)?
+        }
+        _ => return Err(Error::MissingQuery),
+    };
+    hex::decode(&record.signature).map_err(|_| Error::SignatureNotFound)
+}
+
+impl FrostContext {
+    /// Keep the `signature` of the message hashed to `msg_hash` by the job call `call_id`.
+    ///
+    /// Failing to write it does not fail the job, it is only logged.
+    pub(crate) fn save_signature(
+        &self,
+        call_id: u64,
+        pubkey: &[u8],
+        msg_hash: &[u8],
+        signature: &[u8],
+    ) {
+        let record = SignatureRecord {
+            call_id,
+            pubkey: hex::encode(pubkey),
+            msg_hash: hex::encode(msg_hash),
+            signature: hex::encode(signature),
+        };
+        if let Err(e) = save(&self.store, &record) {
+            tracing::warn!(call_id, error = %e, "Failed to save the signature");
+        }
+    }
+
+    /// Claim the messages hashed to `msg_hashes` for the key `pubkey` and the job call
+    /// `call_id`, if enabled with [`FrostContext::with_unique_messages`].
+    ///
+    /// Fails with `MessageAlreadySigned`, claiming none of them, if one was claimed by another
+    /// job call.
+    pub(crate) fn claim_messages(
+        &self,
+        call_id: u64,
+        pubkey: &[u8],
+        msg_hashes: &[[u8; 32]],
+    ) -> Result<(), crate::sign::Error> {
+        if !self.unique_messages {
+            return Ok(());
+        }
+        for (claimed, msg_hash) in msg_hashes.iter().enumerate() {
+            let key = claim_key(pubkey, msg_hash);
+            let ours = call_id.to_be_bytes().to_vec();
+            if self
+                .store
+                .compare_and_swap(key.clone(), None, ours.clone())?
+            {
+                continue;
+            }
+            // The job call may be run again, e.g. after a restart.
+            let owner = self.store.get(&key)?;
+            if owner.as_ref() == Some(&ours) {
+                continue;
+            }
+            self.release_messages(pubkey, &msg_hashes[..claimed]);
+            let call_id = owner
+                .and_then(|bytes| bytes.try_into().ok())
+                .map(u64::from_be_bytes)
+                .unwrap_or_default();
+            return Err(crate::sign::Error::MessageAlreadySigned { call_id });
+        }
+        Ok(())
+    }
+
+    /// Release the messages hashed to `msg_hashes` for the key `pubkey`, claimed by a signing
+    /// that failed on every signer, so that they can be signed again.
+    pub(crate) fn release_messages(&self, pubkey: &[u8], msg_hashes: &[[u8; 32]]) {
+        if !self.unique_messages {
+            return;
+        }
+        for msg_hash in msg_hashes {
+            if let Err(e) = self.store.del(&claim_key(pubkey, msg_hash)) {
+                tracing::warn!(error = %e, "Failed to release the claimed message");
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::testing::{
+        keygen_on_all, operator_contexts, sign_on_all, MockNetwork, MockNetworkConfig, TempDir,
+    };
+    use frost_core::Ciphersuite;
+    use gadget_sdk::subxt_core::ext::sp_core::keccak_256;
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn signature_is_fetched_by_call_id() {
+        type C = frost_secp256k1::Secp256K1Sha256;
+        const CALL_ID: u64 = 932;
+        let network = MockNetwork::new(MockNetworkConfig {
+            latency: Duration::from_millis(50),
+            loss: 0.0,
+        });
+        let dir = TempDir::new("signatures");
+        let contexts = operator_contexts(&network, &dir, 3, CALL_ID);
+
+        let pubkey = keygen_on_all(&contexts, C::ID, 2).await;
+
+        let msg = b"lost signature".to_vec();
+        let signatures = sign_on_all(&contexts, &pubkey, &msg).await;
+        let mut signers = 0;
+        for (signature, context) in signatures.into_iter().zip(contexts) {
+            let fetched = get_signature(Some(CALL_ID), None, None, context.clone()).await;
+            match signature {
+                Ok(signature) => {
+                    signers += 1;
+                    assert_eq!(fetched.unwrap(), signature);
+                    let by_message = get_signature(
+                        None,
+                        Some(pubkey.clone()),
+                        Some(keccak_256(&msg).to_vec()),
+                        context.clone(),
+                    )
+                    .await;
+                    assert_eq!(by_message.unwrap(), signature);
+                }
+                // The operator left out of the signers has nothing to return.
+                Err(_) => assert!(matches!(fetched, Err(Error::SignatureNotFound))),
+            }
+            let missing = get_signature(None, Some(pubkey.clone()), None, context).await;
+            assert!(matches!(missing, Err(Error::MissingQuery)));
+        }
+        assert_eq!(signers, 2);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn message_is_signed_at_most_once() {
+        use crate::testing::MockCoordinator;
+
+        type C = frost_ed25519::Ed25519Sha512;
+        const CALL_ID: u64 = 987;
+        let network = MockNetwork::new(MockNetworkConfig {
+            latency: Duration::from_millis(50),
+            loss: 0.0,
+        });
+        let dir = TempDir::new("unique-messages");
+        let contexts = operator_contexts(&network, &dir, 5, CALL_ID)
+            .into_iter()
+            .map(|context| context.with_unique_messages(true))
+            .collect::<Vec<_>>();
+        let operators = contexts[0].current_operators().await.unwrap();
+
+        let pubkey = keygen_on_all(&contexts, C::ID, 2).await;
+
+        let msg = b"one-time withdrawal".to_vec();
+        let signed = sign_on_all(&contexts, &pubkey, &msg)
+            .await
+            .iter()
+            .map(Result::is_ok)
+            .collect::<Vec<_>>();
+        assert_eq!(signed.iter().filter(|signed| **signed).count(), 2);
+
+        // The operators left out know the message was signed, so they keep it claimed too.
+        let msg_hash = keccak_256(&msg);
+        for context in &contexts {
+            assert_eq!(
+                context.store.get(&claim_key(&pubkey, &msg_hash)).unwrap(),
+                Some(CALL_ID.to_be_bytes().to_vec())
+            );
+        }
+
+        // A later job call asking for the same message is refused by every operator, whichever
+        // signers it selects.
+        let contexts = contexts
+            .into_iter()
+            .map(|context| {
+                context.with_coordinator(MockCoordinator {
+                    operators: operators.clone(),
+                    call_id: CALL_ID + 1,
+                    change_after: None,
+                    caller: None,
+                })
+            })
+            .collect::<Vec<_>>();
+        for result in sign_on_all(&contexts, &pubkey, &msg).await {
+            assert!(
+                result
+                    .as_ref()
+                    .is_err_and(|e| e.contains(&format!("by the job call {CALL_ID}"))),
+                "{result:?}"
+            );
+        }
+    }
+}
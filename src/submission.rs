@@ -0,0 +1,187 @@
+//! Checks of the job results submitted on-chain.
+//!
+//! The result of a job is submitted by the job runner once the job returns, out of reach of the
+//! job itself. With [`FrostContext::with_submission_check`], the keygen and signing jobs read
+//! back the result of their call from the [`Coordinator`](crate::coordinator::Coordinator) once
+//! it is on-chain, and compare it with the one they computed: a mismatch, which means a bug of
+//! the submission or a tampered result, is logged as an error.
+//!
+//! The on-chain result is the one of the first operator to submit, so the results that differ
+//! from an operator to another, like the keygen results signed with
+//! [`FrostContext::with_signed_keygen_result`], are reported as mismatches on the other nodes.
+//!
+//! [`FrostContext::with_submission_check`]: crate::FrostContext::with_submission_check
+//! [`FrostContext::with_signed_keygen_result`]: crate::FrostContext::with_signed_keygen_result
+use std::time::Duration;
+
+use color_eyre::eyre;
+
+use crate::coordinator::Coordinator;
+use crate::FrostContext;
+
+/// How often the submitted result is looked up until it is on-chain.
+const SUBMISSION_POLL: Duration = Duration::from_secs(1);
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("The result of the job call {call_id} on-chain is not the one computed locally")]
+    Mismatch {
+        call_id: u64,
+        submitted: Vec<u8>,
+        local: Vec<u8>,
+    },
+    #[error("The result of the job call {call_id} is not on-chain after {waited:?}")]
+    NotSubmitted { call_id: u64, waited: Duration },
+    #[error(transparent)]
+    Coordinator(eyre::Error),
+}
+
+/// Wait up to `timeout` for the result of the job call `call_id` to be on-chain, and check it
+/// is `local`.
+pub async fn check(
+    coordinator: &dyn Coordinator,
+    call_id: u64,
+    local: &[u8],
+    timeout: Duration,
+) -> Result<(), Error> {
+    let submitted = async {
+        loop {
+            if let Some(submitted) = coordinator.submitted_result(call_id).await? {
+                return Ok::<_, eyre::Error>(submitted);
+            }
+            tokio::time::sleep(SUBMISSION_POLL).await;
+        }
+    };
+    let submitted = tokio::time::timeout(timeout, submitted)
+        .await
+        .map_err(|_| Error::NotSubmitted {
+            call_id,
+            waited: timeout,
+        })?
+        .map_err(Error::Coordinator)?;
+    if submitted != local {
+        return Err(Error::Mismatch {
+            call_id,
+            submitted,
+            local: local.to_vec(),
+        });
+    }
+    Ok(())
+}
+
+impl FrostContext {
+    /// Check in the background that the result of the job call `call_id` submitted on-chain is
+    /// `local`, if enabled, see [`check`].
+    ///
+    /// A mismatch is logged as an error, the job itself already returned.
+    pub(crate) fn check_submission(&self, call_id: u64, local: &[u8]) {
+        let Some(timeout) = self.submission_check else {
+            return;
+        };
+        let (coordinator, local) = (self.coordinator.clone(), local.to_vec());
+        tokio::spawn(async move {
+            match check(coordinator.as_ref(), call_id, &local, timeout).await {
+                Ok(()) => tracing::debug!(call_id, "The submitted result is the local one"),
+                Err(Error::Mismatch {
+                    call_id,
+                    submitted,
+                    local,
+                }) => tracing::error!(
+                    call_id,
+                    submitted = %hex::encode(submitted),
+                    local = %hex::encode(local),
+                    "THE RESULT SUBMITTED ON-CHAIN IS NOT THE ONE COMPUTED LOCALLY, \
+                     the submission is buggy or was tampered with"
+                ),
+                Err(e) => tracing::warn!(error = %e, "Failed to check the submitted result"),
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{BTreeMap, BTreeSet};
+
+    use super::*;
+    use gadget_sdk::subxt_core::ext::sp_core::ecdsa;
+    use gadget_sdk::subxt_core::utils::AccountId32;
+    use gadget_sdk::tangle_subxt::tangle_testnet_runtime::api::runtime_types::sp_arithmetic::per_things::Percent;
+
+    /// A coordinator that only knows the results submitted for its job calls.
+    struct SubmittedResults(BTreeMap<u64, Vec<u8>>);
+
+    #[async_trait::async_trait]
+    impl Coordinator for SubmittedResults {
+        fn service_id(&self) -> eyre::Result<u64> {
+            Ok(0)
+        }
+
+        async fn operators(&self) -> eyre::Result<BTreeMap<AccountId32, ecdsa::Public>> {
+            Ok(BTreeMap::new())
+        }
+
+        async fn restakes(&self) -> eyre::Result<Vec<(AccountId32, Percent)>> {
+            Ok(Vec::new())
+        }
+
+        async fn paused_operators(
+            &self,
+            _call_id: u64,
+            _operators: &BTreeMap<AccountId32, ecdsa::Public>,
+        ) -> eyre::Result<BTreeSet<ecdsa::Public>> {
+            Ok(BTreeSet::new())
+        }
+
+        async fn set_online(&self, _online: bool) -> eyre::Result<()> {
+            Ok(())
+        }
+
+        async fn current_call_id(&self) -> eyre::Result<u64> {
+            Ok(0)
+        }
+
+        async fn call_block(&self, call_id: u64) -> eyre::Result<u64> {
+            Ok(call_id)
+        }
+
+        async fn call_time(&self, _call_id: u64) -> eyre::Result<u64> {
+            Ok(0)
+        }
+
+        async fn caller(&self, _call_id: u64) -> eyre::Result<AccountId32> {
+            Ok(AccountId32([0; 32]))
+        }
+
+        async fn service_owner(&self) -> eyre::Result<AccountId32> {
+            Ok(AccountId32([0; 32]))
+        }
+
+        async fn operators_changed(&self) -> eyre::Result<()> {
+            std::future::pending().await
+        }
+
+        async fn submitted_result(&self, call_id: u64) -> eyre::Result<Option<Vec<u8>>> {
+            Ok(self.0.get(&call_id).cloned())
+        }
+    }
+
+    #[tokio::test]
+    async fn altered_submission_is_detected() {
+        let local = b"signature".to_vec();
+        let mut altered = local.clone();
+        altered[0] ^= 1;
+        let coordinator = SubmittedResults(BTreeMap::from([(1, local.clone()), (2, altered)]));
+        let timeout = Duration::from_millis(100);
+
+        check(&coordinator, 1, &local, timeout).await.unwrap();
+        assert!(matches!(
+            check(&coordinator, 2, &local, timeout).await,
+            Err(Error::Mismatch { call_id: 2, submitted, .. }) if submitted != local
+        ));
+        assert!(matches!(
+            check(&coordinator, 3, &local, timeout).await,
+            Err(Error::NotSubmitted { call_id: 3, .. })
+        ));
+    }
+}
@@ -0,0 +1,472 @@
+//! Testing utilities for protocols running over
+//! [`NetworkDeliveryWrapper`](gadget_sdk::network::round_based_compat::NetworkDeliveryWrapper).
+//!
+//! [`MockNetwork`] is an in-memory, channel-backed stand-in for the libp2p network, so that a
+//! full protocol can be driven in a single process:
+//!
+//! ```ignore
+//! let network = MockNetwork::new(MockNetworkConfig::default());
+//! let mux = network.multiplexer(my_ecdsa_key);
+//! let delivery = NetworkDeliveryWrapper::new(mux, i, task_hash, parties);
+//! ```
+//!
+//! The operators of a service are run on it as [`FrostContext`]s of [`operator_contexts`],
+//! coordinated by a [`MockCoordinator`], and [`on_all`] runs a job on all of them at once.
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::Arc;
+use std::time::Duration;
+
+use color_eyre::eyre;
+use gadget_sdk as sdk;
+use sdk::futures::Future;
+use sdk::keystore::Backend;
+use sdk::network::{Network, NetworkMultiplexer, ProtocolMessage};
+use sdk::parking_lot::RwLock;
+use sdk::random::rand::{self, Rng};
+use sdk::subxt_core::ext::sp_core::{ecdsa, Pair};
+use sdk::subxt_core::utils::AccountId32;
+use sdk::tangle_subxt::tangle_testnet_runtime::api::runtime_types::sp_arithmetic::per_things::Percent;
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+
+use crate::coordinator::Coordinator;
+use crate::operators::ConnectedPeers;
+use crate::FrostContext;
+
+/// How the [`MockNetwork`] delivers messages.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct MockNetworkConfig {
+    /// The delay before a message reaches its recipients.
+    ///
+    /// Without one, a peer can receive the first message of a protocol before it opened the
+    /// stream of the protocol, and the multiplexer does not keep it: the protocols between
+    /// several peers need some latency.
+    pub latency: Duration,
+    /// The probability, between `0.0` and `1.0`, that a message is dropped on its way to a
+    /// recipient.
+    ///
+    /// The multiplexer delivers messages in order, so a dropped message stalls the stream it
+    /// was sent on: this is meant to exercise timeouts and failure handling.
+    pub loss: f64,
+}
+
+type Peers = Arc<RwLock<BTreeMap<ecdsa::Public, UnboundedSender<ProtocolMessage>>>>;
+
+/// An in-memory network that peers join with their ECDSA key.
+///
+/// Like the gossip network, broadcasts reach every other peer and direct messages are routed
+/// by the ECDSA key of their recipient.
+#[derive(Clone, Debug, Default)]
+pub struct MockNetwork {
+    config: MockNetworkConfig,
+    peers: Peers,
+}
+
+impl MockNetwork {
+    /// Create a new, empty, network.
+    pub fn new(config: MockNetworkConfig) -> Self {
+        Self {
+            config,
+            peers: Default::default(),
+        }
+    }
+
+    /// Join the network as `key`.
+    ///
+    /// Joining again with the same key disconnects the previous handle.
+    pub fn connect(&self, key: ecdsa::Public) -> MockNetworkHandle {
+        let (tx, rx) = unbounded_channel();
+        self.peers.write().insert(key, tx);
+        MockNetworkHandle {
+            me: key,
+            config: self.config,
+            peers: self.peers.clone(),
+            rx: tokio::sync::Mutex::new(rx),
+        }
+    }
+
+    /// Leave the network as `key`, the messages sent to it are dropped from now on.
+    pub fn disconnect(&self, key: &ecdsa::Public) {
+        self.peers.write().remove(key);
+    }
+
+    /// Join the network as `key`, returning a multiplexer ready to be used with
+    /// [`NetworkDeliveryWrapper`](gadget_sdk::network::round_based_compat::NetworkDeliveryWrapper).
+    ///
+    /// Must be called from within a tokio runtime.
+    pub fn multiplexer(&self, key: ecdsa::Public) -> Arc<NetworkMultiplexer> {
+        Arc::new(NetworkMultiplexer::new(self.connect(key)))
+    }
+}
+
+/// Every peer of the network is connected to every other one.
+#[async_trait::async_trait]
+impl ConnectedPeers for MockNetwork {
+    async fn connected(&self) -> BTreeSet<ecdsa::Public> {
+        self.peers.read().keys().copied().collect()
+    }
+}
+
+/// A peer of a [`MockNetwork`].
+#[derive(Debug)]
+pub struct MockNetworkHandle {
+    me: ecdsa::Public,
+    config: MockNetworkConfig,
+    peers: Peers,
+    rx: tokio::sync::Mutex<UnboundedReceiver<ProtocolMessage>>,
+}
+
+impl MockNetworkHandle {
+    /// Deliver `message` to `to`, after the configured latency and unless it is lost.
+    fn deliver(&self, to: UnboundedSender<ProtocolMessage>, message: ProtocolMessage) {
+        if self.config.loss > 0.0 && rand::thread_rng().gen_bool(self.config.loss.min(1.0)) {
+            return;
+        }
+        // A recipient that left the network is not an error for the sender.
+        if self.config.latency.is_zero() {
+            let _ = to.send(message);
+        } else {
+            let latency = self.config.latency;
+            tokio::spawn(async move {
+                tokio::time::sleep(latency).await;
+                let _ = to.send(message);
+            });
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl Network for MockNetworkHandle {
+    async fn next_message(&self) -> Option<ProtocolMessage> {
+        self.rx.lock().await.recv().await
+    }
+
+    async fn send_message(&self, message: ProtocolMessage) -> Result<(), sdk::Error> {
+        let recipient = message.recipient.as_ref().map(|r| r.ecdsa_key);
+        let targets = {
+            let peers = self.peers.read();
+            match recipient {
+                Some(Some(key)) => {
+                    let to = peers
+                        .get(&key)
+                        .cloned()
+                        .ok_or_else(|| sdk::Error::Network {
+                            reason: format!("No peer found for ecdsa public key: {key}"),
+                        })?;
+                    vec![to]
+                }
+                Some(None) => {
+                    return Err(sdk::Error::Network {
+                        reason: "Direct message without a recipient key".to_string(),
+                    })
+                }
+                None => peers
+                    .iter()
+                    .filter(|(key, _)| **key != self.me)
+                    .map(|(_, to)| to.clone())
+                    .collect(),
+            }
+        };
+        for to in targets {
+            self.deliver(to, message.clone());
+        }
+        Ok(())
+    }
+}
+
+/// A fixed set of operators, running a single job call.
+pub struct MockCoordinator {
+    /// The operators of the service.
+    pub operators: BTreeMap<AccountId32, ecdsa::Public>,
+    /// The job call being run.
+    pub call_id: u64,
+    /// How long after being watched the operators change, if they ever do.
+    pub change_after: Option<Duration>,
+    /// The account making the call, the first operator if `None`.
+    pub caller: Option<AccountId32>,
+}
+
+/// The owner of the services of a [`MockCoordinator`].
+pub const SERVICE_OWNER: AccountId32 = AccountId32([0; 32]);
+
+/// The id of the services of a [`MockCoordinator`].
+pub const SERVICE_ID: u64 = 1;
+
+/// The timestamp of the genesis block of a [`MockCoordinator`], Monday 2024-01-01 00:00:00 UTC.
+pub const GENESIS_TIME: u64 = 1_704_067_200;
+
+/// The seconds between two blocks of a [`MockCoordinator`].
+pub const BLOCK_TIME: u64 = 6;
+
+#[async_trait::async_trait]
+impl Coordinator for MockCoordinator {
+    fn service_id(&self) -> eyre::Result<u64> {
+        Ok(SERVICE_ID)
+    }
+
+    async fn operators(&self) -> eyre::Result<BTreeMap<AccountId32, ecdsa::Public>> {
+        Ok(self.operators.clone())
+    }
+
+    async fn restakes(&self) -> eyre::Result<Vec<(AccountId32, Percent)>> {
+        Ok(Vec::new())
+    }
+
+    async fn paused_operators(
+        &self,
+        _call_id: u64,
+        _operators: &BTreeMap<AccountId32, ecdsa::Public>,
+    ) -> eyre::Result<BTreeSet<ecdsa::Public>> {
+        Ok(BTreeSet::new())
+    }
+
+    async fn set_online(&self, _online: bool) -> eyre::Result<()> {
+        Ok(())
+    }
+
+    async fn current_call_id(&self) -> eyre::Result<u64> {
+        Ok(self.call_id)
+    }
+
+    /// The call is made in the block following its id.
+    async fn call_block(&self, call_id: u64) -> eyre::Result<u64> {
+        Ok(call_id + 1)
+    }
+
+    /// The blocks are made every [`BLOCK_TIME`] from [`GENESIS_TIME`].
+    async fn call_time(&self, call_id: u64) -> eyre::Result<u64> {
+        Ok(GENESIS_TIME + BLOCK_TIME * self.call_block(call_id).await?)
+    }
+
+    async fn caller(&self, _call_id: u64) -> eyre::Result<AccountId32> {
+        self.caller
+            .clone()
+            .or_else(|| self.operators.keys().next().cloned())
+            .ok_or_else(|| eyre::eyre!("No operators"))
+    }
+
+    async fn service_owner(&self) -> eyre::Result<AccountId32> {
+        Ok(SERVICE_OWNER)
+    }
+
+    async fn operators_changed(&self) -> eyre::Result<()> {
+        match self.change_after {
+            Some(after) => tokio::time::sleep(after).await,
+            None => std::future::pending().await,
+        }
+        Ok(())
+    }
+
+    async fn submitted_result(&self, _call_id: u64) -> eyre::Result<Option<Vec<u8>>> {
+        Ok(None)
+    }
+}
+
+/// A directory removed on drop.
+pub struct TempDir(pub std::path::PathBuf);
+
+impl TempDir {
+    pub fn new(name: &str) -> Self {
+        Self(std::env::temp_dir().join(format!("frost-{name}-{}", std::process::id())))
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+/// The contexts of `n` operators of a `network`, all running the job call `call_id`, with
+/// their keystores in `dir`.
+pub fn operator_contexts(
+    network: &MockNetwork,
+    dir: &TempDir,
+    n: u8,
+    call_id: u64,
+) -> Vec<FrostContext> {
+    let configs = (1..=n)
+        .map(|i| {
+            let keystore = dir.0.join(i.to_string());
+            std::fs::create_dir_all(&keystore).unwrap();
+            let mut config = sdk::config::StdGadgetConfiguration::default();
+            config.keystore_uri = format!("file:{}", keystore.display());
+            config
+                .keystore()
+                .unwrap()
+                .ecdsa_generate_new(Some(&[i; 32]))
+                .unwrap();
+            let key = config.first_ecdsa_signer().unwrap().signer().public();
+            (AccountId32([i; 32]), (config, key))
+        })
+        .collect::<BTreeMap<_, _>>();
+    let operators = configs
+        .iter()
+        .map(|(account, (_, key))| (account.clone(), *key))
+        .collect::<BTreeMap<_, _>>();
+    configs
+        .into_values()
+        .map(|(config, key)| {
+            FrostContext::with_network(config, network.multiplexer(key))
+                .unwrap()
+                .with_coordinator(MockCoordinator {
+                    operators: operators.clone(),
+                    call_id,
+                    change_after: None,
+                    caller: None,
+                })
+        })
+        .collect()
+}
+
+/// How long the jobs run by [`on_all`] may take.
+pub const PROTOCOL_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Run `job` on each of the `contexts` concurrently, returning the results in their order.
+///
+/// Panics if the jobs do not all finish within [`PROTOCOL_TIMEOUT`].
+pub async fn on_all<F, Fut, T, E>(contexts: &[FrostContext], job: F) -> Vec<Result<T, String>>
+where
+    F: Fn(FrostContext) -> Fut,
+    Fut: Future<Output = Result<T, E>> + Send + 'static,
+    T: Send + 'static,
+    E: std::fmt::Display,
+{
+    let runs = contexts
+        .iter()
+        .cloned()
+        .map(|context| {
+            let job = job(context);
+            tokio::spawn(async move { job.await.map_err(|e| e.to_string()) })
+        })
+        .collect::<Vec<_>>();
+    let mut results = Vec::with_capacity(runs.len());
+    for run in runs {
+        let result = tokio::time::timeout(PROTOCOL_TIMEOUT, run)
+            .await
+            .expect("the jobs did not finish")
+            .unwrap();
+        results.push(result);
+    }
+    results
+}
+
+/// Run a keygen of `ciphersuite` with the threshold `t` on all the `contexts`, returning the
+/// group key they agreed on.
+pub async fn keygen_on_all(contexts: &[FrostContext], ciphersuite: &str, t: u16) -> Vec<u8> {
+    let keys = on_all(contexts, |context| {
+        crate::keygen::keygen(ciphersuite.to_string(), t, context)
+    })
+    .await
+    .into_iter()
+    .collect::<Result<BTreeSet<_>, _>>()
+    .unwrap();
+    assert_eq!(
+        keys.len(),
+        1,
+        "the operators did not agree on the group key"
+    );
+    keys.into_iter().next().unwrap()
+}
+
+/// Sign `msg` with the key `pubkey` on all the `contexts`, returning their results.
+pub async fn sign_on_all(
+    contexts: &[FrostContext],
+    pubkey: &[u8],
+    msg: &[u8],
+) -> Vec<Result<Vec<u8>, String>> {
+    on_all(contexts, |context| {
+        crate::sign::sign(pubkey.to_vec(), msg.to_vec(), context)
+    })
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rounds::keygen;
+    use frost_secp256k1::Secp256K1Sha256;
+    use gadget_sdk::network::round_based_compat::NetworkDeliveryWrapper;
+    use gadget_sdk::random::SeedableRng;
+
+    fn key(i: u8) -> ecdsa::Public {
+        let mut key = [0u8; 33];
+        key[0] = 0x02;
+        key[1] = i;
+        ecdsa::Public::from_raw(key)
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn drives_a_full_keygen() {
+        const N: u16 = 4;
+        const T: u16 = 3;
+        let network = MockNetwork::new(MockNetworkConfig {
+            latency: Duration::from_millis(10),
+            loss: 0.0,
+        });
+        let parties = (0..N)
+            .map(|i| (i, key(i as u8)))
+            .collect::<BTreeMap<_, _>>();
+        let task_hash = [42u8; 32];
+        // Join before anyone starts sending, so that no broadcast is missed.
+        let muxes = parties
+            .values()
+            .map(|k| network.multiplexer(*k))
+            .collect::<Vec<_>>();
+
+        let mut tasks = vec![];
+        for (i, mux) in (0..N).zip(muxes) {
+            let delivery = NetworkDeliveryWrapper::new(mux, i, task_hash, parties.clone());
+            let party = round_based::MpcParty::connected(delivery);
+            tasks.push(tokio::spawn(async move {
+                let rng = &mut rand::rngs::StdRng::seed_from_u64(u64::from(i));
+                keygen::run::<_, Secp256K1Sha256, _>(
+                    rng,
+                    T,
+                    N,
+                    i,
+                    keygen::BroadcastCheck::Unchecked,
+                    None,
+                    party,
+                    None,
+                )
+                .await
+            }));
+        }
+
+        let mut verifying_keys = vec![];
+        for task in tasks {
+            let (_, pub_key_pkg) = tokio::time::timeout(Duration::from_secs(60), task)
+                .await
+                .expect("keygen timed out")
+                .unwrap()
+                .unwrap();
+            verifying_keys.push(*pub_key_pkg.verifying_key());
+        }
+        assert!(verifying_keys.windows(2).all(|w| w[0] == w[1]));
+    }
+
+    #[tokio::test]
+    async fn lost_messages_are_not_delivered() {
+        let network = MockNetwork::new(MockNetworkConfig {
+            latency: Duration::ZERO,
+            loss: 1.0,
+        });
+        let alice = network.connect(key(1));
+        let bob = network.connect(key(2));
+        let message = ProtocolMessage {
+            identifier_info: sdk::network::IdentifierInfo {
+                message_id: 0,
+                round_id: 0,
+            },
+            sender: sdk::network::ParticipantInfo {
+                user_id: 0,
+                ecdsa_key: Some(key(1)),
+            },
+            recipient: None,
+            payload: vec![1, 2, 3],
+        };
+        alice.send_message(message).await.unwrap();
+        let received = tokio::time::timeout(Duration::from_millis(50), bob.next_message()).await;
+        assert!(received.is_err());
+    }
+}
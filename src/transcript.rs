@@ -0,0 +1,619 @@
+//! Transcripts of the keygen and signing messages.
+//!
+//! Every keygen and signing records the digest of each message this node sent and received, and
+//! writes the resulting [`Transcript`] to the store under the job call id, whatever the outcome,
+//! to be queried with [`keygen_transcript`], or with [`get_signed_transcript`] signed by the
+//! operator key of this node.
+//!
+//! When the correctness of a session is disputed, the transcripts of two parties can be compared
+//! with [`Transcript::divergences`]: honest parties agree on every broadcast message and on the
+//! point-to-point messages they exchanged, while a party that sent something else than what it
+//! claims is caught on the messages it altered. Signed by the operators, see
+//! [`SignedTranscript`], the transcripts are evidence a third party can check, a party cannot
+//! deny the messages of its own transcript.
+//!
+//! Nor can it deny the messages it sent to the others: every message is signed by its sender
+//! over its session, round, receiver and digest, and the receiver keeps the signature in its
+//! transcript, see [`TranscriptEntry::verify`]. A message whose signature does not verify is
+//! passed on without its payload, so the protocol blames its sender as for a malformed message.
+use std::collections::{BTreeMap, BTreeSet};
+use std::pin::Pin;
+use std::sync::Arc;
+
+use api::services::events::JobCalled;
+use gadget_sdk as sdk;
+use gadget_sdk::futures::stream::BoxStream;
+use gadget_sdk::futures::{future, Sink, SinkExt, StreamExt, TryStreamExt};
+use round_based::{Delivery, Incoming, MessageDestination, Outgoing, ProtocolMessage};
+use sdk::event_listener::tangle::{
+    jobs::{services_post_processor, services_pre_processor},
+    TangleEventListener,
+};
+use sdk::parking_lot::Mutex;
+use sdk::subxt_core::ext::sp_core::{ecdsa, keccak_256, Pair};
+use sdk::tangle_subxt::tangle_testnet_runtime::api;
+
+use crate::codec::Envelope;
+use crate::kv::SharedDynKVStore;
+use crate::FrostContext;
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("No transcript for job call {0}")]
+    NotFound(u64),
+    #[error(transparent)]
+    Config(#[from] sdk::config::Error),
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+    #[error(transparent)]
+    Bincode(#[from] bincode::Error),
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+}
+
+/// A message sent or received during a keygen or signing.
+#[derive(
+    Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, serde::Serialize, serde::Deserialize,
+)]
+pub struct TranscriptEntry {
+    pub round: u16,
+    pub sender: u16,
+    /// The recipient of a point-to-point message, `None` for a broadcast message.
+    pub receiver: Option<u16>,
+    /// The hex encoded SHA-256 digest of the message.
+    pub digest: String,
+    /// The hex encoded recoverable ECDSA signature of the message by the operator of its
+    /// sender, `None` for a received message whose signature does not verify.
+    pub signature: Option<String>,
+}
+
+impl TranscriptEntry {
+    fn new(round: u16, sender: u16, receiver: Option<u16>, payload: &[u8]) -> Self {
+        Self {
+            round,
+            sender,
+            receiver,
+            digest: hex::encode(sdk::compute_sha256_hash!(payload)),
+            signature: None,
+        }
+    }
+
+    /// The `keccak256` hash of the message signed by its sender, binding its digest to the
+    /// session, the round and the receiver it was sent in.
+    fn signing_hash(&self, session: &[u8; 32]) -> [u8; 32] {
+        let receiver = match self.receiver {
+            None => vec![0],
+            Some(j) => [&[1][..], &j.to_be_bytes()].concat(),
+        };
+        keccak_256(
+            &[
+                &session[..],
+                &self.round.to_be_bytes(),
+                &receiver,
+                self.digest.as_bytes(),
+            ]
+            .concat(),
+        )
+    }
+
+    /// Whether the message of the session `session` is signed by `sender`, the operator of its
+    /// sender.
+    pub fn verify(&self, session: &[u8; 32], sender: &ecdsa::Public) -> bool {
+        let signature = self
+            .signature
+            .as_ref()
+            .and_then(|signature| hex::decode(signature).ok())
+            .and_then(|bytes| ecdsa::Signature::from_slice(&bytes));
+        signature
+            .is_some_and(|s| ecdsa::Pair::verify_prehashed(&s, &self.signing_hash(session), sender))
+    }
+
+    /// Whether the parties `a` and `b` must both have seen this message.
+    fn seen_by_both(&self, a: u16, b: u16) -> bool {
+        match self.receiver {
+            None => true,
+            Some(receiver) => {
+                (self.sender, receiver) == (a, b) || (self.sender, receiver) == (b, a)
+            }
+        }
+    }
+}
+
+/// The messages a party sent and received during a keygen or signing.
+#[derive(Clone, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct Transcript {
+    /// The job call id.
+    pub call_id: u64,
+    /// The index of the party.
+    pub party_index: u16,
+    /// The hex encoded name of the network session.
+    pub session: String,
+    /// The hex encoded SHA-256 digest of the messages.
+    pub digest: String,
+    /// The messages, in order of round, sender and receiver.
+    pub messages: Vec<TranscriptEntry>,
+}
+
+impl Transcript {
+    /// The messages that this party and the `other` should both have seen, but that only one
+    /// of them has, or has differently.
+    ///
+    /// Two honest parties have no divergence.
+    pub fn divergences(&self, other: &Transcript) -> Vec<TranscriptEntry> {
+        let (a, b) = (self.party_index, other.party_index);
+        let shared = |transcript: &Transcript| {
+            transcript
+                .messages
+                .iter()
+                .filter(|m| m.seen_by_both(a, b))
+                .cloned()
+                .collect::<BTreeSet<_>>()
+        };
+        let (mine, theirs) = (shared(self), shared(other));
+        mine.symmetric_difference(&theirs).cloned().collect()
+    }
+
+    /// The `keccak256` hash of the transcript signed by its party, see [`SignedTranscript`].
+    fn signing_hash(&self) -> Result<[u8; 32], Error> {
+        Ok(keccak_256(&bincode::serialize(self)?))
+    }
+
+    /// Sign the transcript with the operator key `signer` of its party.
+    pub fn sign(self, signer: &ecdsa::Pair) -> Result<SignedTranscript, Error> {
+        let signature = signer.sign_prehashed(&self.signing_hash()?);
+        Ok(SignedTranscript {
+            transcript: self,
+            operator: hex::encode(signer.public()),
+            signature: hex::encode(signature.0),
+        })
+    }
+}
+
+/// A [`Transcript`] signed by the operator of its party, the ECDSA signature of the `keccak256`
+/// hash of its bincode encoding.
+#[derive(Clone, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct SignedTranscript {
+    pub transcript: Transcript,
+    /// The hex encoded ECDSA key of the operator.
+    pub operator: String,
+    /// The hex encoded recoverable ECDSA signature.
+    pub signature: String,
+}
+
+impl SignedTranscript {
+    /// Whether the transcript is signed by `operator`.
+    ///
+    /// The key claimed in the transcript is not trusted, the one of the operator must be read
+    /// from the chain.
+    pub fn verify(&self, operator: &ecdsa::Public) -> bool {
+        let Ok(hash) = self.transcript.signing_hash() else {
+            return false;
+        };
+        let signature = hex::decode(&self.signature)
+            .ok()
+            .and_then(|bytes| ecdsa::Signature::from_slice(&bytes));
+        signature.is_some_and(|s| ecdsa::Pair::verify_prehashed(&s, &hash, operator))
+    }
+}
+
+/// A delivery recording its messages in a transcript, see [`TranscriptRecorder::record`].
+pub(crate) type TranscriptDelivery<D> = (
+    BoxStream<'static, Result<Incoming<Envelope>, <D as Delivery<Envelope>>::ReceiveError>>,
+    Pin<
+        Box<
+            dyn Sink<Outgoing<Envelope>, Error = <D as Delivery<Envelope>>::SendError>
+                + Send
+                + 'static,
+        >,
+    >,
+);
+
+/// The length of the signature prefixed to the payload of every message.
+const SIGNATURE_LEN: usize = 65;
+
+/// Records the transcript of a keygen or signing, see [`TranscriptRecorder::record`].
+#[derive(Clone)]
+pub(crate) struct TranscriptRecorder {
+    call_id: u64,
+    session: [u8; 32],
+    party_index: u16,
+    signer: ecdsa::Pair,
+    parties: Arc<BTreeMap<u16, ecdsa::Public>>,
+    messages: Arc<Mutex<BTreeSet<TranscriptEntry>>>,
+}
+
+impl std::fmt::Debug for TranscriptRecorder {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TranscriptRecorder")
+            .field("call_id", &self.call_id)
+            .field("session", &hex::encode(self.session))
+            .field("party_index", &self.party_index)
+            .finish_non_exhaustive()
+    }
+}
+
+impl TranscriptRecorder {
+    /// Record the transcript of the party `party_index` in the session `session` of the job
+    /// call `call_id`, signing its messages with its operator key `signer` and checking those of
+    /// the others against the operator keys of the `parties`, in order of party index.
+    pub(crate) fn new<'a>(
+        call_id: u64,
+        session: [u8; 32],
+        party_index: u16,
+        signer: ecdsa::Pair,
+        parties: impl IntoIterator<Item = &'a ecdsa::Public>,
+    ) -> Self {
+        let parties = (0..).zip(parties.into_iter().copied()).collect();
+        Self {
+            call_id,
+            session,
+            party_index,
+            signer,
+            parties: Arc::new(parties),
+            messages: Default::default(),
+        }
+    }
+
+    /// Record the received message and strip its signature, or its whole payload if the
+    /// signature does not verify.
+    fn push_received(&self, mut incoming: Incoming<Envelope>) -> Incoming<Envelope> {
+        let receiver = (!incoming.is_broadcast()).then_some(self.party_index);
+        let (signature, payload) = incoming
+            .msg
+            .payload()
+            .split_at_checked(SIGNATURE_LEN)
+            .unwrap_or((&[], incoming.msg.payload()));
+        let mut entry =
+            TranscriptEntry::new(incoming.msg.round(), incoming.sender, receiver, payload);
+        entry.signature = Some(hex::encode(signature));
+        let verified = self
+            .parties
+            .get(&incoming.sender)
+            .is_some_and(|sender| entry.verify(&self.session, sender));
+        let payload = if verified {
+            payload.to_vec()
+        } else {
+            tracing::warn!(
+                from = incoming.sender,
+                round = entry.round,
+                "Received a message whose signature does not verify"
+            );
+            entry.signature = None;
+            vec![]
+        };
+        incoming.msg = Envelope::new(incoming.msg.version(), incoming.msg.round(), payload);
+        self.messages.lock().insert(entry);
+        incoming
+    }
+
+    /// Record the message to send and sign it.
+    fn push_sent(&self, mut outgoing: Outgoing<Envelope>) -> Outgoing<Envelope> {
+        let receiver = match outgoing.recipient {
+            MessageDestination::AllParties => None,
+            MessageDestination::OneParty(j) => Some(j),
+        };
+        let mut entry = TranscriptEntry::new(
+            outgoing.msg.round(),
+            self.party_index,
+            receiver,
+            outgoing.msg.payload(),
+        );
+        let signature = self
+            .signer
+            .sign_prehashed(&entry.signing_hash(&self.session));
+        entry.signature = Some(hex::encode(signature.0));
+        let payload = [&signature.0[..], outgoing.msg.payload()].concat();
+        outgoing.msg = Envelope::new(outgoing.msg.version(), outgoing.msg.round(), payload);
+        self.messages.lock().insert(entry);
+        outgoing
+    }
+
+    /// Record the messages sent and received on `delivery`, signing the sent ones.
+    pub(crate) fn record<D>(&self, delivery: D) -> TranscriptDelivery<D>
+    where
+        D: Delivery<Envelope>,
+        D::Send: Send + 'static,
+        D::Receive: Send + 'static,
+    {
+        let (incoming, outgoing) = delivery.split();
+        let recorder = self.clone();
+        let incoming = incoming.map_ok(move |incoming| recorder.push_received(incoming));
+        let recorder = self.clone();
+        let outgoing = outgoing.with(move |outgoing: Outgoing<Envelope>| {
+            future::ready(Ok(recorder.push_sent(outgoing)))
+        });
+        (incoming.boxed(), Box::pin(outgoing))
+    }
+
+    /// The transcript of the messages recorded so far.
+    pub(crate) fn finish(&self) -> Result<Transcript, Error> {
+        let messages = self.messages.lock().iter().cloned().collect::<Vec<_>>();
+        let digest = hex::encode(sdk::compute_sha256_hash!(&bincode::serialize(&messages)?));
+        Ok(Transcript {
+            call_id: self.call_id,
+            party_index: self.party_index,
+            session: hex::encode(self.session),
+            digest,
+            messages,
+        })
+    }
+}
+
+fn store_key(call_id: u64) -> String {
+    format!("transcript/{call_id}")
+}
+
+/// Write `transcript` to the store.
+pub(crate) fn save(
+    store: &SharedDynKVStore<String, Vec<u8>>,
+    transcript: &Transcript,
+) -> Result<(), Error> {
+    Ok(store.set(
+        store_key(transcript.call_id),
+        serde_json::to_vec(transcript)?,
+    )?)
+}
+
+/// Read the transcript of the job call `call_id` from the store.
+pub(crate) fn read(
+    store: &SharedDynKVStore<String, Vec<u8>>,
+    call_id: u64,
+) -> Result<Transcript, Error> {
+    let bytes = store
+        .get(&store_key(call_id))?
+        .ok_or(Error::NotFound(call_id))?;
+    Ok(serde_json::from_slice(&bytes)?)
+}
+
+/// Get the transcript of a keygen on this node.
+///
+/// # Parameters
+/// - `call_id`: The call id of the keygen job.
+///
+/// # Returns
+/// The JSON [`Transcript`] of the keygen, to compare with the transcripts of the other
+/// operators.
+///
+/// # Errors
+/// - `NotFound`: If this node did not take part in the keygen.
+#[sdk::job(
+    id = 9,
+    params(call_id),
+    result(_),
+    event_listener(
+        listener = TangleEventListener::<FrostContext, JobCalled>,
+        pre_processor = services_pre_processor,
+        post_processor = services_post_processor,
+    )
+)]
+#[tracing::instrument(skip_all, parent = context.config.span.clone(), err)]
+pub async fn keygen_transcript(call_id: u64, context: FrostContext) -> Result<Vec<u8>, Error> {
+    let transcript = read(&context.store, call_id)?;
+    Ok(serde_json::to_vec(&transcript)?)
+}
+
+/// Get the transcript of a keygen or signing on this node, signed by its operator key.
+///
+/// # Parameters
+/// - `call_id`: The call id of the keygen or signing job.
+///
+/// # Returns
+/// The JSON [`SignedTranscript`] of the session, for a third party to check against the
+/// operator key with [`SignedTranscript::verify`], then against the transcripts of the other
+/// operators.
+///
+/// # Errors
+/// - `NotFound`: If this node did not take part in the session.
+#[sdk::job(
+    id = 21,
+    params(call_id),
+    result(_),
+    event_listener(
+        listener = TangleEventListener::<FrostContext, JobCalled>,
+        pre_processor = services_pre_processor,
+        post_processor = services_post_processor,
+    )
+)]
+#[tracing::instrument(skip_all, parent = context.config.span.clone(), err)]
+pub async fn get_signed_transcript(call_id: u64, context: FrostContext) -> Result<Vec<u8>, Error> {
+    let transcript = read(&context.store, call_id)?;
+    let signer = context.config.first_ecdsa_signer()?;
+    let signed = transcript.sign(signer.signer())?;
+    Ok(serde_json::to_vec(&signed)?)
+}
+
+impl FrostContext {
+    /// Persist the transcript recorded by `recorder`.
+    ///
+    /// Failing to write it does not change the outcome of the keygen or signing, it is only
+    /// logged.
+    pub(crate) fn save_transcript(&self, recorder: &TranscriptRecorder) {
+        let result = recorder
+            .finish()
+            .and_then(|transcript| save(&self.store, &transcript));
+        if let Err(e) = result {
+            tracing::warn!(
+                call_id = recorder.call_id,
+                error = %e,
+                "Failed to save the transcript"
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use super::*;
+    use crate::codec::{versioned, CodecVersion};
+    use crate::rounds::keygen as keygen_protocol;
+    use gadget_sdk::random::rand::rngs::StdRng;
+    use gadget_sdk::random::SeedableRng;
+    use round_based::simulation::Simulation;
+    use round_based::{Mpc, MpcParty};
+
+    /// Alter the messages sent to `victim`, if any.
+    fn tamper<S>(
+        sink: S,
+        victim: Option<u16>,
+    ) -> Pin<Box<dyn Sink<Outgoing<Envelope>, Error = S::Error> + Send>>
+    where
+        S: Sink<Outgoing<Envelope>> + Send + 'static,
+        S::Error: Send,
+    {
+        Box::pin(sink.with(move |mut outgoing: Outgoing<Envelope>| {
+            if victim.is_some_and(|j| outgoing.recipient == MessageDestination::OneParty(j)) {
+                let mut payload = outgoing.msg.payload().to_vec();
+                *payload.last_mut().unwrap() ^= 1;
+                outgoing.msg =
+                    Envelope::new(crate::codec::CODEC_VERSION, outgoing.msg.round(), payload);
+            }
+            future::ready(Ok::<_, S::Error>(outgoing))
+        }))
+    }
+
+    #[tokio::test]
+    async fn honest_transcripts_reconcile() {
+        type C = frost_secp256k1::Secp256K1Sha256;
+        const N: u16 = 4;
+        const T: u16 = 3;
+        // The last party sends the victim another secret share than the one it recorded.
+        const DEVIATOR: u16 = 3;
+        const VICTIM: u16 = 2;
+
+        const SESSION: [u8; 32] = [9; 32];
+
+        let mut simulation = Simulation::<Envelope>::new();
+        let parties = (0..N).map(|_| simulation.add_party()).collect::<Vec<_>>();
+        let signers = (0..N)
+            .map(|i| ecdsa::Pair::from_seed(&[i as u8 + 1; 32]))
+            .collect::<Vec<_>>();
+        let keys = (0..N)
+            .zip(&signers)
+            .map(|(i, signer)| (i, signer.public()))
+            .collect::<BTreeMap<_, _>>();
+        let mut tasks = vec![];
+        let mut recorders = vec![];
+        for (i, party) in (0..N).zip(parties) {
+            let signer = signers[usize::from(i)].clone();
+            let recorder = TranscriptRecorder::new(930, SESSION, i, signer, keys.values());
+            recorders.push(recorder.clone());
+            let (incoming, outgoing) = party.into_party().delivery.split();
+            // The messages are altered on their way to the network, after the recording and the
+            // signing.
+            let outgoing = tamper(outgoing, (i == DEVIATOR).then_some(VICTIM));
+            let delivery = recorder.record((incoming, outgoing));
+            let delivery = versioned(delivery, CodecVersion::default());
+            tasks.push(tokio::spawn(async move {
+                let rng = &mut StdRng::seed_from_u64(u64::from(i));
+                keygen_protocol::run::<_, C, _>(
+                    rng,
+                    T,
+                    N,
+                    i,
+                    keygen_protocol::BroadcastCheck::Unchecked,
+                    None,
+                    MpcParty::connected(delivery),
+                    None,
+                )
+                .await
+                .map(|_| ())
+                .map_err(|e| e.to_string())
+            }));
+        }
+        let mut results = vec![];
+        for task in tasks {
+            results.push(task.await.unwrap());
+        }
+        assert!(results[VICTIM as usize].is_err());
+
+        let store: SharedDynKVStore<String, Vec<u8>> = Arc::new(crate::kv::MemKVStore::new());
+        let transcripts = recorders
+            .iter()
+            .map(|recorder| {
+                let transcript = recorder.finish().unwrap();
+                (recorder.party_index, transcript)
+            })
+            .collect::<BTreeMap<_, _>>();
+        save(&store, &transcripts[&0]).unwrap();
+        assert_eq!(read(&store, 930).unwrap(), transcripts[&0]);
+        assert!(matches!(read(&store, 931), Err(Error::NotFound(931))));
+
+        // The honest parties saw the same broadcasts and point-to-point messages.
+        assert!(results[0].is_ok() && results[1].is_ok());
+        assert_eq!(transcripts[&0].divergences(&transcripts[&1]), vec![]);
+        assert_eq!(transcripts[&0].divergences(&transcripts[&DEVIATOR]), vec![]);
+        // The altered share shows up on both sides of the dispute.
+        let divergences = transcripts[&DEVIATOR].divergences(&transcripts[&VICTIM]);
+        assert_eq!(divergences.len(), 2, "{divergences:?}");
+        for entry in &divergences {
+            assert_eq!((entry.sender, entry.receiver), (DEVIATOR, Some(VICTIM)));
+        }
+        // The victim holds the signature of every message but the altered ones.
+        for entry in &transcripts[&VICTIM].messages {
+            let altered = (entry.sender, entry.receiver) == (DEVIATOR, Some(VICTIM));
+            assert_eq!(entry.signature.is_none(), altered);
+            assert_eq!(entry.verify(&SESSION, &keys[&entry.sender]), !altered);
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn signed_transcripts_verify_against_operator_keys() {
+        use crate::testing::{
+            keygen_on_all, operator_contexts, MockNetwork, MockNetworkConfig, TempDir,
+        };
+        use frost_core::Ciphersuite;
+
+        let network = MockNetwork::new(MockNetworkConfig {
+            latency: std::time::Duration::from_millis(50),
+            loss: 0.0,
+        });
+        let dir = TempDir::new("signed-transcripts");
+        let contexts = operator_contexts(&network, &dir, 3, 993);
+        keygen_on_all(&contexts, frost_secp256k1::Secp256K1Sha256::ID, 2).await;
+
+        let keys = contexts
+            .iter()
+            .map(|context| context.local_identity::<crate::keygen::Error>().unwrap())
+            .collect::<Vec<_>>();
+        let mut transcripts = vec![];
+        for (context, key) in contexts.iter().zip(&keys) {
+            let signed = get_signed_transcript(993, context.clone()).await.unwrap();
+            let signed: SignedTranscript = serde_json::from_slice(&signed).unwrap();
+            assert!(signed.verify(key));
+            assert_eq!(signed.operator, hex::encode(key));
+            transcripts.push(signed);
+        }
+        // A transcript is not signed by the other operators.
+        assert!(!transcripts[0].verify(&keys[1]));
+        // Nor does it verify once altered.
+        let mut altered = transcripts[0].clone();
+        altered.transcript.messages.pop();
+        assert!(!altered.verify(&keys[0]));
+        // The messages are signed by their senders, for the session of the keygen only.
+        let session =
+            crate::session::keygen_session_name(993, frost_secp256k1::Secp256K1Sha256::ID);
+        assert_eq!(transcripts[0].transcript.session, hex::encode(session));
+        let operators = contexts[0].current_operators().await.unwrap();
+        let parties = crate::operators::party_indices(&operators)
+            .map(|(j, _, key)| (j, *key))
+            .collect::<BTreeMap<_, _>>();
+        for entry in &transcripts[0].transcript.messages {
+            assert!(entry.verify(&session, &parties[&entry.sender]));
+            assert!(!entry.verify(&[0; 32], &parties[&entry.sender]));
+        }
+        // The operators agree on the messages they exchanged.
+        assert_eq!(
+            transcripts[0]
+                .transcript
+                .divergences(&transcripts[1].transcript),
+            vec![]
+        );
+
+        assert!(matches!(
+            get_signed_transcript(994, contexts[0].clone()).await,
+            Err(Error::NotFound(994))
+        ));
+    }
+}
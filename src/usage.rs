@@ -0,0 +1,416 @@
+//! Signing statistics of the keys.
+//!
+//! Every signing job of a key updates its [`KeyUsage`] counters in the store once the
+//! signature is produced, by this node or by another signer reporting it, see
+//! [`outcome`](crate::rounds::outcome), so an operator can follow how often each key is used
+//! with [`key_usage_stats`]. The other signers only report their outcome with
+//! [`FrostContext::with_unique_messages`], otherwise an operator counts the signings it
+//! produced. A signing that failed, or whose outcome this node did not learn, is not counted,
+//! and the job fails if the counters cannot be written.
+//!
+//! A key can also be limited to a number of signings with [`set_key_usage_limit`], e.g. to force
+//! its rotation for compliance: once its counter reaches the limit, the signing jobs of the key
+//! fail with `KeyUsageLimitReached`. Each operator checks the limit against its own counters,
+//! which only count the signings it learnt were produced: an operator that missed the outcome
+//! of one, e.g. because its job timed out, lags behind the others and may still accept a signing
+//! they refuse. Concurrent signings of the key are checked against the same counter and may
+//! together exceed the limit.
+use api::services::events::JobCalled;
+use gadget_sdk as sdk;
+use sdk::event_listener::tangle::{
+    jobs::{services_post_processor, services_pre_processor},
+    TangleEventListener,
+};
+use sdk::tangle_subxt::tangle_testnet_runtime::api;
+
+use crate::kv::SharedDynKVStore;
+use crate::FrostContext;
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("The Secret Share for that key is not found")]
+    KeyNotFound,
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[error(transparent)]
+    Unauthorized(#[from] crate::operators::Unauthorized),
+}
+
+impl From<Error> for crate::sign::Error {
+    fn from(e: Error) -> Self {
+        match e {
+            Error::KeyNotFound => crate::sign::Error::KeyNotFound,
+            Error::Json(e) => crate::sign::Error::Json(e),
+            Error::Io(e) => crate::sign::Error::Io(e),
+            Error::Unauthorized(e) => crate::sign::Error::Other(e.into()),
+        }
+    }
+}
+
+/// How a key was used for signing, as seen by this node.
+#[derive(Clone, Debug, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct KeyUsage {
+    /// The number of messages signed with the key.
+    pub signings: u64,
+    /// The number of those this node produced the signature of.
+    pub participations: u64,
+    /// When the key last signed, in seconds since the Unix epoch.
+    pub last_signed: Option<u64>,
+    /// The number of messages the key can sign at most, see [`set_key_usage_limit`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_signings: Option<u64>,
+}
+
+fn store_key(pubkey: &[u8]) -> String {
+    format!("usage/{}", hex::encode(pubkey))
+}
+
+/// Read the usage of the key `pubkey`, all zero if it never signed.
+pub(crate) fn read(
+    store: &SharedDynKVStore<String, Vec<u8>>,
+    pubkey: &[u8],
+) -> Result<KeyUsage, Error> {
+    match store.get(&store_key(pubkey))? {
+        Some(bytes) => Ok(serde_json::from_slice(&bytes)?),
+        None => Ok(KeyUsage::default()),
+    }
+}
+
+/// Count `signings` more messages signed with the key `pubkey` at `now`, in seconds since the
+/// Unix epoch, with this node as a signer if `participated`.
+pub(crate) fn record(
+    store: &SharedDynKVStore<String, Vec<u8>>,
+    pubkey: &[u8],
+    signings: u64,
+    participated: bool,
+    now: u64,
+) -> Result<(), Error> {
+    update(store, pubkey, |usage| {
+        usage.signings += signings;
+        if participated {
+            usage.participations += signings;
+        }
+        usage.last_signed = Some(now);
+    })
+}
+
+/// Apply `f` to the usage of the key `pubkey`, retrying on a concurrent update.
+fn update(
+    store: &SharedDynKVStore<String, Vec<u8>>,
+    pubkey: &[u8],
+    mut f: impl FnMut(&mut KeyUsage),
+) -> Result<(), Error> {
+    let key = store_key(pubkey);
+    loop {
+        let current = store.get(&key)?;
+        let mut usage = match &current {
+            Some(bytes) => serde_json::from_slice(bytes)?,
+            None => KeyUsage::default(),
+        };
+        f(&mut usage);
+        if store.compare_and_swap(key.clone(), current, serde_json::to_vec(&usage)?)? {
+            return Ok(());
+        }
+    }
+}
+
+/// Get the signing statistics of a key.
+///
+/// # Parameters
+/// - `pubkey`: The public key generated by the [`crate::keygen::keygen`] protocol, or its label.
+///
+/// # Returns
+/// The JSON encoded [`KeyUsage`] of the key.
+///
+/// # Errors
+/// - `KeyNotFound`: If the key is not found.
+///
+/// # Note
+/// The statistics are local to this node, another operator may have seen other signings.
+#[sdk::job(
+    id = 13,
+    params(pubkey),
+    result(_),
+    event_listener(
+        listener = TangleEventListener::<FrostContext, JobCalled>,
+        pre_processor = services_pre_processor,
+        post_processor = services_post_processor,
+    )
+)]
+#[tracing::instrument(skip_all, parent = context.config.span.clone(), err)]
+pub async fn key_usage_stats(pubkey: Vec<u8>, context: FrostContext) -> Result<Vec<u8>, Error> {
+    let pubkey = context.resolve_key(pubkey)?;
+    if context.keygen_entry(&hex::encode(&pubkey))?.is_none() {
+        return Err(Error::KeyNotFound);
+    }
+    Ok(serde_json::to_vec(&read(&context.store, &pubkey)?)?)
+}
+
+/// Limit the number of messages a previously generated key can sign on this node.
+///
+/// # Parameters
+/// - `pubkey`: The public key generated by the [`crate::keygen::keygen`] protocol, or its label.
+/// - `max_signings`: The number of messages the key can sign at most, counting those it already
+///   signed, or 0 to lift the limit.
+///
+/// # Returns
+/// The public key.
+///
+/// # Errors
+/// - `KeyNotFound`: If the key is not found.
+/// - `Unauthorized`: If the job is not called by the service owner or one of its operators.
+///
+/// # Note
+/// Every operator must set the same limit, otherwise the signings past the lowest one are
+/// declined by some signers only, and the others wait for them until the job times out.
+#[sdk::job(
+    id = 19,
+    params(pubkey, max_signings),
+    result(_),
+    event_listener(
+        listener = TangleEventListener::<FrostContext, JobCalled>,
+        pre_processor = services_pre_processor,
+        post_processor = services_post_processor,
+    )
+)]
+#[tracing::instrument(skip_all, parent = context.config.span.clone(), err)]
+pub async fn set_key_usage_limit(
+    pubkey: Vec<u8>,
+    max_signings: u64,
+    context: FrostContext,
+) -> Result<Vec<u8>, Error> {
+    context.authorize_caller().await?;
+    let pubkey = context.resolve_key(pubkey)?;
+    if context.keygen_entry(&hex::encode(&pubkey))?.is_none() {
+        return Err(Error::KeyNotFound);
+    }
+    update(&context.store, &pubkey, |usage| {
+        usage.max_signings = (max_signings > 0).then_some(max_signings);
+    })?;
+    Ok(pubkey)
+}
+
+/// Forget the usage of the key `pubkey`, once it is deleted.
+pub(crate) fn forget(
+    store: &SharedDynKVStore<String, Vec<u8>>,
+    pubkey: &[u8],
+) -> Result<(), std::io::Error> {
+    store.del(&store_key(pubkey))
+}
+
+impl FrostContext {
+    /// Check that the key `pubkey` can sign `signings` more messages within its usage limit.
+    pub(crate) fn check_usage_limit(
+        &self,
+        pubkey: &[u8],
+        signings: u64,
+    ) -> Result<(), crate::sign::Error> {
+        let usage = read(&self.store, pubkey)?;
+        match usage.max_signings {
+            Some(max_signings) if usage.signings.saturating_add(signings) > max_signings => {
+                Err(crate::sign::Error::KeyUsageLimitReached {
+                    signings: usage.signings,
+                    max_signings,
+                })
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Count `signings` more messages signed with the key `pubkey`, see [`record`].
+    ///
+    /// Failing to write the counters fails the job, a signing left uncounted could otherwise
+    /// take the key past its usage limit.
+    pub(crate) fn record_usage(
+        &self,
+        pubkey: &[u8],
+        signings: u64,
+        participated: bool,
+    ) -> Result<(), crate::sign::Error> {
+        let now = self.clock.unix_secs();
+        record(&self.store, pubkey, signings, participated, now).map_err(|e| {
+            tracing::error!(error = %e, "Failed to update the key usage");
+            e.into()
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use super::*;
+    use crate::kv::KVStore;
+    use crate::testing::{
+        keygen_on_all, operator_contexts, sign_on_all, MockNetwork, MockNetworkConfig, TempDir,
+    };
+    use frost_core::Ciphersuite;
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn signings_are_counted() {
+        type C = frost_secp256k1::Secp256K1Sha256;
+        let network = MockNetwork::new(MockNetworkConfig {
+            latency: Duration::from_millis(50),
+            loss: 0.0,
+        });
+        let dir = TempDir::new("key-usage");
+        // The operators left out of the signers learn the signatures of the others.
+        let contexts = operator_contexts(&network, &dir, 3, 938)
+            .into_iter()
+            .map(|context| context.with_unique_messages(true))
+            .collect::<Vec<_>>();
+
+        let pubkey = keygen_on_all(&contexts, C::ID, 2).await;
+        for context in &contexts {
+            let usage = key_usage_stats(pubkey.clone(), context.clone()).await;
+            let usage: KeyUsage = serde_json::from_slice(&usage.unwrap()).unwrap();
+            assert_eq!(usage, KeyUsage::default());
+        }
+
+        let mut signed = vec![0u64; contexts.len()];
+        for msg in [&b"first"[..], b"second"] {
+            let results = sign_on_all(&contexts, &pubkey, msg).await;
+            for (result, signed) in results.into_iter().zip(&mut signed) {
+                *signed += u64::from(result.is_ok());
+            }
+        }
+
+        assert_eq!(signed.iter().sum::<u64>(), 4);
+        for (context, signed) in contexts.into_iter().zip(signed) {
+            let usage = key_usage_stats(pubkey.clone(), context).await;
+            let usage: KeyUsage = serde_json::from_slice(&usage.unwrap()).unwrap();
+            assert_eq!(usage.signings, 2);
+            assert_eq!(usage.participations, signed);
+            assert!(usage.last_signed.is_some());
+        }
+    }
+
+    #[test]
+    fn concurrent_records_are_all_counted() {
+        let store: SharedDynKVStore<String, Vec<u8>> = Arc::new(crate::kv::MemKVStore::new());
+        std::thread::scope(|scope| {
+            for t in 0..4 {
+                let store = &store;
+                scope.spawn(move || {
+                    for _ in 0..50 {
+                        record(store, b"key", 1, t % 2 == 0, 1).unwrap();
+                    }
+                });
+            }
+        });
+        let usage = read(&store, b"key").unwrap();
+        assert_eq!(usage.signings, 200);
+        assert_eq!(usage.participations, 100);
+    }
+
+    /// A store whose writes of the usage counters fail.
+    struct UncountedStore(SharedDynKVStore<String, Vec<u8>>);
+
+    impl KVStore for UncountedStore {
+        type Key = String;
+        type Value = Vec<u8>;
+        type Error = std::io::Error;
+
+        fn get(&self, key: &String) -> Result<Option<Vec<u8>>, std::io::Error> {
+            self.0.get(key)
+        }
+
+        fn set(&self, key: String, value: Vec<u8>) -> Result<(), std::io::Error> {
+            if key.starts_with("usage/") {
+                return Err(std::io::Error::other("disk full"));
+            }
+            self.0.set(key, value)
+        }
+
+        fn del(&self, key: &String) -> Result<(), std::io::Error> {
+            self.0.del(key)
+        }
+
+        fn ex(&self, key: &String) -> Result<bool, std::io::Error> {
+            self.0.ex(key)
+        }
+
+        fn compare_and_swap(
+            &self,
+            key: String,
+            expected: Option<Vec<u8>>,
+            new: Vec<u8>,
+        ) -> Result<bool, std::io::Error> {
+            if key.starts_with("usage/") {
+                return Err(std::io::Error::other("disk full"));
+            }
+            self.0.compare_and_swap(key, expected, new)
+        }
+
+        fn keys(&self) -> Result<Vec<Vec<u8>>, std::io::Error> {
+            self.0.keys()
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn signing_fails_when_it_cannot_be_counted() {
+        type C = frost_secp256k1::Secp256K1Sha256;
+        let network = MockNetwork::new(MockNetworkConfig {
+            latency: Duration::from_millis(50),
+            loss: 0.0,
+        });
+        let dir = TempDir::new("key-usage-uncounted");
+        let mut contexts = operator_contexts(&network, &dir, 3, 978);
+        let pubkey = keygen_on_all(&contexts, C::ID, 2).await;
+        for context in &mut contexts {
+            context.store = Arc::new(UncountedStore(context.store.clone()));
+            context.unique_messages = true;
+        }
+
+        for result in sign_on_all(&contexts, &pubkey, b"uncounted").await {
+            let e = result.unwrap_err();
+            assert!(e.to_string().contains("disk full"), "{e}");
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn signing_stops_at_the_usage_limit() {
+        use crate::sign::{sign, Error as SignError};
+
+        type C = frost_ed25519::Ed25519Sha512;
+        let network = MockNetwork::new(MockNetworkConfig {
+            latency: Duration::from_millis(50),
+            loss: 0.0,
+        });
+        let dir = TempDir::new("key-usage-limit");
+        let contexts = operator_contexts(&network, &dir, 3, 978);
+        let pubkey = keygen_on_all(&contexts, C::ID, 3).await;
+        for context in contexts.iter().cloned() {
+            set_key_usage_limit(pubkey.clone(), 2, context)
+                .await
+                .unwrap();
+        }
+
+        for msg in [&b"first"[..], b"second"] {
+            for result in sign_on_all(&contexts, &pubkey, msg).await {
+                result.unwrap();
+            }
+        }
+
+        for context in contexts {
+            let result = sign(pubkey.clone(), b"third".to_vec(), context.clone()).await;
+            assert!(
+                matches!(
+                    result,
+                    Err(SignError::KeyUsageLimitReached {
+                        signings: 2,
+                        max_signings: 2
+                    })
+                ),
+                "{result:?}"
+            );
+            let usage = key_usage_stats(pubkey.clone(), context).await;
+            let usage: KeyUsage = serde_json::from_slice(&usage.unwrap()).unwrap();
+            assert_eq!(usage.signings, 2);
+            assert_eq!(usage.max_signings, Some(2));
+        }
+    }
+}
@@ -0,0 +1,130 @@
+//! Deterministic keygen test vectors, to validate other FROST implementations against this one.
+//!
+//! [`keygen_vectors`] runs the DKG of every party in-process from a single seed and records
+//! every package exchanged, so that another implementation can replay the rounds and check
+//! that it reaches the same key packages.
+use std::collections::BTreeMap;
+
+use frost_core::keys::dkg::{self, round1, round2};
+use frost_core::keys::{KeyPackage, PublicKeyPackage};
+use frost_core::{Ciphersuite, Identifier};
+use rand_chacha::rand_core::SeedableRng;
+use rand_chacha::ChaCha20Rng;
+
+/// The packages of a keygen run, as JSON-serializable test vectors.
+///
+/// Packages are listed in the order of the parties' identifiers.
+#[derive(Clone, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(bound = "C: Ciphersuite")]
+pub struct KeygenVectors<C: Ciphersuite> {
+    /// The ciphersuite `ID`.
+    pub ciphersuite: String,
+    /// The seed of the ChaCha20 RNG shared by the parties, in identifier order.
+    pub seed: u64,
+    pub min_signers: u16,
+    pub max_signers: u16,
+    /// The round 1 package broadcast by each party.
+    pub round1: Vec<(Identifier<C>, round1::Package<C>)>,
+    /// The round 2 packages, as `(sender, recipient, package)`.
+    pub round2: Vec<(Identifier<C>, Identifier<C>, round2::Package<C>)>,
+    /// The resulting key package of each party.
+    pub key_packages: Vec<KeyPackage<C>>,
+    /// The resulting public key package, the same for every party.
+    pub public_key_package: PublicKeyPackage<C>,
+}
+
+/// Run a `min_signers`-out-of-`max_signers` keygen seeded with `seed`, and record its
+/// packages.
+///
+/// The same arguments always produce the same vectors.
+pub fn keygen_vectors<C: Ciphersuite>(
+    seed: u64,
+    min_signers: u16,
+    max_signers: u16,
+) -> Result<KeygenVectors<C>, frost_core::Error<C>> {
+    let mut rng = ChaCha20Rng::seed_from_u64(seed);
+    let ids = (1..=max_signers)
+        .map(Identifier::try_from)
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut round1_secrets = BTreeMap::new();
+    let mut round1_packages = BTreeMap::new();
+    for id in &ids {
+        let (secret, package) = dkg::part1(*id, max_signers, min_signers, &mut rng)?;
+        round1_secrets.insert(*id, secret);
+        round1_packages.insert(*id, package);
+    }
+    let others = |me: &Identifier<C>| {
+        round1_packages
+            .iter()
+            .filter(|(id, _)| *id != me)
+            .map(|(id, p)| (*id, p.clone()))
+            .collect::<BTreeMap<_, _>>()
+    };
+
+    let mut round2_secrets = BTreeMap::new();
+    let mut round2_packages = vec![];
+    for (id, secret) in round1_secrets {
+        let (secret, packages) = dkg::part2(secret, &others(&id))?;
+        round2_secrets.insert(id, secret);
+        round2_packages.extend(packages.into_iter().map(|(to, p)| (id, to, p)));
+    }
+
+    let mut key_packages = vec![];
+    let mut public_key_package = None;
+    for (id, secret) in &round2_secrets {
+        let received = round2_packages
+            .iter()
+            .filter(|(_, to, _)| to == id)
+            .map(|(from, _, p)| (*from, p.clone()))
+            .collect::<BTreeMap<_, _>>();
+        let (key_package, pub_key_package) = dkg::part3(secret, &others(id), &received)?;
+        key_packages.push(key_package);
+        public_key_package = Some(pub_key_package);
+    }
+
+    Ok(KeygenVectors {
+        ciphersuite: C::ID.to_string(),
+        seed,
+        min_signers,
+        max_signers,
+        round1: round1_packages.into_iter().collect(),
+        round2: round2_packages,
+        key_packages,
+        public_key_package: public_key_package.ok_or(frost_core::Error::IncorrectNumberOfShares)?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use frost_core::VerifyingKey;
+    use frost_ed25519::Ed25519Sha512;
+    use frost_secp256k1::Secp256K1Sha256;
+
+    fn replay<C: Ciphersuite>() {
+        let vectors = keygen_vectors::<C>(920, 2, 3).unwrap();
+        let json = serde_json::to_string(&vectors).unwrap();
+        let replayed: KeygenVectors<C> = serde_json::from_str(&json).unwrap();
+        assert_eq!(replayed, vectors);
+        // Deterministic from the seed.
+        assert_eq!(keygen_vectors::<C>(920, 2, 3).unwrap(), vectors);
+        assert_ne!(keygen_vectors::<C>(921, 2, 3).unwrap(), vectors);
+
+        assert_eq!(replayed.round1.len(), 3);
+        assert_eq!(replayed.round2.len(), 3 * 2);
+        // The key shares interpolate to the group key.
+        let group_key = replayed.public_key_package.verifying_key();
+        let secret = frost_core::keys::reconstruct(&replayed.key_packages[..2]).unwrap();
+        assert_eq!(&VerifyingKey::from(secret), group_key);
+        for key_package in &replayed.key_packages {
+            assert_eq!(key_package.verifying_key(), group_key);
+        }
+    }
+
+    #[test]
+    fn vectors_replay_to_the_same_group_key() {
+        replay::<Ed25519Sha512>();
+        replay::<Secp256K1Sha256>();
+    }
+}
@@ -0,0 +1,193 @@
+//! Verification of FROST signatures in batch.
+//!
+//! [`batch_verify`] checks many signatures at once with the batch verification of
+//! [`frost_core::batch`], a single multi-scalar multiplication being much cheaper than verifying
+//! each signature on its own. Any FROST ciphersuite supports it, the signatures being Schnorr
+//! signatures. A failed batch only tells that some signature is invalid, so the signatures are
+//! then verified one by one to tell which.
+use api::services::events::JobCalled;
+use frost_core::batch::{Item, Verifier};
+use frost_core::{Ciphersuite, Signature, VerifyingKey};
+use gadget_sdk as sdk;
+use sdk::event_listener::tangle::{
+    jobs::{services_post_processor, services_pre_processor},
+    TangleEventListener,
+};
+use sdk::random::rand::rngs::OsRng;
+use sdk::tangle_subxt::tangle_testnet_runtime::api;
+
+use crate::redjubjub::JubjubBlake2b512;
+use crate::FrostContext;
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("Unknown ciphersuite: {0}")]
+    UnknownCiphersuite(String),
+    #[error("Got {pubkeys} public keys, {msgs} messages and {signatures} signatures")]
+    LengthMismatch {
+        pubkeys: usize,
+        msgs: usize,
+        signatures: usize,
+    },
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+}
+
+/// The outcome of a [`batch_verify`].
+#[derive(Clone, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct BatchVerification {
+    /// Whether each signature is valid, in the order of the batch.
+    pub valid: Vec<bool>,
+    /// Whether every signature of the batch is valid.
+    pub all_valid: bool,
+}
+
+/// Verify a batch of signatures, each one of the message of the same index under the public key
+/// of the same index.
+///
+/// # Parameters
+/// - `ciphersuite`: The ciphersuite of the keys and signatures.
+/// - `pubkeys`: The public keys, e.g. generated by the [`crate::keygen::keygen`] protocol.
+/// - `msgs`: The signed messages.
+/// - `signatures`: The signatures.
+///
+/// # Returns
+/// The JSON encoded [`BatchVerification`]. A key or a signature that does not decode makes its
+/// signature invalid, it does not fail the job.
+///
+/// # Errors
+/// - `UnknownCiphersuite`: If the ciphersuite is not supported.
+/// - `LengthMismatch`: If there are not as many keys, messages and signatures.
+#[sdk::job(
+    id = 15,
+    params(ciphersuite, pubkeys, msgs, signatures),
+    result(_),
+    event_listener(
+        listener = TangleEventListener::<FrostContext, JobCalled>,
+        pre_processor = services_pre_processor,
+        post_processor = services_post_processor,
+    )
+)]
+#[tracing::instrument(skip_all, parent = context.config.span.clone(), err)]
+pub async fn batch_verify(
+    ciphersuite: String,
+    pubkeys: Vec<Vec<u8>>,
+    msgs: Vec<Vec<u8>>,
+    signatures: Vec<Vec<u8>>,
+    context: FrostContext,
+) -> Result<Vec<u8>, Error> {
+    if pubkeys.len() != msgs.len() || msgs.len() != signatures.len() {
+        return Err(Error::LengthMismatch {
+            pubkeys: pubkeys.len(),
+            msgs: msgs.len(),
+            signatures: signatures.len(),
+        });
+    }
+    let valid = match ciphersuite.as_str() {
+        frost_ed25519::Ed25519Sha512::ID => {
+            verify::<frost_ed25519::Ed25519Sha512>(&pubkeys, &msgs, &signatures)
+        }
+        frost_secp256k1::Secp256K1Sha256::ID => {
+            verify::<frost_secp256k1::Secp256K1Sha256>(&pubkeys, &msgs, &signatures)
+        }
+        frost_ristretto255::Ristretto255Sha512::ID => {
+            verify::<frost_ristretto255::Ristretto255Sha512>(&pubkeys, &msgs, &signatures)
+        }
+        frost_p256::P256Sha256::ID => {
+            verify::<frost_p256::P256Sha256>(&pubkeys, &msgs, &signatures)
+        }
+        JubjubBlake2b512::ID => verify::<JubjubBlake2b512>(&pubkeys, &msgs, &signatures),
+        _ => return Err(Error::UnknownCiphersuite(ciphersuite)),
+    };
+    let all_valid = valid.iter().all(|valid| *valid);
+    Ok(serde_json::to_vec(&BatchVerification { valid, all_valid })?)
+}
+
+/// Whether each signature is valid, verifying them in batch and one by one only if the batch
+/// fails.
+fn verify<C: Ciphersuite>(
+    pubkeys: &[Vec<u8>],
+    msgs: &[Vec<u8>],
+    signatures: &[Vec<u8>],
+) -> Vec<bool> {
+    let items = pubkeys
+        .iter()
+        .zip(msgs)
+        .zip(signatures)
+        .map(|((pubkey, msg), signature)| {
+            let pubkey = VerifyingKey::<C>::deserialize(pubkey).ok()?;
+            let signature = Signature::<C>::deserialize(signature).ok()?;
+            Item::new(pubkey, signature, msg).ok()
+        })
+        .collect::<Vec<_>>();
+    let mut verifier = Verifier::<C>::new();
+    for item in items.iter().flatten() {
+        verifier.queue(item.clone());
+    }
+    // An empty batch does not verify, but then there is no signature to tell apart.
+    if items.iter().any(Option::is_some) && verifier.verify(OsRng).is_ok() {
+        return items.iter().map(Option::is_some).collect();
+    }
+    items
+        .into_iter()
+        .map(|item| item.is_some_and(|item| item.verify_single().is_ok()))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::testing::{operator_contexts, MockNetwork, MockNetworkConfig, TempDir};
+    use frost_core::SigningKey;
+
+    #[tokio::test]
+    async fn mixed_batch_reports_each_signature() {
+        type C = frost_ed25519::Ed25519Sha512;
+        let network = MockNetwork::new(MockNetworkConfig {
+            latency: Duration::ZERO,
+            loss: 0.0,
+        });
+        let dir = TempDir::new("batch-verify");
+        let context = operator_contexts(&network, &dir, 1, 961).remove(0);
+
+        let (mut pubkeys, mut msgs, mut signatures) = (vec![], vec![], vec![]);
+        for i in 0..6u8 {
+            let key = SigningKey::<C>::new(&mut OsRng);
+            let msg = vec![i; 32];
+            pubkeys.push(VerifyingKey::from(&key).serialize().unwrap());
+            signatures.push(key.sign(OsRng, &msg).serialize().unwrap());
+            msgs.push(msg);
+        }
+        // A signature of another message, and a signature that does not decode.
+        msgs[1] = b"not what was signed".to_vec();
+        signatures[4] = vec![0xff; 64];
+
+        let result = batch_verify(
+            C::ID.to_string(),
+            pubkeys.clone(),
+            msgs.clone(),
+            signatures.clone(),
+            context.clone(),
+        )
+        .await
+        .unwrap();
+        let result: BatchVerification = serde_json::from_slice(&result).unwrap();
+        assert_eq!(result.valid, [true, false, true, true, false, true]);
+        assert!(!result.all_valid);
+
+        // Without the invalid signatures, the whole batch verifies.
+        for i in [4, 1] {
+            pubkeys.remove(i);
+            msgs.remove(i);
+            signatures.remove(i);
+        }
+        let result = batch_verify(C::ID.to_string(), pubkeys, msgs, signatures, context)
+            .await
+            .unwrap();
+        let result: BatchVerification = serde_json::from_slice(&result).unwrap();
+        assert_eq!(result.valid, [true; 4]);
+        assert!(result.all_valid);
+    }
+}
@@ -0,0 +1,119 @@
+use gadget_sdk as sdk;
+
+/// The payload posted to the webhook after a successful signing.
+///
+/// All the fields are hex encoded.
+#[derive(Clone, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct SignatureNotification {
+    /// The group public key that produced the signature.
+    pub pubkey: String,
+    /// The keccak256 hash of the signed message.
+    pub msg_hash: String,
+    /// The serialized signature.
+    pub signature: String,
+}
+
+/// A best-effort webhook that gets notified about every produced signature.
+#[derive(Clone, Debug)]
+pub struct Webhook {
+    url: reqwest::Url,
+    client: reqwest::Client,
+}
+
+impl Webhook {
+    /// Create a new webhook that posts to the given `url`.
+    pub fn new(url: reqwest::Url) -> Self {
+        Self {
+            url,
+            client: reqwest::Client::new(),
+        }
+    }
+
+    /// Post the notification to the webhook.
+    ///
+    /// Failures are logged and otherwise ignored, the webhook must never fail a job.
+    pub async fn notify(&self, notification: &SignatureNotification) {
+        let res = self
+            .client
+            .post(self.url.clone())
+            .json(notification)
+            .send()
+            .await
+            .and_then(reqwest::Response::error_for_status);
+        match res {
+            Ok(_) => sdk::debug!(url = %self.url, "Webhook notified"),
+            Err(e) => sdk::warn!(url = %self.url, error = %e, "Failed to notify webhook"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    /// Accept a single HTTP request and return its body.
+    async fn accept_one(listener: TcpListener) -> Vec<u8> {
+        let (mut socket, _) = listener.accept().await.unwrap();
+        let mut buf = Vec::new();
+        let mut chunk = [0u8; 1024];
+        let body_start = loop {
+            let read = socket.read(&mut chunk).await.unwrap();
+            buf.extend_from_slice(&chunk[..read]);
+            if let Some(pos) = buf.windows(4).position(|w| w == b"\r\n\r\n") {
+                break pos + 4;
+            }
+        };
+        let headers = String::from_utf8_lossy(&buf[..body_start]).to_lowercase();
+        let content_length = headers
+            .lines()
+            .find_map(|l| l.strip_prefix("content-length:"))
+            .map(|v| v.trim().parse::<usize>().unwrap())
+            .unwrap();
+        while buf.len() < body_start + content_length {
+            let read = socket.read(&mut chunk).await.unwrap();
+            buf.extend_from_slice(&chunk[..read]);
+        }
+        socket
+            .write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 0\r\n\r\n")
+            .await
+            .unwrap();
+        buf[body_start..body_start + content_length].to_vec()
+    }
+
+    #[tokio::test]
+    async fn it_posts_the_notification() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = tokio::spawn(accept_one(listener));
+
+        let notification = SignatureNotification {
+            pubkey: hex::encode([1u8; 32]),
+            msg_hash: hex::encode([2u8; 32]),
+            signature: hex::encode([3u8; 64]),
+        };
+        let url = format!("http://{addr}/frost").parse().unwrap();
+        Webhook::new(url).notify(&notification).await;
+
+        let body = server.await.unwrap();
+        let received: SignatureNotification = serde_json::from_slice(&body).unwrap();
+        assert_eq!(received, notification);
+    }
+
+    #[tokio::test]
+    async fn it_ignores_unreachable_webhooks() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        drop(listener);
+
+        let notification = SignatureNotification {
+            pubkey: String::new(),
+            msg_hash: String::new(),
+            signature: String::new(),
+        };
+        let url = format!("http://{addr}/frost").parse().unwrap();
+        // Must not panic nor return an error.
+        Webhook::new(url).notify(&notification).await;
+    }
+}
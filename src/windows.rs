@@ -0,0 +1,263 @@
+//! Signing windows of the keys.
+//!
+//! A key can be restricted to sign only during some [`SigningWindow`]s, e.g. the business hours
+//! of a treasury key, set with [`set_signing_windows`] and stored in its
+//! [`KeygenEntry`](crate::keygen::KeygenEntry). The signing jobs of a restricted key fail with
+//! `OutsideAllowedWindow` when the timestamp of the block the job was called in is in none of
+//! its windows.
+//!
+//! The windows are in UTC and evaluated at the time of the call on-chain rather than by the
+//! clock of each node, so every operator that set the same windows takes the same decision,
+//! whatever its time zone, clock drift or when it gets to run the job.
+use api::services::events::JobCalled;
+use gadget_sdk as sdk;
+use sdk::event_listener::tangle::{
+    jobs::{services_post_processor, services_pre_processor},
+    TangleEventListener,
+};
+use sdk::tangle_subxt::tangle_testnet_runtime::api;
+use serde::{Deserialize, Serialize};
+
+use crate::FrostContext;
+
+/// The seconds of a day.
+pub const SECS_PER_DAY: u32 = 24 * 60 * 60;
+/// Every day of the week, see [`SigningWindow::days`].
+pub const EVERY_DAY: u8 = 0b111_1111;
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("The Secret Share for that key is not found")]
+    KeyNotFound,
+    #[error("Malformed signing windows: {0}")]
+    Malformed(String),
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[error(transparent)]
+    Entry(#[from] crate::entry::Error),
+    #[error(transparent)]
+    Unauthorized(#[from] crate::operators::Unauthorized),
+}
+
+/// A time of the week a key can sign at.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SigningWindow {
+    /// The days of the week of the window, bit 0 for Monday to bit 6 for Sunday.
+    #[serde(default = "every_day")]
+    pub days: u8,
+    /// The first second of the window, since midnight UTC.
+    pub start: u32,
+    /// The second the window ends at, excluded, since midnight UTC.
+    pub end: u32,
+}
+
+fn every_day() -> u8 {
+    EVERY_DAY
+}
+
+impl SigningWindow {
+    /// Whether the window contains `unix_secs`, in seconds since the Unix epoch.
+    pub fn contains(&self, unix_secs: u64) -> bool {
+        let days = unix_secs / u64::from(SECS_PER_DAY);
+        // The Unix epoch is a Thursday.
+        let weekday = (days + 3) % 7;
+        let secs = unix_secs % u64::from(SECS_PER_DAY);
+        self.days & (1 << weekday) != 0
+            && (u64::from(self.start)..u64::from(self.end)).contains(&secs)
+    }
+
+    fn validate(&self) -> Result<(), Error> {
+        if self.start >= self.end || self.end > SECS_PER_DAY {
+            return Err(Error::Malformed(format!(
+                "a window from {} to {} is not within a day",
+                self.start, self.end
+            )));
+        }
+        if self.days & EVERY_DAY == 0 || self.days & !EVERY_DAY != 0 {
+            return Err(Error::Malformed(format!("no such days {:#09b}", self.days)));
+        }
+        Ok(())
+    }
+}
+
+/// Whether a key restricted to `windows` can sign at `unix_secs`.
+pub fn allowed(windows: &[SigningWindow], unix_secs: u64) -> bool {
+    windows.iter().any(|window| window.contains(unix_secs))
+}
+
+/// Restrict a previously generated key to sign only during some windows on this node.
+///
+/// # Parameters
+/// - `pubkey`: The public key generated by the [`crate::keygen::keygen`] protocol, or its label.
+/// - `windows`: The JSON list of the [`SigningWindow`]s of the key, or an empty list to lift the
+///   restriction.
+///
+/// # Returns
+/// The public key.
+///
+/// # Errors
+/// - `KeyNotFound`: If the key is not found.
+/// - `Malformed`: If a window is not within a day or has no day.
+/// - `Unauthorized`: If the job is not called by the service owner or one of its operators.
+#[sdk::job(
+    id = 18,
+    params(pubkey, windows),
+    result(_),
+    event_listener(
+        listener = TangleEventListener::<FrostContext, JobCalled>,
+        pre_processor = services_pre_processor,
+        post_processor = services_post_processor,
+    )
+)]
+#[tracing::instrument(skip_all, parent = context.config.span.clone(), err)]
+pub async fn set_signing_windows(
+    pubkey: Vec<u8>,
+    windows: String,
+    context: FrostContext,
+) -> Result<Vec<u8>, Error> {
+    context.authorize_caller().await?;
+    let windows: Vec<SigningWindow> = serde_json::from_str(&windows)?;
+    for window in &windows {
+        window.validate()?;
+    }
+    let pubkey = context.resolve_key(pubkey)?;
+    let hex_pubkey = hex::encode(&pubkey);
+    let mut info = context
+        .keygen_info(&hex_pubkey)?
+        .ok_or(Error::KeyNotFound)?;
+    if windows.is_empty() {
+        if let Some(entry) = info["entry"].as_object_mut() {
+            entry.remove("signing_windows");
+        }
+    } else {
+        info["entry"]["signing_windows"] = serde_json::to_value(windows)?;
+    }
+    let info = crate::entry::encode(context.entry_format, &info)?;
+    context.store.set(hex_pubkey, info)?;
+    Ok(pubkey)
+}
+
+impl FrostContext {
+    /// Check that the key of the JSON envelope `info` can sign at the time of the job call
+    /// `call_id`, see [`FrostContext::call_time`].
+    pub(crate) async fn check_signing_window(
+        &self,
+        info: &serde_json::Value,
+        call_id: u64,
+    ) -> Result<(), crate::sign::Error> {
+        let Some(windows) = info["entry"].get("signing_windows") else {
+            return Ok(());
+        };
+        let windows: Vec<SigningWindow> = serde_json::from_value(windows.clone())?;
+        let now = self
+            .call_time(call_id)
+            .await
+            .map_err(crate::sign::Error::Other)?;
+        match allowed(&windows, now) {
+            true => Ok(()),
+            false => Err(crate::sign::Error::OutsideAllowedWindow { now }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn windows_follow_the_utc_week() {
+        // Monday to Friday, from 9:00 to 17:00 UTC.
+        let business_hours = SigningWindow {
+            days: 0b001_1111,
+            start: 9 * 3600,
+            end: 17 * 3600,
+        };
+        // Monday 2024-01-01 00:00:00 UTC.
+        let monday = 1_704_067_200;
+        assert!(!business_hours.contains(monday));
+        assert!(business_hours.contains(monday + 9 * 3600));
+        assert!(!business_hours.contains(monday + 17 * 3600));
+        // Friday and Saturday at noon.
+        assert!(business_hours.contains(monday + 4 * 86_400 + 12 * 3600));
+        assert!(!business_hours.contains(monday + 5 * 86_400 + 12 * 3600));
+        assert!(!allowed(&[], monday));
+
+        assert!(business_hours.validate().is_ok());
+        let backwards = SigningWindow {
+            start: 17 * 3600,
+            end: 9 * 3600,
+            ..business_hours
+        };
+        assert!(backwards.validate().is_err());
+        let no_day = SigningWindow {
+            days: 0,
+            ..business_hours
+        };
+        assert!(no_day.validate().is_err());
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn signing_is_refused_outside_the_windows() {
+        use crate::sign::{sign, Error as SignError};
+        use crate::testing::{
+            keygen_on_all, operator_contexts, sign_on_all, MockCoordinator, MockNetwork,
+            MockNetworkConfig, TempDir, BLOCK_TIME,
+        };
+        use frost_core::Ciphersuite;
+        use std::time::Duration;
+
+        type C = frost_ed25519::Ed25519Sha512;
+        let network = MockNetwork::new(MockNetworkConfig {
+            latency: Duration::from_millis(50),
+            loss: 0.0,
+        });
+        let dir = TempDir::new("signing-windows");
+        let contexts = operator_contexts(&network, &dir, 3, 976);
+        let operators = contexts[0].current_operators().await.unwrap();
+        let pubkey = keygen_on_all(&contexts, C::ID, 3).await;
+
+        let windows = r#"[{"days": 31, "start": 32400, "end": 61200}]"#;
+        for context in contexts.iter().cloned() {
+            set_signing_windows(pubkey.clone(), windows.to_string(), context)
+                .await
+                .unwrap();
+        }
+        // The job called in the block made at `secs` past the mock genesis, on Monday
+        // 2024-01-01 at midnight UTC.
+        let called_at = |secs: u64| {
+            contexts
+                .iter()
+                .cloned()
+                .map(|context| {
+                    context.with_coordinator(MockCoordinator {
+                        operators: operators.clone(),
+                        call_id: secs / BLOCK_TIME - 1,
+                        change_after: None,
+                        caller: None,
+                    })
+                })
+                .collect::<Vec<_>>()
+        };
+
+        // At 8:00 UTC, an hour before the window opens, whatever the clocks of the nodes say.
+        for context in called_at(8 * 3600) {
+            let result = sign(pubkey.clone(), b"too early".to_vec(), context).await;
+            assert!(
+                matches!(
+                    result,
+                    Err(SignError::OutsideAllowedWindow { now: 1_704_096_000 })
+                ),
+                "{result:?}"
+            );
+        }
+
+        let verifying_key = frost_core::VerifyingKey::<C>::deserialize(&pubkey).unwrap();
+        for signature in sign_on_all(&called_at(9 * 3600), &pubkey, b"on time").await {
+            let signature = signature.unwrap();
+            let signature = frost_core::Signature::<C>::deserialize(&signature).unwrap();
+            verifying_key.verify(b"on time", &signature).unwrap();
+        }
+    }
+}
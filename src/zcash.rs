@@ -0,0 +1,134 @@
+//! Zcash transaction signature fields.
+//!
+//! Two signature fields of a Zcash transaction take a signature of a supported ciphersuite:
+//!
+//! - The JoinSplit signature of the v2 to v4 transactions, an Ed25519 signature of the SIGHASH
+//!   under `joinSplitPubKey`, checked with the [ZIP 215] rules, which accept every signature
+//!   following RFC 8032 as FROST Ed25519 signatures do. See [`join_split_fields`].
+//! - The `spendAuthSig` of a Sapling spend, a RedJubjub signature of the SIGHASH under the
+//!   randomized key `rk` of the spend, which is the group key of a
+//!   [`JubjubBlake2b512`] key. See [`spend_auth_sig`].
+//!
+//! The transparent inputs are signed with ECDSA, not the secp256k1 Schnorr signatures of FROST,
+//! and the Orchard spends with RedPallas, which is not supported.
+//!
+//! [ZIP 215]: https://zips.z.cash/zip-0215
+use frost_core::{Ciphersuite, Signature, VerifyingKey};
+use frost_ed25519::Ed25519Sha512;
+
+use crate::redjubjub::JubjubBlake2b512;
+
+/// The length of the `joinSplitPubKey` and `joinSplitSig` fields.
+pub const JOIN_SPLIT_FIELDS_LEN: usize = 32 + 64;
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("No Zcash signature field takes a {0} signature")]
+    UnsupportedCiphersuite(String),
+    #[error("Frost error: {0}")]
+    Frost(#[from] frost_core::Error<Ed25519Sha512>),
+    #[error("Frost error: {0}")]
+    RedJubjub(#[from] frost_core::Error<JubjubBlake2b512>),
+}
+
+/// The `joinSplitPubKey` and `joinSplitSig` fields of a transaction.
+///
+/// As laid out one after the other in the v2 to v4 transactions: the 32 bytes of the verifying
+/// key, then the 64 bytes `R || S` of the signature of the SIGHASH.
+pub fn join_split_fields(
+    verifying_key: &VerifyingKey<Ed25519Sha512>,
+    signature: &Signature<Ed25519Sha512>,
+) -> Result<[u8; JOIN_SPLIT_FIELDS_LEN], Error> {
+    let mut fields = [0u8; JOIN_SPLIT_FIELDS_LEN];
+    fields[..32].copy_from_slice(&verifying_key.serialize()?);
+    fields[32..].copy_from_slice(&signature.serialize()?);
+    Ok(fields)
+}
+
+/// The `spendAuthSig` field of a Sapling spend: the 64 bytes `R || S` of the signature of the
+/// SIGHASH, to be checked against the verifying key of the signature as `rk`.
+pub fn spend_auth_sig(signature: &Signature<JubjubBlake2b512>) -> Result<[u8; 64], Error> {
+    let mut field = [0u8; 64];
+    field.copy_from_slice(&signature.serialize()?);
+    Ok(field)
+}
+
+/// The Zcash signature fields of a signature of the ciphersuite `ciphersuite`, by `ID`, given
+/// the serialized `pubkey` and `signature` as returned by the keygen and signing jobs.
+pub fn signature_fields(
+    ciphersuite: &str,
+    pubkey: &[u8],
+    signature: &[u8],
+) -> Result<Vec<u8>, Error> {
+    match ciphersuite {
+        Ed25519Sha512::ID => {
+            let verifying_key = VerifyingKey::<Ed25519Sha512>::deserialize(pubkey)?;
+            let signature = Signature::<Ed25519Sha512>::deserialize(signature)?;
+            Ok(join_split_fields(&verifying_key, &signature)?.to_vec())
+        }
+        JubjubBlake2b512::ID => {
+            // The key is the `rk` of the spend, not one of its fields.
+            VerifyingKey::<JubjubBlake2b512>::deserialize(pubkey)?;
+            let signature = Signature::<JubjubBlake2b512>::deserialize(signature)?;
+            Ok(spend_auth_sig(&signature)?.to_vec())
+        }
+        _ => Err(Error::UnsupportedCiphersuite(ciphersuite.to_string())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use super::*;
+    use frost_core::keys::{generate_with_dealer, IdentifierList, KeyPackage};
+    use gadget_sdk::random::rand::rngs::StdRng;
+    use gadget_sdk::random::SeedableRng;
+
+    #[test]
+    fn join_split_signature_passes_zip215_verification() {
+        let rng = &mut StdRng::seed_from_u64(972);
+        let (shares, pub_key_pkg) =
+            generate_with_dealer::<Ed25519Sha512, _>(3, 2, IdentifierList::Default, rng).unwrap();
+        let key_pkgs = shares
+            .into_iter()
+            .take(2)
+            .map(|(id, share)| (id, KeyPackage::try_from(share).unwrap()))
+            .collect::<BTreeMap<_, _>>();
+        // A SIGHASH is a 32 bytes BLAKE2b digest.
+        let sighash = [0x5a; 32];
+        let (mut nonces, mut commitments) = (BTreeMap::new(), BTreeMap::new());
+        for (id, key_pkg) in &key_pkgs {
+            let (nonce, commitment) = frost_core::round1::commit(key_pkg.signing_share(), rng);
+            nonces.insert(*id, nonce);
+            commitments.insert(*id, commitment);
+        }
+        let signing_pkg = frost_core::SigningPackage::new(commitments, &sighash);
+        let shares = key_pkgs
+            .iter()
+            .map(|(id, key_pkg)| {
+                let share = frost_core::round2::sign(&signing_pkg, &nonces[id], key_pkg).unwrap();
+                (*id, share)
+            })
+            .collect();
+        let signature = frost_core::aggregate(&signing_pkg, &shares, &pub_key_pkg).unwrap();
+
+        let fields = signature_fields(
+            Ed25519Sha512::ID,
+            &pub_key_pkg.verifying_key().serialize().unwrap(),
+            &signature.serialize().unwrap(),
+        )
+        .unwrap();
+        assert_eq!(fields.len(), JOIN_SPLIT_FIELDS_LEN);
+        let (join_split_pub_key, join_split_sig) = fields.split_at(32);
+        let key = ed25519_zebra::VerificationKey::try_from(join_split_pub_key).unwrap();
+        let sig = ed25519_zebra::Signature::try_from(join_split_sig).unwrap();
+        key.verify(&sig, &sighash).unwrap();
+        assert!(key.verify(&sig, &[0; 32]).is_err());
+
+        assert!(matches!(
+            signature_fields(frost_secp256k1::Secp256K1Sha256::ID, &[], &[]),
+            Err(Error::UnsupportedCiphersuite(_))
+        ));
+    }
+}